@@ -0,0 +1,38 @@
+use comms::event::UserMessageBroadcastEvent;
+
+/// [Bot] handles the events of the rooms it has joined, answering through the [Replies]
+///
+/// The bot is not handed its own messages and joins. Both handlers do nothing unless implemented.
+pub trait Bot {
+    /// Another user has sent a message to one of the rooms of the bot
+    fn on_message(&mut self, _message: &UserMessageBroadcastEvent, _replies: &mut Replies) {}
+
+    /// Another user has joined one of the rooms of the bot
+    fn on_join(&mut self, _room: &str, _user_id: &str, _replies: &mut Replies) {}
+}
+
+/// A message the bot sends to a room
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub room: String,
+    pub content: String,
+}
+
+/// [Replies] collects the messages a handler of the [Bot] sends, in order
+#[derive(Debug, Default)]
+pub struct Replies {
+    replies: Vec<Reply>,
+}
+
+impl Replies {
+    pub fn send(&mut self, room: &str, content: impl Into<String>) {
+        self.replies.push(Reply {
+            room: String::from(room),
+            content: content.into(),
+        });
+    }
+
+    pub fn into_vec(self) -> Vec<Reply> {
+        self.replies
+    }
+}
@@ -0,0 +1,51 @@
+use comms::event::UserMessageBroadcastEvent;
+
+use crate::{Bot, Replies};
+
+use super::parse_command;
+
+/// [EchoBot] repeats what follows `!echo` back to the room
+#[derive(Debug, Default)]
+pub struct EchoBot;
+
+impl Bot for EchoBot {
+    fn on_message(&mut self, message: &UserMessageBroadcastEvent, replies: &mut Replies) {
+        if let Some(text) = parse_command(&message.content, "echo").filter(|text| !text.is_empty())
+        {
+            replies.send(&message.room, text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replies_to(content: &str) -> Vec<String> {
+        let mut replies = Replies::default();
+        EchoBot.on_message(
+            &UserMessageBroadcastEvent {
+                room: String::from("general"),
+                id: 1,
+                user_id: String::from("alice"),
+                content: String::from(content),
+                timestamp: 0,
+                reply_to: None,
+            },
+            &mut replies,
+        );
+
+        replies
+            .into_vec()
+            .into_iter()
+            .map(|reply| reply.content)
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_repeats_the_text() {
+        assert_eq!(replies_to("!echo hello there"), vec!["hello there"]);
+        assert!(replies_to("!echo").is_empty());
+        assert!(replies_to("hello there").is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+//! The bots shipped with the project, run by the `bots` binary
+
+mod echo;
+mod quote;
+
+pub use echo::EchoBot;
+pub use quote::QuoteBot;
+
+/// The prefix of the messages addressed to the built-in bots, as in `!echo hello`
+pub const COMMAND_PREFIX: char = '!';
+
+/// Returns the arguments of the message if it is the given bot command, as in `!echo hello` for `echo`
+fn parse_command<'a>(content: &'a str, command: &str) -> Option<&'a str> {
+    let rest = content.trim().strip_prefix(COMMAND_PREFIX)?;
+    let rest = rest.strip_prefix(command)?;
+
+    match rest.chars().next() {
+        None => Some(""),
+        Some(c) if c.is_whitespace() => Some(rest.trim_start()),
+        // a longer word, as in `!echoes`
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_are_parsed() {
+        assert_eq!(
+            parse_command("!echo hello there", "echo"),
+            Some("hello there")
+        );
+        assert_eq!(parse_command("  !quote ", "quote"), Some(""));
+        assert_eq!(parse_command("!echoes", "echo"), None);
+        assert_eq!(parse_command("echo hello", "echo"), None);
+    }
+}
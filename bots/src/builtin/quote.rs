@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use anyhow::Context;
+use comms::event::UserMessageBroadcastEvent;
+use rand::seq::SliceRandom;
+
+use crate::{Bot, Replies};
+
+use super::parse_command;
+
+/// The quotes of a [QuoteBot] started without a file of quotes
+const DEFAULT_QUOTES: [&str; 5] = [
+    "Talk is cheap. Show me the code. - Linus Torvalds",
+    "Simplicity is prerequisite for reliability. - Edsger W. Dijkstra",
+    "Programs must be written for people to read, and only incidentally for machines to execute. - Harold Abelson",
+    "Make it work, make it right, make it fast. - Kent Beck",
+    "Fearless concurrency. - The Rust Book",
+];
+
+/// [QuoteBot] answers `!quote` with a random quote, learns new ones with `!quote add <quote>`,
+/// and welcomes the users joining its rooms with one
+#[derive(Debug)]
+pub struct QuoteBot {
+    quotes: Vec<String>,
+}
+
+impl Default for QuoteBot {
+    fn default() -> Self {
+        QuoteBot::new(
+            DEFAULT_QUOTES
+                .iter()
+                .map(|quote| String::from(*quote))
+                .collect(),
+        )
+    }
+}
+
+impl QuoteBot {
+    pub fn new(quotes: Vec<String>) -> Self {
+        QuoteBot { quotes }
+    }
+
+    /// Reads the quotes from a file with one quote per line, the blank lines are skipped
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let quotes = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read the quotes in {}", path.display()))?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        if quotes.is_empty() {
+            return Err(anyhow::anyhow!("{} has no quotes", path.display()));
+        }
+
+        Ok(QuoteBot::new(quotes))
+    }
+
+    fn random_quote(&self) -> Option<&str> {
+        self.quotes
+            .choose(&mut rand::thread_rng())
+            .map(String::as_str)
+    }
+}
+
+impl Bot for QuoteBot {
+    fn on_message(&mut self, message: &UserMessageBroadcastEvent, replies: &mut Replies) {
+        let Some(args) = parse_command(&message.content, "quote") else {
+            return;
+        };
+
+        match args.strip_prefix("add") {
+            Some(quote) if quote.starts_with(char::is_whitespace) => {
+                self.quotes.push(String::from(quote.trim()));
+                replies.send(
+                    &message.room,
+                    format!(
+                        "Quote #{} added, thanks @{}",
+                        self.quotes.len(),
+                        message.user_id
+                    ),
+                );
+            }
+            _ => {
+                if let Some(quote) = self.random_quote() {
+                    replies.send(&message.room, quote);
+                }
+            }
+        }
+    }
+
+    fn on_join(&mut self, room: &str, user_id: &str, replies: &mut Replies) {
+        if let Some(quote) = self.random_quote() {
+            replies.send(room, format!("Welcome @{}! {}", user_id, quote));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replies_to(bot: &mut QuoteBot, content: &str) -> Vec<String> {
+        let mut replies = Replies::default();
+        bot.on_message(
+            &UserMessageBroadcastEvent {
+                room: String::from("general"),
+                id: 1,
+                user_id: String::from("alice"),
+                content: String::from(content),
+                timestamp: 0,
+                reply_to: None,
+            },
+            &mut replies,
+        );
+
+        replies
+            .into_vec()
+            .into_iter()
+            .map(|reply| reply.content)
+            .collect()
+    }
+
+    #[test]
+    fn test_quotes_are_told_and_learned() {
+        let mut bot = QuoteBot::new(vec![]);
+        assert!(replies_to(&mut bot, "!quote").is_empty());
+
+        assert_eq!(
+            replies_to(&mut bot, "!quote add  Less is more. "),
+            vec!["Quote #1 added, thanks @alice"]
+        );
+        assert_eq!(replies_to(&mut bot, "!quote"), vec!["Less is more."]);
+        assert!(replies_to(&mut bot, "quote").is_empty());
+
+        let mut replies = Replies::default();
+        bot.on_join("general", "bob", &mut replies);
+        assert_eq!(replies.into_vec()[0].content, "Welcome @bob! Less is more.");
+    }
+}
@@ -0,0 +1,13 @@
+//! Automations for the chat server, built on the [client] library
+//!
+//! A [Bot] reacts to the messages and the joins of its rooms by queueing [Replies], which [run] sends
+//! under a [RateLimit] so a busy room does not get the bot refused by the server.
+
+mod bot;
+pub mod builtin;
+mod rate_limiter;
+mod runner;
+
+pub use bot::{Bot, Replies, Reply};
+pub use rate_limiter::RateLimit;
+pub use runner::{run, BotConfig};
@@ -0,0 +1,75 @@
+use std::{path::PathBuf, str::FromStr};
+
+use bots::{
+    builtin::{EchoBot, QuoteBot},
+    BotConfig, RateLimit,
+};
+use comms::cli;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_SERVER: &str = "localhost:8080";
+const DEFAULT_ROOMS: &str = "general";
+/// Environment variable with the password the bot logs in with, kept out of the command line
+const PASSWORD_ENV: &str = "CHAT_BOT_PASSWORD";
+/// Environment variables to override the rate limit of the replies, see [RateLimit]
+const RATE_LIMIT_BURST_ENV: &str = "CHAT_BOT_RATE_LIMIT_BURST";
+const RATE_LIMIT_PER_SECOND_ENV: &str = "CHAT_BOT_RATE_LIMIT_PER_SECOND";
+/// Logs what the bot does, unless `RUST_LOG` filters otherwise
+const DEFAULT_LOG_FILTER: &str = "info";
+const USAGE: &str = "usage: bots <echo|quote> [--server <addr>] [--name <username>] [--rooms <room,room>] [--quotes <file>]";
+
+/// Reads and parses an environment variable, panics if it is set to an invalid value
+fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("could not parse the environment variable {}", name))
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER)),
+        )
+        .init();
+
+    let Some(kind) = std::env::args().nth(1) else {
+        return Err(anyhow::anyhow!(USAGE));
+    };
+    let password = std::env::var(PASSWORD_ENV)
+        .map_err(|_| anyhow::anyhow!("set {} to the password of the bot", PASSWORD_ENV))?;
+
+    let default_rate_limit = RateLimit::default();
+    let config = |default_name: &str| BotConfig {
+        addr: cli::flag("--server").unwrap_or_else(|| String::from(DEFAULT_SERVER)),
+        username: cli::flag("--name").unwrap_or_else(|| String::from(default_name)),
+        password: password.clone(),
+        rooms: cli::flag("--rooms")
+            .unwrap_or_else(|| String::from(DEFAULT_ROOMS))
+            .split(',')
+            .map(str::trim)
+            .filter(|room| !room.is_empty())
+            .map(String::from)
+            .collect(),
+        rate_limit: RateLimit {
+            burst: env_var(RATE_LIMIT_BURST_ENV).unwrap_or(default_rate_limit.burst),
+            per_second: env_var(RATE_LIMIT_PER_SECOND_ENV).unwrap_or(default_rate_limit.per_second),
+        },
+    };
+
+    match kind.as_str() {
+        "echo" => bots::run(EchoBot, config("echo_bot")).await,
+        "quote" => {
+            let bot = match cli::flag("--quotes") {
+                Some(path) => QuoteBot::from_file(&PathBuf::from(path))?,
+                None => QuoteBot::default(),
+            };
+
+            bots::run(bot, config("quote_bot")).await
+        }
+        _ => Err(anyhow::anyhow!(USAGE)),
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+/// How many replies a bot can send at once, and how fast it earns them back
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    /// Zero disables the limit
+    pub per_second: f64,
+}
+
+impl Default for RateLimit {
+    /// Stays below the default message limit of the server, so a busy room does not get the bot refused
+    fn default() -> Self {
+        RateLimit {
+            burst: 5,
+            per_second: 1.0,
+        }
+    }
+}
+
+/// [RateLimiter] holds up to `burst` tokens, refilled at `per_second`, each reply takes one
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        RateLimiter {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token, or returns how long until the next one is earned
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        if self.limit.per_second <= 0.0 {
+            return Ok(());
+        }
+
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.limit.per_second,
+        ))
+    }
+
+    /// Waits until a token is earned, then takes it
+    pub async fn take(&mut self) {
+        while let Err(wait) = self.try_take(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_is_refilled_over_time() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(
+            RateLimit {
+                burst: 2,
+                per_second: 2.0,
+            },
+            now,
+        );
+
+        assert!(limiter.try_take(now).is_ok());
+        assert!(limiter.try_take(now).is_ok());
+        assert_eq!(limiter.try_take(now), Err(Duration::from_millis(500)));
+
+        assert!(limiter.try_take(now + Duration::from_millis(500)).is_ok());
+        assert!(limiter.try_take(now + Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_zero_rate_is_not_limited() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(
+            RateLimit {
+                burst: 0,
+                per_second: 0.0,
+            },
+            now,
+        );
+
+        assert!((0..100).all(|_| limiter.try_take(now).is_ok()));
+    }
+}
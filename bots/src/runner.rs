@@ -0,0 +1,154 @@
+use std::time::Instant;
+
+use anyhow::Context;
+use client::Client;
+use comms::event::{Event, RoomParticipationStatus};
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+use crate::{
+    bot::{Bot, Replies, Reply},
+    rate_limiter::{RateLimit, RateLimiter},
+};
+
+/// Where and as whom a bot runs
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    /// The address of the server, prefixed with `tls://` to connect over TLS
+    pub addr: String,
+    /// The user the bot logs in as, registered on its first login
+    pub username: String,
+    pub password: String,
+    /// The rooms the bot joins
+    pub rooms: Vec<String>,
+    pub rate_limit: RateLimit,
+}
+
+/// Logs the bot in and hands it the events of its rooms, until the connection is lost for good
+///
+/// The replies are sent one at a time, waiting for the rate limit meanwhile. A reply the server refuses is
+/// reported and dropped, the bot keeps running.
+pub async fn run(mut bot: impl Bot, config: BotConfig) -> anyhow::Result<()> {
+    let client = Client::connect(&config.addr).await?;
+    let login = client.login(&config.username, &config.password).await?;
+    // subscribed before joining, so the bot hears from the first message on
+    let mut events = client.events();
+
+    for room in config.rooms.iter() {
+        client
+            .join(room)
+            .await
+            .with_context(|| format!("could not join {}", room))?;
+    }
+    info!(
+        user_id = %login.user_id,
+        "the bot is running in {}",
+        config.rooms.join(", ")
+    );
+
+    let mut rate_limiter = RateLimiter::new(config.rate_limit, Instant::now());
+    while let Some(event) = events.next().await {
+        for reply in dispatch(&mut bot, &login.user_id, &event) {
+            rate_limiter.take().await;
+
+            if let Err(err) = client.send_message(&reply.room, &reply.content).await {
+                warn!(room = %reply.room, "could not reply: {:#}", err);
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!("the connection to the server was lost"))
+}
+
+/// Hands the event to the handler of the bot, unless the bot caused it
+fn dispatch(bot: &mut impl Bot, bot_id: &str, event: &Event) -> Vec<Reply> {
+    let mut replies = Replies::default();
+
+    match event {
+        Event::UserMessage(message) if message.user_id != bot_id => {
+            bot.on_message(message, &mut replies);
+        }
+        Event::RoomParticipation(event)
+            if event.status == RoomParticipationStatus::Joined && event.user_id != bot_id =>
+        {
+            bot.on_join(&event.room, &event.user_id, &mut replies);
+        }
+        _ => (),
+    }
+
+    replies.into_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event::{RoomParticipationBroacastEvent, UserMessageBroadcastEvent};
+
+    use super::*;
+
+    /// Answers every message and greets every join
+    struct Parrot;
+
+    impl Bot for Parrot {
+        fn on_message(&mut self, message: &UserMessageBroadcastEvent, replies: &mut Replies) {
+            replies.send(&message.room, message.content.clone());
+        }
+
+        fn on_join(&mut self, room: &str, user_id: &str, replies: &mut Replies) {
+            replies.send(room, format!("hi {}", user_id));
+        }
+    }
+
+    fn message(user_id: &str) -> Event {
+        Event::UserMessage(UserMessageBroadcastEvent {
+            room: String::from("general"),
+            id: 1,
+            user_id: String::from(user_id),
+            content: String::from("hello"),
+            timestamp: 0,
+            reply_to: None,
+        })
+    }
+
+    fn participation(user_id: &str, status: RoomParticipationStatus) -> Event {
+        Event::RoomParticipation(RoomParticipationBroacastEvent {
+            room: String::from("general"),
+            user_id: String::from(user_id),
+            status,
+        })
+    }
+
+    #[test]
+    fn test_bot_is_not_handed_its_own_events() {
+        let reply = |content: &str| Reply {
+            room: String::from("general"),
+            content: String::from(content),
+        };
+
+        assert_eq!(
+            dispatch(&mut Parrot, "parrot", &message("alice")),
+            vec![reply("hello")]
+        );
+        assert!(dispatch(&mut Parrot, "parrot", &message("parrot")).is_empty());
+
+        assert_eq!(
+            dispatch(
+                &mut Parrot,
+                "parrot",
+                &participation("alice", RoomParticipationStatus::Joined)
+            ),
+            vec![reply("hi alice")]
+        );
+        assert!(dispatch(
+            &mut Parrot,
+            "parrot",
+            &participation("alice", RoomParticipationStatus::Left)
+        )
+        .is_empty());
+        assert!(dispatch(
+            &mut Parrot,
+            "parrot",
+            &participation("parrot", RoomParticipationStatus::Joined)
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::Context;
+use comms::{
+    command, event,
+    transport::client::{CommandWriter, PendingRequests},
+};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+    },
+    task::JoinHandle,
+    time::Instant,
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+use crate::connection::{self, ServerHandle};
+
+/// How long a request may wait for the server to answer it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many events are kept for the subscribers which are slower than the server
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// [Client] is a connection to the chat server, kept alive by a background task
+///
+/// The task answers the pings of the server and reconnects when the connection drops, resuming the session
+/// or logging in again with the same credentials and joining the same rooms. The task stops with the [Client].
+///
+/// ```no_run
+/// # async fn bot() -> anyhow::Result<()> {
+/// use tokio_stream::StreamExt;
+///
+/// let client = client::Client::connect("localhost:8080").await?;
+/// client.login("echo_bot", "secret").await?;
+/// client.join("general").await?;
+///
+/// let mut messages = client.messages();
+/// while let Some(message) = messages.next().await {
+///     if message.user_id != "echo_bot" {
+///         client.send_message(&message.room, &message.content).await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client {
+    command_tx: UnboundedSender<command::CommandRequest>,
+    /// Never read, only kept to subscribe to the events
+    event_rx: broadcast::Receiver<event::Event>,
+    requests: PendingRequests,
+    task: JoinHandle<()>,
+}
+
+impl Client {
+    /// Connects to the server at the given address, prefixed with `tls://` to connect over TLS
+    pub async fn connect(addr: &str) -> anyhow::Result<Self> {
+        let server_handle = connection::connect(addr)
+            .await
+            .with_context(|| format!("could not connect to {}", addr))?;
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = broadcast::channel(EVENT_BUFFER_SIZE);
+        let requests = PendingRequests::new();
+
+        let connection = Connection {
+            addr: String::from(addr),
+            event_tx,
+            requests: requests.clone(),
+            session: Session::default(),
+        };
+        let task = tokio::spawn(connection.run(server_handle, command_rx));
+
+        Ok(Client {
+            command_tx,
+            event_rx,
+            requests,
+            task,
+        })
+    }
+
+    /// Logs in with the username and password, registering the username if nobody has taken it yet
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> anyhow::Result<event::LoginSuccessfulReplyEvent> {
+        // subscribed before sending the command, so the answer can not be missed
+        let mut events = self.events();
+        self.send(command::UserCommand::Login(command::LoginCommand {
+            username: String::from(username),
+            password: String::from(password),
+        }))?;
+
+        let answer = async {
+            while let Some(event) = events.next().await {
+                match event {
+                    event::Event::LoginSuccessful(event) => return Ok(event),
+                    event::Event::LoginResult(event) if !event.is_accepted => {
+                        return Err(anyhow::anyhow!(
+                            "the login was rejected: {}",
+                            event.reason.unwrap_or_default()
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+
+            Err(anyhow::anyhow!("the connection closed before logging in"))
+        };
+
+        tokio::time::timeout(REQUEST_TIMEOUT, answer)
+            .await
+            .context("the server did not answer the login")?
+    }
+
+    /// Sends the command without waiting for the server
+    pub fn send(&self, command: command::UserCommand) -> anyhow::Result<()> {
+        self.command_tx
+            .send(command::CommandRequest {
+                request_id: None,
+                command,
+            })
+            .map_err(|_| anyhow::anyhow!("the connection is closed"))
+    }
+
+    /// Sends the command with a request id, and waits for the server to acknowledge it
+    ///
+    /// Fails with the error the server reported, or if the server does not answer in time.
+    pub async fn request(&self, command: command::UserCommand) -> anyhow::Result<()> {
+        let request_id = self.requests.next_request_id();
+        let outcome_rx = self.requests.register(&request_id);
+
+        if self
+            .command_tx
+            .send(command::CommandRequest {
+                request_id: Some(request_id.clone()),
+                command,
+            })
+            .is_err()
+        {
+            self.requests.forget(&request_id);
+
+            return Err(anyhow::anyhow!("the connection is closed"));
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, outcome_rx).await {
+            Ok(Ok(Ok(()))) => Ok(()),
+            Ok(Ok(Err(error))) => Err(anyhow::anyhow!("{}", error.message)),
+            Ok(Err(_)) => Err(anyhow::anyhow!(
+                "the request was dropped before the server answered it"
+            )),
+            Err(_) => {
+                self.requests.forget(&request_id);
+
+                Err(anyhow::anyhow!("the server did not answer the request"))
+            }
+        }
+    }
+
+    /// Joins the room, the room is joined again after reconnecting
+    pub async fn join(&self, room: &str) -> anyhow::Result<()> {
+        self.request(command::UserCommand::JoinRoom(command::JoinRoomCommand {
+            room: String::from(room),
+        }))
+        .await
+    }
+
+    pub async fn leave(&self, room: &str) -> anyhow::Result<()> {
+        self.request(command::UserCommand::LeaveRoom(command::LeaveRoomCommand {
+            room: String::from(room),
+        }))
+        .await
+    }
+
+    pub async fn send_message(&self, room: &str, content: &str) -> anyhow::Result<()> {
+        self.request(command::UserCommand::SendMessage(
+            command::SendMessageCommand {
+                room: String::from(room),
+                content: String::from(content),
+                reply_to: None,
+            },
+        ))
+        .await
+    }
+
+    /// The events sent by the server from now on, the stream ends once the client gives up on reconnecting
+    ///
+    /// A subscriber which falls too far behind the server skips the events it missed.
+    pub fn events(&self) -> impl Stream<Item = event::Event> + Unpin {
+        BroadcastStream::new(self.event_rx.resubscribe()).filter_map(Result::ok)
+    }
+
+    /// The messages sent to the rooms the client has joined from now on, including its own
+    pub fn messages(&self) -> impl Stream<Item = event::UserMessageBroadcastEvent> + Unpin {
+        self.events().filter_map(|event| match event {
+            event::Event::UserMessage(message) => Some(message),
+            _ => None,
+        })
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// What the connection remembers to get back into the session after reconnecting
+#[derive(Debug, Default)]
+struct Session {
+    /// The credentials of the last login, to log in again when the session can not be resumed
+    credentials: Option<(String, String)>,
+    resume_token: Option<String>,
+    joined_rooms: BTreeSet<String>,
+    /// Are the joined rooms to be joined again once logged in
+    is_rejoin_pending: bool,
+}
+
+impl Session {
+    fn track_command(&mut self, command: &command::UserCommand) {
+        match command {
+            command::UserCommand::Login(command) => {
+                self.credentials = Some((command.username.clone(), command.password.clone()));
+            }
+            command::UserCommand::LeaveRoom(command) => {
+                self.joined_rooms.remove(&command.room);
+            }
+            _ => (),
+        }
+    }
+
+    fn login_command(&self) -> Option<command::UserCommand> {
+        self.credentials.as_ref().map(|(username, password)| {
+            command::UserCommand::Login(command::LoginCommand {
+                username: username.clone(),
+                password: password.clone(),
+            })
+        })
+    }
+}
+
+/// The background task of a [Client], which owns the connection to the server
+struct Connection {
+    addr: String,
+    event_tx: broadcast::Sender<event::Event>,
+    requests: PendingRequests,
+    session: Session,
+}
+
+impl Connection {
+    async fn run(
+        mut self,
+        mut server_handle: ServerHandle,
+        mut command_rx: UnboundedReceiver<command::CommandRequest>,
+    ) {
+        loop {
+            match self.serve(&mut server_handle, &mut command_rx).await {
+                // the client is dropped
+                Ok(()) => return,
+                Err(_) => match self.reconnect().await {
+                    Some(reconnected) => server_handle = reconnected,
+                    None => return,
+                },
+            }
+        }
+    }
+
+    /// Passes the events and the commands along until the connection drops, or until the client is dropped
+    async fn serve(
+        &mut self,
+        (event_stream, command_writer): &mut ServerHandle,
+        command_rx: &mut UnboundedReceiver<command::CommandRequest>,
+    ) -> anyhow::Result<()> {
+        let mut heartbeat_interval = None;
+        let mut stalled_at: Option<Instant> = None;
+
+        loop {
+            let stall = async {
+                match stalled_at {
+                    Some(stalled_at) => tokio::time::sleep_until(stalled_at).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                maybe_event = event_stream.next() => {
+                    let event = maybe_event.context("the server closed the connection")??;
+
+                    match &event {
+                        event::Event::Welcome(event) => {
+                            heartbeat_interval = event.heartbeat_interval.map(Duration::from_millis);
+                        }
+                        event::Event::Ping(ping) => {
+                            command_writer
+                                .write(&command::UserCommand::Pong(command::PongCommand { nonce: ping.nonce }))
+                                .await?;
+                        }
+                        _ => self.track_event(command_writer, &event).await?,
+                    }
+                    if matches!(event, event::Event::Welcome(_) | event::Event::Ping(_)) {
+                        stalled_at = heartbeat_interval
+                            .map(|interval| Instant::now() + interval * connection::MISSED_PINGS_BEFORE_STALLED);
+                    }
+
+                    self.requests.resolve(&event);
+                    // nobody may be subscribed to the events
+                    let _ = self.event_tx.send(event);
+                }
+                maybe_request = command_rx.recv() => {
+                    let Some(request) = maybe_request else {
+                        return Ok(());
+                    };
+
+                    self.session.track_command(&request.command);
+                    command_writer.write_request(&request).await?;
+                }
+                _ = stall => return Err(anyhow::anyhow!("the server stopped pinging")),
+            }
+        }
+    }
+
+    async fn track_event(
+        &mut self,
+        command_writer: &mut CommandWriter,
+        event: &event::Event,
+    ) -> anyhow::Result<()> {
+        match event {
+            event::Event::LoginSuccessful(event) => {
+                self.session.resume_token = event.resume_token.clone();
+
+                if std::mem::take(&mut self.session.is_rejoin_pending) {
+                    for room in self.session.joined_rooms.iter() {
+                        command_writer
+                            .write(&command::UserCommand::JoinRoom(command::JoinRoomCommand {
+                                room: room.clone(),
+                            }))
+                            .await?;
+                    }
+                }
+            }
+            event::Event::UserJoinedRoom(event) => {
+                self.session.joined_rooms.insert(event.room.clone());
+            }
+            event::Event::ResumeResult(event) if !event.is_accepted => {
+                self.session.resume_token = None;
+
+                if let Some(login_command) = self.session.login_command() {
+                    self.session.is_rejoin_pending = true;
+                    command_writer.write(&login_command).await?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Reconnects with an exponential backoff, resuming the session if the server gave a token for it,
+    /// otherwise logging in again
+    ///
+    /// None once every attempt has failed.
+    async fn reconnect(&mut self) -> Option<ServerHandle> {
+        for attempt in 1..=connection::MAX_RECONNECT_ATTEMPTS {
+            tokio::time::sleep(connection::reconnect_delay(attempt)).await;
+
+            let resume_token = self.session.resume_token.clone();
+            let result = tokio::time::timeout(connection::RECONNECT_TIMEOUT, async {
+                match resume_token {
+                    Some(resume_token) => connection::resume(&self.addr, resume_token).await,
+                    None => {
+                        let (event_stream, mut command_writer) =
+                            connection::connect(&self.addr).await?;
+
+                        if let Some(login_command) = self.session.login_command() {
+                            command_writer.write(&login_command).await?;
+                        }
+
+                        Ok((event_stream, command_writer))
+                    }
+                }
+            })
+            .await;
+
+            if let Ok(Ok(server_handle)) = result {
+                self.session.is_rejoin_pending = self.session.resume_token.is_none();
+
+                return Some(server_handle);
+            }
+        }
+
+        None
+    }
+}
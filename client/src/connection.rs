@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use comms::{
+    command, protocol,
+    transport::{
+        self,
+        client::{CommandWriter, EventStream},
+    },
+};
+use rand::Rng;
+use tokio::net::TcpStream;
+
+use crate::tls;
+
+/// The delay before the first attempt to reconnect, doubled on each failed attempt up to the max delay
+pub const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// How long an attempt to reconnect may take before it counts as failed
+pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many times to try to reconnect before giving up on the server
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+/// How many pings of the server can be missed before the connection is considered stalled
+pub const MISSED_PINGS_BEFORE_STALLED: u32 = 3;
+
+/// The events read from the server along with the writer of the commands sent to it
+pub type ServerHandle = (EventStream, CommandWriter);
+
+/// Connects to the server and announces the protocol version of the client
+///
+/// The address is connected to over TLS when prefixed with `tls://`, as in `tls://localhost:8443`.
+pub async fn connect(addr: &str) -> anyhow::Result<ServerHandle> {
+    let (event_stream, mut command_writer) = match addr.strip_prefix(tls::TLS_SCHEME) {
+        Some(addr) => transport::client::split_stream(tls::connect(addr).await?),
+        None => transport::client::split_tcp_stream(TcpStream::connect(addr).await?),
+    };
+
+    // announce the protocol version, otherwise the server falls back to the v1 protocol
+    command_writer
+        .write(&command::UserCommand::Hello(command::HelloCommand {
+            protocol_version: protocol::PROTOCOL_VERSION,
+        }))
+        .await?;
+
+    Ok((event_stream, command_writer))
+}
+
+/// Reconnects to the server and asks it to resume the dropped session, instead of logging in again
+pub async fn resume(addr: &str, resume_token: String) -> anyhow::Result<ServerHandle> {
+    let (event_stream, mut command_writer) = connect(addr).await?;
+
+    command_writer
+        .write(&command::UserCommand::ResumeSession(
+            command::ResumeSessionCommand {
+                token: resume_token,
+            },
+        ))
+        .await?;
+
+    Ok((event_stream, command_writer))
+}
+
+/// Returns the delay before the given attempt of a retried task, doubling the base delay on each attempt up to the max delay
+pub fn backoff_delay(attempt: u32, base: Duration, max: Duration) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(max)
+}
+
+/// Returns the delay before the given attempt to reconnect, with a jitter so the clients do not reconnect all at once
+pub fn reconnect_delay(attempt: u32) -> Duration {
+    backoff_delay(attempt, RECONNECT_BASE_DELAY, RECONNECT_MAX_DELAY)
+        .mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_backoff_delay_doubles_up_to_the_max() {
+        let max = SECOND * 10;
+
+        assert_eq!(backoff_delay(1, SECOND, max), SECOND);
+        assert_eq!(backoff_delay(2, SECOND, max), SECOND * 2);
+        assert_eq!(backoff_delay(4, SECOND, max), SECOND * 8);
+        assert_eq!(backoff_delay(5, SECOND, max), max);
+        assert_eq!(backoff_delay(100, SECOND, max), max);
+    }
+
+    #[test]
+    fn test_reconnect_delay_is_jittered_below_the_backoff() {
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            let backoff = backoff_delay(attempt, RECONNECT_BASE_DELAY, RECONNECT_MAX_DELAY);
+            let delay = reconnect_delay(attempt);
+
+            assert!(delay <= backoff && delay >= backoff / 2);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! A headless client of the chat server, to write bots and integration tests without the TUI
+//!
+//! [Client] keeps the connection to the server in a background task, which answers the pings of the server
+//! and reconnects with an exponential backoff when the connection drops, resuming the session or logging in again.
+//! The lower level [connection] functions connect to the server without any of that.
+
+mod client;
+pub mod connection;
+mod tls;
+
+pub use client::Client;
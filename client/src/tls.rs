@@ -0,0 +1,64 @@
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use anyhow::Context;
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+    TlsConnector,
+};
+
+/// The prefix of the server addresses which are connected to over TLS
+pub const TLS_SCHEME: &str = "tls://";
+/// Environment variable with the path of a PEM certificate to trust along with the public ones, such as a self-signed one
+const TLS_CA_CERT_ENV: &str = "CHAT_TLS_CA_CERT";
+
+/// Trusts the public certificate authorities, and the certificate given by the environment variable if any
+fn root_cert_store() -> anyhow::Result<RootCertStore> {
+    let mut root_cert_store = RootCertStore::empty();
+    root_cert_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|trust_anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            trust_anchor.subject,
+            trust_anchor.spki,
+            trust_anchor.name_constraints,
+        )
+    }));
+
+    if let Ok(path) = std::env::var(TLS_CA_CERT_ENV) {
+        let mut reader =
+            BufReader::new(File::open(&path).with_context(|| format!("could not open {}", path))?);
+
+        for certificate in rustls_pemfile::certs(&mut reader)
+            .with_context(|| format!("could not read the certificates in {}", path))?
+        {
+            root_cert_store
+                .add(&Certificate(certificate))
+                .with_context(|| format!("could not trust the certificate in {}", path))?;
+        }
+    }
+
+    Ok(root_cert_store)
+}
+
+/// Connects to the server at the given host and port over TLS, verifying its certificate against the host
+pub async fn connect(addr: &str) -> anyhow::Result<TlsStream<TcpStream>> {
+    let host = addr
+        .rsplit_once(':')
+        .map(|(host, _)| host)
+        .unwrap_or(addr)
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let server_name = ServerName::try_from(host)
+        .with_context(|| format!("'{}' is not a valid host name", host))?;
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_cert_store()?)
+        .with_no_client_auth();
+    let stream = TcpStream::connect(addr).await?;
+
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await
+        .context("the TLS handshake failed")
+}
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use client::Client;
+use comms::{
+    command::{self, CommandRequest, UserCommand},
+    event::{self, Event},
+    transport::server::{self, CommandStream, EventWriter},
+};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn accept(listener: &TcpListener) -> (CommandStream, EventWriter) {
+    let (stream, _) = listener.accept().await.unwrap();
+
+    server::split_tcp_stream(stream)
+}
+
+async fn next_request(command_stream: &mut CommandStream) -> CommandRequest {
+    tokio::time::timeout(TIMEOUT, command_stream.next())
+        .await
+        .expect("the client sent no command")
+        .unwrap()
+        .unwrap()
+}
+
+/// Answers the hello of the client, and its login if it logs in
+async fn greet(command_stream: &mut CommandStream, event_writer: &mut EventWriter) -> UserCommand {
+    assert!(matches!(
+        next_request(command_stream).await.command,
+        UserCommand::Hello(_)
+    ));
+    event_writer
+        .write(&Event::Welcome(event::WelcomeReplyEvent {
+            protocol_version: comms::protocol::PROTOCOL_VERSION,
+            max_message_chars: 1000,
+            features: vec![],
+            heartbeat_interval: None,
+        }))
+        .await
+        .unwrap();
+
+    let request = next_request(command_stream).await;
+    if let UserCommand::Login(login) = &request.command {
+        event_writer
+            .write(&Event::LoginSuccessful(event::LoginSuccessfulReplyEvent {
+                session_id: String::from("session"),
+                user_id: login.username.clone(),
+                rooms: vec![],
+                spaces: vec![],
+                resume_token: Some(String::from("token")),
+            }))
+            .await
+            .unwrap();
+    }
+
+    request.command
+}
+
+async fn ack(event_writer: &mut EventWriter, request: &CommandRequest) {
+    event_writer
+        .write(&Event::CommandAck(event::CommandAckEvent {
+            request_id: request.request_id.clone().unwrap(),
+        }))
+        .await
+        .unwrap();
+}
+
+async fn answer_join(
+    command_stream: &mut CommandStream,
+    event_writer: &mut EventWriter,
+) -> CommandRequest {
+    let request = next_request(command_stream).await;
+    let UserCommand::JoinRoom(join) = &request.command else {
+        panic!("expected a join, got {:?}", request.command);
+    };
+
+    event_writer
+        .write(&Event::UserJoinedRoom(event::UserJoinedRoomReplyEvent {
+            room: join.room.clone(),
+            users: vec![],
+            role: event::RoomRole::Member,
+        }))
+        .await
+        .unwrap();
+    if request.request_id.is_some() {
+        ack(event_writer, &request).await;
+    }
+
+    request
+}
+
+#[tokio::test]
+async fn test_bot_joins_and_receives_its_messages() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let server = tokio::spawn(async move {
+        let (mut command_stream, mut event_writer) = accept(&listener).await;
+        greet(&mut command_stream, &mut event_writer).await;
+        answer_join(&mut command_stream, &mut event_writer).await;
+
+        // echoes the message to the room
+        let request = next_request(&mut command_stream).await;
+        let UserCommand::SendMessage(message) = &request.command else {
+            panic!("expected a message, got {:?}", request.command);
+        };
+        event_writer
+            .write(&Event::UserMessage(event::UserMessageBroadcastEvent {
+                room: message.room.clone(),
+                id: 1,
+                user_id: String::from("bot"),
+                content: message.content.clone(),
+                timestamp: 0,
+                reply_to: None,
+            }))
+            .await
+            .unwrap();
+        ack(&mut event_writer, &request).await;
+
+        // keeps the connection open until the client is done
+        command_stream.next().await;
+    });
+
+    let client = Client::connect(&addr).await.unwrap();
+    let login = client.login("bot", "secret").await.unwrap();
+    assert_eq!(login.user_id, "bot");
+
+    client.join("general").await.unwrap();
+    let mut messages = client.messages();
+    client.send_message("general", "beep").await.unwrap();
+
+    let message = tokio::time::timeout(TIMEOUT, messages.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message.room, "general");
+    assert_eq!(message.content, "beep");
+
+    drop(client);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_logs_in_again_and_rejoins_after_reconnecting() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let server = tokio::spawn(async move {
+        {
+            let (mut command_stream, mut event_writer) = accept(&listener).await;
+            greet(&mut command_stream, &mut event_writer).await;
+            answer_join(&mut command_stream, &mut event_writer).await;
+            // the connection drops here
+        }
+
+        let (mut command_stream, mut event_writer) = accept(&listener).await;
+        let UserCommand::ResumeSession(resume) =
+            greet(&mut command_stream, &mut event_writer).await
+        else {
+            panic!("expected the session to be resumed");
+        };
+        assert_eq!(resume.token, "token");
+
+        // the server has forgotten the session
+        event_writer
+            .write(&Event::ResumeResult(event::ResumeResultReplyEvent {
+                is_accepted: false,
+                reason: Some(String::from("unknown session")),
+            }))
+            .await
+            .unwrap();
+        let UserCommand::Login(login) = next_request(&mut command_stream).await.command else {
+            panic!("expected to log in again");
+        };
+        assert_eq!(
+            (login.username.as_str(), login.password.as_str()),
+            ("bot", "secret")
+        );
+        event_writer
+            .write(&Event::LoginSuccessful(event::LoginSuccessfulReplyEvent {
+                session_id: String::from("session"),
+                user_id: login.username,
+                rooms: vec![],
+                spaces: vec![],
+                resume_token: Some(String::from("token")),
+            }))
+            .await
+            .unwrap();
+
+        let rejoin = answer_join(&mut command_stream, &mut event_writer).await;
+        assert_eq!(
+            rejoin.command,
+            UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: String::from("general")
+            })
+        );
+
+        command_stream.next().await;
+    });
+
+    let client = Client::connect(&addr).await.unwrap();
+    client.login("bot", "secret").await.unwrap();
+    let mut events = client.events();
+    client.join("general").await.unwrap();
+
+    // joined a first time, then joined again once reconnected
+    for _ in 0..2 {
+        tokio::time::timeout(TIMEOUT, async {
+            while !matches!(events.next().await, Some(Event::UserJoinedRoom(_))) {}
+        })
+        .await
+        .unwrap();
+    }
+
+    drop(client);
+    server.await.unwrap();
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+
+use crate::event::RoomRole;
+
+/// A request of the admin console, written as a single line of JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum AdminRequest {
+    /// Lists the connected sessions
+    ListSessions,
+    /// Describes a room, along with its users
+    InspectRoom {
+        room: String,
+    },
+    /// Disconnects every session of the user, who can log in again
+    KickUser {
+        username: String,
+        reason: String,
+    },
+    /// Sends the text to every connected session
+    Announce {
+        text: String,
+    },
+    Stats,
+    /// Exports the stored messages of a room sent within the given range, answered with chunks of lines
+    /// before a final response
+    ExportRoom {
+        room: String,
+        format: ExportFormat,
+        /// The oldest messages exported, in milliseconds since the epoch
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since: Option<u64>,
+        /// The messages sent from this time on are left out, in milliseconds since the epoch
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        until: Option<u64>,
+    },
+}
+
+/// How the exported messages are written, one per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A JSON object per message
+    Json,
+    /// The time, the author and the content of each message, as a person reads them
+    Text,
+}
+
+/// A connected session, as listed to the admin console
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminSession {
+    pub session_id: String,
+    pub username: String,
+    /// When the session was connected, in milliseconds since the epoch
+    pub connected_at: u64,
+}
+
+/// A room, as described to the admin console
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminRoom {
+    pub name: String,
+    pub description: String,
+    pub is_private: bool,
+    /// The users in the room, sorted by name
+    pub users: Vec<String>,
+    /// The owner and the moderators of the room, whether they are in the room or not
+    pub roles: Vec<(String, RoomRole)>,
+    /// How many messages were sent to the room since the server started
+    pub messages_sent: u64,
+}
+
+/// The counters of the server, as dumped to the admin console
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminStats {
+    pub uptime_secs: u64,
+    /// The connected sessions by protocol version
+    pub sessions: Vec<(u16, usize)>,
+    /// The sessions of the dropped connections, kept for their clients to resume them
+    pub dropped_sessions: usize,
+    pub rooms: usize,
+    /// How many messages were sent to the rooms since the server started
+    pub messages_sent: u64,
+}
+
+/// The response to a request of the admin console, written as a single line of JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Sessions {
+        sessions: Vec<AdminSession>,
+    },
+    Room(AdminRoom),
+    Stats(AdminStats),
+    /// A part of an export, followed by more of them until the final response
+    ExportChunk {
+        lines: Vec<String>,
+    },
+    /// The request has been carried out, as described
+    Done {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_tagged() {
+        let request = AdminRequest::KickUser {
+            username: "alice".to_string(),
+            reason: "spamming".to_string(),
+        };
+        let serialized = serde_json::to_string(&request).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"request":"kick_user","username":"alice","reason":"spamming"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<AdminRequest>(&serialized).unwrap(),
+            request
+        );
+        assert_eq!(
+            serde_json::from_str::<AdminRequest>(r#"{"request":"stats"}"#).unwrap(),
+            AdminRequest::Stats
+        );
+    }
+
+    #[test]
+    fn test_export_range_is_optional() {
+        let request = AdminRequest::ExportRoom {
+            room: "general".to_string(),
+            format: ExportFormat::Text,
+            since: Some(1000),
+            until: None,
+        };
+        let serialized = serde_json::to_string(&request).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"request":"export_room","room":"general","format":"text","since":1000}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<AdminRequest>(&serialized).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_responses_are_tagged() {
+        let response = AdminResponse::Stats(AdminStats {
+            uptime_secs: 60,
+            sessions: vec![(5, 2)],
+            dropped_sessions: 0,
+            rooms: 1,
+            messages_sent: 3,
+        });
+        let serialized = serde_json::to_string(&response).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"response":"stats","uptime_secs":60,"sessions":[[5,2]],"dropped_sessions":0,"rooms":1,"messages_sent":3}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<AdminResponse>(&serialized).unwrap(),
+            response
+        );
+    }
+}
@@ -0,0 +1,36 @@
+/// Reads the value following a command line flag, as in `--flag value`
+pub fn flag(name: &str) -> Option<String> {
+    flag_in(std::env::args().skip(1), name)
+}
+
+fn flag_in(mut args: impl Iterator<Item = String>, name: &str) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| String::from(*arg))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_the_value_following_the_flag_is_read() {
+        assert_eq!(
+            flag_in(args(&["--users", "10", "--rooms", "general"]), "--rooms"),
+            Some(String::from("general"))
+        );
+        assert_eq!(flag_in(args(&["--users", "10"]), "--rooms"), None);
+        assert_eq!(flag_in(args(&["--rooms"]), "--rooms"), None);
+    }
+}
@@ -1,5 +1,37 @@
 use serde::{Deserialize, Serialize};
 
+use crate::event::{RoomRole, SpaceRole};
+
+/// User Command for announcing the protocol version of the client.
+/// Must be the first command sent after connecting, clients which do not send it are served the v1 protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelloCommand {
+    // The protocol version the client speaks.
+    #[serde(rename = "v")]
+    pub protocol_version: u16,
+}
+
+/// User Command for logging in, required from v3 clients right after the hello command.
+/// The first login with a username nobody has taken yet registers it with the given password.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginCommand {
+    // The name of the user, which becomes their user id.
+    #[serde(rename = "u")]
+    pub username: String,
+    // The password of the user.
+    #[serde(rename = "p")]
+    pub password: String,
+}
+
+/// User Command for taking over a session dropped without quitting, instead of logging in again.
+/// Sent by v3 clients right after the hello command, with the resume token the login gave.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumeSessionCommand {
+    // The resume token of the dropped session.
+    #[serde(rename = "t")]
+    pub token: String,
+}
+
 /// User Command for joining a room.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct JoinRoomCommand {
@@ -16,6 +48,86 @@ pub struct LeaveRoomCommand {
     pub room: String,
 }
 
+/// User Command for creating a public room, which is listed to every user until its creator deletes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateRoomCommand {
+    // The name of the room to create.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The description of the room.
+    #[serde(rename = "d")]
+    pub description: String,
+}
+
+/// User Command for deleting a room. Only allowed for the creator of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeleteRoomCommand {
+    // The room to delete.
+    #[serde(rename = "r")]
+    pub room: String,
+}
+
+/// User Command for changing the topic of a room, shown as its description. Only allowed for the owner and the moderators of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetRoomTopicCommand {
+    // The room to change the topic of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The new topic of the room.
+    #[serde(rename = "t")]
+    pub topic: String,
+}
+
+/// User Command for kicking a user out of a room, they can join it again. Only allowed for the owner and the moderators of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KickUserCommand {
+    // The room to kick the user out of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user to kick.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for banning a user from a room, they are kicked and can not join it again. Only allowed for the owner and the moderators of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanUserCommand {
+    // The room to ban the user from.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user to ban.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for muting a user in a room, their messages are refused meanwhile. Only allowed for the owner and the moderators of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MuteUserCommand {
+    // The room to mute the user in.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user to mute.
+    #[serde(rename = "u")]
+    pub user_id: String,
+    // How many seconds the user is muted for, zero unmutes the user.
+    #[serde(rename = "s")]
+    pub seconds: u64,
+}
+
+/// User Command for changing the role of a room user. Only allowed for the owner of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetRoomRoleCommand {
+    // The room the user belongs to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user.
+    #[serde(rename = "u")]
+    pub user_id: String,
+    // The new role of the user, a room has a single owner.
+    #[serde(rename = "ro")]
+    pub role: RoomRole,
+}
+
 /// User Command for sending a message.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SendMessageCommand {
@@ -25,6 +137,307 @@ pub struct SendMessageCommand {
     // The content of the message.
     #[serde(rename = "c")]
     pub content: String,
+    // The id of the message in the room this message replies to.
+    #[serde(rename = "rt", default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
+}
+
+/// User Command for editing a message the user has sent to a room. Only allowed for the author of the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditMessageCommand {
+    // The room the message was sent to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the message in the room.
+    #[serde(rename = "i")]
+    pub id: u64,
+    // The new content of the message.
+    #[serde(rename = "c")]
+    pub content: String,
+}
+
+/// User Command for deleting a message the user has sent to a room. Only allowed for the author of the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeleteMessageCommand {
+    // The room the message was sent to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the message in the room.
+    #[serde(rename = "i")]
+    pub id: u64,
+}
+
+/// User Command for reacting to a message of a room with an emoji.
+/// Reacting again with the same emoji takes the reaction back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReactToMessageCommand {
+    // The room the message was sent to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the message in the room.
+    #[serde(rename = "i")]
+    pub id: u64,
+    // The emoji to react with.
+    #[serde(rename = "em")]
+    pub emoji: String,
+}
+
+/// User Command for marking the messages of a joined room as read, up to the given message.
+/// The last read message is sent back along with the history of the room once joined again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarkReadCommand {
+    // The room the message was sent to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the last message read in the room.
+    #[serde(rename = "i")]
+    pub id: u64,
+}
+
+/// User Command for fetching the visible history of a joined room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchRoomHistoryCommand {
+    // The room to fetch the history of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // Fetch the messages around this timestamp instead of the latest ones, in milliseconds since the unix epoch.
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub around: Option<u64>,
+    // Fetch only the last N visible messages, ignored when fetching around a timestamp.
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// User Command for fetching the visible messages of a joined room older than a message, a page at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchMessagesBeforeCommand {
+    // The room to fetch the messages of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // Fetch the messages older than the message with this id.
+    #[serde(rename = "b")]
+    pub before_message_id: u64,
+    // Fetch at most N messages, the server applies its own limit when not given.
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// User Command for searching the stored history of a joined room, the matches are sent back a page at a time.
+/// Only the messages the user is allowed to see by the history visibility of the room are searched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchMessagesCommand {
+    // The room to search the history of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The words the messages should contain, matched from the start of their words regardless of the case.
+    #[serde(rename = "q")]
+    pub query: String,
+    // Return at most N matches, the server applies its own limit when not given.
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    // Continue the search before the cursor of the previous page.
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<u64>,
+}
+
+/// User Command for browsing the directory of the public rooms, which is sent back a page at a time, ordered by name.
+/// The rooms are listed whether the user has joined them or not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrowseRoomsCommand {
+    // Continue the directory after the room of the given name, the cursor of the previous page.
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    // Return at most N rooms, the server applies its own limit when not given.
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// User Command for exporting the full history of a joined room, which is streamed back in chunks.
+/// Only allowed in the rooms which permit exporting their history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRoomHistoryCommand {
+    // The room to export the history of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // Resume the export after the cursor of the last received chunk.
+    #[serde(rename = "af", default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<u64>,
+}
+
+/// User Command for starting the upload of a file to a joined room, its content follows in chunks.
+/// The file is shared with the room once all of its content is received and matches its checksum.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartUploadCommand {
+    // The id the client picked for the upload, which the chunks and the replies refer to.
+    #[serde(rename = "u")]
+    pub upload_id: String,
+    // The room to share the file with.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The name of the file, without any directory.
+    #[serde(rename = "n")]
+    pub name: String,
+    // The size of the file in bytes, at most the limit of the protocol.
+    #[serde(rename = "sz")]
+    pub size: u64,
+    // The checksum of the content of the file.
+    #[serde(rename = "cs")]
+    pub checksum: String,
+}
+
+/// User Command for sending the next chunk of the content of a started upload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadChunkCommand {
+    // The id of the upload the chunk belongs to.
+    #[serde(rename = "u")]
+    pub upload_id: String,
+    // The offset of the chunk in the file, which is the number of bytes received so far.
+    #[serde(rename = "o")]
+    pub offset: u64,
+    // The base64 encoded content of the chunk.
+    #[serde(rename = "d")]
+    pub data: String,
+}
+
+/// User Command for downloading a chunk of a file shared with a joined room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadFileCommand {
+    // The id of the shared file.
+    #[serde(rename = "f")]
+    pub file_id: String,
+    // The offset of the chunk to download, which is the number of bytes received so far.
+    #[serde(rename = "o")]
+    pub offset: u64,
+}
+
+/// User Command for joining a space, which also joins its default rooms.
+/// The first member of a space becomes its admin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JoinSpaceCommand {
+    // The space to join.
+    #[serde(rename = "s")]
+    pub space: String,
+}
+
+/// User Command for leaving a space, which also leaves its rooms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaveSpaceCommand {
+    // The space to leave.
+    #[serde(rename = "s")]
+    pub space: String,
+}
+
+/// User Command for changing the role of a space member. Only allowed for the admins of the space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetSpaceRoleCommand {
+    // The space the member belongs to.
+    #[serde(rename = "s")]
+    pub space: String,
+    // The id of the member.
+    #[serde(rename = "u")]
+    pub user_id: String,
+    // The new role of the member.
+    #[serde(rename = "ro")]
+    pub role: SpaceRole,
+}
+
+/// User Command for removing a member from a space, along with its rooms. Only allowed for the admins of the space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoveSpaceMemberCommand {
+    // The space to remove the member from.
+    #[serde(rename = "s")]
+    pub space: String,
+    // The id of the member.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for sending a direct message to another user, who has to be online.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendDirectMessageCommand {
+    // The id of the user to send the message to.
+    #[serde(rename = "u")]
+    pub user_id: String,
+    // The content of the message.
+    #[serde(rename = "c")]
+    pub content: String,
+}
+
+/// User Command for inviting another user to a private room. Only allowed for the members of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InviteUserCommand {
+    // The private room to invite the user to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user to invite.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for declining an invitation to a private room. Invitations are accepted by joining the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeclineInvitationCommand {
+    // The room the user was invited to.
+    #[serde(rename = "r")]
+    pub room: String,
+}
+
+/// User Command for going away with a status message, or for coming back without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetPresenceCommand {
+    // The status message shown to the other users while away.
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub away_message: Option<String>,
+}
+
+/// User Command for changing the name the user is shown with, or for going back to their username without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeNicknameCommand {
+    // The name shown in place of the username of the user.
+    #[serde(rename = "n", default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+/// User Command for asking the server for the profile of a user.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetUserProfileCommand {
+    // The id of the user whose profile is asked for.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for changing the profile of the user. The fields left out are kept, the empty ones are cleared.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpdateProfileCommand {
+    // A few words about the user.
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    // The pronouns the user goes by, such as `they/them`.
+    #[serde(rename = "p", default, skip_serializing_if = "Option::is_none")]
+    pub pronouns: Option<String>,
+    // The timezone of the user, such as `Europe/Paris`.
+    #[serde(rename = "tz", default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    // The color the name of the user is shown with, as `#rrggbb`.
+    #[serde(rename = "cl", default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// User Command for exporting all the data the server stores about the user.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportMyDataCommand;
+
+/// User Command for deleting the account of the user, which also ends the chat session.
+/// Messages of the user are anonymized once the grace period of the server has passed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeleteMyAccountCommand;
+
+/// User Command for answering a ping of the server, to show the connection is still alive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PongCommand {
+    // The nonce of the ping being answered.
+    #[serde(rename = "n")]
+    pub nonce: u64,
 }
 
 /// User Command for quitting the whole chat session.
@@ -36,16 +449,234 @@ pub struct QuitCommand;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "_ct", rename_all = "snake_case")]
 pub enum UserCommand {
+    Hello(HelloCommand),
+    Login(LoginCommand),
+    ResumeSession(ResumeSessionCommand),
     JoinRoom(JoinRoomCommand),
     LeaveRoom(LeaveRoomCommand),
+    CreateRoom(CreateRoomCommand),
+    DeleteRoom(DeleteRoomCommand),
+    SetRoomTopic(SetRoomTopicCommand),
+    SetRoomRole(SetRoomRoleCommand),
+    KickUser(KickUserCommand),
+    BanUser(BanUserCommand),
+    MuteUser(MuteUserCommand),
     SendMessage(SendMessageCommand),
+    EditMessage(EditMessageCommand),
+    DeleteMessage(DeleteMessageCommand),
+    ReactToMessage(ReactToMessageCommand),
+    MarkRead(MarkReadCommand),
+    FetchRoomHistory(FetchRoomHistoryCommand),
+    FetchMessagesBefore(FetchMessagesBeforeCommand),
+    ExportRoomHistory(ExportRoomHistoryCommand),
+    SearchMessages(SearchMessagesCommand),
+    BrowseRooms(BrowseRoomsCommand),
+    StartUpload(StartUploadCommand),
+    UploadChunk(UploadChunkCommand),
+    DownloadFile(DownloadFileCommand),
+    JoinSpace(JoinSpaceCommand),
+    LeaveSpace(LeaveSpaceCommand),
+    SetSpaceRole(SetSpaceRoleCommand),
+    RemoveSpaceMember(RemoveSpaceMemberCommand),
+    SendDirectMessage(SendDirectMessageCommand),
+    InviteUser(InviteUserCommand),
+    DeclineInvitation(DeclineInvitationCommand),
+    SetPresence(SetPresenceCommand),
+    ChangeNickname(ChangeNicknameCommand),
+    GetUserProfile(GetUserProfileCommand),
+    UpdateProfile(UpdateProfileCommand),
+    ExportMyData(ExportMyDataCommand),
+    DeleteMyAccount(DeleteMyAccountCommand),
+    Pong(PongCommand),
     Quit(QuitCommand),
 }
 
+impl UserCommand {
+    /// The name of the command, as in its `_ct` tag, such as `send_message`
+    pub fn name(&self) -> &'static str {
+        match self {
+            UserCommand::Hello(_) => "hello",
+            UserCommand::Login(_) => "login",
+            UserCommand::ResumeSession(_) => "resume_session",
+            UserCommand::JoinRoom(_) => "join_room",
+            UserCommand::LeaveRoom(_) => "leave_room",
+            UserCommand::CreateRoom(_) => "create_room",
+            UserCommand::DeleteRoom(_) => "delete_room",
+            UserCommand::SetRoomTopic(_) => "set_room_topic",
+            UserCommand::SetRoomRole(_) => "set_room_role",
+            UserCommand::KickUser(_) => "kick_user",
+            UserCommand::BanUser(_) => "ban_user",
+            UserCommand::MuteUser(_) => "mute_user",
+            UserCommand::SendMessage(_) => "send_message",
+            UserCommand::EditMessage(_) => "edit_message",
+            UserCommand::DeleteMessage(_) => "delete_message",
+            UserCommand::ReactToMessage(_) => "react_to_message",
+            UserCommand::MarkRead(_) => "mark_read",
+            UserCommand::FetchRoomHistory(_) => "fetch_room_history",
+            UserCommand::FetchMessagesBefore(_) => "fetch_messages_before",
+            UserCommand::ExportRoomHistory(_) => "export_room_history",
+            UserCommand::SearchMessages(_) => "search_messages",
+            UserCommand::BrowseRooms(_) => "browse_rooms",
+            UserCommand::StartUpload(_) => "start_upload",
+            UserCommand::UploadChunk(_) => "upload_chunk",
+            UserCommand::DownloadFile(_) => "download_file",
+            UserCommand::JoinSpace(_) => "join_space",
+            UserCommand::LeaveSpace(_) => "leave_space",
+            UserCommand::SetSpaceRole(_) => "set_space_role",
+            UserCommand::RemoveSpaceMember(_) => "remove_space_member",
+            UserCommand::SendDirectMessage(_) => "send_direct_message",
+            UserCommand::InviteUser(_) => "invite_user",
+            UserCommand::DeclineInvitation(_) => "decline_invitation",
+            UserCommand::SetPresence(_) => "set_presence",
+            UserCommand::ChangeNickname(_) => "change_nickname",
+            UserCommand::GetUserProfile(_) => "get_user_profile",
+            UserCommand::UpdateProfile(_) => "update_profile",
+            UserCommand::ExportMyData(_) => "export_my_data",
+            UserCommand::DeleteMyAccount(_) => "delete_my_account",
+            UserCommand::Pong(_) => "pong",
+            UserCommand::Quit(_) => "quit",
+        }
+    }
+}
+
+/// A [UserCommand] along with the id of the request it is sent with, if any.
+/// Commands sent with a request id are answered with an acknowledgement or an error carrying the same id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRequest {
+    // The id the client correlates the answer with, unique among the requests of the client.
+    #[serde(rename = "rid", default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub command: UserCommand,
+}
+
+impl From<UserCommand> for CommandRequest {
+    fn from(command: UserCommand) -> Self {
+        CommandRequest {
+            request_id: None,
+            command,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+    use serde_json::Value;
+
     use super::*;
 
+    /// The tags of the commands, see [UserCommand::name]
+    const COMMAND_NAMES: &[&str] = &[
+        "hello",
+        "login",
+        "resume_session",
+        "join_room",
+        "leave_room",
+        "create_room",
+        "delete_room",
+        "set_room_topic",
+        "set_room_role",
+        "kick_user",
+        "ban_user",
+        "mute_user",
+        "send_message",
+        "edit_message",
+        "delete_message",
+        "react_to_message",
+        "mark_read",
+        "fetch_room_history",
+        "fetch_messages_before",
+        "export_room_history",
+        "search_messages",
+        "browse_rooms",
+        "start_upload",
+        "upload_chunk",
+        "download_file",
+        "join_space",
+        "leave_space",
+        "set_space_role",
+        "remove_space_member",
+        "send_direct_message",
+        "invite_user",
+        "decline_invitation",
+        "set_presence",
+        "change_nickname",
+        "get_user_profile",
+        "update_profile",
+        "export_my_data",
+        "delete_my_account",
+        "pong",
+        "quit",
+    ];
+    /// The keys the fields of the commands are renamed to
+    const FIELD_KEYS: &[&str] = &[
+        "a", "af", "b", "c", "cl", "cs", "d", "em", "f", "i", "l", "m", "n", "o", "p", "q", "r",
+        "rid", "ro", "rt", "s", "sz", "t", "tz", "u", "v",
+    ];
+
+    /// Any JSON value, nested a few levels deep
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<u64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".{0,16}".prop_map(Value::from),
+        ];
+
+        leaf.prop_recursive(4, 64, 8, |value| {
+            prop_oneof![
+                prop::collection::vec(value.clone(), 0..8).prop_map(Value::Array),
+                prop::collection::btree_map(".{0,4}", value, 0..8)
+                    .prop_map(|fields| Value::Object(fields.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        /// A command of a known kind with fields of any shape is read, or rejected, without panicking
+        #[test]
+        fn test_commands_with_garbage_fields_are_read_without_panicking(
+            name in prop::sample::select(COMMAND_NAMES),
+            fields in prop::collection::btree_map(prop::sample::select(FIELD_KEYS), json_value(), 0..6),
+        ) {
+            let mut command = fields
+                .into_iter()
+                .map(|(key, value)| (String::from(key), value))
+                .collect::<serde_json::Map<_, _>>();
+            command.insert(String::from("_ct"), Value::from(name));
+            let serialized = serde_json::to_vec(&command).unwrap();
+
+            if let Ok(request) = serde_json::from_slice::<CommandRequest>(&serialized) {
+                prop_assert_eq!(request.command.name(), name);
+            }
+        }
+
+        #[test]
+        fn test_messages_round_trip(
+            request_id in proptest::option::of(".*"),
+            room in ".*",
+            content in ".*",
+            reply_to in proptest::option::of(any::<u64>()),
+        ) {
+            let request = CommandRequest {
+                request_id,
+                command: UserCommand::SendMessage(SendMessageCommand {
+                    room,
+                    content,
+                    reply_to,
+                }),
+            };
+            let serialized = serde_json::to_vec(&request).unwrap();
+
+            // a new line would break the framing of the older clients
+            prop_assert!(!serialized.contains(&b'\n'));
+            prop_assert_eq!(serde_json::from_slice::<CommandRequest>(&serialized).unwrap(), request);
+        }
+    }
+
     // given a command enum, and an expect string, asserts that command is serialized / deserialized appropiately
     fn assert_command_serialization(command: &UserCommand, expected: &str) {
         let serialized = serde_json::to_string(&command).unwrap();
@@ -54,6 +685,47 @@ mod tests {
         assert_eq!(deserialized, *command);
     }
 
+    #[test]
+    fn test_command_name_matches_its_tag() {
+        let command = UserCommand::SendMessage(SendMessageCommand {
+            room: String::from("general"),
+            content: String::from("hello"),
+            reply_to: None,
+        });
+        let serialized = serde_json::to_value(&command).unwrap();
+
+        assert_eq!(serialized["_ct"], command.name());
+        assert_eq!(UserCommand::Quit(QuitCommand).name(), "quit");
+    }
+
+    #[test]
+    fn test_hello_command() {
+        let command = UserCommand::Hello(HelloCommand {
+            protocol_version: 2,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"hello","v":2}"#);
+    }
+
+    #[test]
+    fn test_login_command() {
+        let command = UserCommand::Login(LoginCommand {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"login","u":"user","p":"secret"}"#);
+    }
+
+    #[test]
+    fn test_resume_session_command() {
+        let command = UserCommand::ResumeSession(ResumeSessionCommand {
+            token: "token".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"resume_session","t":"token"}"#);
+    }
+
     #[test]
     fn test_join_command() {
         let command = UserCommand::JoinRoom(JoinRoomCommand {
@@ -72,20 +744,466 @@ mod tests {
         assert_command_serialization(&command, r#"{"_ct":"leave_room","r":"test"}"#);
     }
 
+    #[test]
+    fn test_create_room_command() {
+        let command = UserCommand::CreateRoom(CreateRoomCommand {
+            room: "test".to_string(),
+            description: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"create_room","r":"test","d":"test"}"#);
+    }
+
+    #[test]
+    fn test_delete_room_command() {
+        let command = UserCommand::DeleteRoom(DeleteRoomCommand {
+            room: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"delete_room","r":"test"}"#);
+    }
+
+    #[test]
+    fn test_set_room_topic_command() {
+        let command = UserCommand::SetRoomTopic(SetRoomTopicCommand {
+            room: "test".to_string(),
+            topic: "test".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"set_room_topic","r":"test","t":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_set_room_role_command() {
+        let command = UserCommand::SetRoomRole(SetRoomRoleCommand {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            role: RoomRole::Moderator,
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"set_room_role","r":"test","u":"test","ro":"moderator"}"#,
+        );
+    }
+
+    #[test]
+    fn test_kick_user_command() {
+        let command = UserCommand::KickUser(KickUserCommand {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"kick_user","r":"test","u":"test"}"#);
+    }
+
+    #[test]
+    fn test_ban_user_command() {
+        let command = UserCommand::BanUser(BanUserCommand {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"ban_user","r":"test","u":"test"}"#);
+    }
+
+    #[test]
+    fn test_mute_user_command() {
+        let command = UserCommand::MuteUser(MuteUserCommand {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            seconds: 600,
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"mute_user","r":"test","u":"test","s":600}"#,
+        );
+    }
+
     #[test]
     fn test_message_command() {
         let command = UserCommand::SendMessage(SendMessageCommand {
             room: "test".to_string(),
             content: "test".to_string(),
+            reply_to: None,
         });
 
         assert_command_serialization(&command, r#"{"_ct":"send_message","r":"test","c":"test"}"#);
     }
 
+    #[test]
+    fn test_reply_message_command() {
+        let command = UserCommand::SendMessage(SendMessageCommand {
+            room: "test".to_string(),
+            content: "test".to_string(),
+            reply_to: Some(1),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"send_message","r":"test","c":"test","rt":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_edit_message_command() {
+        let command = UserCommand::EditMessage(EditMessageCommand {
+            room: "test".to_string(),
+            id: 1,
+            content: "test".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"edit_message","r":"test","i":1,"c":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_delete_message_command() {
+        let command = UserCommand::DeleteMessage(DeleteMessageCommand {
+            room: "test".to_string(),
+            id: 1,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"delete_message","r":"test","i":1}"#);
+    }
+
+    #[test]
+    fn test_react_to_message_command() {
+        let command = UserCommand::ReactToMessage(ReactToMessageCommand {
+            room: "test".to_string(),
+            id: 1,
+            emoji: "👍".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"react_to_message","r":"test","i":1,"em":"👍"}"#,
+        );
+    }
+
+    #[test]
+    fn test_mark_read_command() {
+        let command = UserCommand::MarkRead(MarkReadCommand {
+            room: "test".to_string(),
+            id: 7,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"mark_read","r":"test","i":7}"#);
+    }
+
+    #[test]
+    fn test_fetch_room_history_command() {
+        let command = UserCommand::FetchRoomHistory(FetchRoomHistoryCommand {
+            room: "test".to_string(),
+            around: None,
+            limit: None,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"fetch_room_history","r":"test"}"#);
+    }
+
+    #[test]
+    fn test_fetch_room_history_limit_command() {
+        let command = UserCommand::FetchRoomHistory(FetchRoomHistoryCommand {
+            room: "test".to_string(),
+            around: None,
+            limit: Some(100),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"fetch_room_history","r":"test","l":100}"#,
+        );
+    }
+
+    #[test]
+    fn test_fetch_room_history_around_command() {
+        let command = UserCommand::FetchRoomHistory(FetchRoomHistoryCommand {
+            room: "test".to_string(),
+            around: Some(1),
+            limit: None,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"fetch_room_history","r":"test","a":1}"#);
+    }
+
+    #[test]
+    fn test_export_room_history_command() {
+        let command = UserCommand::ExportRoomHistory(ExportRoomHistoryCommand {
+            room: "test".to_string(),
+            after: Some(1),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"export_room_history","r":"test","af":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_fetch_messages_before_command() {
+        let command = UserCommand::FetchMessagesBefore(FetchMessagesBeforeCommand {
+            room: "test".to_string(),
+            before_message_id: 42,
+            limit: Some(50),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"fetch_messages_before","r":"test","b":42,"l":50}"#,
+        );
+    }
+
+    #[test]
+    fn test_start_upload_command() {
+        let command = UserCommand::StartUpload(StartUploadCommand {
+            upload_id: "1".to_string(),
+            room: "test".to_string(),
+            name: "notes.txt".to_string(),
+            size: 3,
+            checksum: "abc".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"start_upload","u":"1","r":"test","n":"notes.txt","sz":3,"cs":"abc"}"#,
+        );
+    }
+
+    #[test]
+    fn test_upload_chunk_command() {
+        let command = UserCommand::UploadChunk(UploadChunkCommand {
+            upload_id: "1".to_string(),
+            offset: 0,
+            data: "YWJj".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"upload_chunk","u":"1","o":0,"d":"YWJj"}"#,
+        );
+    }
+
+    #[test]
+    fn test_download_file_command() {
+        let command = UserCommand::DownloadFile(DownloadFileCommand {
+            file_id: "f1".to_string(),
+            offset: 3,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"download_file","f":"f1","o":3}"#);
+    }
+
+    #[test]
+    fn test_search_messages_command() {
+        let command = UserCommand::SearchMessages(SearchMessagesCommand {
+            room: "test".to_string(),
+            query: "rust".to_string(),
+            limit: Some(20),
+            before: Some(1),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"search_messages","r":"test","q":"rust","l":20,"b":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_browse_rooms_command() {
+        let command = UserCommand::BrowseRooms(BrowseRoomsCommand {
+            after: Some("general".to_string()),
+            limit: Some(20),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"browse_rooms","a":"general","l":20}"#);
+
+        let command = UserCommand::BrowseRooms(BrowseRoomsCommand {
+            after: None,
+            limit: None,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"browse_rooms"}"#);
+    }
+
+    #[test]
+    fn test_join_space_command() {
+        let command = UserCommand::JoinSpace(JoinSpaceCommand {
+            space: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"join_space","s":"test"}"#);
+    }
+
+    #[test]
+    fn test_leave_space_command() {
+        let command = UserCommand::LeaveSpace(LeaveSpaceCommand {
+            space: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"leave_space","s":"test"}"#);
+    }
+
+    #[test]
+    fn test_set_space_role_command() {
+        let command = UserCommand::SetSpaceRole(SetSpaceRoleCommand {
+            space: "test".to_string(),
+            user_id: "user".to_string(),
+            role: SpaceRole::Admin,
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"set_space_role","s":"test","u":"user","ro":"admin"}"#,
+        );
+    }
+
+    #[test]
+    fn test_remove_space_member_command() {
+        let command = UserCommand::RemoveSpaceMember(RemoveSpaceMemberCommand {
+            space: "test".to_string(),
+            user_id: "user".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"remove_space_member","s":"test","u":"user"}"#,
+        );
+    }
+
+    #[test]
+    fn test_send_direct_message_command() {
+        let command = UserCommand::SendDirectMessage(SendDirectMessageCommand {
+            user_id: "user".to_string(),
+            content: "test".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"send_direct_message","u":"user","c":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_invite_user_command() {
+        let command = UserCommand::InviteUser(InviteUserCommand {
+            room: "test".to_string(),
+            user_id: "user".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"invite_user","r":"test","u":"user"}"#);
+    }
+
+    #[test]
+    fn test_set_presence_command() {
+        let command = UserCommand::SetPresence(SetPresenceCommand {
+            away_message: Some("lunch".to_string()),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"set_presence","m":"lunch"}"#);
+
+        let command = UserCommand::SetPresence(SetPresenceCommand { away_message: None });
+
+        assert_command_serialization(&command, r#"{"_ct":"set_presence"}"#);
+    }
+
+    #[test]
+    fn test_change_nickname_command() {
+        let command = UserCommand::ChangeNickname(ChangeNicknameCommand {
+            nickname: Some("Alice Liddell".to_string()),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"change_nickname","n":"Alice Liddell"}"#);
+
+        let command = UserCommand::ChangeNickname(ChangeNicknameCommand { nickname: None });
+
+        assert_command_serialization(&command, r#"{"_ct":"change_nickname"}"#);
+    }
+
+    #[test]
+    fn test_get_user_profile_command() {
+        let command = UserCommand::GetUserProfile(GetUserProfileCommand {
+            user_id: "alice".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"get_user_profile","u":"alice"}"#);
+    }
+
+    #[test]
+    fn test_update_profile_command() {
+        let command = UserCommand::UpdateProfile(UpdateProfileCommand {
+            bio: Some("Rustacean".to_string()),
+            pronouns: None,
+            timezone: Some("".to_string()),
+            color: Some("#ff8700".to_string()),
+        });
+
+        assert_command_serialization(
+            &command,
+            r##"{"_ct":"update_profile","b":"Rustacean","tz":"","cl":"#ff8700"}"##,
+        );
+    }
+
+    #[test]
+    fn test_decline_invitation_command() {
+        let command = UserCommand::DeclineInvitation(DeclineInvitationCommand {
+            room: "test".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"decline_invitation","r":"test"}"#);
+    }
+
+    #[test]
+    fn test_export_my_data_command() {
+        let command = UserCommand::ExportMyData(ExportMyDataCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"export_my_data"}"#);
+    }
+
+    #[test]
+    fn test_delete_my_account_command() {
+        let command = UserCommand::DeleteMyAccount(DeleteMyAccountCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"delete_my_account"}"#);
+    }
+
+    #[test]
+    fn test_pong_command() {
+        let command = UserCommand::Pong(PongCommand { nonce: 7 });
+
+        assert_command_serialization(&command, r#"{"_ct":"pong","n":7}"#);
+    }
+
     #[test]
     fn test_quit_command() {
         let command = UserCommand::Quit(QuitCommand);
 
         assert_command_serialization(&command, r#"{"_ct":"quit"}"#);
     }
+
+    #[test]
+    fn test_command_request() {
+        let request = CommandRequest {
+            request_id: Some("7".to_string()),
+            command: UserCommand::JoinRoom(JoinRoomCommand {
+                room: "test".to_string(),
+            }),
+        };
+
+        let serialized = serde_json::to_string(&request).unwrap();
+        assert_eq!(serialized, r#"{"rid":"7","_ct":"join_room","r":"test"}"#);
+        let deserialized: CommandRequest = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, request);
+
+        // the commands sent without a request id are read as requests as well
+        let deserialized: CommandRequest =
+            serde_json::from_str(r#"{"_ct":"join_room","r":"test"}"#).unwrap();
+        assert_eq!(deserialized, request.command.into());
+    }
 }
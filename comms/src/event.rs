@@ -9,6 +9,202 @@ pub struct RoomDetail {
     /// The description of the room
     #[serde(rename = "d")]
     pub description: String,
+    /// How much of the room's history is visible to members who join later
+    #[serde(rename = "hv", default)]
+    pub history_visibility: HistoryVisibility,
+    /// The template which pre-populates the message input when composing in the room
+    #[serde(rename = "it", default, skip_serializing_if = "Option::is_none")]
+    pub input_template: Option<String>,
+}
+
+/// Policy controlling whether new members can see messages sent before they joined a room
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "k", rename_all = "snake_case")]
+pub enum HistoryVisibility {
+    /// Only the messages sent after the member joined are visible
+    #[default]
+    None,
+    /// The last `count` messages sent before the member joined are visible
+    Last {
+        #[serde(rename = "n")]
+        count: usize,
+    },
+    /// The full history of the room is visible
+    All,
+}
+
+/// The role of a member in a space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpaceRole {
+    Member,
+    /// Admins can change the roles of the members, and remove them from the space
+    Admin,
+}
+
+/// The role of a user in a room, each role is allowed everything the roles before it are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomRole {
+    #[default]
+    Member,
+    /// Moderators can change the topic of the room and moderate its members
+    Moderator,
+    /// The owner can also change the roles of the users and delete the room
+    Owner,
+}
+
+impl RoomRole {
+    fn is_member(&self) -> bool {
+        *self == RoomRole::Member
+    }
+}
+
+/// What a moderator has done to a user of a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "k", rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// The user has been removed from the room, and can join it again
+    Kicked,
+    /// The user has been removed from the room, and can not join it again
+    Banned,
+    /// The messages of the user are refused for the given number of seconds
+    Muted {
+        #[serde(rename = "s")]
+        seconds: u64,
+    },
+    Unmuted,
+}
+
+/// The detail of a given space, which groups rooms together
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpaceDetail {
+    /// The slug of the space
+    #[serde(rename = "n")]
+    pub name: String,
+    /// The description of the space
+    #[serde(rename = "d")]
+    pub description: String,
+    /// The slugs of the rooms in the space, ordered
+    #[serde(rename = "rs")]
+    pub rooms: Vec<String>,
+    /// The slugs of the rooms which are joined along with the space
+    #[serde(rename = "dr")]
+    pub default_rooms: Vec<String>,
+}
+
+/// A member of a space
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpaceMember {
+    /// The id of the member
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The role of the member in the space
+    #[serde(rename = "ro")]
+    pub role: SpaceRole,
+}
+
+/// A reply to the hello command of a v4 or later client, with what the server supports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WelcomeReplyEvent {
+    /// The version of the protocol the client is served
+    #[serde(rename = "v")]
+    pub protocol_version: u16,
+    /// The number of characters a message can be at most
+    #[serde(rename = "mm")]
+    pub max_message_chars: usize,
+    /// The optional features the server supports, see [crate::protocol::features]
+    #[serde(rename = "f")]
+    pub features: Vec<String>,
+    /// How often the server pings the client, in milliseconds, if it does
+    #[serde(rename = "hi", default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<u64>,
+}
+
+/// Sent periodically to v5 clients, which answer it with a pong carrying the same nonce
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingEvent {
+    #[serde(rename = "n")]
+    pub nonce: u64,
+    /// How long the client took to answer the previous ping, in milliseconds, once it has answered one
+    #[serde(rename = "rtt", default, skip_serializing_if = "Option::is_none")]
+    pub round_trip: Option<u64>,
+}
+
+/// A reply to a client whose protocol version the server does not serve anymore, the connection is closed right after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolRejectedReplyEvent {
+    /// The oldest version of the protocol the server serves
+    #[serde(rename = "mv")]
+    pub min_protocol_version: u16,
+}
+
+/// Broadcast to every session when the server is shutting down, the connections are closed once the grace period is over
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerShuttingDownEvent {
+    /// Why the server is shutting down, as told by its operator
+    #[serde(rename = "r")]
+    pub reason: String,
+    /// How long until the server closes the connection, in seconds
+    #[serde(rename = "gs")]
+    pub grace_seconds: u64,
+}
+
+/// Broadcast to every session when the operator of the server makes an announcement, from the admin console
+/// or on the schedule of the configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnouncementEvent {
+    #[serde(rename = "t")]
+    pub text: String,
+}
+
+/// Sent to every session of a user the operator of the server has disconnected, the connections are closed right after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionKickedEvent {
+    /// Why the user was disconnected, as told by the operator
+    #[serde(rename = "r")]
+    pub reason: String,
+}
+
+/// Why the server closed a connection on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The client did not read its events fast enough, and they piled up on the server
+    SlowConsumer,
+}
+
+/// Sent right before the server closes the connection on its own, the client can connect again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisconnectedEvent {
+    #[serde(rename = "r")]
+    pub reason: DisconnectReason,
+}
+
+/// A reply to the login command of the user, followed by a [LoginSuccessfulReplyEvent] when accepted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginResultReplyEvent {
+    /// Whether the credentials were accepted
+    #[serde(rename = "ok")]
+    pub is_accepted: bool,
+    /// Whether the username was registered by this login
+    #[serde(rename = "nu", default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_new_user: bool,
+    /// Why the credentials were rejected
+    #[serde(rename = "re", default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A reply to the resume session command of the user,
+/// followed by the events the session missed while it was disconnected when accepted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumeResultReplyEvent {
+    /// Whether the session was taken over
+    #[serde(rename = "ok")]
+    pub is_accepted: bool,
+    /// Why the session could not be taken over
+    #[serde(rename = "re", default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 /// A user has successfully logged in
@@ -23,6 +219,12 @@ pub struct LoginSuccessfulReplyEvent {
     /// The list of rooms the user can participate, unique and ordered
     #[serde(rename = "rs")]
     pub rooms: Vec<RoomDetail>,
+    /// The list of spaces grouping the rooms, unique and ordered
+    #[serde(rename = "ss", default, skip_serializing_if = "Vec::is_empty")]
+    pub spaces: Vec<SpaceDetail>,
+    /// The token to resume the session with if the connection drops, only given to the users who logged in
+    #[serde(rename = "rt", default, skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
 }
 
 /// Users new room participation status
@@ -56,6 +258,104 @@ pub struct UserJoinedRoomReplyEvent {
     /// The users currently in the room, unique and ordered
     #[serde(rename = "us")]
     pub users: Vec<String>,
+    /// The role of the user in the room
+    #[serde(rename = "ro", default, skip_serializing_if = "RoomRole::is_member")]
+    pub role: RoomRole,
+}
+
+/// A reply to the user when they could not join a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomJoinDeniedReplyEvent {
+    /// The slug of the room the user tried to join
+    #[serde(rename = "r")]
+    pub room: String,
+    /// Why the room could not be joined
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// A user has created a public room, which is added to the room list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomCreatedBroadcastEvent {
+    /// The details of the created room
+    #[serde(rename = "rd")]
+    pub room: RoomDetail,
+    /// The id of the user who has created the room
+    #[serde(rename = "u")]
+    pub created_by: String,
+}
+
+/// A room has been deleted by its creator, it is removed from the room list and its members are left out of it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomDeletedBroadcastEvent {
+    /// The slug of the deleted room
+    #[serde(rename = "r")]
+    pub room: String,
+}
+
+/// The topic of a room, shown as its description, has been changed by its owner or one of its moderators
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomTopicChangedBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The new topic of the room
+    #[serde(rename = "t")]
+    pub topic: String,
+    /// The user who changed the topic
+    #[serde(rename = "u")]
+    pub changed_by: String,
+}
+
+/// The owner of a room has changed the role of one of its users
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomRoleBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The new role of the user
+    #[serde(rename = "ro")]
+    pub role: RoomRole,
+}
+
+/// A moderator of a room has kicked, banned or muted one of its users
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserModeratedBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the moderated user
+    #[serde(rename = "u")]
+    pub user_id: String,
+    #[serde(rename = "a")]
+    pub action: ModerationAction,
+    /// The id of the moderator
+    #[serde(rename = "m")]
+    pub moderator_id: String,
+}
+
+/// A reply to the user when their moderation command is refused, or their message is refused while they are muted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationDeniedReplyEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// Why the command or the message was refused
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// A reply to the user when the room they tried to create, delete or change could not be
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomManagementDeniedReplyEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// Why the room could not be created, deleted or changed
+    #[serde(rename = "re")]
+    pub reason: String,
 }
 
 /// A user has sent a message to a room
@@ -64,106 +364,1498 @@ pub struct UserMessageBroadcastEvent {
     /// The slug of the room the user has sent the message to
     #[serde(rename = "r")]
     pub room: String,
+    /// The id of the message in the room, which increases with each message
+    #[serde(rename = "i", default)]
+    pub id: u64,
     /// The id of the user that has sent the message
     #[serde(rename = "u")]
     pub user_id: String,
     /// The content of the message
     #[serde(rename = "c")]
     pub content: String,
+    /// The time the server received the message at, in milliseconds since the unix epoch
+    #[serde(rename = "t", default)]
+    pub timestamp: u64,
+    /// The id of the message in the room this message replies to
+    #[serde(rename = "rt", default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
 }
 
+/// A single message from the history of a room
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(tag = "_et", rename_all = "snake_case")]
-/// Events that can be sent to the client
-/// Events maybe related to different users and rooms, the receipient is a single chat session
-pub enum Event {
-    LoginSuccessful(LoginSuccessfulReplyEvent),
-    RoomParticipation(RoomParticipationBroacastEvent),
-    UserJoinedRoom(UserJoinedRoomReplyEvent),
-    UserMessage(UserMessageBroadcastEvent),
+pub struct HistoryMessage {
+    /// The id of the message in the room, matching the id it was broadcasted with
+    #[serde(rename = "i", default)]
+    pub id: u64,
+    /// The id of the user that has sent the message
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The content of the message
+    #[serde(rename = "c")]
+    pub content: String,
+    /// The time the message was sent at, in milliseconds since the unix epoch
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+    /// The id of the message in the room this message replies to
+    #[serde(rename = "rt", default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
+    /// Whether the author has edited the message since sending it
+    #[serde(rename = "e", default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_edited: bool,
+    /// The reactions to the message, in the order they were first given
+    #[serde(rename = "rs", default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The users who reacted to a message with the same emoji, counted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reaction {
+    #[serde(rename = "em")]
+    pub emoji: String,
+    /// How many users reacted with the emoji
+    #[serde(rename = "n")]
+    pub count: usize,
+}
 
-    // given an event enum, and an expect string, asserts that event is serialized / deserialized appropiately
-    fn assert_event_serialization(event: &Event, expected: &str) {
-        let serialized = serde_json::to_string(&event).unwrap();
-        assert_eq!(serialized, expected);
-        let deserialized: Event = serde_json::from_str(&serialized).unwrap();
-        assert_eq!(deserialized, *event);
-    }
+/// The reactions to a message have changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageReactionsBroadcastEvent {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the message in the room
+    #[serde(rename = "i")]
+    pub id: u64,
+    /// All the reactions to the message, replacing the previous ones
+    #[serde(rename = "rs")]
+    pub reactions: Vec<Reaction>,
+}
 
-    #[test]
-    fn test_login_successful_event() {
-        let event = Event::LoginSuccessful(LoginSuccessfulReplyEvent {
-            session_id: "session-id-1".to_string(),
-            user_id: "user-id-1".to_string(),
-            rooms: vec![RoomDetail {
-                name: "room-1".to_string(),
-                description: "some description".to_string(),
-            }],
-        });
+/// The author of a message has edited it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageEditedBroadcastEvent {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the message in the room
+    #[serde(rename = "i")]
+    pub id: u64,
+    /// The new content of the message
+    #[serde(rename = "c")]
+    pub content: String,
+}
 
-        assert_event_serialization(
-            &event,
-            r#"{"_et":"login_successful","s":"session-id-1","u":"user-id-1","rs":[{"n":"room-1","d":"some description"}]}"#,
-        );
-    }
+/// The author of a message has deleted it, it is also removed from the room history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageDeletedBroadcastEvent {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the message in the room
+    #[serde(rename = "i")]
+    pub id: u64,
+}
 
-    #[test]
-    fn test_room_participation_join_event() {
-        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
-            room: "test".to_string(),
-            user_id: "test".to_string(),
-            status: RoomParticipationStatus::Joined,
-        });
+/// The oldest messages of a room were pruned by its retention policy, the history now starts at the given id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryTruncatedBroadcastEvent {
+    /// The slug of the room whose history was truncated
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the oldest message kept, the messages before it are gone
+    #[serde(rename = "b")]
+    pub before: u64,
+}
 
-        assert_event_serialization(
-            &event,
-            r#"{"_et":"room_participation","r":"test","u":"test","s":"joined"}"#,
-        );
-    }
+/// A reply to the user when they are not allowed to edit or delete a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageChangeDeniedReplyEvent {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the message in the room
+    #[serde(rename = "i")]
+    pub id: u64,
+    /// Why the message could not be changed
+    #[serde(rename = "re")]
+    pub reason: String,
+}
 
-    #[test]
-    fn test_room_participation_leave_event() {
-        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
-            room: "test".to_string(),
-            user_id: "test".to_string(),
-            status: RoomParticipationStatus::Left,
-        });
+/// A reply to the user with the part of the room history they are allowed to see
+///
+/// Sent right after the user joins a room with the latest visible messages, and whenever the user fetches the history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomHistoryReplyEvent {
+    /// The slug of the room the history belongs to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The visible messages of the room, ordered from oldest to newest
+    #[serde(rename = "ms")]
+    pub messages: Vec<HistoryMessage>,
+    /// The timestamp the history was requested around, if any
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub around: Option<u64>,
+    /// The id of the last message the user has read in the room, if they marked any as read
+    #[serde(rename = "lr", default, skip_serializing_if = "Option::is_none")]
+    pub last_read: Option<u64>,
+}
 
-        assert_event_serialization(
-            &event,
-            r#"{"_et":"room_participation","r":"test","u":"test","s":"left"}"#,
-        );
-    }
+/// A reply to the user with a chunk of the full history of a room being exported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomHistoryChunkReplyEvent {
+    /// The slug of the room the history belongs to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The messages in the chunk, ordered from oldest to newest
+    #[serde(rename = "ms")]
+    pub messages: Vec<HistoryMessage>,
+    /// The position of the last message sent so far, to resume the export after an interruption
+    #[serde(rename = "cu", default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<u64>,
+    /// Whether this is the last chunk of the export
+    #[serde(rename = "l")]
+    pub is_last: bool,
+}
 
-    #[test]
-    fn test_user_joined_room_event() {
-        let event = Event::UserJoinedRoom(UserJoinedRoomReplyEvent {
-            room: "test".to_string(),
-            users: vec!["test".to_string()],
-        });
+/// A reply to the user when they are not allowed to export the history of a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomHistoryExportDeniedReplyEvent {
+    /// The slug of the room the export was requested for
+    #[serde(rename = "r")]
+    pub room: String,
+}
 
-        assert_event_serialization(
-            &event,
-            r#"{"_et":"user_joined_room","r":"test","us":["test"]}"#,
-        );
-    }
+/// A reply to the user with the number of bytes of an upload received so far, which asks for the next chunk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadProgressReplyEvent {
+    /// The id the client picked for the upload
+    #[serde(rename = "u")]
+    pub upload_id: String,
+    /// The number of bytes received so far
+    #[serde(rename = "rc")]
+    pub received: u64,
+}
 
-    #[test]
-    fn test_user_message_event() {
-        let event = Event::UserMessage(UserMessageBroadcastEvent {
-            room: "test".to_string(),
-            user_id: "test".to_string(),
-            content: "test".to_string(),
+/// A broadcast event indicating that a file has been shared with a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSharedBroadcastEvent {
+    /// The slug of the room the file was shared with
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the file, to download it with
+    #[serde(rename = "f")]
+    pub file_id: String,
+    /// The id of the user who shared the file
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The name of the file
+    #[serde(rename = "n")]
+    pub name: String,
+    /// The size of the file in bytes
+    #[serde(rename = "sz")]
+    pub size: u64,
+    /// The checksum of the content of the file
+    #[serde(rename = "cs")]
+    pub checksum: String,
+    /// Unix timestamp in milliseconds of when the file was shared
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+/// A reply to the user with a chunk of a file they download
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChunkReplyEvent {
+    /// The id of the file
+    #[serde(rename = "f")]
+    pub file_id: String,
+    /// The offset of the chunk in the file
+    #[serde(rename = "o")]
+    pub offset: u64,
+    /// The base64 encoded content of the chunk
+    #[serde(rename = "d")]
+    pub data: String,
+    /// The size of the whole file in bytes
+    #[serde(rename = "sz")]
+    pub size: u64,
+    /// The checksum of the content of the whole file
+    #[serde(rename = "cs")]
+    pub checksum: String,
+}
+
+/// A reply to the user when an upload or a download can not go on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileTransferDeniedReplyEvent {
+    /// The id of the upload or of the file being downloaded
+    #[serde(rename = "t")]
+    pub transfer_id: String,
+    /// Why the transfer was stopped
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// A reply to the user with a page of the visible messages of a room older than the requested message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OlderMessagesReplyEvent {
+    /// The slug of the room the messages belong to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the message the page was requested before
+    #[serde(rename = "b")]
+    pub before_message_id: u64,
+    /// The messages, ordered from oldest to newest
+    #[serde(rename = "ms")]
+    pub messages: Vec<HistoryMessage>,
+    /// Whether there are even older visible messages to fetch
+    #[serde(rename = "hm", default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_more: bool,
+}
+
+/// A reply to the user with a page of the messages of a room matching their search, newest first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResultsReplyEvent {
+    /// The slug of the room which was searched
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The query of the search, as sent by the user
+    #[serde(rename = "q")]
+    pub query: String,
+    /// The matching messages, which are not numbered as in the room history
+    #[serde(rename = "ms")]
+    pub messages: Vec<HistoryMessage>,
+    /// The cursor to continue the search with for the older matches, missing when there are no more
+    #[serde(rename = "cu", default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<u64>,
+}
+
+/// A public room as listed in the room directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryRoom {
+    /// The slug of the room
+    #[serde(rename = "n")]
+    pub name: String,
+    /// The description of the room, which is its topic
+    #[serde(rename = "d")]
+    pub description: String,
+    /// How many users are in the room
+    #[serde(rename = "mc")]
+    pub member_count: usize,
+    /// The time of the latest message of the room, missing if none is kept
+    #[serde(rename = "la", default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<u64>,
+}
+
+/// A reply to the user with a page of the directory of the public rooms, ordered by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomDirectoryReplyEvent {
+    /// The cursor the page was requested after, missing for the first page
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// The rooms of the page
+    #[serde(rename = "rs")]
+    pub rooms: Vec<DirectoryRoom>,
+    /// The cursor to continue the directory with, missing when there are no more rooms
+    #[serde(rename = "cu", default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// A reply to the user when they have joined a space
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserJoinedSpaceReplyEvent {
+    /// The slug of the space the user has joined
+    #[serde(rename = "s")]
+    pub space: String,
+    /// The members of the space including the user, in the order they joined
+    #[serde(rename = "ms")]
+    pub members: Vec<SpaceMember>,
+}
+
+/// A member of a space has joined, left, been removed or had their role changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpaceMembershipBroadcastEvent {
+    /// The slug of the space
+    #[serde(rename = "s")]
+    pub space: String,
+    /// The id of the member
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The new role of the member, none if they are no longer a member
+    #[serde(rename = "ro", default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<SpaceRole>,
+}
+
+/// A reply to the user when a space command they sent is not allowed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpaceCommandDeniedReplyEvent {
+    /// The slug of the space the command was sent for
+    #[serde(rename = "s")]
+    pub space: String,
+    /// Why the command is not allowed
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// A direct message between two users, sent to the sessions of both of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectMessageBroadcastEvent {
+    /// The id of the user that has sent the message
+    #[serde(rename = "fu")]
+    pub from_user_id: String,
+    /// The id of the user the message is sent to
+    #[serde(rename = "tu")]
+    pub to_user_id: String,
+    /// The content of the message
+    #[serde(rename = "c")]
+    pub content: String,
+    /// The time the message was sent at, in milliseconds since the unix epoch
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+/// A reply to the user when a direct message they sent could not be delivered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectMessageDeniedReplyEvent {
+    /// The id of the user the message was sent to
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// Why the message could not be delivered
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// An invitation to a private room, sent to the invited user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInvitationBroadcastEvent {
+    /// The room the user is invited to, which is not listed to them otherwise
+    #[serde(rename = "rd")]
+    pub room: RoomDetail,
+    /// The id of the member who has sent the invitation
+    #[serde(rename = "fu")]
+    pub from_user_id: String,
+}
+
+/// A reply to the user when an invitation they sent is not allowed, or could not be delivered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInvitationDeniedReplyEvent {
+    /// The slug of the room the invitation was for
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the invited user
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// Why the invitation is not allowed
+    #[serde(rename = "re")]
+    pub reason: String,
+}
+
+/// A message sent by the user, as kept in the history of a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The content of the message
+    #[serde(rename = "c")]
+    pub content: String,
+    /// The time the message was sent at, in milliseconds since the unix epoch
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+/// A session of the user, as recorded in the access log of the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedSession {
+    /// The id of the session
+    #[serde(rename = "s")]
+    pub session_id: String,
+    /// The time the session was started at, in milliseconds since the unix epoch
+    #[serde(rename = "ca")]
+    pub connected_at: u64,
+    /// The time the session was ended at, if it has ended
+    #[serde(rename = "da", default, skip_serializing_if = "Option::is_none")]
+    pub disconnected_at: Option<u64>,
+}
+
+/// A reply to the user with all the data the server stores about them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserDataExportReplyEvent {
+    /// The id of the user the data belongs to
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The slugs of the rooms the user is currently in
+    #[serde(rename = "jr")]
+    pub joined_rooms: Vec<String>,
+    /// The sessions of the user, ordered from oldest to newest
+    #[serde(rename = "ss")]
+    pub sessions: Vec<ExportedSession>,
+    /// The messages of the user still kept in the room histories, ordered from oldest to newest
+    #[serde(rename = "ms")]
+    pub messages: Vec<ExportedMessage>,
+    /// The name the user is shown with, if they have changed it
+    #[serde(rename = "dn", default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The profile of the user, if they have filled it
+    #[serde(rename = "pf", default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<UserProfile>,
+}
+
+/// A reply to the user confirming that their account will be deleted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountDeletionScheduledReplyEvent {
+    /// The time the messages of the user will be anonymized at, in milliseconds since the unix epoch
+    #[serde(rename = "da")]
+    pub delete_at: u64,
+}
+
+/// A reply to the user when their command is dropped, since they sent too many messages or joins in a short time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitedReplyEvent {
+    /// How long to wait before the next command of the same kind is accepted, in milliseconds
+    #[serde(rename = "ra")]
+    pub retry_after: u64,
+}
+
+/// Whether a user is connected, and whether they are at their keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    /// Connected, but idle for a while or away by their own account
+    Away,
+    Offline,
+}
+
+/// The presence of a user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserPresence {
+    #[serde(rename = "u")]
+    pub user_id: String,
+    #[serde(rename = "s")]
+    pub status: PresenceStatus,
+    /// The status message the user has set when going away
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub away_message: Option<String>,
+    /// The name the user is shown with in place of their id, if they have changed it
+    #[serde(rename = "dn", default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The color the name of the user is shown with, as `#rrggbb`, if they have picked one
+    #[serde(rename = "cl", default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Broadcast to every connected user when the presence of a user changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceChangedBroadcastEvent {
+    #[serde(rename = "p")]
+    pub presence: UserPresence,
+}
+
+/// A reply to the login of the user, or to resuming their session, with the presence of the users who are not offline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceSnapshotReplyEvent {
+    #[serde(rename = "us")]
+    pub users: Vec<UserPresence>,
+}
+
+/// Broadcast to every connected user when a user changes the name they are shown with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NicknameChangedBroadcastEvent {
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The new name of the user, none when they went back to their id
+    #[serde(rename = "dn", default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+/// What a user tells the others about themselves, each field left out until they fill it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    #[serde(rename = "u")]
+    pub user_id: String,
+    #[serde(rename = "dn", default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    #[serde(rename = "p", default, skip_serializing_if = "Option::is_none")]
+    pub pronouns: Option<String>,
+    /// The timezone of the user, such as `Europe/Paris`
+    #[serde(rename = "tz", default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// The color the name of the user is shown with, as `#rrggbb`
+    #[serde(rename = "cl", default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// A reply to the user with the profile they asked for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserProfileReplyEvent {
+    #[serde(rename = "pf")]
+    pub profile: UserProfile,
+}
+
+/// What went wrong with a command, so clients can react to a failure without parsing its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    RoomNotFound,
+    SpaceNotFound,
+    NotAMember,
+    AlreadyJoined,
+    MessageTooLong,
+    PermissionDenied,
+    /// The command was dropped, see [RateLimitedReplyEvent]
+    RateLimited,
+    /// Any other failure, described by the message only
+    Failed,
+}
+
+/// A reply to the user when a command could not be run, instead of failing silently
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandErrorEvent {
+    /// The id of the request the failed command was sent with, if any
+    #[serde(rename = "rid", default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(rename = "c")]
+    pub code: ErrorCode,
+    /// A description of the failure, to show to the user
+    #[serde(rename = "m")]
+    pub message: String,
+}
+
+/// A reply to the user when a command sent with a request id has been run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandAckEvent {
+    /// The id of the request the command was sent with
+    #[serde(rename = "rid")]
+    pub request_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "_et", rename_all = "snake_case")]
+/// Events that can be sent to the client
+/// Events maybe related to different users and rooms, the receipient is a single chat session
+pub enum Event {
+    Welcome(WelcomeReplyEvent),
+    ProtocolRejected(ProtocolRejectedReplyEvent),
+    Ping(PingEvent),
+    ServerShuttingDown(ServerShuttingDownEvent),
+    Announcement(AnnouncementEvent),
+    SessionKicked(SessionKickedEvent),
+    Disconnected(DisconnectedEvent),
+    LoginResult(LoginResultReplyEvent),
+    LoginSuccessful(LoginSuccessfulReplyEvent),
+    ResumeResult(ResumeResultReplyEvent),
+    RoomParticipation(RoomParticipationBroacastEvent),
+    UserJoinedRoom(UserJoinedRoomReplyEvent),
+    RoomJoinDenied(RoomJoinDeniedReplyEvent),
+    RoomCreated(RoomCreatedBroadcastEvent),
+    RoomDeleted(RoomDeletedBroadcastEvent),
+    RoomTopicChanged(RoomTopicChangedBroadcastEvent),
+    RoomManagementDenied(RoomManagementDeniedReplyEvent),
+    RoomRole(RoomRoleBroadcastEvent),
+    UserModerated(UserModeratedBroadcastEvent),
+    ModerationDenied(ModerationDeniedReplyEvent),
+    UserMessage(UserMessageBroadcastEvent),
+    MessageEdited(MessageEditedBroadcastEvent),
+    MessageDeleted(MessageDeletedBroadcastEvent),
+    MessageChangeDenied(MessageChangeDeniedReplyEvent),
+    MessageReactions(MessageReactionsBroadcastEvent),
+    HistoryTruncated(HistoryTruncatedBroadcastEvent),
+    RoomHistory(RoomHistoryReplyEvent),
+    OlderMessages(OlderMessagesReplyEvent),
+    RoomHistoryChunk(RoomHistoryChunkReplyEvent),
+    RoomHistoryExportDenied(RoomHistoryExportDeniedReplyEvent),
+    SearchResults(SearchResultsReplyEvent),
+    RoomDirectory(RoomDirectoryReplyEvent),
+    UploadProgress(UploadProgressReplyEvent),
+    FileShared(FileSharedBroadcastEvent),
+    FileChunk(FileChunkReplyEvent),
+    FileTransferDenied(FileTransferDeniedReplyEvent),
+    UserJoinedSpace(UserJoinedSpaceReplyEvent),
+    SpaceMembership(SpaceMembershipBroadcastEvent),
+    SpaceCommandDenied(SpaceCommandDeniedReplyEvent),
+    DirectMessage(DirectMessageBroadcastEvent),
+    DirectMessageDenied(DirectMessageDeniedReplyEvent),
+    RoomInvitation(RoomInvitationBroadcastEvent),
+    RoomInvitationDenied(RoomInvitationDeniedReplyEvent),
+    UserDataExport(UserDataExportReplyEvent),
+    AccountDeletionScheduled(AccountDeletionScheduledReplyEvent),
+    RateLimited(RateLimitedReplyEvent),
+    PresenceChanged(PresenceChangedBroadcastEvent),
+    PresenceSnapshot(PresenceSnapshotReplyEvent),
+    NicknameChanged(NicknameChangedBroadcastEvent),
+    UserProfile(UserProfileReplyEvent),
+    CommandAck(CommandAckEvent),
+    CommandError(CommandErrorEvent),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // given an event enum, and an expect string, asserts that event is serialized / deserialized appropiately
+    fn assert_event_serialization(event: &Event, expected: &str) {
+        let serialized = serde_json::to_string(&event).unwrap();
+        assert_eq!(serialized, expected);
+        let deserialized: Event = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, *event);
+    }
+
+    #[test]
+    fn test_welcome_event() {
+        let event = Event::Welcome(WelcomeReplyEvent {
+            protocol_version: 4,
+            max_message_chars: 2000,
+            features: vec!["request_ids".to_string()],
+            heartbeat_interval: None,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"welcome","v":4,"mm":2000,"f":["request_ids"]}"#,
+        );
+
+        let event = Event::Welcome(WelcomeReplyEvent {
+            protocol_version: 5,
+            max_message_chars: 2000,
+            features: vec!["heartbeat".to_string()],
+            heartbeat_interval: Some(15000),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"welcome","v":5,"mm":2000,"f":["heartbeat"],"hi":15000}"#,
+        );
+    }
+
+    #[test]
+    fn test_protocol_rejected_event() {
+        let event = Event::ProtocolRejected(ProtocolRejectedReplyEvent {
+            min_protocol_version: 5,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"protocol_rejected","mv":5}"#);
+    }
+
+    #[test]
+    fn test_server_shutting_down_event() {
+        let event = Event::ServerShuttingDown(ServerShuttingDownEvent {
+            reason: "maintenance".to_string(),
+            grace_seconds: 10,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"server_shutting_down","r":"maintenance","gs":10}"#,
+        );
+    }
+
+    #[test]
+    fn test_announcement_event() {
+        let event = Event::Announcement(AnnouncementEvent {
+            text: "welcome to the new server".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"announcement","t":"welcome to the new server"}"#,
+        );
+    }
+
+    #[test]
+    fn test_session_kicked_event() {
+        let event = Event::SessionKicked(SessionKickedEvent {
+            reason: "spamming".to_string(),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"session_kicked","r":"spamming"}"#);
+    }
+
+    #[test]
+    fn test_disconnected_event() {
+        let event = Event::Disconnected(DisconnectedEvent {
+            reason: DisconnectReason::SlowConsumer,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"disconnected","r":"slow_consumer"}"#);
+    }
+
+    #[test]
+    fn test_ping_event() {
+        let event = Event::Ping(PingEvent {
+            nonce: 7,
+            round_trip: None,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"ping","n":7}"#);
+    }
+
+    #[test]
+    fn test_ping_event_with_round_trip() {
+        let event = Event::Ping(PingEvent {
+            nonce: 8,
+            round_trip: Some(42),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"ping","n":8,"rtt":42}"#);
+    }
+
+    #[test]
+    fn test_login_successful_event() {
+        let event = Event::LoginSuccessful(LoginSuccessfulReplyEvent {
+            session_id: "session-id-1".to_string(),
+            user_id: "user-id-1".to_string(),
+            rooms: vec![RoomDetail {
+                name: "room-1".to_string(),
+                description: "some description".to_string(),
+                history_visibility: HistoryVisibility::Last { count: 10 },
+                input_template: Some("today:".to_string()),
+            }],
+            spaces: vec![SpaceDetail {
+                name: "space-1".to_string(),
+                description: "some description".to_string(),
+                rooms: vec!["room-1".to_string()],
+                default_rooms: vec!["room-1".to_string()],
+            }],
+            resume_token: Some("token".to_string()),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"login_successful","s":"session-id-1","u":"user-id-1","rs":[{"n":"room-1","d":"some description","hv":{"k":"last","n":10},"it":"today:"}],"ss":[{"n":"space-1","d":"some description","rs":["room-1"],"dr":["room-1"]}],"rt":"token"}"#,
+        );
+    }
+
+    #[test]
+    fn test_login_result_event() {
+        let event = Event::LoginResult(LoginResultReplyEvent {
+            is_accepted: false,
+            is_new_user: false,
+            reason: Some("wrong password".to_string()),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"login_result","ok":false,"re":"wrong password"}"#,
+        );
+    }
+
+    #[test]
+    fn test_resume_result_event() {
+        let event = Event::ResumeResult(ResumeResultReplyEvent {
+            is_accepted: false,
+            reason: Some("the session has expired".to_string()),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"resume_result","ok":false,"re":"the session has expired"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_detail_defaults_history_visibility() {
+        let room_detail: RoomDetail = serde_json::from_str(r#"{"n":"room-1","d":"desc"}"#).unwrap();
+
+        assert_eq!(room_detail.history_visibility, HistoryVisibility::None);
+        assert_eq!(room_detail.input_template, None);
+    }
+
+    #[test]
+    fn test_room_participation_join_event() {
+        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            status: RoomParticipationStatus::Joined,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_participation","r":"test","u":"test","s":"joined"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_participation_leave_event() {
+        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            status: RoomParticipationStatus::Left,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_participation","r":"test","u":"test","s":"left"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_joined_room_event() {
+        let event = Event::UserJoinedRoom(UserJoinedRoomReplyEvent {
+            room: "test".to_string(),
+            users: vec!["test".to_string()],
+            role: RoomRole::Member,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_joined_room","r":"test","us":["test"]}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_join_denied_event() {
+        let event = Event::RoomJoinDenied(RoomJoinDeniedReplyEvent {
+            room: "test".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_join_denied","r":"test","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_created_event() {
+        let event = Event::RoomCreated(RoomCreatedBroadcastEvent {
+            room: RoomDetail {
+                name: "test".to_string(),
+                description: "test".to_string(),
+                history_visibility: HistoryVisibility::None,
+                input_template: None,
+            },
+            created_by: "user".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_created","rd":{"n":"test","d":"test","hv":{"k":"none"}},"u":"user"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_deleted_event() {
+        let event = Event::RoomDeleted(RoomDeletedBroadcastEvent {
+            room: "test".to_string(),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"room_deleted","r":"test"}"#);
+    }
+
+    #[test]
+    fn test_room_topic_changed_event() {
+        let event = Event::RoomTopicChanged(RoomTopicChangedBroadcastEvent {
+            room: "test".to_string(),
+            topic: "test".to_string(),
+            changed_by: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_topic_changed","r":"test","t":"test","u":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_management_denied_event() {
+        let event = Event::RoomManagementDenied(RoomManagementDeniedReplyEvent {
+            room: "test".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_management_denied","r":"test","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_role_event() {
+        let event = Event::RoomRole(RoomRoleBroadcastEvent {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            role: RoomRole::Moderator,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_role","r":"test","u":"test","ro":"moderator"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_moderated_event() {
+        let event = Event::UserModerated(UserModeratedBroadcastEvent {
+            room: "test".to_string(),
+            user_id: "test".to_string(),
+            action: ModerationAction::Muted { seconds: 600 },
+            moderator_id: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_moderated","r":"test","u":"test","a":{"k":"muted","s":600},"m":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_moderation_denied_event() {
+        let event = Event::ModerationDenied(ModerationDeniedReplyEvent {
+            room: "test".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"moderation_denied","r":"test","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_message_event() {
+        let event = Event::UserMessage(UserMessageBroadcastEvent {
+            room: "test".to_string(),
+            id: 1,
+            user_id: "test".to_string(),
+            content: "test".to_string(),
+            timestamp: 1,
+            reply_to: Some(0),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_message","r":"test","i":1,"u":"test","c":"test","t":1,"rt":0}"#,
+        );
+    }
+
+    #[test]
+    fn test_message_edited_event() {
+        let event = Event::MessageEdited(MessageEditedBroadcastEvent {
+            room: "test".to_string(),
+            id: 1,
+            content: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"message_edited","r":"test","i":1,"c":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_message_deleted_event() {
+        let event = Event::MessageDeleted(MessageDeletedBroadcastEvent {
+            room: "test".to_string(),
+            id: 1,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"message_deleted","r":"test","i":1}"#);
+    }
+
+    #[test]
+    fn test_history_truncated_event() {
+        let event = Event::HistoryTruncated(HistoryTruncatedBroadcastEvent {
+            room: "test".to_string(),
+            before: 42,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"history_truncated","r":"test","b":42}"#);
+    }
+
+    #[test]
+    fn test_message_reactions_event() {
+        let event = Event::MessageReactions(MessageReactionsBroadcastEvent {
+            room: "test".to_string(),
+            id: 1,
+            reactions: vec![Reaction {
+                emoji: "🎉".to_string(),
+                count: 1,
+            }],
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"message_reactions","r":"test","i":1,"rs":[{"em":"🎉","n":1}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_message_change_denied_event() {
+        let event = Event::MessageChangeDenied(MessageChangeDeniedReplyEvent {
+            room: "test".to_string(),
+            id: 1,
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"message_change_denied","r":"test","i":1,"re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_history_event() {
+        let event = Event::RoomHistory(RoomHistoryReplyEvent {
+            room: "test".to_string(),
+            messages: vec![
+                HistoryMessage {
+                    id: 1,
+                    user_id: "test".to_string(),
+                    content: "test".to_string(),
+                    timestamp: 1,
+                    reply_to: None,
+                    is_edited: false,
+                    reactions: vec![],
+                },
+                HistoryMessage {
+                    id: 2,
+                    user_id: "test".to_string(),
+                    content: "test".to_string(),
+                    timestamp: 2,
+                    reply_to: None,
+                    is_edited: true,
+                    reactions: vec![Reaction {
+                        emoji: "👍".to_string(),
+                        count: 2,
+                    }],
+                },
+            ],
+            around: None,
+            last_read: None,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_history","r":"test","ms":[{"i":1,"u":"test","c":"test","t":1},{"i":2,"u":"test","c":"test","t":2,"e":true,"rs":[{"em":"👍","n":2}]}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_history_around_event() {
+        let event = Event::RoomHistory(RoomHistoryReplyEvent {
+            room: "test".to_string(),
+            messages: vec![],
+            around: Some(1),
+            last_read: None,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"room_history","r":"test","ms":[],"a":1}"#);
+    }
+
+    #[test]
+    fn test_room_history_last_read_event() {
+        let event = Event::RoomHistory(RoomHistoryReplyEvent {
+            room: "test".to_string(),
+            messages: vec![],
+            around: None,
+            last_read: Some(4),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_history","r":"test","ms":[],"lr":4}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_history_chunk_event() {
+        let event = Event::RoomHistoryChunk(RoomHistoryChunkReplyEvent {
+            room: "test".to_string(),
+            messages: vec![HistoryMessage {
+                id: 1,
+                user_id: "test".to_string(),
+                content: "test".to_string(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            cursor: Some(3),
+            is_last: true,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_history_chunk","r":"test","ms":[{"i":1,"u":"test","c":"test","t":1}],"cu":3,"l":true}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_history_export_denied_event() {
+        let event = Event::RoomHistoryExportDenied(RoomHistoryExportDeniedReplyEvent {
+            room: "test".to_string(),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"room_history_export_denied","r":"test"}"#);
+    }
+
+    #[test]
+    fn test_older_messages_event() {
+        let event = Event::OlderMessages(OlderMessagesReplyEvent {
+            room: "test".to_string(),
+            before_message_id: 7,
+            messages: vec![HistoryMessage {
+                id: 6,
+                user_id: "test".to_string(),
+                content: "test".to_string(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            has_more: true,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"older_messages","r":"test","b":7,"ms":[{"i":6,"u":"test","c":"test","t":1}],"hm":true}"#,
+        );
+    }
+
+    #[test]
+    fn test_upload_progress_event() {
+        let event = Event::UploadProgress(UploadProgressReplyEvent {
+            upload_id: "1".to_string(),
+            received: 3,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"upload_progress","u":"1","rc":3}"#);
+    }
+
+    #[test]
+    fn test_file_shared_event() {
+        let event = Event::FileShared(FileSharedBroadcastEvent {
+            room: "test".to_string(),
+            file_id: "f1".to_string(),
+            user_id: "test".to_string(),
+            name: "notes.txt".to_string(),
+            size: 3,
+            checksum: "abc".to_string(),
+            timestamp: 1,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"file_shared","r":"test","f":"f1","u":"test","n":"notes.txt","sz":3,"cs":"abc","t":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_file_chunk_event() {
+        let event = Event::FileChunk(FileChunkReplyEvent {
+            file_id: "f1".to_string(),
+            offset: 0,
+            data: "YWJj".to_string(),
+            size: 3,
+            checksum: "abc".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"file_chunk","f":"f1","o":0,"d":"YWJj","sz":3,"cs":"abc"}"#,
+        );
+    }
+
+    #[test]
+    fn test_file_transfer_denied_event() {
+        let event = Event::FileTransferDenied(FileTransferDeniedReplyEvent {
+            transfer_id: "1".to_string(),
+            reason: "too large".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"file_transfer_denied","t":"1","re":"too large"}"#,
+        );
+    }
+
+    #[test]
+    fn test_search_results_event() {
+        let event = Event::SearchResults(SearchResultsReplyEvent {
+            room: "test".to_string(),
+            query: "rust".to_string(),
+            messages: vec![HistoryMessage {
+                id: 0,
+                user_id: "test".to_string(),
+                content: "rust is fun".to_string(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            cursor: Some(3),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"search_results","r":"test","q":"rust","ms":[{"i":0,"u":"test","c":"rust is fun","t":1}],"cu":3}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_directory_event() {
+        let event = Event::RoomDirectory(RoomDirectoryReplyEvent {
+            after: Some("general".to_string()),
+            rooms: vec![
+                DirectoryRoom {
+                    name: "rust".to_string(),
+                    description: "Rust talk".to_string(),
+                    member_count: 3,
+                    last_activity: Some(1),
+                },
+                DirectoryRoom {
+                    name: "quiet".to_string(),
+                    description: "".to_string(),
+                    member_count: 0,
+                    last_activity: None,
+                },
+            ],
+            cursor: Some("quiet".to_string()),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_directory","a":"general","rs":[{"n":"rust","d":"Rust talk","mc":3,"la":1},{"n":"quiet","d":"","mc":0}],"cu":"quiet"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_joined_space_event() {
+        let event = Event::UserJoinedSpace(UserJoinedSpaceReplyEvent {
+            space: "test".to_string(),
+            members: vec![SpaceMember {
+                user_id: "test".to_string(),
+                role: SpaceRole::Admin,
+            }],
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_joined_space","s":"test","ms":[{"u":"test","ro":"admin"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_space_membership_event() {
+        let event = Event::SpaceMembership(SpaceMembershipBroadcastEvent {
+            space: "test".to_string(),
+            user_id: "test".to_string(),
+            role: Some(SpaceRole::Member),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"space_membership","s":"test","u":"test","ro":"member"}"#,
+        );
+    }
+
+    #[test]
+    fn test_space_command_denied_event() {
+        let event = Event::SpaceCommandDenied(SpaceCommandDeniedReplyEvent {
+            space: "test".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"space_command_denied","s":"test","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_direct_message_event() {
+        let event = Event::DirectMessage(DirectMessageBroadcastEvent {
+            from_user_id: "from".to_string(),
+            to_user_id: "to".to_string(),
+            content: "test".to_string(),
+            timestamp: 1,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"direct_message","fu":"from","tu":"to","c":"test","t":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_direct_message_denied_event() {
+        let event = Event::DirectMessageDenied(DirectMessageDeniedReplyEvent {
+            user_id: "test".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"direct_message_denied","u":"test","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_invitation_event() {
+        let event = Event::RoomInvitation(RoomInvitationBroadcastEvent {
+            room: RoomDetail {
+                name: "test".to_string(),
+                description: "test".to_string(),
+                history_visibility: HistoryVisibility::None,
+                input_template: None,
+            },
+            from_user_id: "user".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_invitation","rd":{"n":"test","d":"test","hv":{"k":"none"}},"fu":"user"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_invitation_denied_event() {
+        let event = Event::RoomInvitationDenied(RoomInvitationDeniedReplyEvent {
+            room: "test".to_string(),
+            user_id: "user".to_string(),
+            reason: "test".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_invitation_denied","r":"test","u":"user","re":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_data_export_event() {
+        let event = Event::UserDataExport(UserDataExportReplyEvent {
+            user_id: "test".to_string(),
+            joined_rooms: vec!["room".to_string()],
+            sessions: vec![ExportedSession {
+                session_id: "session".to_string(),
+                connected_at: 1,
+                disconnected_at: None,
+            }],
+            messages: vec![ExportedMessage {
+                room: "room".to_string(),
+                content: "test".to_string(),
+                timestamp: 2,
+            }],
+            display_name: Some("Tester".to_string()),
+            profile: None,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_data_export","u":"test","jr":["room"],"ss":[{"s":"session","ca":1}],"ms":[{"r":"room","c":"test","t":2}],"dn":"Tester"}"#,
+        );
+    }
+
+    #[test]
+    fn test_account_deletion_scheduled_event() {
+        let event =
+            Event::AccountDeletionScheduled(AccountDeletionScheduledReplyEvent { delete_at: 1 });
+
+        assert_event_serialization(&event, r#"{"_et":"account_deletion_scheduled","da":1}"#);
+    }
+
+    #[test]
+    fn test_rate_limited_event() {
+        let event = Event::RateLimited(RateLimitedReplyEvent { retry_after: 1500 });
+
+        assert_event_serialization(&event, r#"{"_et":"rate_limited","ra":1500}"#);
+    }
+
+    #[test]
+    fn test_presence_changed_event() {
+        let event = Event::PresenceChanged(PresenceChangedBroadcastEvent {
+            presence: UserPresence {
+                user_id: "user-id-1".to_string(),
+                status: PresenceStatus::Away,
+                away_message: Some("lunch".to_string()),
+                display_name: None,
+                color: None,
+            },
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"presence_changed","p":{"u":"user-id-1","s":"away","m":"lunch"}}"#,
+        );
+    }
+
+    #[test]
+    fn test_presence_snapshot_event() {
+        let event = Event::PresenceSnapshot(PresenceSnapshotReplyEvent {
+            users: vec![UserPresence {
+                user_id: "user-id-1".to_string(),
+                status: PresenceStatus::Online,
+                away_message: None,
+                display_name: Some("Alice".to_string()),
+                color: Some("#ff8700".to_string()),
+            }],
+        });
+
+        assert_event_serialization(
+            &event,
+            r##"{"_et":"presence_snapshot","us":[{"u":"user-id-1","s":"online","dn":"Alice","cl":"#ff8700"}]}"##,
+        );
+    }
+
+    #[test]
+    fn test_nickname_changed_event() {
+        let event = Event::NicknameChanged(NicknameChangedBroadcastEvent {
+            user_id: "user-id-1".to_string(),
+            display_name: Some("Alice".to_string()),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"nickname_changed","u":"user-id-1","dn":"Alice"}"#,
+        );
+
+        let event = Event::NicknameChanged(NicknameChangedBroadcastEvent {
+            user_id: "user-id-1".to_string(),
+            display_name: None,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"nickname_changed","u":"user-id-1"}"#);
+    }
+
+    #[test]
+    fn test_user_profile_event() {
+        let event = Event::UserProfile(UserProfileReplyEvent {
+            profile: UserProfile {
+                user_id: "alice".to_string(),
+                bio: Some("Rustacean".to_string()),
+                pronouns: Some("she/her".to_string()),
+                timezone: Some("Europe/Paris".to_string()),
+                ..Default::default()
+            },
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_profile","pf":{"u":"alice","b":"Rustacean","p":"she/her","tz":"Europe/Paris"}}"#,
+        );
+    }
+
+    #[test]
+    fn test_command_ack_event() {
+        let event = Event::CommandAck(CommandAckEvent {
+            request_id: "7".to_string(),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"command_ack","rid":"7"}"#);
+    }
+
+    #[test]
+    fn test_command_error_event() {
+        let event = Event::CommandError(CommandErrorEvent {
+            request_id: None,
+            code: ErrorCode::NotAMember,
+            message: "you are not a member of room 'rust'".to_string(),
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"user_message","r":"test","u":"test","c":"test"}"#,
+            r#"{"_et":"command_error","c":"not_a_member","m":"you are not a member of room 'rust'"}"#,
         );
     }
 }
@@ -0,0 +1,46 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{Blake2s256, Digest};
+
+/// The largest file which can be shared to a room, in bytes
+pub const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// The largest chunk of a file sent in a single command or event, in bytes before encoding
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The checksum of the content of a file, as the hex encoded BLAKE2s-256 digest
+pub fn checksum(content: &[u8]) -> String {
+    Blake2s256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Encodes a chunk of a file to be sent in a command or an event
+pub fn encode_chunk(chunk: &[u8]) -> String {
+    STANDARD.encode(chunk)
+}
+
+/// Decodes a chunk of a file received in a command or an event
+pub fn decode_chunk(data: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(STANDARD.decode(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_is_hex_encoded() {
+        assert_eq!(
+            checksum(b"abc"),
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"
+        );
+    }
+
+    #[test]
+    fn test_chunk_round_trips() {
+        let chunk = vec![0, 1, 2, 254, 255];
+
+        assert_eq!(decode_chunk(&encode_chunk(&chunk)).unwrap(), chunk);
+        assert!(decode_chunk("not base64!").is_err());
+    }
+}
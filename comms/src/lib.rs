@@ -1,7 +1,16 @@
+/// Requests and responses of the admin console of the server, served over a Unix socket
+pub mod admin;
+/// Reading of the flags of the command line of the binaries of the workspace
+pub mod cli;
 /// Set of commands which the server can receive and process
 pub mod command;
 /// Set of events split into Broadcast and Reply events according to their source
 pub mod event;
-/// Implementation of event and command transportation over TCP Streams.
-/// Requires 'server' or 'client' features to be enabled and will bring in tokio dependency alongside with other dependencies
+/// Limits, checksums and chunk encoding of the files transferred over the protocol
+pub mod file_transfer;
+/// Protocol versions and the translation of events for older clients
+pub mod protocol;
+/// Implementation of event and command transportation over TCP Streams, or any other stream such as TLS over TCP.
+/// Requires 'server' or 'client' features to be enabled and will bring in tokio dependency alongside with other dependencies.
+/// The 'websocket' feature also lets the server serve clients over WebSockets
 pub mod transport;
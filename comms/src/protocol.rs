@@ -0,0 +1,304 @@
+use crate::event::{Event, UserMessageBroadcastEvent};
+
+/// The latest version of the protocol, which clients announce with a hello command
+pub const PROTOCOL_VERSION: u16 = 5;
+
+/// The optional features a server announces in its welcome, clients ignore the ones they do not know
+pub mod features {
+    /// Commands sent with a request id are answered with an acknowledgement or an error carrying the id
+    pub const REQUEST_IDS: &str = "request_ids";
+    /// A dropped session can be resumed with the token given on login
+    pub const SESSION_RESUME: &str = "session_resume";
+    /// Messages and joins sent too fast are dropped, and the client is told when to retry
+    pub const RATE_LIMITS: &str = "rate_limits";
+    /// The client is pinged periodically, and disconnected once it misses too many pongs
+    pub const HEARTBEAT: &str = "heartbeat";
+    /// The stored history of the rooms can be searched
+    pub const MESSAGE_SEARCH: &str = "message_search";
+    /// The messages older than the ones received can be fetched a page at a time
+    pub const HISTORY_PAGINATION: &str = "history_pagination";
+    /// The last message each user has read in a room is kept, and sent back with the history of the room
+    pub const READ_MARKERS: &str = "read_markers";
+    /// Files can be shared with the rooms, uploaded and downloaded in chunks
+    pub const FILE_TRANSFER: &str = "file_transfer";
+    /// The users can change the name they are shown with, apart from their username
+    pub const NICKNAMES: &str = "nicknames";
+    /// The users have profiles, which the others can ask for
+    pub const PROFILES: &str = "profiles";
+    /// The public rooms can be browsed a page at a time, with their member counts and latest activity
+    pub const ROOM_DIRECTORY: &str = "room_directory";
+}
+
+/// The versions of the protocol a server can serve side by side on the same listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolVersion {
+    /// The initial protocol without a handshake, clients wait for the login event right after connecting
+    V1,
+    /// Clients announce their version with a hello command before the login event is sent
+    V2,
+    /// Clients log in with their credentials after the hello command, v1 and v2 clients are served as guests
+    V3,
+    /// The server answers the hello command with a welcome, carrying what it supports
+    V4,
+    /// The server pings the clients, which answer with a pong to show their connection is alive
+    V5,
+}
+
+impl ProtocolVersion {
+    /// Maps the version announced by a client to the closest version the library can serve
+    pub fn from_announced(version: u16) -> Self {
+        if version >= 5 {
+            ProtocolVersion::V5
+        } else if version == 4 {
+            ProtocolVersion::V4
+        } else if version == 3 {
+            ProtocolVersion::V3
+        } else if version == 2 {
+            ProtocolVersion::V2
+        } else {
+            ProtocolVersion::V1
+        }
+    }
+
+    pub fn number(&self) -> u16 {
+        match self {
+            ProtocolVersion::V1 => 1,
+            ProtocolVersion::V2 => 2,
+            ProtocolVersion::V3 => 3,
+            ProtocolVersion::V4 => 4,
+            ProtocolVersion::V5 => 5,
+        }
+    }
+
+    /// Whether the clients of the version log in with their credentials, and can resume their sessions
+    pub fn has_login(&self) -> bool {
+        matches!(
+            self,
+            ProtocolVersion::V3 | ProtocolVersion::V4 | ProtocolVersion::V5
+        )
+    }
+
+    /// Whether the clients of the version answer the pings of the server
+    pub fn has_heartbeat(&self) -> bool {
+        *self == ProtocolVersion::V5
+    }
+}
+
+/// Translates an event into the events a client of the given version can understand
+///
+/// Events which have no equivalent in an older version are dropped.
+pub fn translate_event(event: Event, version: ProtocolVersion) -> Vec<Event> {
+    if version == ProtocolVersion::V5 {
+        return vec![event];
+    }
+
+    // only v5 clients answer the pings
+    if let Event::Ping(_) = event {
+        return vec![];
+    }
+
+    if version == ProtocolVersion::V4 {
+        return vec![event];
+    }
+
+    // only v4 clients are welcomed with what the server supports
+    if let Event::Welcome(_) = event {
+        return vec![];
+    }
+
+    if version == ProtocolVersion::V3 {
+        return vec![event];
+    }
+
+    // only v3 clients log in with credentials and resume their sessions
+    if let Event::LoginResult(_) | Event::ResumeResult(_) = event {
+        return vec![];
+    }
+
+    if version == ProtocolVersion::V2 {
+        return vec![event];
+    }
+
+    match event {
+        // v1 clients have no notion of history, the messages are replayed as regular messages
+        Event::RoomHistory(event) => event
+            .messages
+            .into_iter()
+            .map(|message| {
+                Event::UserMessage(UserMessageBroadcastEvent {
+                    room: event.room.clone(),
+                    id: message.id,
+                    user_id: message.user_id,
+                    content: message.content,
+                    timestamp: message.timestamp,
+                    reply_to: message.reply_to,
+                })
+            })
+            .collect(),
+        Event::Welcome(_)
+        | Event::Ping(_)
+        | Event::LoginResult(_)
+        | Event::ResumeResult(_)
+        | Event::RoomJoinDenied(_)
+        | Event::RoomCreated(_)
+        | Event::RoomDeleted(_)
+        | Event::RoomTopicChanged(_)
+        | Event::RoomRole(_)
+        | Event::UserModerated(_)
+        | Event::ModerationDenied(_)
+        | Event::RoomManagementDenied(_)
+        | Event::MessageEdited(_)
+        | Event::MessageDeleted(_)
+        | Event::MessageChangeDenied(_)
+        | Event::MessageReactions(_)
+        | Event::HistoryTruncated(_)
+        | Event::OlderMessages(_)
+        | Event::RoomHistoryChunk(_)
+        | Event::RoomHistoryExportDenied(_)
+        | Event::SearchResults(_)
+        | Event::RoomDirectory(_)
+        | Event::UploadProgress(_)
+        | Event::FileShared(_)
+        | Event::FileChunk(_)
+        | Event::FileTransferDenied(_)
+        | Event::UserJoinedSpace(_)
+        | Event::SpaceMembership(_)
+        | Event::SpaceCommandDenied(_)
+        | Event::DirectMessage(_)
+        | Event::DirectMessageDenied(_)
+        | Event::RoomInvitation(_)
+        | Event::RoomInvitationDenied(_)
+        | Event::UserDataExport(_)
+        | Event::AccountDeletionScheduled(_)
+        | Event::RateLimited(_)
+        | Event::PresenceChanged(_)
+        | Event::PresenceSnapshot(_)
+        | Event::NicknameChanged(_)
+        | Event::UserProfile(_)
+        | Event::CommandAck(_)
+        | Event::CommandError(_)
+        | Event::ServerShuttingDown(_)
+        | Event::Announcement(_)
+        | Event::SessionKicked(_)
+        | Event::Disconnected(_) => vec![],
+        // the clients the server does not serve anymore are told so before being disconnected, whether they understand it or not
+        Event::ProtocolRejected(_)
+        | Event::LoginSuccessful(_)
+        | Event::RoomParticipation(_)
+        | Event::UserJoinedRoom(_)
+        | Event::UserMessage(_) => vec![event],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{
+        AccountDeletionScheduledReplyEvent, HistoryMessage, LoginResultReplyEvent, PingEvent,
+        RoomHistoryReplyEvent, WelcomeReplyEvent,
+    };
+
+    #[test]
+    fn test_from_announced() {
+        assert_eq!(ProtocolVersion::from_announced(1), ProtocolVersion::V1);
+        assert_eq!(ProtocolVersion::from_announced(2), ProtocolVersion::V2);
+        assert_eq!(ProtocolVersion::from_announced(3), ProtocolVersion::V3);
+        assert_eq!(ProtocolVersion::from_announced(4), ProtocolVersion::V4);
+        assert_eq!(ProtocolVersion::from_announced(5), ProtocolVersion::V5);
+        assert_eq!(ProtocolVersion::from_announced(6), ProtocolVersion::V5);
+    }
+
+    #[test]
+    fn test_v2_events_are_kept() {
+        let event =
+            Event::AccountDeletionScheduled(AccountDeletionScheduledReplyEvent { delete_at: 1 });
+
+        assert_eq!(
+            translate_event(event.clone(), ProtocolVersion::V2),
+            vec![event]
+        );
+    }
+
+    #[test]
+    fn test_login_result_is_dropped_for_v2() {
+        let event = Event::LoginResult(LoginResultReplyEvent {
+            is_accepted: true,
+            is_new_user: false,
+            reason: None,
+        });
+
+        assert_eq!(
+            translate_event(event.clone(), ProtocolVersion::V3),
+            vec![event.clone()]
+        );
+        assert!(translate_event(event, ProtocolVersion::V2).is_empty());
+    }
+
+    #[test]
+    fn test_welcome_is_dropped_for_v3() {
+        let event = Event::Welcome(WelcomeReplyEvent {
+            protocol_version: 4,
+            max_message_chars: 2000,
+            features: vec![],
+            heartbeat_interval: None,
+        });
+
+        assert_eq!(
+            translate_event(event.clone(), ProtocolVersion::V4),
+            vec![event.clone()]
+        );
+        assert!(translate_event(event, ProtocolVersion::V3).is_empty());
+    }
+
+    #[test]
+    fn test_ping_is_dropped_for_v4() {
+        let event = Event::Ping(PingEvent {
+            nonce: 1,
+            round_trip: None,
+        });
+
+        assert_eq!(
+            translate_event(event.clone(), ProtocolVersion::V5),
+            vec![event.clone()]
+        );
+        assert!(translate_event(event, ProtocolVersion::V4).is_empty());
+    }
+
+    #[test]
+    fn test_room_history_is_replayed_for_v1() {
+        let event = Event::RoomHistory(RoomHistoryReplyEvent {
+            room: "room".to_string(),
+            messages: vec![HistoryMessage {
+                id: 1,
+                user_id: "user".to_string(),
+                content: "test".to_string(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            around: None,
+            last_read: None,
+        });
+
+        assert_eq!(
+            translate_event(event, ProtocolVersion::V1),
+            vec![Event::UserMessage(UserMessageBroadcastEvent {
+                room: "room".to_string(),
+                id: 1,
+                user_id: "user".to_string(),
+                content: "test".to_string(),
+                timestamp: 1,
+                reply_to: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_v2_only_events_are_dropped_for_v1() {
+        let event =
+            Event::AccountDeletionScheduled(AccountDeletionScheduledReplyEvent { delete_at: 1 });
+
+        assert!(translate_event(event, ProtocolVersion::V1).is_empty());
+    }
+}
@@ -1,13 +1,23 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
 use anyhow::Context;
+use serde::Serialize;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{tcp::OwnedWriteHalf, TcpStream},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    sync::oneshot,
 };
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tokio_stream::StreamExt;
 
 use crate::{command, event};
 
-use super::common::{BoxedStream, NEW_LINE};
+use super::common::{self, BoxedStream, BoxedWriter, DecodeError, Framing};
 
 /// [EventStream] is a stream of [crate::event::Event]s sent by the server
 ///
@@ -15,19 +25,23 @@ use super::common::{BoxedStream, NEW_LINE};
 ///
 /// This stream is cancel-safe, meaning that it can be used in [tokio::select]
 /// without the risk of missing events.
-pub type EventStream = BoxedStream<anyhow::Result<event::Event>>;
+pub type EventStream = BoxedStream<Result<event::Event, DecodeError>>;
 
-/// [CommandWriter] is a wrapper around a [TcpStream] which writes [crate::command::UserCommand]s to the server
+/// [CommandWriter] is a wrapper around the write half of a stream, such as a [TcpStream], which writes [crate::command::UserCommand]s to the server
 pub struct CommandWriter {
-    writer: OwnedWriteHalf,
+    writer: BoxedWriter,
+    framing: Framing,
 }
 
 impl CommandWriter {
-    pub fn new(writer: OwnedWriteHalf) -> Self {
-        Self { writer }
+    pub fn new<W: AsyncWrite + Send + 'static>(writer: W, framing: Framing) -> Self {
+        Self {
+            writer: Box::pin(writer),
+            framing,
+        }
     }
 
-    /// Send a [crate::command::UserCommand] to the backing [TcpStream]
+    /// Send a [crate::command::UserCommand] to the backing stream
     ///
     /// # Cancel Safety
     ///
@@ -37,16 +51,114 @@ impl CommandWriter {
     /// partially written, but future calls to `write` will start over
     /// from the beginning of the buffer. Causing undefined behaviour.
     pub async fn write(&mut self, command: &command::UserCommand) -> anyhow::Result<()> {
-        let mut serialized_bytes = serde_json::to_vec(command)?;
-        serialized_bytes.extend_from_slice(NEW_LINE);
+        self.write_serialized(command).await
+    }
+
+    /// Send a [crate::command::CommandRequest] to the backing stream, see [CommandWriter::write] for its cancel safety
+    pub async fn write_request(&mut self, request: &command::CommandRequest) -> anyhow::Result<()> {
+        self.write_serialized(request).await
+    }
+
+    /// Sends the command with a new request id, and waits for the server to acknowledge it or to report its failure
+    ///
+    /// The events have to be passed to [PendingRequests::resolve] meanwhile, such as by another task reading the [EventStream].
+    pub async fn send_and_wait(
+        &mut self,
+        requests: &PendingRequests,
+        command: command::UserCommand,
+    ) -> anyhow::Result<RequestOutcome> {
+        let request_id = requests.next_request_id();
+        let outcome_rx = requests.register(&request_id);
+
+        if let Err(err) = self
+            .write_request(&command::CommandRequest {
+                request_id: Some(request_id.clone()),
+                command,
+            })
+            .await
+        {
+            requests.forget(&request_id);
+
+            return Err(err);
+        }
+
+        outcome_rx
+            .await
+            .context("the request was dropped before the server answered it")
+    }
+
+    async fn write_serialized<T: Serialize>(&mut self, value: &T) -> anyhow::Result<()> {
+        let frame = self.framing.encode(serde_json::to_vec(value)?)?;
 
-        self.writer.write_all(serialized_bytes.as_slice()).await?;
+        self.writer.write_all(frame.as_slice()).await?;
 
         Ok(())
     }
 }
 
-/// Splits a TCP stream into a stream of events and a command writer.
+/// How the server has answered a request, with the error it reported if the command failed
+pub type RequestOutcome = Result<(), event::CommandErrorEvent>;
+
+/// [PendingRequests] correlates the commands sent with a request id to the events answering them
+///
+/// It is cheap to clone, the clones share the requests, so the writer of the commands and the reader of the events can each hold one.
+#[derive(Debug, Clone, Default)]
+pub struct PendingRequests {
+    next_id: Arc<AtomicU64>,
+    outcome_txs: Arc<Mutex<HashMap<String, oneshot::Sender<RequestOutcome>>>>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        PendingRequests::default()
+    }
+
+    /// Hands out a request id which is unique among the requests sharing these pending requests
+    pub fn next_request_id(&self) -> String {
+        self.next_id.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+
+    /// Waits for the answer to the request with the given id, see [PendingRequests::next_request_id]
+    pub fn register(&self, request_id: &str) -> oneshot::Receiver<RequestOutcome> {
+        let (outcome_tx, outcome_rx) = oneshot::channel();
+
+        self.outcome_txs
+            .lock()
+            .unwrap()
+            .insert(String::from(request_id), outcome_tx);
+
+        outcome_rx
+    }
+
+    /// Stops waiting for the answer to the request, such as when it could not be sent
+    pub fn forget(&self, request_id: &str) {
+        self.outcome_txs.lock().unwrap().remove(request_id);
+    }
+
+    /// Completes the request the event answers, returns false if the event does not answer a pending request
+    pub fn resolve(&self, event: &event::Event) -> bool {
+        let (request_id, outcome) = match event {
+            event::Event::CommandAck(event) => (&event.request_id, Ok(())),
+            event::Event::CommandError(event) => match &event.request_id {
+                Some(request_id) => (request_id, Err(event.clone())),
+                None => return false,
+            },
+            _ => return false,
+        };
+
+        match self.outcome_txs.lock().unwrap().remove(request_id) {
+            Some(outcome_tx) => {
+                // the waiting side may have given up on the request
+                let _ = outcome_tx.send(outcome);
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Splits a TCP stream into a stream of events and a command writer, which writes length-prefixed frames.
 ///
 /// # Arguments
 ///
@@ -54,16 +166,47 @@ impl CommandWriter {
 pub fn split_tcp_stream(stream: TcpStream) -> (EventStream, CommandWriter) {
     let (reader, writer) = stream.into_split();
 
+    from_halves(reader, writer, Framing::LengthPrefixed)
+}
+
+/// Splits any stream, such as a TLS stream over TCP, into a stream of events and a command writer,
+/// which writes length-prefixed frames.
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split
+pub fn split_stream<S>(stream: S) -> (EventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    split_stream_with_framing(stream, Framing::LengthPrefixed)
+}
+
+/// Splits any stream into a stream of events and a command writer, which writes the commands in the given framing.
+///
+/// Writing [Framing::Lines] talks to the servers which predate the length-prefixed frames.
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split
+/// - `framing` - How the commands are delimited
+pub fn split_stream_with_framing<S>(stream: S, framing: Framing) -> (EventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+
+    from_halves(reader, writer, framing)
+}
+
+/// The events are read in the framing the server writes them in, whatever the framing of the commands
+fn from_halves<R, W>(reader: R, writer: W, framing: Framing) -> (EventStream, CommandWriter)
+where
+    R: AsyncRead + Send + 'static,
+    W: AsyncWrite + Send + 'static,
+{
     (
-        Box::pin(
-            LinesStream::new(BufReader::new(reader).lines()).map(|line| {
-                line.context("could not read line from the server")
-                    .and_then(|line| {
-                        serde_json::from_str::<event::Event>(&line)
-                            .context("failed to deserialize event from the server")
-                    })
-            }),
-        ),
-        CommandWriter::new(writer),
+        Box::pin(common::read_frames(reader, |_| {}).map(common::decode)),
+        CommandWriter::new(writer, framing),
     )
 }
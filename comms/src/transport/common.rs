@@ -1,7 +1,408 @@
-use std::pin::Pin;
+use std::{error, fmt, io, pin::Pin};
 
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader};
 use tokio_stream::Stream;
 
 pub const NEW_LINE: &[u8; 2] = b"\r\n";
 
+/// The longest frame which is read or written, kept below 16 MiB so the first byte of a frame is always zero
+pub const MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
 pub type BoxedStream<Item> = Pin<Box<dyn Stream<Item = Item> + Send>>;
+
+pub type BoxedWriter = Pin<Box<dyn AsyncWrite + Send>>;
+
+type BoxedReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// [DecodeError] is why a command or an event could not be read from a byte stream
+///
+/// Only a malformed payload leaves the stream going, the other errors end it since the framing is lost.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// Reading from the byte stream failed
+    Io(io::Error),
+    /// The byte stream was closed in the middle of a frame
+    Truncated,
+    /// A frame or a line is longer than [MAX_FRAME_LEN] bytes, the given length being how many were seen
+    TooLong { len: usize },
+    /// The payload is not the JSON of a command or an event
+    Malformed(serde_json::Error),
+}
+
+impl DecodeError {
+    /// Whether the stream goes on with the next payload
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, DecodeError::Malformed(_))
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Io(err) => write!(f, "could not read from the connection: {}", err),
+            DecodeError::Truncated => {
+                write!(f, "the connection was closed in the middle of a frame")
+            }
+            DecodeError::TooLong { len } => write!(
+                f,
+                "the frame of {} bytes is longer than the {} bytes allowed",
+                len, MAX_FRAME_LEN
+            ),
+            DecodeError::Malformed(err) => write!(f, "could not parse the payload: {}", err),
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DecodeError::Io(err) => Some(err),
+            DecodeError::Malformed(err) => Some(err),
+            DecodeError::Truncated | DecodeError::TooLong { .. } => None,
+        }
+    }
+}
+
+/// Parses the payload of a frame, passing the errors of the frame along
+pub fn decode<T: DeserializeOwned>(frame: Result<Vec<u8>, DecodeError>) -> Result<T, DecodeError> {
+    serde_json::from_slice(&frame?).map_err(DecodeError::Malformed)
+}
+
+#[cfg(feature = "server")]
+pub type BoxedSink<Item> = Pin<Box<dyn futures_util::Sink<Item, Error = anyhow::Error> + Send>>;
+
+/// [Framing] is how the serialized commands and events are delimited on a byte stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Each one is prefixed with its length as a big-endian u32, so it can hold any bytes
+    LengthPrefixed,
+    /// Each one is written on its own line, as the clients predating the frames do
+    Lines,
+}
+
+impl Framing {
+    /// Tells the framing of a byte stream from its first byte,
+    /// which is zero for a frame and the start of a JSON object for a line
+    async fn detect<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Framing {
+        match reader.fill_buf().await {
+            Ok([0, ..]) => Framing::LengthPrefixed,
+            // the errors are reported by the next read
+            _ => Framing::Lines,
+        }
+    }
+
+    /// Delimits a serialized command or event to write it
+    pub fn encode(self, mut payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Framing::LengthPrefixed => {
+                anyhow::ensure!(
+                    payload.len() <= MAX_FRAME_LEN,
+                    "the frame of {} bytes is longer than the {} bytes allowed",
+                    payload.len(),
+                    MAX_FRAME_LEN
+                );
+
+                let mut frame = Vec::with_capacity(4 + payload.len());
+                frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                frame.extend_from_slice(&payload);
+
+                Ok(frame)
+            }
+            Framing::Lines => {
+                payload.extend_from_slice(NEW_LINE);
+
+                Ok(payload)
+            }
+        }
+    }
+}
+
+/// Reads the serialized commands or events of a byte stream, in the framing detected from its first byte
+///
+/// The detected framing is passed to `on_detected` before the first one is read.
+/// The stream ends when the byte stream is closed, or right after an error since the framing is lost.
+pub fn read_frames<R, F>(reader: R, on_detected: F) -> BoxedStream<Result<Vec<u8>, DecodeError>>
+where
+    R: AsyncRead + Send + 'static,
+    F: FnOnce(Framing) + Send + 'static,
+{
+    let reader: BoxedReader = Box::pin(reader);
+    let mut reader = BufReader::new(reader);
+
+    let frames = futures_util::stream::once(async move {
+        let framing = Framing::detect(&mut reader).await;
+        on_detected(framing);
+
+        futures_util::stream::unfold(Some(reader), move |reader| async move {
+            let mut reader = reader?;
+            let frame = match framing {
+                Framing::LengthPrefixed => read_frame(&mut reader).await,
+                Framing::Lines => read_line(&mut reader).await,
+            };
+
+            match frame {
+                Ok(Some(frame)) => Some((Ok(frame), Some(reader))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    });
+
+    Box::pin(futures_util::StreamExt::flatten(frames))
+}
+
+/// Reads a length-prefixed frame, or nothing if the byte stream is closed before it
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>, DecodeError> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(DecodeError::Io(err)),
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(DecodeError::TooLong { len });
+    }
+
+    // the buffer grows as the bytes arrive, the length being announced by the peer
+    let mut frame = Vec::new();
+    reader
+        .take(len as u64)
+        .read_to_end(&mut frame)
+        .await
+        .map_err(DecodeError::Io)?;
+
+    if frame.len() < len {
+        return Err(DecodeError::Truncated);
+    }
+
+    Ok(Some(frame))
+}
+
+/// Reads a line without its line ending, or nothing if the byte stream is closed before it
+///
+/// The last line may be left unterminated. A line is read up to [MAX_FRAME_LEN] bytes, so a client
+/// which never ends its line can not have it buffered without bounds.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<Vec<u8>>, DecodeError> {
+    let mut line = Vec::new();
+    let limit = (MAX_FRAME_LEN + NEW_LINE.len()) as u64;
+    reader
+        .take(limit)
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(DecodeError::Io)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    if line.len() > MAX_FRAME_LEN {
+        return Err(DecodeError::TooLong { len: line.len() });
+    }
+
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{command::CommandRequest, event::Event};
+
+    async fn collect_frames(
+        bytes: Vec<u8>,
+    ) -> (Option<Framing>, Vec<Result<Vec<u8>, DecodeError>>) {
+        let (framing_tx, framing_rx) = tokio::sync::oneshot::channel();
+        let frames = read_frames(io::Cursor::new(bytes), move |framing| {
+            let _ = framing_tx.send(framing);
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+        (framing_rx.await.ok(), frames)
+    }
+
+    #[tokio::test]
+    async fn test_frames_keep_their_new_lines() {
+        let payloads = vec![b"{\"m\":\"a\nb\"}".to_vec(), b"{}".to_vec()];
+        let mut bytes = Vec::new();
+        for payload in payloads.clone() {
+            bytes.extend(Framing::LengthPrefixed.encode(payload).unwrap());
+        }
+
+        let (framing, frames) = collect_frames(bytes).await;
+
+        assert_eq!(framing, Some(Framing::LengthPrefixed));
+        assert_eq!(
+            frames.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            payloads
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lines_are_detected() {
+        let mut bytes = Framing::Lines.encode(b"{\"a\":1}".to_vec()).unwrap();
+        bytes.extend(Framing::Lines.encode(b"{}".to_vec()).unwrap());
+
+        let (framing, frames) = collect_frames(bytes).await;
+
+        assert_eq!(framing, Some(Framing::Lines));
+        assert_eq!(
+            frames.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![b"{\"a\":1}".to_vec(), b"{}".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frames_longer_than_allowed_end_the_stream() {
+        let mut bytes = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes().to_vec();
+        bytes.extend(Framing::LengthPrefixed.encode(b"{}".to_vec()).unwrap());
+
+        let (_, frames) = collect_frames(bytes).await;
+
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            frames[0],
+            Err(DecodeError::TooLong { len }) if len == MAX_FRAME_LEN + 1
+        ));
+        assert!(Framing::LengthPrefixed
+            .encode(vec![b' '; MAX_FRAME_LEN + 1])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lines_longer_than_allowed_end_the_stream() {
+        // a line which never ends is not buffered past the limit
+        let mut bytes = vec![b'{'; MAX_FRAME_LEN * 2];
+        bytes.extend(Framing::Lines.encode(b"{}".to_vec()).unwrap());
+
+        let (framing, frames) = collect_frames(bytes).await;
+
+        assert_eq!(framing, Some(Framing::Lines));
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(frames[0], Err(DecodeError::TooLong { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_truncated_frames_end_the_stream() {
+        let mut bytes = Framing::LengthPrefixed.encode(b"{}".to_vec()).unwrap();
+        bytes.extend(
+            &Framing::LengthPrefixed
+                .encode(b"{\"a\":1}".to_vec())
+                .unwrap()[..6],
+        );
+
+        let (_, frames) = collect_frames(bytes).await;
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].as_ref().unwrap(), b"{}");
+        assert!(matches!(frames[1], Err(DecodeError::Truncated)));
+    }
+
+    #[tokio::test]
+    async fn test_frames_announcing_more_than_they_send_end_the_stream() {
+        let mut bytes = (MAX_FRAME_LEN as u32).to_be_bytes().to_vec();
+        bytes.extend(b"{}");
+
+        let (_, frames) = collect_frames(bytes).await;
+
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(frames[0], Err(DecodeError::Truncated)));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_payloads_are_recoverable() {
+        let mut bytes = Framing::Lines.encode(b"{\"_ct\":".to_vec()).unwrap();
+        bytes.extend(Framing::Lines.encode(vec![b'{', 0xff, b'}']).unwrap());
+        bytes.extend(
+            Framing::Lines
+                .encode(br#"{"_ct":"quit"}"#.to_vec())
+                .unwrap(),
+        );
+
+        let (_, frames) = collect_frames(bytes).await;
+        let commands = frames
+            .into_iter()
+            .map(decode::<CommandRequest>)
+            .collect::<Vec<_>>();
+
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(&commands[0], Err(err) if err.is_recoverable()));
+        assert!(matches!(&commands[1], Err(err) if err.is_recoverable()));
+        assert!(commands[2].is_ok());
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    proptest! {
+        /// Whatever a hostile peer sends, the frames are read until the bytes or the framing run out,
+        /// and decoded, without panicking
+        #[test]
+        fn test_garbage_is_read_without_panicking(
+            bytes in prop::collection::vec(any::<u8>(), 0..4096),
+            is_length_prefixed in any::<bool>(),
+        ) {
+            let mut bytes = bytes;
+            if is_length_prefixed {
+                bytes.insert(0, 0);
+            }
+
+            let (_, frames) = block_on(collect_frames(bytes));
+
+            // nothing is read after an error, the framing is lost
+            if let Some(index) = frames.iter().position(Result::is_err) {
+                prop_assert_eq!(index, frames.len() - 1);
+            }
+            for payload in frames.into_iter().flatten() {
+                let _ = decode::<CommandRequest>(Ok(payload.clone()));
+                let _ = decode::<Event>(Ok(payload));
+            }
+        }
+
+        #[test]
+        fn test_frames_round_trip(payloads in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..512), 0..16)) {
+            let mut bytes = Vec::new();
+            for payload in payloads.iter() {
+                bytes.extend(Framing::LengthPrefixed.encode(payload.clone()).unwrap());
+            }
+
+            let (_, frames) = block_on(collect_frames(bytes));
+
+            prop_assert_eq!(frames.into_iter().map(Result::unwrap).collect::<Vec<_>>(), payloads);
+        }
+
+        /// The lines of JSON never hold a new line, nor start with a zero byte
+        #[test]
+        fn test_lines_round_trip(lines in prop::collection::vec("[^\\x00\\r\\n][^\\r\\n]{0,256}", 1..16)) {
+            let mut bytes = Vec::new();
+            for line in lines.iter() {
+                bytes.extend(Framing::Lines.encode(line.clone().into_bytes()).unwrap());
+            }
+
+            let (framing, frames) = block_on(collect_frames(bytes));
+
+            prop_assert_eq!(framing, Some(Framing::Lines));
+            prop_assert_eq!(
+                frames.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+                lines.into_iter().map(String::into_bytes).collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -3,6 +3,10 @@
 pub mod client;
 #[cfg(any(feature = "client", feature = "server"))]
 mod common;
-/// Transport over TCP implementation for a server to interact with a single client TCP Stream
+/// Transport over TCP implementation for a server to interact with a single client TCP Stream,
+/// and over WebSockets with the 'websocket' feature
 #[cfg(feature = "server")]
 pub mod server;
+
+#[cfg(any(feature = "client", feature = "server"))]
+pub use common::{DecodeError, Framing, MAX_FRAME_LEN};
@@ -1,33 +1,71 @@
-use anyhow::Context;
+use std::sync::{Arc, OnceLock};
+
+use futures_util::SinkExt;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{tcp::OwnedWriteHalf, TcpStream},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
 };
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tokio_stream::StreamExt;
 
 use crate::{command, event};
 
-use super::common::{BoxedStream, NEW_LINE};
+use super::common::{self, BoxedSink, BoxedStream, BoxedWriter, DecodeError, Framing};
 
-/// [CommandStream] is a stream of [crate::command::UserCommand]s sent by the client
+/// [CommandStream] is a stream of [crate::command::CommandRequest]s sent by the client
 ///
 /// # Cancel Safety
 ///
 /// This stream is cancel-safe, meaning that it can be used in [tokio::select!]
 /// without the risk of missing commands.
-pub type CommandStream = BoxedStream<anyhow::Result<command::UserCommand>>;
+pub type CommandStream = BoxedStream<Result<command::CommandRequest, DecodeError>>;
 
-/// [EventWriter] is a wrapper around a [TcpStream] which writes [crate::event::Event]s to the client
+/// [EventWriter] is a wrapper around the write half of a connection, such as a [TcpStream] or a WebSocket, which writes [crate::event::Event]s to the client
 pub struct EventWriter {
-    writer: OwnedWriteHalf,
+    /// Takes the serialized events, and frames them for the connection
+    frames: BoxedSink<String>,
 }
 
 impl EventWriter {
-    pub fn new(writer: OwnedWriteHalf) -> Self {
-        Self { writer }
+    /// Writes the events to a byte stream, one per line
+    pub fn new<W: AsyncWrite + Send + 'static>(writer: W) -> Self {
+        Self::with_framing(writer, Arc::new(OnceLock::from(Framing::Lines)))
+    }
+
+    /// Writes the events to a byte stream in the framing the client has been detected to use
+    ///
+    /// The events written before the client has sent anything are written one per line,
+    /// as the framing can not change once something has been written.
+    fn with_framing<W: AsyncWrite + Send + 'static>(
+        writer: W,
+        framing: Arc<OnceLock<Framing>>,
+    ) -> Self {
+        let writer: BoxedWriter = Box::pin(writer);
+
+        Self::from_frames(futures_util::sink::unfold(
+            (writer, framing),
+            |(mut writer, framing), frame: String| async move {
+                let bytes = framing
+                    .get_or_init(|| Framing::Lines)
+                    .encode(frame.into_bytes())?;
+
+                writer.write_all(bytes.as_slice()).await?;
+
+                Ok::<_, anyhow::Error>((writer, framing))
+            },
+        ))
     }
 
-    /// Send a [crate::event::Event] to the backing [TcpStream]
+    /// Writes each serialized event as a frame to the given sink
+    fn from_frames<S>(frames: S) -> Self
+    where
+        S: futures_util::Sink<String, Error = anyhow::Error> + Send + 'static,
+    {
+        Self {
+            frames: Box::pin(frames),
+        }
+    }
+
+    /// Send a [crate::event::Event] to the backing stream
     ///
     /// # Cancel Safety
     ///
@@ -37,12 +75,9 @@ impl EventWriter {
     /// partially written, but future calls to `write` will start over
     /// from the beginning of the buffer. Causing undefined behaviour.
     pub async fn write(&mut self, event: &event::Event) -> anyhow::Result<()> {
-        let mut serialized_bytes = serde_json::to_vec(event)?;
-        serialized_bytes.extend_from_slice(NEW_LINE);
+        let serialized = serde_json::to_string(event)?;
 
-        self.writer.write_all(serialized_bytes.as_slice()).await?;
-
-        Ok(())
+        self.frames.send(serialized).await
     }
 }
 
@@ -54,16 +89,76 @@ impl EventWriter {
 pub fn split_tcp_stream(stream: TcpStream) -> (CommandStream, EventWriter) {
     let (reader, writer) = stream.into_split();
 
+    from_halves(reader, writer)
+}
+
+/// Splits any stream, such as a TLS stream over TCP, into a stream of commands and an event writer.
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split
+pub fn split_stream<S>(stream: S) -> (CommandStream, EventWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+
+    from_halves(reader, writer)
+}
+
+/// The commands are read in the framing of the client, either length-prefixed frames or lines,
+/// and the events are written back in the same framing
+fn from_halves<R, W>(reader: R, writer: W) -> (CommandStream, EventWriter)
+where
+    R: AsyncRead + Send + 'static,
+    W: AsyncWrite + Send + 'static,
+{
+    let framing = Arc::new(OnceLock::new());
+    let detected_framing = framing.clone();
+
     (
         Box::pin(
-            LinesStream::new(BufReader::new(reader).lines()).map(|line| {
-                line.context("could not read line from the client")
-                    .and_then(|line| {
-                        serde_json::from_str::<command::UserCommand>(&line)
-                            .context("failed to deserialize command from client")
-                    })
-            }),
+            common::read_frames(reader, move |framing| {
+                // the events may already be written one per line to a client which was slow to speak
+                let _ = detected_framing.set(framing);
+            })
+            .map(common::decode),
         ),
-        EventWriter::new(writer),
+        EventWriter::with_framing(writer, framing),
     )
 }
+
+/// Splits a WebSocket into a stream of commands and an event writer, each text message carries a single command or event.
+///
+/// The stream of commands ends when the client closes the WebSocket, or the connection fails.
+///
+/// # Arguments
+///
+/// - `stream` - An accepted [tokio_tungstenite::WebSocketStream] to split
+#[cfg(feature = "websocket")]
+pub fn split_websocket<S>(
+    stream: tokio_tungstenite::WebSocketStream<S>,
+) -> (CommandStream, EventWriter)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    use tokio_tungstenite::tungstenite::Message;
+
+    let (sink, stream) = futures_util::StreamExt::split(stream);
+    let commands = stream
+        .map_while(|message| match message {
+            Ok(Message::Close(_)) | Err(_) => None,
+            Ok(message) => Some(message),
+        })
+        .filter_map(|message| match message {
+            Message::Text(text) => Some(common::decode(Ok(text.into_bytes()))),
+            Message::Binary(bytes) => Some(common::decode(Ok(bytes))),
+            // the pings are answered by the WebSocket itself
+            _ => None,
+        });
+    let frames = sink
+        .sink_map_err(|err| anyhow::Error::new(err).context("could not write to the client"))
+        .with(|frame: String| std::future::ready(Ok::<_, anyhow::Error>(Message::Text(frame))));
+
+    (Box::pin(commands), EventWriter::from_frames(frames))
+}
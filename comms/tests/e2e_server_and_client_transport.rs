@@ -1,12 +1,14 @@
 use comms::{
     command::{self, UserCommand},
     event::{self, Event},
-    transport,
+    transport::{self, client::PendingRequests, Framing},
 };
 use tokio::net::{TcpListener, TcpStream};
 use tokio_stream::StreamExt;
 
 const PORT: usize = 8081;
+const REQUEST_PORT: usize = 8084;
+const LINES_PORT: usize = 8085;
 
 #[tokio::test]
 async fn assert_server_client_transport() {
@@ -21,11 +23,16 @@ async fn assert_server_client_transport() {
         vec![
             UserCommand::JoinRoom(command::JoinRoomCommand {
                 room: "room-1".into(),
-            }),
-            UserCommand::SendMessage(command::SendMessageCommand {
-                room: "room-1".into(),
-                content: "content-1".into(),
-            }),
+            })
+            .into(),
+            command::CommandRequest {
+                request_id: Some("request-1".into()),
+                command: UserCommand::SendMessage(command::SendMessageCommand {
+                    room: "room-1".into(),
+                    content: "content-1".into(),
+                    reply_to: None,
+                }),
+            },
         ]
     );
 
@@ -35,11 +42,13 @@ async fn assert_server_client_transport() {
             user_id: "user-id-1".into(),
             session_id: "session-id-1".into(),
             rooms: Vec::default(),
+            spaces: Vec::default(),
+            resume_token: None,
         }),]
     );
 }
 
-async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
+async fn execute_server() -> anyhow::Result<Vec<command::CommandRequest>> {
     // bind to the example port to wait for client connection
     let listener = TcpListener::bind(format!("0.0.0.0:{}", PORT))
         .await
@@ -62,6 +71,8 @@ async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
             user_id: "user-id-1".into(),
             session_id: "session-id-1".into(),
             rooms: Vec::default(),
+            spaces: Vec::default(),
+            resume_token: None,
         }))
         .await?;
 
@@ -110,11 +121,149 @@ async fn execute_client() -> anyhow::Result<Vec<event::Event>> {
         .await?;
 
     command_writer
-        .write(&UserCommand::SendMessage(command::SendMessageCommand {
-            room: "room-1".into(),
-            content: "content-1".into(),
-        }))
+        .write_request(&command::CommandRequest {
+            request_id: Some("request-1".into()),
+            command: UserCommand::SendMessage(command::SendMessageCommand {
+                room: "room-1".into(),
+                content: "content-1".into(),
+                reply_to: None,
+            }),
+        })
         .await?;
 
     Ok(collected_events)
 }
+
+#[tokio::test]
+async fn assert_send_and_wait() {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", REQUEST_PORT))
+        .await
+        .expect("could not bind to the port");
+
+    // the server acknowledges the first request and fails the second one
+    let server = tokio::spawn(async move {
+        let (tcp_stream, _addr) = listener.accept().await?;
+        let (mut command_stream, mut event_writer) =
+            transport::server::split_tcp_stream(tcp_stream);
+        let mut is_first = true;
+
+        while let Some(request) = command_stream.next().await {
+            let request_id = request?.request_id.expect("the request has an id");
+            let event = if is_first {
+                Event::CommandAck(event::CommandAckEvent { request_id })
+            } else {
+                Event::CommandError(event::CommandErrorEvent {
+                    request_id: Some(request_id),
+                    code: event::ErrorCode::RoomNotFound,
+                    message: "room 'room-2' not found".into(),
+                })
+            };
+            is_first = false;
+
+            event_writer.write(&event).await?;
+        }
+
+        anyhow::Ok(())
+    });
+
+    let tcp_stream = TcpStream::connect(format!("localhost:{}", REQUEST_PORT))
+        .await
+        .expect("could not connect to the server");
+    let (mut event_stream, mut command_writer) = transport::client::split_tcp_stream(tcp_stream);
+    let requests = PendingRequests::new();
+
+    // the events are read by another task, which resolves the requests they answer
+    tokio::spawn({
+        let requests = requests.clone();
+
+        async move {
+            while let Some(Ok(event)) = event_stream.next().await {
+                requests.resolve(&event);
+            }
+        }
+    });
+
+    let joined = command_writer
+        .send_and_wait(
+            &requests,
+            UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "room-1".into(),
+            }),
+        )
+        .await
+        .expect("the request is answered");
+    assert_eq!(joined, Ok(()));
+
+    let joined = command_writer
+        .send_and_wait(
+            &requests,
+            UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "room-2".into(),
+            }),
+        )
+        .await
+        .expect("the request is answered");
+    assert!(matches!(joined, Err(event) if event.code == event::ErrorCode::RoomNotFound));
+
+    drop(command_writer);
+    assert!(server.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn assert_line_delimited_client() {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", LINES_PORT))
+        .await
+        .expect("could not bind to the port");
+
+    // the server answers each command with a message containing a new line
+    let server = tokio::spawn(async move {
+        let (tcp_stream, _addr) = listener.accept().await?;
+        let (mut command_stream, mut event_writer) =
+            transport::server::split_tcp_stream(tcp_stream);
+
+        while let Some(request) = command_stream.next().await {
+            let request_id = request?.request_id.expect("the request has an id");
+
+            event_writer
+                .write(&Event::CommandError(event::CommandErrorEvent {
+                    request_id: Some(request_id),
+                    code: event::ErrorCode::Failed,
+                    message: "first line\nsecond line".into(),
+                }))
+                .await?;
+        }
+
+        anyhow::Ok(())
+    });
+
+    let tcp_stream = TcpStream::connect(format!("localhost:{}", LINES_PORT))
+        .await
+        .expect("could not connect to the server");
+    let (mut event_stream, mut command_writer) =
+        transport::client::split_stream_with_framing(tcp_stream, Framing::Lines);
+
+    for request_id in ["request-1", "request-2"] {
+        command_writer
+            .write_request(&command::CommandRequest {
+                request_id: Some(request_id.into()),
+                command: UserCommand::LeaveRoom(command::LeaveRoomCommand {
+                    room: "room-1".into(),
+                }),
+            })
+            .await
+            .expect("the command is written");
+
+        let event = event_stream
+            .next()
+            .await
+            .expect("the server answers")
+            .expect("the event is read");
+        assert!(matches!(event, Event::CommandError(event)
+            if event.request_id.as_deref() == Some(request_id)
+                && event.message == "first line\nsecond line"));
+    }
+
+    // both halves hold the connection open
+    drop((event_stream, command_writer));
+    assert!(server.await.unwrap().is_ok());
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "websocket")]
+
+use comms::{
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+const PORT: usize = 8083;
+
+#[tokio::test]
+async fn assert_server_websocket_transport() {
+    let (server_collected_commands, client_collected_messages) =
+        tokio::join!(execute_server(), execute_client());
+
+    assert!(server_collected_commands.is_ok());
+    assert!(client_collected_messages.is_ok());
+
+    // the ping is answered by the WebSocket, it never reaches the command stream
+    assert_eq!(
+        server_collected_commands.unwrap(),
+        vec![UserCommand::JoinRoom(command::JoinRoomCommand {
+            room: "room-1".into(),
+        })]
+    );
+
+    let client_collected_messages = client_collected_messages.unwrap();
+    assert_eq!(client_collected_messages.len(), 2);
+    // every event is sent as a single text message
+    assert_eq!(
+        serde_json::from_str::<Event>(&client_collected_messages[0]).unwrap(),
+        Event::LoginSuccessful(event::LoginSuccessfulReplyEvent {
+            user_id: "user-id-1".into(),
+            session_id: "session-id-1".into(),
+            rooms: Vec::default(),
+            spaces: Vec::default(),
+            resume_token: None,
+        })
+    );
+    // the pong echoes the payload of the ping
+    assert_eq!(client_collected_messages[1], "ping");
+}
+
+async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", PORT))
+        .await
+        .expect("could not bind to the port");
+
+    let (tcp_stream, _addr) = listener.accept().await?;
+    let websocket = tokio_tungstenite::accept_async(tcp_stream).await?;
+
+    let (mut command_stream, mut event_writer) = transport::server::split_websocket(websocket);
+    let mut collected_commands = Vec::new();
+
+    event_writer
+        .write(&Event::LoginSuccessful(event::LoginSuccessfulReplyEvent {
+            user_id: "user-id-1".into(),
+            session_id: "session-id-1".into(),
+            rooms: Vec::default(),
+            spaces: Vec::default(),
+            resume_token: None,
+        }))
+        .await?;
+
+    // the stream of commands ends once the client closes the WebSocket
+    while let Some(result) = command_stream.next().await {
+        collected_commands.push(result?.command);
+    }
+
+    Ok(collected_commands)
+}
+
+async fn execute_client() -> anyhow::Result<Vec<String>> {
+    let (mut websocket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://localhost:{}", PORT)).await?;
+    let mut collected_messages = Vec::new();
+
+    match websocket.next().await {
+        Some(Ok(Message::Text(text))) => collected_messages.push(text),
+        other => return Err(anyhow::anyhow!("unexpected message: {:?}", other)),
+    }
+
+    websocket.send(Message::Ping(b"ping".to_vec())).await?;
+    websocket
+        .send(Message::Text(serde_json::to_string(
+            &UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "room-1".into(),
+            }),
+        )?))
+        .await?;
+
+    match websocket.next().await {
+        Some(Ok(Message::Pong(payload))) => collected_messages.push(String::from_utf8(payload)?),
+        other => return Err(anyhow::anyhow!("unexpected message: {:?}", other)),
+    }
+
+    websocket.close(None).await?;
+
+    Ok(collected_messages)
+}
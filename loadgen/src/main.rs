@@ -0,0 +1,206 @@
+//! Simulates users chatting on the chat server, to measure how it performs under load
+//!
+//! Each simulated user is a [client::Client], which logs in, joins one of the rooms and sends messages at a steady
+//! rate. The messages carry the time they were sent at, so every user of the room measures how long they took
+//! to be delivered.
+
+mod report;
+
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use client::Client;
+use comms::cli;
+use rand::Rng;
+use tokio::{task::JoinSet, time::Instant};
+use tokio_stream::StreamExt;
+
+use crate::report::Report;
+
+const DEFAULT_SERVER: &str = "localhost:8080";
+const DEFAULT_USERS: usize = 100;
+const DEFAULT_ROOMS: &str = "general";
+/// Below the default message limit of the server, so the users are not refused
+const DEFAULT_MESSAGES_PER_SECOND: f64 = 1.0;
+const DEFAULT_DURATION_SECS: u64 = 60;
+/// Environment variable with the password the users log in with, the default one is used without it
+const PASSWORD_ENV: &str = "CHAT_LOADGEN_PASSWORD";
+const DEFAULT_PASSWORD: &str = "loadgen_password";
+const USAGE: &str = "usage: loadgen [--server <addr>] [--users <count>] [--rooms <room,room>] [--rate <messages per second>] [--duration <secs>]";
+
+/// The users are named after their index, and registered on their first login
+const USERNAME_PREFIX: &str = "loadgen_";
+const MESSAGE_PREFIX: &str = "loadgen";
+/// How long the users keep receiving once the run is over, for the messages still on their way
+const DRAIN_PERIOD: Duration = Duration::from_secs(5);
+
+/// What the simulated users do, read from the command line
+#[derive(Debug)]
+struct LoadConfig {
+    addr: String,
+    password: String,
+    users: usize,
+    /// The users are spread evenly across the rooms
+    rooms: Vec<String>,
+    /// How many messages each user sends per second
+    messages_per_second: f64,
+    duration: Duration,
+}
+
+/// Reads and parses the value of a command line flag, fails with the usage if it is invalid
+fn parsed_cli_flag<T: FromStr>(name: &str, default: T) -> anyhow::Result<T> {
+    match cli::flag(name) {
+        Some(value) => value.parse().map_err(|_| anyhow::anyhow!(USAGE)),
+        None => Ok(default),
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the clock is before the unix epoch")
+        .as_micros() as u64
+}
+
+/// Returns when a message of a simulated user was sent, in microseconds since the epoch
+fn parse_sent_at(content: &str) -> Option<u64> {
+    content
+        .strip_prefix(MESSAGE_PREFIX)?
+        .strip_prefix(':')?
+        .parse()
+        .ok()
+}
+
+/// Runs a simulated user until the end of the run, recording what it observed
+async fn run_user(
+    user: usize,
+    config: Arc<LoadConfig>,
+    report: Arc<Mutex<Report>>,
+    ends_at: Instant,
+) {
+    let room = &config.rooms[user % config.rooms.len()];
+
+    let client = match connect(&config, user, room).await {
+        Ok(client) => client,
+        Err(err) => {
+            report.lock().unwrap().record_error(format!("{:#}", err));
+            return;
+        }
+    };
+    report.lock().unwrap().connected += 1;
+
+    let mut messages = client.messages();
+    let receiver = tokio::spawn({
+        let report = Arc::clone(&report);
+
+        async move {
+            let receive = async {
+                while let Some(message) = messages.next().await {
+                    let Some(sent_at) = parse_sent_at(&message.content) else {
+                        continue;
+                    };
+
+                    let latency = Duration::from_micros(now_micros().saturating_sub(sent_at));
+                    report.lock().unwrap().delivered.record(latency);
+                }
+
+                report
+                    .lock()
+                    .unwrap()
+                    .record_error(String::from("the connection to the server was lost"));
+            };
+
+            let _ = tokio::time::timeout_at(ends_at + DRAIN_PERIOD, receive).await;
+        }
+    });
+
+    if config.messages_per_second > 0.0 {
+        let period = Duration::from_secs_f64(1.0 / config.messages_per_second);
+        // the users start at random times within the period, so they do not all send at once
+        let starts_at = Instant::now() + period.mul_f64(rand::thread_rng().gen_range(0.0..1.0));
+        let mut interval = tokio::time::interval_at(starts_at, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        while interval.tick().await < ends_at {
+            report.lock().unwrap().sent += 1;
+
+            let sent_at = Instant::now();
+            let content = format!("{}:{}", MESSAGE_PREFIX, now_micros());
+            match client.send_message(room, &content).await {
+                Ok(()) => report
+                    .lock()
+                    .unwrap()
+                    .acknowledged
+                    .record(sent_at.elapsed()),
+                Err(err) => report
+                    .lock()
+                    .unwrap()
+                    .record_error(format!("could not send a message: {:#}", err)),
+            }
+        }
+    }
+
+    let _ = receiver.await;
+}
+
+async fn connect(config: &LoadConfig, user: usize, room: &str) -> anyhow::Result<Client> {
+    let client = Client::connect(&config.addr).await?;
+    client
+        .login(&format!("{}{}", USERNAME_PREFIX, user), &config.password)
+        .await?;
+    client
+        .join(room)
+        .await
+        .map_err(|err| anyhow::anyhow!("could not join {}: {:#}", room, err))?;
+
+    Ok(client)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Arc::new(LoadConfig {
+        addr: cli::flag("--server").unwrap_or_else(|| String::from(DEFAULT_SERVER)),
+        password: std::env::var(PASSWORD_ENV).unwrap_or_else(|_| String::from(DEFAULT_PASSWORD)),
+        users: parsed_cli_flag("--users", DEFAULT_USERS)?,
+        rooms: cli::flag("--rooms")
+            .unwrap_or_else(|| String::from(DEFAULT_ROOMS))
+            .split(',')
+            .map(str::trim)
+            .filter(|room| !room.is_empty())
+            .map(String::from)
+            .collect(),
+        messages_per_second: parsed_cli_flag("--rate", DEFAULT_MESSAGES_PER_SECOND)?,
+        duration: Duration::from_secs(parsed_cli_flag("--duration", DEFAULT_DURATION_SECS)?),
+    });
+    if config.rooms.is_empty() {
+        return Err(anyhow::anyhow!(USAGE));
+    }
+
+    println!(
+        "{} users chatting in {} at {} messages per second each, for {}s",
+        config.users,
+        config.rooms.join(", "),
+        config.messages_per_second,
+        config.duration.as_secs()
+    );
+
+    let report = Arc::new(Mutex::new(Report::default()));
+    let ends_at = Instant::now() + config.duration;
+    let mut users = JoinSet::new();
+    for user in 0..config.users {
+        users.spawn(run_user(
+            user,
+            Arc::clone(&config),
+            Arc::clone(&report),
+            ends_at,
+        ));
+    }
+    while users.join_next().await.is_some() {}
+
+    print!("{}", report.lock().unwrap());
+
+    Ok(())
+}
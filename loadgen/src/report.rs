@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+/// [Latencies] collects how long each operation of a kind took, to sum them up in percentiles
+#[derive(Debug, Default)]
+pub struct Latencies {
+    samples: Vec<Duration>,
+}
+
+/// The percentiles of the [Latencies]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The percentiles of the recorded latencies, none until one is recorded
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let mut samples = self.samples.clone();
+        samples.sort_unstable();
+
+        let last = samples.len().checked_sub(1)?;
+        let percentile = |ratio: f64| samples[((last as f64) * ratio).round() as usize];
+
+        Some(LatencySummary {
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: samples[last],
+        })
+    }
+}
+
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(summary) = self.summary() else {
+            return write!(f, "no samples");
+        };
+        let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+
+        write!(
+            f,
+            "p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+            millis(summary.p50),
+            millis(summary.p90),
+            millis(summary.p99),
+            millis(summary.max)
+        )
+    }
+}
+
+/// [Report] aggregates what the simulated users observed during a run
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The users who logged in and joined their room
+    pub connected: usize,
+    pub sent: usize,
+    /// How long the sent messages took to be acknowledged by the server
+    pub acknowledged: Latencies,
+    /// How long the messages took from being sent to being received by each user of the room
+    pub delivered: Latencies,
+    /// How many times each error happened
+    pub errors: BTreeMap<String, usize>,
+}
+
+impl Report {
+    pub fn record_error(&mut self, error: String) {
+        *self.errors.entry(error).or_default() += 1;
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "users connected: {}", self.connected)?;
+        writeln!(
+            f,
+            "messages: {} sent, {} acknowledged, {} delivered",
+            self.sent,
+            self.acknowledged.len(),
+            self.delivered.len()
+        )?;
+        writeln!(f, "ack latency: {}", self.acknowledged)?;
+        writeln!(f, "delivery latency: {}", self.delivered)?;
+        writeln!(f, "errors: {}", self.errors.values().sum::<usize>())?;
+
+        for (error, count) in self.errors.iter() {
+            writeln!(f, "  {}x {}", count, error)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_picks_the_percentiles() {
+        let mut latencies = Latencies::default();
+        // recorded out of order, as the users report them
+        for millis in (1..=100).rev() {
+            latencies.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(
+            latencies.summary(),
+            Some(LatencySummary {
+                p50: Duration::from_millis(51),
+                p90: Duration::from_millis(90),
+                p99: Duration::from_millis(99),
+                max: Duration::from_millis(100),
+            })
+        );
+        assert_eq!(
+            latencies.to_string(),
+            "p50 51.0ms, p90 90.0ms, p99 99.0ms, max 100.0ms"
+        );
+    }
+
+    #[test]
+    fn test_summary_needs_a_sample() {
+        let latencies = Latencies::default();
+
+        assert_eq!(latencies.summary(), None);
+        assert_eq!(latencies.to_string(), "no samples");
+    }
+
+    #[test]
+    fn test_errors_are_counted_by_message() {
+        let mut report = Report::default();
+        report.record_error(String::from("could not connect"));
+        report.record_error(String::from("rate limited"));
+        report.record_error(String::from("could not connect"));
+
+        assert_eq!(report.errors.get("could not connect"), Some(&2));
+        assert!(report
+            .to_string()
+            .ends_with("errors: 3\n  2x could not connect\n  1x rate limited\n"));
+    }
+}
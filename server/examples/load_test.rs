@@ -0,0 +1,294 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use comms::{
+    command::{HelloCommand, JoinRoomCommand, SendMessageCommand, UserCommand},
+    event::Event,
+    transport::{self, client::CommandWriter},
+};
+use serde::Deserialize;
+use tokio::{
+    net::TcpStream,
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+use tokio_stream::StreamExt;
+
+/// Broadcast Latency Load Test for the Chat Server
+///
+/// Connects a large number of synthetic clients to a running server, spreads them across the public rooms,
+/// then has a sender in each room broadcast timestamped messages to the others.
+/// Every receiver measures how long each message took from being sent to being read, and the run
+/// reports the latency percentiles along with the messages which never arrived.
+///
+/// The server and the load test run on the same host, so their clocks agree.
+/// !IMPORTANT! Be sure to check and configure your socket limits, before you run the tests
+const DEFAULT_SERVER_ADDR: &str = "localhost:8080";
+/// Environment variable to override the address of the server
+const SERVER_ADDR_ENV: &str = "LOAD_SERVER_ADDR";
+/// Environment variable to override how many clients receive the broadcasts
+const CLIENT_COUNT_ENV: &str = "LOAD_CLIENT_COUNT";
+/// Environment variable to override how many messages are broadcast to each room
+const MESSAGE_COUNT_ENV: &str = "LOAD_MESSAGE_COUNT";
+const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
+
+/// Load Test Configuration
+const DEFAULT_CLIENT_COUNT: usize = 10_000;
+const DEFAULT_MESSAGE_COUNT: usize = 20;
+// How many clients connect and join their room at once, so the accept queue of the server does not overflow
+const CONCURRENT_HANDSHAKES: usize = 256;
+// How many milliseconds each sender waits between its messages, below the default message rate limit
+const SENDER_DELAY_MILLIS: u64 = 500;
+// How long the receivers are given to read the last messages once they have all been sent
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+// The clients announce the v2 protocol to be served as guests, so the load is not dominated by password hashing
+const GUEST_PROTOCOL_VERSION: u16 = 2;
+
+const MESSAGE_PREFIX: &str = "load";
+
+#[derive(Debug, Clone, Deserialize)]
+struct ChatRoomMetadata {
+    name: String,
+    #[serde(default)]
+    visibility: Option<String>,
+}
+
+/// [LoadReport] aggregates what the receivers observed
+#[derive(Debug, Default)]
+struct LoadReport {
+    joined: usize,
+    /// Receivers whose connection ended before the run did
+    disconnected: usize,
+    /// How long each received message took to be broadcast, in microseconds
+    latencies: Vec<u64>,
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the clock is before the unix epoch")
+        .as_micros() as u64
+}
+
+fn format_message(sent_at: u64) -> String {
+    format!("{}:{}", MESSAGE_PREFIX, sent_at)
+}
+
+/// Returns when a message of a load test sender was sent, in microseconds since the epoch
+fn parse_sent_at(content: &str) -> Option<u64> {
+    content
+        .strip_prefix(MESSAGE_PREFIX)?
+        .strip_prefix(':')?
+        .parse()
+        .ok()
+}
+
+fn env_var(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("could not parse {}", name))
+        })
+        .unwrap_or(default)
+}
+
+/// Connects as a guest and joins the room, returning once the server has confirmed the join
+async fn connect_and_join(
+    server_addr: &str,
+    room: &str,
+) -> anyhow::Result<(transport::client::EventStream, CommandWriter)> {
+    let tcp_stream = TcpStream::connect(server_addr).await?;
+    let (mut event_stream, mut command_writer) = transport::client::split_tcp_stream(tcp_stream);
+
+    command_writer
+        .write(&UserCommand::Hello(HelloCommand {
+            protocol_version: GUEST_PROTOCOL_VERSION,
+        }))
+        .await?;
+    command_writer
+        .write(&UserCommand::JoinRoom(JoinRoomCommand {
+            room: String::from(room),
+        }))
+        .await?;
+
+    loop {
+        match event_stream.next().await {
+            Some(Ok(Event::UserJoinedRoom(joined))) if joined.room == room => break,
+            Some(Ok(Event::RoomJoinDenied(denied))) => {
+                return Err(anyhow::anyhow!("could not join '{}': {:?}", room, denied))
+            }
+            Some(Ok(_)) => continue,
+            _ => return Err(anyhow::anyhow!("server did not confirm the join")),
+        }
+    }
+
+    Ok((event_stream, command_writer))
+}
+
+/// Joins the room and records the latency of every broadcast message, until the connection ends
+///
+/// The permit of the handshake is given back once the join is confirmed, or has failed.
+async fn run_receiver(
+    server_addr: Arc<str>,
+    room: String,
+    handshake: OwnedSemaphorePermit,
+    report: Arc<Mutex<LoadReport>>,
+) -> anyhow::Result<()> {
+    let (mut event_stream, _command_writer) = connect_and_join(&server_addr, &room).await?;
+    report.lock().unwrap().joined += 1;
+    drop(handshake);
+
+    while let Some(Ok(event)) = event_stream.next().await {
+        let Event::UserMessage(message) = event else {
+            continue;
+        };
+        let Some(sent_at) = parse_sent_at(&message.content) else {
+            continue;
+        };
+
+        let latency = now_micros().saturating_sub(sent_at);
+        report.lock().unwrap().latencies.push(latency);
+    }
+
+    report.lock().unwrap().disconnected += 1;
+    Ok(())
+}
+
+/// Joins the room and broadcasts the timestamped messages to it, one at a time
+async fn run_sender(
+    server_addr: Arc<str>,
+    room: String,
+    message_count: usize,
+) -> anyhow::Result<()> {
+    let (mut event_stream, mut command_writer) = connect_and_join(&server_addr, &room).await?;
+    // the events of the room are read for the connection not to be disconnected as a slow consumer
+    let reader = tokio::spawn(async move { while event_stream.next().await.is_some() {} });
+
+    for _ in 0..message_count {
+        command_writer
+            .write(&UserCommand::SendMessage(SendMessageCommand {
+                room: room.clone(),
+                content: format_message(now_micros()),
+                reply_to: None,
+            }))
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(SENDER_DELAY_MILLIS)).await;
+    }
+
+    reader.abort();
+    Ok(())
+}
+
+/// The latency below which the given ratio of the messages were broadcast, in milliseconds
+fn percentile(sorted_latencies: &[u64], ratio: f64) -> f64 {
+    let Some(last) = sorted_latencies.len().checked_sub(1) else {
+        return 0.0;
+    };
+    let index = ((last as f64) * ratio).round() as usize;
+
+    sorted_latencies[index] as f64 / 1000.0
+}
+
+#[tokio::main]
+async fn main() {
+    let server_addr: Arc<str> = std::env::var(SERVER_ADDR_ENV)
+        .unwrap_or_else(|_| String::from(DEFAULT_SERVER_ADDR))
+        .into();
+    let client_count = env_var(CLIENT_COUNT_ENV, DEFAULT_CLIENT_COUNT);
+    let message_count = env_var(MESSAGE_COUNT_ENV, DEFAULT_MESSAGE_COUNT);
+
+    let chat_room_metadatas: Vec<ChatRoomMetadata> = serde_json::from_str(CHAT_ROOMS_METADATAS)
+        .expect("could not parse the chat rooms metadatas");
+    // guests can not be invited to the private rooms
+    let rooms = chat_room_metadatas
+        .into_iter()
+        .filter(|metadata| metadata.visibility.as_deref() != Some("private"))
+        .map(|metadata| metadata.name)
+        .collect::<Vec<_>>();
+
+    let report = Arc::new(Mutex::new(LoadReport::default()));
+    let handshakes = Arc::new(Semaphore::new(CONCURRENT_HANDSHAKES));
+    let mut receivers: JoinSet<anyhow::Result<()>> = JoinSet::new();
+
+    let started_at = Instant::now();
+    for client in 0..client_count {
+        let handshake = Arc::clone(&handshakes)
+            .acquire_owned()
+            .await
+            .expect("the handshakes semaphore is never closed");
+
+        receivers.spawn(run_receiver(
+            Arc::clone(&server_addr),
+            rooms[client % rooms.len()].clone(),
+            handshake,
+            Arc::clone(&report),
+        ));
+    }
+
+    // every receiver has joined, or failed to, once all the permits are given back
+    let _all_handshakes = handshakes
+        .acquire_many(CONCURRENT_HANDSHAKES as u32)
+        .await
+        .expect("the handshakes semaphore is never closed");
+    let joined = report.lock().unwrap().joined;
+    println!(
+        "{} of {} clients joined in {:.1}s",
+        joined,
+        client_count,
+        started_at.elapsed().as_secs_f64()
+    );
+
+    // each receiver is sent the messages of the sender of its room
+    let expected = joined * message_count;
+    let mut senders: JoinSet<anyhow::Result<()>> = JoinSet::new();
+    for room in rooms.iter().take(client_count) {
+        senders.spawn(run_sender(
+            Arc::clone(&server_addr),
+            room.clone(),
+            message_count,
+        ));
+    }
+    while let Some(result) = senders.join_next().await {
+        if let Ok(Err(err)) = result {
+            println!("a sender failed: {}", err);
+        }
+    }
+
+    let drain_started_at = Instant::now();
+    while report.lock().unwrap().latencies.len() < expected
+        && drain_started_at.elapsed() < DRAIN_TIMEOUT
+    {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    receivers.abort_all();
+
+    let mut report = report.lock().unwrap();
+    report.latencies.sort_unstable();
+    let received = report.latencies.len();
+
+    println!(
+        "received {} of {} messages, {} clients disconnected",
+        received, expected, report.disconnected
+    );
+    println!(
+        "broadcast latency: p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+        percentile(&report.latencies, 0.5),
+        percentile(&report.latencies, 0.9),
+        percentile(&report.latencies, 0.99),
+        percentile(&report.latencies, 1.0),
+    );
+
+    if joined < client_count || received < expected || report.disconnected > 0 {
+        println!(
+            "load test failed, clients could not join or were disconnected, or messages were lost"
+        );
+        std::process::exit(1);
+    }
+
+    println!("load test passed");
+}
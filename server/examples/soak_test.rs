@@ -0,0 +1,581 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use comms::{
+    command::{
+        ExportRoomHistoryCommand, HelloCommand, JoinRoomCommand, LoginCommand, SendMessageCommand,
+        UserCommand,
+    },
+    event::Event,
+    protocol,
+    transport::{
+        self,
+        client::{CommandWriter, EventStream},
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    process::{Child, Command},
+    sync::Notify,
+    task::JoinHandle,
+};
+use tokio_stream::StreamExt;
+
+/// Kill-and-Resume Soak Test for the Chat Server
+///
+/// Runs the server binary as a child process and keeps a fixed number of client processes chatting in a single room,
+/// while the server is killed and restarted, and random clients are killed and started again.
+/// Every message carries a per client sequence number, kept across restarts, which the receivers check for gaps and duplicates.
+///
+/// The clients are this example run again with the `--client` argument, they report what they send and receive
+/// on their standard output, one line at a time, and the checks are made by the process which runs them.
+///
+/// The sessions only live in memory, so each restart starts a new epoch with fresh connections.
+/// Messages in flight when a process is killed are expected to be lost, acknowledged messages are not:
+/// once the run is over, the server is restarted one last time and every acknowledged message is looked up
+/// in the history it exports from its store.
+///
+/// Build the server first with `cargo build --bin server`, or point `SOAK_SERVER_BIN` to the binary.
+const SERVER_ADDR: &str = "localhost:8080";
+const DEFAULT_SERVER_BIN: &str = "target/debug/server";
+/// Environment variable to override the path of the server binary
+const SERVER_BIN_ENV: &str = "SOAK_SERVER_BIN";
+/// Environment variable to override how long the soak test runs, in seconds
+const DURATION_ENV: &str = "SOAK_DURATION_SECS";
+/// The argument which runs this example as a client process, followed by the client and its first sequence number
+const CLIENT_ARG: &str = "--client";
+
+/// Soak Test Configuration
+const DEFAULT_DURATION: Duration = Duration::from_secs(10 * 60);
+// The room every client joins and chats in, its whole history can be exported to check it against the store
+const ROOM: &str = "rust";
+// The number of clients kept running
+const CLIENT_COUNT: usize = 8;
+// How many milliseconds to wait between each client message
+const CLIENT_CHAT_DELAY_MILLIS: u64 = 100;
+// How often the server process is killed and restarted
+const SERVER_RESTART_INTERVAL: Duration = Duration::from_secs(30);
+// How often a random client process is killed and started again
+const CLIENT_KILL_INTERVAL: Duration = Duration::from_secs(5);
+// How long to wait for the server to accept connections after a restart
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+const MESSAGE_PREFIX: &str = "soak";
+// Every client logs in as its own user, which is registered on the first login
+const CLIENT_PASSWORD: &str = "soak_password";
+// The user which exports the history once the run is over
+const CHECKER_USERNAME: &str = "soak_checker";
+// The clients chat in a steady burst, which the spam guard would mute them for
+const SERVER_CONFIG: &str = "[limits]\nspam_max_burst_score = 0\n";
+
+/// [SoakReport] aggregates what the clients observed across every epoch
+#[derive(Debug, Default)]
+struct SoakReport {
+    connections: usize,
+    sent: usize,
+    acknowledged: usize,
+    received: usize,
+    /// Messages of a sender which a receiver skipped, while receiving the ones after them
+    lost: usize,
+    /// Messages a receiver got more than once, or out of order, or which are stored more than once
+    duplicated: usize,
+    /// Messages found in the store after the last restart
+    stored: usize,
+    /// Acknowledged messages missing from the store after the last restart
+    unpersisted: usize,
+}
+
+impl SoakReport {
+    fn has_violations(&self) -> bool {
+        self.lost > 0 || self.duplicated > 0 || self.unpersisted > 0
+    }
+}
+
+/// [Ledger] keeps track of the single messages, which are checked once the run is over
+///
+/// A receiver skipping a message is only a loss if the message was acknowledged to its sender,
+/// the ones in flight when the sender was killed never reach the room.
+#[derive(Debug, Default)]
+struct Ledger {
+    /// The messages, by client and sequence number, acknowledged to their senders
+    acknowledged: HashSet<(usize, u64)>,
+    /// The messages the receivers skipped, while receiving the ones after them
+    skipped: Vec<(usize, u64)>,
+}
+
+/// [ClientLine] is a line a client process reports on its standard output
+#[derive(Debug, PartialEq)]
+enum ClientLine {
+    /// The client joined the room
+    Connected,
+    /// The client is about to send the message with the given sequence number
+    Sending(u64),
+    /// The client received a message, from the given client with the given sequence number
+    Received(usize, u64),
+}
+
+impl ClientLine {
+    fn format(&self) -> String {
+        match self {
+            ClientLine::Connected => String::from("connected"),
+            ClientLine::Sending(seq) => format!("sending {}", seq),
+            ClientLine::Received(client, seq) => format!("received {} {}", client, seq),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split(' ');
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("connected"), None, None) => Some(ClientLine::Connected),
+            (Some("sending"), Some(seq), None) => Some(ClientLine::Sending(seq.parse().ok()?)),
+            (Some("received"), Some(client), Some(seq)) => Some(ClientLine::Received(
+                client.parse().ok()?,
+                seq.parse().ok()?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn format_message(client: usize, seq: u64) -> String {
+    format!("{}:{}:{}", MESSAGE_PREFIX, client, seq)
+}
+
+/// Returns the client and the sequence number of a message sent by a soak client
+fn parse_message(content: &str) -> Option<(usize, u64)> {
+    let mut parts = content.split(':');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(MESSAGE_PREFIX), Some(client), Some(seq)) => {
+            Some((client.parse().ok()?, seq.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+async fn spawn_server(
+    server_bin: &str,
+    database_path: &Path,
+    config_path: &Path,
+) -> anyhow::Result<Child> {
+    Ok(Command::new(server_bin)
+        .arg("--config")
+        .arg(config_path)
+        // the store is kept across the restarts of the run, but not across runs
+        .env("CHAT_DATABASE_PATH", database_path)
+        // the clients chat every 100ms, faster than the default message rate limit
+        .env("CHAT_RATE_LIMIT_MESSAGES_PER_SEC", "0")
+        // the clients do not answer the pings, they would be disconnected after a minute
+        .env("CHAT_HEARTBEAT_INTERVAL_SECS", "0")
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?)
+}
+
+/// Connects to the server, retrying until it accepts connections
+async fn connect() -> TcpStream {
+    loop {
+        match TcpStream::connect(SERVER_ADDR).await {
+            Ok(stream) => return stream,
+            Err(_) => tokio::time::sleep(CONNECT_RETRY_DELAY).await,
+        }
+    }
+}
+
+/// Connects and logs in as the given user, then joins the room
+///
+/// Returns the id of the user along with the connection, once the join is confirmed.
+async fn join_room(username: String) -> anyhow::Result<(String, EventStream, CommandWriter)> {
+    let (mut event_stream, mut command_writer) =
+        transport::client::split_tcp_stream(connect().await);
+
+    command_writer
+        .write(&UserCommand::Hello(HelloCommand {
+            protocol_version: protocol::PROTOCOL_VERSION,
+        }))
+        .await?;
+    command_writer
+        .write(&UserCommand::Login(LoginCommand {
+            username,
+            password: String::from(CLIENT_PASSWORD),
+        }))
+        .await?;
+
+    // the welcome and the login result come first, then the login successful event if the credentials are accepted
+    let user_id = loop {
+        match event_stream.next().await {
+            Some(Ok(Event::Welcome(_))) => continue,
+            Some(Ok(Event::LoginResult(result))) if result.is_accepted => continue,
+            Some(Ok(Event::LoginSuccessful(login_event))) => break login_event.user_id,
+            _ => return Err(anyhow::anyhow!("server did not send login successful")),
+        }
+    };
+
+    command_writer
+        .write(&UserCommand::JoinRoom(JoinRoomCommand {
+            room: String::from(ROOM),
+        }))
+        .await?;
+
+    // wait for the join to be confirmed, so every message sent afterwards is expected to be received
+    loop {
+        match event_stream.next().await {
+            Some(Ok(Event::UserJoinedRoom(_))) => break,
+            Some(Ok(_)) => continue,
+            _ => return Err(anyhow::anyhow!("server did not confirm the join")),
+        }
+    }
+
+    Ok((user_id, event_stream, command_writer))
+}
+
+/// Runs a client process until its connection ends, when the server is killed, reporting on its standard output
+///
+/// The sequence numbers go on from the given one, the next one after the last message of the previous client process.
+async fn run_client(client: usize, first_seq: u64) -> anyhow::Result<()> {
+    let (_, mut event_stream, mut command_writer) = join_room(format!("soak_{}", client)).await?;
+
+    println!("{}", ClientLine::Connected.format());
+
+    let sender = async move {
+        let mut rng = StdRng::from_entropy();
+
+        // sleep initially for a time to distribute the messaging times
+        tokio::time::sleep(Duration::from_millis(
+            rng.gen_range(1..CLIENT_CHAT_DELAY_MILLIS),
+        ))
+        .await;
+
+        for seq in first_seq.. {
+            // reported before it is sent, so the sequence number is not reused if the client is killed meanwhile
+            println!("{}", ClientLine::Sending(seq).format());
+            let sent = command_writer
+                .write(&UserCommand::SendMessage(SendMessageCommand {
+                    room: String::from(ROOM),
+                    content: format_message(client, seq),
+                    reply_to: None,
+                }))
+                .await;
+
+            if sent.is_err() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(CLIENT_CHAT_DELAY_MILLIS)).await;
+        }
+    };
+
+    let receiver = async {
+        while let Some(Ok(event)) = event_stream.next().await {
+            let Event::UserMessage(message) = event else {
+                continue;
+            };
+
+            if let Some((sender, seq)) = parse_message(&message.content) {
+                println!("{}", ClientLine::Received(sender, seq).format());
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = sender => {}
+        _ = receiver => {}
+    }
+
+    Ok(())
+}
+
+/// Records what a client process reported
+///
+/// `last_seqs` is the last sequence number the client process received from each sender,
+/// the first one received is the baseline.
+fn record_client_line(
+    client: usize,
+    line: ClientLine,
+    next_seq: &AtomicU64,
+    last_seqs: &mut HashMap<usize, u64>,
+    report: &Mutex<SoakReport>,
+    ledger: &Mutex<Ledger>,
+) {
+    let mut report = report.lock().unwrap();
+    let mut ledger = ledger.lock().unwrap();
+
+    let (sender, seq) = match line {
+        ClientLine::Connected => {
+            report.connections += 1;
+            return;
+        }
+        ClientLine::Sending(seq) => {
+            report.sent += 1;
+            next_seq.store(seq + 1, Ordering::Relaxed);
+            return;
+        }
+        ClientLine::Received(sender, seq) => (sender, seq),
+    };
+
+    // the clients receive their own messages once the room has stored them
+    if sender == client {
+        report.acknowledged += 1;
+        ledger.acknowledged.insert((sender, seq));
+    } else {
+        report.received += 1;
+    }
+
+    match last_seqs.insert(sender, seq) {
+        Some(last_seq) if seq <= last_seq => {
+            report.duplicated += 1;
+            println!(
+                "client {} received seq {} of client {} after seq {}",
+                client, seq, sender, last_seq
+            );
+        }
+        // whether the skipped messages were lost is only known once their acknowledgements are in
+        Some(last_seq) if seq > last_seq + 1 => {
+            ledger
+                .skipped
+                .extend((last_seq + 1..seq).map(|skipped_seq| (sender, skipped_seq)));
+        }
+        _ => (),
+    }
+}
+
+/// Runs a client process until it exits, killing it when asked to
+///
+/// Its output is read to the end, so every message it reported is recorded before the next process of the client starts.
+async fn run_client_process(
+    client: usize,
+    next_seq: &AtomicU64,
+    kill: &Notify,
+    report: &Mutex<SoakReport>,
+    ledger: &Mutex<Ledger>,
+) -> anyhow::Result<()> {
+    let mut process = Command::new(std::env::current_exe()?)
+        .arg(CLIENT_ARG)
+        .arg(client.to_string())
+        .arg(next_seq.load(Ordering::Relaxed).to_string())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut lines = BufReader::new(process.stdout.take().unwrap()).lines();
+    let mut last_seqs = HashMap::new();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+
+                if let Some(line) = ClientLine::parse(&line) {
+                    record_client_line(client, line, next_seq, &mut last_seqs, report, ledger);
+                }
+            }
+            _ = kill.notified() => {
+                process.start_kill()?;
+            }
+        }
+    }
+
+    process.wait().await?;
+
+    Ok(())
+}
+
+/// Keeps a process of the client running, starting a new one whenever the previous one exits
+fn spawn_client(
+    client: usize,
+    next_seq: Arc<AtomicU64>,
+    kill: Arc<Notify>,
+    report: Arc<Mutex<SoakReport>>,
+    ledger: Arc<Mutex<Ledger>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = run_client_process(client, &next_seq, &kill, &report, &ledger).await {
+                println!("client {} failed: {}", client, err);
+                tokio::time::sleep(CONNECT_RETRY_DELAY).await;
+            }
+        }
+    })
+}
+
+/// Exports the whole history of the room, returning how many times each soak message is stored
+async fn export_stored_messages() -> anyhow::Result<HashMap<(usize, u64), usize>> {
+    let (_, mut event_stream, mut command_writer) =
+        join_room(String::from(CHECKER_USERNAME)).await?;
+
+    command_writer
+        .write(&UserCommand::ExportRoomHistory(ExportRoomHistoryCommand {
+            room: String::from(ROOM),
+            after: None,
+        }))
+        .await?;
+
+    let mut stored: HashMap<(usize, u64), usize> = HashMap::new();
+    loop {
+        match event_stream.next().await {
+            Some(Ok(Event::RoomHistoryChunk(chunk))) => {
+                for message in chunk.messages.iter() {
+                    if let Some(key) = parse_message(&message.content) {
+                        *stored.entry(key).or_default() += 1;
+                    }
+                }
+
+                if chunk.is_last {
+                    return Ok(stored);
+                }
+            }
+            Some(Ok(_)) => continue,
+            _ => return Err(anyhow::anyhow!("server did not finish the export")),
+        }
+    }
+}
+
+/// Settles the messages the receivers skipped, and looks up every acknowledged message in the store
+fn check_messages(report: &mut SoakReport, ledger: &Ledger, stored: &HashMap<(usize, u64), usize>) {
+    report.lost += ledger
+        .skipped
+        .iter()
+        .filter(|message| ledger.acknowledged.contains(message))
+        .count();
+    report.stored = stored.len();
+    report.duplicated += stored.values().filter(|count| **count > 1).count();
+
+    for &(client, seq) in ledger.acknowledged.iter() {
+        if !stored.contains_key(&(client, seq)) {
+            report.unpersisted += 1;
+            println!("seq {} of client {} is not in the store", seq, client);
+        }
+    }
+}
+
+fn remove_files(database_path: &Path, config_path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut path = database_path.as_os_str().to_owned();
+        path.push(suffix);
+        let _ = std::fs::remove_file(PathBuf::from(path));
+    }
+    let _ = std::fs::remove_file(config_path);
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, client, first_seq] = args.as_slice() {
+        if flag == CLIENT_ARG {
+            let client = client.parse().expect("could not parse the client");
+            let first_seq = first_seq
+                .parse()
+                .expect("could not parse the first sequence number");
+
+            // the process exits once its connection ends, and is started again by the soak test
+            if let Err(err) = run_client(client, first_seq).await {
+                eprintln!("client {} failed: {}", client, err);
+            }
+            return;
+        }
+    }
+
+    let server_bin =
+        std::env::var(SERVER_BIN_ENV).unwrap_or_else(|_| String::from(DEFAULT_SERVER_BIN));
+    let duration = std::env::var(DURATION_ENV)
+        .ok()
+        .map(|secs| {
+            secs.parse()
+                .map(Duration::from_secs)
+                .expect("could not parse the soak duration")
+        })
+        .unwrap_or(DEFAULT_DURATION);
+
+    let database_path =
+        std::env::temp_dir().join(format!("chat-soak-{}.sqlite3", std::process::id()));
+    let config_path = std::env::temp_dir().join(format!("chat-soak-{}.toml", std::process::id()));
+    std::fs::write(&config_path, SERVER_CONFIG).expect("could not write the server configuration");
+    let report = Arc::new(Mutex::new(SoakReport::default()));
+    let ledger = Arc::new(Mutex::new(Ledger::default()));
+    let next_seqs: Vec<Arc<AtomicU64>> = (0..CLIENT_COUNT)
+        .map(|_| Arc::new(AtomicU64::new(0)))
+        .collect();
+    let kills: Vec<Arc<Notify>> = (0..CLIENT_COUNT).map(|_| Arc::new(Notify::new())).collect();
+    let mut rng = StdRng::from_entropy();
+    let mut server = spawn_server(&server_bin, &database_path, &config_path)
+        .await
+        .expect("could not start the server");
+    let clients: Vec<JoinHandle<()>> = (0..CLIENT_COUNT)
+        .map(|client| {
+            spawn_client(
+                client,
+                Arc::clone(&next_seqs[client]),
+                Arc::clone(&kills[client]),
+                Arc::clone(&report),
+                Arc::clone(&ledger),
+            )
+        })
+        .collect();
+
+    let started_at = Instant::now();
+    let mut server_restart = tokio::time::interval(SERVER_RESTART_INTERVAL);
+    let mut client_kill = tokio::time::interval(CLIENT_KILL_INTERVAL);
+    let mut epoch = 1;
+    // the first ticks complete immediately
+    server_restart.tick().await;
+    client_kill.tick().await;
+
+    while started_at.elapsed() < duration {
+        tokio::select! {
+            _ = server_restart.tick() => {
+                let _ = server.kill().await;
+                server = spawn_server(&server_bin, &database_path, &config_path)
+                    .await
+                    .expect("could not restart the server");
+                epoch += 1;
+                println!("restarted the server, epoch: {}", epoch);
+            }
+            _ = client_kill.tick() => {
+                let client = rng.gen_range(0..CLIENT_COUNT);
+
+                kills[client].notify_one();
+                println!("killed and restarted client {}", client);
+            }
+        }
+    }
+
+    // the client processes are killed along with the tasks running them
+    for client in clients {
+        client.abort();
+        let _ = client.await;
+    }
+
+    // the acknowledged messages are expected to survive a restart of the server
+    let _ = server.kill().await;
+    server = spawn_server(&server_bin, &database_path, &config_path)
+        .await
+        .expect("could not restart the server");
+    let stored = export_stored_messages()
+        .await
+        .expect("could not export the stored messages");
+    let _ = server.kill().await;
+    remove_files(&database_path, &config_path);
+
+    let mut report = report.lock().unwrap();
+    check_messages(&mut report, &ledger.lock().unwrap(), &stored);
+    println!("epochs: {}, {:?}", epoch, report);
+
+    if report.has_violations() {
+        println!("soak test failed, messages were lost, duplicated or not stored");
+        std::process::exit(1);
+    }
+
+    println!("soak test passed");
+}
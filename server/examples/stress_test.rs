@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use comms::{
-    command::{JoinRoomCommand, UserCommand},
+    command::{HelloCommand, JoinRoomCommand, UserCommand},
     event::Event,
     transport,
 };
@@ -17,7 +17,6 @@ use tokio_stream::StreamExt;
 /// The number of users, number of rooms joined per user and chattines of users can be configured.
 ///
 /// !IMPORTANT! Be sure to check and configure your socket limits, before you run the tests
-
 const SERVER_ADDR: &str = "localhost:8080";
 const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
 
@@ -31,6 +30,8 @@ const LOAD_INCREMENTS: &str = r#"[
 const NUMBER_OF_ROOMS_TO_JOIN: usize = 5;
 // How many milliseconds to wait between each user message
 const USER_CHAT_DELAY_MILLIS: u64 = 10_000;
+// The users announce the v2 protocol to be served as guests, so the load is not dominated by password hashing
+const GUEST_PROTOCOL_VERSION: u16 = 2;
 
 /// [RotatingIterator] is a simple iterator that rotates through a list of items
 /// and starts from the beginning when the end is reached.
@@ -59,6 +60,8 @@ impl<T: Clone> Iterator for RotatingIterator<T> {
 struct ChatRoomMetadata {
     name: String,
     description: String,
+    #[serde(default)]
+    visibility: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,7 +76,7 @@ async fn spawn_single_user(rooms_to_join: Vec<String>) -> anyhow::Result<()> {
 
     match result.as_ref() {
         Ok(_) => println!("exited without problems"),
-        Err(err) => println!("some error occurred = {}", err.to_string()),
+        Err(err) => println!("some error occurred = {}", err),
     }
 
     result
@@ -83,6 +86,12 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
     let tcp_stream = TcpStream::connect(SERVER_ADDR).await?;
     let (mut event_stream, mut command_writer) = transport::client::split_tcp_stream(tcp_stream);
 
+    command_writer
+        .write(&UserCommand::Hello(HelloCommand {
+            protocol_version: GUEST_PROTOCOL_VERSION,
+        }))
+        .await?;
+
     let _login_event = match event_stream.next().await {
         Some(Ok(Event::LoginSuccessful(login_event))) => login_event,
         _ => return Err(anyhow::anyhow!("server did not send login successfull")),
@@ -113,8 +122,9 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
                 let _ = command_writer
                     .write(&UserCommand::SendMessage(
                         comms::command::SendMessageCommand {
-                            room: String::from(room_name),
+                            room: room_name,
                             content: nanoid!(),
+                            reply_to: None,
                         },
                     ))
                     .await;
@@ -124,7 +134,7 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
         }
     });
 
-    while let Some(_) = event_stream.next().await {}
+    while event_stream.next().await.is_some() {}
 
     join_handle.abort();
     Ok(())
@@ -136,6 +146,11 @@ async fn main() {
         serde_json::from_str(LOAD_INCREMENTS).expect("could not parse the load increments");
     let chat_room_metadatas: Vec<ChatRoomMetadata> = serde_json::from_str(CHAT_ROOMS_METADATAS)
         .expect("could not parse the chat rooms metadatas");
+    // guests can not be invited to the private rooms
+    let chat_room_metadatas = chat_room_metadatas
+        .into_iter()
+        .filter(|metadata| metadata.visibility.as_deref() != Some("private"))
+        .collect();
 
     let mut room_iterator = RotatingIterator::new(chat_room_metadatas);
     let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
@@ -164,5 +179,5 @@ async fn main() {
         }
     }
 
-    while let Some(_) = join_set.join_next().await {}
+    while join_set.join_next().await.is_some() {}
 }
@@ -0,0 +1,88 @@
+use std::sync::Mutex;
+
+use comms::{admin::AdminSession, event::ExportedSession};
+use tracing::info;
+
+use crate::clock::now_millis;
+
+#[derive(Debug)]
+struct AccessLogEntry {
+    user_id: String,
+    session: ExportedSession,
+}
+
+/// [AccessLog] records when the sessions of each user start and end
+///
+/// It is kept in memory, and is part of the data exported to a user on request.
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    entries: Mutex<Vec<AccessLogEntry>>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        AccessLog::default()
+    }
+
+    pub fn record_connect(&self, session_id: &str, user_id: &str) {
+        info!(username = user_id, session_id, "connected");
+
+        self.entries.lock().unwrap().push(AccessLogEntry {
+            user_id: String::from(user_id),
+            session: ExportedSession {
+                session_id: String::from(session_id),
+                connected_at: now_millis(),
+                disconnected_at: None,
+            },
+        });
+    }
+
+    /// Records the end of the latest connection of the session, a resumed session has one entry per connection
+    pub fn record_disconnect(&self, session_id: &str) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if let Some(entry) = entries
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.session.session_id == session_id)
+        {
+            info!(username = %entry.user_id, session_id, "disconnected");
+
+            entry.session.disconnected_at = Some(now_millis());
+        }
+    }
+
+    /// Returns the sessions of the given user, ordered from oldest to newest
+    pub fn sessions_of(&self, user_id: &str) -> Vec<ExportedSession> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.user_id == user_id)
+            .map(|entry| entry.session.clone())
+            .collect()
+    }
+
+    /// Returns the sessions which are connected, ordered from oldest to newest
+    pub fn connected_sessions(&self) -> Vec<AdminSession> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.session.disconnected_at.is_none())
+            .map(|entry| AdminSession {
+                session_id: entry.session.session_id.clone(),
+                username: entry.user_id.clone(),
+                connected_at: entry.session.connected_at,
+            })
+            .collect()
+    }
+
+    /// Removes every record of the given user
+    pub fn forget_user(&self, user_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|entry| entry.user_id != user_id);
+    }
+}
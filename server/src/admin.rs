@@ -0,0 +1,241 @@
+use std::{sync::Arc, time::Instant};
+
+use anyhow::Context;
+use chrono::{TimeZone, Utc};
+use comms::{
+    admin::{AdminRequest, AdminResponse, AdminStats, ExportFormat},
+    event::{self, Event, HistoryMessage},
+};
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{unix::OwnedWriteHalf, UnixStream},
+    sync::broadcast,
+};
+use tracing::{info, warn};
+
+use crate::session::SessionContext;
+
+/// The longest request line read from the console, longer ones close the connection
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+/// Number of the messages read from the store and written in each chunk of an export
+const EXPORT_CHUNK_SIZE: usize = 500;
+
+/// Writes a response as a line of JSON
+async fn write_response(
+    writer: &mut OwnedWriteHalf,
+    response: &AdminResponse,
+) -> anyhow::Result<()> {
+    let mut response =
+        serde_json::to_string(response).context("could not serialize the response")?;
+    response.push('\n');
+
+    writer
+        .write_all(response.as_bytes())
+        .await
+        .context("could not write the admin response")
+}
+
+/// Writes an exported message as a line of the given format
+fn export_line(format: ExportFormat, message: &HistoryMessage) -> String {
+    match format {
+        ExportFormat::Json => json!({
+            "user_id": message.user_id,
+            "content": message.content,
+            "timestamp": message.timestamp,
+            "edited": message.is_edited,
+        })
+        .to_string(),
+        ExportFormat::Text => format!(
+            "[{}] <{}> {}{}",
+            Utc.timestamp_millis_opt(message.timestamp as i64)
+                .single()
+                .map(|date_time| date_time.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default(),
+            message.user_id,
+            // a multiline message stays on its own line
+            message.content.replace('\n', " "),
+            if message.is_edited { " (edited)" } else { "" }
+        ),
+    }
+}
+
+/// [AdminConsole] serves the requests of the operator of the server over its Unix socket
+///
+/// Each request and each response is a line of JSON, see [AdminRequest] and [AdminResponse].
+/// The socket is only reachable by the user running the server, which is what authenticates the operator.
+pub struct AdminConsole {
+    session_context: SessionContext,
+    started_at: Instant,
+}
+
+impl AdminConsole {
+    pub fn new(session_context: SessionContext) -> Self {
+        AdminConsole {
+            session_context,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Answers the requests of a connection, one at a time, until the console or the server closes it
+    pub async fn serve_connection(
+        self: Arc<Self>,
+        socket: UnixStream,
+        mut quit_rx: broadcast::Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let (reader, mut writer) = socket.into_split();
+        let mut reader = BufReader::new(reader.take(MAX_REQUEST_BYTES as u64));
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let read = tokio::select! {
+                read = reader.read_line(&mut line) => read.context("could not read the admin request")?,
+                _ = quit_rx.recv() => return Ok(()),
+            };
+            if read == 0 {
+                return Ok(());
+            }
+            // the rest of a request longer than allowed would be read as the next request, the connection is closed instead
+            if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+                let response = AdminResponse::Error {
+                    message: format!("the request is longer than {} bytes", MAX_REQUEST_BYTES),
+                };
+
+                return write_response(&mut writer, &response).await;
+            }
+            // each request gets its own budget of bytes
+            reader.get_mut().set_limit(MAX_REQUEST_BYTES as u64);
+
+            let response = match serde_json::from_str::<AdminRequest>(line.trim_end()) {
+                Ok(request) => self.handle_request(request, &mut writer).await,
+                Err(err) => AdminResponse::Error {
+                    message: format!("could not parse the request: {}", err),
+                },
+            };
+
+            write_response(&mut writer, &response).await?;
+        }
+    }
+
+    /// Carries out the request, returns the final response to it, the exports write their chunks before it
+    async fn handle_request(
+        &self,
+        request: AdminRequest,
+        writer: &mut OwnedWriteHalf,
+    ) -> AdminResponse {
+        info!(?request, "admin request");
+
+        let result = match request {
+            AdminRequest::ListSessions => Ok(AdminResponse::Sessions {
+                sessions: self.session_context.access_log.connected_sessions(),
+            }),
+            AdminRequest::InspectRoom { room } => self
+                .session_context
+                .room_manager
+                .inspect_room(&room)
+                .await
+                .map(AdminResponse::Room),
+            AdminRequest::KickUser { username, reason } => self.kick_user(&username, reason),
+            AdminRequest::Announce { text } => self.announce(text),
+            AdminRequest::Stats => Ok(self.stats().await),
+            AdminRequest::ExportRoom {
+                room,
+                format,
+                since,
+                until,
+            } => self.export_room(&room, format, since, until, writer).await,
+        };
+
+        result.unwrap_or_else(|err| {
+            warn!("could not carry out the admin request: {:#}", err);
+
+            AdminResponse::Error {
+                message: format!("{:#}", err),
+            }
+        })
+    }
+
+    /// Disconnects every session of the user, telling them why, their dropped sessions are given up on as well
+    fn kick_user(&self, username: &str, reason: String) -> anyhow::Result<AdminResponse> {
+        self.session_context.direct_message_router.deliver(
+            username,
+            Event::SessionKicked(event::SessionKickedEvent { reason }),
+        )?;
+
+        Ok(AdminResponse::Done {
+            message: format!("kicked '{}'", username),
+        })
+    }
+
+    fn announce(&self, text: String) -> anyhow::Result<AdminResponse> {
+        let text = String::from(text.trim());
+        if text.is_empty() {
+            return Err(anyhow::anyhow!("the announcement is empty"));
+        }
+
+        // no session being connected is not an error, there is just nobody to tell
+        let sessions = self
+            .session_context
+            .server_events
+            .send(Event::Announcement(event::AnnouncementEvent { text }))
+            .unwrap_or(0);
+
+        Ok(AdminResponse::Done {
+            message: format!("announced to {} sessions", sessions),
+        })
+    }
+
+    /// Streams the stored messages of the room sent within the range, a chunk at a time,
+    /// so the whole history of a room is never held in memory
+    async fn export_room(
+        &self,
+        room: &str,
+        format: ExportFormat,
+        since: Option<u64>,
+        until: Option<u64>,
+        writer: &mut OwnedWriteHalf,
+    ) -> anyhow::Result<AdminResponse> {
+        let mut after = None;
+        let mut exported = 0;
+
+        loop {
+            let page = self
+                .session_context
+                .room_manager
+                .export_page(room, since, until, after, EXPORT_CHUNK_SIZE)
+                .await?;
+            let Some((last_id, _)) = page.last() else {
+                break;
+            };
+            after = Some(*last_id);
+            exported += page.len();
+
+            let lines = page
+                .iter()
+                .map(|(_, message)| export_line(format, message))
+                .collect();
+            write_response(writer, &AdminResponse::ExportChunk { lines }).await?;
+
+            if page.len() < EXPORT_CHUNK_SIZE {
+                break;
+            }
+        }
+
+        Ok(AdminResponse::Done {
+            message: format!("exported {} messages of room '{}'", exported, room),
+        })
+    }
+
+    async fn stats(&self) -> AdminResponse {
+        let messages_sent = self.session_context.room_manager.messages_sent().await;
+
+        AdminResponse::Stats(AdminStats {
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            sessions: self.session_context.protocol_metrics.active_sessions(),
+            dropped_sessions: self.session_context.session_registry.dropped_sessions(),
+            rooms: messages_sent.len(),
+            messages_sent: messages_sent.iter().map(|(_, sent)| sent).sum(),
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use comms::event::{AnnouncementEvent, Event};
+use tokio::{sync::broadcast, time::Instant};
+use tracing::debug;
+
+use crate::config::ScheduledAnnouncement;
+
+/// Sends the announcements scheduled by the configuration to every session, each one at its interval,
+/// until the server shuts down
+pub async fn run_scheduled_announcements(
+    announcements: Vec<ScheduledAnnouncement>,
+    server_events: broadcast::Sender<Event>,
+    mut quit_rx: broadcast::Receiver<()>,
+) -> anyhow::Result<()> {
+    let started_at = Instant::now();
+    let mut due_at = announcements
+        .iter()
+        .map(|announcement| started_at + Duration::from_secs(announcement.every_secs))
+        .collect::<Vec<_>>();
+
+    loop {
+        let Some((index, next_due_at)) = due_at
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|(_, due_at)| *due_at)
+        else {
+            return Ok(());
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep_until(next_due_at) => {}
+            _ = quit_rx.recv() => return Ok(()),
+        }
+
+        let announcement = &announcements[index];
+        // nobody being connected is not an error, there is just nobody to tell
+        let sessions = server_events
+            .send(Event::Announcement(AnnouncementEvent {
+                text: announcement.text.clone(),
+            }))
+            .unwrap_or(0);
+        debug!(sessions, "sent a scheduled announcement");
+
+        due_at[index] += Duration::from_secs(announcement.every_secs);
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{Days, NaiveDate};
+use comms::admin::{AdminRequest, AdminResponse, ExportFormat};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+/// Environment variable with the path of the Unix socket of the admin console, as the server reads it
+const ADMIN_SOCKET_PATH_ENV: &str = "CHAT_ADMIN_SOCKET_PATH";
+const USAGE: &str = "usage: chat-admin [--socket <path>] <sessions | room <name> | kick <username> [reason] | announce <text> | stats | export <room> [--format json|text] [--since <yyyy-mm-dd>] [--until <yyyy-mm-dd>]>";
+const DEFAULT_KICK_REASON: &str = "kicked by the operator";
+
+/// Midnight of the given date in UTC, in milliseconds since the epoch
+fn date_millis(date: NaiveDate) -> u64 {
+    date.and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis()
+        .max(0) as u64
+}
+
+/// Reads the export of a room with its options, the range goes from the start of the first day to the end of the last one
+fn parse_export(room: &str, options: &[String]) -> anyhow::Result<AdminRequest> {
+    let mut format = ExportFormat::Text;
+    let mut since = None;
+    let mut until = None;
+
+    for option in options.chunks(2) {
+        let [name, value] = option else {
+            return Err(anyhow::anyhow!(USAGE));
+        };
+        let date = || {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| anyhow::anyhow!("dates are written as yyyy-mm-dd, not '{}'", value))
+        };
+
+        match name.as_str() {
+            "--format" => {
+                format = match value.as_str() {
+                    "json" => ExportFormat::Json,
+                    "text" => ExportFormat::Text,
+                    _ => return Err(anyhow::anyhow!(USAGE)),
+                }
+            }
+            "--since" => since = Some(date_millis(date()?)),
+            "--until" => {
+                let date = date()?;
+                until = Some(date_millis(
+                    date.checked_add_days(Days::new(1)).unwrap_or(date),
+                ));
+            }
+            _ => return Err(anyhow::anyhow!(USAGE)),
+        }
+    }
+
+    Ok(AdminRequest::ExportRoom {
+        room: String::from(room),
+        format,
+        since,
+        until,
+    })
+}
+
+/// Reads the request from the command line, the flags aside
+fn parse_request(args: &[String]) -> anyhow::Result<AdminRequest> {
+    let request = match args {
+        [command] if command == "sessions" => AdminRequest::ListSessions,
+        [command] if command == "stats" => AdminRequest::Stats,
+        [command, room] if command == "room" => AdminRequest::InspectRoom { room: room.clone() },
+        [command, username, reason @ ..] if command == "kick" => AdminRequest::KickUser {
+            username: username.clone(),
+            reason: match reason {
+                [] => String::from(DEFAULT_KICK_REASON),
+                reason => reason.join(" "),
+            },
+        },
+        [command, room, options @ ..] if command == "export" => parse_export(room, options)?,
+        [command, text @ ..] if command == "announce" && !text.is_empty() => {
+            AdminRequest::Announce {
+                text: text.join(" "),
+            }
+        }
+        _ => return Err(anyhow::anyhow!(USAGE)),
+    };
+
+    Ok(request)
+}
+
+/// Prints the response the way an operator reads it, fails on an error response
+///
+/// The lines of an export are printed as they are.
+fn print_response(response: AdminResponse) -> anyhow::Result<()> {
+    match response {
+        AdminResponse::ExportChunk { lines } => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        AdminResponse::Sessions { sessions } => {
+            println!("{} connected sessions", sessions.len());
+            for session in sessions {
+                println!(
+                    "{}\t{}\tconnected at {}",
+                    session.session_id, session.username, session.connected_at
+                );
+            }
+        }
+        AdminResponse::Room(room) => {
+            println!(
+                "{}{}: {}",
+                room.name,
+                if room.is_private { " (private)" } else { "" },
+                room.description
+            );
+            println!("{} messages sent", room.messages_sent);
+            println!("{} users: {}", room.users.len(), room.users.join(", "));
+            for (username, role) in room.roles {
+                println!("{}\t{:?}", username, role);
+            }
+        }
+        AdminResponse::Stats(stats) => {
+            println!("uptime: {}s", stats.uptime_secs);
+            for (version, count) in stats.sessions {
+                println!("sessions on protocol v{}: {}", version, count);
+            }
+            println!("dropped sessions: {}", stats.dropped_sessions);
+            println!("rooms: {}", stats.rooms);
+            println!("messages sent: {}", stats.messages_sent);
+        }
+        AdminResponse::Done { message } => println!("{}", message),
+        AdminResponse::Error { message } => return Err(anyhow::anyhow!(message)),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let socket_path = match args.iter().position(|arg| arg == "--socket") {
+        Some(index) if index + 1 < args.len() => {
+            let socket_path = args.remove(index + 1);
+            args.remove(index);
+
+            socket_path
+        }
+        Some(_) => return Err(anyhow::anyhow!(USAGE)),
+        None => std::env::var(ADMIN_SOCKET_PATH_ENV).map_err(|_| {
+            anyhow::anyhow!(
+                "give the admin socket with --socket or {}",
+                ADMIN_SOCKET_PATH_ENV
+            )
+        })?,
+    };
+    let request = parse_request(&args)?;
+
+    let socket = UnixStream::connect(&socket_path)
+        .await
+        .map_err(|err| anyhow::anyhow!("could not connect to {}: {}", socket_path, err))?;
+    let is_export = matches!(request, AdminRequest::ExportRoom { .. });
+    let (reader, mut writer) = socket.into_split();
+    let mut request = serde_json::to_string(&request)?;
+    request.push('\n');
+    writer.write_all(request.as_bytes()).await?;
+
+    // the chunks of an export come before its final response, which goes to the standard error
+    // so the standard output can be redirected to a file
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let line = lines
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("the server closed the admin console"))?;
+
+        match serde_json::from_str(&line)? {
+            AdminResponse::Done { message } if is_export => {
+                eprintln!("{}", message);
+                return Ok(());
+            }
+            response @ AdminResponse::ExportChunk { .. } => print_response(response)?,
+            response => return print_response(response),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use comms::event::ErrorCode;
+
+/// [CommandError] is a failure of a user command which the client is told the [ErrorCode] of
+///
+/// It is carried through `anyhow::Error` like the other failures, the errors which are not
+/// a [CommandError] are reported with [ErrorCode::Failed].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    RoomNotFound(String),
+    SpaceNotFound(String),
+    /// The user is not a member of the room
+    NotAMember(String),
+    AlreadyJoinedRoom(String),
+    AlreadyJoinedSpace(String),
+    /// The message is longer than the given number of characters
+    MessageTooLong(usize),
+    /// The user is not allowed to run the command, for the given reason
+    PermissionDenied(String),
+    /// The display name is longer than the given number of characters, or has control characters
+    InvalidDisplayName(usize),
+    /// A field of the profile can not be changed as asked, for the given reason
+    InvalidProfile(String),
+    /// The user can not be muted for longer than the given number of seconds
+    InvalidMuteDuration(u64),
+}
+
+impl CommandError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CommandError::RoomNotFound(_) => ErrorCode::RoomNotFound,
+            CommandError::SpaceNotFound(_) => ErrorCode::SpaceNotFound,
+            CommandError::NotAMember(_) => ErrorCode::NotAMember,
+            CommandError::AlreadyJoinedRoom(_) | CommandError::AlreadyJoinedSpace(_) => {
+                ErrorCode::AlreadyJoined
+            }
+            CommandError::MessageTooLong(_) => ErrorCode::MessageTooLong,
+            CommandError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            CommandError::InvalidDisplayName(_)
+            | CommandError::InvalidProfile(_)
+            | CommandError::InvalidMuteDuration(_) => ErrorCode::Failed,
+        }
+    }
+
+    /// Returns the code of any error a command has failed with
+    pub fn code_of(err: &anyhow::Error) -> ErrorCode {
+        err.downcast_ref::<CommandError>()
+            .map(CommandError::code)
+            .unwrap_or(ErrorCode::Failed)
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::RoomNotFound(room) => write!(f, "room '{}' not found", room),
+            CommandError::SpaceNotFound(space) => write!(f, "space '{}' not found", space),
+            CommandError::NotAMember(room) => write!(f, "you are not a member of room '{}'", room),
+            CommandError::AlreadyJoinedRoom(room) => write!(f, "already joined room '{}'", room),
+            CommandError::AlreadyJoinedSpace(space) => {
+                write!(f, "already joined space '{}'", space)
+            }
+            CommandError::MessageTooLong(max_chars) => {
+                write!(f, "messages are at most {} characters long", max_chars)
+            }
+            CommandError::PermissionDenied(reason) | CommandError::InvalidProfile(reason) => {
+                write!(f, "{}", reason)
+            }
+            CommandError::InvalidDisplayName(max_chars) => write!(
+                f,
+                "nicknames are at most {} characters long, without control characters",
+                max_chars
+            ),
+            CommandError::InvalidMuteDuration(max_seconds) => {
+                write!(f, "users are muted for at most {} seconds", max_seconds)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::room_manager::{ChatRoomMetadata, ContentFilterRule, WordListFilter};
+
+const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
+
+/// The limits of the server, any of them left out keeps its default value
+///
+/// They are applied again when the configuration is reloaded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// The window in which the exact duplicates of a message sent by the same user are dropped, in milliseconds
+    pub duplicate_suppression_window_ms: Option<u64>,
+    /// How many characters the messages sent to the rooms can be at most
+    pub max_message_chars: Option<usize>,
+    /// How many messages and joins each connection can send per second, 0 disables the limit
+    pub messages_per_second: Option<f64>,
+    pub joins_per_second: Option<f64>,
+    /// How many invalid commands are tolerated before the responses are delayed, and get the ip of a client banned
+    pub tarpit_free_strikes: Option<u32>,
+    pub tarpit_ban_strikes: Option<u32>,
+    /// How long a banned ip is refused, in seconds
+    pub tarpit_ban_duration_secs: Option<u64>,
+    /// How many times a user can send the same message within the window, in seconds, before being muted for spamming
+    pub spam_max_duplicates: Option<u32>,
+    pub spam_duplicate_window_secs: Option<u64>,
+    /// The score a user can reach within the window, in seconds, each message scoring 1 plus 1 for each of its links
+    pub spam_max_burst_score: Option<u32>,
+    pub spam_burst_window_secs: Option<u64>,
+    /// How many links a single message can hold
+    pub spam_max_links: Option<u32>,
+    /// How long the users caught spamming are muted, in seconds
+    pub spam_mute_secs: Option<u64>,
+    /// How often the clients are pinged, 0 disables the pings, and how many pings in a row they can leave unanswered
+    pub heartbeat_interval_secs: Option<u64>,
+    pub heartbeat_max_missed_pongs: Option<u32>,
+    /// How long a connected user can be idle before being shown away, in seconds
+    pub presence_away_after_secs: Option<u64>,
+    /// The oldest protocol version the clients are served with
+    pub min_protocol_version: Option<u16>,
+    /// How many events are queued for each client before the superseded ones are dropped, or the client disconnected
+    pub outbound_queue_capacity: Option<usize>,
+}
+
+/// An announcement sent to every session at its interval, the first time once the interval has passed
+/// since the server started or the configuration was reloaded
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledAnnouncement {
+    pub text: String,
+    /// How often the announcement is sent, in seconds
+    pub every_secs: u64,
+}
+
+/// [ServerConfig] is the TOML configuration file of the server, read at startup and again on `SIGHUP`
+///
+/// The ports are only bound at startup, a reload adds the rooms which were not defined yet and updates the limits
+/// along with the scheduled announcements and what the clients are told when the server shuts down.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// The port of the WebSocket listener, which serves the same commands and events as text messages
+    pub websocket_port: u16,
+    /// The port of the TLS listener, which is only started when a certificate is given
+    pub tls_port: u16,
+    /// The port of the HTTP listener of the webhooks, which is only started when webhooks are defined
+    pub webhook_port: u16,
+    /// The port of the Matrix application service, which is only started when the Matrix bridge is defined
+    pub matrix_bridge_port: u16,
+    /// The port of the HTTP listener serving the metrics, they are not served without it
+    pub metrics_port: Option<u16>,
+    /// The path of the Unix socket of the admin console, it is not served without it
+    pub admin_socket_path: Option<PathBuf>,
+    pub limits: LimitsConfig,
+    /// How long the clients are given to wrap up once the server is shutting down, in seconds
+    pub shutdown_grace_period_secs: u64,
+    /// What the clients are told when the server is shutting down
+    pub shutdown_reason: String,
+    pub announcements: Vec<ScheduledAnnouncement>,
+    /// The rooms defined at startup, the ones of the resources when left out
+    pub rooms: Vec<ChatRoomMetadata>,
+    /// The content rules the messages of the rooms are filtered with, in order
+    pub content_filters: Vec<ContentFilterRule>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            port: 8080,
+            websocket_port: 8082,
+            tls_port: 8443,
+            webhook_port: 8083,
+            matrix_bridge_port: 8084,
+            metrics_port: None,
+            admin_socket_path: None,
+            limits: LimitsConfig::default(),
+            shutdown_grace_period_secs: 10,
+            shutdown_reason: String::from("the server is restarting"),
+            announcements: Vec::new(),
+            rooms: serde_json::from_str(CHAT_ROOMS_METADATAS)
+                .expect("could not parse the chat rooms metadatas"),
+            content_filters: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads the configuration from its TOML file
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let config: ServerConfig = toml::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("could not read {}", path.display()))?,
+        )
+        .with_context(|| format!("could not parse {}", path.display()))?;
+
+        for (index, room) in config.rooms.iter().enumerate() {
+            if config.rooms[..index]
+                .iter()
+                .any(|other_room| other_room.name == room.name)
+            {
+                return Err(anyhow::anyhow!("room '{}' is defined twice", room.name));
+            }
+        }
+
+        for announcement in config.announcements.iter() {
+            if announcement.text.trim().is_empty() || announcement.every_secs == 0 {
+                return Err(anyhow::anyhow!(
+                    "announcements need a text and an interval of at least a second"
+                ));
+            }
+        }
+
+        // the patterns are checked here, so that a configuration with an invalid one is not applied
+        WordListFilter::new(&config.content_filters)?;
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use comms::event::{self, Event};
+
+use crate::{
+    clock::now_millis, room_manager::SessionAndUserId, session::OutboundSender,
+    sharded_map::ShardedMap,
+};
+
+/// [DirectMessageRouter] delivers the direct messages, and the other events addressed to a user
+/// rather than a room such as invitations, to the sessions of the users
+///
+/// Each chat session registers the queue its events are sent to the user from,
+/// so a direct message reaches every session of the recipient and the sender without waiting on them.
+/// The sessions are sharded by user, so the users do not wait on each other to register their sessions.
+#[derive(Debug, Default)]
+pub struct DirectMessageRouter {
+    sessions: ShardedMap<String, HashMap<String, OutboundSender>>,
+}
+
+impl DirectMessageRouter {
+    pub fn new() -> Self {
+        DirectMessageRouter::default()
+    }
+
+    pub fn register(&self, session_and_user_id: &SessionAndUserId, outbound_tx: OutboundSender) {
+        self.sessions
+            .shard(&session_and_user_id.user_id)
+            .entry(session_and_user_id.user_id.clone())
+            .or_default()
+            .insert(session_and_user_id.session_id.clone(), outbound_tx);
+    }
+
+    pub fn unregister(&self, session_and_user_id: &SessionAndUserId) {
+        let mut sessions = self.sessions.shard(&session_and_user_id.user_id);
+
+        if let Some(user_sessions) = sessions.get_mut(&session_and_user_id.user_id) {
+            user_sessions.remove(&session_and_user_id.session_id);
+
+            if user_sessions.is_empty() {
+                sessions.remove(&session_and_user_id.user_id);
+            }
+        }
+    }
+
+    /// Sends an event to every session of the user, fails if the user has no active session
+    pub fn deliver(&self, user_id: &str, event: Event) -> anyhow::Result<()> {
+        let sessions = self.sessions.shard(user_id);
+        let user_sessions = sessions
+            .get(user_id)
+            .ok_or_else(|| anyhow::anyhow!("user '{}' is not online", user_id))?;
+
+        for outbound_tx in user_sessions.values() {
+            outbound_tx.push(event.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Sends a direct message to the sessions of the recipient, and echoes it to the sessions of the sender
+    /// Fails if the recipient has no active session
+    pub fn send(
+        &self,
+        from_user_id: &str,
+        to_user_id: &str,
+        content: String,
+    ) -> anyhow::Result<()> {
+        if from_user_id == to_user_id {
+            return Err(anyhow::anyhow!("can not send a direct message to yourself"));
+        }
+
+        let event = Event::DirectMessage(event::DirectMessageBroadcastEvent {
+            from_user_id: String::from(from_user_id),
+            to_user_id: String::from(to_user_id),
+            content,
+            timestamp: now_millis(),
+        });
+
+        {
+            let sessions = self.sessions.shard(to_user_id);
+            let Some(recipient_sessions) = sessions.get(to_user_id) else {
+                return Err(anyhow::anyhow!("user '{}' is not online", to_user_id));
+            };
+
+            for outbound_tx in recipient_sessions.values() {
+                outbound_tx.push(event.clone());
+            }
+        }
+
+        // the shard of the recipient is released first, the sender may share it
+        if let Some(sender_sessions) = self.sessions.shard(from_user_id).get(from_user_id) {
+            for outbound_tx in sender_sessions.values() {
+                outbound_tx.push(event.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,50 +1,783 @@
-use std::sync::Arc;
+use std::{
+    fs::{DirBuilder, Permissions},
+    net::SocketAddr,
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
-use room_manager::RoomManagerBuilder;
-use tokio::{net::TcpListener, signal::ctrl_c, sync::broadcast, task::JoinSet};
+use comms::{cli, event};
+use room_manager::{
+    RoomManagerBuilder, WordListFilter, DEFAULT_DUPLICATE_SUPPRESSION_WINDOW,
+    DEFAULT_MAX_MESSAGE_CHARS,
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    signal::{
+        ctrl_c,
+        unix::{signal, SignalKind},
+    },
+    sync::{broadcast, watch},
+    task::{JoinError, JoinSet},
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 
-use crate::room_manager::ChatRoomMetadata;
+use crate::{
+    access_log::AccessLog,
+    admin::AdminConsole,
+    config::{LimitsConfig, ServerConfig},
+    direct_message_router::DirectMessageRouter,
+    matrix_bridge::AppService,
+    metrics::{MetricsEndpoint, ServerMetrics},
+    outgoing_webhooks::OutgoingWebhook,
+    presence_tracker::PresenceTracker,
+    session::{
+        HeartbeatPolicy, ProtocolMetrics, RateLimit, RateLimitPolicy, SessionContext,
+        SessionRegistry, DEFAULT_OUTBOUND_QUEUE_CAPACITY,
+    },
+    space_manager::{ChatSpaceMetadata, SpaceManager},
+    spam_guard::{SpamGuard, SpamPolicy},
+    storage::{AttachmentStore, BanStore, CredentialStore, MessageStore, ProfileStore},
+    tarpit::{Tarpit, TarpitPolicy},
+    webhooks::Webhooks,
+};
 
+mod access_log;
+mod admin;
+mod announcements;
+mod clock;
+mod command_error;
+mod config;
+mod direct_message_router;
+mod matrix_bridge;
+mod metrics;
+mod outgoing_webhooks;
+mod presence_tracker;
 mod room_manager;
 mod session;
+mod sharded_map;
+mod space_manager;
+mod spam_guard;
+mod storage;
+mod tarpit;
+mod tls;
+mod webhooks;
 
-const PORT: u16 = 8080;
-const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
+/// Command line flag with the path of the TOML configuration file, the default configuration is used without it
+const CONFIG_FLAG: &str = "--config";
+/// Command line flags with the paths of the PEM certificate chain and private key of the TLS listener
+const TLS_CERT_FLAG: &str = "--tls-cert";
+const TLS_KEY_FLAG: &str = "--tls-key";
+/// How many of the announcements and the shutdown notice are buffered for the sessions which are slow to forward them
+const SERVER_EVENTS_CAPACITY: usize = 16;
+/// How long a client has to complete the TLS or the WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const CHAT_SPACES_METADATAS: &str = include_str!("../resources/chat_spaces_metadatas.json");
+/// Environment variable to override the duplicate message suppression window, in milliseconds
+const DUPLICATE_SUPPRESSION_WINDOW_ENV: &str = "CHAT_DUPLICATE_SUPPRESSION_WINDOW_MS";
+/// Environment variable to override how many characters the messages can be at most
+const MAX_MESSAGE_CHARS_ENV: &str = "CHAT_MAX_MESSAGE_CHARS";
+/// Environment variable to override the grace period before a deleted account is anonymized, in seconds
+const ACCOUNT_DELETION_GRACE_PERIOD_ENV: &str = "CHAT_ACCOUNT_DELETION_GRACE_PERIOD_SECS";
+const DEFAULT_ACCOUNT_DELETION_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+/// Environment variable to override how many invalid commands are tolerated before responses are delayed
+const TARPIT_FREE_STRIKES_ENV: &str = "CHAT_TARPIT_FREE_STRIKES";
+/// Environment variable to override how many invalid commands get the ip of a client banned
+const TARPIT_BAN_STRIKES_ENV: &str = "CHAT_TARPIT_BAN_STRIKES";
+/// Environment variable to override how long a banned ip is refused, in seconds
+const TARPIT_BAN_DURATION_ENV: &str = "CHAT_TARPIT_BAN_DURATION_SECS";
+/// Environment variables to override how many messages and joins each connection can send per second, 0 disables the limit
+const RATE_LIMIT_MESSAGES_PER_SECOND_ENV: &str = "CHAT_RATE_LIMIT_MESSAGES_PER_SEC";
+const RATE_LIMIT_JOINS_PER_SECOND_ENV: &str = "CHAT_RATE_LIMIT_JOINS_PER_SEC";
+/// Environment variables to override how often the clients are pinged, 0 disables the pings,
+/// and how many pings in a row they can leave unanswered before being disconnected
+const HEARTBEAT_INTERVAL_ENV: &str = "CHAT_HEARTBEAT_INTERVAL_SECS";
+const HEARTBEAT_MAX_MISSED_PONGS_ENV: &str = "CHAT_HEARTBEAT_MAX_MISSED_PONGS";
+/// Environment variable to override how long a connected user can be idle before being shown away, in seconds
+const PRESENCE_AWAY_AFTER_ENV: &str = "CHAT_PRESENCE_AWAY_AFTER_SECS";
+const DEFAULT_PRESENCE_AWAY_AFTER: Duration = Duration::from_secs(5 * 60);
+/// Environment variable to disconnect the clients older than the given protocol version, all of them are served by default
+const MIN_PROTOCOL_VERSION_ENV: &str = "CHAT_MIN_PROTOCOL_VERSION";
+/// Environment variable to override how many events are queued for each client before it is treated as a slow consumer
+const OUTBOUND_QUEUE_CAPACITY_ENV: &str = "CHAT_OUTBOUND_QUEUE_CAPACITY";
+/// Environment variable to override how long the clients are given to wrap up once the server is shutting down, in seconds
+const SHUTDOWN_GRACE_PERIOD_ENV: &str = "CHAT_SHUTDOWN_GRACE_PERIOD_SECS";
+/// Environment variable to override the path of the SQLite database the messages are persisted to
+const DATABASE_PATH_ENV: &str = "CHAT_DATABASE_PATH";
+const DEFAULT_DATABASE_PATH: &str = "chat.sqlite3";
+/// Environment variable to override the directory the files shared with the rooms are kept in
+const ATTACHMENTS_DIR_ENV: &str = "CHAT_ATTACHMENTS_DIR";
+const DEFAULT_ATTACHMENTS_DIR: &str = "attachments";
+/// Environment variable with the path of the JSON file defining the webhooks, none are served without it
+const WEBHOOKS_PATH_ENV: &str = "CHAT_WEBHOOKS_PATH";
+/// Environment variable with the path of the JSON file defining the outgoing webhooks, which post the messages of the rooms
+const OUTGOING_WEBHOOKS_PATH_ENV: &str = "CHAT_OUTGOING_WEBHOOKS_PATH";
+/// Environment variable with the path of the JSON file defining the Matrix bridge, the rooms are not bridged without it
+const MATRIX_BRIDGE_PATH_ENV: &str = "CHAT_MATRIX_BRIDGE_PATH";
+/// Environment variable with the port of the HTTP listener serving the metrics at `/metrics`, overriding the configuration
+const METRICS_PORT_ENV: &str = "CHAT_METRICS_PORT";
+/// Environment variable with the path of the Unix socket of the admin console, overriding the configuration
+const ADMIN_SOCKET_PATH_ENV: &str = "CHAT_ADMIN_SOCKET_PATH";
+/// Environment variable with the format of the logs, `json` for one JSON object per line, readable text otherwise
+const LOG_FORMAT_ENV: &str = "CHAT_LOG_FORMAT";
+/// The logs kept when `RUST_LOG` does not filter them, as in `RUST_LOG=server=debug`
+const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Reads and parses an environment variable, panics if it is set to an invalid value
+fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("could not parse the environment variable {}", name))
+    })
+}
+
+/// Logs the events of the server and the spans of its sessions and commands, as filtered by `RUST_LOG`
+fn init_tracing() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match env_var::<String>(LOG_FORMAT_ENV).as_deref() {
+        Some("json") => subscriber.json().init(),
+        _ => subscriber.init(),
+    }
+}
+
+/// The limits of the server, each one read from its environment variable,
+/// or else from the configuration file, or else its default value
+struct Limits {
+    duplicate_suppression_window: Duration,
+    max_message_chars: usize,
+    rate_limit_policy: RateLimitPolicy,
+    tarpit_policy: TarpitPolicy,
+    spam_policy: SpamPolicy,
+    heartbeat_policy: HeartbeatPolicy,
+    presence_away_after: Duration,
+    min_protocol_version: u16,
+    outbound_queue_capacity: usize,
+}
+
+impl Limits {
+    fn new(config: &LimitsConfig) -> Self {
+        let default_rate_limit_policy = RateLimitPolicy::default();
+        let default_tarpit_policy = TarpitPolicy::default();
+        let default_spam_policy = SpamPolicy::default();
+        let default_heartbeat_policy = HeartbeatPolicy::default();
+
+        Limits {
+            duplicate_suppression_window: env_var(DUPLICATE_SUPPRESSION_WINDOW_ENV)
+                .or(config.duplicate_suppression_window_ms)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_DUPLICATE_SUPPRESSION_WINDOW),
+            max_message_chars: env_var(MAX_MESSAGE_CHARS_ENV)
+                .or(config.max_message_chars)
+                .unwrap_or(DEFAULT_MAX_MESSAGE_CHARS),
+            rate_limit_policy: RateLimitPolicy {
+                messages: RateLimit {
+                    per_second: env_var(RATE_LIMIT_MESSAGES_PER_SECOND_ENV)
+                        .or(config.messages_per_second)
+                        .unwrap_or(default_rate_limit_policy.messages.per_second),
+                    ..default_rate_limit_policy.messages
+                },
+                joins: RateLimit {
+                    per_second: env_var(RATE_LIMIT_JOINS_PER_SECOND_ENV)
+                        .or(config.joins_per_second)
+                        .unwrap_or(default_rate_limit_policy.joins.per_second),
+                    ..default_rate_limit_policy.joins
+                },
+            },
+            tarpit_policy: TarpitPolicy {
+                free_strikes: env_var(TARPIT_FREE_STRIKES_ENV)
+                    .or(config.tarpit_free_strikes)
+                    .unwrap_or(default_tarpit_policy.free_strikes),
+                ban_strikes: env_var(TARPIT_BAN_STRIKES_ENV)
+                    .or(config.tarpit_ban_strikes)
+                    .unwrap_or(default_tarpit_policy.ban_strikes),
+                ban_duration: env_var(TARPIT_BAN_DURATION_ENV)
+                    .or(config.tarpit_ban_duration_secs)
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_tarpit_policy.ban_duration),
+                ..default_tarpit_policy
+            },
+            spam_policy: SpamPolicy {
+                duplicate_window: config
+                    .spam_duplicate_window_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_spam_policy.duplicate_window),
+                max_duplicates: config
+                    .spam_max_duplicates
+                    .unwrap_or(default_spam_policy.max_duplicates),
+                burst_window: config
+                    .spam_burst_window_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_spam_policy.burst_window),
+                max_burst_score: config
+                    .spam_max_burst_score
+                    .unwrap_or(default_spam_policy.max_burst_score),
+                max_links: config
+                    .spam_max_links
+                    .unwrap_or(default_spam_policy.max_links),
+                mute_duration: config
+                    .spam_mute_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_spam_policy.mute_duration),
+            },
+            heartbeat_policy: HeartbeatPolicy {
+                interval: env_var(HEARTBEAT_INTERVAL_ENV)
+                    .or(config.heartbeat_interval_secs)
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_heartbeat_policy.interval),
+                max_missed_pongs: env_var(HEARTBEAT_MAX_MISSED_PONGS_ENV)
+                    .or(config.heartbeat_max_missed_pongs)
+                    .unwrap_or(default_heartbeat_policy.max_missed_pongs),
+            },
+            presence_away_after: env_var(PRESENCE_AWAY_AFTER_ENV)
+                .or(config.presence_away_after_secs)
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_PRESENCE_AWAY_AFTER),
+            min_protocol_version: env_var(MIN_PROTOCOL_VERSION_ENV)
+                .or(config.min_protocol_version)
+                .unwrap_or(1),
+            outbound_queue_capacity: env_var(OUTBOUND_QUEUE_CAPACITY_ENV)
+                .or(config.outbound_queue_capacity)
+                .unwrap_or(DEFAULT_OUTBOUND_QUEUE_CAPACITY),
+        }
+    }
+}
+
+/// Reads the configuration file given with `--config`, or the default configuration without one
+fn load_config() -> anyhow::Result<ServerConfig> {
+    match cli::flag(CONFIG_FLAG) {
+        Some(config_path) => ServerConfig::load(Path::new(&config_path)),
+        None => Ok(ServerConfig::default()),
+    }
+}
+
+/// Reads the configuration file again, adding the rooms it defines and applying its limits, returning it
+///
+/// The connections are kept: their rate limits and the length of their messages change right away, while
+/// the heartbeat, the oldest protocol version served and the capacity of the outbound queues apply to the next
+/// connections, which are also the ones told about the new length.
+async fn reload_config(
+    session_context: &mut SessionContext,
+    rate_limit_tx: &watch::Sender<RateLimitPolicy>,
+) -> anyhow::Result<ServerConfig> {
+    let config = load_config()?;
+    let limits = Limits::new(&config.limits);
+
+    let added_rooms = session_context.room_manager.define_rooms(&config.rooms);
+    session_context
+        .room_manager
+        .set_duplicate_suppression_window(limits.duplicate_suppression_window)
+        .await;
+    session_context
+        .room_manager
+        .set_max_message_chars(limits.max_message_chars);
+    session_context
+        .room_manager
+        .set_message_filter(Arc::new(WordListFilter::new(&config.content_filters)?))
+        .await;
+    rate_limit_tx.send_replace(limits.rate_limit_policy);
+    session_context.tarpit.set_policy(limits.tarpit_policy);
+    session_context.spam_guard.set_policy(limits.spam_policy);
+    session_context
+        .presence_tracker
+        .set_away_after(limits.presence_away_after);
+    session_context.heartbeat_policy = limits.heartbeat_policy;
+    session_context.min_protocol_version = limits.min_protocol_version;
+    session_context.outbound_queue_capacity = limits.outbound_queue_capacity;
+
+    info!(?added_rooms, "reloaded the configuration");
+    Ok(config)
+}
+
+/// Accepts the next connection on the TLS listener, never resolves when there is none
+async fn accept_tls(
+    tls_listener: &Option<(TcpListener, TlsAcceptor)>,
+) -> std::io::Result<(TcpStream, SocketAddr, TlsAcceptor)> {
+    match tls_listener {
+        Some((listener, acceptor)) => {
+            let (socket, addr) = listener.accept().await?;
+
+            Ok((socket, addr, acceptor.clone()))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Accepts the next connection on the webhook listener, never resolves when there is none
+async fn accept_webhook(
+    webhook_listener: &Option<(TcpListener, Arc<Webhooks>)>,
+) -> std::io::Result<(TcpStream, SocketAddr, Arc<Webhooks>)> {
+    match webhook_listener {
+        Some((listener, webhooks)) => {
+            let (socket, addr) = listener.accept().await?;
+
+            Ok((socket, addr, Arc::clone(webhooks)))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_metrics(
+    metrics_listener: &Option<(TcpListener, Arc<MetricsEndpoint>)>,
+) -> std::io::Result<(TcpStream, SocketAddr, Arc<MetricsEndpoint>)> {
+    match metrics_listener {
+        Some((listener, endpoint)) => {
+            let (socket, addr) = listener.accept().await?;
+
+            Ok((socket, addr, Arc::clone(endpoint)))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_matrix_bridge(
+    matrix_bridge_listener: &Option<(TcpListener, Arc<AppService>)>,
+) -> std::io::Result<(TcpStream, SocketAddr, Arc<AppService>)> {
+    match matrix_bridge_listener {
+        Some((listener, appservice)) => {
+            let (socket, addr) = listener.accept().await?;
+
+            Ok((socket, addr, Arc::clone(appservice)))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_admin(
+    admin_listener: &Option<(UnixListener, Arc<AdminConsole>)>,
+) -> std::io::Result<(UnixStream, Arc<AdminConsole>)> {
+    match admin_listener {
+        Some((listener, console)) => {
+            let (socket, _) = listener.accept().await?;
+
+            Ok((socket, Arc::clone(console)))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Binds the admin socket in a private directory, so nobody else can reach it before its permissions are restricted,
+/// then moves it into place
+fn bind_admin_socket(admin_socket_path: &Path) -> std::io::Result<UnixListener> {
+    let parent = admin_socket_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let private_dir = parent.join(format!(".admin-{}", nanoid::nanoid!(8)));
+    DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+    let private_path = private_dir.join("admin.sock");
+    let bound = UnixListener::bind(&private_path).and_then(|listener| {
+        // only the user running the server can reach the console
+        std::fs::set_permissions(&private_path, Permissions::from_mode(0o600))?;
+        std::fs::rename(&private_path, admin_socket_path)?;
+
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&private_dir);
+
+    bound
+}
+
+/// Logs why a task of the server failed, the aborted ones are expected
+fn log_task_result(result: Result<anyhow::Result<()>, JoinError>) {
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => error!("{:#}", err),
+        Err(err) if err.is_cancelled() => {}
+        Err(err) => error!("a task panicked: {}", err),
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let chat_room_metadatas: Vec<ChatRoomMetadata> = serde_json::from_str(CHAT_ROOMS_METADATAS)
-        .expect("could not parse the chat rooms metadatas");
+    init_tracing();
+    let mut config = load_config().expect("could not load the configuration");
+    let chat_space_metadatas: Vec<ChatSpaceMetadata> = serde_json::from_str(CHAT_SPACES_METADATAS)
+        .expect("could not parse the chat spaces metadatas");
+
+    for metadata in chat_space_metadatas.iter() {
+        for room in metadata.rooms.iter().chain(metadata.default_rooms.iter()) {
+            if !config
+                .rooms
+                .iter()
+                .any(|room_metadata| room_metadata.name.eq(room))
+            {
+                panic!(
+                    "space '{}' refers to an unknown room '{}'",
+                    metadata.name, room
+                );
+            }
+        }
+    }
+
+    let limits = Limits::new(&config.limits);
+    let account_deletion_grace_period = env_var(ACCOUNT_DELETION_GRACE_PERIOD_ENV)
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_ACCOUNT_DELETION_GRACE_PERIOD);
+    let server_metrics = Arc::new(ServerMetrics::new());
+    let tarpit = Arc::new(Tarpit::new(
+        limits.tarpit_policy,
+        Arc::clone(&server_metrics),
+    ));
+    let (rate_limit_tx, rate_limit_rx) = watch::channel(limits.rate_limit_policy);
+    let presence_tracker = Arc::new(PresenceTracker::new(limits.presence_away_after));
+    presence_tracker.spawn_idle_sweep();
+    let database_path: PathBuf =
+        env_var(DATABASE_PATH_ENV).unwrap_or_else(|| PathBuf::from(DEFAULT_DATABASE_PATH));
+    let message_store =
+        Arc::new(MessageStore::open(&database_path).expect("could not open the message database"));
+    let (server_events, _) = broadcast::channel(SERVER_EVENTS_CAPACITY);
+    let ban_store =
+        Arc::new(BanStore::open(&database_path).expect("could not open the ban database"));
+    let credential_store = Arc::new(
+        CredentialStore::open(&database_path).expect("could not open the credential database"),
+    );
+    let profile_store =
+        Arc::new(ProfileStore::open(&database_path).expect("could not open the profile database"));
+    let attachments_dir: PathBuf =
+        env_var(ATTACHMENTS_DIR_ENV).unwrap_or_else(|| PathBuf::from(DEFAULT_ATTACHMENTS_DIR));
+    let attachment_store = Arc::new(
+        AttachmentStore::open(&database_path, &attachments_dir)
+            .expect("could not open the attachment database"),
+    );
+    let space_manager = Arc::new(SpaceManager::new(chat_space_metadatas));
     let room_manager = Arc::new(
-        chat_room_metadatas
-            .into_iter()
-            .fold(RoomManagerBuilder::new(), |builder, metadata| {
-                builder.create_room(metadata)
-            })
+        config
+            .rooms
+            .iter()
+            .cloned()
+            .fold(
+                RoomManagerBuilder::new()
+                    .duplicate_suppression_window(limits.duplicate_suppression_window)
+                    .max_message_chars(limits.max_message_chars)
+                    .message_filter(Arc::new(
+                        WordListFilter::new(&config.content_filters)
+                            .expect("could not compile the content filters"),
+                    ))
+                    .message_store(Arc::clone(&message_store))
+                    .ban_store(ban_store)
+                    .attachment_store(attachment_store),
+                |builder, metadata| builder.create_room(metadata),
+            )
             .build(),
     );
+    room_manager.spawn_retention_sweep();
+
+    let mut session_context = SessionContext {
+        room_manager,
+        space_manager,
+        direct_message_router: Arc::new(DirectMessageRouter::new()),
+        access_log: Arc::new(AccessLog::new()),
+        credential_store,
+        profile_store,
+        protocol_metrics: Arc::new(ProtocolMetrics::new()),
+        server_metrics,
+        session_registry: Arc::new(SessionRegistry::new()),
+        presence_tracker,
+        tarpit: Arc::clone(&tarpit),
+        spam_guard: Arc::new(SpamGuard::new(limits.spam_policy)),
+        rate_limit_policy: rate_limit_rx,
+        heartbeat_policy: limits.heartbeat_policy,
+        min_protocol_version: limits.min_protocol_version,
+        account_deletion_grace_period,
+        server_events,
+        outbound_queue_capacity: limits.outbound_queue_capacity,
+    };
+
+    // the deletions are kept in the database, those left pending by a restart are not lost
+    match session::resume_account_deletions(&session_context) {
+        Ok(0) => (),
+        Ok(deletions) => info!(deletions, "resumed the pending account deletions"),
+        Err(err) => error!("could not resume the pending account deletions: {}", err),
+    }
 
     let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
-    let server = TcpListener::bind(format!("0.0.0.0:{}", PORT))
+    let server = TcpListener::bind(format!("0.0.0.0:{}", config.port))
         .await
         .expect("could not bind to the port");
+    let websocket_server = TcpListener::bind(format!("0.0.0.0:{}", config.websocket_port))
+        .await
+        .expect("could not bind to the WebSocket port");
+    // a port given as 0 is picked by the system, the bound one is logged
+    let port = server.local_addr().expect("could not read the port").port();
+    let websocket_port = websocket_server
+        .local_addr()
+        .expect("could not read the WebSocket port")
+        .port();
+    let tls_listener = match (cli::flag(TLS_CERT_FLAG), cli::flag(TLS_KEY_FLAG)) {
+        (Some(certificate_path), Some(private_key_path)) => {
+            let acceptor =
+                tls::load_acceptor(Path::new(&certificate_path), Path::new(&private_key_path))
+                    .expect("could not load the TLS certificate");
+            let listener = TcpListener::bind(format!("0.0.0.0:{}", config.tls_port))
+                .await
+                .expect("could not bind to the TLS port");
+
+            info!("Listening for TLS on port {}", config.tls_port);
+            Some((listener, acceptor))
+        }
+        (None, None) => None,
+        _ => panic!(
+            "{} and {} must be given together",
+            TLS_CERT_FLAG, TLS_KEY_FLAG
+        ),
+    };
+    let webhook_listener = match env_var::<PathBuf>(WEBHOOKS_PATH_ENV) {
+        Some(webhooks_path) => {
+            let webhooks = Webhooks::load(&webhooks_path, &session_context.room_manager)
+                .await
+                .expect("could not load the webhooks");
+            let listener = TcpListener::bind(format!("0.0.0.0:{}", config.webhook_port))
+                .await
+                .expect("could not bind to the webhook port");
+
+            info!(
+                "Listening for {} webhooks on port {}",
+                webhooks.count(),
+                config.webhook_port
+            );
+            Some((listener, Arc::new(webhooks)))
+        }
+        None => None,
+    };
+    let metrics_listener = match env_var::<u16>(METRICS_PORT_ENV).or(config.metrics_port) {
+        Some(metrics_port) => {
+            let endpoint = MetricsEndpoint::new(
+                Arc::clone(&session_context.server_metrics),
+                Arc::clone(&session_context.protocol_metrics),
+                Arc::clone(&session_context.room_manager),
+            );
+            let listener = TcpListener::bind(format!("0.0.0.0:{}", metrics_port))
+                .await
+                .expect("could not bind to the metrics port");
+
+            info!("Serving the metrics on port {}", metrics_port);
+            Some((listener, Arc::new(endpoint)))
+        }
+        None => None,
+    };
+    let admin_socket_path =
+        env_var::<PathBuf>(ADMIN_SOCKET_PATH_ENV).or(config.admin_socket_path.clone());
+    let admin_listener = match &admin_socket_path {
+        Some(admin_socket_path) => {
+            // the socket of a previous run is left behind when the server did not shut down gracefully
+            if admin_socket_path.exists() {
+                std::fs::remove_file(admin_socket_path)
+                    .expect("could not remove the previous admin socket");
+            }
+            let listener =
+                bind_admin_socket(admin_socket_path).expect("could not bind to the admin socket");
+
+            info!(
+                "Serving the admin console at {}",
+                admin_socket_path.display()
+            );
+            Some((
+                listener,
+                Arc::new(AdminConsole::new(session_context.clone())),
+            ))
+        }
+        None => None,
+    };
     let (quit_tx, quit_rx) = broadcast::channel::<()>(1);
+    if let Some(outgoing_webhooks_path) = env_var::<PathBuf>(OUTGOING_WEBHOOKS_PATH_ENV) {
+        let outgoing_webhooks =
+            OutgoingWebhook::load_all(&outgoing_webhooks_path, &session_context.room_manager)
+                .await
+                .expect("could not load the outgoing webhooks");
+
+        info!("Posting to {} outgoing webhooks", outgoing_webhooks.len());
+        for outgoing_webhook in outgoing_webhooks {
+            join_set.spawn(outgoing_webhook.run(quit_rx.resubscribe()));
+        }
+    }
+    let matrix_bridge_listener = match env_var::<PathBuf>(MATRIX_BRIDGE_PATH_ENV) {
+        Some(matrix_bridge_path) => {
+            let (appservice, relays) = matrix_bridge::load(
+                &matrix_bridge_path,
+                Arc::clone(&session_context.room_manager),
+            )
+            .await
+            .expect("could not load the Matrix bridge");
+            for relay in relays {
+                join_set.spawn(relay.run(quit_rx.resubscribe()));
+            }
+            let listener = TcpListener::bind(format!("0.0.0.0:{}", config.matrix_bridge_port))
+                .await
+                .expect("could not bind to the Matrix bridge port");
+
+            info!(
+                "Bridging {} rooms to Matrix, listening for the homeserver on port {}",
+                appservice.count(),
+                config.matrix_bridge_port
+            );
+            Some((listener, Arc::new(appservice)))
+        }
+        None => None,
+    };
 
-    println!("Listening on port {}", PORT);
+    // started again with the announcements of the configuration whenever they change
+    let mut announcements_task = join_set.spawn(announcements::run_scheduled_announcements(
+        config.announcements.clone(),
+        session_context.server_events.clone(),
+        quit_rx.resubscribe(),
+    ));
+
+    let mut hangup = signal(SignalKind::hangup()).expect("could not listen for SIGHUP");
+    let mut terminate = signal(SignalKind::terminate()).expect("could not listen for SIGTERM");
+
+    info!("Listening on port {}", port);
+    info!("Listening for WebSockets on port {}", websocket_port);
     loop {
         tokio::select! {
             Ok(_) = ctrl_c() => {
-                println!("Server interrupted. Gracefully shutting down.");
-                quit_tx.send(()).context("failed to send quit signal").unwrap();
+                info!("Server interrupted. Gracefully shutting down.");
+                break;
+            }
+            Some(_) = terminate.recv() => {
+                info!("Server terminated. Gracefully shutting down.");
                 break;
             }
-            Ok((socket, _)) = server.accept() => {
-                join_set.spawn(session::handle_user_session(Arc::clone(&room_manager), quit_rx.resubscribe(), socket));
+            Some(_) = hangup.recv() => {
+                match reload_config(&mut session_context, &rate_limit_tx).await {
+                    Ok(reloaded_config) => {
+                        if reloaded_config.announcements != config.announcements {
+                            announcements_task.abort();
+                            announcements_task = join_set.spawn(announcements::run_scheduled_announcements(
+                                reloaded_config.announcements.clone(),
+                                session_context.server_events.clone(),
+                                quit_rx.resubscribe(),
+                            ));
+                        }
+                        config = reloaded_config;
+                    }
+                    Err(err) => error!("could not reload the configuration: {:#}", err),
+                }
+            }
+            Ok((socket, addr)) = server.accept() => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                join_set.spawn(session::handle_user_session(
+                    session_context.clone(),
+                    quit_rx.resubscribe(),
+                    addr.ip(),
+                    socket,
+                ));
+            }
+            Ok((socket, addr)) = websocket_server.accept() => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                let session_context = session_context.clone();
+                let quit_rx = quit_rx.resubscribe();
+
+                // the handshake runs in the task, so a slow client does not hold up the other connections
+                join_set.spawn(async move {
+                    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, tokio_tungstenite::accept_async(socket))
+                        .await
+                        .context("the WebSocket handshake timed out")?
+                        .context("the WebSocket handshake failed")?;
+
+                    session::handle_user_session(session_context, quit_rx, addr.ip(), stream).await
+                });
+            }
+            Ok((socket, addr, tls_acceptor)) = accept_tls(&tls_listener) => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                let session_context = session_context.clone();
+                let quit_rx = quit_rx.resubscribe();
+
+                // the handshake runs in the task, so a slow client does not hold up the other connections
+                join_set.spawn(async move {
+                    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, tls_acceptor.accept(socket))
+                        .await
+                        .context("the TLS handshake timed out")?
+                        .context("the TLS handshake failed")?;
+
+                    session::handle_user_session(session_context, quit_rx, addr.ip(), stream).await
+                });
+            }
+            Ok((socket, addr, webhooks)) = accept_webhook(&webhook_listener) => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                join_set.spawn(webhooks.serve_connection(socket, quit_rx.resubscribe()));
+            }
+            Ok((socket, addr, endpoint)) = accept_metrics(&metrics_listener) => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                join_set.spawn(endpoint.serve_connection(socket, quit_rx.resubscribe()));
+            }
+            Ok((socket, addr, appservice)) = accept_matrix_bridge(&matrix_bridge_listener) => {
+                if tarpit.refuses(addr.ip()) {
+                    continue;
+                }
+
+                join_set.spawn(appservice.serve_connection(socket, quit_rx.resubscribe()));
             }
+            Ok((socket, console)) = accept_admin(&admin_listener) => {
+                join_set.spawn(console.serve_connection(socket, quit_rx.resubscribe()));
+            }
+            // the finished tasks are reaped as they go, rather than piling up until the shutdown
+            Some(result) = join_set.join_next() => log_task_result(result),
         }
     }
 
-    while join_set.join_next().await.is_some() {}
-    println!("Server shut down");
+    // no connection is accepted anymore, while the sessions are given some time to wrap up
+    drop((
+        server,
+        websocket_server,
+        tls_listener,
+        webhook_listener,
+        metrics_listener,
+        matrix_bridge_listener,
+        admin_listener,
+    ));
+    if let Some(admin_socket_path) = admin_socket_path {
+        let _ = std::fs::remove_file(admin_socket_path);
+    }
+    let grace_period =
+        env_var(SHUTDOWN_GRACE_PERIOD_ENV).unwrap_or(config.shutdown_grace_period_secs);
+    let _ = session_context
+        .server_events
+        .send(event::Event::ServerShuttingDown(
+            event::ServerShuttingDownEvent {
+                reason: config.shutdown_reason.clone(),
+                grace_seconds: grace_period,
+            },
+        ));
+    info!("Closing the connections in {}s", grace_period);
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs(grace_period)) => {}
+        Ok(_) = ctrl_c() => info!("Server interrupted again. Closing the connections now."),
+    }
+    quit_tx
+        .send(())
+        .context("failed to send quit signal")
+        .unwrap();
+
+    while let Some(result) = join_set.join_next().await {
+        log_task_result(result);
+    }
+    if let Err(err) = message_store.checkpoint() {
+        error!("{:#}", err);
+    }
+    info!("Server shut down");
 }
@@ -0,0 +1,549 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use comms::event;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{
+    body::{Bytes, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::{net::TcpStream, sync::broadcast};
+use tracing::warn;
+
+use crate::room_manager::{RoomManager, SessionAndUserId, UserSessionHandle};
+
+use super::MatrixBridgeConfig;
+
+/// The path the homeserver pushes the transactions of events to, followed by the transaction id
+const TRANSACTIONS_PATH: &str = "/_matrix/app/v1/transactions/";
+/// The path of the transactions before the API was versioned, still used by some homeservers
+const LEGACY_TRANSACTIONS_PATH: &str = "/transactions/";
+const PING_PATH: &str = "/_matrix/app/v1/ping";
+/// The transactions are batches of events, much larger than the webhook payloads
+const MAX_TRANSACTION_BYTES: usize = 4 * 1024 * 1024;
+/// How many transaction ids are remembered, so a transaction pushed again is not posted twice
+const SEEN_TRANSACTIONS: usize = 1024;
+/// How long the homeserver has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A batch of events the homeserver pushes to the application service
+#[derive(Debug, Deserialize)]
+struct Transaction {
+    #[serde(default)]
+    events: Vec<MatrixEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MatrixEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    room_id: Option<String>,
+    sender: String,
+    state_key: Option<String>,
+    #[serde(default)]
+    content: serde_json::Value,
+}
+
+/// A Matrix user in a room of the server, which stays in the room until it leaves the Matrix room
+struct MatrixUser {
+    handle: UserSessionHandle,
+    /// Kept so the room can be posted to while none of its users is connected
+    _broadcast_rx: broadcast::Receiver<event::Event>,
+}
+
+/// [AppService] serves the application service API the homeserver pushes the events of the Matrix rooms to
+///
+/// The messages, the joins and the topics of the bridged Matrix rooms are posted to their rooms, the Matrix
+/// users being in the rooms as their Matrix user ids, such as `@alice:example.org`.
+pub struct AppService {
+    config: Arc<MatrixBridgeConfig>,
+    room_manager: Arc<RoomManager>,
+    /// The room bridged to each Matrix room
+    rooms: HashMap<String, String>,
+    /// The Matrix users in the rooms, by room and Matrix user id
+    matrix_users: tokio::sync::Mutex<HashMap<(String, String), MatrixUser>>,
+    /// The ids of the last transactions, in the order they were pushed
+    seen_transactions: Mutex<(HashSet<String>, VecDeque<String>)>,
+}
+
+impl AppService {
+    pub fn new(config: Arc<MatrixBridgeConfig>, room_manager: Arc<RoomManager>) -> Self {
+        let rooms = config
+            .rooms
+            .iter()
+            .map(|bridged_room| {
+                (
+                    bridged_room.matrix_room_id.clone(),
+                    bridged_room.room.clone(),
+                )
+            })
+            .collect();
+
+        AppService {
+            config,
+            room_manager,
+            rooms,
+            matrix_users: tokio::sync::Mutex::new(HashMap::new()),
+            seen_transactions: Mutex::new((HashSet::new(), VecDeque::new())),
+        }
+    }
+
+    /// How many rooms are bridged
+    pub fn count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    /// Serves the requests of an HTTP connection, one at a time, until the homeserver or the server closes it
+    pub async fn serve_connection(
+        self: Arc<Self>,
+        socket: TcpStream,
+        mut quit_rx: broadcast::Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let service = service_fn(move |request| {
+            let appservice = Arc::clone(&self);
+
+            async move { Ok::<_, Infallible>(appservice.handle_request(request).await) }
+        });
+        let connection = http1::Builder::new()
+            .keep_alive(false)
+            .serve_connection(TokioIo::new(socket), service);
+
+        tokio::select! {
+            result = tokio::time::timeout(REQUEST_TIMEOUT, connection) => result
+                .context("the homeserver request timed out")?
+                .context("could not serve the homeserver request"),
+            _ = quit_rx.recv() => Ok(()),
+        }
+    }
+
+    async fn handle_request(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        if !self.is_authorized(&request) {
+            return error_response(StatusCode::FORBIDDEN, "M_FORBIDDEN", "bad hs_token");
+        }
+
+        let path = request.uri().path().to_string();
+        let transaction_id = path
+            .strip_prefix(TRANSACTIONS_PATH)
+            .or_else(|| path.strip_prefix(LEGACY_TRANSACTIONS_PATH));
+
+        match (request.method(), transaction_id) {
+            (&Method::PUT, Some(transaction_id)) => {
+                let transaction_id = transaction_id.to_string();
+
+                match self.push_transaction(&transaction_id, request).await {
+                    Ok(()) => json_response(StatusCode::OK, json!({})),
+                    Err(err) => {
+                        error_response(StatusCode::BAD_REQUEST, "M_BAD_JSON", &format!("{:#}", err))
+                    }
+                }
+            }
+            (&Method::POST, None) if path == PING_PATH => json_response(StatusCode::OK, json!({})),
+            // the users and the room aliases of the bridge are not created on demand
+            _ => error_response(
+                StatusCode::NOT_FOUND,
+                "M_NOT_FOUND",
+                "nothing is served here",
+            ),
+        }
+    }
+
+    /// Whether the request carries the token of the homeserver, as a bearer token or in the legacy query parameter
+    fn is_authorized(&self, request: &Request<Incoming>) -> bool {
+        let bearer_token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|authorization| authorization.to_str().ok())
+            .and_then(|authorization| authorization.strip_prefix("Bearer "));
+        let query_token = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("access_token="))
+        });
+
+        // compares in constant time, so the token can not be guessed byte by byte
+        bearer_token.or(query_token).is_some_and(|token| {
+            token
+                .as_bytes()
+                .ct_eq(self.config.hs_token.as_bytes())
+                .into()
+        })
+    }
+
+    async fn push_transaction(
+        &self,
+        transaction_id: &str,
+        request: Request<Incoming>,
+    ) -> anyhow::Result<()> {
+        let body = Limited::new(request.into_body(), MAX_TRANSACTION_BYTES)
+            .collect()
+            .await
+            .map_err(|err| anyhow::anyhow!("could not read the transaction: {}", err))?
+            .to_bytes();
+        let transaction: Transaction =
+            serde_json::from_slice(&body).context("could not parse the transaction")?;
+
+        if !self.remember_transaction(transaction_id) {
+            return Ok(());
+        }
+
+        for matrix_event in transaction.events {
+            // an event which can not be posted is skipped, so the homeserver does not push it again and again
+            if let Err(err) = self.post_event(&matrix_event).await {
+                warn!(
+                    kind = %matrix_event.kind,
+                    sender = %matrix_event.sender,
+                    "could not post an event from Matrix: {:#}",
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remembers the transaction, false if it was already pushed
+    fn remember_transaction(&self, transaction_id: &str) -> bool {
+        let mut seen_transactions = self.seen_transactions.lock().unwrap();
+        let (ids, order) = &mut *seen_transactions;
+
+        if !ids.insert(transaction_id.to_string()) {
+            return false;
+        }
+        order.push_back(transaction_id.to_string());
+        if order.len() > SEEN_TRANSACTIONS {
+            if let Some(oldest) = order.pop_front() {
+                ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    async fn post_event(&self, matrix_event: &MatrixEvent) -> anyhow::Result<()> {
+        let Some(room) = matrix_event
+            .room_id
+            .as_ref()
+            .and_then(|room_id| self.rooms.get(room_id))
+        else {
+            return Ok(());
+        };
+        // the events of the bridge come from the server in the first place
+        if self.config.is_bridge_user(&matrix_event.sender) {
+            return Ok(());
+        }
+
+        let content = &matrix_event.content;
+        match matrix_event.kind.as_str() {
+            "m.room.message" => {
+                let Some(body) = content["body"].as_str() else {
+                    return Ok(());
+                };
+                let text = match content["msgtype"].as_str() {
+                    Some("m.emote") => format!("* {} {}", matrix_event.sender, body),
+                    _ => body.to_string(),
+                };
+
+                let mut matrix_users = self.matrix_users.lock().await;
+                self.join_matrix_user(&mut matrix_users, room, &matrix_event.sender)
+                    .await?
+                    .handle
+                    .send_message(text, None)
+                    .await
+            }
+            "m.room.member" => {
+                let user_id = matrix_event
+                    .state_key
+                    .as_deref()
+                    .unwrap_or(&matrix_event.sender);
+                if self.config.is_bridge_user(user_id) {
+                    return Ok(());
+                }
+
+                let mut matrix_users = self.matrix_users.lock().await;
+                match content["membership"].as_str() {
+                    Some("join") => self
+                        .join_matrix_user(&mut matrix_users, room, user_id)
+                        .await
+                        .map(|_| ()),
+                    Some("leave" | "ban") => {
+                        match matrix_users.remove(&(room.clone(), user_id.to_string())) {
+                            Some(matrix_user) => {
+                                self.room_manager
+                                    .drop_user_session_handle(matrix_user.handle)
+                                    .await
+                            }
+                            None => Ok(()),
+                        }
+                    }
+                    _ => Ok(()),
+                }
+            }
+            "m.room.topic" => {
+                let topic = content["topic"].as_str().unwrap_or_default();
+
+                self.room_manager
+                    .set_bridged_room_topic(room, topic, &matrix_event.sender)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Joins the room as the Matrix user, unless it is in the room already
+    async fn join_matrix_user<'a>(
+        &self,
+        matrix_users: &'a mut HashMap<(String, String), MatrixUser>,
+        room: &str,
+        matrix_user_id: &str,
+    ) -> anyhow::Result<&'a MatrixUser> {
+        let key = (room.to_string(), matrix_user_id.to_string());
+
+        if !matrix_users.contains_key(&key) {
+            let (broadcast_rx, handle, _, _) = self
+                .room_manager
+                .join_room(
+                    room,
+                    &SessionAndUserId {
+                        session_id: format!("matrix-{}", matrix_user_id),
+                        user_id: matrix_user_id.to_string(),
+                    },
+                )
+                .await
+                .with_context(|| format!("{} could not join room '{}'", matrix_user_id, room))?;
+
+            matrix_users.insert(
+                key.clone(),
+                MatrixUser {
+                    handle,
+                    _broadcast_rx: broadcast_rx,
+                },
+            );
+        }
+
+        Ok(&matrix_users[&key])
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+
+    response
+}
+
+/// Answers with a Matrix error, as in `{"errcode": "M_FORBIDDEN", "error": "bad hs_token"}`
+fn error_response(status: StatusCode, errcode: &str, error: &str) -> Response<Full<Bytes>> {
+    json_response(status, json!({ "errcode": errcode, "error": error }))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::room_manager::RoomManagerBuilder;
+
+    const HS_TOKEN: &str = "hs_secret";
+    const MATRIX_ROOM_ID: &str = "!general:example.org";
+
+    /// Serves the application service on a port picked by the system,
+    /// returns its address along with the events of the bridged room
+    async fn serve() -> (String, broadcast::Receiver<event::Event>) {
+        let room_manager = Arc::new(
+            RoomManagerBuilder::new()
+                .create_room(
+                    serde_json::from_value(json!({ "name": "general", "description": "General" }))
+                        .unwrap(),
+                )
+                .build(),
+        );
+        let events = room_manager.subscribe_room("general").await.unwrap();
+        let config: MatrixBridgeConfig = serde_json::from_value(json!({
+            "homeserver_url": "http://127.0.0.1:1",
+            "server_name": "example.org",
+            "as_token": "as_secret",
+            "hs_token": HS_TOKEN,
+            "sender_localpart": "chat_bridge",
+            "rooms": [{ "room": "general", "matrix_room_id": MATRIX_ROOM_ID }],
+        }))
+        .unwrap();
+        let appservice = Arc::new(AppService::new(Arc::new(config), room_manager));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (_quit_tx, quit_rx) = broadcast::channel(1);
+
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(
+                    Arc::clone(&appservice).serve_connection(socket, quit_rx.resubscribe()),
+                );
+            }
+        });
+
+        (addr, events)
+    }
+
+    async fn push(
+        addr: &str,
+        transaction_id: &str,
+        events: serde_json::Value,
+    ) -> reqwest::StatusCode {
+        reqwest::Client::new()
+            .put(format!("{}{}{}", addr, TRANSACTIONS_PATH, transaction_id))
+            .bearer_auth(HS_TOKEN)
+            .body(json!({ "events": events }).to_string())
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    fn message(sender: &str, msgtype: &str, body: &str) -> serde_json::Value {
+        json!({
+            "type": "m.room.message",
+            "room_id": MATRIX_ROOM_ID,
+            "sender": sender,
+            "content": { "msgtype": msgtype, "body": body },
+        })
+    }
+
+    /// The messages posted to the room so far, as their authors and contents
+    fn posted_messages(events: &mut broadcast::Receiver<event::Event>) -> Vec<(String, String)> {
+        let mut messages = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let event::Event::UserMessage(message) = event {
+                messages.push((message.user_id, message.content));
+            }
+        }
+
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_refuses_the_requests_without_the_hs_token() {
+        let (addr, _) = serve().await;
+        let client = reqwest::Client::new();
+        let ping_url = format!("{}{}", addr, PING_PATH);
+
+        for request in [
+            client.post(&ping_url),
+            client.post(&ping_url).bearer_auth("hs_secreT"),
+            client.post(&ping_url).bearer_auth("hs_secret_and_more"),
+            client.post(format!("{}?access_token=hs_secre", ping_url)),
+        ] {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        }
+
+        for request in [
+            client.post(&ping_url).bearer_auth(HS_TOKEN),
+            client.post(format!("{}?access_token={}", ping_url, HS_TOKEN)),
+        ] {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_posts_a_transaction_pushed_again_once() {
+        let (addr, mut events) = serve().await;
+
+        let hello = json!([message("@bob:example.org", "m.text", "hello")]);
+        // the homeserver pushes the transaction again when it missed the answer
+        for _ in 0..2 {
+            assert_eq!(
+                push(&addr, "1", hello.clone()).await,
+                reqwest::StatusCode::OK
+            );
+        }
+        let again = json!([message("@bob:example.org", "m.text", "hello again")]);
+        assert_eq!(push(&addr, "2", again).await, reqwest::StatusCode::OK);
+
+        assert_eq!(
+            posted_messages(&mut events),
+            vec![
+                (String::from("@bob:example.org"), String::from("hello")),
+                (
+                    String::from("@bob:example.org"),
+                    String::from("hello again")
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_translates_the_matrix_events() {
+        let (addr, mut events) = serve().await;
+
+        let transaction = json!([
+            message("@bob:example.org", "m.text", "hello"),
+            message("@bob:example.org", "m.emote", "waves"),
+            // the messages of the bridge itself come from the server in the first place
+            message("@chat_alice:example.org", "m.text", "echo"),
+            message("@chat_bridge:example.org", "m.notice", "echo"),
+            // the rooms which are not bridged are left alone
+            {
+                "type": "m.room.message",
+                "room_id": "!elsewhere:example.org",
+                "sender": "@bob:example.org",
+                "content": { "msgtype": "m.text", "body": "elsewhere" },
+            },
+            {
+                "type": "m.room.topic",
+                "room_id": MATRIX_ROOM_ID,
+                "sender": "@bob:example.org",
+                "state_key": "",
+                "content": { "topic": "Bridged from Matrix" },
+            },
+        ]);
+        assert_eq!(push(&addr, "1", transaction).await, reqwest::StatusCode::OK);
+
+        let mut topics = vec![];
+        let mut messages = vec![];
+        while let Ok(event) = events.try_recv() {
+            match event {
+                event::Event::UserMessage(message) => {
+                    messages.push((message.user_id, message.content))
+                }
+                event::Event::RoomTopicChanged(changed) => {
+                    topics.push((changed.changed_by, changed.topic))
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            messages,
+            vec![
+                (String::from("@bob:example.org"), String::from("hello")),
+                (
+                    String::from("@bob:example.org"),
+                    String::from("* @bob:example.org waves")
+                ),
+            ]
+        );
+        assert_eq!(
+            topics,
+            vec![(
+                String::from("@bob:example.org"),
+                String::from("Bridged from Matrix")
+            )]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::{fmt, time::Duration};
+
+use anyhow::Context;
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::outgoing_webhooks::{retry_delay, MAX_DELIVERY_ATTEMPTS};
+
+use super::MatrixBridgeConfig;
+
+/// How long the homeserver has to answer a call
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The error the homeserver answered a call with, as in `M_FORBIDDEN`
+#[derive(Debug, Deserialize)]
+pub struct MatrixError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    #[serde(default)]
+    pub errcode: String,
+    #[serde(default)]
+    pub error: String,
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the homeserver answered {} {}: {}",
+            self.status, self.errcode, self.error
+        )
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+/// [Homeserver] calls the client-server API of the homeserver as the application service,
+/// acting as the users of the bridge with the `user_id` query parameter
+pub struct Homeserver {
+    client: reqwest::Client,
+    base_url: Url,
+    as_token: String,
+}
+
+impl Homeserver {
+    pub fn new(config: &MatrixBridgeConfig) -> anyhow::Result<Self> {
+        let base_url = Url::parse(&config.homeserver_url)
+            .with_context(|| format!("'{}' is not a valid URL", config.homeserver_url))?;
+        if base_url.cannot_be_a_base() {
+            return Err(anyhow::anyhow!(
+                "'{}' is not a valid homeserver URL",
+                config.homeserver_url
+            ));
+        }
+        let client = reqwest::Client::builder()
+            .timeout(CALL_TIMEOUT)
+            .build()
+            .context("could not create the HTTP client")?;
+
+        Ok(Homeserver {
+            client,
+            base_url,
+            as_token: config.as_token.clone(),
+        })
+    }
+
+    /// Registers the user of the bridge, succeeding if it is already registered
+    pub async fn register(&self, localpart: &str) -> anyhow::Result<()> {
+        let result = self
+            .call(
+                Method::POST,
+                &["register"],
+                None,
+                json!({ "type": "m.login.application_service", "username": localpart }),
+            )
+            .await;
+
+        match result {
+            Err(err)
+                if err
+                    .downcast_ref::<MatrixError>()
+                    .is_some_and(|err| err.errcode == "M_USER_IN_USE") =>
+            {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    pub async fn join(&self, room_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.call(
+            Method::POST,
+            &["rooms", room_id, "join"],
+            Some(user_id),
+            json!({}),
+        )
+        .await
+    }
+
+    /// Invites the user to the room as the bridge itself
+    pub async fn invite(&self, room_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.call(
+            Method::POST,
+            &["rooms", room_id, "invite"],
+            None,
+            json!({ "user_id": user_id }),
+        )
+        .await
+    }
+
+    pub async fn leave(&self, room_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.call(
+            Method::POST,
+            &["rooms", room_id, "leave"],
+            Some(user_id),
+            json!({}),
+        )
+        .await
+    }
+
+    /// Sends a text message as the user, the homeserver ignoring a transaction id it has already seen
+    pub async fn send_text(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        transaction_id: &str,
+        text: &str,
+    ) -> anyhow::Result<()> {
+        self.call(
+            Method::PUT,
+            &["rooms", room_id, "send", "m.room.message", transaction_id],
+            Some(user_id),
+            json!({ "msgtype": "m.text", "body": text }),
+        )
+        .await
+    }
+
+    /// Sets the topic of the room as the bridge itself
+    pub async fn set_topic(&self, room_id: &str, topic: &str) -> anyhow::Result<()> {
+        self.call(
+            Method::PUT,
+            &["rooms", room_id, "state", "m.room.topic", ""],
+            None,
+            json!({ "topic": topic }),
+        )
+        .await
+    }
+
+    /// Calls the client-server API, retrying with an exponential backoff while the homeserver is unreachable
+    /// or overloaded
+    async fn call(
+        &self,
+        method: Method,
+        path: &[&str],
+        user_id: Option<&str>,
+        body: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the homeserver URL is a base")
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(path);
+        if let Some(user_id) = user_id {
+            url.query_pairs_mut().append_pair("user_id", user_id);
+        }
+        let body = body.to_string();
+        let mut attempt = 1;
+
+        loop {
+            let result = self
+                .client
+                .request(method.clone(), url.clone())
+                .bearer_auth(&self.as_token)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+
+            let retry_reason = match result {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    anyhow::anyhow!("the homeserver answered {}", response.status())
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.bytes().await.unwrap_or_default();
+                    let mut error =
+                        serde_json::from_slice::<MatrixError>(&body).unwrap_or(MatrixError {
+                            status,
+                            errcode: String::new(),
+                            error: String::new(),
+                        });
+                    error.status = status;
+
+                    return Err(error.into());
+                }
+                Err(err) => err.into(),
+            };
+
+            if attempt == MAX_DELIVERY_ATTEMPTS {
+                return Err(retry_reason.context(format!("could not call {}", url.path())));
+            }
+            info!(
+                path = url.path(),
+                attempt, "could not call the homeserver, retrying: {:#}", retry_reason
+            );
+            tokio::time::sleep(retry_delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::room_manager::RoomManager;
+
+use self::homeserver::Homeserver;
+pub use self::{appservice::AppService, relay::Relay};
+
+mod appservice;
+mod homeserver;
+mod relay;
+
+fn default_user_prefix() -> String {
+    String::from("chat_")
+}
+
+/// A room of the server and the Matrix room it is bridged to
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgedRoom {
+    pub room: String,
+    /// The id of the Matrix room, as in `!abcdef:example.org`
+    pub matrix_room_id: String,
+}
+
+/// [MatrixBridgeConfig] defines the Matrix application service bridging the rooms, read from its JSON file
+///
+/// The tokens and the localparts match the registration of the application service with the homeserver.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatrixBridgeConfig {
+    /// Where the client-server API of the homeserver is served, as in `https://matrix.example.org`
+    pub homeserver_url: String,
+    /// The domain of the user ids of the homeserver, as in `example.org`
+    pub server_name: String,
+    /// The token the application service calls the homeserver with
+    pub as_token: String,
+    /// The token the homeserver calls the application service with
+    pub hs_token: String,
+    /// The localpart of the Matrix user of the bridge itself
+    pub sender_localpart: String,
+    /// The prefix of the localparts of the Matrix users standing for the users of the server
+    #[serde(default = "default_user_prefix")]
+    pub user_prefix: String,
+    pub rooms: Vec<BridgedRoom>,
+}
+
+impl MatrixBridgeConfig {
+    /// The Matrix user of the bridge, which sets the topics and invites the other users of the bridge
+    fn bot_id(&self) -> String {
+        format!("@{}:{}", self.sender_localpart, self.server_name)
+    }
+
+    /// The localpart of the Matrix user standing for a user of the server
+    fn puppet_localpart(&self, user_id: &str) -> String {
+        // localparts are lowercase, guest ids may not be
+        let user_id = user_id.to_lowercase().replace(
+            |c: char| !(c.is_ascii_alphanumeric() || "._=-/".contains(c)),
+            "_",
+        );
+
+        format!("{}{}", self.user_prefix, user_id)
+    }
+
+    fn puppet_id(&self, user_id: &str) -> String {
+        format!("@{}:{}", self.puppet_localpart(user_id), self.server_name)
+    }
+
+    /// Whether the Matrix user belongs to the bridge, whose events come from the server in the first place
+    fn is_bridge_user(&self, matrix_user_id: &str) -> bool {
+        let server_suffix = format!(":{}", self.server_name);
+
+        matrix_user_id == self.bot_id()
+            || (matrix_user_id.starts_with(&format!("@{}", self.user_prefix))
+                && matrix_user_id.ends_with(&server_suffix))
+    }
+}
+
+/// Whether the user of the server is a Matrix user posting through the bridge
+///
+/// They are known by their Matrix user id, as in `@alice:example.org`, which is never a valid username of the server.
+fn is_matrix_user(user_id: &str) -> bool {
+    user_id.starts_with('@')
+}
+
+/// Reads the bridge from its JSON file, returning the application service the homeserver calls
+/// and the relays posting the events of each bridged room to Matrix
+pub async fn load(
+    path: &Path,
+    room_manager: Arc<RoomManager>,
+) -> anyhow::Result<(AppService, Vec<Relay>)> {
+    let config: MatrixBridgeConfig = serde_json::from_str(
+        &std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?,
+    )
+    .with_context(|| format!("could not parse {}", path.display()))?;
+    let config = Arc::new(config);
+    let homeserver = Arc::new(Homeserver::new(&config)?);
+
+    let mut relays = vec![];
+    for bridged_room in config.rooms.iter() {
+        let events = room_manager
+            .subscribe_room(&bridged_room.room)
+            .await
+            .with_context(|| format!("could not subscribe to room '{}'", bridged_room.room))?;
+
+        relays.push(Relay::new(
+            bridged_room.clone(),
+            Arc::clone(&config),
+            Arc::clone(&homeserver),
+            events,
+        ));
+    }
+
+    Ok((AppService::new(config, room_manager), relays))
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashSet, sync::Arc};
+
+use comms::event::{Event, RoomParticipationStatus};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::clock::now_millis;
+
+use super::{homeserver::Homeserver, is_matrix_user, BridgedRoom, MatrixBridgeConfig};
+
+/// [Relay] posts the messages, the joins and the topics of a room to its Matrix room as they arrive
+///
+/// Each user of the room posts as their own Matrix user of the bridge, registered and joined to the
+/// Matrix room the first time they show up. The events of the Matrix users are not posted back.
+pub struct Relay {
+    bridged_room: BridgedRoom,
+    config: Arc<MatrixBridgeConfig>,
+    homeserver: Arc<Homeserver>,
+    events: broadcast::Receiver<Event>,
+    /// The Matrix users of the bridge which have joined the Matrix room
+    joined_puppets: HashSet<String>,
+    /// Sets the transaction ids apart from the ones of a previous run, the message ids restarting with the history
+    started_at: u64,
+}
+
+impl Relay {
+    pub fn new(
+        bridged_room: BridgedRoom,
+        config: Arc<MatrixBridgeConfig>,
+        homeserver: Arc<Homeserver>,
+        events: broadcast::Receiver<Event>,
+    ) -> Self {
+        Relay {
+            bridged_room,
+            config,
+            homeserver,
+            events,
+            joined_puppets: HashSet::new(),
+            started_at: now_millis(),
+        }
+    }
+
+    /// Relays the events of the room until the room is deleted or the server shuts down
+    pub async fn run(mut self, mut quit_rx: broadcast::Receiver<()>) -> anyhow::Result<()> {
+        tokio::select! {
+            result = self.relay_events() => result,
+            _ = quit_rx.recv() => Ok(()),
+        }
+    }
+
+    async fn relay_events(&mut self) -> anyhow::Result<()> {
+        // the bridge sets the topics and invites the users, which it can only do from the room
+        if let Err(err) = self
+            .homeserver
+            .join(&self.bridged_room.matrix_room_id, &self.config.bot_id())
+            .await
+        {
+            warn!(
+                matrix_room_id = %self.bridged_room.matrix_room_id,
+                "the Matrix bridge could not join the room: {:#}",
+                err
+            );
+        }
+
+        loop {
+            match self.events.recv().await {
+                Ok(event) => {
+                    if let Err(err) = self.relay(&event).await {
+                        warn!(
+                            room = %self.bridged_room.room,
+                            matrix_room_id = %self.bridged_room.matrix_room_id,
+                            "could not relay an event: {:#}",
+                            err
+                        );
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!(
+                    room = %self.bridged_room.room,
+                    matrix_room_id = %self.bridged_room.matrix_room_id,
+                    "skipped {} events while relaying",
+                    skipped
+                ),
+                Err(RecvError::Closed) => {
+                    return Err(anyhow::anyhow!(
+                        "room '{}' is closed",
+                        self.bridged_room.room
+                    ))
+                }
+            }
+        }
+    }
+
+    async fn relay(&mut self, event: &Event) -> anyhow::Result<()> {
+        let room_id = self.bridged_room.matrix_room_id.clone();
+
+        match event {
+            Event::UserMessage(message) if !is_matrix_user(&message.user_id) => {
+                let puppet_id = self.join_puppet(&message.user_id).await?;
+                let transaction_id = format!("chat-{}-{}", self.started_at, message.id);
+
+                self.homeserver
+                    .send_text(&room_id, &puppet_id, &transaction_id, &message.content)
+                    .await
+            }
+            Event::RoomParticipation(event) if !is_matrix_user(&event.user_id) => {
+                match event.status {
+                    RoomParticipationStatus::Joined => {
+                        self.join_puppet(&event.user_id).await.map(|_| ())
+                    }
+                    RoomParticipationStatus::Left => {
+                        let puppet_id = self.config.puppet_id(&event.user_id);
+
+                        if self.joined_puppets.remove(&puppet_id) {
+                            self.homeserver.leave(&room_id, &puppet_id).await
+                        } else {
+                            Ok(())
+                        }
+                    }
+                }
+            }
+            Event::RoomTopicChanged(event) if !is_matrix_user(&event.changed_by) => {
+                self.homeserver.set_topic(&room_id, &event.topic).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers the Matrix user of the user and joins it to the Matrix room, unless it has joined already
+    async fn join_puppet(&mut self, user_id: &str) -> anyhow::Result<String> {
+        let puppet_id = self.config.puppet_id(user_id);
+        if self.joined_puppets.contains(&puppet_id) {
+            return Ok(puppet_id);
+        }
+
+        let room_id = &self.bridged_room.matrix_room_id;
+        self.homeserver
+            .register(&self.config.puppet_localpart(user_id))
+            .await?;
+        if self.homeserver.join(room_id, &puppet_id).await.is_err() {
+            // the Matrix room may only be joined on invitation
+            self.homeserver.invite(room_id, &puppet_id).await?;
+            self.homeserver.join(room_id, &puppet_id).await?;
+        }
+
+        self.joined_puppets.insert(puppet_id.clone());
+        Ok(puppet_id)
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use tokio::{net::TcpStream, sync::broadcast};
+
+use crate::{room_manager::RoomManager, session::ProtocolMetrics};
+
+/// The path the metrics are scraped from
+const METRICS_PATH: &str = "/metrics";
+/// The content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// The upper bounds of the buckets of the latency histograms, in seconds
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+/// How long the scraper has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Prometheus histogram of durations, counting the observations at most as long as each bucket
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        for (bucket, upper_bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    /// Writes the samples of the histogram, the labels being written before the bucket of each sample
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let label_set = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+
+        for (bucket, upper_bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, upper_bound, bucket
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        );
+        let _ = writeln!(out, "{}_sum{} {}", name, label_set, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, label_set, self.count);
+    }
+}
+
+/// Escapes a label value, as the names of the rooms are chosen by the users
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// [ServerMetrics] records how long the server takes to serve the users, for operators to graph its health
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    /// How long the commands took to handle, by command name
+    command_latencies: Mutex<BTreeMap<&'static str, Histogram>>,
+    /// How long the messages took from being sent to a room to being written to each of its members
+    fan_out_delays: Mutex<Histogram>,
+    /// The events dropped on their way to the clients which could not keep up
+    dropped_events: AtomicU64,
+    /// The clients disconnected because their events piled up
+    slow_consumer_disconnects: AtomicU64,
+    /// The invalid commands whose connections were delayed by the tarpit
+    tarpit_delays: AtomicU64,
+    /// The ips banned by the tarpit
+    tarpit_bans: AtomicU64,
+    /// The connections refused because their ip was banned by the tarpit
+    tarpit_refusals: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        ServerMetrics::default()
+    }
+
+    pub fn observe_command(&self, command: &'static str, latency: Duration) {
+        self.command_latencies
+            .lock()
+            .unwrap()
+            .entry(command)
+            .or_default()
+            .observe(latency);
+    }
+
+    pub fn observe_fan_out(&self, delay: Duration) {
+        self.fan_out_delays.lock().unwrap().observe(delay);
+    }
+
+    pub fn record_dropped_events(&self, events: usize) {
+        self.dropped_events
+            .fetch_add(events as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_slow_consumer(&self) {
+        self.slow_consumer_disconnects
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tarpit_delay(&self) {
+        self.tarpit_delays.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tarpit_ban(&self) {
+        self.tarpit_bans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tarpit_refusal(&self) {
+        self.tarpit_refusals.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// [MetricsEndpoint] serves the metrics of the server over HTTP at `/metrics`, for Prometheus to scrape them
+///
+/// The active sessions and the messages of the rooms are read at each scrape, the latencies are recorded
+/// by the sessions as they go.
+pub struct MetricsEndpoint {
+    server_metrics: Arc<ServerMetrics>,
+    protocol_metrics: Arc<ProtocolMetrics>,
+    room_manager: Arc<RoomManager>,
+}
+
+impl MetricsEndpoint {
+    pub fn new(
+        server_metrics: Arc<ServerMetrics>,
+        protocol_metrics: Arc<ProtocolMetrics>,
+        room_manager: Arc<RoomManager>,
+    ) -> Self {
+        MetricsEndpoint {
+            server_metrics,
+            protocol_metrics,
+            room_manager,
+        }
+    }
+
+    /// Serves the requests of an HTTP connection, one at a time, until the scraper or the server closes it
+    pub async fn serve_connection(
+        self: Arc<Self>,
+        socket: TcpStream,
+        mut quit_rx: broadcast::Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let service = service_fn(move |request| {
+            let endpoint = Arc::clone(&self);
+
+            async move { Ok::<_, Infallible>(endpoint.handle_request(request).await) }
+        });
+        let connection = http1::Builder::new()
+            .keep_alive(false)
+            .serve_connection(TokioIo::new(socket), service);
+
+        tokio::select! {
+            result = tokio::time::timeout(REQUEST_TIMEOUT, connection) => result
+                .context("the metrics request timed out")?
+                .context("could not serve the metrics request"),
+            _ = quit_rx.recv() => Ok(()),
+        }
+    }
+
+    async fn handle_request(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        if request.uri().path() != METRICS_PATH {
+            return text_response(
+                StatusCode::NOT_FOUND,
+                String::from("metrics are served at /metrics"),
+            );
+        }
+        if request.method() != Method::GET {
+            return text_response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                String::from("metrics are fetched"),
+            );
+        }
+
+        let mut response = text_response(StatusCode::OK, self.render().await);
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(CONTENT_TYPE),
+        );
+
+        response
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    async fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "chat_sessions",
+            "gauge",
+            "Connected sessions by protocol version",
+        );
+        for (version, sessions) in self.protocol_metrics.active_sessions() {
+            let _ = writeln!(
+                out,
+                "chat_sessions{{protocol_version=\"{}\"}} {}",
+                version, sessions
+            );
+        }
+
+        write_header(
+            &mut out,
+            "chat_room_messages_total",
+            "counter",
+            "Messages sent to each room since the server started",
+        );
+        for (room, messages_sent) in self.room_manager.messages_sent().await {
+            let _ = writeln!(
+                out,
+                "chat_room_messages_total{{room=\"{}\"}} {}",
+                escape_label(&room),
+                messages_sent
+            );
+        }
+
+        write_header(
+            &mut out,
+            "chat_command_duration_seconds",
+            "histogram",
+            "How long the commands of the users took to handle",
+        );
+        for (command, histogram) in self.server_metrics.command_latencies.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "chat_command_duration_seconds",
+                &format!("command=\"{}\"", command),
+            );
+        }
+
+        write_header(
+            &mut out,
+            "chat_fan_out_duration_seconds",
+            "histogram",
+            "How long the messages took from being sent to a room to being written to each of its members",
+        );
+        self.server_metrics.fan_out_delays.lock().unwrap().render(
+            &mut out,
+            "chat_fan_out_duration_seconds",
+            "",
+        );
+
+        write_header(
+            &mut out,
+            "chat_outbound_events_dropped_total",
+            "counter",
+            "Events dropped on their way to the clients which could not keep up",
+        );
+        let _ = writeln!(
+            out,
+            "chat_outbound_events_dropped_total {}",
+            self.server_metrics.dropped_events.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut out,
+            "chat_slow_consumer_disconnects_total",
+            "counter",
+            "Clients disconnected because their events piled up",
+        );
+        let _ = writeln!(
+            out,
+            "chat_slow_consumer_disconnects_total {}",
+            self.server_metrics
+                .slow_consumer_disconnects
+                .load(Ordering::Relaxed)
+        );
+
+        for (name, help, counter) in [
+            (
+                "chat_tarpit_delays_total",
+                "Invalid commands whose connections were delayed by the tarpit",
+                &self.server_metrics.tarpit_delays,
+            ),
+            (
+                "chat_tarpit_bans_total",
+                "Ips banned by the tarpit after too many invalid commands",
+                &self.server_metrics.tarpit_bans,
+            ),
+            (
+                "chat_tarpit_refused_connections_total",
+                "Connections refused because their ip was banned by the tarpit",
+                &self.server_metrics.tarpit_refusals,
+            ),
+        ] {
+            write_header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+
+    response
+}
@@ -0,0 +1,204 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use comms::event::{Event, UserMessageBroadcastEvent};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+use crate::{room_manager::RoomManager, webhooks};
+
+/// How many times a message is posted before giving up on it
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+/// The delay before the first retry, doubled on each failed attempt up to the max delay
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+/// How long the receiver of the webhook has to answer a post
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// [OutgoingWebhookConfig] defines an outgoing webhook, read from the JSON file of the outgoing webhooks
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutgoingWebhookConfig {
+    /// The room whose messages are posted
+    pub room: String,
+    /// Where the messages are posted to
+    pub url: String,
+    /// The secret the payloads are signed with, the payloads are not signed without one
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// The JSON payload posted for a message, which the incoming webhooks of another server accept as is
+#[derive(Debug, Serialize)]
+struct MessagePayload<'a> {
+    room: &'a str,
+    id: u64,
+    user_id: &'a str,
+    text: &'a str,
+    timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<u64>,
+}
+
+/// Why a post has failed, and whether it is worth posting again
+enum DeliveryError {
+    /// A network failure, a timeout, or the receiver being unavailable or overloaded
+    Retryable(anyhow::Error),
+    /// The receiver has refused the payload
+    Permanent(anyhow::Error),
+}
+
+/// [OutgoingWebhook] posts the messages of a room to a URL as they arrive, such as to bridge the room to another system
+///
+/// The messages are posted one at a time and in order, each one being retried with an exponential backoff
+/// before giving up on it. The messages arriving while the receiver is down for long are skipped.
+pub struct OutgoingWebhook {
+    config: OutgoingWebhookConfig,
+    client: reqwest::Client,
+    events: broadcast::Receiver<Event>,
+}
+
+impl OutgoingWebhook {
+    /// Reads the outgoing webhooks from a JSON file, and subscribes each one to the messages of its room
+    pub async fn load_all(path: &Path, room_manager: &RoomManager) -> anyhow::Result<Vec<Self>> {
+        let configs: Vec<OutgoingWebhookConfig> = serde_json::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("could not read {}", path.display()))?,
+        )
+        .with_context(|| format!("could not parse {}", path.display()))?;
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .context("could not create the HTTP client")?;
+        let mut webhooks = vec![];
+
+        for config in configs {
+            reqwest::Url::parse(&config.url)
+                .with_context(|| format!("'{}' is not a valid URL", config.url))?;
+            let events = room_manager
+                .subscribe_room(&config.room)
+                .await
+                .with_context(|| format!("could not subscribe to room '{}'", config.room))?;
+
+            webhooks.push(OutgoingWebhook {
+                config,
+                client: client.clone(),
+                events,
+            });
+        }
+
+        Ok(webhooks)
+    }
+
+    /// Posts the messages of the room until the room is deleted or the server shuts down
+    pub async fn run(mut self, mut quit_rx: broadcast::Receiver<()>) -> anyhow::Result<()> {
+        tokio::select! {
+            result = self.forward_messages() => result,
+            _ = quit_rx.recv() => Ok(()),
+        }
+    }
+
+    async fn forward_messages(&mut self) -> anyhow::Result<()> {
+        loop {
+            match self.events.recv().await {
+                Ok(Event::UserMessage(message)) => {
+                    if let Err(err) = self.deliver(&message).await {
+                        warn!(
+                            room = %message.room,
+                            message_id = message.id,
+                            url = %self.config.url,
+                            "could not post the message: {:#}",
+                            err
+                        );
+                    }
+                }
+                Ok(_) => (),
+                Err(RecvError::Lagged(skipped)) => warn!(
+                    room = %self.config.room,
+                    url = %self.config.url,
+                    "skipped {} events while posting",
+                    skipped
+                ),
+                Err(RecvError::Closed) => {
+                    return Err(anyhow::anyhow!("room '{}' is closed", self.config.room))
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, message: &UserMessageBroadcastEvent) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(&MessagePayload {
+            room: &message.room,
+            id: message.id,
+            user_id: &message.user_id,
+            text: &message.content,
+            timestamp: message.timestamp,
+            reply_to: message.reply_to,
+        })?;
+        let mut attempt = 1;
+
+        loop {
+            match self.post(&payload).await {
+                Ok(()) => return Ok(()),
+                Err(DeliveryError::Retryable(err)) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                    info!(
+                        url = %self.config.url,
+                        attempt,
+                        "could not post, retrying: {:#}",
+                        err
+                    );
+                    tokio::time::sleep(retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                Err(DeliveryError::Retryable(err) | DeliveryError::Permanent(err)) => {
+                    return Err(err)
+                }
+            }
+        }
+    }
+
+    async fn post(&self, payload: &[u8]) -> Result<(), DeliveryError> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.to_vec());
+        if let Some(token) = self.config.token.as_deref() {
+            // signed again for each attempt, so the retries are not taken for stale deliveries
+            let timestamp = webhooks::now_secs();
+            request = request
+                .header(webhooks::TIMESTAMP_HEADER, timestamp)
+                .header(
+                    webhooks::SIGNATURE_HEADER,
+                    webhooks::sign(token, timestamp, payload),
+                );
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| DeliveryError::Retryable(err.into()))?;
+        let status = response.status();
+
+        if status.is_success() {
+            Ok(())
+        } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Err(DeliveryError::Retryable(anyhow::anyhow!(
+                "the receiver answered {}",
+                status
+            )))
+        } else {
+            Err(DeliveryError::Permanent(anyhow::anyhow!(
+                "the receiver refused the message with {}",
+                status
+            )))
+        }
+    }
+}
+
+/// Returns the delay before retrying after the given attempt, doubling on each attempt up to the max delay
+pub fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(RETRY_MAX_DELAY)
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use comms::event::{self, Event, PresenceStatus, UserPresence};
+use tokio::sync::broadcast;
+
+use crate::sharded_map::ShardedMap;
+
+/// How often the idle users are looked for, to mark them away
+const IDLE_SWEEP_PERIOD: Duration = Duration::from_secs(30);
+/// The number of characters an away message can be at most
+pub const MAX_AWAY_MESSAGE_CHARS: usize = 100;
+/// The number of characters a display name can be at most
+pub const MAX_DISPLAY_NAME_CHARS: usize = 32;
+/// How many presence changes are buffered for the sessions which are slow to forward them
+const PRESENCE_BUFFER_SIZE: usize = 1000;
+
+/// The connections of a user, along with what the other users were last told about them
+#[derive(Debug)]
+struct TrackedUser {
+    /// The last time each connected session of the user has sent a command
+    last_activity_at: HashMap<String, Instant>,
+    away_message: Option<String>,
+    /// The name the user is shown with in place of their id, if they have changed it
+    display_name: Option<String>,
+    /// The color the name of the user is shown with, if they have picked one
+    color: Option<String>,
+    announced: UserPresence,
+}
+
+impl TrackedUser {
+    /// Away if the user said so, or if none of their sessions has sent a command for a while
+    fn presence(&self, user_id: &str, away_after: Duration, now: Instant) -> UserPresence {
+        let is_idle = self
+            .last_activity_at
+            .values()
+            .all(|last_activity_at| now.duration_since(*last_activity_at) >= away_after);
+
+        let status = if self.last_activity_at.is_empty() {
+            PresenceStatus::Offline
+        } else if self.away_message.is_some() || is_idle {
+            PresenceStatus::Away
+        } else {
+            PresenceStatus::Online
+        };
+
+        UserPresence {
+            user_id: String::from(user_id),
+            status,
+            away_message: self
+                .away_message
+                .clone()
+                .filter(|_| status == PresenceStatus::Away),
+            display_name: self.display_name.clone(),
+            color: self.color.clone(),
+        }
+    }
+}
+
+/// [PresenceTracker] derives whether each user is online, away or offline from their connected sessions
+/// and how long ago they were active, and broadcasts the changes to every connected user
+///
+/// The name each user is shown with, and its color, go along with their presence, their changes are broadcast as well.
+///
+/// The sessions kept for a dropped connection to resume them are not connected, their users are offline.
+#[derive(Debug)]
+pub struct PresenceTracker {
+    away_after: Mutex<Duration>,
+    users: ShardedMap<String, TrackedUser>,
+    broadcast_tx: broadcast::Sender<Event>,
+}
+
+impl PresenceTracker {
+    pub fn new(away_after: Duration) -> Self {
+        let (broadcast_tx, _) = broadcast::channel(PRESENCE_BUFFER_SIZE);
+
+        PresenceTracker {
+            away_after: Mutex::new(away_after),
+            users: ShardedMap::new(),
+            broadcast_tx,
+        }
+    }
+
+    /// Receives the presence changes of every user
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.broadcast_tx.subscribe()
+    }
+
+    /// The presence of the users who are not offline
+    pub fn snapshot(&self) -> Vec<UserPresence> {
+        let mut presences = Vec::new();
+        self.users.for_each_shard(|users| {
+            presences.extend(users.values().map(|user| user.announced.clone()));
+        });
+
+        presences
+    }
+
+    /// Tracks a connected session of the user, shown with the given name and color, until the returned guard is dropped
+    pub fn connect(
+        self: &Arc<Self>,
+        user_id: &str,
+        session_id: &str,
+        display_name: Option<String>,
+        color: Option<String>,
+    ) -> ConnectedSession {
+        self.update(user_id, |user, now| {
+            user.last_activity_at.insert(String::from(session_id), now);
+            user.display_name = display_name;
+            user.color = color;
+        });
+
+        ConnectedSession {
+            tracker: Arc::clone(self),
+            user_id: String::from(user_id),
+            session_id: String::from(session_id),
+        }
+    }
+
+    /// Records a command sent by the session, which brings back a user who was away by being idle
+    pub fn record_activity(&self, user_id: &str, session_id: &str) {
+        self.update(user_id, |user, now| {
+            if let Some(last_activity_at) = user.last_activity_at.get_mut(session_id) {
+                *last_activity_at = now;
+            }
+        });
+    }
+
+    /// Sets the user away with the status message until they come back by setting none
+    pub fn set_away_message(&self, user_id: &str, away_message: Option<String>) {
+        self.update(user_id, |user, _| user.away_message = away_message);
+    }
+
+    /// Changes the name the user is shown with, and tells every connected user about it
+    pub fn set_display_name(&self, user_id: &str, display_name: Option<String>) {
+        if let Some(user) = self.users.shard(user_id).get_mut(user_id) {
+            // the change is told by its own event, rather than as a change of presence
+            user.display_name = display_name.clone();
+            user.announced.display_name = display_name.clone();
+        }
+
+        let _ = self.broadcast_tx.send(Event::NicknameChanged(
+            event::NicknameChangedBroadcastEvent {
+                user_id: String::from(user_id),
+                display_name,
+            },
+        ));
+    }
+
+    /// Changes the color the name of the user is shown with, which is broadcast as a change of their presence
+    pub fn set_color(&self, user_id: &str, color: Option<String>) {
+        self.update(user_id, |user, _| user.color = color);
+    }
+
+    /// Changes how long the users can be idle before being shown away, from the next idle sweep on
+    pub fn set_away_after(&self, away_after: Duration) {
+        *self.away_after.lock().unwrap() = away_after;
+    }
+
+    /// Marks the users who have been idle for a while as away, periodically
+    pub fn spawn_idle_sweep(self: &Arc<Self>) {
+        let tracker = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_SWEEP_PERIOD);
+
+            loop {
+                interval.tick().await;
+
+                let mut user_ids = Vec::new();
+                tracker.users.for_each_shard(|users| {
+                    user_ids.extend(users.keys().cloned());
+                });
+                for user_id in user_ids {
+                    tracker.update(&user_id, |_, _| {});
+                }
+            }
+        });
+    }
+
+    fn disconnect(&self, user_id: &str, session_id: &str) {
+        self.update(user_id, |user, _| {
+            user.last_activity_at.remove(session_id);
+        });
+    }
+
+    /// Changes the tracked user, and broadcasts their presence if it has changed
+    ///
+    /// The users who are offline are not tracked anymore, their away message is forgotten.
+    fn update<F: FnOnce(&mut TrackedUser, Instant)>(&self, user_id: &str, change: F) {
+        let now = Instant::now();
+        let mut users = self.users.shard(user_id);
+
+        let user = users
+            .entry(String::from(user_id))
+            .or_insert_with(|| TrackedUser {
+                last_activity_at: HashMap::new(),
+                away_message: None,
+                display_name: None,
+                color: None,
+                announced: UserPresence {
+                    user_id: String::from(user_id),
+                    status: PresenceStatus::Offline,
+                    away_message: None,
+                    display_name: None,
+                    color: None,
+                },
+            });
+        change(user, now);
+
+        let presence = user.presence(user_id, *self.away_after.lock().unwrap(), now);
+        let is_changed = presence != user.announced;
+
+        if presence.status == PresenceStatus::Offline {
+            users.remove(user_id);
+        } else {
+            user.announced = presence.clone();
+        }
+
+        if !is_changed {
+            return;
+        }
+
+        // nobody may be connected to be told
+        let _ = self.broadcast_tx.send(Event::PresenceChanged(
+            event::PresenceChangedBroadcastEvent { presence },
+        ));
+    }
+}
+
+/// A connected session tracked by the [PresenceTracker], until it is dropped
+pub struct ConnectedSession {
+    tracker: Arc<PresenceTracker>,
+    user_id: String,
+    session_id: String,
+}
+
+impl Drop for ConnectedSession {
+    fn drop(&mut self) {
+        self.tracker.disconnect(&self.user_id, &self.session_id);
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+
+/// What a [MessageFilter] has decided about a message sent to a room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterVerdict {
+    /// The message is sent as it is
+    Pass,
+    /// The message is refused, the sender is told the reason
+    Reject(String),
+    /// The message is sent with the given content in place of its own
+    Rewrite(String),
+}
+
+/// [MessageFilter] looks at each message sent or edited in a room before it is kept in the history
+/// and fanned out to the members of the room
+///
+/// The server is built with a [WordListFilter], another filter can be given to the
+/// [super::RoomManagerBuilder] to enforce other content rules.
+pub trait MessageFilter: fmt::Debug + Send + Sync {
+    fn filter(&self, room: &str, user_id: &str, content: &str) -> FilterVerdict;
+}
+
+/// What is done with a message matching a [ContentFilterRule]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Reject,
+    /// The matches are replaced by as many `*`
+    Redact,
+    /// The message is prefixed with the tag of the rule
+    Tag,
+}
+
+/// A content rule of the configuration, matching the messages by their words or by regular expressions
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContentFilterRule {
+    /// The rooms the rule applies to, every room when left out
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    /// Whole words matched without case
+    #[serde(default)]
+    pub words: Vec<String>,
+    /// Regular expressions matched anywhere in the messages
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub action: FilterAction,
+    /// What the tagged messages are prefixed with
+    #[serde(default = "default_tag")]
+    pub tag: String,
+}
+
+fn default_tag() -> String {
+    String::from("[flagged]")
+}
+
+/// A [ContentFilterRule] whose words and patterns are compiled
+#[derive(Debug)]
+struct CompiledRule {
+    rooms: Vec<String>,
+    regexes: Vec<Regex>,
+    action: FilterAction,
+    tag: String,
+}
+
+impl CompiledRule {
+    fn applies_to(&self, room: &str) -> bool {
+        self.rooms.is_empty() || self.rooms.iter().any(|rule_room| rule_room == room)
+    }
+
+    fn is_match(&self, content: &str) -> bool {
+        self.regexes.iter().any(|regex| regex.is_match(content))
+    }
+}
+
+/// [WordListFilter] is the [MessageFilter] of the server, applying the content rules of its configuration
+///
+/// The rules are applied in the order they are defined: the first rejecting rule matching refuses
+/// the message, the redacting rules mask their matches for the rules following them, and the
+/// message is tagged once by the first tagging rule matching.
+#[derive(Debug, Default)]
+pub struct WordListFilter {
+    rules: Vec<CompiledRule>,
+}
+
+impl WordListFilter {
+    /// Compiles the rules, fails if one of their patterns is not a valid regular expression
+    pub fn new(rules: &[ContentFilterRule]) -> anyhow::Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let mut regexes = rule
+                    .patterns
+                    .iter()
+                    .map(|pattern| {
+                        Regex::new(pattern).with_context(|| {
+                            format!("invalid content filter pattern '{}'", pattern)
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                if !rule.words.is_empty() {
+                    let words = rule
+                        .words
+                        .iter()
+                        .map(|word| regex::escape(word))
+                        .collect::<Vec<_>>();
+                    regexes.push(Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|")))?);
+                }
+
+                Ok(CompiledRule {
+                    rooms: rule.rooms.clone(),
+                    regexes,
+                    action: rule.action,
+                    tag: rule.tag.clone(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(WordListFilter { rules })
+    }
+}
+
+impl MessageFilter for WordListFilter {
+    fn filter(&self, room: &str, _user_id: &str, content: &str) -> FilterVerdict {
+        let mut filtered = String::from(content);
+        let mut tag = None;
+
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(room)) {
+            if !rule.is_match(&filtered) {
+                continue;
+            }
+
+            match rule.action {
+                FilterAction::Reject => {
+                    return FilterVerdict::Reject(String::from(
+                        "the message breaks the content rules of the room",
+                    ))
+                }
+                FilterAction::Redact => {
+                    for regex in rule.regexes.iter() {
+                        filtered = regex
+                            .replace_all(&filtered, |captures: &regex::Captures| {
+                                "*".repeat(captures[0].chars().count())
+                            })
+                            .into_owned();
+                    }
+                }
+                FilterAction::Tag => {
+                    tag.get_or_insert_with(|| rule.tag.clone());
+                }
+            }
+        }
+
+        if let Some(tag) = tag {
+            filtered = format!("{} {}", tag, filtered);
+        }
+
+        if filtered == content {
+            FilterVerdict::Pass
+        } else {
+            FilterVerdict::Rewrite(filtered)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: FilterAction) -> ContentFilterRule {
+        ContentFilterRule {
+            rooms: vec![],
+            words: vec![],
+            patterns: vec![],
+            action,
+            tag: default_tag(),
+        }
+    }
+
+    #[test]
+    fn test_messages_matching_no_rule_pass() {
+        let filter = WordListFilter::new(&[ContentFilterRule {
+            words: vec![String::from("darn")],
+            ..rule(FilterAction::Reject)
+        }])
+        .unwrap();
+
+        assert_eq!(
+            filter.filter("general", "alice", "darning socks"),
+            FilterVerdict::Pass
+        );
+        assert!(matches!(
+            filter.filter("general", "alice", "DARN it"),
+            FilterVerdict::Reject(_)
+        ));
+    }
+
+    #[test]
+    fn test_matches_are_redacted_for_the_rules_following() {
+        let filter = WordListFilter::new(&[
+            ContentFilterRule {
+                patterns: vec![String::from(r"\d{4}-\d{4}")],
+                ..rule(FilterAction::Redact)
+            },
+            ContentFilterRule {
+                patterns: vec![String::from(r"\d{4}")],
+                ..rule(FilterAction::Reject)
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(
+            filter.filter("general", "alice", "call 1234-5678"),
+            FilterVerdict::Rewrite(String::from("call *********"))
+        );
+        assert!(matches!(
+            filter.filter("general", "alice", "room 1234"),
+            FilterVerdict::Reject(_)
+        ));
+    }
+
+    #[test]
+    fn test_messages_are_tagged_once_in_the_rooms_of_the_rule() {
+        let filter = WordListFilter::new(&[
+            ContentFilterRule {
+                rooms: vec![String::from("support")],
+                words: vec![String::from("refund")],
+                tag: String::from("[billing]"),
+                ..rule(FilterAction::Tag)
+            },
+            ContentFilterRule {
+                words: vec![String::from("refund")],
+                ..rule(FilterAction::Tag)
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(
+            filter.filter("support", "alice", "refund please"),
+            FilterVerdict::Rewrite(String::from("[billing] refund please"))
+        );
+        assert_eq!(
+            filter.filter("general", "alice", "refund please"),
+            FilterVerdict::Rewrite(String::from("[flagged] refund please"))
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_refused() {
+        let err = WordListFilter::new(&[ContentFilterRule {
+            patterns: vec![String::from("(unclosed")],
+            ..rule(FilterAction::Reject)
+        }])
+        .unwrap_err();
+
+        assert!(err.to_string().contains("(unclosed"));
+    }
+}
@@ -1,47 +1,134 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use tokio::sync::Mutex;
+use crate::storage::{AttachmentStore, BanStore, MessageStore};
 
-use self::room::ChatRoom;
-pub use self::room::{ChatRoomMetadata, SessionAndUserId, UserSessionHandle};
+pub use self::message_filter::{ContentFilterRule, MessageFilter, WordListFilter};
+pub use self::room::{ChatRoomMetadata, RoomVisibility, SessionAndUserId, UserSessionHandle};
+use self::room_manager::spawn_chat_room;
 
-pub use self::room_manager::RoomManager;
+pub use self::room_manager::{RoomListEvent, RoomManager};
 
+mod message_filter;
 mod room;
 #[allow(clippy::module_inception)]
 mod room_manager;
 
+/// Default window in which the exact duplicates of a message sent by the same user are dropped
+pub const DEFAULT_DUPLICATE_SUPPRESSION_WINDOW: Duration = Duration::from_secs(2);
+/// Default number of characters a message can be at most
+pub const DEFAULT_MAX_MESSAGE_CHARS: usize = 2000;
+
 #[derive(Debug)]
 pub struct RoomManagerBuilder {
-    chat_rooms: Vec<(ChatRoomMetadata, Arc<Mutex<room::ChatRoom>>)>,
+    chat_room_metadatas: Vec<ChatRoomMetadata>,
+    duplicate_suppression_window: Duration,
+    max_message_chars: usize,
+    message_store: Option<Arc<MessageStore>>,
+    ban_store: Option<Arc<BanStore>>,
+    attachment_store: Option<Arc<AttachmentStore>>,
+    message_filter: Arc<dyn MessageFilter>,
 }
 
 impl RoomManagerBuilder {
     pub fn new() -> Self {
         RoomManagerBuilder {
-            chat_rooms: Vec::new(),
+            chat_room_metadatas: Vec::new(),
+            duplicate_suppression_window: DEFAULT_DUPLICATE_SUPPRESSION_WINDOW,
+            max_message_chars: DEFAULT_MAX_MESSAGE_CHARS,
+            message_store: None,
+            ban_store: None,
+            attachment_store: None,
+            message_filter: Arc::new(WordListFilter::default()),
         }
     }
 
     /// Add a room to the room manager
     /// Will panic if a room with the same name already exists
     pub fn create_room(mut self, metadata: ChatRoomMetadata) -> Self {
-        let chat_room = Arc::new(Mutex::new(ChatRoom::new(metadata.clone())));
-
         if self
-            .chat_rooms
+            .chat_room_metadatas
             .iter()
-            .any(|(m, _)| m.name.eq(&metadata.name))
+            .any(|m| m.name.eq(&metadata.name))
         {
             panic!("room with the same name already exists");
         }
 
-        self.chat_rooms.push((metadata, chat_room));
+        self.chat_room_metadatas.push(metadata);
+
+        self
+    }
+
+    /// Set the window in which the exact duplicates of a message sent by the same user are dropped
+    /// A zero duration disables the duplicate suppression
+    pub fn duplicate_suppression_window(mut self, window: Duration) -> Self {
+        self.duplicate_suppression_window = window;
+
+        self
+    }
+
+    /// Set the number of characters the messages sent and edited in the rooms can be at most
+    pub fn max_message_chars(mut self, max_message_chars: usize) -> Self {
+        self.max_message_chars = max_message_chars;
+
+        self
+    }
+
+    /// Persist the messages of the rooms to the given store, and restore their histories from it
+    pub fn message_store(mut self, message_store: Arc<MessageStore>) -> Self {
+        self.message_store = Some(message_store);
 
         self
     }
 
+    /// Keep the bans of the rooms in the given store, and restore them from it
+    pub fn ban_store(mut self, ban_store: Arc<BanStore>) -> Self {
+        self.ban_store = Some(ban_store);
+
+        self
+    }
+
+    /// Keep the files shared with the rooms in the given store, files can not be shared without one
+    pub fn attachment_store(mut self, attachment_store: Arc<AttachmentStore>) -> Self {
+        self.attachment_store = Some(attachment_store);
+
+        self
+    }
+
+    /// Filter the messages sent and edited in the rooms with the given filter, rather than letting them all through
+    pub fn message_filter(mut self, message_filter: Arc<dyn MessageFilter>) -> Self {
+        self.message_filter = message_filter;
+
+        self
+    }
+
+    /// Spawns the task of every room, the room manager is built within the runtime
     pub fn build(self) -> RoomManager {
-        RoomManager::new(self.chat_rooms)
+        let duplicate_suppression_window = self.duplicate_suppression_window;
+        let message_store = self.message_store;
+        let ban_store = self.ban_store;
+        let message_filter = self.message_filter;
+
+        RoomManager::new(
+            self.chat_room_metadatas
+                .into_iter()
+                .map(|metadata| {
+                    let chat_room = spawn_chat_room(
+                        metadata.clone(),
+                        duplicate_suppression_window,
+                        Arc::clone(&message_filter),
+                        message_store.clone(),
+                        ban_store.clone(),
+                    );
+
+                    (metadata, chat_room)
+                })
+                .collect(),
+            duplicate_suppression_window,
+            self.max_message_chars,
+            message_filter,
+            message_store,
+            ban_store,
+            self.attachment_store,
+        )
     }
 }
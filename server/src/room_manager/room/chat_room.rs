@@ -1,37 +1,202 @@
-use comms::event::{self, Event};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use comms::event::{self, Event, HistoryMessage, HistoryVisibility, RoomRole};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::{
+    clock::now_millis,
+    command_error::CommandError,
+    storage::{Attachment, MessageStore},
+};
 
+use super::super::message_filter::{FilterVerdict, MessageFilter};
 use super::{
-    user_registry::UserRegistry, user_session_handle::UserSessionHandle, SessionAndUserId,
+    room_history::{HistoryChunk, OlderMessages, RoomHistory, StoredHistory},
+    room_permission::RoomPermission,
+    user_registry::UserRegistry,
+    SessionAndUserId,
 };
 
+/// Whether anyone can see and join a room, or only the users invited by its members
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomVisibility {
+    #[default]
+    Public,
+    Private,
+}
+
+/// How long the messages of a room are kept, every message is kept when neither limit is set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// The number of the latest messages kept, the older ones are pruned
+    pub max_messages: Option<usize>,
+    /// How old the messages can be before being pruned, in seconds
+    pub max_age_secs: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_messages.is_none() && self.max_age_secs.is_none()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// [ChatRoomMetadata] holds the metadata that identifies a chat room
 pub struct ChatRoomMetadata {
     pub name: String,
     pub description: String,
+    /// Private rooms are not listed to the users, and can only be joined once invited
+    #[serde(default)]
+    pub visibility: RoomVisibility,
+    #[serde(default)]
+    pub history_visibility: HistoryVisibility,
+    /// Template pre-populating the message input of the users composing in the room
+    #[serde(default)]
+    pub input_template: Option<String>,
+    /// Whether the members of the room are allowed to export its full history
+    #[serde(default)]
+    pub history_export: bool,
+    /// The user who created the room from their client, who owns it, rooms defined at startup have no owner
+    #[serde(default)]
+    pub created_by: Option<String>,
+    /// The users the room starts with as its moderators
+    #[serde(default)]
+    pub moderators: Vec<String>,
+    /// How long the messages of the room are kept, in memory and in the store
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+}
+
+impl ChatRoomMetadata {
+    /// The details of the room as they are sent to the users
+    pub fn to_room_detail(&self) -> event::RoomDetail {
+        event::RoomDetail {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            history_visibility: self.history_visibility.clone(),
+            input_template: self.input_template.clone(),
+        }
+    }
 }
 
 const BROADCAST_CHANNEL_CAPACITY: usize = 100;
 
 #[derive(Debug)]
-/// [ChatRoom] handles the participants of a chat room and the primary broadcast channel
-/// A [UserSessionHandle] is handed out to a user when they join the room
+/// [ChatRoom] handles the participants of a chat room, its history and the primary broadcast channel
+///
+/// A room is owned by its own task, and reached through a [super::RoomHandle].
 pub struct ChatRoom {
     metadata: ChatRoomMetadata,
     broadcast_tx: broadcast::Sender<event::Event>,
     user_registry: UserRegistry,
+    history: RoomHistory,
+    /// The users invited to a private room, invitations are kept so the invitees can join again later
+    invited_user_ids: HashSet<String>,
+    /// The users who can not join the room anymore
+    banned_user_ids: HashSet<String>,
+    /// The users whose messages are refused, until the given timestamp in milliseconds
+    muted_until: HashMap<String, u64>,
+    /// The roles of the users who are not plain members, seeded from the metadata
+    roles: HashMap<String, RoomRole>,
+    /// Looks at the messages before they are kept and sent to the users
+    message_filter: Arc<dyn MessageFilter>,
 }
 
 impl ChatRoom {
-    pub fn new(metadata: ChatRoomMetadata) -> Self {
+    /// Creates a room, which drops the duplicate messages sent by the same user within the given window,
+    /// filters the messages with the given filter and persists them to the given store, if any
+    pub fn new(
+        metadata: ChatRoomMetadata,
+        duplicate_window: Duration,
+        message_filter: Arc<dyn MessageFilter>,
+        store: Option<Arc<MessageStore>>,
+    ) -> Self {
         let (broadcast_tx, _) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
+        let history = RoomHistory::new(&metadata.name, duplicate_window, store);
+        let roles = metadata
+            .moderators
+            .iter()
+            .map(|user_id| (user_id.clone(), RoomRole::Moderator))
+            .chain(
+                metadata
+                    .created_by
+                    .iter()
+                    .map(|user_id| (user_id.clone(), RoomRole::Owner)),
+            )
+            .collect();
 
         ChatRoom {
             metadata,
             broadcast_tx,
             user_registry: UserRegistry::new(),
+            history,
+            invited_user_ids: HashSet::new(),
+            banned_user_ids: HashSet::new(),
+            muted_until: HashMap::new(),
+            roles,
+            message_filter,
+        }
+    }
+
+    pub fn metadata(&self) -> &ChatRoomMetadata {
+        &self.metadata
+    }
+
+    /// Restores the history read from the store, before the room is handed any request
+    pub fn restore_history(&mut self, stored: StoredHistory) {
+        self.history.restore(stored);
+    }
+
+    /// Subscribes to the events of the room without joining it
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.broadcast_tx.subscribe()
+    }
+
+    /// Returns how many messages were sent to the room since the server started
+    pub fn messages_sent(&self) -> u64 {
+        self.history.messages_sent()
+    }
+
+    /// Returns the time of the latest message of the room, if any is kept
+    pub fn last_activity(&self) -> Option<u64> {
+        self.history.last_activity()
+    }
+
+    /// Changes the window in which the duplicate messages sent by the same user are dropped
+    pub fn set_duplicate_window(&mut self, duplicate_window: Duration) {
+        self.history.set_duplicate_window(duplicate_window);
+    }
+
+    /// Changes the filter of the messages sent and edited from now on
+    pub fn set_message_filter(&mut self, message_filter: Arc<dyn MessageFilter>) {
+        self.message_filter = message_filter;
+    }
+
+    /// Runs the message through the filter of the room, returns the content to keep and send
+    ///
+    /// Fails if the filter rejects the message.
+    fn filter_message(&self, user_id: &str, content: String) -> anyhow::Result<String> {
+        match self
+            .message_filter
+            .filter(&self.metadata.name, user_id, &content)
+        {
+            FilterVerdict::Pass => Ok(content),
+            FilterVerdict::Rewrite(content) => Ok(content),
+            FilterVerdict::Reject(reason) => {
+                debug!(room = %self.metadata.name, "rejected a message");
+
+                Err(CommandError::PermissionDenied(reason).into())
+            }
         }
     }
 
@@ -39,27 +204,397 @@ impl ChatRoom {
         self.user_registry.get_unique_user_ids()
     }
 
-    /// Add a participant to the room and broadcast that they joined
+    /// Whether the user is allowed to join the room, anyone who is not banned can join a public room
     ///
-    /// # Returns
+    /// Only the owner, the moderators and the invited users can join a private room.
+    pub fn is_joinable_by(&self, user_id: &str) -> bool {
+        self.metadata.visibility == RoomVisibility::Public
+            || self.role_of(user_id) != RoomRole::Member
+            || self.invited_user_ids.contains(user_id)
+    }
+
+    pub fn role_of(&self, user_id: &str) -> RoomRole {
+        self.roles.get(user_id).copied().unwrap_or_default()
+    }
+
+    /// Returns the users allowed to see the private room, the ones who can join it and the ones in it,
+    /// or None if the room is public and everyone sees it
+    pub fn private_audience(&self) -> Option<HashSet<String>> {
+        if self.metadata.visibility == RoomVisibility::Public {
+            return None;
+        }
+
+        let audience = self
+            .roles
+            .iter()
+            .filter(|(_, role)| **role != RoomRole::Member)
+            .map(|(user_id, _)| user_id.clone())
+            .chain(self.invited_user_ids.iter().cloned())
+            .chain(self.get_unique_user_ids())
+            .collect();
+
+        Some(audience)
+    }
+
+    /// Returns the users who are not plain members, sorted by name
+    pub fn roles(&self) -> Vec<(String, RoomRole)> {
+        let mut roles = self
+            .roles
+            .iter()
+            .map(|(user_id, role)| (user_id.clone(), *role))
+            .collect::<Vec<_>>();
+        roles.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        roles
+    }
+
+    /// Fails unless the role of the user allows the permission
+    pub fn check_permission(
+        &self,
+        user_id: &str,
+        permission: RoomPermission,
+    ) -> anyhow::Result<()> {
+        let required_role = permission.required_role();
+
+        if self.role_of(user_id) >= required_role {
+            return Ok(());
+        }
+
+        Err(CommandError::PermissionDenied(format!(
+            "only the {} of room '{}' can {}",
+            match required_role {
+                RoomRole::Owner => "owner",
+                _ => "owner and the moderators",
+            },
+            self.metadata.name,
+            permission.describe()
+        ))
+        .into())
+    }
+
+    /// Changes the role of a user and broadcasts it, the owner of the room is the only one who keeps their role
+    pub fn set_role(&mut self, user_id: &str, role: RoomRole) -> anyhow::Result<()> {
+        if role == RoomRole::Owner || self.role_of(user_id) == RoomRole::Owner {
+            return Err(anyhow::anyhow!(
+                "room '{}' has a single owner",
+                self.metadata.name
+            ));
+        }
+
+        match role {
+            RoomRole::Member => self.roles.remove(user_id),
+            _ => self.roles.insert(String::from(user_id), role),
+        };
+
+        let _ = self
+            .broadcast_tx
+            .send(event::Event::RoomRole(event::RoomRoleBroadcastEvent {
+                room: self.metadata.name.clone(),
+                user_id: String::from(user_id),
+                role,
+            }));
+
+        Ok(())
+    }
+
+    pub fn is_banned(&self, user_id: &str) -> bool {
+        self.banned_user_ids.contains(user_id)
+    }
+
+    /// Bans the user from the room, along with withdrawing their invitation
+    pub fn ban(&mut self, user_id: &str) {
+        self.invited_user_ids.remove(user_id);
+        self.banned_user_ids.insert(String::from(user_id));
+    }
+
+    /// Refuses the messages of the user until the given timestamp, a past timestamp unmutes the user
+    pub fn mute(&mut self, user_id: &str, until: u64) {
+        self.muted_until.insert(String::from(user_id), until);
+    }
+
+    /// Fails if the user is muted in the room
+    pub fn check_not_muted(&self, user_id: &str) -> anyhow::Result<()> {
+        let now = now_millis();
+
+        if let Some(until) = self.muted_until.get(user_id).filter(|until| **until > now) {
+            return Err(CommandError::PermissionDenied(format!(
+                "you are muted in room '{}' for {} more seconds",
+                self.metadata.name,
+                (until - now).div_ceil(1000)
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Send a message of the user to the room and record it to the room history, optionally as a reply to another message
     ///
-    /// - A broadcast receiver for the user to receive messages from the room
-    /// - A [UserSessionHandle] for the user to be able to interact with the room
-    pub fn join(
+    /// The message is broadcast once it is stored, so a message its sender received is not lost if the server stops.
+    /// Exact duplicates of a recently sent message are dropped, to guard against clients retrying.
+    /// A reply to a message which is not in the room history anymore is sent as a regular message.
+    /// The content is the one the filter of the room lets through, it fails if the user is muted in the room
+    /// or if the filter rejects the message.
+    pub fn send_message(
         &mut self,
-        session_and_user_id: &SessionAndUserId,
-    ) -> (broadcast::Receiver<Event>, UserSessionHandle) {
+        user_id: &str,
+        content: String,
+        reply_to: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let timestamp = now_millis();
+
+        self.check_not_muted(user_id)?;
+        let content = self.filter_message(user_id, content)?;
+
+        let reply_to = reply_to.filter(|reply_to| self.history.contains(*reply_to));
+        let id = self.history.push(HistoryMessage {
+            id: 0,
+            user_id: String::from(user_id),
+            content: content.clone(),
+            timestamp,
+            reply_to,
+            is_edited: false,
+            reactions: vec![],
+        });
+
+        let Some(id) = id else {
+            debug!(room = %self.metadata.name, "suppressed a duplicate message");
+
+            return Ok(());
+        };
+
+        // the broadcast acknowledges the message to its sender, so it waits for the message to be stored
         let broadcast_tx = self.broadcast_tx.clone();
-        let broadcast_rx = broadcast_tx.subscribe();
-        let user_session_handle = UserSessionHandle::new(
-            self.metadata.name.clone(),
-            broadcast_tx,
-            session_and_user_id.clone(),
-        );
+        let event = Event::UserMessage(event::UserMessageBroadcastEvent {
+            room: self.metadata.name.clone(),
+            id,
+            user_id: String::from(user_id),
+            content,
+            timestamp,
+            reply_to,
+        });
+        self.history.after_stored(move || {
+            // nobody is left to tell if every member has left in the meantime
+            let _ = broadcast_tx.send(event);
+        });
+        debug!(room = %self.metadata.name, message_id = id, "sent a message");
+
+        Ok(())
+    }
+
+    /// Tell the users of the room about a file a user has shared with it
+    pub fn share_file(&self, attachment: Attachment) -> anyhow::Result<()> {
+        self.broadcast_tx
+            .send(Event::FileShared(event::FileSharedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                file_id: attachment.id,
+                user_id: attachment.user_id,
+                name: attachment.name,
+                size: attachment.size,
+                checksum: attachment.checksum,
+                timestamp: attachment.timestamp,
+            }))
+            .context("could not write to the broadcast channel")?;
+
+        Ok(())
+    }
+
+    /// Edit a message the user has sent to the room and broadcast its new content
+    ///
+    /// Fails if the message is not in the room history anymore, if the user is not its author,
+    /// if the user is muted in the room or if the filter of the room rejects the new content
+    pub fn edit_message(&mut self, user_id: &str, id: u64, content: String) -> anyhow::Result<()> {
+        self.check_not_muted(user_id)?;
+        let content = self.filter_message(user_id, content)?;
+        self.history.edit(id, user_id, content.clone())?;
+
+        self.broadcast_tx
+            .send(Event::MessageEdited(event::MessageEditedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                id,
+                content,
+            }))
+            .context("could not write to the broadcast channel")?;
+
+        Ok(())
+    }
+
+    /// Delete a message the user has sent to the room and broadcast its deletion
+    ///
+    /// Fails if the message is not in the room history anymore, or if the user is not its author
+    pub fn delete_message(&mut self, user_id: &str, id: u64) -> anyhow::Result<()> {
+        self.history.delete(id, user_id)?;
+
+        self.broadcast_tx
+            .send(Event::MessageDeleted(event::MessageDeletedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                id,
+            }))
+            .context("could not write to the broadcast channel")?;
+
+        Ok(())
+    }
+
+    /// Prunes the messages the retention policy of the room does not keep anymore,
+    /// and tells the users of the room where its history now starts if it has pruned some of the ones they were sent
+    pub fn prune_history(&mut self) {
+        let retention = &self.metadata.retention;
+        if retention.is_unlimited() {
+            return;
+        }
+
+        let oldest_timestamp = retention
+            .max_age_secs
+            .map(|max_age_secs| now_millis().saturating_sub(max_age_secs * 1000));
+
+        if let Some(before) = self.history.prune(retention.max_messages, oldest_timestamp) {
+            // nobody may be in the room to be told
+            let _ = self.broadcast_tx.send(Event::HistoryTruncated(
+                event::HistoryTruncatedBroadcastEvent {
+                    room: self.metadata.name.clone(),
+                    before,
+                },
+            ));
+        }
+    }
+
+    /// Mark the messages of the room as read by the user, up to the given message
+    pub fn mark_read(&mut self, user_id: &str, id: u64) -> anyhow::Result<()> {
+        self.history.mark_read(user_id, id)
+    }
+
+    /// Returns the id of the last message of the room read by the user, if they marked any as read
+    pub fn last_read(&self, user_id: &str) -> Option<u64> {
+        self.history.last_read_by(user_id)
+    }
+
+    /// React to a message of the room, or take the reaction back, and broadcast the reactions to the message
+    ///
+    /// Fails if the message is not in the room history anymore, or if the reaction is not a single emoji
+    pub fn react_to_message(&mut self, user_id: &str, id: u64, emoji: &str) -> anyhow::Result<()> {
+        let reactions = self.history.toggle_reaction(id, user_id, emoji)?;
+
+        self.broadcast_tx
+            .send(Event::MessageReactions(
+                event::MessageReactionsBroadcastEvent {
+                    room: self.metadata.name.clone(),
+                    id,
+                    reactions,
+                },
+            ))
+            .context("could not write to the broadcast channel")?;
+
+        Ok(())
+    }
+
+    /// Tells the users of the room what a moderator has done to one of them
+    pub fn broadcast_moderation(
+        &self,
+        user_id: &str,
+        action: event::ModerationAction,
+        moderator_id: &str,
+    ) {
+        let _ = self.broadcast_tx.send(event::Event::UserModerated(
+            event::UserModeratedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                user_id: String::from(user_id),
+                action,
+                moderator_id: String::from(moderator_id),
+            },
+        ));
+    }
+
+    /// Invites a user to the private room, returns false if they are already invited
+    pub fn invite(&mut self, user_id: &str) -> bool {
+        self.invited_user_ids.insert(String::from(user_id))
+    }
+
+    /// Withdraws the invitation of the user
+    pub fn revoke_invitation(&mut self, user_id: &str) {
+        self.invited_user_ids.remove(user_id);
+    }
+
+    /// Returns the messages of the room the given user is allowed to see,
+    /// according to the history visibility policy of the room
+    pub fn get_visible_history(
+        &self,
+        user_id: &str,
+        around: Option<u64>,
+        limit: Option<usize>,
+    ) -> Vec<HistoryMessage> {
+        self.history
+            .visible_to(user_id, &self.metadata.history_visibility, around, limit)
+    }
+
+    /// Returns a page of the messages the given user is allowed to see, older than the given message,
+    /// and whether there are more of them
+    pub fn get_visible_before(&self, user_id: &str, before: u64, limit: usize) -> OlderMessages {
+        self.history
+            .visible_before(user_id, &self.metadata.history_visibility, before, limit)
+    }
+
+    /// Returns the timestamp of the first message the given user is allowed to see
+    pub fn get_visible_since(&self, user_id: &str) -> u64 {
+        self.history
+            .visible_since(user_id, &self.metadata.history_visibility)
+    }
+
+    /// Returns a chunk of the history of the room the given user is allowed to see, for exporting it
+    pub fn get_history_chunk(
+        &self,
+        user_id: &str,
+        after: Option<u64>,
+        limit: usize,
+    ) -> HistoryChunk {
+        self.history
+            .chunk_after(user_id, &self.metadata.history_visibility, after, limit)
+    }
+
+    /// Returns the ids of the messages the given user is allowed to see, including the ones only kept in the store
+    pub fn get_visible_ids(&self, user_id: &str) -> Range<u64> {
+        self.history
+            .visible_ids(user_id, &self.metadata.history_visibility)
+    }
+
+    /// Returns all the messages of the given user kept in the room history
+    pub fn get_messages_of(&self, user_id: &str) -> Vec<HistoryMessage> {
+        self.history.messages_of(user_id)
+    }
+
+    /// Anonymizes the messages of the given user in the room history
+    pub fn anonymize_user(&mut self, user_id: &str) {
+        self.history.anonymize_user(user_id);
+    }
+
+    /// Changes the topic of the room, shown as its description, and broadcasts it to the members
+    pub fn set_topic(&mut self, topic: &str, changed_by: &str) {
+        self.metadata.description = String::from(topic);
+
+        let _ = self.broadcast_tx.send(event::Event::RoomTopicChanged(
+            event::RoomTopicChangedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                topic: String::from(topic),
+                changed_by: String::from(changed_by),
+            },
+        ));
+    }
+
+    /// Add a participant to the room and broadcast that they joined
+    ///
+    /// Returns a broadcast receiver for the user to receive messages from the room
+    pub fn join(&mut self, session_and_user_id: &SessionAndUserId) -> broadcast::Receiver<Event> {
+        let broadcast_rx = self.broadcast_tx.subscribe();
+
+        self.history.record_membership(&session_and_user_id.user_id);
+
+        // the members of a private room can always join it again
+        if self.metadata.visibility == RoomVisibility::Private {
+            self.invite(&session_and_user_id.user_id);
+        }
 
         // If the user is new e.g. they do not have another session with same user id,
         // broadcast that they joined to all users
-        if self.user_registry.insert(&user_session_handle) {
+        if self.user_registry.insert(session_and_user_id) {
             let _ = self.broadcast_tx.send(event::Event::RoomParticipation(
                 event::RoomParticipationBroacastEvent {
                     user_id: session_and_user_id.user_id.clone(),
@@ -69,16 +604,34 @@ impl ChatRoom {
             ));
         }
 
-        (broadcast_rx, user_session_handle)
+        broadcast_rx
     }
 
     /// Remove a participant from the room and broadcast that they left
-    /// Consume the [UserSessionHandle] to drop it
-    pub fn leave(&mut self, user_session_handle: UserSessionHandle) {
-        if self.user_registry.remove(&user_session_handle) {
+    pub fn leave(&mut self, session_and_user_id: &SessionAndUserId) {
+        if self.user_registry.remove(session_and_user_id) {
+            let _ = self.broadcast_tx.send(event::Event::RoomParticipation(
+                event::RoomParticipationBroacastEvent {
+                    user_id: session_and_user_id.user_id.clone(),
+                    room: self.metadata.name.clone(),
+                    status: event::RoomParticipationStatus::Left,
+                },
+            ));
+        }
+    }
+
+    /// Remove a participant who chose to leave the room, forgetting the membership of the user
+    /// once their last session has left
+    pub fn quit(&mut self, session_and_user_id: &SessionAndUserId) {
+        // the other sessions of the user still in the room keep the history they see
+        let has_left_last_session = self.user_registry.remove(session_and_user_id);
+
+        if has_left_last_session {
+            self.history.forget_membership(&session_and_user_id.user_id);
+
             let _ = self.broadcast_tx.send(event::Event::RoomParticipation(
                 event::RoomParticipationBroacastEvent {
-                    user_id: String::from(user_session_handle.user_id()),
+                    user_id: session_and_user_id.user_id.clone(),
                     room: self.metadata.name.clone(),
                     status: event::RoomParticipationStatus::Left,
                 },
@@ -86,3 +639,147 @@ impl ChatRoom {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room_manager::WordListFilter;
+
+    /// A room owned by alice, with bob as a moderator
+    fn room() -> ChatRoom {
+        room_with_visibility(RoomVisibility::Public)
+    }
+
+    fn room_with_visibility(visibility: RoomVisibility) -> ChatRoom {
+        ChatRoom::new(
+            ChatRoomMetadata {
+                name: String::from("book-club"),
+                description: String::from("One chapter a week"),
+                visibility,
+                history_visibility: HistoryVisibility::default(),
+                input_template: None,
+                history_export: false,
+                created_by: Some(String::from("alice")),
+                moderators: vec![String::from("bob")],
+                retention: RetentionPolicy::default(),
+            },
+            Duration::ZERO,
+            Arc::new(WordListFilter::default()),
+            None,
+        )
+    }
+
+    fn is_denied(result: anyhow::Result<()>) -> bool {
+        matches!(
+            result.unwrap_err().downcast_ref::<CommandError>(),
+            Some(CommandError::PermissionDenied(_))
+        )
+    }
+
+    fn session(session_id: &str, user_id: &str) -> SessionAndUserId {
+        SessionAndUserId {
+            session_id: String::from(session_id),
+            user_id: String::from(user_id),
+        }
+    }
+
+    #[test]
+    fn test_the_membership_is_kept_while_another_session_of_the_user_is_in_the_room() {
+        let mut room = room();
+        let _ = room.join(&session("phone", "carol"));
+        let _ = room.join(&session("laptop", "carol"));
+        room.send_message("bob", String::from("helo"), None)
+            .unwrap();
+
+        room.quit(&session("phone", "carol"));
+        assert_eq!(room.get_visible_history("carol", None, None).len(), 1);
+
+        // the history starts over once the last session has left
+        room.quit(&session("laptop", "carol"));
+        let _ = room.join(&session("laptop", "carol"));
+        assert!(room.get_visible_history("carol", None, None).is_empty());
+    }
+
+    #[test]
+    fn test_members_can_not_moderate_nor_change_the_topic() {
+        let room = room();
+
+        for permission in [
+            RoomPermission::Moderate,
+            RoomPermission::SetTopic,
+            RoomPermission::ManageRoles,
+            RoomPermission::DeleteRoom,
+        ] {
+            assert!(is_denied(room.check_permission("carol", permission)));
+        }
+    }
+
+    #[test]
+    fn test_moderators_moderate_and_change_the_topic() {
+        let room = room();
+
+        assert!(room
+            .check_permission("bob", RoomPermission::Moderate)
+            .is_ok());
+        assert!(room
+            .check_permission("bob", RoomPermission::SetTopic)
+            .is_ok());
+        // the roles and the room itself are left to the owner
+        assert!(is_denied(
+            room.check_permission("bob", RoomPermission::ManageRoles)
+        ));
+        assert!(is_denied(
+            room.check_permission("bob", RoomPermission::DeleteRoom)
+        ));
+    }
+
+    #[test]
+    fn test_the_owner_is_allowed_everything() {
+        let room = room();
+
+        for permission in [
+            RoomPermission::Moderate,
+            RoomPermission::SetTopic,
+            RoomPermission::ManageRoles,
+            RoomPermission::DeleteRoom,
+        ] {
+            assert!(room.check_permission("alice", permission).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_the_permissions_follow_the_role_changes() {
+        let mut room = room();
+
+        room.set_role("carol", RoomRole::Moderator).unwrap();
+        room.set_role("bob", RoomRole::Member).unwrap();
+
+        assert!(room
+            .check_permission("carol", RoomPermission::Moderate)
+            .is_ok());
+        assert!(is_denied(
+            room.check_permission("bob", RoomPermission::Moderate)
+        ));
+        // the room keeps its single owner
+        assert!(room.set_role("carol", RoomRole::Owner).is_err());
+        assert!(room.set_role("alice", RoomRole::Member).is_err());
+    }
+
+    #[test]
+    fn test_only_the_staff_and_the_invited_users_join_a_private_room() {
+        let mut room = room_with_visibility(RoomVisibility::Private);
+
+        // nobody claims a private room by guessing its name, even before any invitation
+        assert!(room.is_joinable_by("alice"));
+        assert!(room.is_joinable_by("bob"));
+        assert!(!room.is_joinable_by("carol"));
+
+        room.invite("carol");
+        assert!(room.is_joinable_by("carol"));
+        assert!(!room.is_joinable_by("dave"));
+
+        room.revoke_invitation("carol");
+        assert!(!room.is_joinable_by("carol"));
+        assert!(room.is_joinable_by("bob"));
+    }
+}
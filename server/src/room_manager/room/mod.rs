@@ -1,6 +1,12 @@
 mod chat_room;
+mod room_handle;
+mod room_history;
+mod room_permission;
 mod user_registry;
 mod user_session_handle;
 
-pub use self::chat_room::{ChatRoom, ChatRoomMetadata};
+pub use self::chat_room::{ChatRoom, ChatRoomMetadata, RetentionPolicy, RoomVisibility};
+pub use self::room_handle::RoomHandle;
+pub use self::room_history::{HistoryChunk, StoredHistory, ANONYMIZED_USER_ID};
+pub use self::room_permission::RoomPermission;
 pub use self::user_session_handle::{SessionAndUserId, UserSessionHandle};
@@ -0,0 +1,122 @@
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error};
+
+use super::ChatRoom;
+
+/// How many requests can be waiting for a room before the tasks sending them wait in turn
+const ROOM_REQUESTS_CAPACITY: usize = 256;
+
+enum RoomRequest {
+    /// Runs on the room
+    Run(Box<dyn FnOnce(&mut ChatRoom) + Send>),
+    /// Stops the room, the sender is dropped once it is stopped
+    Close(oneshot::Sender<()>),
+}
+
+/// [RoomHandle] sends requests to the task owning a [ChatRoom], which runs them one at a time
+///
+/// Each room is owned by its own task, so the rooms never wait on each other and no lock is taken
+/// to reach the state of a room. The task ends once every handle to the room is dropped, or the room is closed.
+#[derive(Debug, Clone)]
+pub struct RoomHandle {
+    room: String,
+    requests_tx: mpsc::Sender<RoomRequest>,
+}
+
+impl RoomHandle {
+    /// Hands the room over to a task of its own
+    pub fn spawn(chat_room: ChatRoom) -> Self {
+        let room = chat_room.metadata().name.clone();
+        let (requests_tx, requests_rx) = mpsc::channel::<RoomRequest>(ROOM_REQUESTS_CAPACITY);
+
+        tokio::spawn(serve(chat_room, requests_rx));
+
+        RoomHandle { room, requests_tx }
+    }
+
+    /// Hands the room over to a task of its own, which first reads what `restore` returns off the threads
+    /// of the runtime and applies it to the room
+    ///
+    /// The requests sent meanwhile run once the room is restored.
+    pub fn spawn_restored<T: Send + 'static>(
+        mut chat_room: ChatRoom,
+        restore: impl FnOnce() -> T + Send + 'static,
+        apply: impl FnOnce(&mut ChatRoom, T) + Send + 'static,
+    ) -> Self {
+        let room = chat_room.metadata().name.clone();
+        let (requests_tx, requests_rx) = mpsc::channel::<RoomRequest>(ROOM_REQUESTS_CAPACITY);
+
+        tokio::spawn(async move {
+            match tokio::task::spawn_blocking(restore).await {
+                Ok(restored) => apply(&mut chat_room, restored),
+                Err(err) => {
+                    error!(room = %chat_room.metadata().name, "could not restore the room: {}", err)
+                }
+            }
+
+            serve(chat_room, requests_rx).await;
+        });
+
+        RoomHandle { room, requests_tx }
+    }
+
+    /// Runs the request on the room once the requests sent before it have run, and returns what it returned
+    pub async fn call<T, F>(&self, request: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ChatRoom) -> T + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.requests_tx
+            .send(RoomRequest::Run(Box::new(
+                move |chat_room: &mut ChatRoom| {
+                    let _ = reply_tx.send(request(chat_room));
+                },
+            )))
+            .await
+            .map_err(|_| anyhow::anyhow!("room '{}' is closed", self.room))?;
+
+        reply_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("room '{}' is closed", self.room))
+    }
+
+    /// Stops the room once the requests sent before have run, the requests sent afterwards fail on every handle
+    ///
+    /// Fails if the room was closed already, so a room is closed once.
+    pub async fn close(&self) -> anyhow::Result<()> {
+        let (closed_tx, closed_rx) = oneshot::channel();
+
+        self.requests_tx
+            .send(RoomRequest::Close(closed_tx))
+            .await
+            .map_err(|_| anyhow::anyhow!("room '{}' is closed", self.room))?;
+
+        // the sender is dropped without a reply if the room was closed by an earlier request
+        match closed_rx.await {
+            Ok(()) => Ok(()),
+            Err(_) => Err(anyhow::anyhow!("room '{}' is closed", self.room)),
+        }
+    }
+}
+
+/// Runs the requests sent to the room one at a time, until every handle to the room is dropped or it is closed
+async fn serve(mut chat_room: ChatRoom, mut requests_rx: mpsc::Receiver<RoomRequest>) {
+    while let Some(request) = requests_rx.recv().await {
+        match request {
+            RoomRequest::Run(request) => request(&mut chat_room),
+            RoomRequest::Close(closed_tx) => {
+                // the requests queued after it are dropped along with the receiver, failing their callers
+                requests_rx.close();
+                drop(requests_rx);
+                let _ = closed_tx.send(());
+                debug!(room = %chat_room.metadata().name, "the room is closed");
+
+                return;
+            }
+        }
+    }
+
+    debug!(room = %chat_room.metadata().name, "the room is not used anymore");
+}
@@ -0,0 +1,799 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use comms::event::{HistoryMessage, HistoryVisibility, Reaction};
+use tracing::error;
+
+use crate::storage::MessageStore;
+
+const MAX_HISTORY_SIZE: usize = 1000;
+/// The user id the messages of a deleted account are attributed to
+pub const ANONYMIZED_USER_ID: &str = "[deleted]";
+/// Number of messages returned on each side of the requested timestamp, when fetching around a date
+const HISTORY_WINDOW_SIZE: usize = 40;
+/// Maximum number of characters of a reaction, enough for the emojis joined from several code points
+const MAX_REACTION_CHARS: usize = 8;
+/// Maximum number of distinct reactions to a single message
+const MAX_REACTIONS_PER_MESSAGE: usize = 20;
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    /// Position of the message in the room, increases monotonically
+    seq: u64,
+    message: HistoryMessage,
+    /// The users who reacted with each emoji, in the order the emojis were first given
+    ///
+    /// Only the counts are handed out, as the reactions of the message.
+    reacted_by: Vec<(String, BTreeSet<String>)>,
+}
+
+/// A part of the full history, as handed out to an export
+#[derive(Debug, Clone)]
+pub struct HistoryChunk {
+    pub messages: Vec<HistoryMessage>,
+    /// The position of the last message in the chunk, or the requested position if the chunk is empty
+    pub cursor: Option<u64>,
+    /// Whether the chunk reaches the end of the history
+    pub is_last: bool,
+}
+
+impl HistoryChunk {
+    /// Makes a chunk of up to `limit` messages read after the given position, in a history ending before `end`
+    pub fn new(messages: Vec<HistoryMessage>, after: Option<u64>, limit: usize, end: u64) -> Self {
+        let cursor = messages.last().map(|message| message.id).or(after);
+
+        HistoryChunk {
+            is_last: messages.len() < limit || cursor.is_none_or(|cursor| cursor + 1 >= end),
+            messages,
+            cursor,
+        }
+    }
+}
+
+/// A page of the messages older than a given one which a user is allowed to see, as kept in memory
+#[derive(Debug, Clone)]
+pub struct OlderMessages {
+    pub messages: Vec<HistoryMessage>,
+    /// Whether there are even older messages kept in memory which the user is allowed to see
+    pub has_more: bool,
+    /// The ids to read the rest of the page from the store within, once the messages kept in memory are used up
+    pub stored_range: Option<Range<u64>>,
+}
+
+/// The most recent messages of a room, the next id it hands out and where the history of each member starts,
+/// as read from the [MessageStore] to restore its [RoomHistory]
+#[derive(Debug, Default)]
+pub struct StoredHistory {
+    messages: Vec<HistoryMessage>,
+    next_seq: u64,
+    member_since: HashMap<String, u64>,
+}
+
+impl StoredHistory {
+    /// Reads the stored history of the room, starting over from an empty one if it can not be read
+    ///
+    /// The reads are blocking, so it should not be called on the async runtime.
+    pub fn load(store: &MessageStore, room: &str) -> Self {
+        let messages = store
+            .load_recent(room, MAX_HISTORY_SIZE)
+            .unwrap_or_else(|err| {
+                error!(room, "could not restore the history: {}", err);
+                Vec::new()
+            });
+        let next_seq = store.next_seq(room).unwrap_or_else(|err| {
+            error!(room, "could not restore the next message id: {}", err);
+            0
+        });
+        let member_since = store.load_memberships(room).unwrap_or_else(|err| {
+            error!(room, "could not restore the memberships: {}", err);
+            HashMap::new()
+        });
+
+        StoredHistory {
+            messages,
+            next_seq,
+            member_since,
+        }
+    }
+}
+
+/// [RoomHistory] keeps the most recent messages of a room in memory
+///
+/// It also remembers the position in the history at which each user first joined the room,
+/// so the [HistoryVisibility] policy of the room can be enforced relative to that position.
+///
+/// When a [MessageStore] is given, every appended message is also persisted,
+/// and the most recent stored messages can be restored with their ids, see [RoomHistory::restore].
+#[derive(Debug)]
+pub struct RoomHistory {
+    room: String,
+    entries: VecDeque<HistoryEntry>,
+    next_seq: u64,
+    member_since: HashMap<String, u64>,
+    /// The id of the last message each user has read, only kept in memory
+    last_read: HashMap<String, u64>,
+    /// Exact duplicates of a message sent by the same user within this window are dropped
+    duplicate_window: Duration,
+    store: Option<Arc<MessageStore>>,
+    /// How many messages were appended since the server started, for the metrics
+    messages_sent: u64,
+}
+
+impl RoomHistory {
+    pub fn new(room: &str, duplicate_window: Duration, store: Option<Arc<MessageStore>>) -> Self {
+        RoomHistory {
+            room: String::from(room),
+            entries: VecDeque::new(),
+            next_seq: 0,
+            member_since: HashMap::new(),
+            last_read: HashMap::new(),
+            duplicate_window,
+            store,
+            messages_sent: 0,
+        }
+    }
+
+    /// Restores the messages read from the store, before any message is appended
+    pub fn restore(&mut self, stored: StoredHistory) {
+        self.entries = stored
+            .messages
+            .into_iter()
+            .map(|message| HistoryEntry {
+                seq: message.id,
+                message,
+                reacted_by: vec![],
+            })
+            .collect();
+        // the ids go on from the last one handed out, so they are not handed out again after restarts,
+        // even when the latest messages were deleted
+        self.next_seq = self
+            .entries
+            .back()
+            .map(|entry| entry.seq + 1)
+            .unwrap_or(0)
+            .max(stored.next_seq)
+            .max(self.next_seq);
+        self.member_since = stored.member_since;
+    }
+
+    pub fn set_duplicate_window(&mut self, duplicate_window: Duration) {
+        self.duplicate_window = duplicate_window;
+    }
+
+    /// Record the current position of the history as the membership start of the user, in the store too
+    /// Does nothing if the user has already been a member of the room before
+    pub fn record_membership(&mut self, user_id: &str) {
+        if self.member_since.contains_key(user_id) {
+            return;
+        }
+
+        self.member_since
+            .insert(String::from(user_id), self.next_seq);
+        if let Some(store) = &self.store {
+            store.record_membership(&self.room, user_id, self.next_seq);
+        }
+    }
+
+    /// Forgets the membership of the user, the history they can see starts over when they join again
+    pub fn forget_membership(&mut self, user_id: &str) {
+        if self.member_since.remove(user_id).is_some() {
+            if let Some(store) = &self.store {
+                store.forget_membership(&self.room, user_id);
+            }
+        }
+        self.last_read.remove(user_id);
+    }
+
+    /// Records the message as the last one read by the user, unless they have read a later one already
+    pub fn mark_read(&mut self, user_id: &str, id: u64) -> anyhow::Result<()> {
+        if id >= self.next_seq {
+            return Err(anyhow!("the message is not in the room history"));
+        }
+
+        self.last_read
+            .entry(String::from(user_id))
+            .and_modify(|last_read| *last_read = (*last_read).max(id))
+            .or_insert(id);
+
+        Ok(())
+    }
+
+    /// Returns the id of the last message read by the user, if they marked any as read
+    pub fn last_read_by(&self, user_id: &str) -> Option<u64> {
+        self.last_read.get(user_id).copied()
+    }
+
+    /// Returns true if the same user has sent the same content, in reply to the same message, within the duplicate window
+    fn is_duplicate(&self, message: &HistoryMessage) -> bool {
+        let window_start = message
+            .timestamp
+            .saturating_sub(self.duplicate_window.as_millis() as u64);
+
+        self.entries
+            .iter()
+            .rev()
+            .take_while(|entry| entry.message.timestamp >= window_start)
+            .any(|entry| {
+                entry.message.user_id == message.user_id
+                    && entry.message.content == message.content
+                    && entry.message.reply_to == message.reply_to
+            })
+    }
+
+    /// Append a message to the history, dropping the oldest message if the history is full
+    /// The message is assigned the next id of the room
+    ///
+    /// Returns the id of the message, or None without appending if the message is a duplicate within the duplicate window
+    pub fn push(&mut self, mut message: HistoryMessage) -> Option<u64> {
+        if !self.duplicate_window.is_zero() && self.is_duplicate(&message) {
+            return None;
+        }
+
+        let seq = self.next_seq;
+        message.id = seq;
+
+        // the message is written by the store later on, a failing write does not stop the room
+        if let Some(store) = &self.store {
+            store.append(&self.room, &message);
+        }
+
+        if self.entries.len() >= MAX_HISTORY_SIZE {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(HistoryEntry {
+            seq,
+            message,
+            reacted_by: vec![],
+        });
+        self.next_seq += 1;
+        self.messages_sent += 1;
+
+        Some(seq)
+    }
+
+    /// Runs the callback once the messages appended so far are stored, right away if there is no store
+    pub fn after_stored(&self, callback: impl FnOnce() + Send + 'static) {
+        match &self.store {
+            Some(store) => store.after_writes(callback),
+            None => callback(),
+        }
+    }
+
+    /// Removes the messages beyond the latest `max_messages`, and the ones sent before the given timestamp,
+    /// from the history and from the store
+    ///
+    /// Returns the id the history now starts at, or None if none of the messages kept in memory were removed.
+    pub fn prune(
+        &mut self,
+        max_messages: Option<usize>,
+        oldest_timestamp: Option<u64>,
+    ) -> Option<u64> {
+        if let Some(store) = &self.store {
+            store.prune(&self.room, max_messages, oldest_timestamp);
+        }
+
+        let mut is_pruned = false;
+        while let Some(entry) = self.entries.front() {
+            let is_too_many =
+                max_messages.is_some_and(|max_messages| self.entries.len() > max_messages);
+            let is_too_old = oldest_timestamp
+                .is_some_and(|oldest_timestamp| entry.message.timestamp < oldest_timestamp);
+            if !is_too_many && !is_too_old {
+                break;
+            }
+
+            self.entries.pop_front();
+            is_pruned = true;
+        }
+
+        is_pruned.then(|| {
+            self.entries
+                .front()
+                .map(|entry| entry.seq)
+                .unwrap_or(self.next_seq)
+        })
+    }
+
+    /// Returns how many messages were appended since the server started
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+
+    /// Returns the time of the latest message kept in the history, if any
+    pub fn last_activity(&self) -> Option<u64> {
+        self.entries.back().map(|entry| entry.message.timestamp)
+    }
+
+    /// Returns true if the message with the given id is kept in the history
+    pub fn contains(&self, id: u64) -> bool {
+        self.entries.iter().any(|entry| entry.message.id == id)
+    }
+
+    /// Returns the position of the message with the given id in the entries, if the user is its author
+    fn authored_entry_idx(&self, id: u64, user_id: &str) -> anyhow::Result<usize> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|entry| entry.message.id == id)
+            .ok_or_else(|| anyhow!("the message is not in the room history anymore"))?;
+
+        if self.entries[idx].message.user_id != user_id {
+            return Err(anyhow!("only the author of a message can change it"));
+        }
+
+        Ok(idx)
+    }
+
+    /// Replaces the content of a message sent by the given user and marks it as edited
+    pub fn edit(&mut self, id: u64, user_id: &str, content: String) -> anyhow::Result<()> {
+        let idx = self.authored_entry_idx(id, user_id)?;
+        let message = &mut self.entries[idx].message;
+        message.content = content;
+        message.is_edited = true;
+
+        if let Some(store) = &self.store {
+            store.edit(&self.room, message);
+        }
+
+        Ok(())
+    }
+
+    /// Removes a message sent by the given user from the history
+    pub fn delete(&mut self, id: u64, user_id: &str) -> anyhow::Result<()> {
+        let idx = self.authored_entry_idx(id, user_id)?;
+        let entry = self.entries.remove(idx).unwrap();
+
+        if let Some(store) = &self.store {
+            store.delete(&self.room, entry.message.id);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the reaction of the user to a message, or takes it back if they already reacted with the same emoji
+    ///
+    /// Returns all the reactions to the message. Reactions are only kept in memory.
+    pub fn toggle_reaction(
+        &mut self,
+        id: u64,
+        user_id: &str,
+        emoji: &str,
+    ) -> anyhow::Result<Vec<Reaction>> {
+        if emoji.is_empty()
+            || emoji.chars().count() > MAX_REACTION_CHARS
+            || emoji.chars().any(char::is_whitespace)
+        {
+            return Err(anyhow!("a reaction is a single emoji"));
+        }
+
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.message.id == id)
+            .ok_or_else(|| anyhow!("the message is not in the room history anymore"))?;
+
+        match entry
+            .reacted_by
+            .iter_mut()
+            .position(|(reaction, _)| reaction == emoji)
+        {
+            Some(idx) => {
+                let user_ids = &mut entry.reacted_by[idx].1;
+                if !user_ids.remove(user_id) {
+                    user_ids.insert(String::from(user_id));
+                }
+
+                if user_ids.is_empty() {
+                    entry.reacted_by.remove(idx);
+                }
+            }
+            None if entry.reacted_by.len() >= MAX_REACTIONS_PER_MESSAGE => {
+                return Err(anyhow!("the message has too many different reactions"));
+            }
+            None => entry
+                .reacted_by
+                .push((String::from(emoji), BTreeSet::from([String::from(user_id)]))),
+        }
+
+        entry.message.reactions = entry
+            .reacted_by
+            .iter()
+            .map(|(emoji, user_ids)| Reaction {
+                emoji: emoji.clone(),
+                count: user_ids.len(),
+            })
+            .collect();
+
+        Ok(entry.message.reactions.clone())
+    }
+
+    /// Returns the messages the given user is allowed to see according to the visibility policy
+    ///
+    /// If `around` is given, only a window of messages around the first message sent at or after
+    /// the given timestamp is returned. Otherwise, if `limit` is given, only the last `limit` messages are returned.
+    pub fn visible_to(
+        &self,
+        user_id: &str,
+        visibility: &HistoryVisibility,
+        around: Option<u64>,
+        limit: Option<usize>,
+    ) -> Vec<HistoryMessage> {
+        let visible_from = self.visible_from(user_id, visibility);
+
+        let visible = self
+            .entries
+            .iter()
+            .filter(|entry| entry.seq >= visible_from)
+            .map(|entry| &entry.message)
+            .collect::<Vec<_>>();
+
+        let (start, end) = match around {
+            None => (
+                limit
+                    .map(|limit| visible.len().saturating_sub(limit))
+                    .unwrap_or(0),
+                visible.len(),
+            ),
+            Some(timestamp) => {
+                let idx = visible
+                    .iter()
+                    .position(|message| message.timestamp >= timestamp)
+                    .unwrap_or(visible.len());
+
+                (
+                    idx.saturating_sub(HISTORY_WINDOW_SIZE),
+                    visible.len().min(idx + HISTORY_WINDOW_SIZE),
+                )
+            }
+        };
+
+        visible[start..end]
+            .iter()
+            .map(|&message| message.clone())
+            .collect()
+    }
+
+    /// Returns up to `limit` of the messages the given user is allowed to see, older than the message with the given id
+    ///
+    /// When the messages kept in memory run out before the page is full, the older messages the user
+    /// is allowed to see are left to be read from the store, if there is one.
+    pub fn visible_before(
+        &self,
+        user_id: &str,
+        visibility: &HistoryVisibility,
+        before: u64,
+        limit: usize,
+    ) -> OlderMessages {
+        let visible_from = self.visible_from(user_id, visibility);
+
+        let older = self
+            .entries
+            .iter()
+            .filter(|entry| entry.seq >= visible_from && entry.seq < before)
+            .map(|entry| &entry.message)
+            .collect::<Vec<_>>();
+        let start = older.len().saturating_sub(limit);
+        let messages = older[start..]
+            .iter()
+            .map(|&message| message.clone())
+            .collect::<Vec<_>>();
+
+        let oldest_kept = self
+            .entries
+            .front()
+            .map(|entry| entry.seq)
+            .unwrap_or(self.next_seq)
+            .min(before);
+        let stored_range =
+            (self.store.is_some() && messages.len() < limit && visible_from < oldest_kept)
+                .then_some(visible_from..oldest_kept);
+
+        OlderMessages {
+            messages,
+            has_more: start > 0,
+            stored_range,
+        }
+    }
+
+    /// Returns the position of the first message the user is allowed to see
+    fn visible_from(&self, user_id: &str, visibility: &HistoryVisibility) -> u64 {
+        let member_since = self
+            .member_since
+            .get(user_id)
+            .copied()
+            .unwrap_or(self.next_seq);
+
+        match visibility {
+            HistoryVisibility::None => member_since,
+            HistoryVisibility::Last { count } => member_since.saturating_sub(*count as u64),
+            HistoryVisibility::All => 0,
+        }
+    }
+
+    /// Returns the timestamp of the first message the user is allowed to see, for searching the stored messages
+    ///
+    /// The stored messages older than the ones kept in memory are only visible when the full history is.
+    pub fn visible_since(&self, user_id: &str, visibility: &HistoryVisibility) -> u64 {
+        if let HistoryVisibility::All = visibility {
+            return 0;
+        }
+
+        let visible_from = self.visible_from(user_id, visibility);
+
+        // nothing is visible yet, not even the latest stored message
+        self.entries
+            .iter()
+            .find(|entry| entry.seq >= visible_from)
+            .map(|entry| entry.message.timestamp)
+            .unwrap_or(i64::MAX as u64)
+    }
+
+    /// Returns the ids of the messages the given user is allowed to see, including the ones only kept in the store
+    pub fn visible_ids(&self, user_id: &str, visibility: &HistoryVisibility) -> Range<u64> {
+        self.visible_from(user_id, visibility)..self.next_seq
+    }
+
+    /// Returns up to `limit` of the messages the given user is allowed to see, recorded after the given position
+    ///
+    /// Messages which were dropped from the history since the position was handed out are skipped.
+    pub fn chunk_after(
+        &self,
+        user_id: &str,
+        visibility: &HistoryVisibility,
+        after: Option<u64>,
+        limit: usize,
+    ) -> HistoryChunk {
+        let visible_from = self.visible_from(user_id, visibility);
+        let messages = self
+            .entries
+            .iter()
+            .filter(|entry| entry.seq >= visible_from)
+            .filter(|entry| after.map(|after| entry.seq > after).unwrap_or(true))
+            .take(limit)
+            .map(|entry| entry.message.clone())
+            .collect();
+
+        HistoryChunk::new(messages, after, limit, self.next_seq)
+    }
+
+    /// Returns all the messages of the given user kept in the history, regardless of the visibility policy
+    pub fn messages_of(&self, user_id: &str) -> Vec<HistoryMessage> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.message.user_id == user_id)
+            .map(|entry| entry.message.clone())
+            .collect()
+    }
+
+    /// Attributes the messages of the given user kept in memory to an anonymous user and forgets their membership
+    ///
+    /// The stored messages of the user are anonymized in every room at once, see [MessageStore::reassign_user].
+    pub fn anonymize_user(&mut self, user_id: &str) {
+        for entry in self.entries.iter_mut() {
+            if entry.message.user_id == user_id {
+                entry.message.user_id = String::from(ANONYMIZED_USER_ID);
+            }
+        }
+
+        self.forget_membership(user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(user_id: &str, content: &str) -> HistoryMessage {
+        HistoryMessage {
+            id: 0,
+            user_id: String::from(user_id),
+            content: String::from(content),
+            timestamp: 1_000,
+            reply_to: None,
+            is_edited: false,
+            reactions: vec![],
+        }
+    }
+
+    fn ids(messages: &[HistoryMessage]) -> Vec<u64> {
+        messages.iter().map(|message| message.id).collect()
+    }
+
+    fn history_with_member_since(user_id: &str, sent_before: usize) -> RoomHistory {
+        let mut history = RoomHistory::new("general", Duration::ZERO, None);
+        for idx in 0..sent_before {
+            history.push(message("bob", &idx.to_string()));
+        }
+        history.record_membership(user_id);
+        history.push(message("bob", "after"));
+        history
+    }
+
+    /// Makes the history of the room as it is restored from the store after a restart
+    fn restored_history(store: &Arc<MessageStore>) -> RoomHistory {
+        let mut history = RoomHistory::new("general", Duration::ZERO, Some(Arc::clone(store)));
+        history.restore(StoredHistory::load(store, "general"));
+        history
+    }
+
+    #[test]
+    fn test_the_same_reply_to_different_messages_is_not_a_duplicate() {
+        let mut history = RoomHistory::new("general", Duration::from_secs(10), None);
+        history.push(message("alice", "first"));
+        history.push(message("alice", "second"));
+
+        let reply = |reply_to| HistoryMessage {
+            reply_to: Some(reply_to),
+            ..message("bob", "+1")
+        };
+        assert_eq!(history.push(reply(0)), Some(2));
+        assert_eq!(history.push(reply(1)), Some(3));
+
+        // the same reply to the same message is still dropped
+        assert_eq!(history.push(reply(1)), None);
+    }
+
+    #[test]
+    fn test_members_see_the_history_the_visibility_allows() {
+        let history = history_with_member_since("alice", 5);
+
+        let visible = |visibility| ids(&history.visible_to("alice", &visibility, None, None));
+        assert_eq!(visible(HistoryVisibility::None), vec![5]);
+        assert_eq!(visible(HistoryVisibility::Last { count: 2 }), vec![3, 4, 5]);
+        assert_eq!(
+            visible(HistoryVisibility::Last { count: 10 }),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(visible(HistoryVisibility::All), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_users_who_are_not_members_see_the_history_as_if_they_joined_now() {
+        let mut history = history_with_member_since("alice", 5);
+
+        let last = HistoryVisibility::Last { count: 2 };
+        assert_eq!(
+            ids(&history.visible_to("mallory", &last, None, None)),
+            vec![4, 5]
+        );
+        assert!(history
+            .visible_to("mallory", &HistoryVisibility::None, None, None)
+            .is_empty());
+        assert_eq!(
+            history
+                .visible_to("mallory", &HistoryVisibility::All, None, None)
+                .len(),
+            6
+        );
+
+        // the history a member sees starts over when they join again
+        history.forget_membership("alice");
+        history.push(message("bob", "gone"));
+        history.record_membership("alice");
+        assert!(history
+            .visible_to("alice", &HistoryVisibility::None, None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_the_visible_history_is_limited_around_a_timestamp_or_to_the_last_messages() {
+        let history = history_with_member_since("alice", 5);
+
+        let visible = history.visible_to("alice", &HistoryVisibility::All, None, Some(2));
+        assert_eq!(ids(&visible), vec![4, 5]);
+
+        // the messages the user is not allowed to see are left out of the window
+        let visible = history.visible_to(
+            "alice",
+            &HistoryVisibility::Last { count: 1 },
+            Some(0),
+            None,
+        );
+        assert_eq!(ids(&visible), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_older_messages_are_read_from_the_store_once_the_memory_runs_out() {
+        let path = std::env::temp_dir().join(format!("chat-history-{}.sqlite3", nanoid::nanoid!()));
+        let store = Arc::new(MessageStore::open(&path).unwrap());
+        let mut history = RoomHistory::new("general", Duration::ZERO, Some(Arc::clone(&store)));
+        history.record_membership("alice");
+        for idx in 0..MAX_HISTORY_SIZE + 5 {
+            history.push(message("bob", &idx.to_string()));
+        }
+        history.record_membership("carol");
+        store.flush();
+
+        let older = history.visible_before("alice", &HistoryVisibility::None, 10, 8);
+        assert_eq!(ids(&older.messages), vec![5, 6, 7, 8, 9]);
+        assert!(!older.has_more);
+        assert_eq!(older.stored_range, Some(0..5));
+
+        let (stored, has_more) = store.load_before("general", 0..5, 3).unwrap();
+        assert_eq!(ids(&stored), vec![2, 3, 4]);
+        assert!(has_more);
+
+        // the members who joined later are not handed the messages sent before, stored or not
+        let older = history.visible_before("carol", &HistoryVisibility::Last { count: 3 }, 10, 8);
+        assert!(older.messages.is_empty());
+        assert_eq!(older.stored_range, None);
+    }
+
+    #[test]
+    fn test_the_ids_of_the_deleted_messages_are_not_handed_out_again_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("chat-history-{}.sqlite3", nanoid::nanoid!()));
+        let store = Arc::new(MessageStore::open(&path).unwrap());
+        let mut history = RoomHistory::new("general", Duration::ZERO, Some(Arc::clone(&store)));
+        for idx in 0..3 {
+            history.push(message("bob", &idx.to_string()));
+        }
+        history.delete(2, "bob").unwrap();
+        store.flush();
+
+        let mut history = restored_history(&store);
+        assert_eq!(history.push(message("bob", "3")), Some(3));
+
+        // nor when every message of the room is gone
+        history.delete(0, "bob").unwrap();
+        history.delete(1, "bob").unwrap();
+        history.delete(3, "bob").unwrap();
+        store.flush();
+
+        let mut history = restored_history(&store);
+        assert_eq!(history.push(message("bob", "4")), Some(4));
+    }
+
+    #[test]
+    fn test_the_members_see_the_history_they_could_see_before_a_restart() {
+        let path = std::env::temp_dir().join(format!("chat-history-{}.sqlite3", nanoid::nanoid!()));
+        let store = Arc::new(MessageStore::open(&path).unwrap());
+        let mut history = RoomHistory::new("general", Duration::ZERO, Some(Arc::clone(&store)));
+        history.push(message("bob", "0"));
+        history.record_membership("alice");
+        history.record_membership("carol");
+        history.push(message("bob", "1"));
+        history.forget_membership("carol");
+        store.flush();
+
+        let history = restored_history(&store);
+        let visible = history.visible_to("alice", &HistoryVisibility::None, None, None);
+        assert_eq!(ids(&visible), vec![1]);
+
+        // the members who left start over
+        let visible = history.visible_to("carol", &HistoryVisibility::None, None, None);
+        assert!(visible.is_empty());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_export_is_limited_to_the_visible_history() {
+        let mut history = RoomHistory::new("general", Duration::ZERO, None);
+        for idx in 0..5 {
+            history.push(message("bob", &idx.to_string()));
+        }
+        history.record_membership("alice");
+        history.push(message("bob", "5"));
+
+        let last = HistoryVisibility::Last { count: 2 };
+        assert_eq!(history.visible_ids("alice", &last), 3..6);
+
+        let chunk = history.chunk_after("alice", &last, None, 2);
+        assert_eq!(ids(&chunk.messages), vec![3, 4]);
+        assert!(!chunk.is_last);
+
+        let chunk = history.chunk_after("alice", &last, chunk.cursor, 2);
+        assert_eq!(ids(&chunk.messages), vec![5]);
+        assert!(chunk.is_last);
+
+        // nothing sent before joining is exported when none of the history is visible
+        let chunk = history.chunk_after("alice", &HistoryVisibility::None, Some(0), 10);
+        assert_eq!(ids(&chunk.messages), vec![5]);
+    }
+}
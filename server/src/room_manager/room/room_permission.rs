@@ -0,0 +1,30 @@
+use comms::event::RoomRole;
+
+/// [RoomPermission] is what a user has to be allowed to do, before a command changing a room is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomPermission {
+    SetTopic,
+    Moderate,
+    ManageRoles,
+    DeleteRoom,
+}
+
+impl RoomPermission {
+    /// The least role allowed to do it, the roles after it are allowed as well
+    pub fn required_role(&self) -> RoomRole {
+        match self {
+            RoomPermission::SetTopic | RoomPermission::Moderate => RoomRole::Moderator,
+            RoomPermission::ManageRoles | RoomPermission::DeleteRoom => RoomRole::Owner,
+        }
+    }
+
+    /// Describes the permission, as in `only the owner of room 'rust' can delete it`
+    pub fn describe(&self) -> &'static str {
+        match self {
+            RoomPermission::SetTopic => "change its topic",
+            RoomPermission::Moderate => "moderate its users",
+            RoomPermission::ManageRoles => "change the roles of its users",
+            RoomPermission::DeleteRoom => "delete it",
+        }
+    }
+}
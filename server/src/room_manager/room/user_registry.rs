@@ -1,6 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
-use super::user_session_handle::UserSessionHandle;
+use super::SessionAndUserId;
 
 #[derive(Debug)]
 pub struct UserRegistry {
@@ -20,14 +20,11 @@ impl UserRegistry {
     }
 
     /// Add a user to the room, returns true if the user is a new user
-    pub fn insert(&mut self, user_session_handle: &UserSessionHandle) -> bool {
-        let user_id = String::from(user_session_handle.user_id());
-        let session_id = String::from(user_session_handle.session_id());
+    pub fn insert(&mut self, session_and_user_id: &SessionAndUserId) -> bool {
+        let user_id = session_and_user_id.user_id.clone();
+        let session_id = session_and_user_id.session_id.clone();
 
-        let sessions = self
-            .user_id_to_sessions
-            .entry(user_id.clone())
-            .or_insert_with(HashSet::new);
+        let sessions = self.user_id_to_sessions.entry(user_id.clone()).or_default();
 
         sessions.insert(session_id);
 
@@ -42,9 +39,9 @@ impl UserRegistry {
 
     /// Removes a given session from the participant list, returns true if the user is no longer in the room
     /// Does nothing and returns false if the user does not exist
-    pub fn remove(&mut self, user_session_handle: &UserSessionHandle) -> bool {
-        let user_id = String::from(user_session_handle.user_id());
-        let session_id = String::from(user_session_handle.session_id());
+    pub fn remove(&mut self, session_and_user_id: &SessionAndUserId) -> bool {
+        let user_id = session_and_user_id.user_id.clone();
+        let session_id = session_and_user_id.session_id.clone();
 
         let to_remove = self.user_id_to_sessions.get_mut(&user_id);
 
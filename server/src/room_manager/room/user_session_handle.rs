@@ -1,6 +1,6 @@
-use anyhow::Context;
-use comms::event;
-use tokio::sync::broadcast;
+use crate::storage::Attachment;
+
+use super::RoomHandle;
 
 #[derive(Debug, Clone)]
 pub struct SessionAndUserId {
@@ -13,52 +13,120 @@ pub struct SessionAndUserId {
 /// send messages to a specific room.
 ///
 /// It is created when a user joins a room and is handed out to the user.
+/// Its requests are run by the task of the room, in the order the users of the room sent them.
 pub struct UserSessionHandle {
-    /// The name of the room which is associated with this handle
-    room: String,
-    /// The channel to use for sending events to the all users of the room
-    broadcast_tx: broadcast::Sender<event::Event>,
+    /// The room which is associated with this handle
+    room_handle: RoomHandle,
     /// The session and user id associated with this handle
     session_and_user_id: SessionAndUserId,
 }
 
 impl UserSessionHandle {
-    pub(super) fn new(
-        room: String,
-        broadcast_tx: broadcast::Sender<event::Event>,
+    pub(in crate::room_manager) fn new(
+        room_handle: RoomHandle,
         session_and_user_id: SessionAndUserId,
     ) -> Self {
         UserSessionHandle {
-            room,
-            broadcast_tx,
+            room_handle,
             session_and_user_id,
         }
     }
 
-    pub fn room(&self) -> &str {
-        &self.room
+    /// Send a message to the room and record it to the room history, optionally as a reply to another message
+    ///
+    /// Exact duplicates of a recently sent message are dropped, to guard against clients retrying.
+    /// A reply to a message which is not in the room history anymore is sent as a regular message.
+    /// Fails if the user is muted in the room.
+    pub async fn send_message(&self, content: String, reply_to: Option<u64>) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.send_message(&user_id, content, reply_to))
+            .await?
+    }
+
+    /// Fails if the user is muted in the room
+    pub async fn check_not_muted(&self) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.check_not_muted(&user_id))
+            .await?
+    }
+
+    /// Tell the users of the room about a file the user has shared with it
+    pub async fn share_file(&self, attachment: Attachment) -> anyhow::Result<()> {
+        self.room_handle
+            .call(move |chat_room| chat_room.share_file(attachment))
+            .await?
+    }
+
+    /// Edit a message the user has sent to the room and broadcast its new content
+    ///
+    /// Fails if the message is not in the room history anymore, or if the user is not its author
+    pub async fn edit_message(&self, id: u64, content: String) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.edit_message(&user_id, id, content))
+            .await?
+    }
+
+    /// Delete a message the user has sent to the room and broadcast its deletion
+    ///
+    /// Fails if the message is not in the room history anymore, or if the user is not its author
+    pub async fn delete_message(&self, id: u64) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.delete_message(&user_id, id))
+            .await?
     }
 
-    pub fn session_id(&self) -> &str {
-        &self.session_and_user_id.session_id
+    /// Mark the messages of the room as read by the user, up to the given message
+    pub async fn mark_read(&self, id: u64) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.mark_read(&user_id, id))
+            .await?
     }
 
-    pub fn user_id(&self) -> &str {
-        &self.session_and_user_id.user_id
+    /// Returns the id of the last message of the room read by the user, if they marked any as read
+    pub async fn last_read(&self) -> anyhow::Result<Option<u64>> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.last_read(&user_id))
+            .await
     }
 
-    /// Send a message to the room
-    pub fn send_message(&self, content: String) -> anyhow::Result<()> {
-        self.broadcast_tx
-            .send(comms::event::Event::UserMessage(
-                event::UserMessageBroadcastEvent {
-                    room: self.room.clone(),
-                    user_id: self.session_and_user_id.user_id.clone(),
-                    content,
-                },
-            ))
-            .context("could not write to the broadcast channel")?;
-
-        Ok(())
+    /// React to a message of the room, or take the reaction back, and broadcast the reactions to the message
+    ///
+    /// Fails if the message is not in the room history anymore, or if the reaction is not a single emoji
+    pub async fn react_to_message(&self, id: u64, emoji: String) -> anyhow::Result<()> {
+        let user_id = self.session_and_user_id.user_id.clone();
+
+        self.room_handle
+            .call(move |chat_room| chat_room.react_to_message(&user_id, id, &emoji))
+            .await?
+    }
+
+    /// Leaves the room, which tells its users once the last session of the user has left
+    pub async fn leave(self) -> anyhow::Result<()> {
+        let session_and_user_id = self.session_and_user_id;
+
+        self.room_handle
+            .call(move |chat_room| chat_room.leave(&session_and_user_id))
+            .await
+    }
+
+    /// Quits the room at the request of the user, who is no longer a member of it once their last session has left
+    pub async fn quit(self) -> anyhow::Result<()> {
+        let session_and_user_id = self.session_and_user_id;
+
+        self.room_handle
+            .call(move |chat_room| chat_room.quit(&session_and_user_id))
+            .await
     }
 }
@@ -1,36 +1,557 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
-use comms::event::Event;
-use tokio::sync::{broadcast, Mutex};
+use anyhow::Context;
+use comms::{
+    admin::AdminRoom,
+    event::{
+        self, DirectoryRoom, Event, ExportedMessage, HistoryMessage, HistoryVisibility, RoomRole,
+    },
+};
+use tokio::sync::broadcast;
+use tracing::error;
 
-use super::room::{ChatRoom, ChatRoomMetadata, SessionAndUserId, UserSessionHandle};
+use crate::{
+    clock::now_millis,
+    command_error::CommandError,
+    storage::{Attachment, AttachmentStore, BanStore, MessageStore, SearchPage},
+};
 
-pub type RoomJoinResult = (broadcast::Receiver<Event>, UserSessionHandle, Vec<String>);
+use super::message_filter::MessageFilter;
+use super::room::{
+    ChatRoom, ChatRoomMetadata, HistoryChunk, RetentionPolicy, RoomHandle, RoomPermission,
+    RoomVisibility, SessionAndUserId, StoredHistory, UserSessionHandle, ANONYMIZED_USER_ID,
+};
 
+/// The receiver of the room events, the handle to interact with the room, the users of the room and the role of the user
+pub type RoomJoinResult = (
+    broadcast::Receiver<Event>,
+    UserSessionHandle,
+    Vec<String>,
+    RoomRole,
+);
+
+/// Maximum number of rooms, including the ones created by the users
+const MAX_ROOMS: usize = 256;
+const ROOM_NAME_LENGTH: RangeInclusive<usize> = 2..=32;
+const ROOM_LIST_CHANNEL_CAPACITY: usize = 100;
+const MAX_TOPIC_CHARS: usize = 200;
+/// The longest a user can be muted for, a ban is the way to keep them quiet for longer
+const MAX_MUTE_DURATION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// How often the messages the rooms do not keep anymore are pruned
+const RETENTION_SWEEP_PERIOD: Duration = Duration::from_secs(60);
+
+/// Returns why a room can not be created with the given name, if it can not be
+fn validate_room_name(name: &str) -> Option<String> {
+    let is_valid = ROOM_NAME_LENGTH.contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+    (!is_valid).then(|| {
+        format!(
+            "room names are {} to {} lowercase letters, digits, dashes or underscores",
+            ROOM_NAME_LENGTH.start(),
+            ROOM_NAME_LENGTH.end()
+        )
+    })
+}
+
+/// Returns when a mute of the given number of seconds starting now ends, in milliseconds
+fn mute_deadline(seconds: u64) -> u64 {
+    now_millis().saturating_add(seconds.saturating_mul(1000))
+}
+
+/// [RoomListEvent] is a room being created or deleted, told to the users who can see the room
 #[derive(Debug, Clone)]
+pub struct RoomListEvent {
+    pub event: Event,
+    /// The users who can see the private room, everyone sees a public one
+    audience: Option<Arc<HashSet<String>>>,
+}
+
+impl RoomListEvent {
+    fn public(event: Event) -> Self {
+        RoomListEvent {
+            event,
+            audience: None,
+        }
+    }
+
+    pub fn is_visible_to(&self, user_id: &str) -> bool {
+        self.audience
+            .as_ref()
+            .is_none_or(|audience| audience.contains(user_id))
+    }
+}
+
+/// [RoomManager] holds the rooms of the server, the ones defined at startup and the ones created by the users
+///
+/// The changes to the room list are broadcast to every session subscribed with [RoomManager::subscribe_room_list].
+/// Each room is owned by its own task, the manager only looks up their handles by name.
+#[derive(Debug)]
 pub struct RoomManager {
-    chat_rooms: HashMap<String, Arc<Mutex<ChatRoom>>>,
-    chat_room_metadatas: Vec<ChatRoomMetadata>,
+    chat_rooms: RwLock<HashMap<String, RoomHandle>>,
+    /// The metadata of the rooms, in the order they were defined or created
+    chat_room_metadatas: RwLock<Vec<ChatRoomMetadata>>,
+    /// The duplicate suppression window of the rooms created from now on
+    duplicate_suppression_window: RwLock<Duration>,
+    /// The number of characters the messages of every room can be at most
+    max_message_chars: RwLock<usize>,
+    /// The filter of the messages of the rooms created from now on
+    message_filter: RwLock<Arc<dyn MessageFilter>>,
+    message_store: Option<Arc<MessageStore>>,
+    ban_store: Option<Arc<BanStore>>,
+    attachment_store: Option<Arc<AttachmentStore>>,
+    room_list_tx: broadcast::Sender<RoomListEvent>,
+}
+
+/// Creates a room, restoring its history and its bans from the given stores, and hands it over to its own task
+pub(super) fn spawn_chat_room(
+    metadata: ChatRoomMetadata,
+    duplicate_suppression_window: Duration,
+    message_filter: Arc<dyn MessageFilter>,
+    message_store: Option<Arc<MessageStore>>,
+    ban_store: Option<Arc<BanStore>>,
+) -> RoomHandle {
+    let chat_room = ChatRoom::new(
+        metadata,
+        duplicate_suppression_window,
+        message_filter,
+        message_store.clone(),
+    );
+
+    if message_store.is_none() && ban_store.is_none() {
+        return RoomHandle::spawn(chat_room);
+    }
+
+    let room_name = chat_room.metadata().name.clone();
+    RoomHandle::spawn_restored(
+        chat_room,
+        move || {
+            let history = message_store.map(|store| StoredHistory::load(&store, &room_name));
+            let banned_user_ids = ban_store.map(|store| store.banned_user_ids(&room_name));
+
+            (history, banned_user_ids)
+        },
+        |chat_room, (history, banned_user_ids)| {
+            if let Some(history) = history {
+                chat_room.restore_history(history);
+            }
+
+            match banned_user_ids {
+                Some(Ok(user_ids)) => user_ids.iter().for_each(|user_id| chat_room.ban(user_id)),
+                Some(Err(err)) => error!(
+                    room = %chat_room.metadata().name,
+                    "could not restore the bans: {}",
+                    err
+                ),
+                None => (),
+            }
+        },
+    )
 }
 
 impl RoomManager {
-    pub(super) fn new(chat_rooms: Vec<(ChatRoomMetadata, Arc<Mutex<ChatRoom>>)>) -> RoomManager {
+    pub(super) fn new(
+        chat_rooms: Vec<(ChatRoomMetadata, RoomHandle)>,
+        duplicate_suppression_window: Duration,
+        max_message_chars: usize,
+        message_filter: Arc<dyn MessageFilter>,
+        message_store: Option<Arc<MessageStore>>,
+        ban_store: Option<Arc<BanStore>>,
+        attachment_store: Option<Arc<AttachmentStore>>,
+    ) -> RoomManager {
         let chat_room_metadatas = chat_rooms
             .iter()
             .map(|(metadata, _)| metadata.clone())
             .collect();
+        let (room_list_tx, _) = broadcast::channel(ROOM_LIST_CHANNEL_CAPACITY);
 
         RoomManager {
-            chat_room_metadatas,
-            chat_rooms: chat_rooms
-                .into_iter()
-                .map(|(metadata, chat_room)| (metadata.name.clone(), chat_room))
-                .collect(),
+            chat_room_metadatas: RwLock::new(chat_room_metadatas),
+            chat_rooms: RwLock::new(
+                chat_rooms
+                    .into_iter()
+                    .map(|(metadata, chat_room)| (metadata.name.clone(), chat_room))
+                    .collect(),
+            ),
+            duplicate_suppression_window: RwLock::new(duplicate_suppression_window),
+            max_message_chars: RwLock::new(max_message_chars),
+            message_filter: RwLock::new(message_filter),
+            message_store,
+            ban_store,
+            attachment_store,
+            room_list_tx,
         }
     }
 
-    pub fn chat_room_metadatas(&self) -> &Vec<ChatRoomMetadata> {
-        &self.chat_room_metadatas
+    pub fn chat_room_metadatas(&self) -> Vec<ChatRoomMetadata> {
+        self.chat_room_metadatas.read().unwrap().clone()
+    }
+
+    /// Subscribes to the rooms being created and deleted
+    pub fn subscribe_room_list(&self) -> broadcast::Receiver<RoomListEvent> {
+        self.room_list_tx.subscribe()
+    }
+
+    /// Subscribes to the events of a room without joining it, such as to forward its messages elsewhere
+    pub async fn subscribe_room(
+        &self,
+        room_name: &str,
+    ) -> anyhow::Result<broadcast::Receiver<Event>> {
+        self.get_room(room_name)?
+            .call(|room| room.subscribe())
+            .await
+    }
+
+    /// Returns how many messages were sent to each room since the server started, by room name
+    pub async fn messages_sent(&self) -> Vec<(String, u64)> {
+        let mut chat_rooms = self
+            .chat_rooms
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(room_name, room)| (room_name.clone(), room.clone()))
+            .collect::<Vec<_>>();
+        chat_rooms.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut messages_sent = Vec::with_capacity(chat_rooms.len());
+
+        for (room_name, room) in chat_rooms {
+            // a room deleted meanwhile has no messages to count anymore
+            if let Ok(sent) = room.call(|room| room.messages_sent()).await {
+                messages_sent.push((room_name, sent));
+            }
+        }
+
+        messages_sent
+    }
+
+    /// Lists a page of the public rooms by name, after the given room, along with the cursor of the next page if there is one
+    pub async fn room_directory(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> (Vec<DirectoryRoom>, Option<String>) {
+        let mut metadatas = self
+            .chat_room_metadatas
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|metadata| metadata.visibility == RoomVisibility::Public)
+            .filter(|metadata| after.is_none_or(|after| metadata.name.as_str() > after))
+            .cloned()
+            .collect::<Vec<_>>();
+        metadatas.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let cursor = (metadatas.len() > limit).then(|| metadatas[limit - 1].name.clone());
+        metadatas.truncate(limit);
+        let mut rooms = Vec::with_capacity(metadatas.len());
+
+        for metadata in metadatas {
+            let Ok(room) = self.get_room(&metadata.name) else {
+                continue;
+            };
+
+            // a room deleted meanwhile is left out of the page
+            if let Ok((member_count, last_activity)) = room
+                .call(|room| (room.get_unique_user_ids().len(), room.last_activity()))
+                .await
+            {
+                rooms.push(DirectoryRoom {
+                    name: metadata.name,
+                    description: metadata.description,
+                    member_count,
+                    last_activity,
+                });
+            }
+        }
+
+        (rooms, cursor)
+    }
+
+    /// Describes a room to the admin console, whether it is private or not
+    pub async fn inspect_room(&self, room_name: &str) -> anyhow::Result<AdminRoom> {
+        self.get_room(room_name)?
+            .call(|room| {
+                let metadata = room.metadata();
+                let mut users = room.get_unique_user_ids();
+                users.sort();
+
+                AdminRoom {
+                    name: metadata.name.clone(),
+                    description: metadata.description.clone(),
+                    is_private: metadata.visibility == RoomVisibility::Private,
+                    users,
+                    roles: room.roles(),
+                    messages_sent: room.messages_sent(),
+                }
+            })
+            .await
+    }
+
+    fn get_room(&self, room_name: &str) -> anyhow::Result<RoomHandle> {
+        self.chat_rooms
+            .read()
+            .unwrap()
+            .get(room_name)
+            .cloned()
+            .ok_or_else(|| CommandError::RoomNotFound(String::from(room_name)).into())
+    }
+
+    /// Creates a public room on behalf of a user, who is the only one allowed to delete it
+    pub fn create_room(
+        &self,
+        room_name: &str,
+        description: &str,
+        created_by: &str,
+    ) -> anyhow::Result<()> {
+        if let Some(reason) = validate_room_name(room_name) {
+            return Err(anyhow::anyhow!(reason));
+        }
+
+        let metadata = ChatRoomMetadata {
+            name: String::from(room_name),
+            description: String::from(description),
+            visibility: RoomVisibility::Public,
+            history_visibility: HistoryVisibility::default(),
+            input_template: None,
+            history_export: false,
+            created_by: Some(String::from(created_by)),
+            moderators: vec![],
+            retention: RetentionPolicy::default(),
+        };
+
+        {
+            let mut chat_rooms = self.chat_rooms.write().unwrap();
+
+            if chat_rooms.contains_key(room_name) {
+                return Err(anyhow::anyhow!("room '{}' already exists", room_name));
+            }
+
+            if chat_rooms.len() >= MAX_ROOMS {
+                return Err(anyhow::anyhow!("the server can not hold more rooms"));
+            }
+
+            self.insert_room(&mut chat_rooms, metadata.clone());
+        }
+
+        let _ = self
+            .room_list_tx
+            .send(RoomListEvent::public(Event::RoomCreated(
+                event::RoomCreatedBroadcastEvent {
+                    room: metadata.to_room_detail(),
+                    created_by: String::from(created_by),
+                },
+            )));
+
+        Ok(())
+    }
+
+    /// Adds the rooms defined in the configuration which do not exist yet, returning the names of the added ones
+    ///
+    /// The rooms which exist already are left as they are, along with their users.
+    pub fn define_rooms(&self, metadatas: &[ChatRoomMetadata]) -> Vec<String> {
+        let mut added_rooms = vec![];
+
+        for metadata in metadatas {
+            {
+                let mut chat_rooms = self.chat_rooms.write().unwrap();
+                if chat_rooms.contains_key(&metadata.name) {
+                    continue;
+                }
+
+                self.insert_room(&mut chat_rooms, metadata.clone());
+            }
+
+            added_rooms.push(metadata.name.clone());
+            // the private rooms are not listed to the users
+            if metadata.visibility == RoomVisibility::Public {
+                let _ = self
+                    .room_list_tx
+                    .send(RoomListEvent::public(Event::RoomCreated(
+                        event::RoomCreatedBroadcastEvent {
+                            room: metadata.to_room_detail(),
+                            created_by: metadata.created_by.clone().unwrap_or_default(),
+                        },
+                    )));
+            }
+        }
+
+        added_rooms
+    }
+
+    fn insert_room(
+        &self,
+        chat_rooms: &mut HashMap<String, RoomHandle>,
+        metadata: ChatRoomMetadata,
+    ) {
+        let chat_room = spawn_chat_room(
+            metadata.clone(),
+            *self.duplicate_suppression_window.read().unwrap(),
+            Arc::clone(&self.message_filter.read().unwrap()),
+            self.message_store.clone(),
+            self.ban_store.clone(),
+        );
+        chat_rooms.insert(metadata.name.clone(), chat_room);
+        self.chat_room_metadatas.write().unwrap().push(metadata);
+    }
+
+    /// Prunes the messages of the rooms according to their retention policy, periodically
+    pub fn spawn_retention_sweep(self: &Arc<Self>) {
+        let room_manager = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RETENTION_SWEEP_PERIOD);
+
+            loop {
+                interval.tick().await;
+
+                let chat_rooms = room_manager
+                    .chat_rooms
+                    .read()
+                    .unwrap()
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for room in chat_rooms {
+                    let _ = room.call(|room| room.prune_history()).await;
+                }
+            }
+        });
+    }
+
+    /// Changes the duplicate suppression window of every room, including the ones created from now on
+    pub async fn set_duplicate_suppression_window(&self, window: Duration) {
+        *self.duplicate_suppression_window.write().unwrap() = window;
+        let chat_rooms = self
+            .chat_rooms
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for room in chat_rooms {
+            let _ = room
+                .call(move |room| room.set_duplicate_window(window))
+                .await;
+        }
+    }
+
+    /// The number of characters the messages sent and edited in the rooms can be at most
+    pub fn max_message_chars(&self) -> usize {
+        *self.max_message_chars.read().unwrap()
+    }
+
+    /// Changes the number of characters the messages of every room can be at most, the messages already sent are kept
+    pub fn set_max_message_chars(&self, max_message_chars: usize) {
+        *self.max_message_chars.write().unwrap() = max_message_chars;
+    }
+
+    /// Fails if the content of a message is longer than the rooms accept
+    pub fn check_message_length(&self, content: &str) -> anyhow::Result<()> {
+        let max_message_chars = self.max_message_chars();
+
+        if content.chars().count() > max_message_chars {
+            return Err(CommandError::MessageTooLong(max_message_chars).into());
+        }
+
+        Ok(())
+    }
+
+    /// Changes the filter of the messages of every room, including the ones created from now on
+    pub async fn set_message_filter(&self, message_filter: Arc<dyn MessageFilter>) {
+        *self.message_filter.write().unwrap() = Arc::clone(&message_filter);
+        let chat_rooms = self
+            .chat_rooms
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for room in chat_rooms {
+            let message_filter = Arc::clone(&message_filter);
+            let _ = room
+                .call(move |room| room.set_message_filter(message_filter))
+                .await;
+        }
+    }
+
+    /// Deletes a room on behalf of its owner, along with its history
+    ///
+    /// The sessions in the room drop their handles once they are told about the deletion.
+    pub async fn delete_room(&self, room_name: &str, user_id: &str) -> anyhow::Result<()> {
+        let user_id = String::from(user_id);
+        let room = self.get_room(room_name)?;
+        let audience = room
+            .call(move |room| {
+                room.check_permission(&user_id, RoomPermission::DeleteRoom)
+                    .map(|_| room.private_audience())
+            })
+            .await??;
+
+        // the room is closed first, so nothing is stored for it once its stored messages are deleted,
+        // and it is deleted once, by whoever closed it
+        if room.close().await.is_err() {
+            return Err(CommandError::RoomNotFound(String::from(room_name)).into());
+        }
+
+        // the stored messages are gone before the name is freed, so a room created again under it
+        // does not restore them
+        if let Some(store) = self.message_store.clone() {
+            let deleted_room = String::from(room_name);
+            let deleted = tokio::task::spawn_blocking(move || {
+                store.delete_room(&deleted_room);
+                store.flush();
+            })
+            .await;
+            if let Err(err) = deleted {
+                error!(room = room_name, "could not delete the messages: {}", err);
+            }
+        }
+
+        self.chat_rooms.write().unwrap().remove(room_name);
+        self.chat_room_metadatas
+            .write()
+            .unwrap()
+            .retain(|metadata| metadata.name != room_name);
+
+        if let Some(store) = self.ban_store.clone() {
+            let deleted_room = String::from(room_name);
+            let deleted =
+                tokio::task::spawn_blocking(move || store.delete_room(&deleted_room)).await;
+            if let Err(err) = deleted.unwrap_or_else(|err| Err(err.into())) {
+                error!(room = room_name, "could not delete the bans: {}", err);
+            }
+        }
+
+        if let Some(store) = self.attachment_store.clone() {
+            let deleted_room = String::from(room_name);
+            let deleted =
+                tokio::task::spawn_blocking(move || store.delete_room(&deleted_room)).await;
+            if let Err(err) = deleted.unwrap_or_else(|err| Err(err.into())) {
+                error!(
+                    room = room_name,
+                    "could not delete the attachments: {}", err
+                );
+            }
+        }
+
+        // a private room is not revealed to the users who could not see it
+        let _ = self.room_list_tx.send(RoomListEvent {
+            event: Event::RoomDeleted(event::RoomDeletedBroadcastEvent {
+                room: String::from(room_name),
+            }),
+            audience: audience.map(Arc::new),
+        });
+
+        Ok(())
     }
 
     /// Joins to a room given a user session
@@ -39,31 +560,804 @@ impl RoomManager {
         room_name: &str,
         session_and_user_id: &SessionAndUserId,
     ) -> anyhow::Result<RoomJoinResult> {
-        let room = self
-            .chat_rooms
-            .get(room_name)
-            .ok_or_else(|| anyhow::anyhow!("room '{}' not found", room_name))?;
+        let room_handle = self.get_room(room_name)?;
+        let joining = session_and_user_id.clone();
+
+        let (broadcast_rx, user_ids, role) = room_handle
+            .call(move |room| {
+                let room_name = &room.metadata().name;
+
+                if room.is_banned(&joining.user_id) {
+                    return Err(CommandError::PermissionDenied(format!(
+                        "you are banned from room '{}'",
+                        room_name
+                    )));
+                }
 
-        let mut room = room.lock().await;
-        let (broadcast_rx, user_session_handle) = room.join(session_and_user_id);
+                if !room.is_joinable_by(&joining.user_id) {
+                    return Err(CommandError::PermissionDenied(format!(
+                        "room '{}' is private, ask a member for an invitation",
+                        room_name
+                    )));
+                }
+
+                let broadcast_rx = room.join(&joining);
+
+                Ok((
+                    broadcast_rx,
+                    room.get_unique_user_ids(),
+                    room.role_of(&joining.user_id),
+                ))
+            })
+            .await??;
 
         Ok((
             broadcast_rx,
-            user_session_handle,
-            room.get_unique_user_ids().clone(),
+            UserSessionHandle::new(room_handle, session_and_user_id.clone()),
+            user_ids,
+            role,
         ))
     }
 
-    pub async fn drop_user_session_handle(&self, handle: UserSessionHandle) -> anyhow::Result<()> {
-        let room = self
-            .chat_rooms
-            .get(handle.room())
-            .ok_or_else(|| anyhow::anyhow!("room '{}' not found", handle.room()))?;
+    /// Changes the topic of a room on behalf of its creator or one of its moderators
+    pub async fn set_room_topic(
+        &self,
+        room_name: &str,
+        topic: &str,
+        user_id: &str,
+    ) -> anyhow::Result<()> {
+        self.change_room_topic(room_name, topic, user_id, true)
+            .await
+    }
+
+    /// Changes the topic of a room as it was changed in a bridged room, which has checked the permission of the user
+    pub async fn set_bridged_room_topic(
+        &self,
+        room_name: &str,
+        topic: &str,
+        changed_by: &str,
+    ) -> anyhow::Result<()> {
+        self.change_room_topic(room_name, topic, changed_by, false)
+            .await
+    }
+
+    async fn change_room_topic(
+        &self,
+        room_name: &str,
+        topic: &str,
+        user_id: &str,
+        check_permission: bool,
+    ) -> anyhow::Result<()> {
+        let topic = topic.trim();
+
+        if topic.is_empty() || topic.chars().count() > MAX_TOPIC_CHARS {
+            return Err(anyhow::anyhow!(
+                "topics are 1 to {} characters long",
+                MAX_TOPIC_CHARS
+            ));
+        }
+
+        let (topic, user_id) = (String::from(topic), String::from(user_id));
+        self.get_room(room_name)?
+            .call({
+                let topic = topic.clone();
+
+                move |room| {
+                    if check_permission {
+                        room.check_permission(&user_id, RoomPermission::SetTopic)?;
+                    }
+                    room.set_topic(&topic, &user_id);
+
+                    anyhow::Ok(())
+                }
+            })
+            .await??;
+
+        // the users logging in later are listed the new topic
+        if let Some(metadata) = self
+            .chat_room_metadatas
+            .write()
+            .unwrap()
+            .iter_mut()
+            .find(|metadata| metadata.name == room_name)
+        {
+            metadata.description = topic;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the role of a user of a room on behalf of its owner, and tells the users of the room about it
+    pub async fn set_room_role(
+        &self,
+        room_name: &str,
+        owner_id: &str,
+        user_id: &str,
+        role: RoomRole,
+    ) -> anyhow::Result<()> {
+        let (owner_id, user_id) = (String::from(owner_id), String::from(user_id));
 
-        let mut room = room.lock().await;
+        self.get_room(room_name)?
+            .call(move |room| {
+                room.check_permission(&owner_id, RoomPermission::ManageRoles)?;
+                room.set_role(&user_id, role)
+            })
+            .await?
+    }
+
+    /// Kicks, bans or mutes a user of a room on behalf of its creator or one of its moderators,
+    /// and tells the users of the room about it
+    ///
+    /// The sessions of a kicked or banned user leave the room once they are told about it.
+    pub async fn moderate_user(
+        &self,
+        room_name: &str,
+        moderator_id: &str,
+        user_id: &str,
+        action: event::ModerationAction,
+    ) -> anyhow::Result<()> {
+        if let event::ModerationAction::Muted { seconds } = action {
+            if seconds > MAX_MUTE_DURATION.as_secs() {
+                return Err(CommandError::InvalidMuteDuration(MAX_MUTE_DURATION.as_secs()).into());
+            }
+        }
+
+        let (moderator_id, user_id) = (String::from(moderator_id), String::from(user_id));
+        let is_ban = matches!(action, event::ModerationAction::Banned);
+        let ban = (
+            String::from(room_name),
+            user_id.clone(),
+            moderator_id.clone(),
+        );
+
+        self.get_room(room_name)?
+            .call(move |room| {
+                let room_name = room.metadata().name.clone();
+
+                room.check_permission(&moderator_id, RoomPermission::Moderate)?;
+
+                if user_id == moderator_id || room.role_of(&user_id) != RoomRole::Member {
+                    return Err(CommandError::PermissionDenied(format!(
+                        "the owner and the moderators of room '{}' can not be moderated",
+                        room_name
+                    ))
+                    .into());
+                }
+
+                let is_member = room.get_unique_user_ids().contains(&user_id);
 
-        room.leave(handle);
+                match &action {
+                    event::ModerationAction::Kicked if !is_member => {
+                        return Err(anyhow::anyhow!(
+                            "user '{}' is not in room '{}'",
+                            user_id,
+                            room_name
+                        ));
+                    }
+                    event::ModerationAction::Kicked => (),
+                    event::ModerationAction::Banned => {
+                        if room.is_banned(&user_id) {
+                            return Err(anyhow::anyhow!(
+                                "user '{}' is already banned from room '{}'",
+                                user_id,
+                                room_name
+                            ));
+                        }
+
+                        room.ban(&user_id);
+                    }
+                    event::ModerationAction::Muted { seconds } => {
+                        room.mute(&user_id, mute_deadline(*seconds));
+                    }
+                    event::ModerationAction::Unmuted => room.mute(&user_id, 0),
+                }
+
+                room.broadcast_moderation(&user_id, action, &moderator_id);
+
+                Ok(())
+            })
+            .await??;
+
+        // the ban is stored off the room, so that the other requests to the room do not wait for the database
+        if let (true, Some(store)) = (is_ban, self.ban_store.clone()) {
+            let (room_name, user_id, moderator_id) = ban;
+            tokio::task::spawn_blocking(move || store.ban(&room_name, &user_id, &moderator_id))
+                .await?
+                .context("the ban could not be stored, it lasts until the server restarts")?;
+        }
 
         Ok(())
     }
+
+    /// Mutes a user of a room on behalf of the server itself, whatever their role,
+    /// and tells the users of the room about it as coming from the given id
+    ///
+    /// The mute is cut down to the longest one allowed rather than refused.
+    pub async fn auto_mute(
+        &self,
+        room_name: &str,
+        moderator_id: &str,
+        user_id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<()> {
+        let (moderator_id, user_id) = (String::from(moderator_id), String::from(user_id));
+        let seconds = seconds.min(MAX_MUTE_DURATION.as_secs());
+
+        self.get_room(room_name)?
+            .call(move |room| {
+                room.mute(&user_id, mute_deadline(seconds));
+                room.broadcast_moderation(
+                    &user_id,
+                    event::ModerationAction::Muted { seconds },
+                    &moderator_id,
+                );
+            })
+            .await
+    }
+
+    /// Invites a user to a private room on behalf of one of its members
+    /// Returns the metadata of the room, to be sent to the invitee
+    pub async fn invite(
+        &self,
+        room_name: &str,
+        inviter_id: &str,
+        invitee_id: &str,
+    ) -> anyhow::Result<ChatRoomMetadata> {
+        let (inviter_id, invitee_id) = (String::from(inviter_id), String::from(invitee_id));
+
+        self.get_room(room_name)?
+            .call(move |room| {
+                let room_name = room.metadata().name.clone();
+
+                if room.metadata().visibility == RoomVisibility::Public {
+                    return Err(anyhow::anyhow!(
+                        "room '{}' is public, anyone can join it",
+                        room_name
+                    ));
+                }
+
+                if !room.get_unique_user_ids().contains(&inviter_id) {
+                    return Err(CommandError::NotAMember(room_name).into());
+                }
+
+                if !room.invite(&invitee_id) {
+                    return Err(anyhow::anyhow!(
+                        "user '{}' is already invited to room '{}'",
+                        invitee_id,
+                        room_name
+                    ));
+                }
+
+                Ok(room.metadata().clone())
+            })
+            .await?
+    }
+
+    /// Withdraws the invitation of a user to a room, when they decline it or could not be told about it
+    pub async fn revoke_invitation(&self, room_name: &str, user_id: &str) -> anyhow::Result<()> {
+        let user_id = String::from(user_id);
+
+        self.get_room(room_name)?
+            .call(move |room| room.revoke_invitation(&user_id))
+            .await
+    }
+
+    /// Returns the history of a room which is visible to the given user,
+    /// optionally narrowed down to the messages around a timestamp
+    pub async fn get_visible_history(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        around: Option<u64>,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<HistoryMessage>> {
+        let user_id = String::from(user_id);
+
+        self.get_room(room_name)?
+            .call(move |room| room.get_visible_history(&user_id, around, limit))
+            .await
+    }
+
+    /// Returns a page of the history of a room which is visible to the given user, older than the given message,
+    /// and whether there are more of them
+    pub async fn get_visible_history_before(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        before: u64,
+        limit: usize,
+    ) -> anyhow::Result<(Vec<HistoryMessage>, bool)> {
+        let user_id = String::from(user_id);
+
+        let older = self
+            .get_room(room_name)?
+            .call(move |room| room.get_visible_before(&user_id, before, limit))
+            .await?;
+        let (Some(stored_range), Some(store)) = (older.stored_range, self.message_store.clone())
+        else {
+            return Ok((older.messages, older.has_more));
+        };
+
+        // the page goes on with the stored messages older than the ones kept in memory
+        let room_name = String::from(room_name);
+        let stored_limit = limit - older.messages.len();
+        let (mut messages, has_more) = tokio::task::spawn_blocking(move || {
+            store.load_before(&room_name, stored_range, stored_limit)
+        })
+        .await??;
+        messages.extend(older.messages);
+
+        Ok((messages, has_more))
+    }
+
+    /// Searches the stored messages of a room the given user is allowed to see, newest first
+    pub async fn search_history(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        query: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<SearchPage> {
+        let store = self.message_store.clone().ok_or_else(|| {
+            anyhow::anyhow!("the messages are not stored, they can not be searched")
+        })?;
+        let user_id = String::from(user_id);
+        let visible_since = self
+            .get_room(room_name)?
+            .call(move |room| room.get_visible_since(&user_id))
+            .await?;
+
+        // the search may take a while, it is kept off the threads of the runtime
+        let (room_name, query) = (String::from(room_name), String::from(query));
+        tokio::task::spawn_blocking(move || {
+            store.search(&room_name, &query, visible_since, before, limit)
+        })
+        .await?
+    }
+
+    /// Returns a page of the stored messages of a room sent within the given range, for the operator to export them,
+    /// see [MessageStore::export_page]
+    pub async fn export_page(
+        &self,
+        room_name: &str,
+        since: Option<u64>,
+        until: Option<u64>,
+        after: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, HistoryMessage)>> {
+        let store = self.message_store.clone().ok_or_else(|| {
+            anyhow::anyhow!("the messages are not stored, they can not be exported")
+        })?;
+        self.get_room(room_name)?;
+
+        let room_name = String::from(room_name);
+        tokio::task::spawn_blocking(move || {
+            store.export_page(&room_name, since, until, after, limit)
+        })
+        .await?
+    }
+
+    fn attachment_store(&self) -> anyhow::Result<Arc<AttachmentStore>> {
+        self.attachment_store
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("the server does not keep shared files"))
+    }
+
+    /// Stores a file uploaded by a user and shares it with the room through the handle of the user
+    ///
+    /// Fails if the user is muted in the room, in which case the file is not stored.
+    pub async fn share_file(
+        &self,
+        handle: &UserSessionHandle,
+        attachment: Attachment,
+        content: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let store = self.attachment_store()?;
+
+        handle.check_not_muted().await?;
+        // the file is written off the threads of the runtime, and outside of the room
+        let attachment = tokio::task::spawn_blocking(move || {
+            store.save(&attachment, &content).map(|()| attachment)
+        })
+        .await??;
+
+        handle.share_file(attachment).await
+    }
+
+    /// Returns the details of a file shared with a room
+    pub async fn get_attachment(&self, id: &str) -> anyhow::Result<Attachment> {
+        let store = self.attachment_store()?;
+        let file_id = String::from(id);
+
+        tokio::task::spawn_blocking(move || store.get(&file_id))
+            .await??
+            .ok_or_else(|| anyhow::anyhow!("file '{}' not found", id))
+    }
+
+    /// Reads a chunk of the content of a file shared with a room
+    pub async fn read_attachment_chunk(
+        &self,
+        id: &str,
+        offset: u64,
+        len: usize,
+    ) -> anyhow::Result<Vec<u8>> {
+        let store = self.attachment_store()?;
+        let id = String::from(id);
+
+        tokio::task::spawn_blocking(move || store.read_chunk(&id, offset, len)).await?
+    }
+
+    /// Whether the members of the room are allowed to export its full history
+    pub fn is_history_exportable(&self, room_name: &str) -> bool {
+        self.chat_room_metadatas
+            .read()
+            .unwrap()
+            .iter()
+            .any(|metadata| metadata.name == room_name && metadata.history_export)
+    }
+
+    /// Returns a chunk of the history of a room the given user is allowed to see, starting after the given position
+    ///
+    /// The whole history is read from the store when there is one, rather than the part of it kept in memory.
+    pub async fn get_history_chunk(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        after: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<HistoryChunk> {
+        let room = self.get_room(room_name)?;
+        let user_id = String::from(user_id);
+
+        let Some(store) = self.message_store.clone() else {
+            return room
+                .call(move |room| room.get_history_chunk(&user_id, after, limit))
+                .await;
+        };
+
+        let visible_ids = room
+            .call(move |room| room.get_visible_ids(&user_id))
+            .await?;
+        let start = after
+            .map(|after| after + 1)
+            .unwrap_or(0)
+            .max(visible_ids.start);
+        let end = visible_ids.end;
+
+        let room_name = String::from(room_name);
+        let messages = tokio::task::spawn_blocking(move || {
+            // the messages sent until now are written first, so the export reaches the latest of them
+            store.flush();
+            store.load_after(&room_name, start..end, limit)
+        })
+        .await??;
+
+        Ok(HistoryChunk::new(messages, after, limit, end))
+    }
+
+    /// Returns the messages of the given user in every room, read from the store when there is one
+    pub async fn get_messages_of(&self, user_id: &str) -> anyhow::Result<Vec<ExportedMessage>> {
+        if let Some(store) = self.message_store.clone() {
+            let user_id = String::from(user_id);
+            let stored = tokio::task::spawn_blocking(move || {
+                store.flush();
+                store.messages_of(&user_id)
+            })
+            .await??;
+
+            let mut messages = stored
+                .into_iter()
+                .map(|(room, message)| ExportedMessage {
+                    room,
+                    content: message.content,
+                    timestamp: message.timestamp,
+                })
+                .collect::<Vec<_>>();
+            messages.sort_by_key(|message| message.timestamp);
+
+            return Ok(messages);
+        }
+
+        let mut messages = vec![];
+        let chat_rooms = self.chat_rooms.read().unwrap().clone();
+
+        for (room_name, room) in chat_rooms.iter() {
+            let user_id = String::from(user_id);
+            // a room deleted meanwhile does not keep any message anymore
+            let Ok(room_messages) = room.call(move |room| room.get_messages_of(&user_id)).await
+            else {
+                continue;
+            };
+
+            messages.extend(room_messages.into_iter().map(|message| ExportedMessage {
+                room: room_name.clone(),
+                content: message.content,
+                timestamp: message.timestamp,
+            }));
+        }
+
+        messages.sort_by_key(|message| message.timestamp);
+
+        Ok(messages)
+    }
+
+    /// Anonymizes the messages of the given user in the history of every room, and in the store
+    ///
+    /// Returns once the stored messages are anonymized.
+    pub async fn anonymize_user(&self, user_id: &str) -> anyhow::Result<()> {
+        let chat_rooms = self.chat_rooms.read().unwrap().clone();
+
+        for room in chat_rooms.values() {
+            let user_id = String::from(user_id);
+            let _ = room.call(move |room| room.anonymize_user(&user_id)).await;
+        }
+
+        if let Some(store) = self.message_store.clone() {
+            let user_id = String::from(user_id);
+            tokio::task::spawn_blocking(move || {
+                store.reassign_user(&user_id, ANONYMIZED_USER_ID);
+                store.flush();
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn drop_user_session_handle(&self, handle: UserSessionHandle) -> anyhow::Result<()> {
+        handle.leave().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room_manager::RoomManagerBuilder;
+
+    fn room_owned_by(owner: &str) -> RoomManager {
+        room_builder_owned_by(owner).build()
+    }
+
+    fn room_builder_owned_by(owner: &str) -> RoomManagerBuilder {
+        RoomManagerBuilder::new().create_room(ChatRoomMetadata {
+            name: String::from("general"),
+            description: String::from("General chat"),
+            visibility: RoomVisibility::Public,
+            history_visibility: HistoryVisibility::default(),
+            input_template: None,
+            history_export: false,
+            created_by: Some(String::from(owner)),
+            moderators: vec![],
+            retention: RetentionPolicy::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_the_bans_are_restored_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("chat-bans-{}.sqlite3", nanoid::nanoid!()));
+        let restart = || {
+            room_builder_owned_by("alice")
+                .ban_store(Arc::new(BanStore::open(&path).unwrap()))
+                .build()
+        };
+
+        let room_manager = restart();
+        room_manager
+            .moderate_user("general", "alice", "bob", event::ModerationAction::Banned)
+            .await
+            .unwrap();
+        drop(room_manager);
+
+        // the first request to the restarted room waits for the bans to be restored
+        let room_manager = restart();
+        let is_banned = room_manager
+            .get_room("general")
+            .unwrap()
+            .call(|room| room.is_banned("bob"))
+            .await
+            .unwrap();
+        assert!(is_banned);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_the_messages_are_stored_once_they_are_broadcast() {
+        let path = std::env::temp_dir().join(format!("chat-rooms-{}.sqlite3", nanoid::nanoid!()));
+        let store = Arc::new(MessageStore::open(&path).unwrap());
+        let room_manager = room_builder_owned_by("alice")
+            .message_store(Arc::clone(&store))
+            .build();
+        let room = room_manager.get_room("general").unwrap();
+        let mut events = room.call(|room| room.subscribe()).await.unwrap();
+        room.call(|room| room.send_message("bob", String::from("helo"), None))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the broadcast acknowledges the message to its sender, it is stored by then without flushing the store
+        assert!(matches!(events.recv().await, Ok(Event::UserMessage(_))));
+        assert_eq!(store.load_recent("general", 10).unwrap().len(), 1);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_the_deletion_of_a_private_room_is_told_to_the_users_who_could_see_it() {
+        let room_manager = RoomManagerBuilder::new()
+            .create_room(ChatRoomMetadata {
+                name: String::from("secret"),
+                description: String::from("Secret chat"),
+                visibility: RoomVisibility::Private,
+                history_visibility: HistoryVisibility::default(),
+                input_template: None,
+                history_export: false,
+                created_by: Some(String::from("alice")),
+                moderators: vec![String::from("bob")],
+                retention: RetentionPolicy::default(),
+            })
+            .build();
+        room_manager
+            .get_room("secret")
+            .unwrap()
+            .call(|room| room.invite("carol"))
+            .await
+            .unwrap();
+        let mut room_list_rx = room_manager.subscribe_room_list();
+
+        room_manager.delete_room("secret", "alice").await.unwrap();
+
+        let room_list_event = room_list_rx.recv().await.unwrap();
+        assert!(matches!(room_list_event.event, Event::RoomDeleted(_)));
+        for user_id in ["alice", "bob", "carol"] {
+            assert!(room_list_event.is_visible_to(user_id));
+        }
+        assert!(!room_list_event.is_visible_to("dave"));
+    }
+
+    #[tokio::test]
+    async fn test_the_messages_are_gone_from_the_store_once_the_room_is_deleted() {
+        let path = std::env::temp_dir().join(format!("chat-rooms-{}.sqlite3", nanoid::nanoid!()));
+        let store = Arc::new(MessageStore::open(&path).unwrap());
+        let room_manager = room_builder_owned_by("alice")
+            .message_store(Arc::clone(&store))
+            .build();
+        let room = room_manager.get_room("general").unwrap();
+        let _events = room.call(|room| room.subscribe()).await.unwrap();
+        room.call(|room| room.send_message("bob", String::from("helo"), None))
+            .await
+            .unwrap()
+            .unwrap();
+
+        room_manager.delete_room("general", "alice").await.unwrap();
+
+        // the sessions still holding the room until they are told about the deletion can not store anything
+        let sent = room
+            .call(|room| room.send_message("bob", String::from("late"), None))
+            .await;
+        assert!(sent.is_err());
+        store.flush();
+
+        // a room created again under the name right away restores nothing
+        assert!(store.load_recent("general", 10).unwrap().is_empty());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_refuses_to_mute_for_too_long() {
+        let room_manager = room_owned_by("alice");
+
+        let err = room_manager
+            .moderate_user(
+                "general",
+                "alice",
+                "bob",
+                event::ModerationAction::Muted { seconds: u64::MAX },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CommandError>(),
+            Some(&CommandError::InvalidMuteDuration(
+                MAX_MUTE_DURATION.as_secs()
+            ))
+        );
+
+        // the room is still there to take the next command
+        room_manager
+            .moderate_user(
+                "general",
+                "alice",
+                "bob",
+                event::ModerationAction::Muted { seconds: 60 },
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cuts_the_automatic_mutes_down_to_the_longest_one() {
+        let room_manager = room_owned_by("alice");
+
+        room_manager
+            .auto_mute("general", "spam_guard", "bob", u64::MAX)
+            .await
+            .unwrap();
+
+        let err = room_manager
+            .get_room("general")
+            .unwrap()
+            .call(|room| room.check_not_muted("bob"))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "you are muted in room 'general' for {} more seconds",
+                MAX_MUTE_DURATION.as_secs()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_muted_users_can_not_edit_their_messages() {
+        let room_manager = room_owned_by("alice");
+        let room = room_manager.get_room("general").unwrap();
+        let _events = room.call(|room| room.subscribe()).await.unwrap();
+        room.call(|room| room.send_message("bob", String::from("helo"), None))
+            .await
+            .unwrap()
+            .unwrap();
+
+        room_manager
+            .moderate_user(
+                "general",
+                "alice",
+                "bob",
+                event::ModerationAction::Muted { seconds: 60 },
+            )
+            .await
+            .unwrap();
+
+        let err = room
+            .call(|room| room.edit_message("bob", 0, String::from("spam")))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::PermissionDenied(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_members_can_not_kick_mute_nor_change_the_topic() {
+        let room_manager = room_owned_by("alice");
+
+        for action in [
+            event::ModerationAction::Kicked,
+            event::ModerationAction::Muted { seconds: 60 },
+        ] {
+            let err = room_manager
+                .moderate_user("general", "carol", "dave", action)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CommandError>(),
+                Some(CommandError::PermissionDenied(_))
+            ));
+        }
+
+        let err = room_manager
+            .set_room_topic("general", "Off topic", "carol")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::PermissionDenied(_))
+        ));
+    }
 }
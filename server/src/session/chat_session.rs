@@ -1,91 +1,595 @@
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::Context;
 use comms::{
-    command::UserCommand,
+    command::{self, UserCommand},
     event::{self, Event},
+    file_transfer::{self, MAX_CHUNK_SIZE, MAX_FILE_SIZE},
 };
+use nanoid::nanoid;
 use tokio::{
-    sync::mpsc,
+    sync::broadcast::{self, error::RecvError},
     task::{AbortHandle, JoinSet},
 };
+use tracing::warn;
 
-use crate::room_manager::{RoomManager, SessionAndUserId, UserSessionHandle};
+use crate::{
+    clock::now_millis,
+    command_error::CommandError,
+    direct_message_router::DirectMessageRouter,
+    room_manager::{RoomListEvent, RoomManager, SessionAndUserId, UserSessionHandle},
+    space_manager::SpaceManager,
+    spam_guard::{SpamGuard, SPAM_GUARD_ID},
+    storage::Attachment,
+};
+
+use super::outbound_queue::{self, OutboundReceiver, OutboundSender};
+
+/// Number of messages sent in each chunk of a room history export
+const EXPORT_CHUNK_SIZE: usize = 100;
+/// Number of the matches sent in each page of a search, when the user does not ask for fewer
+const MAX_SEARCH_PAGE_SIZE: usize = 50;
+/// Number of the rooms sent in each page of the room directory, when the user does not ask for fewer
+const MAX_DIRECTORY_PAGE_SIZE: usize = 50;
+/// Number of the older messages sent in each page, when the user does not ask for fewer
+const MAX_HISTORY_PAGE_SIZE: usize = 100;
+/// Number of the latest visible messages replayed to a user right after joining a room
+const JOIN_HISTORY_SIZE: usize = 100;
+/// Number of the uploads a user can have going at once, their content is kept in memory until they complete
+const MAX_PENDING_UPLOADS: usize = 2;
+/// Number of characters the name of a shared file can be at most
+const MAX_FILE_NAME_CHARS: usize = 255;
+
+/// An upload the user has started, whose content is kept until all of it is received
+struct PendingUpload {
+    room: String,
+    name: String,
+    size: u64,
+    checksum: String,
+    /// The content received so far
+    content: Vec<u8>,
+}
+
+/// Returns why a file can not be shared with the given name, if it can not be
+fn validate_file_name(name: &str) -> Option<String> {
+    let is_valid = !name.is_empty()
+        && name.chars().count() <= MAX_FILE_NAME_CHARS
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\']);
+
+    (!is_valid).then(|| {
+        format!(
+            "file names are 1 to {} characters long, without any directory",
+            MAX_FILE_NAME_CHARS
+        )
+    })
+}
 
 pub(super) struct ChatSession {
     session_and_user_id: SessionAndUserId,
     room_manager: Arc<RoomManager>,
+    space_manager: Arc<SpaceManager>,
+    direct_message_router: Arc<DirectMessageRouter>,
+    spam_guard: Arc<SpamGuard>,
     joined_rooms: HashMap<String, (UserSessionHandle, AbortHandle)>,
+    /// The spaces the user is a member of, with the task forwarding their membership changes
+    joined_spaces: HashMap<String, AbortHandle>,
+    /// The uploads the user has started, by the id the client picked for them
+    uploads: HashMap<String, PendingUpload>,
     join_set: JoinSet<()>,
-    mpsc_tx: mpsc::Sender<Event>,
-    mpsc_rx: mpsc::Receiver<Event>,
+    outbound_tx: OutboundSender,
+    outbound_rx: OutboundReceiver,
+    /// The id of the request the command being handled was sent with
+    request_id: Option<String>,
+    /// Why the command being handled has failed, unless a dedicated denial has told the user already
+    failure: Option<anyhow::Error>,
 }
 
 impl ChatSession {
-    pub fn new(session_id: &str, user_id: &str, room_manager: Arc<RoomManager>) -> Self {
-        let (mpsc_tx, mpsc_rx) = mpsc::channel(100);
+    pub fn new(
+        session_id: &str,
+        user_id: &str,
+        room_manager: Arc<RoomManager>,
+        space_manager: Arc<SpaceManager>,
+        direct_message_router: Arc<DirectMessageRouter>,
+        spam_guard: Arc<SpamGuard>,
+        outbound_queue_capacity: usize,
+    ) -> Self {
+        let (outbound_tx, outbound_rx) = outbound_queue::channel(outbound_queue_capacity);
         let session_and_user_id = SessionAndUserId {
             session_id: String::from(session_id),
             user_id: String::from(user_id),
         };
 
+        // direct messages are delivered through the same queue as the room events
+        direct_message_router.register(&session_and_user_id, outbound_tx.clone());
+
+        // every user is told about the rooms being created and deleted, whether they joined them or not,
+        // as long as they can see them
+        let mut join_set = JoinSet::new();
+        join_set.spawn(forward_room_list(
+            room_manager.subscribe_room_list(),
+            String::from(user_id),
+            outbound_tx.clone(),
+        ));
+
         ChatSession {
             session_and_user_id,
             room_manager,
+            space_manager,
+            direct_message_router,
+            spam_guard,
             joined_rooms: HashMap::new(),
-            join_set: JoinSet::new(),
-            mpsc_tx,
-            mpsc_rx,
+            joined_spaces: HashMap::new(),
+            uploads: HashMap::new(),
+            join_set,
+            outbound_tx,
+            outbound_rx,
+            request_id: None,
+            failure: None,
         }
     }
 
-    /// Handle a user command related to room management such as; join, leave, send message
-    pub async fn handle_user_command(&mut self, cmd: UserCommand) -> anyhow::Result<()> {
+    /// Handle a user command related to room and space management and messaging such as; join, leave, send message, fetch history
+    ///
+    /// A command sent with a request id is answered with an acknowledgement or an error carrying the id,
+    /// instead of the dedicated denials, so the client can tell the outcome of every request the same way.
+    pub async fn handle_user_command(
+        &mut self,
+        cmd: UserCommand,
+        request_id: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.request_id = request_id;
+        self.failure = None;
+
+        self.run_user_command(cmd).await?;
+
+        let event = match (self.failure.take(), self.request_id.take()) {
+            (Some(err), request_id) => Event::CommandError(event::CommandErrorEvent {
+                request_id,
+                code: CommandError::code_of(&err),
+                message: err.to_string(),
+            }),
+            (None, Some(request_id)) => Event::CommandAck(event::CommandAckEvent { request_id }),
+            (None, None) => return Ok(()),
+        };
+
+        self.outbound_tx.push(event);
+
+        Ok(())
+    }
+
+    async fn run_user_command(&mut self, cmd: UserCommand) -> anyhow::Result<()> {
         match cmd {
             UserCommand::JoinRoom(cmd) => {
-                if self.joined_rooms.contains_key(&cmd.room) {
-                    return Err(anyhow::anyhow!("already joined room '{}'", &cmd.room));
+                // clients show the room as joined right away, they are told when the join fails instead of being disconnected
+                if let Err(err) = self.join_room(cmd.room.clone()).await {
+                    let denial = Event::RoomJoinDenied(event::RoomJoinDeniedReplyEvent {
+                        room: cmd.room,
+                        reason: err.to_string(),
+                    });
+
+                    self.deny(denial, err).await?;
+                }
+            }
+            UserCommand::JoinSpace(cmd) => {
+                if let Err(err) = self.join_space(cmd.space).await {
+                    self.report_error(err);
+                }
+            }
+            UserCommand::LeaveSpace(cmd) => {
+                // the rooms are left once the membership change is received, see [ChatSession::handle_event]
+                if let Err(err) = self
+                    .space_manager
+                    .leave_space(&cmd.space, &self.session_and_user_id.user_id)
+                    .await
+                {
+                    self.report_error(err);
+                }
+            }
+            UserCommand::SetSpaceRole(cmd) => {
+                if let Err(err) = self
+                    .space_manager
+                    .set_role(
+                        &cmd.space,
+                        &self.session_and_user_id.user_id,
+                        &cmd.user_id,
+                        cmd.role,
+                    )
+                    .await
+                {
+                    self.deny_space_command(cmd.space, err).await?;
+                }
+            }
+            UserCommand::RemoveSpaceMember(cmd) => {
+                if let Err(err) = self
+                    .space_manager
+                    .remove_member(&cmd.space, &self.session_and_user_id.user_id, &cmd.user_id)
+                    .await
+                {
+                    self.deny_space_command(cmd.space, err).await?;
+                }
+            }
+            UserCommand::SendMessage(cmd) => {
+                let content = cmd.content.clone();
+                let sent = match self.joined_room(&cmd.room) {
+                    Ok(handle) => match self.room_manager.check_message_length(&cmd.content) {
+                        Ok(()) => handle.send_message(cmd.content, cmd.reply_to).await,
+                        Err(err) => Err(err),
+                    },
+                    Err(err) => Err(err),
+                };
+
+                match sent {
+                    Ok(_) => self.guard_against_spam(&cmd.room, &content).await,
+                    Err(err) => self.report_error(err),
+                }
+            }
+            UserCommand::EditMessage(cmd) => {
+                let edited = match self.joined_room(&cmd.room) {
+                    Ok(handle) => match self.room_manager.check_message_length(&cmd.content) {
+                        Ok(()) => handle.edit_message(cmd.id, cmd.content).await,
+                        Err(err) => Err(err),
+                    },
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = edited {
+                    self.deny_message_change(cmd.room, cmd.id, err).await?;
+                }
+            }
+            UserCommand::DeleteMessage(cmd) => {
+                let deleted = match self.joined_room(&cmd.room) {
+                    Ok(handle) => handle.delete_message(cmd.id).await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = deleted {
+                    self.deny_message_change(cmd.room, cmd.id, err).await?;
+                }
+            }
+            UserCommand::ReactToMessage(cmd) => {
+                let reacted = match self.joined_room(&cmd.room) {
+                    Ok(handle) => handle.react_to_message(cmd.id, cmd.emoji).await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = reacted {
+                    self.deny_message_change(cmd.room, cmd.id, err).await?;
+                }
+            }
+            UserCommand::MarkRead(cmd) => {
+                let marked = match self.joined_room(&cmd.room) {
+                    Ok(handle) => handle.mark_read(cmd.id).await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = marked {
+                    self.report_error(err);
+                }
+            }
+            UserCommand::StartUpload(cmd) => {
+                let upload_id = cmd.upload_id.clone();
+
+                match self.start_upload(cmd).await {
+                    Ok(()) => {
+                        self.outbound_tx.push(Event::UploadProgress(
+                            event::UploadProgressReplyEvent {
+                                upload_id,
+                                received: 0,
+                            },
+                        ));
+                    }
+                    Err(err) => self.deny_file_transfer(upload_id, err).await?,
+                }
+            }
+            UserCommand::UploadChunk(cmd) => match self.receive_upload_chunk(&cmd).await {
+                Ok(received) => {
+                    self.outbound_tx
+                        .push(Event::UploadProgress(event::UploadProgressReplyEvent {
+                            upload_id: cmd.upload_id,
+                            received,
+                        }));
                 }
+                Err(err) => {
+                    // a failed upload is not resumed, it is started over
+                    self.uploads.remove(&cmd.upload_id);
+                    self.deny_file_transfer(cmd.upload_id, err).await?;
+                }
+            },
+            UserCommand::DownloadFile(cmd) => match self.read_file_chunk(&cmd).await {
+                Ok(chunk) => self.outbound_tx.push(Event::FileChunk(chunk)),
+                Err(err) => self.deny_file_transfer(cmd.file_id, err).await?,
+            },
+            UserCommand::SendDirectMessage(cmd) => {
+                if let Err(err) = self.direct_message_router.send(
+                    &self.session_and_user_id.user_id,
+                    &cmd.user_id,
+                    cmd.content,
+                ) {
+                    let denial = Event::DirectMessageDenied(event::DirectMessageDeniedReplyEvent {
+                        user_id: cmd.user_id,
+                        reason: err.to_string(),
+                    });
 
-                let (mut broadcast_rx, user_session_handle, user_ids) = self
+                    self.deny(denial, err).await?;
+                }
+            }
+            UserCommand::InviteUser(cmd) => {
+                if let Err(err) = self.invite_user(&cmd.room, &cmd.user_id).await {
+                    let denial =
+                        Event::RoomInvitationDenied(event::RoomInvitationDeniedReplyEvent {
+                            room: cmd.room,
+                            user_id: cmd.user_id,
+                            reason: err.to_string(),
+                        });
+
+                    self.deny(denial, err).await?;
+                }
+            }
+            UserCommand::DeclineInvitation(cmd) => {
+                // declining an invitation to an unknown room is a no-op
+                let _ = self
                     .room_manager
-                    .join_room(&cmd.room, &self.session_and_user_id)
-                    .await?;
+                    .revoke_invitation(&cmd.room, &self.session_and_user_id.user_id)
+                    .await;
+            }
+            UserCommand::FetchRoomHistory(cmd) => {
+                // only the members of a room can fetch its history
+                let history = match self.joined_room(&cmd.room) {
+                    Ok(handle) => {
+                        // a room closed meanwhile fails to give its history right after
+                        let last_read = handle.last_read().await.unwrap_or_default();
 
-                // spawn a task to forward broadcasted messages to the users' mpsc channel
-                // hence the user can receive messages from different rooms via single channel
-                let abort_handle = self.join_set.spawn({
-                    let mpsc_tx = self.mpsc_tx.clone();
+                        self.room_manager
+                            .get_visible_history(
+                                &cmd.room,
+                                &self.session_and_user_id.user_id,
+                                cmd.around,
+                                cmd.limit,
+                            )
+                            .await
+                            .map(|messages| (messages, last_read))
+                    }
+                    Err(err) => Err(err),
+                };
 
-                    // start with sending the user joined room event as a reply to the user
-                    mpsc_tx
-                        .send(Event::UserJoinedRoom(event::UserJoinedRoomReplyEvent {
-                            room: cmd.room.clone(),
-                            users: user_ids,
-                        }))
-                        .await?;
+                match history {
+                    Ok((messages, last_read)) => {
+                        self.outbound_tx
+                            .push(Event::RoomHistory(event::RoomHistoryReplyEvent {
+                                room: cmd.room,
+                                messages,
+                                around: cmd.around,
+                                last_read,
+                            }));
+                    }
+                    Err(err) => self.report_error(err),
+                }
+            }
+            UserCommand::FetchMessagesBefore(cmd) => {
+                // only the members of a room can fetch its history
+                let page = match self.joined_room(&cmd.room) {
+                    Ok(_) => {
+                        self.room_manager
+                            .get_visible_history_before(
+                                &cmd.room,
+                                &self.session_and_user_id.user_id,
+                                cmd.before_message_id,
+                                cmd.limit
+                                    .unwrap_or(MAX_HISTORY_PAGE_SIZE)
+                                    .min(MAX_HISTORY_PAGE_SIZE),
+                            )
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+
+                match page {
+                    Ok((messages, has_more)) => {
+                        self.outbound_tx.push(Event::OlderMessages(
+                            event::OlderMessagesReplyEvent {
+                                room: cmd.room,
+                                before_message_id: cmd.before_message_id,
+                                messages,
+                                has_more,
+                            },
+                        ));
+                    }
+                    Err(err) => self.report_error(err),
+                }
+            }
+            UserCommand::SearchMessages(cmd) => {
+                // only the members of a room can search its history
+                let page = match self.joined_room(&cmd.room) {
+                    Ok(_) => {
+                        self.room_manager
+                            .search_history(
+                                &cmd.room,
+                                &self.session_and_user_id.user_id,
+                                &cmd.query,
+                                cmd.before,
+                                cmd.limit
+                                    .unwrap_or(MAX_SEARCH_PAGE_SIZE)
+                                    .min(MAX_SEARCH_PAGE_SIZE),
+                            )
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+
+                match page {
+                    Ok(page) => {
+                        self.outbound_tx.push(Event::SearchResults(
+                            event::SearchResultsReplyEvent {
+                                room: cmd.room,
+                                query: cmd.query,
+                                messages: page.messages,
+                                cursor: page.cursor,
+                            },
+                        ));
+                    }
+                    Err(err) => self.report_error(err),
+                }
+            }
+            UserCommand::BrowseRooms(cmd) => {
+                // a page holds at least one room, so the cursor always moves on
+                let limit = cmd
+                    .limit
+                    .unwrap_or(MAX_DIRECTORY_PAGE_SIZE)
+                    .clamp(1, MAX_DIRECTORY_PAGE_SIZE);
+                let (rooms, cursor) = self
+                    .room_manager
+                    .room_directory(cmd.after.as_deref(), limit)
+                    .await;
+
+                self.outbound_tx
+                    .push(Event::RoomDirectory(event::RoomDirectoryReplyEvent {
+                        after: cmd.after,
+                        rooms,
+                        cursor,
+                    }));
+            }
+            UserCommand::ExportRoomHistory(cmd) => {
+                // only the members of a room which permits exports can export its history
+                if !self.joined_rooms.contains_key(&cmd.room)
+                    || !self.room_manager.is_history_exportable(&cmd.room)
+                {
+                    let err = CommandError::PermissionDenied(format!(
+                        "exporting the history of room '{}' is not allowed",
+                        cmd.room
+                    ));
+                    let denial =
+                        Event::RoomHistoryExportDenied(event::RoomHistoryExportDeniedReplyEvent {
+                            room: cmd.room,
+                        });
+
+                    return self.deny(denial, err.into()).await;
+                }
+
+                // stream the chunks from a separate task, so the session keeps processing other commands
+                self.join_set.spawn({
+                    let room_manager = Arc::clone(&self.room_manager);
+                    let outbound_tx = self.outbound_tx.clone();
+                    let user_id = self.session_and_user_id.user_id.clone();
 
                     async move {
-                        while let Ok(event) = broadcast_rx.recv().await {
-                            let _ = mpsc_tx.send(event).await;
+                        let mut after = cmd.after;
+
+                        loop {
+                            // the export is limited to the messages the user is allowed to see, as the history pages are
+                            let chunk = match room_manager
+                                .get_history_chunk(&cmd.room, &user_id, after, EXPORT_CHUNK_SIZE)
+                                .await
+                            {
+                                Ok(chunk) => chunk,
+                                Err(err) => {
+                                    // the command was acknowledged with the first chunk still to come,
+                                    // the user is told the export stopped without a request id
+                                    warn!(
+                                        room = cmd.room,
+                                        "could not export the history: {:#}", err
+                                    );
+                                    outbound_tx.push(Event::CommandError(
+                                        event::CommandErrorEvent {
+                                            request_id: None,
+                                            code: CommandError::code_of(&err),
+                                            message: format!(
+                                                "the export of room '{}' has stopped: {}",
+                                                cmd.room, err
+                                            ),
+                                        },
+                                    ));
+
+                                    break;
+                                }
+                            };
+                            after = chunk.cursor;
+
+                            let is_last = chunk.is_last;
+                            // the chunks are read at the pace of the client, instead of piling up in its queue
+                            let sent = outbound_tx
+                                .send(Event::RoomHistoryChunk(event::RoomHistoryChunkReplyEvent {
+                                    room: cmd.room.clone(),
+                                    messages: chunk.messages,
+                                    cursor: chunk.cursor,
+                                    is_last,
+                                }))
+                                .await;
+
+                            if is_last || sent.is_err() {
+                                break;
+                            }
                         }
                     }
                 });
-
-                // store references to the user session handle and abort handle
-                // this is used to send messages to the room and to cancel the task when user leaves the room
-                self.joined_rooms
-                    .insert(cmd.room.clone(), (user_session_handle, abort_handle));
             }
-            UserCommand::SendMessage(cmd) => {
-                if let Some((user_session_handle, _)) = self.joined_rooms.get(&cmd.room) {
-                    let _ = user_session_handle.send_message(cmd.content);
+            UserCommand::CreateRoom(cmd) => {
+                if let Err(err) = self.room_manager.create_room(
+                    &cmd.room,
+                    &cmd.description,
+                    &self.session_and_user_id.user_id,
+                ) {
+                    self.deny_room_management(cmd.room, err).await?;
                 }
             }
+            UserCommand::DeleteRoom(cmd) => {
+                if let Err(err) = self
+                    .room_manager
+                    .delete_room(&cmd.room, &self.session_and_user_id.user_id)
+                    .await
+                {
+                    self.deny_room_management(cmd.room, err).await?;
+                }
+            }
+            UserCommand::SetRoomTopic(cmd) => {
+                if let Err(err) = self
+                    .room_manager
+                    .set_room_topic(&cmd.room, &cmd.topic, &self.session_and_user_id.user_id)
+                    .await
+                {
+                    self.deny_room_management(cmd.room, err).await?;
+                }
+            }
+            UserCommand::SetRoomRole(cmd) => {
+                if let Err(err) = self
+                    .room_manager
+                    .set_room_role(
+                        &cmd.room,
+                        &self.session_and_user_id.user_id,
+                        &cmd.user_id,
+                        cmd.role,
+                    )
+                    .await
+                {
+                    self.deny_moderation(cmd.room, err).await?;
+                }
+            }
+            UserCommand::KickUser(cmd) => {
+                self.moderate_user(cmd.room, cmd.user_id, event::ModerationAction::Kicked)
+                    .await?;
+            }
+            UserCommand::BanUser(cmd) => {
+                self.moderate_user(cmd.room, cmd.user_id, event::ModerationAction::Banned)
+                    .await?;
+            }
+            UserCommand::MuteUser(cmd) => {
+                let action = match cmd.seconds {
+                    0 => event::ModerationAction::Unmuted,
+                    seconds => event::ModerationAction::Muted { seconds },
+                };
+
+                self.moderate_user(cmd.room, cmd.user_id, action).await?;
+            }
             UserCommand::LeaveRoom(cmd) => {
-                // remove the room from joined rooms and drop user session handle for the room
-                if let Some(urp) = self.joined_rooms.remove(&cmd.room) {
-                    self.cleanup_room(urp).await?;
+                // remove the room from joined rooms and quit the room with the user session handle,
+                // unlike a disconnect the membership of the user ends with it
+                match self.joined_rooms.remove(&cmd.room) {
+                    Some((user_session_handle, abort_handle)) => {
+                        user_session_handle.quit().await?;
+                        abort_handle.abort();
+                    }
+                    None => self.report_error(CommandError::NotAMember(cmd.room).into()),
                 }
             }
             _ => {}
@@ -94,9 +598,483 @@ impl ChatSession {
         Ok(())
     }
 
+    /// Joins a room and forwards its broadcasted events to the user,
+    /// followed by a replay of the latest messages of the room the user is allowed to see
+    async fn join_room(&mut self, room: String) -> anyhow::Result<()> {
+        if self.joined_rooms.contains_key(&room) {
+            return Err(CommandError::AlreadyJoinedRoom(room).into());
+        }
+
+        let (broadcast_rx, user_session_handle, user_ids, role) = self
+            .room_manager
+            .join_room(&room, &self.session_and_user_id)
+            .await?;
+
+        // the replay is read before the room is taken as joined, so a failed read leaves nothing behind
+        let replay = async {
+            let messages = self
+                .room_manager
+                .get_visible_history(
+                    &room,
+                    &self.session_and_user_id.user_id,
+                    None,
+                    Some(JOIN_HISTORY_SIZE),
+                )
+                .await?;
+            // the user may have read the room in an earlier session
+            let last_read = user_session_handle.last_read().await?;
+
+            anyhow::Ok((messages, last_read))
+        };
+        let (messages, last_read) = match replay.await {
+            Ok(replay) => replay,
+            Err(err) => {
+                self.room_manager
+                    .drop_user_session_handle(user_session_handle)
+                    .await?;
+
+                return Err(err);
+            }
+        };
+
+        // start with sending the user joined room event as a reply to the user
+        self.outbound_tx
+            .push(Event::UserJoinedRoom(event::UserJoinedRoomReplyEvent {
+                room: room.clone(),
+                users: user_ids,
+                role,
+            }));
+
+        // spawn a task to forward broadcasted messages to the outbound queue of the user
+        // hence the user can receive messages from different rooms via single queue
+        let abort_handle = self
+            .join_set
+            .spawn(forward_events(broadcast_rx, self.outbound_tx.clone()));
+
+        // store references to the user session handle and abort handle
+        // this is used to send messages to the room and to cancel the task when user leaves the room
+        self.joined_rooms
+            .insert(room.clone(), (user_session_handle, abort_handle));
+
+        // the messages broadcasted meanwhile may arrive before the replay, clients merge them by their ids
+        self.outbound_tx
+            .push(Event::RoomHistory(event::RoomHistoryReplyEvent {
+                room,
+                messages,
+                around: None,
+                last_read,
+            }));
+
+        Ok(())
+    }
+
+    /// Forwards the membership changes of the spaces the user is already a member of, as if they were joined,
+    /// for a new session of the user
+    ///
+    /// The default rooms are left to the client, which joins its rooms again by itself.
+    pub async fn follow_spaces(&mut self) {
+        let followed = self
+            .space_manager
+            .follow_spaces(&self.session_and_user_id.user_id)
+            .await;
+
+        for (space, (broadcast_rx, members)) in followed {
+            self.outbound_tx
+                .push(Event::UserJoinedSpace(event::UserJoinedSpaceReplyEvent {
+                    space: space.clone(),
+                    members,
+                }));
+
+            let abort_handle = self
+                .join_set
+                .spawn(forward_events(broadcast_rx, self.outbound_tx.clone()));
+            self.joined_spaces.insert(space, abort_handle);
+        }
+    }
+
+    /// Joins a space and forwards its membership changes to the user, along with its default rooms
+    async fn join_space(&mut self, space: String) -> anyhow::Result<()> {
+        if self.joined_spaces.contains_key(&space) {
+            return Err(CommandError::AlreadyJoinedSpace(space).into());
+        }
+
+        let (broadcast_rx, members) = self
+            .space_manager
+            .join_space(&space, &self.session_and_user_id.user_id)
+            .await?;
+
+        self.outbound_tx
+            .push(Event::UserJoinedSpace(event::UserJoinedSpaceReplyEvent {
+                space: space.clone(),
+                members,
+            }));
+
+        // forward the membership changes of the space, like the broadcasted messages of a room
+        let abort_handle = self
+            .join_set
+            .spawn(forward_events(broadcast_rx, self.outbound_tx.clone()));
+
+        self.joined_spaces.insert(space.clone(), abort_handle);
+
+        // the default rooms are joined along with the space, unless they are already joined
+        let default_rooms = self
+            .space_manager
+            .get_metadata(&space)
+            .map(|metadata| metadata.default_rooms.clone())
+            .unwrap_or_default();
+
+        for room in default_rooms {
+            if !self.joined_rooms.contains_key(&room) {
+                self.join_room(room).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the handle to send to a room with, fails unless the user has joined the room
+    fn joined_room(&self, room: &str) -> anyhow::Result<&UserSessionHandle> {
+        self.joined_rooms
+            .get(room)
+            .map(|(user_session_handle, _)| user_session_handle)
+            .ok_or_else(|| CommandError::NotAMember(String::from(room)).into())
+    }
+
+    /// Starts an upload to a joined room, once its file is found fit to be shared
+    async fn start_upload(&mut self, cmd: command::StartUploadCommand) -> anyhow::Result<()> {
+        self.joined_room(&cmd.room)?.check_not_muted().await?;
+
+        if self.uploads.contains_key(&cmd.upload_id) {
+            return Err(anyhow::anyhow!(
+                "upload '{}' is already started",
+                cmd.upload_id
+            ));
+        }
+
+        if self.uploads.len() >= MAX_PENDING_UPLOADS {
+            return Err(CommandError::PermissionDenied(format!(
+                "at most {} files can be uploaded at once",
+                MAX_PENDING_UPLOADS
+            ))
+            .into());
+        }
+
+        if cmd.size == 0 || cmd.size > MAX_FILE_SIZE {
+            return Err(CommandError::PermissionDenied(format!(
+                "files are 1 to {} bytes large",
+                MAX_FILE_SIZE
+            ))
+            .into());
+        }
+
+        if let Some(reason) = validate_file_name(&cmd.name) {
+            return Err(anyhow::anyhow!(reason));
+        }
+
+        self.uploads.insert(
+            cmd.upload_id,
+            PendingUpload {
+                room: cmd.room,
+                name: cmd.name,
+                size: cmd.size,
+                checksum: cmd.checksum,
+                // the content grows with the chunks received, rather than with the size announced
+                content: Vec::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Appends a chunk to its upload, and shares the file once all of its content is received
+    ///
+    /// Returns the number of bytes received so far.
+    async fn receive_upload_chunk(
+        &mut self,
+        cmd: &command::UploadChunkCommand,
+    ) -> anyhow::Result<u64> {
+        let upload = self
+            .uploads
+            .get_mut(&cmd.upload_id)
+            .ok_or_else(|| anyhow::anyhow!("upload '{}' not found", cmd.upload_id))?;
+
+        if cmd.offset != upload.content.len() as u64 {
+            return Err(anyhow::anyhow!(
+                "expected the chunk at offset {}",
+                upload.content.len()
+            ));
+        }
+
+        let chunk = file_transfer::decode_chunk(&cmd.data)?;
+        let received = upload.content.len() as u64 + chunk.len() as u64;
+        if chunk.is_empty() || chunk.len() > MAX_CHUNK_SIZE || received > upload.size {
+            return Err(anyhow::anyhow!(
+                "chunks are 1 to {} bytes large, up to the size of the file",
+                MAX_CHUNK_SIZE
+            ));
+        }
+
+        upload.content.extend_from_slice(&chunk);
+        if received < upload.size {
+            return Ok(received);
+        }
+
+        let upload = self.uploads.remove(&cmd.upload_id).unwrap();
+        if file_transfer::checksum(&upload.content) != upload.checksum {
+            return Err(anyhow::anyhow!(
+                "the content of the file does not match its checksum"
+            ));
+        }
+
+        let attachment = Attachment {
+            id: nanoid!(10),
+            room: upload.room,
+            user_id: self.session_and_user_id.user_id.clone(),
+            name: upload.name,
+            size: upload.size,
+            checksum: upload.checksum,
+            timestamp: now_millis(),
+        };
+        let handle = self.joined_room(&attachment.room)?;
+        self.room_manager
+            .share_file(handle, attachment, upload.content)
+            .await?;
+
+        Ok(received)
+    }
+
+    /// Reads a chunk of a file shared with a joined room
+    async fn read_file_chunk(
+        &self,
+        cmd: &command::DownloadFileCommand,
+    ) -> anyhow::Result<event::FileChunkReplyEvent> {
+        let attachment = self.room_manager.get_attachment(&cmd.file_id).await?;
+
+        // only the members of the room a file was shared with can download it
+        self.joined_room(&attachment.room)?;
+
+        if cmd.offset > attachment.size {
+            return Err(anyhow::anyhow!(
+                "file '{}' is only {} bytes large",
+                cmd.file_id,
+                attachment.size
+            ));
+        }
+
+        let chunk = self
+            .room_manager
+            .read_attachment_chunk(&cmd.file_id, cmd.offset, MAX_CHUNK_SIZE)
+            .await?;
+
+        Ok(event::FileChunkReplyEvent {
+            file_id: attachment.id,
+            offset: cmd.offset,
+            data: file_transfer::encode_chunk(&chunk),
+            size: attachment.size,
+            checksum: attachment.checksum,
+        })
+    }
+
+    /// Invites a user to a private room the user is a member of, and tells the invitee about it
+    async fn invite_user(&self, room: &str, invitee_id: &str) -> anyhow::Result<()> {
+        let metadata = self
+            .room_manager
+            .invite(room, &self.session_and_user_id.user_id, invitee_id)
+            .await?;
+
+        let delivered = self.direct_message_router.deliver(
+            invitee_id,
+            Event::RoomInvitation(event::RoomInvitationBroadcastEvent {
+                room: metadata.to_room_detail(),
+                from_user_id: self.session_and_user_id.user_id.clone(),
+            }),
+        );
+
+        // an invitation the invitee is not told about is withdrawn, so it can be sent again
+        if delivered.is_err() {
+            self.room_manager
+                .revoke_invitation(room, invitee_id)
+                .await?;
+        }
+
+        delivered
+    }
+
+    /// Reacts to the events concerning the user, before they are sent to the user
+    pub async fn handle_event(&mut self, event: &Event) -> anyhow::Result<()> {
+        // the user has left or was removed from a space, its rooms are left along with it
+        if let Event::SpaceMembership(event) = event {
+            if event.user_id == self.session_and_user_id.user_id && event.role.is_none() {
+                if let Some(abort_handle) = self.joined_spaces.remove(&event.space) {
+                    abort_handle.abort();
+                }
+
+                let rooms = self
+                    .space_manager
+                    .get_metadata(&event.space)
+                    .map(|metadata| metadata.rooms.clone())
+                    .unwrap_or_default();
+
+                for room in rooms {
+                    if let Some(urp) = self.joined_rooms.remove(&room) {
+                        self.cleanup_room(urp).await?;
+                    }
+                }
+            }
+        }
+
+        // the user has been kicked or banned from a room, which is left like the user left it
+        if let Event::UserModerated(event) = event {
+            let is_removed = matches!(
+                event.action,
+                event::ModerationAction::Kicked | event::ModerationAction::Banned
+            );
+
+            if is_removed && event.user_id == self.session_and_user_id.user_id {
+                if let Some(urp) = self.joined_rooms.remove(&event.room) {
+                    self.cleanup_room(urp).await?;
+                }
+            }
+        }
+
+        // the room is gone along with the handles of its users, only the forwarding task is left to stop
+        if let Event::RoomDeleted(event) = event {
+            if let Some((_, abort_handle)) = self.joined_rooms.remove(&event.room) {
+                abort_handle.abort();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deny_space_command(
+        &mut self,
+        space: String,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let denial = Event::SpaceCommandDenied(event::SpaceCommandDeniedReplyEvent {
+            space,
+            reason: err.to_string(),
+        });
+
+        self.deny(denial, err).await
+    }
+
+    async fn deny_message_change(
+        &mut self,
+        room: String,
+        id: u64,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let denial = Event::MessageChangeDenied(event::MessageChangeDeniedReplyEvent {
+            room,
+            id,
+            reason: err.to_string(),
+        });
+
+        self.deny(denial, err).await
+    }
+
+    async fn moderate_user(
+        &mut self,
+        room: String,
+        user_id: String,
+        action: event::ModerationAction,
+    ) -> anyhow::Result<()> {
+        if let Err(err) = self
+            .room_manager
+            .moderate_user(&room, &self.session_and_user_id.user_id, &user_id, action)
+            .await
+        {
+            self.deny_moderation(room, err).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn deny_moderation(&mut self, room: String, err: anyhow::Error) -> anyhow::Result<()> {
+        let denial = Event::ModerationDenied(event::ModerationDeniedReplyEvent {
+            room,
+            reason: err.to_string(),
+        });
+
+        self.deny(denial, err).await
+    }
+
+    async fn deny_room_management(
+        &mut self,
+        room: String,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let denial = Event::RoomManagementDenied(event::RoomManagementDeniedReplyEvent {
+            room,
+            reason: err.to_string(),
+        });
+
+        self.deny(denial, err).await
+    }
+
+    async fn deny_file_transfer(
+        &mut self,
+        transfer_id: String,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let denial = Event::FileTransferDenied(event::FileTransferDeniedReplyEvent {
+            transfer_id,
+            reason: err.to_string(),
+        });
+
+        self.deny(denial, err).await
+    }
+
+    /// Sends the dedicated denial of the failed command, or reports the failure if the command was sent with a request id
+    async fn deny(&mut self, denial: Event, err: anyhow::Error) -> anyhow::Result<()> {
+        match self.request_id {
+            Some(_) => self.report_error(err),
+            None => self.outbound_tx.push(denial),
+        }
+
+        Ok(())
+    }
+
+    /// Mutes the user in the room the message was sent to if it is found to be spam,
+    /// the message itself is kept and the users of the room are told about the mute
+    ///
+    /// The other rooms are left alone, the user may only have flooded this one.
+    async fn guard_against_spam(&self, room: &str, content: &str) {
+        let user_id = &self.session_and_user_id.user_id;
+        let Some((offense, mute_duration)) = self.spam_guard.check(user_id, content) else {
+            return;
+        };
+
+        if let Err(err) = self
+            .room_manager
+            .auto_mute(room, SPAM_GUARD_ID, user_id, mute_duration.as_secs())
+            .await
+        {
+            warn!(room, %offense, "could not mute a spamming user: {}", err);
+        }
+    }
+
+    /// Records why the command failed, the user is told once the command has been handled
+    fn report_error(&mut self, err: anyhow::Error) {
+        self.failure = Some(err);
+    }
+
+    /// The names of the rooms the user is currently participating in
+    pub fn joined_rooms(&self) -> Vec<String> {
+        self.joined_rooms.keys().cloned().collect()
+    }
+
     // TODO: optimize the performance of this function. leaving one by one may not be a good idea.
-    /// Leave all the rooms the user is currently participating in
-    pub async fn leave_all_rooms(&mut self) -> anyhow::Result<()> {
+    /// Leave all the rooms the user is currently participating in, and stop following their spaces
+    ///
+    /// The user stays a member of the spaces, only leaving them or being removed ends the membership.
+    pub async fn leave_all(&mut self) -> anyhow::Result<()> {
+        for (_, abort_handle) in self.joined_spaces.drain() {
+            abort_handle.abort();
+        }
+
         // drain the joined rooms to a variable, necessary to avoid borrowing self
         let drained = self.joined_rooms.drain().collect::<Vec<_>>();
 
@@ -122,11 +1100,58 @@ impl ChatSession {
         Ok(())
     }
 
-    /// Recieve an event that may have originated from any of the rooms the user is actively participating in
-    pub async fn recv(&mut self) -> anyhow::Result<Event> {
-        self.mpsc_rx
-            .recv()
-            .await
-            .context("could not recv from the broadcast channel")
+    /// Recieve an event that may have originated from any of the rooms the user is actively participating in,
+    /// none once the user has fallen too far behind to be sent the events which can not be dropped
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.outbound_rx.recv().await
+    }
+
+    /// How many events were dropped since the last time they were counted, the user being behind on them
+    pub fn take_dropped_events(&mut self) -> usize {
+        self.outbound_rx.take_dropped()
+    }
+}
+
+impl Drop for ChatSession {
+    fn drop(&mut self) {
+        self.direct_message_router
+            .unregister(&self.session_and_user_id);
+    }
+}
+
+/// Forwards the changes to the room list the user can see to the outbound queue of the user, until the channel is closed
+async fn forward_room_list(
+    mut room_list_rx: broadcast::Receiver<RoomListEvent>,
+    user_id: String,
+    outbound_tx: OutboundSender,
+) {
+    loop {
+        match room_list_rx.recv().await {
+            Ok(room_list_event) if room_list_event.is_visible_to(&user_id) => {
+                outbound_tx.push(room_list_event.event)
+            }
+            Ok(_) => (),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    skipped,
+                    "skipped room list events while forwarding them to the user"
+                )
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Forwards the events of a room or a space to the outbound queue of the user, until the channel is closed
+async fn forward_events(mut broadcast_rx: broadcast::Receiver<Event>, outbound_tx: OutboundSender) {
+    loop {
+        match broadcast_rx.recv().await {
+            Ok(event) => outbound_tx.push(event),
+            // queuing never waits, so only a forwarding task starved by the runtime lags behind
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "skipped events while forwarding them to the user")
+            }
+            Err(RecvError::Closed) => break,
+        }
     }
 }
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+/// How often the clients are pinged, and how many pings in a row they can leave unanswered
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatPolicy {
+    /// Zero disables the pings
+    pub interval: Duration,
+    pub max_missed_pongs: u32,
+}
+
+impl Default for HeartbeatPolicy {
+    fn default() -> Self {
+        HeartbeatPolicy {
+            interval: Duration::from_secs(15),
+            max_missed_pongs: 3,
+        }
+    }
+}
+
+impl HeartbeatPolicy {
+    pub fn is_enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+}
+
+/// What to do on a beat of the [Heartbeat]
+pub(super) enum Beat {
+    /// Ping the client with the nonce, and how long it took to answer the previous ping
+    Ping {
+        nonce: u64,
+        round_trip: Option<Duration>,
+    },
+    /// The client has missed too many pongs, its connection is considered dead
+    Flatline,
+}
+
+/// [Heartbeat] paces the pings of a single connection, and counts the pings left unanswered
+pub(super) struct Heartbeat {
+    /// None when the pings are disabled
+    interval: Option<Interval>,
+    max_missed_pongs: u32,
+    last_nonce: u64,
+    /// The pings sent since the last pong
+    unanswered_pings: u32,
+    /// When the last ping was sent, until its pong is received
+    last_ping_at: Option<Instant>,
+    /// How long the client took to answer the last ping it answered
+    round_trip: Option<Duration>,
+}
+
+impl Heartbeat {
+    pub fn new(policy: &HeartbeatPolicy) -> Self {
+        // the first ping is sent one interval after connecting
+        let interval = policy.is_enabled().then(|| {
+            let mut interval =
+                tokio::time::interval_at(Instant::now() + policy.interval, policy.interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            interval
+        });
+
+        Heartbeat {
+            interval,
+            max_missed_pongs: policy.max_missed_pongs,
+            last_nonce: 0,
+            unanswered_pings: 0,
+            last_ping_at: None,
+            round_trip: None,
+        }
+    }
+
+    /// Waits for the next ping to send, forever if the pings are disabled
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe, it can be used in [tokio::select!].
+    pub async fn beat(&mut self) -> Beat {
+        match self.interval.as_mut() {
+            Some(interval) => interval.tick().await,
+            None => std::future::pending().await,
+        };
+
+        if self.unanswered_pings >= self.max_missed_pongs {
+            return Beat::Flatline;
+        }
+
+        self.unanswered_pings += 1;
+        self.last_nonce += 1;
+        self.last_ping_at = Some(Instant::now());
+
+        Beat::Ping {
+            nonce: self.last_nonce,
+            round_trip: self.round_trip,
+        }
+    }
+
+    /// Any pong shows the connection is alive, even one answering an older ping late
+    ///
+    /// Only the pong of the last ping measures the round trip, a late one would overstate it.
+    pub fn record_pong(&mut self, nonce: u64) {
+        if nonce <= self.last_nonce {
+            self.unanswered_pings = 0;
+        }
+
+        if nonce == self.last_nonce {
+            if let Some(last_ping_at) = self.last_ping_at.take() {
+                self.round_trip = Some(last_ping_at.elapsed());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disabled_heartbeat_never_beats() {
+        let mut heartbeat = Heartbeat::new(&HeartbeatPolicy {
+            interval: Duration::ZERO,
+            max_missed_pongs: 3,
+        });
+
+        let beat = tokio::time::timeout(Duration::from_millis(50), heartbeat.beat()).await;
+
+        assert!(beat.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_flatlines_after_the_missed_pongs() {
+        let mut heartbeat = Heartbeat::new(&HeartbeatPolicy {
+            interval: Duration::from_millis(10),
+            max_missed_pongs: 2,
+        });
+
+        assert!(matches!(
+            heartbeat.beat().await,
+            Beat::Ping { nonce: 1, .. }
+        ));
+        assert!(matches!(
+            heartbeat.beat().await,
+            Beat::Ping { nonce: 2, .. }
+        ));
+        assert!(matches!(heartbeat.beat().await, Beat::Flatline));
+
+        heartbeat.record_pong(1);
+        assert!(matches!(
+            heartbeat.beat().await,
+            Beat::Ping { nonce: 3, .. }
+        ));
+    }
+}
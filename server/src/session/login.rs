@@ -0,0 +1,168 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use comms::{
+    command::{CommandRequest, UserCommand},
+    event::{Event, LoginResultReplyEvent, ResumeResultReplyEvent},
+    transport::DecodeError,
+};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{error, info, warn};
+
+use super::{
+    protocol::VersionedEventWriter,
+    resume::{ResumedSession, SessionRegistry},
+};
+use crate::{
+    storage::{Authentication, CredentialStore},
+    tarpit::{Penalty, Tarpit},
+};
+
+/// How many rejected logins a client gets before it is disconnected
+const MAX_LOGIN_ATTEMPTS: usize = 3;
+/// How long a client has to log in after connecting
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How a client got past the login
+pub(super) enum LoginOutcome {
+    /// The client logged in as the user with the given name
+    LoggedIn(String),
+    /// The client took over its dropped session
+    Resumed(Box<ResumedSession>),
+}
+
+/// Checks the credentials off the async runtime, since hashing the password is slow on purpose
+async fn authenticate(
+    credential_store: &Arc<CredentialStore>,
+    username: String,
+    password: String,
+) -> Authentication {
+    let credential_store = Arc::clone(credential_store);
+    let result =
+        tokio::task::spawn_blocking(move || credential_store.authenticate(&username, &password))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+
+    result.unwrap_or_else(|err| {
+        error!("could not check the credentials: {}", err);
+
+        Authentication::Rejected {
+            reason: String::from("could not check the credentials, try again later"),
+        }
+    })
+}
+
+/// Waits for the client to log in or resume its dropped session, replying to each attempt with its result
+///
+/// The other commands are ignored until the client is logged in, the invalid ones are struck in the tarpit like after the login.
+/// The rejected logins and resumes strike the ip of the client in the tarpit, so the passwords and the tokens can not be guessed
+/// by reconnecting for more attempts.
+/// Returns None if the client disconnected, timed out, ran out of attempts or was banned.
+pub(super) async fn wait_for_login<S>(
+    commands: &mut S,
+    event_writer: &mut VersionedEventWriter,
+    credential_store: &Arc<CredentialStore>,
+    session_registry: &SessionRegistry,
+    tarpit: &Tarpit,
+    peer_ip: IpAddr,
+) -> anyhow::Result<Option<LoginOutcome>>
+where
+    S: Stream<Item = Result<CommandRequest, DecodeError>> + Unpin,
+{
+    let login = async {
+        let mut attempts = 0;
+
+        while let Some(cmd) = commands.next().await {
+            let cmd = match cmd.map(|request| request.command) {
+                Ok(UserCommand::Login(cmd)) => cmd,
+                // a session which can not be resumed anymore does not use up a login attempt, but is struck in the tarpit
+                Ok(UserCommand::ResumeSession(cmd)) => {
+                    let resumed_session = session_registry.resume(&cmd.token).await;
+
+                    event_writer
+                        .write(Event::ResumeResult(ResumeResultReplyEvent {
+                            is_accepted: resumed_session.is_some(),
+                            reason: resumed_session
+                                .is_none()
+                                .then(|| String::from("the session has expired")),
+                        }))
+                        .await?;
+
+                    match resumed_session {
+                        Some(resumed_session) => {
+                            return Ok(Some(LoginOutcome::Resumed(Box::new(resumed_session))))
+                        }
+                        None => match tarpit.strike(peer_ip) {
+                            Penalty::None => continue,
+                            Penalty::Delay(delay) => {
+                                tokio::time::sleep(delay).await;
+                                continue;
+                            }
+                            Penalty::Ban => break,
+                        },
+                    }
+                }
+                // The commands end right after the connection fails or loses its framing, as if the client closed it
+                Err(err) if !err.is_recoverable() => {
+                    info!("could not read from the client: {}", err);
+                    break;
+                }
+                // Clients which keep sending invalid commands are slowed down, then disconnected, logged in or not
+                Err(_) => match tarpit.strike(peer_ip) {
+                    Penalty::None => continue,
+                    Penalty::Delay(delay) => {
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    Penalty::Ban => break,
+                },
+                _ => continue,
+            };
+
+            let authentication =
+                authenticate(credential_store, cmd.username.clone(), cmd.password).await;
+            let (is_new_user, reason) = match authentication {
+                Authentication::Accepted => (false, None),
+                Authentication::Registered => {
+                    info!(username = %cmd.username, "registered the user");
+                    (true, None)
+                }
+                Authentication::Rejected { reason } => (false, Some(reason)),
+            };
+            let is_accepted = reason.is_none();
+
+            event_writer
+                .write(Event::LoginResult(LoginResultReplyEvent {
+                    is_accepted,
+                    is_new_user,
+                    reason,
+                }))
+                .await?;
+
+            if is_accepted {
+                return Ok(Some(LoginOutcome::LoggedIn(cmd.username)));
+            }
+
+            match tarpit.strike(peer_ip) {
+                Penalty::None => {}
+                Penalty::Delay(delay) => tokio::time::sleep(delay).await,
+                Penalty::Ban => {
+                    warn!(username = %cmd.username, "banned after too many failed logins");
+                    break;
+                }
+            }
+
+            attempts += 1;
+            if attempts >= MAX_LOGIN_ATTEMPTS {
+                warn!(username = %cmd.username, "too many failed logins");
+                break;
+            }
+        }
+
+        Ok(None)
+    };
+
+    tokio::time::timeout(LOGIN_TIMEOUT, login)
+        .await
+        .unwrap_or(Ok(None))
+}
@@ -1,88 +1,790 @@
-use std::sync::Arc;
+use std::{net::IpAddr, ops::ControlFlow, sync::Arc, time::Duration};
 
 use comms::{
-    command::UserCommand,
-    event::{self, RoomDetail},
-    transport,
+    command::{CommandRequest, UserCommand},
+    event::{self, SpaceDetail},
+    protocol::features,
 };
 use nanoid::nanoid;
-use tokio::{net::TcpStream, sync::broadcast};
+use tokio::sync::{broadcast, watch};
 use tokio_stream::StreamExt;
+use tracing::{debug, info, warn, Instrument};
 
-use crate::room_manager::RoomManager;
+/// How long writing an event to the client can take before the client is disconnected as a slow consumer
+const SLOW_CONSUMER_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a slow consumer is given to read why it is disconnected
+const DISCONNECTED_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
 
-use self::chat_session::ChatSession;
+use crate::{
+    access_log::AccessLog,
+    clock::now_millis,
+    command_error::CommandError,
+    direct_message_router::DirectMessageRouter,
+    metrics::ServerMetrics,
+    presence_tracker::{PresenceTracker, MAX_AWAY_MESSAGE_CHARS, MAX_DISPLAY_NAME_CHARS},
+    room_manager::{RoomManager, RoomVisibility},
+    space_manager::SpaceManager,
+    spam_guard::SpamGuard,
+    storage::{CredentialStore, ProfileChange, ProfileStore},
+    tarpit::{Penalty, Tarpit},
+};
+
+use self::{
+    chat_session::ChatSession,
+    heartbeat::{Beat, Heartbeat},
+    login::LoginOutcome,
+    protocol::VersionedEventWriter,
+    rate_limiter::SessionRateLimiter,
+    resume::ResumedSession,
+};
+pub use self::{
+    heartbeat::HeartbeatPolicy,
+    outbound_queue::{OutboundSender, DEFAULT_OUTBOUND_QUEUE_CAPACITY},
+    protocol::ProtocolMetrics,
+    rate_limiter::{RateLimit, RateLimitPolicy, TokenBucket},
+    resume::SessionRegistry,
+    transport::Transport,
+};
 
 mod chat_session;
+mod heartbeat;
+mod login;
+mod outbound_queue;
+mod protocol;
+mod rate_limiter;
+mod resume;
+mod transport;
+mod user_data;
+
+/// [SessionContext] holds the server wide state shared by the user sessions
+#[derive(Debug, Clone)]
+pub struct SessionContext {
+    pub room_manager: Arc<RoomManager>,
+    pub space_manager: Arc<SpaceManager>,
+    pub direct_message_router: Arc<DirectMessageRouter>,
+    pub access_log: Arc<AccessLog>,
+    pub credential_store: Arc<CredentialStore>,
+    pub profile_store: Arc<ProfileStore>,
+    pub protocol_metrics: Arc<ProtocolMetrics>,
+    /// The latencies of the commands and of the messages, served with the metrics
+    pub server_metrics: Arc<ServerMetrics>,
+    pub session_registry: Arc<SessionRegistry>,
+    pub presence_tracker: Arc<PresenceTracker>,
+    pub tarpit: Arc<Tarpit>,
+    /// Mutes the users caught spamming in the rooms, with thresholds changed when the configuration is reloaded
+    pub spam_guard: Arc<SpamGuard>,
+    /// How many messages and joins each connection can send, changed for every connection when the configuration is reloaded
+    pub rate_limit_policy: watch::Receiver<RateLimitPolicy>,
+    /// How often the clients answering pings are pinged, and how many pongs they can miss
+    pub heartbeat_policy: HeartbeatPolicy,
+    /// The oldest version of the protocol the clients are served with, the older ones are disconnected
+    pub min_protocol_version: u16,
+    /// How long to wait before anonymizing the messages of a deleted account
+    pub account_deletion_grace_period: Duration,
+    /// The events of the server itself sent to every session, such as the announcements of its operator and its shutdown
+    pub server_events: broadcast::Sender<event::Event>,
+    /// How many events are queued for each client before the superseded ones are dropped, or the client disconnected
+    pub outbound_queue_capacity: usize,
+}
+
+/// Schedules the account deletions which the server had not finished before it last stopped,
+/// the ones whose grace period has passed meanwhile are finished right away
+///
+/// Returns how many deletions were scheduled.
+pub fn resume_account_deletions(session_context: &SessionContext) -> anyhow::Result<usize> {
+    let deletions = session_context.credential_store.pending_deletions()?;
 
-/// Given a tcp stream and a room manager, handles the user session
-/// until the user quits the session, or the tcp stream is closed for some reason, or the server shuts down
-pub async fn handle_user_session(
-    room_manager: Arc<RoomManager>,
+    for (user_id, deleted_at) in deletions.iter() {
+        user_data::schedule_account_deletion(
+            Arc::clone(&session_context.room_manager),
+            Arc::clone(&session_context.access_log),
+            Arc::clone(&session_context.credential_store),
+            user_id,
+            deleted_at + session_context.account_deletion_grace_period.as_millis() as u64,
+        );
+    }
+
+    Ok(deletions.len())
+}
+
+/// Given an accepted connection over any transport and the server wide state, handles the user session
+/// until the user quits the session, or the stream is closed for some reason, or the server shuts down
+#[tracing::instrument(name = "session", skip_all, fields(%peer_ip, session_id, username))]
+pub async fn handle_user_session<T: Transport>(
+    context: SessionContext,
     mut quit_rx: broadcast::Receiver<()>,
-    stream: TcpStream,
+    peer_ip: IpAddr,
+    transport: T,
 ) -> anyhow::Result<()> {
-    let session_id = nanoid!();
-    // Generate a random id for the user, since we don't have a login system
-    let user_id = String::from(&nanoid!()[0..5]);
-    // Split the tcp stream into a command stream and an event writer with better ergonomics
-    let (mut commands, mut event_writer) = transport::server::split_tcp_stream(stream);
+    let SessionContext {
+        room_manager,
+        space_manager,
+        direct_message_router,
+        access_log,
+        credential_store,
+        profile_store,
+        protocol_metrics,
+        server_metrics,
+        session_registry,
+        presence_tracker,
+        tarpit,
+        spam_guard,
+        mut rate_limit_policy,
+        heartbeat_policy,
+        min_protocol_version,
+        account_deletion_grace_period,
+        server_events,
+        outbound_queue_capacity,
+    } = context;
+    let mut server_events_rx = server_events.subscribe();
+    let (mut commands, event_writer) = transport.split();
+    // Old and new clients are served side by side, the version is detected before the login
+    let (protocol_version, first_command) = protocol::negotiate_protocol(&mut commands).await;
+    let _tracked_session = protocol_metrics.track_session(protocol_version);
+    let mut event_writer = VersionedEventWriter::new(event_writer, protocol_version);
+
+    // The clients the server does not serve anymore are told so, instead of failing on the events they do not understand
+    if protocol_version.number() < min_protocol_version {
+        event_writer
+            .write(event::Event::ProtocolRejected(
+                event::ProtocolRejectedReplyEvent {
+                    min_protocol_version,
+                },
+            ))
+            .await?;
+
+        return Ok(());
+    }
 
-    // Welcoming the user with a login successful event and necessary information about the server
+    // Only the clients which answer the pings are pinged
+    let is_heartbeat_enabled = protocol_version.has_heartbeat() && heartbeat_policy.is_enabled();
+    let mut features = vec![
+        features::REQUEST_IDS,
+        features::SESSION_RESUME,
+        features::RATE_LIMITS,
+        features::MESSAGE_SEARCH,
+        features::HISTORY_PAGINATION,
+        features::READ_MARKERS,
+        features::FILE_TRANSFER,
+        features::NICKNAMES,
+        features::PROFILES,
+        features::ROOM_DIRECTORY,
+    ];
+    if is_heartbeat_enabled {
+        features.push(features::HEARTBEAT);
+    }
+
+    // Only the clients which know about the welcome are sent it, see [comms::protocol::translate_event]
     event_writer
-        .write(&event::Event::LoginSuccessful(
-            event::LoginSuccessfulReplyEvent {
-                session_id: session_id.clone(),
-                user_id: user_id.clone(),
-                rooms: room_manager
-                    .chat_room_metadatas()
-                    .iter()
-                    .map(|metadata| RoomDetail {
-                        name: metadata.name.clone(),
-                        description: metadata.description.clone(),
-                    })
-                    .collect(),
+        .write(event::Event::Welcome(event::WelcomeReplyEvent {
+            protocol_version: protocol_version.number(),
+            max_message_chars: room_manager.max_message_chars(),
+            features: features.into_iter().map(String::from).collect(),
+            heartbeat_interval: is_heartbeat_enabled
+                .then_some(heartbeat_policy.interval.as_millis() as u64),
+        }))
+        .await?;
+    // A v1 client may have sent a command instead of a hello, it is processed first
+    let mut commands = tokio_stream::iter(first_command.map(Ok)).chain(commands);
+
+    let login_outcome = if protocol_version.has_login() {
+        tokio::select! {
+            outcome = login::wait_for_login(&mut commands, &mut event_writer, &credential_store, &session_registry, &tarpit, peer_ip) => match outcome? {
+                Some(outcome) => outcome,
+                None => return Ok(()),
+            },
+            Ok(_) = quit_rx.recv() => return Ok(()),
+        }
+    } else {
+        // Older clients can not log in, they are given a random guest id, which is never a valid username
+        LoginOutcome::LoggedIn(format!("guest-{}", &nanoid!()[0..5]))
+    };
+
+    let (session_id, user_id, resume_token, mut chat_session) = match login_outcome {
+        LoginOutcome::LoggedIn(user_id) => {
+            let session_id = nanoid!();
+            // Only the users who logged in can resume their sessions, guests have nothing to prove who they are
+            let resume_token = protocol_version.has_login().then(|| nanoid!());
+
+            access_log.record_connect(&session_id, &user_id);
+
+            // Welcoming the user with a login successful event and necessary information about the server
+            event_writer
+                .write(event::Event::LoginSuccessful(
+                    event::LoginSuccessfulReplyEvent {
+                        session_id: session_id.clone(),
+                        user_id: user_id.clone(),
+                        // private rooms are only revealed to the users invited to them
+                        rooms: room_manager
+                            .chat_room_metadatas()
+                            .iter()
+                            .filter(|metadata| metadata.visibility == RoomVisibility::Public)
+                            .map(|metadata| metadata.to_room_detail())
+                            .collect(),
+                        spaces: space_manager
+                            .chat_space_metadatas()
+                            .iter()
+                            .map(|metadata| SpaceDetail {
+                                name: metadata.name.clone(),
+                                description: metadata.description.clone(),
+                                rooms: metadata.rooms.clone(),
+                                default_rooms: metadata.default_rooms.clone(),
+                            })
+                            .collect(),
+                        resume_token: resume_token.clone(),
+                    },
+                ))
+                .await?;
+
+            // Create a chat session with the given room manager
+            // Chat Session will abstract the user session handling logic for multiple rooms
+            let mut chat_session = ChatSession::new(
+                &session_id,
+                &user_id,
+                Arc::clone(&room_manager),
+                Arc::clone(&space_manager),
+                Arc::clone(&direct_message_router),
+                spam_guard,
+                outbound_queue_capacity,
+            );
+            // the spaces the user joined in their previous sessions are still theirs
+            chat_session.follow_spaces().await;
+
+            (session_id, user_id, resume_token, chat_session)
+        }
+        // The resumed session is still in its rooms, the client only misses the events sent while it was away
+        LoginOutcome::Resumed(resumed_session) => {
+            let ResumedSession {
+                token,
+                session_id,
+                user_id,
+                chat_session,
+                missed_events,
+            } = *resumed_session;
+
+            access_log.record_connect(&session_id, &user_id);
+
+            for event in missed_events {
+                event_writer.write(event).await?;
+            }
+
+            (session_id, user_id, Some(token), chat_session)
+        }
+    };
+    tracing::Span::current()
+        .record("session_id", session_id.as_str())
+        .record("username", user_id.as_str());
+
+    // The user is online while connected, and is told about the presence of the others
+    // The display name and the color are only shown along with the presence, the user is shown without the ones
+    // which can not be read
+    let (credentials, profiles) = (Arc::clone(&credential_store), Arc::clone(&profile_store));
+    let connected_user_id = user_id.clone();
+    let (display_name, color) = run_blocking(move || {
+        Ok((
+            credentials.display_name(&connected_user_id),
+            profiles
+                .profile(&connected_user_id)
+                .map(|profile| profile.color),
+        ))
+    })
+    .await
+    .unwrap_or_else(|err| (Err(err), Ok(None)));
+    let display_name = display_name.unwrap_or_else(|err| {
+        warn!("could not read the display name: {:#}", err);
+        None
+    });
+    let color = color.unwrap_or_else(|err| {
+        warn!("could not read the profile: {:#}", err);
+        None
+    });
+    let mut presence_rx = presence_tracker.subscribe();
+    let _connected_session = presence_tracker.connect(&user_id, &session_id, display_name, color);
+    event_writer
+        .write(event::Event::PresenceSnapshot(
+            event::PresenceSnapshotReplyEvent {
+                users: presence_tracker.snapshot(),
             },
         ))
         .await?;
 
-    // Create a chat session with the given room manager
-    // Chat Session will abstract the user session handling logic for multiple rooms
-    let mut chat_session = ChatSession::new(&session_id, &user_id, room_manager);
+    // The limits apply to the connection, a resumed session starts over with full buckets
+    let mut rate_limiter = SessionRateLimiter::new(&rate_limit_policy.borrow_and_update());
+    let mut heartbeat = Heartbeat::new(&heartbeat_policy);
 
-    loop {
+    // Whether the session is kept for the client to resume it after the connection dropped
+    let is_kept = loop {
         tokio::select! {
             cmd = commands.next() => match cmd {
+                // If the tcp stream of a user who can resume the session is closed, the session is kept for a while
+                None if resume_token.is_some() => break true,
                 // If the user closes the tcp stream, or sends a quit cmd
                 // We need to cleanup resources in a way that the other users are notified about the user's departure
-                None | Some(Ok(UserCommand::Quit(_))) => {
-                    chat_session.leave_all_rooms().await?;
-                    break;
+                None | Some(Ok(CommandRequest { command: UserCommand::Quit(_), .. })) => {
+                    chat_session.leave_all().await?;
+                    break false;
                 }
                 // Handle a valid user command
-                Some(Ok(cmd)) => match cmd {
+                Some(Ok(CommandRequest { request_id, command: cmd })) => {
+                    // Answering the pings does not make an idle user active
+                    if !matches!(cmd, UserCommand::Pong(_)) {
+                        presence_tracker.record_activity(&user_id, &session_id);
+                    }
+
+                    if rate_limit_policy.has_changed().unwrap_or(false) {
+                        rate_limiter.set_policy(&rate_limit_policy.borrow_and_update());
+                    }
+                    // Commands sent too fast are dropped, the user is told when to send them again
+                    if let Err(retry_after) = rate_limiter.check(&cmd) {
+                        event_writer
+                            .write(event::Event::RateLimited(event::RateLimitedReplyEvent {
+                                retry_after: retry_after.as_millis() as u64,
+                            }))
+                            .await?;
+                        fail_request(&mut event_writer, request_id, event::ErrorCode::RateLimited, "the command was sent too fast").await?;
+                        continue;
+                    }
+
+                    let command_name = cmd.name();
+                    let command_span = command_span(&cmd, request_id.as_deref());
+                    let started_at = std::time::Instant::now();
+
+                    let flow = async {
+                    match cmd {
+                    UserCommand::Pong(cmd) => heartbeat.record_pong(cmd.nonce),
+                    UserCommand::SetPresence(cmd) => {
+                        let away_message = cmd
+                            .away_message
+                            .map(|away_message| String::from(away_message.trim()))
+                            .filter(|away_message| !away_message.is_empty());
+
+                        if away_message.as_ref().is_some_and(|away_message| away_message.chars().count() > MAX_AWAY_MESSAGE_CHARS) {
+                            let err = CommandError::MessageTooLong(MAX_AWAY_MESSAGE_CHARS);
+
+                            event_writer
+                                .write(event::Event::CommandError(event::CommandErrorEvent {
+                                    request_id,
+                                    code: err.code(),
+                                    message: err.to_string(),
+                                }))
+                                .await?;
+                        } else {
+                            presence_tracker.set_away_message(&user_id, away_message);
+                            acknowledge_request(&mut event_writer, request_id).await?;
+                        }
+                    }
+                    UserCommand::ChangeNickname(cmd) => {
+                        let display_name = cmd
+                            .nickname
+                            .map(|nickname| String::from(nickname.trim()))
+                            .filter(|nickname| !nickname.is_empty());
+
+                        if display_name.as_ref().is_some_and(|display_name| {
+                            display_name.chars().count() > MAX_DISPLAY_NAME_CHARS
+                                || display_name.chars().any(char::is_control)
+                        }) {
+                            let err = CommandError::InvalidDisplayName(MAX_DISPLAY_NAME_CHARS);
+
+                            event_writer
+                                .write(event::Event::CommandError(event::CommandErrorEvent {
+                                    request_id,
+                                    code: err.code(),
+                                    message: err.to_string(),
+                                }))
+                                .await?;
+                        } else {
+                            let credentials = Arc::clone(&credential_store);
+                            let (renamed_user_id, stored_display_name) = (user_id.clone(), display_name.clone());
+                            let stored = run_blocking(move || {
+                                credentials.set_display_name(&renamed_user_id, stored_display_name.as_deref())
+                            })
+                            .await;
+
+                            // the nickname is only shown once it is stored, so it is not lost on the next login
+                            match stored {
+                                Ok(()) => {
+                                    presence_tracker.set_display_name(&user_id, display_name);
+                                    acknowledge_request(&mut event_writer, request_id).await?;
+                                }
+                                Err(err) => report_error(&mut event_writer, request_id, err).await?,
+                            }
+                        }
+                    }
+                    UserCommand::GetUserProfile(cmd) => {
+                        let (credentials, profiles) = (Arc::clone(&credential_store), Arc::clone(&profile_store));
+                        let profile = run_blocking(move || {
+                            Ok(event::UserProfile {
+                                display_name: credentials.display_name(&cmd.user_id)?,
+                                ..profiles.profile(&cmd.user_id)?
+                            })
+                        })
+                        .await;
+
+                        match profile {
+                            Ok(profile) => {
+                                event_writer
+                                    .write(event::Event::UserProfile(event::UserProfileReplyEvent { profile }))
+                                    .await?;
+                                acknowledge_request(&mut event_writer, request_id).await?;
+                            }
+                            Err(err) => report_error(&mut event_writer, request_id, err).await?,
+                        }
+                    }
+                    UserCommand::UpdateProfile(cmd) => {
+                        let trim = |field: Option<String>| field.map(|field| String::from(field.trim()));
+                        let change = ProfileChange {
+                            bio: trim(cmd.bio),
+                            pronouns: trim(cmd.pronouns),
+                            timezone: trim(cmd.timezone),
+                            color: trim(cmd.color).map(|color| color.to_lowercase()),
+                        };
+
+                        if let Some(reason) = change.validate() {
+                            let err = CommandError::InvalidProfile(reason);
+
+                            event_writer
+                                .write(event::Event::CommandError(event::CommandErrorEvent {
+                                    request_id,
+                                    code: err.code(),
+                                    message: err.to_string(),
+                                }))
+                                .await?;
+                        } else {
+                            let (credentials, profiles) = (Arc::clone(&credential_store), Arc::clone(&profile_store));
+                            let updated_user_id = user_id.clone();
+                            // the user is told about their profile as changed, to show it
+                            let profile = run_blocking(move || {
+                                Ok(event::UserProfile {
+                                    display_name: credentials.display_name(&updated_user_id)?,
+                                    ..profiles.update(&updated_user_id, change)?
+                                })
+                            })
+                            .await;
+
+                            match profile {
+                                Ok(profile) => {
+                                    presence_tracker.set_color(&user_id, profile.color.clone());
+                                    event_writer
+                                        .write(event::Event::UserProfile(event::UserProfileReplyEvent { profile }))
+                                        .await?;
+                                    acknowledge_request(&mut event_writer, request_id).await?;
+                                }
+                                Err(err) => report_error(&mut event_writer, request_id, err).await?,
+                            }
+                        }
+                    }
                     // For user session related commands, we need to handle them in the chat session
-                    UserCommand::JoinRoom(_) | UserCommand::SendMessage(_) | UserCommand::LeaveRoom(_) => {
-                        chat_session.handle_user_command(cmd).await?;
+                    UserCommand::JoinRoom(_)
+                    | UserCommand::SendMessage(_)
+                    | UserCommand::EditMessage(_)
+                    | UserCommand::DeleteMessage(_)
+                    | UserCommand::ReactToMessage(_)
+                    | UserCommand::MarkRead(_)
+                    | UserCommand::LeaveRoom(_)
+                    | UserCommand::CreateRoom(_)
+                    | UserCommand::DeleteRoom(_)
+                    | UserCommand::SetRoomTopic(_)
+                    | UserCommand::SetRoomRole(_)
+                    | UserCommand::KickUser(_)
+                    | UserCommand::BanUser(_)
+                    | UserCommand::MuteUser(_)
+                    | UserCommand::FetchRoomHistory(_)
+                    | UserCommand::FetchMessagesBefore(_)
+                    | UserCommand::ExportRoomHistory(_)
+                    | UserCommand::SearchMessages(_)
+                    | UserCommand::BrowseRooms(_)
+                    | UserCommand::StartUpload(_)
+                    | UserCommand::UploadChunk(_)
+                    | UserCommand::DownloadFile(_)
+                    | UserCommand::JoinSpace(_)
+                    | UserCommand::LeaveSpace(_)
+                    | UserCommand::SetSpaceRole(_)
+                    | UserCommand::RemoveSpaceMember(_)
+                    | UserCommand::SendDirectMessage(_)
+                    | UserCommand::InviteUser(_)
+                    | UserCommand::DeclineInvitation(_) => {
+                        chat_session.handle_user_command(cmd, request_id).await?;
+                    }
+                    UserCommand::ExportMyData(_) => {
+                        let (credentials, profiles) = (Arc::clone(&credential_store), Arc::clone(&profile_store));
+                        let exported_user_id = user_id.clone();
+                        let stored = run_blocking(move || {
+                            Ok((credentials.display_name(&exported_user_id)?, profiles.profile(&exported_user_id)?))
+                        })
+                        .await;
+
+                        match stored {
+                            Ok((display_name, profile)) => {
+                                let export = user_data::export_user_data(
+                                    &room_manager,
+                                    &access_log,
+                                    &user_id,
+                                    chat_session.joined_rooms(),
+                                    display_name,
+                                    profile,
+                                )
+                                .await?;
+
+                                event_writer.write(event::Event::UserDataExport(export)).await?;
+                                acknowledge_request(&mut event_writer, request_id).await?;
+                            }
+                            Err(err) => report_error(&mut event_writer, request_id, err).await?,
+                        }
+                    }
+                    // The session ends once the deletion is scheduled, the user id is never handed out again
+                    UserCommand::DeleteMyAccount(_) => {
+                        let (credentials, profiles) = (Arc::clone(&credential_store), Arc::clone(&profile_store));
+                        let deleted_user_id = user_id.clone();
+                        let deleted = run_blocking(move || {
+                            credentials.delete_user(&deleted_user_id)?;
+                            profiles.delete_user(&deleted_user_id)
+                        })
+                        .await;
+                        if let Err(err) = deleted {
+                            report_error(&mut event_writer, request_id, err).await?;
+                            return Ok(ControlFlow::Continue(()));
+                        }
+
+                        // the other sessions of the user end as well, and their dropped sessions can not be resumed anymore
+                        let _ = direct_message_router.deliver(
+                            &user_id,
+                            event::Event::SessionKicked(event::SessionKickedEvent {
+                                reason: String::from("the account was deleted"),
+                            }),
+                        );
+                        // the spaces are left for good, unlike when a session ends
+                        space_manager.leave_all_spaces(&user_id).await;
+                        chat_session.leave_all().await?;
+
+                        let delete_at = now_millis() + account_deletion_grace_period.as_millis() as u64;
+                        user_data::schedule_account_deletion(
+                            Arc::clone(&room_manager),
+                            Arc::clone(&access_log),
+                            Arc::clone(&credential_store),
+                            &user_id,
+                            delete_at,
+                        );
+
+                        event_writer
+                            .write(event::Event::AccountDeletionScheduled(
+                                event::AccountDeletionScheduledReplyEvent { delete_at },
+                            ))
+                            .await?;
+                        acknowledge_request(&mut event_writer, request_id).await?;
+                        return Ok(ControlFlow::Break(false));
+                    }
+                    // the version is only negotiated, and the user logged in, once right after connecting
+                    _ => {
+                        fail_request(&mut event_writer, request_id, event::ErrorCode::Failed, "the command is only accepted right after connecting").await?;
+                    }
+                    }
+
+                    Ok::<_, anyhow::Error>(ControlFlow::Continue(()))
+                    }
+                    .instrument(command_span)
+                    .await?;
+
+                    server_metrics.observe_command(command_name, started_at.elapsed());
+                    if let ControlFlow::Break(is_kept) = flow {
+                        break is_kept;
+                    }
+                }
+                // The commands end right after the connection fails or loses its framing, as if the client closed it
+                Some(Err(err)) if !err.is_recoverable() => {
+                    info!("could not read from the client: {}", err);
+                }
+                // Clients which keep sending invalid commands are slowed down, then disconnected
+                Some(Err(_)) => {
+                    match tarpit.strike(peer_ip) {
+                        Penalty::None => {}
+                        Penalty::Delay(delay) => tokio::time::sleep(delay).await,
+                        Penalty::Ban => {
+                            chat_session.leave_all().await?;
+                            break false;
+                        }
                     }
-                    _ => {}
                 }
-                _ => {}
             },
+            // A connection which stopped answering the pings is dead, the session is not kept for it
+            beat = heartbeat.beat(), if is_heartbeat_enabled => match beat {
+                Beat::Ping { nonce, round_trip } => {
+                    let round_trip = round_trip.map(|round_trip| round_trip.as_millis() as u64);
+                    event_writer.write(event::Event::Ping(event::PingEvent { nonce, round_trip })).await?;
+                }
+                Beat::Flatline => {
+                    info!("dropping the session, it missed {} pongs", heartbeat_policy.max_missed_pongs);
+                    chat_session.leave_all().await?;
+                    break false;
+                }
+            },
+            // The presence changes of every user are sent to the user
+            Ok(event) = presence_rx.recv() => {
+                event_writer.write(event).await?;
+            }
+            // Once the server is shutting down, the session goes on until the server closes the connection
+            Ok(event) = server_events_rx.recv() => {
+                event_writer.write(event).await?;
+            }
             // Aggregated events from the chat session are sent to the user
-            Ok(event) = chat_session.recv() => {
-                event_writer.write(&event).await?;
+            // A client which can not keep up is disconnected, rather than having its events pile up on the server
+            event = chat_session.recv() => {
+                server_metrics.record_dropped_events(chat_session.take_dropped_events());
+                let Some(event) = event else {
+                    info!("closing the connection, the client can not keep up with its events");
+                    disconnect_slow_consumer(&mut event_writer, &server_metrics).await;
+                    chat_session.leave_all().await?;
+                    break false;
+                };
+                chat_session.handle_event(&event).await?;
+                // the timestamp of a message is taken when it is broadcast to the room
+                let sent_at = match &event {
+                    event::Event::UserMessage(message) => Some(message.timestamp),
+                    _ => None,
+                };
+                let is_kicked = matches!(event, event::Event::SessionKicked(_));
+                match tokio::time::timeout(SLOW_CONSUMER_WRITE_TIMEOUT, event_writer.write(event)).await {
+                    Ok(written) => written?,
+                    // the event may be partly written, the client is not told why as it stopped reading anyway
+                    Err(_) => {
+                        info!("closing the connection, the client stopped reading its events");
+                        server_metrics.record_slow_consumer();
+                        chat_session.leave_all().await?;
+                        break false;
+                    }
+                }
+                if let Some(sent_at) = sent_at {
+                    server_metrics.observe_fan_out(Duration::from_millis(now_millis().saturating_sub(sent_at)));
+                }
+                // the user is told why before being disconnected, the session can not be resumed
+                if is_kicked {
+                    info!("closing the connection, the session was kicked");
+                    chat_session.leave_all().await?;
+                    break false;
+                }
             }
             // If the server is shutting down, we can just close the tcp streams
             // and exit the session handler. Since the server is shutting down,
             // we don't need to notify other users about the user's departure or cleanup resources
             Ok(_) = quit_rx.recv() => {
                 drop(event_writer);
-                println!("Gracefully shutting down user tcp stream.");
-                break;
+                debug!("closing the connection, the server is shutting down");
+                break false;
             }
         }
+    };
+
+    access_log.record_disconnect(&session_id);
+
+    if let (true, Some(resume_token)) = (is_kept, resume_token) {
+        session_registry.keep(resume_token, session_id, user_id, chat_session);
+    }
+
+    Ok(())
+}
+
+/// Tells the client it is disconnected for not keeping up, as far as it still reads its events
+async fn disconnect_slow_consumer(
+    event_writer: &mut VersionedEventWriter,
+    server_metrics: &ServerMetrics,
+) {
+    server_metrics.record_slow_consumer();
+
+    let _ = tokio::time::timeout(
+        DISCONNECTED_WRITE_TIMEOUT,
+        event_writer.write(event::Event::Disconnected(event::DisconnectedEvent {
+            reason: event::DisconnectReason::SlowConsumer,
+        })),
+    )
+    .await;
+}
+
+/// Tells the client the command sent with the request id has been run, the commands sent without one are not acknowledged
+async fn acknowledge_request(
+    event_writer: &mut VersionedEventWriter,
+    request_id: Option<String>,
+) -> anyhow::Result<()> {
+    if let Some(request_id) = request_id {
+        event_writer
+            .write(event::Event::CommandAck(event::CommandAckEvent {
+                request_id,
+            }))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Tells the client the command sent with the request id has failed, so it does not wait for an answer in vain
+async fn fail_request(
+    event_writer: &mut VersionedEventWriter,
+    request_id: Option<String>,
+    code: event::ErrorCode,
+    message: &str,
+) -> anyhow::Result<()> {
+    if let Some(request_id) = request_id {
+        event_writer
+            .write(event::Event::CommandError(event::CommandErrorEvent {
+                request_id: Some(request_id),
+                code,
+                message: String::from(message),
+            }))
+            .await?;
     }
 
     Ok(())
 }
+
+/// Tells the client the command has failed, whether it was sent with a request id or not,
+/// rather than ending the session over a failure of the server
+async fn report_error(
+    event_writer: &mut VersionedEventWriter,
+    request_id: Option<String>,
+    err: anyhow::Error,
+) -> anyhow::Result<()> {
+    warn!("the command has failed: {:#}", err);
+
+    event_writer
+        .write(event::Event::CommandError(event::CommandErrorEvent {
+            request_id,
+            code: CommandError::code_of(&err),
+            message: err.to_string(),
+        }))
+        .await
+}
+
+/// Runs a call to the stores off the threads of the runtime, as the stores block on their database
+async fn run_blocking<T: Send + 'static>(
+    call: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(call).await?
+}
+
+/// The span of a command, with the room and the message it is about, if any
+fn command_span(cmd: &UserCommand, request_id: Option<&str>) -> tracing::Span {
+    let (room, message_id) = match cmd {
+        UserCommand::EditMessage(cmd) => (Some(cmd.room.as_str()), Some(cmd.id)),
+        UserCommand::DeleteMessage(cmd) => (Some(cmd.room.as_str()), Some(cmd.id)),
+        UserCommand::ReactToMessage(cmd) => (Some(cmd.room.as_str()), Some(cmd.id)),
+        UserCommand::MarkRead(cmd) => (Some(cmd.room.as_str()), Some(cmd.id)),
+        UserCommand::SendMessage(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::JoinRoom(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::LeaveRoom(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::CreateRoom(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::DeleteRoom(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::SetRoomTopic(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::SetRoomRole(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::KickUser(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::BanUser(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::MuteUser(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::FetchRoomHistory(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::FetchMessagesBefore(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::ExportRoomHistory(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::SearchMessages(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::StartUpload(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::InviteUser(cmd) => (Some(cmd.room.as_str()), None),
+        UserCommand::DeclineInvitation(cmd) => (Some(cmd.room.as_str()), None),
+        _ => (None, None),
+    };
+
+    tracing::info_span!("command", name = cmd.name(), request_id, room, message_id)
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use comms::event::Event;
+use tokio::sync::Notify;
+
+/// How many events are queued for a client by default, before they are dropped or the client is disconnected
+pub const DEFAULT_OUTBOUND_QUEUE_CAPACITY: usize = 512;
+
+/// What a later event replaces in an earlier one, an event being dropped on its way to a client which can not
+/// keep up only once a later event with the same key is queued
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum SupersessionKey<'a> {
+    /// The presence of the user, sent whole
+    Presence(&'a str),
+    /// The reactions to a message of a room, sent whole
+    Reactions(&'a str, u64),
+    /// The number of bytes received of an upload, counted from its start
+    UploadProgress(&'a str),
+    /// The heartbeat only waits on the answer to its latest ping
+    Ping,
+}
+
+fn supersession_key(event: &Event) -> Option<SupersessionKey<'_>> {
+    match event {
+        Event::PresenceChanged(event) => Some(SupersessionKey::Presence(&event.presence.user_id)),
+        Event::MessageReactions(event) => Some(SupersessionKey::Reactions(&event.room, event.id)),
+        Event::UploadProgress(event) => Some(SupersessionKey::UploadProgress(&event.upload_id)),
+        Event::Ping(_) => Some(SupersessionKey::Ping),
+        _ => None,
+    }
+}
+
+/// Finds the oldest queued event which a later queued event, or the event to queue, supersedes
+fn find_superseded(events: &VecDeque<Event>, event: &Event) -> Option<usize> {
+    let mut latest_indexes = HashMap::new();
+    for (index, key) in events
+        .iter()
+        .enumerate()
+        .filter_map(|(index, event)| Some((index, supersession_key(event)?)))
+    {
+        latest_indexes.insert(key, index);
+    }
+    if let Some(key) = supersession_key(event) {
+        latest_indexes.insert(key, events.len());
+    }
+
+    events.iter().enumerate().position(|(index, event)| {
+        supersession_key(event).is_some_and(|key| latest_indexes[&key] != index)
+    })
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<Event>,
+    /// How many events were dropped since the receiver last took the count
+    dropped: usize,
+    /// Set once an event which can not be dropped found the queue full, the client is disconnected then
+    is_overflowed: bool,
+    /// Set once the receiver is gone, the events are not queued anymore
+    is_closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    /// Wakes up the receiver when an event is queued or the queue overflows
+    pushed: Notify,
+    /// Wakes up the senders waiting for room in the queue
+    popped: Notify,
+}
+
+impl Shared {
+    /// Queues the event, making room for it by dropping a superseded event if the queue is full, or else overflows
+    /// the queue
+    fn push(&self, state: &mut QueueState, event: Event) {
+        if state.events.len() >= self.capacity {
+            if let Some(index) = find_superseded(&state.events, &event) {
+                state.events.remove(index);
+                state.dropped += 1;
+            } else {
+                // the queued events are never going to be written, their memory is freed right away
+                state.events.clear();
+                state.is_overflowed = true;
+
+                return;
+            }
+        }
+
+        state.events.push_back(event);
+    }
+}
+
+/// Creates the bounded queue of the events on their way to a client, the sender being cloned for every source of events
+pub fn channel(capacity: usize) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(QueueState::default()),
+        capacity: capacity.max(1),
+        pushed: Notify::new(),
+        popped: Notify::new(),
+    });
+
+    (
+        OutboundSender {
+            shared: Arc::clone(&shared),
+        },
+        OutboundReceiver { shared },
+    )
+}
+
+/// [OutboundSender] queues the events of the rooms, the spaces, the direct messages and the replies for a client
+///
+/// Queuing never waits on the client, so a slow client does not hold up the rooms broadcasting to it.
+#[derive(Debug, Clone)]
+pub struct OutboundSender {
+    shared: Arc<Shared>,
+}
+
+impl OutboundSender {
+    /// Queues the event without waiting, dropping the oldest superseded event when the queue is full
+    ///
+    /// An event which finds no room and no superseded event to replace overflows the queue, the client being
+    /// disconnected as a slow consumer.
+    pub fn push(&self, event: Event) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.is_overflowed || state.is_closed {
+            return;
+        }
+
+        self.shared.push(&mut state, event);
+        drop(state);
+        self.shared.pushed.notify_one();
+    }
+
+    /// Queues the event once there is room for it, so a long stream of replies goes at the pace of the client
+    pub async fn send(&self, event: Event) -> anyhow::Result<()> {
+        loop {
+            let popped = self.shared.popped.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.is_overflowed || state.is_closed {
+                    return Err(anyhow::anyhow!("the client is not read from anymore"));
+                }
+
+                if state.events.len() < self.shared.capacity {
+                    self.shared.push(&mut state, event);
+                    drop(state);
+                    self.shared.pushed.notify_one();
+
+                    return Ok(());
+                }
+            }
+
+            popped.await;
+        }
+    }
+}
+
+/// [OutboundReceiver] takes the queued events one at a time, to write them to the client
+#[derive(Debug)]
+pub struct OutboundReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutboundReceiver {
+    /// Takes the next event, or none once the queue has overflowed
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            let pushed = self.shared.pushed.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.is_overflowed {
+                    return None;
+                }
+
+                if let Some(event) = state.events.pop_front() {
+                    drop(state);
+                    self.shared.popped.notify_waiters();
+
+                    return Some(event);
+                }
+            }
+
+            pushed.await;
+        }
+    }
+
+    /// How many events were dropped since the last time they were counted
+    pub fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.shared.state.lock().unwrap().dropped)
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.is_closed = true;
+        state.events.clear();
+        drop(state);
+
+        self.shared.popped.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event::{
+        CommandAckEvent, PingEvent, PresenceChangedBroadcastEvent, PresenceStatus,
+        RateLimitedReplyEvent, UserPresence,
+    };
+
+    use super::*;
+
+    fn ping(nonce: u64) -> Event {
+        Event::Ping(PingEvent {
+            nonce,
+            round_trip: None,
+        })
+    }
+
+    fn presence(user_id: &str, status: PresenceStatus) -> Event {
+        Event::PresenceChanged(PresenceChangedBroadcastEvent {
+            presence: UserPresence {
+                user_id: String::from(user_id),
+                status,
+                away_message: None,
+                display_name: None,
+                color: None,
+            },
+        })
+    }
+
+    fn rate_limited(retry_after: u64) -> Event {
+        Event::RateLimited(RateLimitedReplyEvent { retry_after })
+    }
+
+    fn ack(request_id: &str) -> Event {
+        Event::CommandAck(CommandAckEvent {
+            request_id: String::from(request_id),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_drops_the_superseded_events_under_pressure() {
+        let (tx, mut rx) = channel(3);
+
+        tx.push(ping(1));
+        tx.push(ack("1"));
+        tx.push(ping(2));
+        // the ping superseded by a later one makes room for the new event, whether it can be dropped or not
+        tx.push(ack("2"));
+        tx.push(ping(3));
+
+        assert_eq!(rx.take_dropped(), 2);
+        assert_eq!(rx.recv().await, Some(ack("1")));
+        assert_eq!(rx.recv().await, Some(ack("2")));
+        assert_eq!(rx.recv().await, Some(ping(3)));
+        assert_eq!(rx.take_dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drops_only_the_presence_of_the_same_user() {
+        let (tx, mut rx) = channel(2);
+
+        tx.push(presence("alice", PresenceStatus::Online));
+        tx.push(presence("bob", PresenceStatus::Online));
+        tx.push(presence("alice", PresenceStatus::Away));
+
+        assert_eq!(rx.take_dropped(), 1);
+        assert_eq!(
+            rx.recv().await,
+            Some(presence("bob", PresenceStatus::Online))
+        );
+        assert_eq!(
+            rx.recv().await,
+            Some(presence("alice", PresenceStatus::Away))
+        );
+
+        tx.push(presence("alice", PresenceStatus::Online));
+        tx.push(presence("bob", PresenceStatus::Away));
+        // nothing supersedes the presence of carol or the queued ones, the client can not keep up
+        tx.push(presence("carol", PresenceStatus::Online));
+
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_never_drops_the_rate_limited_events() {
+        let (tx, mut rx) = channel(2);
+
+        tx.push(rate_limited(1000));
+        tx.push(rate_limited(2000));
+        tx.push(rate_limited(3000));
+
+        assert_eq!(rx.take_dropped(), 0);
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_disconnects_the_slow_client_on_an_event_which_can_not_be_dropped() {
+        let (tx, mut rx) = channel(2);
+
+        tx.push(ack("1"));
+        tx.push(ack("2"));
+        tx.push(ack("3"));
+
+        // the queued events are not written anymore, the session ends on it
+        assert_eq!(rx.recv().await, None);
+        assert!(tx.send(ack("4")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sends_at_the_pace_of_the_client() {
+        let (tx, mut rx) = channel(1);
+
+        tx.send(ack("1")).await.unwrap();
+        let sending = tokio::spawn({
+            let tx = tx.clone();
+
+            async move { tx.send(ack("2")).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!sending.is_finished());
+
+        assert_eq!(rx.recv().await, Some(ack("1")));
+        sending.await.unwrap().unwrap();
+        assert_eq!(rx.recv().await, Some(ack("2")));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use comms::{
+    command::{CommandRequest, UserCommand},
+    event::Event,
+    protocol::{self, ProtocolVersion},
+    transport::server::{CommandStream, EventWriter},
+};
+use tokio_stream::StreamExt;
+use tracing::debug;
+
+/// How long to wait for the hello command of a client before serving it the v1 protocol
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Waits for the client to announce its protocol version
+///
+/// Returns the negotiated version, and the first command if the client sent something other than a hello.
+pub(super) async fn negotiate_protocol(
+    commands: &mut CommandStream,
+) -> (ProtocolVersion, Option<CommandRequest>) {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, commands.next()).await {
+        Ok(Some(Ok(CommandRequest {
+            command: UserCommand::Hello(cmd),
+            ..
+        }))) => (ProtocolVersion::from_announced(cmd.protocol_version), None),
+        Ok(Some(Ok(cmd))) => (ProtocolVersion::V1, Some(cmd)),
+        _ => (ProtocolVersion::V1, None),
+    }
+}
+
+/// [VersionedEventWriter] translates the events to the protocol version of the client before writing them
+pub(super) struct VersionedEventWriter {
+    writer: EventWriter,
+    version: ProtocolVersion,
+}
+
+impl VersionedEventWriter {
+    pub fn new(writer: EventWriter, version: ProtocolVersion) -> Self {
+        VersionedEventWriter { writer, version }
+    }
+
+    pub async fn write(&mut self, event: Event) -> anyhow::Result<()> {
+        for event in protocol::translate_event(event, self.version) {
+            self.writer.write(&event).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [ProtocolMetrics] counts the active sessions per protocol version,
+/// to know how many clients remain on the old version during a migration
+#[derive(Debug, Default)]
+pub struct ProtocolMetrics {
+    v1_sessions: AtomicUsize,
+    v2_sessions: AtomicUsize,
+    v3_sessions: AtomicUsize,
+    v4_sessions: AtomicUsize,
+    v5_sessions: AtomicUsize,
+}
+
+impl ProtocolMetrics {
+    pub fn new() -> Self {
+        ProtocolMetrics::default()
+    }
+
+    fn counter(&self, version: ProtocolVersion) -> &AtomicUsize {
+        match version {
+            ProtocolVersion::V1 => &self.v1_sessions,
+            ProtocolVersion::V2 => &self.v2_sessions,
+            ProtocolVersion::V3 => &self.v3_sessions,
+            ProtocolVersion::V4 => &self.v4_sessions,
+            ProtocolVersion::V5 => &self.v5_sessions,
+        }
+    }
+
+    /// Counts a session of the given version until the returned guard is dropped
+    pub(super) fn track_session(self: &Arc<Self>, version: ProtocolVersion) -> TrackedSession {
+        self.counter(version).fetch_add(1, Ordering::Relaxed);
+        self.report();
+
+        TrackedSession {
+            metrics: Arc::clone(self),
+            version,
+        }
+    }
+
+    /// Returns the number of active sessions of each protocol version, from the oldest one
+    pub fn active_sessions(&self) -> Vec<(u16, usize)> {
+        [
+            ProtocolVersion::V1,
+            ProtocolVersion::V2,
+            ProtocolVersion::V3,
+            ProtocolVersion::V4,
+            ProtocolVersion::V5,
+        ]
+        .into_iter()
+        .map(|version| {
+            (
+                version.number(),
+                self.counter(version).load(Ordering::Relaxed),
+            )
+        })
+        .collect()
+    }
+
+    fn report(&self) {
+        debug!(
+            v1 = self.v1_sessions.load(Ordering::Relaxed),
+            v2 = self.v2_sessions.load(Ordering::Relaxed),
+            v3 = self.v3_sessions.load(Ordering::Relaxed),
+            v4 = self.v4_sessions.load(Ordering::Relaxed),
+            v5 = self.v5_sessions.load(Ordering::Relaxed),
+            "active sessions by protocol version"
+        );
+    }
+}
+
+/// A session counted in the [ProtocolMetrics], until it is dropped
+pub(super) struct TrackedSession {
+    metrics: Arc<ProtocolMetrics>,
+    version: ProtocolVersion,
+}
+
+impl Drop for TrackedSession {
+    fn drop(&mut self) {
+        self.metrics
+            .counter(self.version)
+            .fetch_sub(1, Ordering::Relaxed);
+        self.metrics.report();
+    }
+}
@@ -0,0 +1,228 @@
+use std::time::{Duration, Instant};
+
+use comms::command::UserCommand;
+
+/// How many commands of a kind a session can send at once, and how fast it earns them back
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    /// Zero disables the limit
+    pub per_second: f64,
+}
+
+/// The limits of the [SessionRateLimiter]
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    /// Applies to the messages sent to the rooms and the users, and to the edited messages
+    pub messages: RateLimit,
+    /// Applies to joining the rooms and the spaces
+    pub joins: RateLimit,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        RateLimitPolicy {
+            messages: RateLimit {
+                burst: 10,
+                per_second: 5.0,
+            },
+            joins: RateLimit {
+                burst: 5,
+                per_second: 1.0,
+            },
+        }
+    }
+}
+
+/// [TokenBucket] holds up to `burst` tokens, refilled at `per_second`, each command takes one
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+        }
+    }
+
+    /// Changes the limit, keeping the tokens earned so far up to the new burst
+    pub fn set_limit(&mut self, limit: RateLimit) {
+        self.limit = limit;
+        self.tokens = self.tokens.min(f64::from(limit.burst));
+    }
+
+    /// Takes a token, or returns how long until the next one is earned
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        if self.limit.per_second <= 0.0 {
+            return Ok(());
+        }
+
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.limit.per_second,
+        ))
+    }
+}
+
+/// [SessionRateLimiter] throttles the messages and the joins of a single connection,
+/// the other commands are not limited
+#[derive(Debug)]
+pub struct SessionRateLimiter {
+    messages: TokenBucket,
+    joins: TokenBucket,
+}
+
+impl SessionRateLimiter {
+    pub fn new(policy: &RateLimitPolicy) -> Self {
+        let now = Instant::now();
+
+        SessionRateLimiter {
+            messages: TokenBucket::new(policy.messages, now),
+            joins: TokenBucket::new(policy.joins, now),
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: &RateLimitPolicy) {
+        self.messages.set_limit(policy.messages);
+        self.joins.set_limit(policy.joins);
+    }
+
+    /// Lets the command through, or returns how long to wait before sending a command of its kind again
+    pub fn check(&mut self, cmd: &UserCommand) -> Result<(), Duration> {
+        let bucket = match cmd {
+            UserCommand::SendMessage(_)
+            | UserCommand::SendDirectMessage(_)
+            | UserCommand::EditMessage(_) => &mut self.messages,
+            UserCommand::JoinRoom(_) | UserCommand::JoinSpace(_) => &mut self.joins,
+            _ => return Ok(()),
+        };
+
+        bucket.try_take(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::command::{JoinRoomCommand, LeaveRoomCommand, SendMessageCommand};
+
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 3,
+        per_second: 2.0,
+    };
+
+    #[test]
+    fn test_lets_a_burst_through_then_refuses() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+
+        for _ in 0..3 {
+            assert_eq!(bucket.try_take(start), Ok(()));
+        }
+        assert_eq!(bucket.try_take(start), Err(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_refills_at_the_rate_up_to_the_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+        for _ in 0..3 {
+            bucket.try_take(start).unwrap();
+        }
+
+        // a token is earned every half second
+        let later = start + Duration::from_millis(250);
+        assert_eq!(bucket.try_take(later), Err(Duration::from_millis(250)));
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.try_take(later), Ok(()));
+        assert!(bucket.try_take(later).is_err());
+
+        // a long pause earns no more than the burst
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(bucket.try_take(much_later), Ok(()));
+        }
+        assert!(bucket.try_take(much_later).is_err());
+    }
+
+    #[test]
+    fn test_keeps_the_tokens_up_to_the_new_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+
+        bucket.set_limit(RateLimit {
+            burst: 1,
+            per_second: 2.0,
+        });
+        assert_eq!(bucket.try_take(start), Ok(()));
+        assert!(bucket.try_take(start).is_err());
+    }
+
+    #[test]
+    fn test_a_zero_rate_disables_the_limit() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(
+            RateLimit {
+                burst: 0,
+                per_second: 0.0,
+            },
+            start,
+        );
+
+        for _ in 0..100 {
+            assert_eq!(bucket.try_take(start), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_limits_the_messages_and_the_joins_apart() {
+        let mut rate_limiter = SessionRateLimiter::new(&RateLimitPolicy {
+            messages: RateLimit {
+                burst: 1,
+                per_second: 1.0,
+            },
+            joins: RateLimit {
+                burst: 1,
+                per_second: 1.0,
+            },
+        });
+        let message = UserCommand::SendMessage(SendMessageCommand {
+            room: String::from("general"),
+            content: String::from("hello"),
+            reply_to: None,
+        });
+        let join = UserCommand::JoinRoom(JoinRoomCommand {
+            room: String::from("general"),
+        });
+
+        assert!(rate_limiter.check(&message).is_ok());
+        assert!(rate_limiter.check(&message).is_err());
+        // the joins have a bucket of their own
+        assert!(rate_limiter.check(&join).is_ok());
+        assert!(rate_limiter.check(&join).is_err());
+        // the other commands are not limited
+        let leave = UserCommand::LeaveRoom(LeaveRoomCommand {
+            room: String::from("general"),
+        });
+        assert!(rate_limiter.check(&leave).is_ok());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use comms::event::Event;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use super::chat_session::ChatSession;
+use crate::sharded_map::ShardedMap;
+
+/// How long a dropped session is kept for the client to resume it
+const RESUME_GRACE_PERIOD: Duration = Duration::from_secs(60);
+/// How many events are buffered for a dropped session, it is ended once they overflow
+const RESUME_BUFFER_SIZE: usize = 1000;
+
+/// A dropped session taken over by a reconnected client, with the events it missed meanwhile
+pub(super) struct ResumedSession {
+    pub token: String,
+    pub session_id: String,
+    pub user_id: String,
+    pub chat_session: ChatSession,
+    pub missed_events: Vec<Event>,
+}
+
+/// The task keeping a dropped session is asked for it through the channel, and replies with it
+type ResumeRequest = oneshot::Sender<ResumedSession>;
+
+/// [SessionRegistry] keeps the sessions of the clients which dropped the connection without quitting
+///
+/// A dropped session stays in its rooms and spaces, buffering the events it receives,
+/// until the client resumes it with its token or the grace period passes.
+#[derive(Default)]
+pub struct SessionRegistry {
+    /// The channels to request the dropped sessions with, by their resume tokens
+    dropped_sessions: ShardedMap<String, oneshot::Sender<ResumeRequest>>,
+}
+
+impl fmt::Debug for SessionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionRegistry")
+            .field("dropped_sessions", &self.dropped_sessions.len())
+            .finish()
+    }
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        SessionRegistry::default()
+    }
+
+    /// How many dropped sessions are kept
+    pub fn dropped_sessions(&self) -> usize {
+        self.dropped_sessions.len()
+    }
+
+    /// Keeps the session of a dropped connection, so it can be resumed with the token within the grace period
+    pub(super) fn keep(
+        self: &Arc<Self>,
+        token: String,
+        session_id: String,
+        user_id: String,
+        mut chat_session: ChatSession,
+    ) {
+        let (resume_tx, mut resume_rx) = oneshot::channel::<ResumeRequest>();
+        self.dropped_sessions.insert(token.clone(), resume_tx);
+
+        let registry = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut missed_events = Vec::new();
+            let grace_period = tokio::time::sleep(RESUME_GRACE_PERIOD);
+            tokio::pin!(grace_period);
+
+            loop {
+                tokio::select! {
+                    Ok(reply_tx) = &mut resume_rx => {
+                        let _ = reply_tx.send(ResumedSession {
+                            token,
+                            session_id,
+                            user_id,
+                            chat_session,
+                            missed_events,
+                        });
+
+                        return;
+                    }
+                    event = chat_session.recv() => {
+                        let Some(event) = event else {
+                            info!(%session_id, "the dropped session fell too far behind its events");
+                            break;
+                        };
+                        // a kicked session is not resumed
+                        if matches!(event, Event::SessionKicked(_)) {
+                            info!(%session_id, "the dropped session was kicked");
+                            break;
+                        }
+                        if let Err(err) = chat_session.handle_event(&event).await {
+                            warn!(%session_id, "could not handle an event of the dropped session: {}", err);
+                            break;
+                        }
+
+                        missed_events.push(event);
+                        // a partial replay would hide the gap from the client
+                        if missed_events.len() > RESUME_BUFFER_SIZE {
+                            info!(%session_id, "the dropped session missed too many events");
+                            break;
+                        }
+                    }
+                    _ = &mut grace_period => break,
+                }
+            }
+
+            registry.dropped_sessions.remove(&token);
+
+            // the other users are notified about the departure only once the session is given up on
+            if let Err(err) = chat_session.leave_all().await {
+                warn!(
+                    %session_id,
+                    "could not leave the rooms of the dropped session: {}",
+                    err
+                );
+            }
+        });
+    }
+
+    /// Takes over the dropped session with the given token, if it is still kept
+    pub(super) async fn resume(&self, token: &str) -> Option<ResumedSession> {
+        let resume_tx = self.dropped_sessions.remove(token)?;
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        resume_tx.send(reply_tx).ok()?;
+
+        // the session may be given up on right before the request is received
+        reply_rx.await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::command::{JoinRoomCommand, UserCommand};
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::{
+        direct_message_router::DirectMessageRouter,
+        room_manager::{RoomManager, RoomManagerBuilder, SessionAndUserId, UserSessionHandle},
+        space_manager::SpaceManager,
+        spam_guard::{SpamGuard, SpamPolicy},
+    };
+
+    const TOKEN: &str = "resume_token";
+
+    fn room_manager() -> Arc<RoomManager> {
+        Arc::new(
+            RoomManagerBuilder::new()
+                .create_room(
+                    serde_json::from_value(
+                        serde_json::json!({ "name": "general", "description": "General" }),
+                    )
+                    .unwrap(),
+                )
+                // the tests send many messages in a row
+                .duplicate_suppression_window(Duration::ZERO)
+                .build(),
+        )
+    }
+
+    /// Keeps the dropped session of bob, who has joined the room
+    async fn keep_bob(registry: &Arc<SessionRegistry>, room_manager: &Arc<RoomManager>) {
+        let mut chat_session = ChatSession::new(
+            "bob_session",
+            "bob",
+            Arc::clone(room_manager),
+            Arc::new(SpaceManager::new(vec![])),
+            Arc::new(DirectMessageRouter::new()),
+            Arc::new(SpamGuard::new(SpamPolicy::default())),
+            4 * RESUME_BUFFER_SIZE,
+        );
+        chat_session
+            .handle_user_command(
+                UserCommand::JoinRoom(JoinRoomCommand {
+                    room: String::from("general"),
+                }),
+                None,
+            )
+            .await
+            .unwrap();
+
+        registry.keep(
+            String::from(TOKEN),
+            String::from("bob_session"),
+            String::from("bob"),
+            chat_session,
+        );
+    }
+
+    /// Joins the room as alice, returns her handle and her events along with the users in the room
+    async fn join_alice(
+        room_manager: &RoomManager,
+    ) -> (UserSessionHandle, broadcast::Receiver<Event>, Vec<String>) {
+        let (events, handle, user_ids, _) = room_manager
+            .join_room(
+                "general",
+                &SessionAndUserId {
+                    session_id: String::from("alice_session"),
+                    user_id: String::from("alice"),
+                },
+            )
+            .await
+            .unwrap();
+
+        (handle, events, user_ids)
+    }
+
+    /// Lets the tasks of the runtime run until they are all waiting
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replays_the_missed_events_after_reconnecting() {
+        let (registry, room_manager) = (Arc::new(SessionRegistry::new()), room_manager());
+        keep_bob(&registry, &room_manager).await;
+        let (alice, _alice_events, _) = join_alice(&room_manager).await;
+
+        alice
+            .send_message(String::from("are you there?"), None)
+            .await
+            .unwrap();
+        settle().await;
+
+        let resumed = registry.resume(TOKEN).await.unwrap();
+        assert_eq!(resumed.user_id, "bob");
+        let missed_messages = resumed
+            .missed_events
+            .iter()
+            .filter_map(|event| match event {
+                Event::UserMessage(message) => Some(message.content.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(missed_messages, vec!["are you there?"]);
+        // the session is taken over once
+        assert_eq!(registry.dropped_sessions(), 0);
+        assert!(registry.resume(TOKEN).await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_on_the_session_which_missed_too_many_events() {
+        let (registry, room_manager) = (Arc::new(SessionRegistry::new()), room_manager());
+        keep_bob(&registry, &room_manager).await;
+        let (alice, _alice_events, _) = join_alice(&room_manager).await;
+
+        // the client is made to start over with a full resync rather than a partial replay
+        for i in 0..=RESUME_BUFFER_SIZE {
+            alice
+                .send_message(format!("message {}", i), None)
+                .await
+                .unwrap();
+        }
+        settle().await;
+
+        assert!(registry.resume(TOKEN).await.is_none());
+        assert_eq!(registry.dropped_sessions(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_on_the_session_after_the_grace_period() {
+        let (registry, room_manager) = (Arc::new(SessionRegistry::new()), room_manager());
+        keep_bob(&registry, &room_manager).await;
+
+        tokio::time::sleep(RESUME_GRACE_PERIOD - Duration::from_secs(1)).await;
+        assert_eq!(registry.dropped_sessions(), 1);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(registry.resume(TOKEN).await.is_none());
+        assert_eq!(registry.dropped_sessions(), 0);
+
+        // the session has left the room along with it
+        let (_, _, user_ids) = join_alice(&room_manager).await;
+        assert_eq!(user_ids, vec![String::from("alice")]);
+    }
+}
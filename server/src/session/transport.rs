@@ -0,0 +1,30 @@
+use comms::transport::server::{self, CommandStream, EventWriter};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tokio_tungstenite::WebSocketStream;
+
+/// [Transport] is an accepted client connection which a user session can be served over
+///
+/// Every transport carries the same commands and events, the user session only sees them through
+/// the split stream of commands and event writer.
+pub trait Transport: Send + 'static {
+    fn split(self) -> (CommandStream, EventWriter);
+}
+
+impl Transport for TcpStream {
+    fn split(self) -> (CommandStream, EventWriter) {
+        server::split_tcp_stream(self)
+    }
+}
+
+impl Transport for TlsStream<TcpStream> {
+    fn split(self) -> (CommandStream, EventWriter) {
+        server::split_stream(self)
+    }
+}
+
+impl Transport for WebSocketStream<TcpStream> {
+    fn split(self) -> (CommandStream, EventWriter) {
+        server::split_websocket(self)
+    }
+}
@@ -0,0 +1,70 @@
+use std::{sync::Arc, time::Duration};
+
+use comms::event::{UserDataExportReplyEvent, UserProfile};
+use tracing::{error, info};
+
+use crate::{
+    access_log::AccessLog, clock::now_millis, room_manager::RoomManager, storage::CredentialStore,
+};
+
+/// Collects all the data the server stores about the given user
+pub(super) async fn export_user_data(
+    room_manager: &RoomManager,
+    access_log: &AccessLog,
+    user_id: &str,
+    joined_rooms: Vec<String>,
+    display_name: Option<String>,
+    profile: UserProfile,
+) -> anyhow::Result<UserDataExportReplyEvent> {
+    let is_profile_filled = profile.bio.is_some()
+        || profile.pronouns.is_some()
+        || profile.timezone.is_some()
+        || profile.color.is_some();
+
+    Ok(UserDataExportReplyEvent {
+        user_id: String::from(user_id),
+        joined_rooms,
+        sessions: access_log.sessions_of(user_id),
+        messages: room_manager.get_messages_of(user_id).await?,
+        display_name,
+        profile: is_profile_filled.then_some(profile),
+    })
+}
+
+/// Schedules the messages of the given user to be anonymized and their records to be removed at the given time,
+/// right away if it has passed already
+///
+/// The deletion is kept in the credential store until it is done, so it is scheduled again after a restart.
+pub(super) fn schedule_account_deletion(
+    room_manager: Arc<RoomManager>,
+    access_log: Arc<AccessLog>,
+    credential_store: Arc<CredentialStore>,
+    user_id: &str,
+    delete_at: u64,
+) {
+    let user_id = String::from(user_id);
+
+    tokio::spawn(async move {
+        let grace_period = Duration::from_millis(delete_at.saturating_sub(now_millis()));
+        tokio::time::sleep(grace_period).await;
+
+        match delete_account(&room_manager, &access_log, credential_store, &user_id).await {
+            Ok(()) => info!(username = %user_id, "deleted the account"),
+            Err(err) => error!(username = %user_id, "could not delete the account: {}", err),
+        }
+    });
+}
+
+/// Anonymizes the messages of the user, forgets their sessions and records that the deletion is done
+async fn delete_account(
+    room_manager: &RoomManager,
+    access_log: &AccessLog,
+    credential_store: Arc<CredentialStore>,
+    user_id: &str,
+) -> anyhow::Result<()> {
+    room_manager.anonymize_user(user_id).await?;
+    access_log.forget_user(user_id);
+
+    let user_id = String::from(user_id);
+    tokio::task::spawn_blocking(move || credential_store.mark_anonymized(&user_id)).await?
+}
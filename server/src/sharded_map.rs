@@ -0,0 +1,156 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    sync::{Mutex, MutexGuard},
+};
+
+/// How many shards a map is split into, a power of two so the shard of a key is picked by masking its hash
+const SHARD_COUNT: usize = 64;
+
+/// [ShardedMap] is a map split into shards, each behind a lock of its own, which a key always hashes to
+///
+/// The sessions of tens of thousands of connections register, look up and unregister themselves all the time,
+/// with a single lock every connection would wait on the others. Only the entries sharing a shard wait on each other.
+pub struct ShardedMap<K, V> {
+    shards: Box<[Mutex<HashMap<K, V>>]>,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        ShardedMap {
+            shards: (0..SHARD_COUNT)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for ShardedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedMap")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<K, V> ShardedMap<K, V> {
+    pub fn new() -> Self {
+        ShardedMap::default()
+    }
+
+    /// How many entries are kept, counted one shard at a time so it may be off while they change
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Visits every shard, locking them one at a time
+    pub fn for_each_shard<F: FnMut(&mut HashMap<K, V>)>(&self, mut visit: F) {
+        for shard in self.shards.iter() {
+            visit(&mut shard.lock().unwrap());
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> ShardedMap<K, V> {
+    /// Locks the shard the key is kept in, the other shards can be used meanwhile
+    ///
+    /// A shard is never to be locked twice at once, two keys may share one.
+    pub fn shard<Q>(&self, key: &Q) -> MutexGuard<'_, HashMap<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        let index = self.hasher.hash_one(key) as usize & (SHARD_COUNT - 1);
+
+        self.shards[index].lock().unwrap()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.shard(&key).insert(key, value)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_spreads_the_keys_over_the_shards() {
+        let map = ShardedMap::new();
+        for i in 0..SHARD_COUNT * 100 {
+            map.insert(format!("session-{}", i), i);
+        }
+
+        let mut sizes = vec![];
+        map.for_each_shard(|shard| sizes.push(shard.len()));
+        assert_eq!(sizes.len(), SHARD_COUNT);
+        assert_eq!(sizes.iter().sum::<usize>(), SHARD_COUNT * 100);
+        // 100 keys a shard on average, none is left empty nor takes a large share of them
+        assert!(
+            sizes.iter().all(|size| (25..=250).contains(size)),
+            "{:?}",
+            sizes
+        );
+    }
+
+    #[test]
+    fn test_finds_the_key_in_its_shard() {
+        let map = ShardedMap::new();
+
+        assert_eq!(map.insert(String::from("alice"), 1), None);
+        assert_eq!(map.insert(String::from("alice"), 2), Some(1));
+        assert_eq!(map.shard("alice").get("alice"), Some(&2));
+        assert_eq!(map.remove("alice"), Some(2));
+        assert_eq!(map.remove("alice"), None);
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_inserts_and_removes_from_many_threads() {
+        let map = Arc::new(ShardedMap::new());
+
+        let threads = (0..8)
+            .map(|t| {
+                let map = Arc::clone(&map);
+
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        map.insert((t, i), i);
+                    }
+                    // every other key is removed again, while the other threads still insert theirs
+                    for i in (0..1000).step_by(2) {
+                        assert_eq!(map.remove(&(t, i)), Some(i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(map.len(), 8 * 500);
+        for t in 0..8 {
+            for i in 0..1000 {
+                let expected = (i % 2 == 1).then_some(i);
+                assert_eq!(map.shard(&(t, i)).get(&(t, i)).copied(), expected);
+            }
+        }
+    }
+}
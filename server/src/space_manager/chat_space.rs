@@ -0,0 +1,179 @@
+use comms::event::{Event, SpaceMember, SpaceMembershipBroadcastEvent, SpaceRole};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::command_error::CommandError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// [ChatSpaceMetadata] holds the metadata that identifies a space, and the rooms it groups
+pub struct ChatSpaceMetadata {
+    pub name: String,
+    pub description: String,
+    pub rooms: Vec<String>,
+    /// The rooms which are joined along with the space, must be a subset of the rooms
+    #[serde(default)]
+    pub default_rooms: Vec<String>,
+}
+
+const BROADCAST_CHANNEL_CAPACITY: usize = 100;
+
+#[derive(Debug)]
+/// [ChatSpace] handles the members of a space and their roles
+///
+/// Membership changes are broadcasted to the members, including the member whose membership changed,
+/// so a removed member can leave the rooms of the space.
+/// The membership belongs to the user, it is only changed by leaving or being removed, not by the sessions ending.
+pub struct ChatSpace {
+    metadata: ChatSpaceMetadata,
+    broadcast_tx: broadcast::Sender<Event>,
+    /// Members in the order they joined, the longest standing member is promoted when the last admin leaves
+    members: Vec<SpaceMember>,
+}
+
+impl ChatSpace {
+    pub fn new(metadata: ChatSpaceMetadata) -> Self {
+        let (broadcast_tx, _) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
+
+        ChatSpace {
+            metadata,
+            broadcast_tx,
+            members: Vec::new(),
+        }
+    }
+
+    pub fn members(&self) -> &Vec<SpaceMember> {
+        &self.members
+    }
+
+    /// Adds the user to the members, the first member becomes the admin of the space
+    pub fn join(&mut self, user_id: &str) -> anyhow::Result<broadcast::Receiver<Event>> {
+        if self.role_of(user_id).is_some() {
+            return Err(anyhow::anyhow!(
+                "already joined space '{}'",
+                self.metadata.name
+            ));
+        }
+
+        let role = if self.has_admin() {
+            SpaceRole::Member
+        } else {
+            SpaceRole::Admin
+        };
+        self.members.push(SpaceMember {
+            user_id: String::from(user_id),
+            role,
+        });
+        self.broadcast_membership(user_id, Some(role));
+
+        // subscribe after the broadcast, the user learns about their own role from the members instead
+        Ok(self.broadcast_tx.subscribe())
+    }
+
+    /// Subscribes to the membership changes on behalf of a member, such as a new session of theirs
+    ///
+    /// Returns None if the user is not a member.
+    pub fn follow(&self, user_id: &str) -> Option<broadcast::Receiver<Event>> {
+        self.role_of(user_id).map(|_| self.broadcast_tx.subscribe())
+    }
+
+    /// Removes the user from the members, returns false if they were not a member
+    pub fn leave(&mut self, user_id: &str) -> bool {
+        let count = self.members.len();
+        self.members.retain(|member| member.user_id != user_id);
+
+        if self.members.len() == count {
+            return false;
+        }
+
+        self.broadcast_membership(user_id, None);
+
+        // a space is never left without an admin
+        if !self.has_admin() {
+            if let Some(member) = self.members.first_mut() {
+                member.role = SpaceRole::Admin;
+
+                let user_id = member.user_id.clone();
+                self.broadcast_membership(&user_id, Some(SpaceRole::Admin));
+            }
+        }
+
+        true
+    }
+
+    /// Changes the role of a member, on behalf of an admin
+    pub fn set_role(
+        &mut self,
+        admin_id: &str,
+        user_id: &str,
+        role: SpaceRole,
+    ) -> anyhow::Result<()> {
+        self.ensure_admin(admin_id)?;
+
+        let admin_count = self
+            .members
+            .iter()
+            .filter(|member| member.role == SpaceRole::Admin)
+            .count();
+        let member = self
+            .members
+            .iter_mut()
+            .find(|member| member.user_id == user_id)
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a member of the space", user_id))?;
+
+        if member.role == SpaceRole::Admin && role == SpaceRole::Member && admin_count == 1 {
+            return Err(anyhow::anyhow!("the space needs at least one admin"));
+        }
+
+        member.role = role;
+        self.broadcast_membership(user_id, Some(role));
+
+        Ok(())
+    }
+
+    /// Removes a member from the space, on behalf of an admin
+    pub fn remove_member(&mut self, admin_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.ensure_admin(admin_id)?;
+
+        if !self.leave(user_id) {
+            return Err(anyhow::anyhow!(
+                "'{}' is not a member of the space",
+                user_id
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn role_of(&self, user_id: &str) -> Option<SpaceRole> {
+        self.members
+            .iter()
+            .find(|member| member.user_id == user_id)
+            .map(|member| member.role)
+    }
+
+    fn has_admin(&self) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.role == SpaceRole::Admin)
+    }
+
+    fn ensure_admin(&self, user_id: &str) -> anyhow::Result<()> {
+        match self.role_of(user_id) {
+            Some(SpaceRole::Admin) => Ok(()),
+            _ => Err(CommandError::PermissionDenied(String::from(
+                "only the admins of the space can do that",
+            ))
+            .into()),
+        }
+    }
+
+    fn broadcast_membership(&self, user_id: &str, role: Option<SpaceRole>) {
+        let _ = self
+            .broadcast_tx
+            .send(Event::SpaceMembership(SpaceMembershipBroadcastEvent {
+                space: self.metadata.name.clone(),
+                user_id: String::from(user_id),
+                role,
+            }));
+    }
+}
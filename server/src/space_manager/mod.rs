@@ -0,0 +1,6 @@
+pub use self::chat_space::ChatSpaceMetadata;
+pub use self::space_manager::SpaceManager;
+
+mod chat_space;
+#[allow(clippy::module_inception)]
+mod space_manager;
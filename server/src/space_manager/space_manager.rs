@@ -0,0 +1,163 @@
+use std::{collections::HashMap, sync::Arc};
+
+use comms::event::{Event, SpaceMember, SpaceRole};
+use tokio::sync::{broadcast, Mutex};
+
+use crate::command_error::CommandError;
+
+use super::chat_space::{ChatSpace, ChatSpaceMetadata};
+
+pub type SpaceJoinResult = (broadcast::Receiver<Event>, Vec<SpaceMember>);
+
+#[derive(Debug, Clone)]
+/// [SpaceManager] holds the spaces, which group the rooms of the [crate::room_manager::RoomManager]
+pub struct SpaceManager {
+    chat_spaces: HashMap<String, Arc<Mutex<ChatSpace>>>,
+    chat_space_metadatas: Vec<ChatSpaceMetadata>,
+}
+
+impl SpaceManager {
+    pub fn new(chat_space_metadatas: Vec<ChatSpaceMetadata>) -> SpaceManager {
+        SpaceManager {
+            chat_spaces: chat_space_metadatas
+                .iter()
+                .map(|metadata| {
+                    (
+                        metadata.name.clone(),
+                        Arc::new(Mutex::new(ChatSpace::new(metadata.clone()))),
+                    )
+                })
+                .collect(),
+            chat_space_metadatas,
+        }
+    }
+
+    pub fn chat_space_metadatas(&self) -> &Vec<ChatSpaceMetadata> {
+        &self.chat_space_metadatas
+    }
+
+    pub fn get_metadata(&self, space_name: &str) -> Option<&ChatSpaceMetadata> {
+        self.chat_space_metadatas
+            .iter()
+            .find(|metadata| metadata.name == space_name)
+    }
+
+    /// Joins to a space, returns the receiver of the membership changes and the current members
+    pub async fn join_space(
+        &self,
+        space_name: &str,
+        user_id: &str,
+    ) -> anyhow::Result<SpaceJoinResult> {
+        let mut space = self.get_space(space_name)?.lock().await;
+        let broadcast_rx = space.join(user_id)?;
+
+        Ok((broadcast_rx, space.members().clone()))
+    }
+
+    /// Subscribes to the membership changes of every space the user is a member of, along with their current members
+    ///
+    /// The spaces are followed by each session of the user, the membership outlives the sessions.
+    pub async fn follow_spaces(&self, user_id: &str) -> Vec<(String, SpaceJoinResult)> {
+        let mut followed = vec![];
+
+        for metadata in &self.chat_space_metadatas {
+            let Ok(space) = self.get_space(&metadata.name) else {
+                continue;
+            };
+            let space = space.lock().await;
+
+            if let Some(broadcast_rx) = space.follow(user_id) {
+                followed.push((
+                    metadata.name.clone(),
+                    (broadcast_rx, space.members().clone()),
+                ));
+            }
+        }
+
+        followed
+    }
+
+    /// Leaves a space, returns false if the user was not a member
+    pub async fn leave_space(&self, space_name: &str, user_id: &str) -> anyhow::Result<bool> {
+        let mut space = self.get_space(space_name)?.lock().await;
+
+        Ok(space.leave(user_id))
+    }
+
+    /// Leaves every space the user is a member of, when their account is deleted
+    pub async fn leave_all_spaces(&self, user_id: &str) {
+        for space in self.chat_spaces.values() {
+            space.lock().await.leave(user_id);
+        }
+    }
+
+    pub async fn set_role(
+        &self,
+        space_name: &str,
+        admin_id: &str,
+        user_id: &str,
+        role: SpaceRole,
+    ) -> anyhow::Result<()> {
+        let mut space = self.get_space(space_name)?.lock().await;
+
+        space.set_role(admin_id, user_id, role)
+    }
+
+    pub async fn remove_member(
+        &self,
+        space_name: &str,
+        admin_id: &str,
+        user_id: &str,
+    ) -> anyhow::Result<()> {
+        let mut space = self.get_space(space_name)?.lock().await;
+
+        space.remove_member(admin_id, user_id)
+    }
+
+    fn get_space(&self, space_name: &str) -> anyhow::Result<&Arc<Mutex<ChatSpace>>> {
+        self.chat_spaces
+            .get(space_name)
+            .ok_or_else(|| CommandError::SpaceNotFound(String::from(space_name)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space_manager() -> SpaceManager {
+        SpaceManager::new(vec![ChatSpaceMetadata {
+            name: String::from("rustaceans"),
+            description: String::from("Everything Rust"),
+            rooms: vec![String::from("rust")],
+            default_rooms: vec![],
+        }])
+    }
+
+    #[tokio::test]
+    async fn test_the_members_keep_their_roles_for_their_next_sessions() {
+        let space_manager = space_manager();
+        drop(
+            space_manager
+                .join_space("rustaceans", "alice")
+                .await
+                .unwrap(),
+        );
+        drop(space_manager.join_space("rustaceans", "bob").await.unwrap());
+
+        // the sessions of both are gone, the spaces are followed again by their next ones
+        let followed = space_manager.follow_spaces("bob").await;
+        assert_eq!(followed.len(), 1);
+        let (space, (_, members)) = &followed[0];
+        assert_eq!(space, "rustaceans");
+        assert_eq!(
+            members
+                .iter()
+                .map(|member| (member.user_id.as_str(), member.role))
+                .collect::<Vec<_>>(),
+            vec![("alice", SpaceRole::Admin), ("bob", SpaceRole::Member)]
+        );
+
+        assert!(space_manager.follow_spaces("carol").await.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// The id the mutes given by the [SpamGuard] are told to come from, which no username can take
+pub const SPAM_GUARD_ID: &str = "spam-guard";
+
+/// The thresholds of the [SpamGuard], a threshold of 0 disables its heuristic
+#[derive(Debug, Clone)]
+pub struct SpamPolicy {
+    /// How long the messages of a user are remembered to look for their duplicates
+    pub duplicate_window: Duration,
+    /// How many times a user can send the same message within the duplicate window, in any room
+    pub max_duplicates: u32,
+    /// How long the messages of a user are scored together to detect a burst
+    pub burst_window: Duration,
+    /// The score a user can reach within the burst window, each message scores 1 plus 1 for each of its links
+    pub max_burst_score: u32,
+    /// How many links a single message can hold
+    pub max_links: u32,
+    /// How long a user caught spamming is muted in the room they sent the message to
+    pub mute_duration: Duration,
+}
+
+impl Default for SpamPolicy {
+    fn default() -> Self {
+        SpamPolicy {
+            duplicate_window: Duration::from_secs(30),
+            max_duplicates: 3,
+            burst_window: Duration::from_secs(10),
+            max_burst_score: 20,
+            max_links: 5,
+            mute_duration: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Why the [SpamGuard] has caught a user spamming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamOffense {
+    /// The same message was sent too many times
+    Duplicates,
+    /// Too many messages, or too many links, were sent in a short time
+    Burst,
+    /// A single message held too many links
+    Links,
+}
+
+impl fmt::Display for SpamOffense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpamOffense::Duplicates => write!(f, "repeating the same message"),
+            SpamOffense::Burst => write!(f, "sending too many messages at once"),
+            SpamOffense::Links => write!(f, "sending too many links"),
+        }
+    }
+}
+
+/// A message recently sent by a user
+#[derive(Debug)]
+struct SentMessage {
+    sent_at: Instant,
+    content: String,
+    score: u32,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    /// The recent messages of each user, the oldest first
+    messages: HashMap<String, VecDeque<SentMessage>>,
+    /// The last time the users who have stopped sending messages were forgotten
+    swept_at: Option<Instant>,
+}
+
+/// [SpamGuard] looks at the messages sent by each user for duplicates, bursts and links,
+/// and tells when they should be muted for a while
+///
+/// The messages are remembered in memory, for as long as the longest of the windows.
+#[derive(Debug, Default)]
+pub struct SpamGuard {
+    policy: RwLock<SpamPolicy>,
+    history: Mutex<History>,
+    muted_users: AtomicUsize,
+}
+
+impl SpamGuard {
+    pub fn new(policy: SpamPolicy) -> Self {
+        SpamGuard {
+            policy: RwLock::new(policy),
+            ..Default::default()
+        }
+    }
+
+    /// Changes the thresholds, the mutes already given keep their duration
+    pub fn set_policy(&self, policy: SpamPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    /// Records a message sent by the user, returns why they are spamming along with how long to mute them, if they are
+    ///
+    /// The messages of a user caught spamming are forgotten, they start afresh once unmuted.
+    pub fn check(&self, user_id: &str, content: &str) -> Option<(SpamOffense, Duration)> {
+        let policy = self.policy.read().unwrap().clone();
+        let now = Instant::now();
+        let kept_for = policy.duplicate_window.max(policy.burst_window);
+        let links = count_links(content);
+
+        let mut history = self.history.lock().unwrap();
+        history.sweep(now, kept_for);
+
+        let messages = history.messages.entry(String::from(user_id)).or_default();
+        while messages
+            .front()
+            .is_some_and(|message| now.duration_since(message.sent_at) >= kept_for)
+        {
+            messages.pop_front();
+        }
+        messages.push_back(SentMessage {
+            sent_at: now,
+            content: String::from(content),
+            score: 1 + links,
+        });
+
+        let duplicates = messages
+            .iter()
+            .filter(|message| now.duration_since(message.sent_at) < policy.duplicate_window)
+            .filter(|message| message.content == content)
+            .count() as u32;
+        let burst_score = messages
+            .iter()
+            .filter(|message| now.duration_since(message.sent_at) < policy.burst_window)
+            .map(|message| message.score)
+            .sum::<u32>();
+
+        let offense = if policy.max_links > 0 && links > policy.max_links {
+            SpamOffense::Links
+        } else if policy.max_duplicates > 0 && duplicates > policy.max_duplicates {
+            SpamOffense::Duplicates
+        } else if policy.max_burst_score > 0 && burst_score > policy.max_burst_score {
+            SpamOffense::Burst
+        } else {
+            return None;
+        };
+
+        history.messages.remove(user_id);
+        drop(history);
+
+        self.muted_users.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            user_id,
+            %offense,
+            "muted for {}s for spamming",
+            policy.mute_duration.as_secs()
+        );
+        debug!(
+            muted_users = self.muted_users.load(Ordering::Relaxed),
+            "spam guard mutes"
+        );
+
+        Some((offense, policy.mute_duration))
+    }
+}
+
+impl History {
+    /// Forgets the users whose messages are all older than the given duration, at most once per that duration
+    fn sweep(&mut self, now: Instant, kept_for: Duration) {
+        if self
+            .swept_at
+            .is_some_and(|swept_at| now.duration_since(swept_at) < kept_for)
+        {
+            return;
+        }
+
+        self.messages.retain(|_, messages| {
+            messages
+                .back()
+                .is_some_and(|message| now.duration_since(message.sent_at) < kept_for)
+        });
+        self.swept_at = Some(now);
+    }
+}
+
+/// Counts the web links of the message
+fn count_links(content: &str) -> u32 {
+    content
+        .split_whitespace()
+        .filter(|word| {
+            let word = word.to_ascii_lowercase();
+            word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+        })
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A policy with only the given heuristic enabled
+    fn only(policy: SpamPolicy) -> SpamGuard {
+        SpamGuard::new(SpamPolicy {
+            max_duplicates: 0,
+            max_burst_score: 0,
+            max_links: 0,
+            ..policy
+        })
+    }
+
+    #[test]
+    fn test_repeating_the_same_message_is_caught() {
+        let guard = SpamGuard::new(SpamPolicy {
+            max_duplicates: 2,
+            max_burst_score: 0,
+            ..SpamPolicy::default()
+        });
+
+        assert_eq!(guard.check("alice", "buy now"), None);
+        assert_eq!(guard.check("alice", "buy now"), None);
+        assert_eq!(guard.check("alice", "something else"), None);
+        // the other users sending the same message do not count
+        assert_eq!(guard.check("bob", "buy now"), None);
+        assert_eq!(
+            guard.check("alice", "buy now"),
+            Some((SpamOffense::Duplicates, Duration::from_secs(5 * 60)))
+        );
+
+        // the user starts afresh once caught
+        assert_eq!(guard.check("alice", "buy now"), None);
+    }
+
+    #[test]
+    fn test_bursts_are_scored_with_their_links() {
+        let guard = SpamGuard::new(SpamPolicy {
+            max_burst_score: 4,
+            max_duplicates: 0,
+            ..SpamPolicy::default()
+        });
+
+        assert_eq!(guard.check("alice", "see https://example.com"), None);
+        assert_eq!(guard.check("alice", "and www.example.org"), None);
+        assert_eq!(
+            guard.check("alice", "hi"),
+            Some((SpamOffense::Burst, Duration::from_secs(5 * 60)))
+        );
+        assert_eq!(guard.check("bob", "hi"), None);
+    }
+
+    #[test]
+    fn test_too_many_links_in_a_message_are_caught() {
+        let guard = SpamGuard::new(SpamPolicy {
+            max_links: 2,
+            ..SpamPolicy::default()
+        });
+
+        assert_eq!(
+            guard.check("alice", "http://a.example HTTPS://b.example"),
+            None
+        );
+        assert_eq!(
+            guard
+                .check("alice", "http://a.example https://b.example www.c.example")
+                .map(|(offense, _)| offense),
+            Some(SpamOffense::Links)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_messages_older_than_the_windows_are_forgotten() {
+        let guard = SpamGuard::new(SpamPolicy {
+            duplicate_window: Duration::from_millis(50),
+            max_duplicates: 1,
+            burst_window: Duration::from_millis(50),
+            max_burst_score: 1,
+            ..SpamPolicy::default()
+        });
+
+        assert_eq!(guard.check("alice", "hello"), None);
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert!(guard.check("alice", "hello").is_some());
+
+        assert_eq!(guard.check("alice", "hello"), None);
+        tokio::time::advance(Duration::from_millis(51)).await;
+        assert_eq!(guard.check("alice", "hello"), None);
+    }
+
+    #[test]
+    fn test_a_threshold_of_zero_disables_its_heuristic() {
+        let guard = only(SpamPolicy::default());
+
+        for _ in 0..50 {
+            assert_eq!(
+                guard.check(
+                    "alice",
+                    "https://a.example https://b.example https://c.example"
+                ),
+                None
+            );
+        }
+    }
+}
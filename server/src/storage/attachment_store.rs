@@ -0,0 +1,244 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::warn;
+
+/// A file shared with a room
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub room: String,
+    pub user_id: String,
+    pub name: String,
+    pub size: u64,
+    pub checksum: String,
+    pub timestamp: u64,
+}
+
+/// [AttachmentStore] keeps the files shared with the rooms in a directory,
+/// and their details in a SQLite database, so they can be downloaded after server restarts
+#[derive(Debug)]
+pub struct AttachmentStore {
+    connection: Mutex<Connection>,
+    /// The directory the content of the files is written to, each file named by its id
+    directory: PathBuf,
+}
+
+impl AttachmentStore {
+    /// Opens the database at the given path and the directory of the files, creating them and the schema if necessary
+    pub fn open(path: &Path, directory: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(directory).context("could not create the attachment directory")?;
+        let connection =
+            Connection::open(path).context("could not open the attachment database")?;
+
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA synchronous = NORMAL;
+                CREATE TABLE IF NOT EXISTS attachments (
+                    id TEXT PRIMARY KEY,
+                    room TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    checksum TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS attachments_by_room ON attachments (room);",
+            )
+            .context("could not create the attachment database schema")?;
+
+        Ok(AttachmentStore {
+            connection: Mutex::new(connection),
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    /// The path the content of the file is written to, refusing the ids which would lead out of the directory
+    fn path_of(&self, id: &str) -> anyhow::Result<PathBuf> {
+        let mut components = Path::new(id).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if name == id => Ok(self.directory.join(id)),
+            _ => Err(anyhow::anyhow!("invalid attachment id '{}'", id)),
+        }
+    }
+
+    /// Writes the content of the file, then records its details
+    pub fn save(&self, attachment: &Attachment, content: &[u8]) -> anyhow::Result<()> {
+        std::fs::write(self.path_of(&attachment.id)?, content)
+            .context("could not write the attachment")?;
+
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO attachments (id, room, user_id, name, size, checksum, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    attachment.id,
+                    attachment.room,
+                    attachment.user_id,
+                    attachment.name,
+                    attachment.size,
+                    attachment.checksum,
+                    attachment.timestamp
+                ],
+            )
+            .context("could not store the attachment")?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> anyhow::Result<Option<Attachment>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT id, room, user_id, name, size, checksum, timestamp FROM attachments WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(Attachment {
+                        id: row.get(0)?,
+                        room: row.get(1)?,
+                        user_id: row.get(2)?,
+                        name: row.get(3)?,
+                        size: row.get(4)?,
+                        checksum: row.get(5)?,
+                        timestamp: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+            .context("could not load the attachment")
+    }
+
+    /// Reads at most `len` bytes of the content of the file, starting at the given offset
+    pub fn read_chunk(&self, id: &str, offset: u64, len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut file = File::open(self.path_of(id)?).context("could not open the attachment")?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut chunk = Vec::with_capacity(len);
+        file.take(len as u64)
+            .read_to_end(&mut chunk)
+            .context("could not read the attachment")?;
+
+        Ok(chunk)
+    }
+
+    /// Deletes every file shared with the room, when the room itself is deleted
+    pub fn delete_room(&self, room: &str) -> anyhow::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        let ids = connection
+            .prepare("SELECT id FROM attachments WHERE room = ?1")?
+            .query_map(params![room], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not load the attachments of the room")?;
+
+        for id in ids {
+            let removed = self
+                .path_of(&id)
+                .and_then(|path| Ok(std::fs::remove_file(path)?));
+            if let Err(err) = removed {
+                warn!(attachment_id = %id, "could not delete the attachment: {}", err);
+            }
+        }
+
+        connection
+            .execute("DELETE FROM attachments WHERE room = ?1", params![room])
+            .context("could not delete the attachments of the room")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> AttachmentStore {
+        let dir = std::env::temp_dir().join(format!("chat-attachments-{}", nanoid::nanoid!()));
+
+        AttachmentStore::open(&dir.join("attachments.sqlite3"), &dir.join("files")).unwrap()
+    }
+
+    fn attachment(id: &str, room: &str, content: &[u8]) -> Attachment {
+        Attachment {
+            id: String::from(id),
+            room: String::from(room),
+            user_id: String::from("alice"),
+            name: String::from("notes.txt"),
+            size: content.len() as u64,
+            checksum: comms::file_transfer::checksum(content),
+            timestamp: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_the_content_is_read_back_in_chunks() {
+        let store = store();
+        let content = (0..=255).collect::<Vec<u8>>();
+        store
+            .save(&attachment("file-1", "general", &content), &content)
+            .unwrap();
+
+        let saved = store.get("file-1").unwrap().unwrap();
+        assert_eq!(saved.size, 256);
+        assert_eq!(saved.checksum, comms::file_transfer::checksum(&content));
+        assert!(store.get("file-2").unwrap().is_none());
+
+        assert_eq!(store.read_chunk("file-1", 0, 100).unwrap(), &content[..100]);
+        // the last chunk stops at the end of the file
+        assert_eq!(
+            store.read_chunk("file-1", 200, 100).unwrap(),
+            &content[200..]
+        );
+        assert!(store.read_chunk("file-1", 256, 100).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_the_ids_leading_out_of_the_directory_are_refused() {
+        let store = store();
+        let secret = store.directory.parent().unwrap().join("secret");
+        std::fs::write(&secret, b"secret").unwrap();
+
+        for id in [
+            "../secret",
+            "files/../../secret",
+            "/etc/passwd",
+            "",
+            ".",
+            "..",
+        ] {
+            assert!(store.read_chunk(id, 0, 100).is_err(), "{:?}", id);
+            assert!(
+                store.save(&attachment(id, "general", b"x"), b"x").is_err(),
+                "{:?}",
+                id
+            );
+        }
+        assert_eq!(std::fs::read(&secret).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_the_files_of_a_deleted_room_are_removed() {
+        let store = store();
+        store
+            .save(&attachment("file-1", "general", b"one"), b"one")
+            .unwrap();
+        store
+            .save(&attachment("file-2", "rust", b"two"), b"two")
+            .unwrap();
+
+        store.delete_room("general").unwrap();
+
+        assert!(store.get("file-1").unwrap().is_none());
+        assert!(!store.directory.join("file-1").exists());
+        assert_eq!(store.read_chunk("file-2", 0, 100).unwrap(), b"two");
+    }
+}
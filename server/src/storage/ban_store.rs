@@ -0,0 +1,75 @@
+use std::{collections::HashSet, path::Path, sync::Mutex};
+
+use anyhow::Context;
+use rusqlite::{params, Connection};
+
+use crate::clock::now_millis;
+
+/// [BanStore] keeps the users banned from each room in a SQLite database,
+/// so the bans survive server restarts
+#[derive(Debug)]
+pub struct BanStore {
+    connection: Mutex<Connection>,
+}
+
+impl BanStore {
+    /// Opens the database at the given path, creating it and its schema if necessary
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path).context("could not open the ban database")?;
+
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA synchronous = NORMAL;
+                CREATE TABLE IF NOT EXISTS bans (
+                    room TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    banned_by TEXT NOT NULL,
+                    banned_at INTEGER NOT NULL,
+                    PRIMARY KEY (room, user_id)
+                );",
+            )
+            .context("could not create the ban database schema")?;
+
+        Ok(BanStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn ban(&self, room: &str, user_id: &str, banned_by: &str) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR IGNORE INTO bans (room, user_id, banned_by, banned_at) VALUES (?1, ?2, ?3, ?4)",
+                params![room, user_id, banned_by, now_millis()],
+            )
+            .context("could not store the ban")?;
+
+        Ok(())
+    }
+
+    /// Returns the ids of the users banned from the room
+    pub fn banned_user_ids(&self, room: &str) -> anyhow::Result<HashSet<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT user_id FROM bans WHERE room = ?1")?;
+
+        let user_ids = statement
+            .query_map(params![room], |row| row.get(0))?
+            .collect::<Result<HashSet<_>, _>>()
+            .context("could not load the bans")?;
+
+        Ok(user_ids)
+    }
+
+    /// Lifts every ban of the room, when the room itself is deleted
+    pub fn delete_room(&self, room: &str) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM bans WHERE room = ?1", params![room])
+            .context("could not delete the bans of the room")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,399 @@
+use std::{path::Path, sync::Mutex};
+
+use anyhow::Context;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::clock::now_millis;
+
+const USERNAME_LENGTH: std::ops::RangeInclusive<usize> = 3..=20;
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// Why a login is rejected, the same whether the password is wrong or the account is deleted,
+/// so the deleted accounts can not be told apart
+const WRONG_CREDENTIALS: &str = "wrong username or password";
+
+/// The outcome of a login attempt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Authentication {
+    /// The password matches the one of the existing user
+    Accepted,
+    /// Nobody had taken the username, it is now registered with the password
+    Registered,
+    Rejected {
+        reason: String,
+    },
+}
+
+/// [CredentialStore] keeps the argon2 hashes of the passwords of the users in a SQLite database
+///
+/// Hashing is slow on purpose, so the methods are blocking and should not be called on the async runtime.
+/// The lock is not held while hashing, hence concurrent logins do not wait for each other.
+#[derive(Debug)]
+pub struct CredentialStore {
+    connection: Mutex<Connection>,
+}
+
+/// Returns why the username can not be registered, if it can not be
+fn validate_username(username: &str) -> Option<String> {
+    let is_valid = USERNAME_LENGTH.contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+    (!is_valid).then(|| {
+        format!(
+            "usernames are {} to {} lowercase letters, digits or underscores",
+            USERNAME_LENGTH.start(),
+            USERNAME_LENGTH.end()
+        )
+    })
+}
+
+impl CredentialStore {
+    /// Opens the database at the given path, creating it and its schema if necessary
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection =
+            Connection::open(path).context("could not open the credential database")?;
+
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA synchronous = NORMAL;
+                CREATE TABLE IF NOT EXISTS users (
+                    username TEXT PRIMARY KEY,
+                    password_hash TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    deleted_at INTEGER,
+                    display_name TEXT,
+                    anonymized_at INTEGER
+                );",
+            )
+            .context("could not create the credential database schema")?;
+
+        Ok(CredentialStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Returns the password hash of the user and whether their account is deleted
+    fn find_user(&self, username: &str) -> anyhow::Result<Option<(String, bool)>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT password_hash, deleted_at IS NOT NULL FROM users WHERE username = ?1",
+                params![username],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .context("could not read the credentials")
+    }
+
+    fn verify(
+        username: &str,
+        password: &str,
+        password_hash: &str,
+    ) -> anyhow::Result<Authentication> {
+        let password_hash = PasswordHash::new(password_hash)
+            .map_err(|err| anyhow::anyhow!("invalid password hash of '{}': {}", username, err))?;
+
+        Ok(
+            match Argon2::default().verify_password(password.as_bytes(), &password_hash) {
+                Ok(_) => Authentication::Accepted,
+                Err(_) => Authentication::Rejected {
+                    reason: String::from(WRONG_CREDENTIALS),
+                },
+            },
+        )
+    }
+
+    /// Checks the password of the user, or registers the username with it if nobody has taken it yet
+    ///
+    /// The logins to a deleted account are rejected as if the password was wrong,
+    /// after hashing the password anyway so they take as long, since the hash of the deleted account is cleared.
+    pub fn authenticate(&self, username: &str, password: &str) -> anyhow::Result<Authentication> {
+        if let Some((password_hash, is_deleted)) = self.find_user(username)? {
+            if is_deleted {
+                let _ = Argon2::default()
+                    .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng));
+
+                return Ok(Authentication::Rejected {
+                    reason: String::from(WRONG_CREDENTIALS),
+                });
+            }
+
+            return CredentialStore::verify(username, password, &password_hash);
+        }
+
+        if let Some(reason) = validate_username(username) {
+            return Ok(Authentication::Rejected { reason });
+        }
+
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Ok(Authentication::Rejected {
+                reason: format!(
+                    "passwords are at least {} characters long",
+                    MIN_PASSWORD_LENGTH
+                ),
+            });
+        }
+
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| anyhow::anyhow!("could not hash the password: {}", err))?
+            .to_string();
+
+        let inserted = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR IGNORE INTO users (username, password_hash, created_at) VALUES (?1, ?2, ?3)",
+                params![username, password_hash, now_millis()],
+            )
+            .context("could not store the credentials")?;
+
+        // someone else registered the username meanwhile
+        if inserted == 0 {
+            return self.authenticate(username, password);
+        }
+
+        Ok(Authentication::Registered)
+    }
+
+    /// Returns the name the user is shown with, if they have changed it
+    pub fn display_name(&self, username: &str) -> anyhow::Result<Option<String>> {
+        let display_name = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT display_name FROM users WHERE username = ?1",
+                params![username],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()
+            .context("could not read the display name")?;
+
+        Ok(display_name.flatten())
+    }
+
+    /// Changes the name the user is shown with, or goes back to their username without one
+    ///
+    /// The guests are not registered, their display name is not kept.
+    pub fn set_display_name(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE users SET display_name = ?1 WHERE username = ?2",
+                params![display_name, username],
+            )
+            .context("could not store the display name")?;
+
+        Ok(())
+    }
+
+    /// Prevents the user from logging in again and removes their password hash, the username is never handed out again
+    ///
+    /// The deletion is kept until the messages of the user are anonymized, see [CredentialStore::pending_deletions].
+    pub fn delete_user(&self, username: &str) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE users SET deleted_at = ?1, display_name = NULL, password_hash = ''
+                WHERE username = ?2 AND deleted_at IS NULL",
+                params![now_millis(), username],
+            )
+            .context("could not delete the credentials")?;
+
+        Ok(())
+    }
+
+    /// Returns the deleted users whose messages are not anonymized yet, along with the time they were deleted at
+    pub fn pending_deletions(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT username, deleted_at FROM users
+            WHERE deleted_at IS NOT NULL AND anonymized_at IS NULL",
+        )?;
+
+        let deletions = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not read the pending deletions")?;
+
+        Ok(deletions)
+    }
+
+    /// Records that the messages of the deleted user are anonymized, which ends their deletion
+    pub fn mark_anonymized(&self, username: &str) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE users SET anonymized_at = ?1 WHERE username = ?2",
+                params![now_millis(), username],
+            )
+            .context("could not record the anonymization")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("chat-credentials-{}.sqlite3", nanoid::nanoid!()))
+    }
+
+    fn open_store() -> CredentialStore {
+        CredentialStore::open(&temp_path()).unwrap()
+    }
+
+    #[test]
+    fn test_credentials_are_kept_across_restarts_and_only_hashed() {
+        let path = temp_path();
+        CredentialStore::open(&path)
+            .unwrap()
+            .authenticate("alice", "correct horse")
+            .unwrap();
+
+        let store = CredentialStore::open(&path).unwrap();
+        let (password_hash, is_deleted) = store.find_user("alice").unwrap().unwrap();
+        assert!(!password_hash.contains("correct horse"));
+        assert!(!is_deleted);
+
+        assert_eq!(
+            store.authenticate("alice", "correct horse").unwrap(),
+            Authentication::Accepted
+        );
+        assert!(matches!(
+            store.authenticate("alice", "Correct horse").unwrap(),
+            Authentication::Rejected { .. }
+        ));
+    }
+
+    #[test]
+    fn test_deleted_usernames_are_not_registered_again() {
+        let store = open_store();
+        store.authenticate("alice", "correct horse").unwrap();
+        store.delete_user("alice").unwrap();
+        store.mark_anonymized("alice").unwrap();
+
+        assert_eq!(
+            store.authenticate("alice", "another password").unwrap(),
+            Authentication::Rejected {
+                reason: String::from(WRONG_CREDENTIALS)
+            }
+        );
+    }
+
+    #[test]
+    fn test_logins_are_accepted_with_the_registered_password_only() {
+        let store = open_store();
+
+        assert_eq!(
+            store.authenticate("alice", "correct horse").unwrap(),
+            Authentication::Registered
+        );
+        assert_eq!(
+            store.authenticate("alice", "correct horse").unwrap(),
+            Authentication::Accepted
+        );
+        assert_eq!(
+            store.authenticate("alice", "wrong horse").unwrap(),
+            Authentication::Rejected {
+                reason: String::from(WRONG_CREDENTIALS)
+            }
+        );
+    }
+
+    #[test]
+    fn test_password_hashes_are_removed_when_the_account_is_deleted() {
+        let path = temp_path();
+        let store = CredentialStore::open(&path).unwrap();
+        store.authenticate("alice", "correct horse").unwrap();
+        store.delete_user("alice").unwrap();
+
+        let (password_hash, is_deleted) = store.find_user("alice").unwrap().unwrap();
+        assert!(password_hash.is_empty());
+        assert!(is_deleted);
+
+        let stored_hash: String = CredentialStore::open(&path)
+            .unwrap()
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT password_hash FROM users WHERE username = 'alice'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(stored_hash.is_empty());
+    }
+
+    #[test]
+    fn test_deleted_accounts_are_rejected_like_a_wrong_password() {
+        let store = open_store();
+        store.authenticate("alice", "correct horse").unwrap();
+        store.delete_user("alice").unwrap();
+
+        let wrong_password = store.authenticate("alice", "wrong horse").unwrap();
+        let right_password = store.authenticate("alice", "correct horse").unwrap();
+
+        assert_eq!(right_password, wrong_password);
+        assert_eq!(
+            right_password,
+            Authentication::Rejected {
+                reason: String::from(WRONG_CREDENTIALS)
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_usernames_and_short_passwords_are_not_registered() {
+        let store = open_store();
+
+        assert!(matches!(
+            store.authenticate("al", "correct horse").unwrap(),
+            Authentication::Rejected { .. }
+        ));
+        assert!(matches!(
+            store.authenticate("alice", "short").unwrap(),
+            Authentication::Rejected { .. }
+        ));
+        assert_eq!(
+            store.authenticate("alice", "correct horse").unwrap(),
+            Authentication::Registered
+        );
+    }
+
+    #[test]
+    fn test_deletion_is_pending_until_the_user_is_anonymized() {
+        let store = open_store();
+        store.authenticate("alice", "correct horse").unwrap();
+        store.authenticate("bob_1", "battery staple").unwrap();
+
+        store.delete_user("alice").unwrap();
+
+        let deletions = store.pending_deletions().unwrap();
+        assert_eq!(deletions.len(), 1);
+        assert_eq!(deletions[0].0, "alice");
+
+        store.mark_anonymized("alice").unwrap();
+        assert!(store.pending_deletions().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,658 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use anyhow::Context;
+use comms::event::HistoryMessage;
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::{debug, error};
+
+pub use self::attachment_store::{Attachment, AttachmentStore};
+pub use self::ban_store::BanStore;
+pub use self::credential_store::{Authentication, CredentialStore};
+pub use self::profile_store::{ProfileChange, ProfileStore};
+
+mod attachment_store;
+mod ban_store;
+mod credential_store;
+mod profile_store;
+
+/// A page of the stored messages matching a search, newest first
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    pub messages: Vec<HistoryMessage>,
+    /// The position to continue the search before, None once there are no older matches
+    pub cursor: Option<u64>,
+}
+
+/// Turns the words of a search into an FTS5 query matching the messages containing words starting with each of them
+///
+/// The words are quoted, so the FTS5 syntax the user may type is searched as text instead.
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads a stored message from the columns of the row starting at the given one,
+/// which are its id in the room, author, content, timestamp, edited flag and the message it replies to
+fn history_message(row: &rusqlite::Row, first: usize) -> rusqlite::Result<HistoryMessage> {
+    Ok(HistoryMessage {
+        id: row.get::<_, i64>(first)? as u64,
+        user_id: row.get(first + 1)?,
+        content: row.get(first + 2)?,
+        timestamp: row.get(first + 3)?,
+        reply_to: row
+            .get::<_, Option<i64>>(first + 5)?
+            .map(|reply_to| reply_to as u64),
+        is_edited: row.get(first + 4)?,
+        reactions: vec![],
+    })
+}
+
+/// A write to the stored messages, waiting for the writer thread
+type Write = Box<dyn FnOnce(&Connection) + Send>;
+
+/// [MessageStore] persists the messages sent to the rooms in a SQLite database,
+/// so the room histories survive server restarts
+///
+/// The writes are queued to a thread of their own, so the rooms never wait on the database.
+/// They are applied in the order they were queued in, which is the broadcast order.
+/// The reads are blocking, and should not be called on the async runtime.
+#[derive(Debug)]
+pub struct MessageStore {
+    connection: Arc<Mutex<Connection>>,
+    writes_tx: mpsc::Sender<Write>,
+}
+
+impl MessageStore {
+    /// Opens the database at the given path, creating it and its schema if necessary
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path).context("could not open the message database")?;
+
+        // the full-text index follows the messages through triggers
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA synchronous = NORMAL;
+                CREATE TABLE IF NOT EXISTS messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    room TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    edited INTEGER NOT NULL DEFAULT 0,
+                    seq INTEGER,
+                    reply_to INTEGER
+                );
+                CREATE INDEX IF NOT EXISTS messages_by_room ON messages (room, id);
+                CREATE UNIQUE INDEX IF NOT EXISTS messages_by_seq ON messages (room, seq);
+                CREATE TABLE IF NOT EXISTS room_seqs (
+                    room TEXT PRIMARY KEY,
+                    next_seq INTEGER NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS memberships (
+                    room TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    since INTEGER NOT NULL,
+                    PRIMARY KEY (room, user_id)
+                );
+                CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts
+                    USING fts5(content, content = 'messages', content_rowid = 'id');
+                CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+                CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+                    INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+                END;
+                CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;",
+            )
+            .context("could not create the message database schema")?;
+
+        let connection = Arc::new(Mutex::new(connection));
+        let (writes_tx, writes_rx) = mpsc::channel::<Write>();
+
+        // the thread stops once the store is dropped, after applying the writes left in the queue
+        let writer_connection = Arc::clone(&connection);
+        std::thread::Builder::new()
+            .name(String::from("message-writer"))
+            .spawn(move || {
+                for write in writes_rx {
+                    write(&writer_connection.lock().unwrap());
+                }
+            })
+            .context("could not start the message writer thread")?;
+
+        Ok(MessageStore {
+            connection,
+            writes_tx,
+        })
+    }
+
+    /// Writes the messages of the write-ahead log back to the database file, so it is complete on its own
+    ///
+    /// The queued writes are applied first. The other stores share the database, and thus the log.
+    pub fn checkpoint(&self) -> anyhow::Result<()> {
+        self.flush();
+
+        self.connection
+            .lock()
+            .unwrap()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .context("could not checkpoint the message database")
+    }
+
+    /// Queues a write to the stored messages of the room, or of every room, applied by the writer thread
+    /// after the ones queued before it
+    ///
+    /// A failing write does not stop the others, it is logged with what it was meant to do.
+    fn queue(
+        &self,
+        room: Option<&str>,
+        action: &'static str,
+        write: impl FnOnce(&Connection) -> rusqlite::Result<()> + Send + 'static,
+    ) {
+        let room = room.map(String::from);
+        let write: Write = Box::new(move |connection| {
+            if let Err(err) = write(connection) {
+                error!(room = room.as_deref(), "could not {}: {}", action, err);
+            }
+        });
+
+        if self.writes_tx.send(write).is_err() {
+            error!("could not {}: the writer thread has stopped", action);
+        }
+    }
+
+    /// Runs the callback on the writer thread once the writes queued so far are applied
+    ///
+    /// The callback does not run if the writer thread has stopped.
+    pub fn after_writes(&self, callback: impl FnOnce() + Send + 'static) {
+        let write: Write = Box::new(move |_| callback());
+
+        if self.writes_tx.send(write).is_err() {
+            error!("could not wait for the writes: the writer thread has stopped");
+        }
+    }
+
+    /// Waits for the writes queued so far to be applied
+    pub fn flush(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+
+        self.after_writes(move || {
+            let _ = done_tx.send(());
+        });
+        let _ = done_rx.recv();
+    }
+
+    /// Stores a message along with its id in the room, which it keeps across restarts
+    pub fn append(&self, room: &str, message: &HistoryMessage) {
+        let params = (
+            String::from(room),
+            message.user_id.clone(),
+            message.content.clone(),
+            message.timestamp,
+            message.id as i64,
+            message.reply_to.map(|reply_to| reply_to as i64),
+        );
+
+        self.queue(Some(room), "store the message", move |connection| {
+            connection.execute(
+                "INSERT INTO messages (room, user_id, content, timestamp, seq, reply_to)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params.clone(),
+            )?;
+            // the ids handed out are remembered apart from the messages, which may be deleted
+            connection.execute(
+                "INSERT INTO room_seqs (room, next_seq) VALUES (?1, ?2 + 1)
+                ON CONFLICT (room) DO UPDATE SET next_seq = MAX(next_seq, excluded.next_seq)",
+                params![params.0, params.4],
+            )?;
+
+            Ok(())
+        });
+    }
+
+    /// Returns the id the next message of the room is sent with, past every id it has handed out so far
+    ///
+    /// The ids of the deleted and pruned messages are not handed out again, the clients may still refer to them.
+    pub fn next_seq(&self, room: &str) -> anyhow::Result<u64> {
+        let next_seq = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT next_seq FROM room_seqs WHERE room = ?1",
+                params![room],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .context("could not load the next message id")?;
+
+        Ok(next_seq.unwrap_or(0) as u64)
+    }
+
+    /// Stores the id of the first message the user could see as a member of the room, unless one is stored already
+    pub fn record_membership(&self, room: &str, user_id: &str, since: u64) {
+        let params = (String::from(room), String::from(user_id), since as i64);
+
+        self.queue(Some(room), "store the membership", move |connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO memberships (room, user_id, since) VALUES (?1, ?2, ?3)",
+                params,
+            )?;
+
+            Ok(())
+        });
+    }
+
+    /// Removes the stored membership of the user in the room
+    pub fn forget_membership(&self, room: &str, user_id: &str) {
+        let params = (String::from(room), String::from(user_id));
+
+        self.queue(Some(room), "remove the membership", move |connection| {
+            connection.execute(
+                "DELETE FROM memberships WHERE room = ?1 AND user_id = ?2",
+                params,
+            )?;
+
+            Ok(())
+        });
+    }
+
+    /// Returns the id of the first message each member of the room could see, by user id
+    pub fn load_memberships(&self, room: &str) -> anyhow::Result<HashMap<String, u64>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT user_id, since FROM memberships WHERE room = ?1")?;
+
+        let memberships = statement
+            .query_map(params![room], |row| {
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()
+            .context("could not load the memberships")?;
+
+        Ok(memberships)
+    }
+
+    /// Returns the last `limit` messages of the room, ordered from oldest to newest
+    ///
+    /// The messages keep the ids they were sent with, the reactions are not stored.
+    pub fn load_recent(&self, room: &str, limit: usize) -> anyhow::Result<Vec<HistoryMessage>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT seq, user_id, content, timestamp, edited, reply_to FROM (
+                SELECT id, seq, user_id, content, timestamp, edited, reply_to FROM messages
+                WHERE room = ?1 ORDER BY id DESC LIMIT ?2
+            ) ORDER BY id ASC",
+        )?;
+
+        let messages = statement
+            .query_map(params![room, limit as i64], |row| history_message(row, 0))?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not load the stored messages")?;
+
+        Ok(messages)
+    }
+
+    /// Returns up to `limit` of the stored messages of the room with ids within the given range, the newest ones,
+    /// ordered from oldest to newest
+    ///
+    /// Also returns whether there are older messages within the range.
+    pub fn load_before(
+        &self,
+        room: &str,
+        ids: Range<u64>,
+        limit: usize,
+    ) -> anyhow::Result<(Vec<HistoryMessage>, bool)> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT seq, user_id, content, timestamp, edited, reply_to FROM messages
+            WHERE room = ?1 AND seq >= ?2 AND seq < ?3 ORDER BY seq DESC LIMIT ?4",
+        )?;
+
+        // one more message than asked for tells whether there are older ones
+        let mut messages = statement
+            .query_map(
+                params![room, ids.start as i64, ids.end as i64, limit as i64 + 1],
+                |row| history_message(row, 0),
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not load the older stored messages")?;
+
+        let has_more = messages.len() > limit;
+        messages.truncate(limit);
+        messages.reverse();
+
+        Ok((messages, has_more))
+    }
+
+    /// Returns up to `limit` of the stored messages of the room with ids within the given range, the oldest ones,
+    /// ordered from oldest to newest
+    pub fn load_after(
+        &self,
+        room: &str,
+        ids: Range<u64>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<HistoryMessage>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT seq, user_id, content, timestamp, edited, reply_to FROM messages
+            WHERE room = ?1 AND seq >= ?2 AND seq < ?3 ORDER BY seq ASC LIMIT ?4",
+        )?;
+
+        let messages = statement
+            .query_map(
+                params![room, ids.start as i64, ids.end as i64, limit as i64],
+                |row| history_message(row, 0),
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not load the stored messages")?;
+
+        Ok(messages)
+    }
+
+    /// Returns a page of the stored messages of the room sent within the given range, oldest first, with their position
+    ///
+    /// Only the messages stored after the position of the previous page are returned, so a whole history
+    /// can be read a page at a time.
+    pub fn export_page(
+        &self,
+        room: &str,
+        since: Option<u64>,
+        until: Option<u64>,
+        after: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, HistoryMessage)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, seq, user_id, content, timestamp, edited, reply_to FROM messages
+            WHERE room = ?1 AND timestamp >= ?2 AND timestamp < ?3 AND id > ?4
+            ORDER BY id ASC LIMIT ?5",
+        )?;
+
+        let messages = statement
+            .query_map(
+                params![
+                    room,
+                    since.unwrap_or(0) as i64,
+                    until.map(|until| until as i64).unwrap_or(i64::MAX),
+                    after.map(|after| after as i64).unwrap_or(-1),
+                    limit as i64
+                ],
+                |row| Ok((row.get::<_, i64>(0)? as u64, history_message(row, 1)?)),
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not export the stored messages")?;
+
+        Ok(messages)
+    }
+
+    /// Returns a page of the stored messages of the room matching the query, newest first
+    ///
+    /// Only the messages sent from the given timestamp on are searched, and only those before the cursor
+    /// of the previous page.
+    pub fn search(
+        &self,
+        room: &str,
+        query: &str,
+        visible_since: u64,
+        before: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<SearchPage> {
+        let fts_query = fts_query(query);
+        if fts_query.is_empty() {
+            return Ok(SearchPage {
+                messages: vec![],
+                cursor: None,
+            });
+        }
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT messages.id, seq, user_id, messages.content, timestamp, edited, reply_to
+            FROM messages_fts JOIN messages ON messages.id = messages_fts.rowid
+            WHERE messages_fts MATCH ?1 AND room = ?2 AND timestamp >= ?3 AND messages.id < ?4
+            ORDER BY messages.id DESC LIMIT ?5",
+        )?;
+
+        // one more match than asked for tells whether there is another page
+        let mut rows = statement
+            .query_map(
+                params![
+                    fts_query,
+                    room,
+                    visible_since,
+                    before.map(|before| before as i64).unwrap_or(i64::MAX),
+                    limit as i64 + 1
+                ],
+                |row| Ok((row.get::<_, i64>(0)? as u64, history_message(row, 1)?)),
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not search the stored messages")?;
+
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+
+        Ok(SearchPage {
+            cursor: rows.last().map(|(id, _)| *id).filter(|_| has_more),
+            messages: rows.into_iter().map(|(_, message)| message).collect(),
+        })
+    }
+
+    /// Replaces the content of a stored message, found by its id in the room, and marks it as edited
+    pub fn edit(&self, room: &str, message: &HistoryMessage) {
+        let params = (
+            message.content.clone(),
+            String::from(room),
+            message.id as i64,
+        );
+
+        self.queue(Some(room), "edit the stored message", move |connection| {
+            connection.execute(
+                "UPDATE messages SET content = ?1, edited = 1 WHERE room = ?2 AND seq = ?3",
+                params,
+            )?;
+
+            Ok(())
+        });
+    }
+
+    /// Deletes a stored message, found by its id in the room as when editing it
+    pub fn delete(&self, room: &str, id: u64) {
+        let params = (String::from(room), id as i64);
+
+        self.queue(Some(room), "delete the stored message", move |connection| {
+            connection.execute("DELETE FROM messages WHERE room = ?1 AND seq = ?2", params)?;
+
+            Ok(())
+        });
+    }
+
+    /// Deletes the stored messages of the room beyond the latest `max_messages`, and the ones sent before the given timestamp
+    pub fn prune(&self, room: &str, max_messages: Option<usize>, oldest_timestamp: Option<u64>) {
+        let room_name = String::from(room);
+
+        self.queue(Some(room), "prune the stored messages", move |connection| {
+            let mut pruned = 0;
+
+            if let Some(oldest_timestamp) = oldest_timestamp {
+                pruned += connection.execute(
+                    "DELETE FROM messages WHERE room = ?1 AND timestamp < ?2",
+                    params![room_name, oldest_timestamp],
+                )?;
+            }
+
+            if let Some(max_messages) = max_messages {
+                pruned += connection.execute(
+                    "DELETE FROM messages WHERE room = ?1 AND id NOT IN (
+                        SELECT id FROM messages WHERE room = ?1 ORDER BY id DESC LIMIT ?2
+                    )",
+                    params![room_name, max_messages as i64],
+                )?;
+            }
+
+            if pruned > 0 {
+                debug!(room = room_name, pruned, "pruned the stored messages");
+            }
+
+            Ok(())
+        });
+    }
+
+    /// Deletes every stored message of the room, when the room itself is deleted
+    pub fn delete_room(&self, room: &str) {
+        let room_name = String::from(room);
+
+        self.queue(
+            Some(room),
+            "delete the stored messages of the room",
+            move |connection| {
+                connection.execute("DELETE FROM messages WHERE room = ?1", params![room_name])?;
+                connection.execute(
+                    "DELETE FROM memberships WHERE room = ?1",
+                    params![room_name],
+                )?;
+
+                Ok(())
+            },
+        );
+    }
+
+    /// Attributes the stored messages of the given user in every room to another user id
+    pub fn reassign_user(&self, user_id: &str, new_user_id: &str) {
+        let params = (String::from(new_user_id), String::from(user_id));
+
+        self.queue(None, "reassign the stored messages", move |connection| {
+            connection.execute(
+                "UPDATE messages SET user_id = ?1 WHERE user_id = ?2",
+                params,
+            )?;
+
+            Ok(())
+        });
+    }
+
+    /// Returns every stored message of the given user along with its room, ordered by room and then from oldest to newest
+    pub fn messages_of(&self, user_id: &str) -> anyhow::Result<Vec<(String, HistoryMessage)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT room, seq, user_id, content, timestamp, edited, reply_to FROM messages
+            WHERE user_id = ?1 ORDER BY room, id",
+        )?;
+
+        let messages = statement
+            .query_map(params![user_id], |row| {
+                Ok((row.get(0)?, history_message(row, 1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not read the stored messages of the user")?;
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64, user_id: &str, content: &str, reply_to: Option<u64>) -> HistoryMessage {
+        HistoryMessage {
+            id,
+            user_id: String::from(user_id),
+            content: String::from(content),
+            timestamp: 1_000,
+            reply_to,
+            is_edited: false,
+            reactions: vec![],
+        }
+    }
+
+    fn database_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("chat-messages-{}.sqlite3", nanoid::nanoid!()))
+    }
+
+    #[test]
+    fn test_messages_sent_in_the_same_millisecond_are_edited_and_deleted_apart() {
+        let store = MessageStore::open(&database_path()).unwrap();
+        store.append("general", &message(0, "alice", "one", None));
+        store.append("general", &message(1, "alice", "two", None));
+        store.append("general", &message(2, "alice", "three", None));
+
+        store.edit("general", &message(0, "alice", "uno", None));
+        store.delete("general", 2);
+        store.flush();
+
+        let messages = store.load_recent("general", 10).unwrap();
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| (message.id, message.content.as_str(), message.is_edited))
+                .collect::<Vec<_>>(),
+            vec![(0, "uno", true), (1, "two", false)]
+        );
+    }
+
+    #[test]
+    fn test_messages_of_a_user_are_anonymized_in_every_room() {
+        let store = MessageStore::open(&database_path()).unwrap();
+        store.append("general", &message(0, "alice", "hi", None));
+        store.append("rust", &message(0, "alice", "hello", None));
+        store.append("rust", &message(1, "bob", "hey", None));
+        store.flush();
+
+        assert_eq!(
+            store
+                .messages_of("alice")
+                .unwrap()
+                .into_iter()
+                .map(|(room, message)| (room, message.content))
+                .collect::<Vec<_>>(),
+            vec![
+                (String::from("general"), String::from("hi")),
+                (String::from("rust"), String::from("hello")),
+            ]
+        );
+
+        store.reassign_user("alice", "[deleted]");
+        store.flush();
+
+        assert!(store.messages_of("alice").unwrap().is_empty());
+        assert_eq!(store.messages_of("[deleted]").unwrap().len(), 2);
+        assert_eq!(store.messages_of("bob").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_messages_keep_their_ids_and_replies_when_reopened() {
+        let path = database_path();
+        let store = MessageStore::open(&path).unwrap();
+        store.append("general", &message(7, "alice", "hi", None));
+        store.append("general", &message(8, "bob", "hello", Some(7)));
+        store.append("rust", &message(0, "bob", "hello rust", None));
+        store.flush();
+        drop(store);
+
+        let store = MessageStore::open(&path).unwrap();
+        let messages = store.load_recent("general", 10).unwrap();
+
+        assert_eq!(messages[0].id, 7);
+        assert_eq!((messages[1].id, messages[1].reply_to), (8, Some(7)));
+        assert_eq!(
+            store
+                .search("general", "hello", 0, None, 10)
+                .unwrap()
+                .messages[0]
+                .id,
+            8
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{path::Path, sync::Mutex};
+
+use anyhow::Context;
+use comms::event::UserProfile;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::clock::now_millis;
+
+/// The number of characters a bio can be at most
+const MAX_BIO_CHARS: usize = 280;
+/// The number of characters the pronouns and the timezone can be at most
+const MAX_FIELD_CHARS: usize = 64;
+
+/// A change to the profile of a user, the fields left out are kept and the empty ones are cleared
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileChange {
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    pub color: Option<String>,
+}
+
+impl ProfileChange {
+    /// Returns why the change can not be made, if it can not
+    pub fn validate(&self) -> Option<String> {
+        let fields = [
+            ("bios", &self.bio, MAX_BIO_CHARS),
+            ("pronouns", &self.pronouns, MAX_FIELD_CHARS),
+            ("timezones", &self.timezone, MAX_FIELD_CHARS),
+        ];
+
+        for (name, value, max_chars) in fields {
+            let Some(value) = value else {
+                continue;
+            };
+
+            if value.chars().count() > max_chars || value.chars().any(char::is_control) {
+                return Some(format!(
+                    "{} are at most {} characters long, without control characters",
+                    name, max_chars
+                ));
+            }
+        }
+
+        let is_valid_color = |color: &str| {
+            color.is_empty()
+                || (color.len() == 7
+                    && color.starts_with('#')
+                    && color[1..].chars().all(|char| char.is_ascii_hexdigit()))
+        };
+        if self
+            .color
+            .as_deref()
+            .is_some_and(|color| !is_valid_color(color))
+        {
+            return Some(String::from("colors are written as #rrggbb"));
+        }
+
+        None
+    }
+}
+
+/// [ProfileStore] keeps the profiles of the users in a SQLite database, so they survive server restarts
+///
+/// The display names are kept along with the credentials, the profiles returned leave them out.
+#[derive(Debug)]
+pub struct ProfileStore {
+    connection: Mutex<Connection>,
+}
+
+impl ProfileStore {
+    /// Opens the database at the given path, creating it and its schema if necessary
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path).context("could not open the profile database")?;
+
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA synchronous = NORMAL;
+                CREATE TABLE IF NOT EXISTS profiles (
+                    user_id TEXT PRIMARY KEY,
+                    bio TEXT,
+                    pronouns TEXT,
+                    timezone TEXT,
+                    color TEXT,
+                    updated_at INTEGER NOT NULL
+                );",
+            )
+            .context("could not create the profile database schema")?;
+
+        Ok(ProfileStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Returns the profile of the user, empty if they have not filled it
+    pub fn profile(&self, user_id: &str) -> anyhow::Result<UserProfile> {
+        let profile = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT bio, pronouns, timezone, color FROM profiles WHERE user_id = ?1",
+                params![user_id],
+                |row| {
+                    Ok(UserProfile {
+                        user_id: String::from(user_id),
+                        display_name: None,
+                        bio: row.get(0)?,
+                        pronouns: row.get(1)?,
+                        timezone: row.get(2)?,
+                        color: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .context("could not read the profile")?;
+
+        Ok(profile.unwrap_or_else(|| UserProfile {
+            user_id: String::from(user_id),
+            ..Default::default()
+        }))
+    }
+
+    /// Applies the change to the profile of the user, returns the profile as changed
+    pub fn update(&self, user_id: &str, change: ProfileChange) -> anyhow::Result<UserProfile> {
+        let profile = self.profile(user_id)?;
+        // an empty field clears the one stored, a missing one keeps it
+        let merge = |changed: Option<String>, stored: Option<String>| match changed {
+            Some(changed) => Some(changed).filter(|changed| !changed.is_empty()),
+            None => stored,
+        };
+        let profile = UserProfile {
+            bio: merge(change.bio, profile.bio),
+            pronouns: merge(change.pronouns, profile.pronouns),
+            timezone: merge(change.timezone, profile.timezone),
+            color: merge(change.color, profile.color),
+            ..profile
+        };
+
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO profiles (user_id, bio, pronouns, timezone, color, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    user_id,
+                    profile.bio,
+                    profile.pronouns,
+                    profile.timezone,
+                    profile.color,
+                    now_millis()
+                ],
+            )
+            .context("could not store the profile")?;
+
+        Ok(profile)
+    }
+
+    /// Forgets the profile of the user, when their account is deleted
+    pub fn delete_user(&self, user_id: &str) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM profiles WHERE user_id = ?1", params![user_id])
+            .context("could not delete the profile")?;
+
+        Ok(())
+    }
+}
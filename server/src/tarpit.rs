@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::metrics::ServerMetrics;
+
+/// The thresholds of the [Tarpit]
+#[derive(Debug, Clone)]
+pub struct TarpitPolicy {
+    /// How many invalid commands an ip can send before its responses are delayed
+    pub free_strikes: u32,
+    /// How much the delay grows with each invalid command after the free ones
+    pub delay_step: Duration,
+    /// How many invalid commands an ip can send before it is disconnected and banned
+    pub ban_strikes: u32,
+    /// How long a banned ip is refused new connections, and how long the strikes of an ip are remembered
+    pub ban_duration: Duration,
+}
+
+impl Default for TarpitPolicy {
+    fn default() -> Self {
+        TarpitPolicy {
+            free_strikes: 3,
+            delay_step: Duration::from_millis(500),
+            ban_strikes: 10,
+            ban_duration: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// What to do with a connection which has sent an invalid command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Penalty {
+    None,
+    /// Wait before processing anything else from the connection
+    Delay(Duration),
+    /// Disconnect, the ip is banned until the ban duration passes
+    Ban,
+}
+
+/// [Tarpit] slows down and eventually bans the connections which keep sending invalid commands,
+/// so naive scanners can not keep the server busy
+///
+/// The strikes add up per ip across its connections, so reconnecting does not reset them.
+/// They are forgotten once the ip has not struck for the ban duration.
+/// The strikes and the bans are kept in memory and are lost on restart.
+/// The delays, the bans and the refused connections are counted in the [ServerMetrics].
+#[derive(Debug)]
+pub struct Tarpit {
+    policy: RwLock<TarpitPolicy>,
+    bans: Mutex<HashMap<IpAddr, Instant>>,
+    /// How many times each ip has struck, and when it last did
+    strikes: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+    server_metrics: Arc<ServerMetrics>,
+}
+
+impl Tarpit {
+    pub fn new(policy: TarpitPolicy, server_metrics: Arc<ServerMetrics>) -> Self {
+        Tarpit {
+            policy: RwLock::new(policy),
+            bans: Mutex::default(),
+            strikes: Mutex::default(),
+            server_metrics,
+        }
+    }
+
+    /// Changes the thresholds, the bans already given keep their duration
+    pub fn set_policy(&self, policy: TarpitPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    /// Checks whether the ip is banned, counting the connection as refused if it is
+    pub fn refuses(&self, ip: IpAddr) -> bool {
+        let mut bans = self.bans.lock().unwrap();
+        let now = Instant::now();
+        bans.retain(|_, banned_until| *banned_until > now);
+
+        if !bans.contains_key(&ip) {
+            return false;
+        }
+
+        self.server_metrics.record_tarpit_refusal();
+
+        true
+    }
+
+    /// Records an invalid command, or a rejected login, from the ip
+    pub fn strike(&self, ip: IpAddr) -> Penalty {
+        let strikes = self.count_strike(ip);
+        let penalty = self.penalty(strikes);
+
+        match penalty {
+            Penalty::None => return penalty,
+            Penalty::Delay(_) => {
+                self.server_metrics.record_tarpit_delay();
+            }
+            Penalty::Ban => {
+                let ban_duration = self.policy.read().unwrap().ban_duration;
+                // the ip starts over once its ban has passed
+                self.strikes.lock().unwrap().remove(&ip);
+                self.bans
+                    .lock()
+                    .unwrap()
+                    .insert(ip, Instant::now() + ban_duration);
+                self.server_metrics.record_tarpit_ban();
+                warn!(
+                    %ip,
+                    strikes,
+                    "banned for {}s after too many invalid commands",
+                    ban_duration.as_secs()
+                );
+            }
+        }
+
+        penalty
+    }
+
+    /// Counts a strike of the ip, returning how many times it has struck so far including this one
+    fn count_strike(&self, ip: IpAddr) -> u32 {
+        let memory = self.policy.read().unwrap().ban_duration;
+        let mut strikes = self.strikes.lock().unwrap();
+        let now = Instant::now();
+        strikes.retain(|_, (_, struck_at)| now.duration_since(*struck_at) < memory);
+
+        let (count, struck_at) = strikes.entry(ip).or_insert((0, now));
+        *count += 1;
+        *struck_at = now;
+
+        *count
+    }
+
+    fn penalty(&self, strikes: u32) -> Penalty {
+        let policy = self.policy.read().unwrap();
+
+        if strikes >= policy.ban_strikes {
+            Penalty::Ban
+        } else if strikes > policy.free_strikes {
+            Penalty::Delay(policy.delay_step * (strikes - policy.free_strikes))
+        } else {
+            Penalty::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn policy() -> TarpitPolicy {
+        TarpitPolicy {
+            free_strikes: 1,
+            delay_step: Duration::from_millis(100),
+            ban_strikes: 3,
+            ban_duration: Duration::from_secs(60),
+        }
+    }
+
+    fn tarpit_with(policy: TarpitPolicy) -> Tarpit {
+        Tarpit::new(policy, Arc::new(ServerMetrics::new()))
+    }
+
+    fn tarpit() -> Tarpit {
+        tarpit_with(policy())
+    }
+
+    #[test]
+    fn test_strikes_add_up_per_ip() {
+        let tarpit = tarpit();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert_eq!(tarpit.strike(ip), Penalty::None);
+        assert_eq!(
+            tarpit.strike(ip),
+            Penalty::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(tarpit.strike(other_ip), Penalty::None);
+        assert!(!tarpit.refuses(ip));
+
+        assert_eq!(tarpit.strike(ip), Penalty::Ban);
+        assert!(tarpit.refuses(ip));
+        assert!(!tarpit.refuses(other_ip));
+    }
+
+    #[test]
+    fn test_the_delay_grows_with_each_strike_until_the_ban() {
+        let tarpit = tarpit_with(TarpitPolicy {
+            ban_strikes: 4,
+            ..policy()
+        });
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert_eq!(tarpit.strike(ip), Penalty::None);
+        assert_eq!(
+            tarpit.strike(ip),
+            Penalty::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(
+            tarpit.strike(ip),
+            Penalty::Delay(Duration::from_millis(200))
+        );
+        assert_eq!(tarpit.strike(ip), Penalty::Ban);
+
+        // the ip starts over once banned
+        assert_eq!(tarpit.strike(ip), Penalty::None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bans_are_lifted_after_the_ban_duration() {
+        let tarpit = tarpit_with(TarpitPolicy {
+            free_strikes: 0,
+            ban_strikes: 1,
+            ban_duration: Duration::from_millis(50),
+            ..TarpitPolicy::default()
+        });
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert_eq!(tarpit.strike(ip), Penalty::Ban);
+        assert!(tarpit.refuses(ip));
+
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert!(tarpit.refuses(ip));
+
+        tokio::time::advance(Duration::from_millis(2)).await;
+        assert!(!tarpit.refuses(ip));
+    }
+
+    #[test]
+    fn test_strikes_are_forgotten_after_the_ban_duration() {
+        let tarpit = tarpit_with(TarpitPolicy {
+            ban_duration: Duration::ZERO,
+            ..policy()
+        });
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        for _ in 0..5 {
+            assert_eq!(tarpit.strike(ip), Penalty::None);
+        }
+        assert!(!tarpit.refuses(ip));
+    }
+}
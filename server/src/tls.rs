@@ -0,0 +1,59 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::Context;
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+
+/// Reads the certificate chain from a PEM file
+fn load_certificates(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("could not open {}", path.display()))?,
+    );
+    let certificates = rustls_pemfile::certs(&mut reader)
+        .with_context(|| format!("could not read the certificates in {}", path.display()))?;
+
+    if certificates.is_empty() {
+        return Err(anyhow::anyhow!("no certificate in {}", path.display()));
+    }
+
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first PKCS#8, RSA or EC private key from a PEM file
+fn load_private_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("could not open {}", path.display()))?,
+    );
+
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)
+        .with_context(|| format!("could not read the private key in {}", path.display()))?
+    {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => (),
+        }
+    }
+
+    Err(anyhow::anyhow!("no private key in {}", path.display()))
+}
+
+/// Creates the acceptor which wraps the accepted tcp streams in TLS, with the certificate chain and its private key
+pub fn load_acceptor(
+    certificate_path: &Path,
+    private_key_path: &Path,
+) -> anyhow::Result<TlsAcceptor> {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            load_certificates(certificate_path)?,
+            load_private_key(private_key_path)?,
+        )
+        .context("the private key does not match the certificate")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
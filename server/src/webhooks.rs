@@ -0,0 +1,551 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use comms::event;
+use hmac::{Hmac, Mac};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::{
+    body::{Bytes, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::{net::TcpStream, sync::broadcast};
+
+use crate::{
+    clock::now_millis,
+    room_manager::{RoomManager, SessionAndUserId, UserSessionHandle},
+    session::{RateLimit, TokenBucket},
+};
+
+/// The path the webhooks are posted to, followed by their name
+const WEBHOOK_PATH: &str = "/webhooks/";
+/// The header carrying the HMAC-SHA256 of the timestamp and the payload, keyed with the token of the webhook
+pub const SIGNATURE_HEADER: &str = "x-chat-signature";
+const SIGNATURE_PREFIX: &str = "sha256=";
+/// The header carrying when the payload was signed, in seconds since the unix epoch
+pub const TIMESTAMP_HEADER: &str = "x-chat-timestamp";
+/// How far the timestamp of a payload can be from the clock of the server, so a captured payload can not be replayed later on,
+/// while the payloads delivered within it are told apart by their signatures
+const MAX_TIMESTAMP_SKEW: Duration = Duration::from_secs(5 * 60);
+/// The largest payload accepted, the messages are much shorter anyway
+const MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+/// How long a sender has to send its request, so a slow one does not hold a connection forever
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_burst() -> u32 {
+    10
+}
+
+fn default_per_second() -> f64 {
+    1.0
+}
+
+/// [WebhookConfig] defines a webhook, read from the JSON file of the webhooks
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    /// Names the webhook in the path it is posted to, as in `/webhooks/ci`
+    pub name: String,
+    /// The room the payloads are posted to
+    pub room: String,
+    /// The user the payloads are posted as
+    pub user_id: String,
+    /// The secret the payloads are signed with, shared with the sender only
+    pub token: String,
+    /// How many payloads can be posted at once, at least 1
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    /// How many more payloads can be posted per second, 0 disables the limit
+    #[serde(default = "default_per_second")]
+    pub per_second: f64,
+}
+
+/// The JSON payload posted to a webhook
+#[derive(Debug, Deserialize)]
+struct WebhookPayload {
+    /// The message posted to the room
+    text: String,
+}
+
+/// Why a payload was not posted, answered with the matching HTTP status
+#[derive(Debug)]
+enum WebhookError {
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    /// The signature is missing or does not match the payload
+    BadSignature,
+    /// The payload was signed too long ago, or too far in the future
+    StaleTimestamp,
+    /// The same signed payload was delivered already
+    Replayed,
+    BadPayload(String),
+    /// The webhook has posted too much, it can post again after the given delay
+    RateLimited(Duration),
+    /// The room has refused the message, such as a message too long
+    Refused(String),
+}
+
+impl WebhookError {
+    fn status(&self) -> StatusCode {
+        match self {
+            WebhookError::NotFound => StatusCode::NOT_FOUND,
+            WebhookError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            WebhookError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            WebhookError::BadSignature | WebhookError::StaleTimestamp => StatusCode::UNAUTHORIZED,
+            WebhookError::Replayed => StatusCode::CONFLICT,
+            WebhookError::BadPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            WebhookError::Refused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    fn into_response(self) -> Response<Full<Bytes>> {
+        let mut response = text_response(self.status(), self.to_string());
+
+        if let WebhookError::RateLimited(retry_after) = self {
+            // rounded up, so retrying right after the delay is not refused again
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, seconds.into());
+        }
+
+        response
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::NotFound => write!(f, "webhook not found"),
+            WebhookError::MethodNotAllowed => write!(f, "webhooks are posted"),
+            WebhookError::PayloadTooLarge => {
+                write!(f, "payloads are at most {} bytes large", MAX_PAYLOAD_BYTES)
+            }
+            WebhookError::BadSignature => write!(
+                f,
+                "the {} header does not match the {} header and the payload",
+                SIGNATURE_HEADER, TIMESTAMP_HEADER
+            ),
+            WebhookError::StaleTimestamp => write!(
+                f,
+                "the {} header is more than {} seconds away from the time of the server",
+                TIMESTAMP_HEADER,
+                MAX_TIMESTAMP_SKEW.as_secs()
+            ),
+            WebhookError::Replayed => write!(f, "the payload was delivered already"),
+            WebhookError::BadPayload(reason) => write!(f, "invalid payload: {}", reason),
+            WebhookError::RateLimited(retry_after) => write!(
+                f,
+                "too many payloads, retry in {:.1} seconds",
+                retry_after.as_secs_f64()
+            ),
+            WebhookError::Refused(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+
+    response
+}
+
+fn payload_mac(token: &str, timestamp: u64, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(token.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(payload);
+
+    mac
+}
+
+/// The current time in seconds since the unix epoch, as the payloads are timestamped
+pub fn now_secs() -> u64 {
+    now_millis() / 1000
+}
+
+/// Signs the payload with the token, as `sha256=` followed by the hex HMAC-SHA256 of the timestamp,
+/// a dot and the payload, keyed with the token
+pub fn sign(token: &str, timestamp: u64, payload: &[u8]) -> String {
+    format!(
+        "{}{}",
+        SIGNATURE_PREFIX,
+        hex::encode(
+            payload_mac(token, timestamp, payload)
+                .finalize()
+                .into_bytes()
+        )
+    )
+}
+
+/// Decodes the digest of a signature, see [sign]
+///
+/// The hex digits are read in either case, so the signatures are told apart by their digests only.
+fn decode_signature(signature: &str) -> Option<Vec<u8>> {
+    signature
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(|digest| hex::decode(digest).ok())
+}
+
+/// Checks the signature of the timestamped payload, see [sign]
+fn verify_signature(token: &str, timestamp: u64, payload: &[u8], signature: &str) -> bool {
+    let Some(digest) = decode_signature(signature) else {
+        return false;
+    };
+    let mac = payload_mac(token, timestamp, payload);
+
+    // compares in constant time, so the signature can not be guessed byte by byte
+    mac.verify_slice(&digest).is_ok()
+}
+
+/// Is the timestamp of the payload close enough to the current time, both in seconds since the unix epoch
+fn is_fresh(timestamp: u64, now: u64) -> bool {
+    timestamp.abs_diff(now) <= MAX_TIMESTAMP_SKEW.as_secs()
+}
+
+/// [SeenSignatures] remembers the digests of the payloads delivered while their timestamps are fresh,
+/// so a captured payload can not be replayed before it turns stale
+#[derive(Debug, Default)]
+struct SeenSignatures {
+    /// The timestamp of each payload, by the digest of its signature
+    digests: HashMap<Vec<u8>, u64>,
+}
+
+impl SeenSignatures {
+    /// Records the digest of a payload being delivered, returns false if it was seen already
+    ///
+    /// The digests of the payloads which are stale by now are forgotten, they are refused anyway.
+    fn insert(&mut self, digest: &[u8], timestamp: u64, now: u64) -> bool {
+        self.digests
+            .retain(|_, timestamp| is_fresh(*timestamp, now));
+
+        self.digests.insert(digest.to_vec(), timestamp).is_none()
+    }
+
+    /// Forgets the digest of a payload which was not delivered after all, so the sender can retry it as it is
+    fn remove(&mut self, digest: &[u8]) {
+        self.digests.remove(digest);
+    }
+}
+
+struct Webhook {
+    token: String,
+    /// The webhook stays in its room as its user while the server runs
+    handle: UserSessionHandle,
+    /// Kept so the room can be posted to while none of its users is connected
+    _broadcast_rx: broadcast::Receiver<event::Event>,
+    rate_limiter: Mutex<TokenBucket>,
+    seen_signatures: Mutex<SeenSignatures>,
+}
+
+/// [Webhooks] lets external services, such as a CI system, post messages into the rooms over HTTP
+///
+/// Each webhook posts into its room as its own user, the payloads being signed with the token of the webhook.
+pub struct Webhooks {
+    webhooks: HashMap<String, Webhook>,
+}
+
+impl Webhooks {
+    /// Reads the webhooks from a JSON file, and joins the room of each webhook as its user
+    pub async fn load(path: &Path, room_manager: &RoomManager) -> anyhow::Result<Self> {
+        let configs: Vec<WebhookConfig> = serde_json::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("could not read {}", path.display()))?,
+        )
+        .with_context(|| format!("could not parse {}", path.display()))?;
+        let now = Instant::now();
+        let mut webhooks = HashMap::new();
+
+        for config in configs {
+            if webhooks.contains_key(&config.name) {
+                return Err(anyhow::anyhow!(
+                    "webhook '{}' is defined twice",
+                    config.name
+                ));
+            }
+            if config.burst == 0 {
+                return Err(anyhow::anyhow!(
+                    "webhook '{}' has a burst of 0, it could never post",
+                    config.name
+                ));
+            }
+
+            let (broadcast_rx, handle, _, _) = room_manager
+                .join_room(
+                    &config.room,
+                    &SessionAndUserId {
+                        session_id: format!("webhook-{}", config.name),
+                        user_id: config.user_id.clone(),
+                    },
+                )
+                .await
+                .with_context(|| format!("webhook '{}' could not join its room", config.name))?;
+
+            webhooks.insert(
+                config.name,
+                Webhook {
+                    token: config.token,
+                    handle,
+                    _broadcast_rx: broadcast_rx,
+                    rate_limiter: Mutex::new(TokenBucket::new(
+                        RateLimit {
+                            burst: config.burst,
+                            per_second: config.per_second,
+                        },
+                        now,
+                    )),
+                    seen_signatures: Mutex::default(),
+                },
+            );
+        }
+
+        Ok(Webhooks { webhooks })
+    }
+
+    /// How many webhooks are defined
+    pub fn count(&self) -> usize {
+        self.webhooks.len()
+    }
+
+    /// Serves the requests of an HTTP connection, one at a time, until the sender or the server closes it
+    pub async fn serve_connection(
+        self: Arc<Self>,
+        socket: TcpStream,
+        mut quit_rx: broadcast::Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let service = service_fn(move |request| {
+            let webhooks = Arc::clone(&self);
+
+            async move { Ok::<_, Infallible>(webhooks.handle_request(request).await) }
+        });
+        let connection = http1::Builder::new()
+            .keep_alive(false)
+            .serve_connection(TokioIo::new(socket), service);
+
+        tokio::select! {
+            result = tokio::time::timeout(REQUEST_TIMEOUT, connection) => result
+                .context("the webhook request timed out")?
+                .context("could not serve the webhook request"),
+            _ = quit_rx.recv() => Ok(()),
+        }
+    }
+
+    async fn handle_request(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        match self.try_handle_request(request).await {
+            Ok(()) => text_response(StatusCode::NO_CONTENT, String::new()),
+            Err(err) => err.into_response(),
+        }
+    }
+
+    async fn try_handle_request(&self, request: Request<Incoming>) -> Result<(), WebhookError> {
+        let webhook = request
+            .uri()
+            .path()
+            .strip_prefix(WEBHOOK_PATH)
+            .and_then(|name| self.webhooks.get(name))
+            .ok_or(WebhookError::NotFound)?;
+        if request.method() != Method::POST {
+            return Err(WebhookError::MethodNotAllowed);
+        }
+
+        let signature = request
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|signature| signature.to_str().ok())
+            .map(String::from)
+            .ok_or(WebhookError::BadSignature)?;
+        let timestamp = request
+            .headers()
+            .get(TIMESTAMP_HEADER)
+            .and_then(|timestamp| timestamp.to_str().ok())
+            .and_then(|timestamp| timestamp.parse::<u64>().ok())
+            .ok_or(WebhookError::BadSignature)?;
+        let payload = Limited::new(request.into_body(), MAX_PAYLOAD_BYTES)
+            .collect()
+            .await
+            .map_err(|err| match err.downcast_ref::<LengthLimitError>() {
+                Some(_) => WebhookError::PayloadTooLarge,
+                None => WebhookError::BadPayload(err.to_string()),
+            })?
+            .to_bytes();
+        if !verify_signature(&webhook.token, timestamp, &payload, &signature) {
+            return Err(WebhookError::BadSignature);
+        }
+        let now = now_secs();
+        if !is_fresh(timestamp, now) {
+            return Err(WebhookError::StaleTimestamp);
+        }
+
+        // the digest is held while the payload is delivered, so the same payload sent twice at once is delivered once,
+        // and kept only once it is delivered
+        let digest = decode_signature(&signature).ok_or(WebhookError::BadSignature)?;
+        if !webhook
+            .seen_signatures
+            .lock()
+            .unwrap()
+            .insert(&digest, timestamp, now)
+        {
+            return Err(WebhookError::Replayed);
+        }
+
+        let delivered = Webhooks::deliver(webhook, &payload).await;
+        if delivered.is_err() {
+            webhook.seen_signatures.lock().unwrap().remove(&digest);
+        }
+
+        delivered
+    }
+
+    /// Posts the verified payload into the room of the webhook
+    async fn deliver(webhook: &Webhook, payload: &[u8]) -> Result<(), WebhookError> {
+        // only the signed payloads count, so nobody else can use up the rate of the webhook
+        webhook
+            .rate_limiter
+            .lock()
+            .unwrap()
+            .try_take(Instant::now())
+            .map_err(WebhookError::RateLimited)?;
+
+        let payload: WebhookPayload = serde_json::from_slice(payload)
+            .map_err(|err| WebhookError::BadPayload(err.to_string()))?;
+        if payload.text.trim().is_empty() {
+            return Err(WebhookError::BadPayload(String::from("the text is empty")));
+        }
+
+        webhook
+            .handle
+            .send_message(payload.text, None)
+            .await
+            .map_err(|err| WebhookError::Refused(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &[u8] = br#"{"text": "build #42 passed"}"#;
+
+    #[test]
+    fn test_signatures_match_the_token_the_timestamp_and_the_payload() {
+        let signature = sign("secret", 1_700_000_000, PAYLOAD);
+
+        assert!(verify_signature(
+            "secret",
+            1_700_000_000,
+            PAYLOAD,
+            &signature
+        ));
+        assert!(!verify_signature(
+            "other",
+            1_700_000_000,
+            PAYLOAD,
+            &signature
+        ));
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_001,
+            PAYLOAD,
+            &signature
+        ));
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_000,
+            br#"{"text": "build #42 failed"}"#,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_malformed_signatures_are_rejected() {
+        let signature = sign("secret", 1_700_000_000, PAYLOAD);
+        let digest = signature.strip_prefix(SIGNATURE_PREFIX).unwrap();
+
+        assert!(!verify_signature("secret", 1_700_000_000, PAYLOAD, digest));
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_000,
+            PAYLOAD,
+            "sha256=zz"
+        ));
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_000,
+            PAYLOAD,
+            "sha256="
+        ));
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_000,
+            PAYLOAD,
+            &signature[..signature.len() - 2]
+        ));
+    }
+
+    #[test]
+    fn test_only_the_payloads_signed_lately_are_fresh() {
+        let now = 1_700_000_000;
+        let skew = MAX_TIMESTAMP_SKEW.as_secs();
+
+        assert!(is_fresh(now, now));
+        assert!(is_fresh(now - skew, now));
+        assert!(is_fresh(now + skew, now));
+        assert!(!is_fresh(now - skew - 1, now));
+        assert!(!is_fresh(now + skew + 1, now));
+        assert!(!is_fresh(0, now));
+    }
+
+    #[test]
+    fn test_the_payloads_are_delivered_once_while_they_are_fresh() {
+        let now = 1_700_000_000;
+        let skew = MAX_TIMESTAMP_SKEW.as_secs();
+        let digest = |timestamp| decode_signature(&sign("secret", timestamp, PAYLOAD)).unwrap();
+        let mut seen_signatures = SeenSignatures::default();
+
+        assert!(seen_signatures.insert(&digest(now), now, now));
+        assert!(!seen_signatures.insert(&digest(now), now, now + skew));
+        assert!(seen_signatures.insert(&digest(now + 1), now + 1, now + 1));
+
+        // the stale digests are forgotten, they are refused as stale instead
+        seen_signatures.insert(b"other", now + skew + 1, now + skew + 1);
+        assert_eq!(seen_signatures.digests.len(), 2);
+
+        // a payload which was not delivered after all can be retried
+        seen_signatures.remove(&digest(now + 1));
+        assert!(seen_signatures.insert(&digest(now + 1), now + 1, now + skew + 1));
+    }
+
+    #[test]
+    fn test_the_signatures_in_another_case_are_the_same_payload() {
+        let now = 1_700_000_000;
+        let signature = sign("secret", now, PAYLOAD);
+        let uppercased = format!(
+            "{}{}",
+            SIGNATURE_PREFIX,
+            signature
+                .strip_prefix(SIGNATURE_PREFIX)
+                .unwrap()
+                .to_uppercase()
+        );
+        assert_ne!(signature, uppercased);
+        assert!(verify_signature("secret", now, PAYLOAD, &uppercased));
+
+        let mut seen_signatures = SeenSignatures::default();
+        assert!(seen_signatures.insert(&decode_signature(&signature).unwrap(), now, now));
+        assert!(!seen_signatures.insert(&decode_signature(&uppercased).unwrap(), now, now));
+    }
+}
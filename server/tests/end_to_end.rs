@@ -0,0 +1,805 @@
+mod harness;
+
+use std::{os::unix::fs::PermissionsExt, time::Duration};
+
+use comms::{
+    admin::{AdminRequest, AdminResponse, ExportFormat},
+    command::{
+        BanUserCommand, CreateRoomCommand, DeleteMessageCommand, DeleteMyAccountCommand,
+        DeleteRoomCommand, DownloadFileCommand, EditMessageCommand, KickUserCommand,
+        MuteUserCommand, ReactToMessageCommand, SetRoomRoleCommand, SetRoomTopicCommand,
+        StartUploadCommand, UploadChunkCommand, UserCommand,
+    },
+    event::{Event, ModerationAction, RoomParticipationStatus, RoomRole},
+    file_transfer::{self, MAX_FILE_SIZE},
+};
+use harness::{next_log, next_matching, within, TestServer};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+use tokio_stream::Stream;
+
+#[tokio::test]
+async fn test_messages_fan_out_to_the_members_of_the_room() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+    let carol = server.login("carol").await;
+    let dave = server.login("dave").await;
+
+    for client in [&alice, &bob, &carol] {
+        within(client.join("general")).await.unwrap();
+    }
+    within(dave.join("rust")).await.unwrap();
+
+    let mut bob_messages = bob.messages();
+    let mut carol_messages = carol.messages();
+    let mut dave_messages = dave.messages();
+
+    within(alice.send_message("general", "hello"))
+        .await
+        .unwrap();
+    within(dave.send_message("rust", "elsewhere"))
+        .await
+        .unwrap();
+
+    for messages in [&mut bob_messages, &mut carol_messages] {
+        let message = next_matching(messages, Some).await;
+        assert_eq!(message.room, "general");
+        assert_eq!(message.user_id, "alice");
+        assert_eq!(message.content, "hello");
+    }
+
+    // the message of general was broadcast before dave sent his, so he would have been sent it first
+    let message = next_matching(&mut dave_messages, Some).await;
+    assert_eq!(message.content, "elsewhere");
+}
+
+#[tokio::test]
+async fn test_members_who_left_are_not_sent_the_messages() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    for client in [&alice, &bob] {
+        within(client.join("general")).await.unwrap();
+        within(client.join("rust")).await.unwrap();
+    }
+
+    let mut alice_events = alice.events();
+    let mut bob_messages = bob.messages();
+    within(bob.leave("general")).await.unwrap();
+
+    next_matching(&mut alice_events, |event| match event {
+        Event::RoomParticipation(participation)
+            if participation.room == "general"
+                && participation.user_id == "bob"
+                && participation.status == RoomParticipationStatus::Left =>
+        {
+            Some(())
+        }
+        _ => None,
+    })
+    .await;
+
+    within(alice.send_message("general", "bob is gone"))
+        .await
+        .unwrap();
+    within(alice.send_message("rust", "still here"))
+        .await
+        .unwrap();
+
+    let message = next_matching(&mut bob_messages, Some).await;
+    assert_eq!(message.room, "rust");
+    assert_eq!(message.content, "still here");
+}
+
+#[tokio::test]
+async fn test_clients_reconnect_and_rejoin_after_a_restart() {
+    let mut server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    for client in [&alice, &bob] {
+        within(client.join("general")).await.unwrap();
+    }
+
+    let mut alice_events = alice.events();
+    let mut bob_events = bob.events();
+    server.restart().await;
+
+    // the sessions were lost with the server, the clients log in and join their rooms again
+    for events in [&mut alice_events, &mut bob_events] {
+        next_matching(events, |event| match event {
+            Event::UserJoinedRoom(joined) if joined.room == "general" => Some(()),
+            _ => None,
+        })
+        .await;
+    }
+
+    within(alice.send_message("general", "welcome back"))
+        .await
+        .unwrap();
+
+    let message = next_matching(&mut bob_events, |event| match event {
+        Event::UserMessage(message) => Some(message),
+        _ => None,
+    })
+    .await;
+    assert_eq!(message.user_id, "alice");
+    assert_eq!(message.content, "welcome back");
+}
+
+#[tokio::test]
+async fn test_authors_edit_and_delete_their_messages() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    for client in [&alice, &bob] {
+        within(client.join("general")).await.unwrap();
+    }
+
+    let mut bob_events = bob.events();
+    within(alice.send_message("general", "helo")).await.unwrap();
+    let id = next_matching(&mut bob_events, |event| match event {
+        Event::UserMessage(message) => Some(message.id),
+        _ => None,
+    })
+    .await;
+
+    let edit = |content: &str| {
+        UserCommand::EditMessage(EditMessageCommand {
+            room: String::from("general"),
+            id,
+            content: String::from(content),
+        })
+    };
+    // only the author can change the message
+    assert!(within(bob.request(edit("hijacked"))).await.is_err());
+    within(alice.request(edit("hello"))).await.unwrap();
+
+    let edited = next_matching(&mut bob_events, |event| match event {
+        Event::MessageEdited(edited) => Some(edited),
+        _ => None,
+    })
+    .await;
+    assert_eq!(edited.id, id);
+    assert_eq!(edited.content, "hello");
+
+    let delete = UserCommand::DeleteMessage(DeleteMessageCommand {
+        room: String::from("general"),
+        id,
+    });
+    assert!(within(bob.request(delete.clone())).await.is_err());
+    within(alice.request(delete)).await.unwrap();
+
+    let deleted = next_matching(&mut bob_events, |event| match event {
+        Event::MessageDeleted(deleted) => Some(deleted),
+        _ => None,
+    })
+    .await;
+    assert_eq!(deleted.id, id);
+}
+
+#[tokio::test]
+async fn test_reactions_are_counted_by_the_server() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+    let carol = server.login("carol").await;
+
+    for client in [&alice, &bob, &carol] {
+        within(client.join("general")).await.unwrap();
+    }
+
+    let mut alice_events = alice.events();
+    within(alice.send_message("general", "lunch?"))
+        .await
+        .unwrap();
+    let id = next_matching(&mut alice_events, |event| match event {
+        Event::UserMessage(message) => Some(message.id),
+        _ => None,
+    })
+    .await;
+
+    for client in [&bob, &carol] {
+        within(
+            client.request(UserCommand::ReactToMessage(ReactToMessageCommand {
+                room: String::from("general"),
+                id,
+                emoji: String::from("👍"),
+            })),
+        )
+        .await
+        .unwrap();
+    }
+
+    // the reactions are sent whole each time, the second one counts both users
+    let count = next_matching(&mut alice_events, |event| match event {
+        Event::MessageReactions(reactions) if reactions.id == id => reactions
+            .reactions
+            .into_iter()
+            .find(|reaction| reaction.emoji == "👍" && reaction.count == 2),
+        _ => None,
+    })
+    .await;
+    assert_eq!(count.count, 2);
+}
+
+#[tokio::test]
+async fn test_rooms_are_created_and_deleted_by_their_creator() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    let mut bob_events = bob.events();
+    within(alice.request(UserCommand::CreateRoom(CreateRoomCommand {
+        room: String::from("book-club"),
+        description: String::from("One chapter a week"),
+    })))
+    .await
+    .unwrap();
+
+    let created = next_matching(&mut bob_events, |event| match event {
+        Event::RoomCreated(created) => Some(created),
+        _ => None,
+    })
+    .await;
+    assert_eq!(created.room.name, "book-club");
+    assert_eq!(created.created_by, "alice");
+    within(bob.join("book-club")).await.unwrap();
+
+    let delete = UserCommand::DeleteRoom(DeleteRoomCommand {
+        room: String::from("book-club"),
+    });
+    assert!(within(bob.request(delete.clone())).await.is_err());
+    within(alice.request(delete)).await.unwrap();
+
+    next_matching(&mut bob_events, |event| match event {
+        Event::RoomDeleted(deleted) if deleted.room == "book-club" => Some(()),
+        _ => None,
+    })
+    .await;
+    assert!(within(bob.join("book-club")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_the_topic_is_changed_by_the_owner_of_the_room() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    within(alice.request(UserCommand::CreateRoom(CreateRoomCommand {
+        room: String::from("book-club"),
+        description: String::from("One chapter a week"),
+    })))
+    .await
+    .unwrap();
+    within(bob.join("book-club")).await.unwrap();
+
+    let set_topic = |topic: &str| {
+        UserCommand::SetRoomTopic(SetRoomTopicCommand {
+            room: String::from("book-club"),
+            topic: String::from(topic),
+        })
+    };
+    let mut bob_events = bob.events();
+    // the members of the room can not change its topic
+    assert!(within(bob.request(set_topic("Spoilers"))).await.is_err());
+    within(alice.request(set_topic("Two chapters a week")))
+        .await
+        .unwrap();
+
+    let changed = next_matching(&mut bob_events, |event| match event {
+        Event::RoomTopicChanged(changed) => Some(changed),
+        _ => None,
+    })
+    .await;
+    assert_eq!(changed.room, "book-club");
+    assert_eq!(changed.topic, "Two chapters a week");
+    assert_eq!(changed.changed_by, "alice");
+}
+
+#[tokio::test]
+async fn test_moderators_kick_ban_and_mute_the_members() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+    let carol = server.login("carol").await;
+
+    within(alice.request(UserCommand::CreateRoom(CreateRoomCommand {
+        room: String::from("book-club"),
+        description: String::from("One chapter a week"),
+    })))
+    .await
+    .unwrap();
+    for client in [&alice, &bob, &carol] {
+        within(client.join("book-club")).await.unwrap();
+    }
+
+    let mut alice_events = alice.events();
+    let mut carol_events = carol.events();
+    within(alice.request(UserCommand::MuteUser(MuteUserCommand {
+        room: String::from("book-club"),
+        user_id: String::from("bob"),
+        seconds: 60,
+    })))
+    .await
+    .unwrap();
+    assert_eq!(
+        moderated(&mut alice_events, "bob").await,
+        ModerationAction::Muted { seconds: 60 }
+    );
+    assert!(within(bob.send_message("book-club", "hello?"))
+        .await
+        .is_err());
+
+    within(alice.request(UserCommand::KickUser(KickUserCommand {
+        room: String::from("book-club"),
+        user_id: String::from("carol"),
+    })))
+    .await
+    .unwrap();
+    // the room is left by the session of the user before they are told
+    assert_eq!(
+        moderated(&mut carol_events, "carol").await,
+        ModerationAction::Kicked
+    );
+    // a kicked user can come back, a banned one can not
+    within(carol.join("book-club")).await.unwrap();
+
+    within(alice.request(UserCommand::BanUser(BanUserCommand {
+        room: String::from("book-club"),
+        user_id: String::from("carol"),
+    })))
+    .await
+    .unwrap();
+    assert_eq!(
+        moderated(&mut carol_events, "carol").await,
+        ModerationAction::Banned
+    );
+    assert!(within(carol.join("book-club")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_the_roles_are_checked_before_the_room_commands() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+    let carol = server.login("carol").await;
+
+    within(alice.request(UserCommand::CreateRoom(CreateRoomCommand {
+        room: String::from("book-club"),
+        description: String::from("One chapter a week"),
+    })))
+    .await
+    .unwrap();
+    for client in [&alice, &bob, &carol] {
+        within(client.join("book-club")).await.unwrap();
+    }
+
+    let kick_carol = UserCommand::KickUser(KickUserCommand {
+        room: String::from("book-club"),
+        user_id: String::from("carol"),
+    });
+    let set_role = |user_id: &str, role| {
+        UserCommand::SetRoomRole(SetRoomRoleCommand {
+            room: String::from("book-club"),
+            user_id: String::from(user_id),
+            role,
+        })
+    };
+    // members moderate nobody, and only the owner hands out roles
+    assert!(within(bob.request(kick_carol.clone())).await.is_err());
+    assert!(within(bob.request(set_role("bob", RoomRole::Moderator)))
+        .await
+        .is_err());
+
+    let mut bob_events = bob.events();
+    within(alice.request(set_role("bob", RoomRole::Moderator)))
+        .await
+        .unwrap();
+    let role = next_matching(&mut bob_events, |event| match event {
+        Event::RoomRole(role) if role.user_id == "bob" => Some(role.role),
+        _ => None,
+    })
+    .await;
+    assert_eq!(role, RoomRole::Moderator);
+
+    within(bob.request(kick_carol)).await.unwrap();
+    // the owner is out of reach of the moderators
+    assert!(within(bob.request(UserCommand::KickUser(KickUserCommand {
+        room: String::from("book-club"),
+        user_id: String::from("alice"),
+    })))
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_spammers_are_muted_in_the_room_they_flooded() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+
+    for room in ["general", "rust"] {
+        within(alice.join(room)).await.unwrap();
+    }
+
+    let mut alice_events = alice.events();
+    // the fourth time the same message is sent within 30 seconds is one too many
+    for _ in 0..4 {
+        within(alice.send_message("general", "buy now"))
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        moderated(&mut alice_events, "alice").await,
+        ModerationAction::Muted { seconds: 5 * 60 }
+    );
+
+    assert!(within(alice.send_message("general", "sorry"))
+        .await
+        .is_err());
+    within(alice.send_message("rust", "still here"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_files_are_shared_with_the_members_of_the_room() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    for client in [&alice, &bob] {
+        within(client.join("general")).await.unwrap();
+    }
+
+    let content = b"the minutes of the meeting";
+    let start_upload = |upload_id: &str, name: &str, size| {
+        UserCommand::StartUpload(StartUploadCommand {
+            upload_id: String::from(upload_id),
+            room: String::from("general"),
+            name: String::from(name),
+            size,
+            checksum: file_transfer::checksum(content),
+        })
+    };
+    assert!(
+        within(alice.request(start_upload("too-large", "minutes.txt", MAX_FILE_SIZE + 1)))
+            .await
+            .is_err()
+    );
+    for name in ["../minutes.txt", "notes/minutes.txt", "..", ""] {
+        assert!(
+            within(alice.request(start_upload("bad-name", name, content.len() as u64)))
+                .await
+                .is_err(),
+            "{:?}",
+            name
+        );
+    }
+
+    let mut bob_events = bob.events();
+    within(alice.request(start_upload("minutes", "minutes.txt", content.len() as u64)))
+        .await
+        .unwrap();
+    within(alice.request(UserCommand::UploadChunk(UploadChunkCommand {
+        upload_id: String::from("minutes"),
+        offset: 0,
+        data: file_transfer::encode_chunk(content),
+    })))
+    .await
+    .unwrap();
+
+    let shared = next_matching(&mut bob_events, |event| match event {
+        Event::FileShared(shared) => Some(shared),
+        _ => None,
+    })
+    .await;
+    assert_eq!(shared.user_id, "alice");
+    assert_eq!(shared.name, "minutes.txt");
+    assert_eq!(shared.size, content.len() as u64);
+
+    within(bob.request(UserCommand::DownloadFile(DownloadFileCommand {
+        file_id: shared.file_id.clone(),
+        offset: 0,
+    })))
+    .await
+    .unwrap();
+    let chunk = next_matching(&mut bob_events, |event| match event {
+        Event::FileChunk(chunk) => Some(chunk),
+        _ => None,
+    })
+    .await;
+    assert_eq!(file_transfer::decode_chunk(&chunk.data).unwrap(), content);
+
+    // the ids of the files are never taken as paths
+    assert!(
+        within(bob.request(UserCommand::DownloadFile(DownloadFileCommand {
+            file_id: String::from("../chat.sqlite3"),
+            offset: 0,
+        })))
+        .await
+        .is_err()
+    );
+
+    // the uploads waiting for their chunks are capped for each user
+    for upload_id in ["first", "second"] {
+        within(alice.request(start_upload(upload_id, "minutes.txt", MAX_FILE_SIZE)))
+            .await
+            .unwrap();
+    }
+    assert!(
+        within(alice.request(start_upload("third", "minutes.txt", MAX_FILE_SIZE)))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_the_admin_console_is_only_reachable_by_the_user_running_the_server() {
+    let server = TestServer::start().await;
+
+    let socket_path = server.admin_socket_path();
+    let mode = std::fs::metadata(&socket_path)
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // the private directory the socket was bound in is gone once the socket is moved into place
+    let leftovers = std::fs::read_dir(socket_path.parent().unwrap())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(".admin-"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[tokio::test]
+async fn test_the_admin_console_lists_the_sessions_and_inspects_the_rooms() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    within(alice.join("general")).await.unwrap();
+
+    let [AdminResponse::Sessions { sessions }] =
+        &server.admin(&AdminRequest::ListSessions).await[..]
+    else {
+        panic!("the sessions were not listed");
+    };
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].username, "alice");
+
+    let [AdminResponse::Room(room)] = &server
+        .admin(&AdminRequest::InspectRoom {
+            room: String::from("general"),
+        })
+        .await[..]
+    else {
+        panic!("the room was not inspected");
+    };
+    assert_eq!(room.users, vec![String::from("alice")]);
+
+    let responses = server
+        .admin(&AdminRequest::InspectRoom {
+            room: String::from("nowhere"),
+        })
+        .await;
+    assert!(matches!(responses[..], [AdminResponse::Error { .. }]));
+}
+
+#[tokio::test]
+async fn test_the_admin_console_kicks_the_users() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+
+    let mut alice_events = alice.events();
+    let responses = server
+        .admin(&AdminRequest::KickUser {
+            username: String::from("alice"),
+            reason: String::from("take a break"),
+        })
+        .await;
+    assert!(matches!(responses[..], [AdminResponse::Done { .. }]));
+
+    let reason = next_matching(&mut alice_events, |event| match event {
+        Event::SessionKicked(kicked) => Some(kicked.reason),
+        _ => None,
+    })
+    .await;
+    assert_eq!(reason, "take a break");
+}
+
+#[tokio::test]
+async fn test_deleting_an_account_ends_the_other_sessions_of_the_user() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let other_alice = server.login("alice").await;
+
+    let mut other_events = other_alice.events();
+    within(alice.request(UserCommand::DeleteMyAccount(DeleteMyAccountCommand)))
+        .await
+        .unwrap();
+
+    let reason = next_matching(&mut other_events, |event| match event {
+        Event::SessionKicked(kicked) => Some(kicked.reason),
+        _ => None,
+    })
+    .await;
+    assert_eq!(reason, "the account was deleted");
+}
+
+#[tokio::test]
+async fn test_the_admin_console_announces_to_every_session() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    let bob = server.login("bob").await;
+
+    let mut events = [alice.events(), bob.events()];
+    let responses = server
+        .admin(&AdminRequest::Announce {
+            text: String::from("restarting at noon"),
+        })
+        .await;
+    assert!(matches!(responses[..], [AdminResponse::Done { .. }]));
+
+    for events in events.iter_mut() {
+        let text = next_matching(events, |event| match event {
+            Event::Announcement(announcement) => Some(announcement.text),
+            _ => None,
+        })
+        .await;
+        assert_eq!(text, "restarting at noon");
+    }
+
+    let responses = server
+        .admin(&AdminRequest::Announce {
+            text: String::from("  "),
+        })
+        .await;
+    assert!(matches!(responses[..], [AdminResponse::Error { .. }]));
+}
+
+#[tokio::test]
+async fn test_the_admin_console_dumps_the_stats() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    within(alice.join("general")).await.unwrap();
+    within(alice.send_message("general", "hello"))
+        .await
+        .unwrap();
+
+    let [AdminResponse::Stats(stats)] = &server.admin(&AdminRequest::Stats).await[..] else {
+        panic!("the stats were not dumped");
+    };
+    assert_eq!(
+        stats.sessions.iter().map(|(_, count)| count).sum::<usize>(),
+        1
+    );
+    assert_eq!(stats.messages_sent, 1);
+    assert!(stats.rooms > 0);
+}
+
+#[tokio::test]
+async fn test_the_admin_console_closes_the_connection_on_a_request_too_long() {
+    let server = TestServer::start().await;
+
+    let socket = UnixStream::connect(server.admin_socket_path())
+        .await
+        .unwrap();
+    let (reader, mut writer) = socket.into_split();
+    // the tail of the long request must not be taken for the stats request
+    let mut requests = format!(
+        "{{\"padding\":\"{}\"}}{}\n",
+        "x".repeat(20 * 1024),
+        serde_json::to_string(&AdminRequest::Stats).unwrap()
+    );
+    requests.push_str(&serde_json::to_string(&AdminRequest::Stats).unwrap());
+    requests.push('\n');
+    // the console may close the connection before reading all of it
+    let _ = writer.write_all(requests.as_bytes()).await;
+
+    let mut lines = BufReader::new(reader).lines();
+    let response: AdminResponse =
+        serde_json::from_str(&within(lines.next_line()).await.unwrap().unwrap()).unwrap();
+    assert!(matches!(response, AdminResponse::Error { .. }));
+    // the connection is reset rather than closed when the rest of the requests is left unread
+    assert!(!matches!(within(lines.next_line()).await, Ok(Some(_))));
+}
+
+#[tokio::test]
+async fn test_the_admin_console_exports_the_rooms() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    within(alice.join("general")).await.unwrap();
+    for content in ["hello", "world"] {
+        within(alice.send_message("general", content))
+            .await
+            .unwrap();
+    }
+
+    let export = AdminRequest::ExportRoom {
+        room: String::from("general"),
+        format: ExportFormat::Text,
+        since: None,
+        until: None,
+    };
+    // the messages are written to the store in the background
+    let responses = within(async {
+        loop {
+            let responses = server.admin(&export).await;
+            if let Some(AdminResponse::Done { message }) = responses.last() {
+                if message == "exported 2 messages of room 'general'" {
+                    return responses;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+
+    let [AdminResponse::ExportChunk { lines }, AdminResponse::Done { .. }] = &responses[..] else {
+        panic!("the room was not exported: {:?}", responses);
+    };
+    assert!(lines[0].ends_with("<alice> hello"), "{}", lines[0]);
+    assert!(lines[1].ends_with("<alice> world"), "{}", lines[1]);
+}
+
+#[tokio::test]
+async fn test_a_reloaded_configuration_is_applied() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    within(alice.join("general")).await.unwrap();
+
+    let mut logs = server.logs();
+    server.reload("[limits]\nmax_message_chars = 5\n").await;
+    next_log(&mut logs, "reloaded the configuration").await;
+
+    // the connected sessions are held to the new length right away
+    assert!(within(alice.send_message("general", "hello!"))
+        .await
+        .is_err());
+    within(alice.send_message("general", "hello"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_an_invalid_configuration_is_rejected_keeping_the_previous_one() {
+    let server = TestServer::start().await;
+    let alice = server.login("alice").await;
+    within(alice.join("general")).await.unwrap();
+
+    let mut logs = server.logs();
+    server.reload("[limits]\nmax_message_chars = 5\n").await;
+    next_log(&mut logs, "reloaded the configuration").await;
+    for invalid_config in [
+        "[limits]\nmax_message_chars = \"many\"\n",
+        "[limits]\nmax_message_chars = 500\nunknown_limit = 1\n",
+        "[[content_filters]]\npatterns = [\"(unclosed\"]\naction = \"reject\"\n",
+    ] {
+        server.reload(invalid_config).await;
+        next_log(&mut logs, "could not reload the configuration").await;
+    }
+
+    assert!(within(alice.send_message("general", "hello!"))
+        .await
+        .is_err());
+    within(alice.send_message("general", "hello"))
+        .await
+        .unwrap();
+}
+
+/// The next moderation of the user the client is told about
+async fn moderated(
+    events: &mut (impl Stream<Item = Event> + Unpin),
+    user_id: &str,
+) -> ModerationAction {
+    next_matching(events, |event| match event {
+        Event::UserModerated(moderated) if moderated.user_id == user_id => Some(moderated.action),
+        _ => None,
+    })
+    .await
+}
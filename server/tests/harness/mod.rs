@@ -0,0 +1,251 @@
+//! Runs the server binary on a port picked by the system, for the tests to connect clients to it
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use client::Client;
+use comms::admin::{AdminRequest, AdminResponse};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    process::{Child, Command},
+    sync::broadcast,
+};
+use tokio_stream::{Stream, StreamExt};
+
+/// How long a step of a test is given, logging in hashes the password which is slow in debug builds
+const STEP_TIMEOUT: Duration = Duration::from_secs(20);
+const PASSWORD: &str = "harness_password";
+
+/// [TestServer] is a server running in a process of its own, with a database in a directory of its own
+///
+/// The process is killed and the directory removed once it is dropped.
+pub struct TestServer {
+    process: Child,
+    dir: PathBuf,
+    port: u16,
+    /// The messages the server logs, across its restarts
+    logs_tx: broadcast::Sender<String>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let dir = std::env::temp_dir().join(format!("chat-server-test-{}", nanoid::nanoid!()));
+        std::fs::create_dir_all(&dir).expect("could not create the directory of the server");
+
+        let (logs_tx, _) = broadcast::channel(1024);
+        let (process, port) = spawn(&dir, 0, &logs_tx).await;
+
+        TestServer {
+            process,
+            dir,
+            port,
+            logs_tx,
+        }
+    }
+
+    pub fn addr(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    /// Connects a client logged in as the user, who is registered on their first login
+    pub async fn login(&self, username: &str) -> Client {
+        within(async {
+            let client = Client::connect(&self.addr())
+                .await
+                .expect("could not connect to the server");
+            client
+                .login(username, PASSWORD)
+                .await
+                .expect("could not log in");
+
+            client
+        })
+        .await
+    }
+
+    /// The Unix socket of the admin console of the server
+    pub fn admin_socket_path(&self) -> PathBuf {
+        self.dir.join("admin.sock")
+    }
+
+    /// Sends the request to the admin console, returns its responses up to the final one
+    pub async fn admin(&self, request: &AdminRequest) -> Vec<AdminResponse> {
+        within(async {
+            let socket = UnixStream::connect(self.admin_socket_path())
+                .await
+                .expect("could not connect to the admin console");
+            let (reader, mut writer) = socket.into_split();
+
+            let mut line = serde_json::to_string(request).unwrap();
+            line.push('\n');
+            writer
+                .write_all(line.as_bytes())
+                .await
+                .expect("could not send the admin request");
+
+            let mut lines = BufReader::new(reader).lines();
+            let mut responses = vec![];
+            while let Some(line) = lines
+                .next_line()
+                .await
+                .expect("could not read the admin response")
+            {
+                let response: AdminResponse = serde_json::from_str(&line).unwrap();
+                let is_final = !matches!(response, AdminResponse::ExportChunk { .. });
+                responses.push(response);
+
+                if is_final {
+                    return responses;
+                }
+            }
+
+            panic!("the admin console closed the connection")
+        })
+        .await
+    }
+
+    /// Kills the server and starts it again on the same port with the same database, the sessions are lost
+    pub async fn restart(&mut self) {
+        self.process
+            .kill()
+            .await
+            .expect("could not kill the server");
+
+        let (process, _) = spawn(&self.dir, self.port, &self.logs_tx).await;
+        self.process = process;
+    }
+
+    /// The messages the server logs from now on
+    pub fn logs(&self) -> broadcast::Receiver<String> {
+        self.logs_tx.subscribe()
+    }
+
+    /// Replaces the configuration file, keeping the ports, and tells the server to reload it with `SIGHUP`
+    pub async fn reload(&self, config: &str) {
+        std::fs::write(
+            self.dir.join("server.toml"),
+            format!("port = {}\nwebsocket_port = 0\n{}", self.port, config),
+        )
+        .expect("could not write the configuration of the server");
+
+        let pid = self.process.id().expect("the server has exited");
+        let status = Command::new("kill")
+            .arg("-HUP")
+            .arg(pid.to_string())
+            .status()
+            .await
+            .expect("could not signal the server");
+        assert!(status.success(), "could not signal the server");
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Starts the server on the port, returning once it listens along with the port it was bound to
+async fn spawn(dir: &Path, port: u16, logs_tx: &broadcast::Sender<String>) -> (Child, u16) {
+    let config_path = dir.join("server.toml");
+    std::fs::write(
+        &config_path,
+        format!("port = {}\nwebsocket_port = 0\n", port),
+    )
+    .expect("could not write the configuration of the server");
+
+    let mut process = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--config")
+        .arg(&config_path)
+        .env("CHAT_DATABASE_PATH", dir.join("chat.sqlite3"))
+        .env("CHAT_ATTACHMENTS_DIR", dir.join("attachments"))
+        .env("CHAT_ADMIN_SOCKET_PATH", dir.join("admin.sock"))
+        .env("CHAT_LOG_FORMAT", "json")
+        .env("RUST_LOG", "info")
+        // the tests send faster than the default limits allow
+        .env("CHAT_RATE_LIMIT_MESSAGES_PER_SEC", "0")
+        .env("CHAT_RATE_LIMIT_JOINS_PER_SEC", "0")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("could not start the server");
+
+    let mut logs = BufReader::new(process.stdout.take().expect("the logs are piped")).lines();
+    let port = within(async {
+        while let Some(line) = logs.next_line().await.expect("could not read the logs") {
+            if let Some(port) = log_message(&line).as_deref().and_then(listening_port) {
+                return port;
+            }
+        }
+
+        panic!("the server exited before listening")
+    })
+    .await;
+
+    // the logs are still read, so the server never waits on a full pipe
+    let logs_tx = logs_tx.clone();
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = logs.next_line().await {
+            if let Some(message) = log_message(&line) {
+                let _ = logs_tx.send(message);
+            }
+        }
+    });
+
+    (process, port)
+}
+
+/// Reads the message of a JSON log line
+fn log_message(line: &str) -> Option<String> {
+    let log: serde_json::Value = serde_json::from_str(line).ok()?;
+
+    log["fields"]["message"].as_str().map(String::from)
+}
+
+/// Reads the port from the log message announcing it, as in `Listening on port 8080`
+fn listening_port(message: &str) -> Option<u16> {
+    message.strip_prefix("Listening on port ")?.parse().ok()
+}
+
+/// Skips the log messages until one starts with the prefix, fails the test if it takes too long
+pub async fn next_log(logs: &mut broadcast::Receiver<String>, prefix: &str) -> String {
+    within(async {
+        loop {
+            match logs.recv().await {
+                Ok(message) if message.starts_with(prefix) => return message,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => panic!("the logs ended"),
+            }
+        }
+    })
+    .await
+}
+
+/// Awaits the future, fails the test if it takes too long
+pub async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(STEP_TIMEOUT, future)
+        .await
+        .expect("timed out")
+}
+
+/// Skips the events until one is picked, fails the test if the stream ends first
+pub async fn next_matching<E, T>(
+    events: &mut (impl Stream<Item = E> + Unpin),
+    mut pick: impl FnMut(E) -> Option<T>,
+) -> T {
+    within(async {
+        while let Some(event) = events.next().await {
+            if let Some(picked) = pick(event) {
+                return picked;
+            }
+        }
+
+        panic!("the stream ended")
+    })
+    .await
+}
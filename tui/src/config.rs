@@ -0,0 +1,418 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::keymap::{self, Keymap};
+
+/// The version of the config schema, bumped whenever a setting is added, changed or removed
+pub const CONFIG_VERSION: u32 = 11;
+/// Environment variable to override the location of the config file
+const CONFIG_PATH_ENV: &str = "CHAT_TUI_CONFIG";
+
+/// How the new messages of the inactive rooms are told about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertPolicy {
+    #[default]
+    None,
+    /// Rings the bell of the terminal
+    Bell,
+    /// Flashes the title of the terminal with the room, through an OSC escape sequence
+    Flash,
+}
+
+/// How the rooms which are not in a space are ordered in the room list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomSort {
+    /// The favorite rooms first, then the rooms with unread mentions or messages, each group by name
+    #[default]
+    Activity,
+    /// By name alone, the favorites included
+    Alphabetical,
+}
+
+/// How the chat page shares its width between the side panels and the messages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// The share of the width taken by the rooms on the left, in percent
+    pub left_panel_percent: u16,
+    /// The share of the width taken by the room users on the right, in percent
+    pub right_panel_percent: u16,
+    /// The terminal width below which the left panel is collapsed, in columns
+    pub collapse_left_panel_below: u16,
+    /// The terminal width below which the right panel is collapsed, in columns
+    pub collapse_right_panel_below: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            left_panel_percent: 20,
+            right_panel_percent: 20,
+            collapse_left_panel_below: 80,
+            collapse_right_panel_below: 100,
+        }
+    }
+}
+
+/// ClientConfig holds the settings of the client, persisted as a toml file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// The version of the schema the file was written with
+    pub version: u32,
+    /// The server address pre-populated on the connect page
+    pub server_addr: String,
+    /// Should the room input templates pre-populate the message input
+    pub use_input_templates: bool,
+    /// Words which highlight a message like a mention does, added in version 2
+    pub highlight_words: Vec<String>,
+    /// The strftime format of the time the messages are prefixed with, no prefix when empty, added in version 3
+    pub time_format: String,
+    /// The name of the built-in theme, added in version 4
+    pub theme: String,
+    /// Colors overriding those of the theme by their role, such as `accent = "light blue"`, added in version 4
+    pub colors: BTreeMap<String, String>,
+    /// Should the mentions and the direct messages the user is not looking at be notified on the desktop, added in version 5
+    pub desktop_notifications: bool,
+    /// How the new messages of the inactive rooms are told about, added in version 6
+    pub alert: AlertPolicy,
+    /// The widths of the side panels of the chat page, added in version 7
+    pub layout: LayoutConfig,
+    /// Should the chat page take the keys as vim does, moving through the messages in the normal mode and typing
+    /// them in the insert mode, added in version 8
+    pub vim_mode: bool,
+    /// The users whose messages are hidden in every room and whose direct messages are dropped, added in version 9
+    pub ignored_users: Vec<String>,
+    /// The rooms listed ahead of the others, added in version 10
+    pub favorite_rooms: Vec<String>,
+    /// How the rooms which are not in a space are ordered in the room list, added in version 10
+    pub room_sort: RoomSort,
+    /// Should the images linked to by the messages be fetched to preview them, which tells the hosts of the images
+    /// the address of the viewer, added in version 11
+    pub preview_image_links: bool,
+    /// The key bindings, which are kept in their own file next to the config file
+    #[serde(skip)]
+    pub keymap: Keymap,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            version: CONFIG_VERSION,
+            server_addr: String::from("localhost:8080"),
+            use_input_templates: true,
+            highlight_words: vec![],
+            time_format: String::from("%H:%M"),
+            theme: String::from("dark"),
+            colors: BTreeMap::new(),
+            desktop_notifications: true,
+            alert: AlertPolicy::None,
+            layout: LayoutConfig::default(),
+            vim_mode: false,
+            ignored_users: vec![],
+            favorite_rooms: vec![],
+            room_sort: RoomSort::Activity,
+            preview_image_links: false,
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+/// A difference between the config file and the current schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+    /// A new setting, which is written with its default value
+    Added { key: String, value: String },
+    /// A setting whose value is no longer valid, which is reset to its default value
+    Reset {
+        key: String,
+        previous: String,
+        value: String,
+    },
+    /// A setting which is no longer used, which is dropped
+    Removed { key: String, previous: String },
+}
+
+/// The changes needed to upgrade a config file to the current schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigMigration {
+    pub from_version: u32,
+    pub changes: Vec<ConfigChange>,
+    /// The config after applying the changes, which is written to the file once accepted
+    pub upgraded: ClientConfig,
+}
+
+#[derive(Debug)]
+pub enum LoadedConfig {
+    Current(ClientConfig),
+    /// The file was written with an older schema, the user needs to review the changes
+    NeedsMigration(ConfigMigration),
+}
+
+/// The path of the config file, in the config directory of the user unless overridden
+pub fn config_path() -> Option<PathBuf> {
+    std::env::var_os(CONFIG_PATH_ENV)
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("rust-chat-server").join("tui.toml")))
+}
+
+/// Loads the config file along with the key bindings file, creating them with the default settings if they do not exist
+pub fn load(path: &Path) -> anyhow::Result<LoadedConfig> {
+    let keymap = keymap::load(&path.with_file_name(keymap::KEYS_FILE_NAME))?;
+
+    if !path.exists() {
+        let config = ClientConfig {
+            keymap,
+            ..Default::default()
+        };
+        save(path, &config)?;
+
+        return Ok(LoadedConfig::Current(config));
+    }
+
+    let table: toml::Table = std::fs::read_to_string(path)
+        .context("could not read the config file")?
+        .parse()
+        .context("could not parse the config file")?;
+
+    Ok(match plan_migration(&table)? {
+        Some(migration) => LoadedConfig::NeedsMigration(ConfigMigration {
+            upgraded: ClientConfig {
+                keymap,
+                ..migration.upgraded
+            },
+            ..migration
+        }),
+        None => LoadedConfig::Current(ClientConfig {
+            keymap,
+            ..table.try_into()?
+        }),
+    })
+}
+
+pub fn save(path: &Path, config: &ClientConfig) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("could not create the config directory")?;
+    }
+
+    std::fs::write(path, toml::to_string_pretty(config)?).context("could not write the config file")
+}
+
+/// Compares the settings in the file to the current schema, returns None if the file is up to date
+///
+/// Valid settings are kept as they are, the rest are added, reset or removed.
+pub fn plan_migration(file: &toml::Table) -> anyhow::Result<Option<ConfigMigration>> {
+    let from_version = file
+        .get("version")
+        .and_then(|version| version.as_integer())
+        .unwrap_or(0) as u32;
+    let defaults = toml::Table::try_from(ClientConfig::default())?;
+
+    let mut changes = vec![];
+    let mut upgraded = defaults.clone();
+
+    for (key, default_value) in defaults.iter().filter(|(key, _)| *key != "version") {
+        let Some(file_value) = file.get(key) else {
+            changes.push(ConfigChange::Added {
+                key: key.clone(),
+                value: default_value.to_string(),
+            });
+            continue;
+        };
+
+        // a value is valid if the config can still be read with it
+        let mut candidate = defaults.clone();
+        candidate.insert(key.clone(), file_value.clone());
+
+        if candidate.try_into::<ClientConfig>().is_ok() {
+            upgraded.insert(key.clone(), file_value.clone());
+        } else {
+            changes.push(ConfigChange::Reset {
+                key: key.clone(),
+                previous: file_value.to_string(),
+                value: default_value.to_string(),
+            });
+        }
+    }
+
+    for (key, file_value) in file.iter() {
+        if !defaults.contains_key(key) {
+            changes.push(ConfigChange::Removed {
+                key: key.clone(),
+                previous: file_value.to_string(),
+            });
+        }
+    }
+
+    if changes.is_empty() && from_version == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    Ok(Some(ConfigMigration {
+        from_version,
+        changes,
+        upgraded: upgraded.try_into()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> toml::Table {
+        content.parse().unwrap()
+    }
+
+    #[test]
+    fn test_current_config_needs_no_migration() {
+        let file = toml::Table::try_from(ClientConfig::default()).unwrap();
+
+        assert_eq!(plan_migration(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_setting_is_added_and_others_are_kept() {
+        let migration = plan_migration(&parse(r#"server_addr = "example.com:8080""#))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(migration.from_version, 0);
+        assert_eq!(
+            migration.changes,
+            vec![
+                ConfigChange::Added {
+                    key: "alert".into(),
+                    value: r#""none""#.into(),
+                },
+                ConfigChange::Added {
+                    key: "colors".into(),
+                    value: "{}".into(),
+                },
+                ConfigChange::Added {
+                    key: "desktop_notifications".into(),
+                    value: "true".into(),
+                },
+                ConfigChange::Added {
+                    key: "favorite_rooms".into(),
+                    value: "[]".into(),
+                },
+                ConfigChange::Added {
+                    key: "highlight_words".into(),
+                    value: "[]".into(),
+                },
+                ConfigChange::Added {
+                    key: "ignored_users".into(),
+                    value: "[]".into(),
+                },
+                ConfigChange::Added {
+                    key: "layout".into(),
+                    value: "{ collapse_left_panel_below = 80, collapse_right_panel_below = 100, left_panel_percent = 20, right_panel_percent = 20 }".into(),
+                },
+                ConfigChange::Added {
+                    key: "preview_image_links".into(),
+                    value: "false".into(),
+                },
+                ConfigChange::Added {
+                    key: "room_sort".into(),
+                    value: r#""activity""#.into(),
+                },
+                ConfigChange::Added {
+                    key: "theme".into(),
+                    value: r#""dark""#.into(),
+                },
+                ConfigChange::Added {
+                    key: "time_format".into(),
+                    value: r#""%H:%M""#.into(),
+                },
+                ConfigChange::Added {
+                    key: "use_input_templates".into(),
+                    value: "true".into(),
+                },
+                ConfigChange::Added {
+                    key: "vim_mode".into(),
+                    value: "false".into(),
+                },
+            ]
+        );
+        assert_eq!(migration.upgraded.server_addr, "example.com:8080");
+        assert_eq!(migration.upgraded.version, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn test_invalid_setting_is_reset() {
+        let migration = plan_migration(&parse(
+            r#"
+            version = 11
+            server_addr = "localhost:8080"
+            use_input_templates = "yes"
+            highlight_words = []
+            time_format = "%H:%M"
+            theme = "dark"
+            colors = {}
+            desktop_notifications = true
+            alert = "flash"
+            layout = {}
+            vim_mode = false
+            ignored_users = []
+            favorite_rooms = []
+            room_sort = "activity"
+            preview_image_links = false
+            "#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            migration.changes,
+            vec![ConfigChange::Reset {
+                key: "use_input_templates".into(),
+                previous: r#""yes""#.into(),
+                value: "true".into(),
+            }]
+        );
+        assert!(migration.upgraded.use_input_templates);
+    }
+
+    #[test]
+    fn test_unknown_setting_is_removed() {
+        let migration = plan_migration(&parse(
+            r#"
+            version = 11
+            server_addr = "localhost:8080"
+            use_input_templates = false
+            highlight_words = []
+            time_format = "%H:%M"
+            theme = "dark"
+            colors = {}
+            desktop_notifications = true
+            alert = "bell"
+            layout = { left_panel_percent = 25 }
+            vim_mode = true
+            ignored_users = ["spammer"]
+            favorite_rooms = ["general"]
+            room_sort = "alphabetical"
+            preview_image_links = true
+            font = "monospace"
+            "#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            migration.changes,
+            vec![ConfigChange::Removed {
+                key: "font".into(),
+                previous: r#""monospace""#.into(),
+            }]
+        );
+        assert!(!migration.upgraded.use_input_templates);
+        assert_eq!(migration.upgraded.layout.left_panel_percent, 25);
+    }
+}
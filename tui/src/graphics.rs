@@ -0,0 +1,403 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Cursor, Write},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crossterm::{cursor, queue};
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, RgbaImage};
+use ratatui::layout::Rect;
+
+/// The widest and the tallest a preview gets, in cells of the terminal
+const PREVIEW_MAX_COLUMNS: u32 = 40;
+const PREVIEW_MAX_ROWS: u32 = 10;
+/// The largest images decoded for a preview, in pixels on each side
+const MAX_DECODED_SIDE: u32 = 8192;
+/// The size of a cell in pixels when the terminal does not tell it
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+/// Kitty takes the images in chunks of at most this many bytes of base64
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// The protocols the terminals draw images inline with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    /// The inline images of iTerm2, which WezTerm understands as well
+    Iterm,
+    Sixel,
+}
+
+/// Detects the graphics protocol of the terminal, None if it can not draw images
+pub fn detect() -> Option<GraphicsProtocol> {
+    detect_from(|name| std::env::var(name).ok())
+}
+
+/// Detects the graphics protocol from the environment variables the terminals set,
+/// `CHAT_TUI_GRAPHICS` overrides the detection with `kitty`, `iterm`, `sixel` or `none`
+fn detect_from(var: impl Fn(&str) -> Option<String>) -> Option<GraphicsProtocol> {
+    if let Some(protocol) = var("CHAT_TUI_GRAPHICS") {
+        return match protocol.to_lowercase().as_str() {
+            "kitty" => Some(GraphicsProtocol::Kitty),
+            "iterm" => Some(GraphicsProtocol::Iterm),
+            "sixel" => Some(GraphicsProtocol::Sixel),
+            _ => None,
+        };
+    }
+
+    // tmux swallows the graphics of the terminal it runs in
+    if var("TMUX").is_some() {
+        return None;
+    }
+
+    let term = var("TERM").unwrap_or_default();
+    let term_program = var("TERM_PROGRAM").unwrap_or_default();
+
+    if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" {
+        Some(GraphicsProtocol::Kitty)
+    } else if term_program == "iTerm.app"
+        || term_program == "WezTerm"
+        || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+    {
+        Some(GraphicsProtocol::Iterm)
+    } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+        Some(GraphicsProtocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// The size of a cell of the terminal in pixels, as told by the terminal
+pub fn cell_size() -> (u32, u32) {
+    crossterm::terminal::window_size()
+        .ok()
+        .filter(|size| size.columns > 0 && size.rows > 0 && size.width > 0 && size.height > 0)
+        .map(|size| {
+            (
+                (size.width / size.columns) as u32,
+                (size.height / size.rows) as u32,
+            )
+        })
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .unwrap_or(DEFAULT_CELL_SIZE)
+}
+
+/// An image scaled down to a preview, along with the escape sequence drawing it
+#[derive(Debug)]
+pub struct PreviewImage {
+    /// The number of cells the preview covers
+    pub columns: u16,
+    pub rows: u16,
+    pub protocol: GraphicsProtocol,
+    /// Draws the preview from the position of the cursor
+    pub sequence: String,
+}
+
+/// Decodes an image and scales it down to a preview drawn with the protocol,
+/// given the size of a cell of the terminal in pixels
+pub fn prepare_preview(
+    protocol: GraphicsProtocol,
+    content: &[u8],
+    cell_size: (u32, u32),
+) -> anyhow::Result<PreviewImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODED_SIDE);
+    limits.max_image_height = Some(MAX_DECODED_SIDE);
+
+    let mut reader = ImageReader::new(Cursor::new(content)).with_guessed_format()?;
+    reader.limits(limits);
+    let image = reader.decode().context("not a supported image")?;
+
+    let (cell_width, cell_height) = cell_size;
+    let (max_width, max_height) = (
+        PREVIEW_MAX_COLUMNS * cell_width,
+        PREVIEW_MAX_ROWS * cell_height,
+    );
+    // the small images are kept as they are rather than blown up
+    let image = if image.width() > max_width || image.height() > max_height {
+        image.thumbnail(max_width, max_height)
+    } else {
+        image
+    };
+
+    let sequence = match protocol {
+        GraphicsProtocol::Kitty => encode_kitty(&encode_png(&image)?),
+        GraphicsProtocol::Iterm => {
+            encode_iterm(&encode_png(&image)?, image.width(), image.height())
+        }
+        GraphicsProtocol::Sixel => encode_sixel(&image.to_rgba8()),
+    };
+
+    Ok(PreviewImage {
+        columns: image.width().div_ceil(cell_width).max(1) as u16,
+        rows: image.height().div_ceil(cell_height).max(1) as u16,
+        protocol,
+        sequence,
+    })
+}
+
+fn encode_png(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image
+        .write_to(Cursor::new(&mut png), ImageFormat::Png)
+        .context("could not encode the preview")?;
+
+    Ok(png)
+}
+
+/// Transmits and places the PNG at once, without moving the cursor, in chunks as kitty requires
+fn encode_kitty(png: &[u8]) -> String {
+    let payload = STANDARD.encode(png);
+    let chunks = payload
+        .as_bytes()
+        .chunks(KITTY_CHUNK_SIZE)
+        .collect::<Vec<_>>();
+    let mut sequence = String::new();
+
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let has_more = u8::from(idx + 1 < chunks.len());
+        // the chunks are sliced from base64, hence ascii
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+
+        if idx == 0 {
+            let _ = write!(
+                sequence,
+                "\x1b_Ga=T,f=100,q=2,C=1,m={has_more};{chunk}\x1b\\"
+            );
+        } else {
+            let _ = write!(sequence, "\x1b_Gm={has_more};{chunk}\x1b\\");
+        }
+    }
+
+    sequence
+}
+
+fn encode_iterm(png: &[u8], width: u32, height: u32) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={}px;height={}px;preserveAspectRatio=1:{}\x07",
+        png.len(),
+        width,
+        height,
+        STANDARD.encode(png)
+    )
+}
+
+/// Encodes the image as sixels, its colors reduced to a palette of 6 levels of red, green and blue,
+/// the transparent pixels leave the background as it is
+fn encode_sixel(image: &RgbaImage) -> String {
+    const LEVELS: u32 = 6;
+
+    let (width, height) = image.dimensions();
+    let level = |channel: u8| (channel as u32 * (LEVELS - 1) + 127) / 255;
+    let mut sequence = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+
+    for color in 0..LEVELS.pow(3) {
+        let percent = |level: u32| level * 100 / (LEVELS - 1);
+        let _ = write!(
+            sequence,
+            "#{};2;{};{};{}",
+            color,
+            percent(color / (LEVELS * LEVELS)),
+            percent(color / LEVELS % LEVELS),
+            percent(color % LEVELS)
+        );
+    }
+
+    // each band of 6 rows is drawn color by color, a sixel setting the pixels of its color in a column
+    for band in (0..height).step_by(6) {
+        let mut sixels_by_color: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                let pixel = image.get_pixel(x, y);
+                if pixel[3] < 128 {
+                    continue;
+                }
+
+                let color =
+                    level(pixel[0]) * LEVELS * LEVELS + level(pixel[1]) * LEVELS + level(pixel[2]);
+                sixels_by_color
+                    .entry(color)
+                    .or_insert_with(|| vec![0; width as usize])[x as usize] |= 1 << (y - band);
+            }
+        }
+
+        for (color, sixels) in sixels_by_color {
+            let _ = write!(sequence, "#{}", color);
+            push_sixels(&mut sequence, &sixels);
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Pushes the sixels of a color, repeating the runs of the same sixel and leaving out the empty ones at the end
+fn push_sixels(sequence: &mut String, sixels: &[u8]) {
+    let len = sixels
+        .iter()
+        .rposition(|sixel| *sixel != 0)
+        .map_or(0, |idx| idx + 1);
+    let sixels = &sixels[..len];
+    let mut idx = 0;
+
+    while idx < sixels.len() {
+        let run = sixels[idx..]
+            .iter()
+            .take_while(|sixel| **sixel == sixels[idx])
+            .count();
+        let sixel = char::from(63 + sixels[idx]);
+
+        if run > 3 {
+            let _ = write!(sequence, "!{}{}", run, sixel);
+        } else {
+            sequence.extend(std::iter::repeat_n(sixel, run));
+        }
+        idx += run;
+    }
+}
+
+/// A preview drawn over the cells left blank for it
+#[derive(Debug, Clone)]
+pub struct ImagePlacement {
+    /// The key of the source of the image
+    pub key: String,
+    pub area: Rect,
+    pub image: Arc<PreviewImage>,
+}
+
+impl PartialEq for ImagePlacement {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.area == other.area
+    }
+}
+
+/// Draws the previews at their placements, the cursor is put back where it was afterwards
+pub fn draw_images(writer: &mut impl Write, placements: &[ImagePlacement]) -> io::Result<()> {
+    if placements.is_empty() {
+        return Ok(());
+    }
+
+    queue!(writer, cursor::SavePosition)?;
+    for placement in placements {
+        queue!(writer, cursor::MoveTo(placement.area.x, placement.area.y))?;
+        writer.write_all(placement.image.sequence.as_bytes())?;
+    }
+    queue!(writer, cursor::RestorePosition)?;
+
+    writer.flush()
+}
+
+/// Deletes the previews kitty has placed, which it keeps aside from the cells they are drawn over
+pub fn delete_kitty_images(
+    writer: &mut impl Write,
+    placements: &[ImagePlacement],
+) -> io::Result<()> {
+    if placements
+        .iter()
+        .any(|placement| placement.image.protocol == GraphicsProtocol::Kitty)
+    {
+        writer.write_all(b"\x1b_Ga=d,d=A,q=2\x1b\\")?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::Rgba;
+
+    use super::*;
+
+    fn detect_with(vars: &[(&str, &str)]) -> Option<GraphicsProtocol> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        detect_from(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_protocol_is_detected_from_the_environment() {
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-kitty")]),
+            Some(GraphicsProtocol::Kitty)
+        );
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-256color"), ("TERM_PROGRAM", "WezTerm")]),
+            Some(GraphicsProtocol::Iterm)
+        );
+        assert_eq!(
+            detect_with(&[("TERM", "foot")]),
+            Some(GraphicsProtocol::Sixel)
+        );
+        assert_eq!(detect_with(&[("TERM", "xterm-256color")]), None);
+        // tmux swallows the graphics, unless told otherwise
+        assert_eq!(
+            detect_with(&[("TERM", "foot"), ("TMUX", "/tmp/tmux")]),
+            None
+        );
+        assert_eq!(
+            detect_with(&[("TMUX", "/tmp/tmux"), ("CHAT_TUI_GRAPHICS", "sixel")]),
+            Some(GraphicsProtocol::Sixel)
+        );
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-kitty"), ("CHAT_TUI_GRAPHICS", "none")]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sixels_repeat_the_runs_of_a_color() {
+        let image = RgbaImage::from_fn(8, 2, |x, _| {
+            if x < 6 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let sequence = encode_sixel(&image);
+
+        assert!(sequence.starts_with("\x1bP0;1;0q\"1;1;8;2"));
+        assert!(sequence.ends_with("\x1b\\"));
+        // the 2 rows of red are the bits 0 and 1 of the 6 first columns, the transparent ones are left out
+        assert!(sequence.contains("#180!6B$-"));
+    }
+
+    #[test]
+    fn test_previews_cover_whole_cells() {
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([0, 0, 255, 255])));
+        let preview = prepare_preview(
+            GraphicsProtocol::Sixel,
+            &encode_png(&image).unwrap(),
+            (10, 20),
+        )
+        .unwrap();
+
+        assert_eq!((preview.columns, preview.rows), (10, 3));
+        assert!(prepare_preview(GraphicsProtocol::Kitty, b"not an image", (10, 20)).is_err());
+    }
+
+    #[test]
+    fn test_kitty_images_are_sent_in_chunks() {
+        // 3 bytes are 4 characters of base64
+        let sequence = encode_kitty(&vec![0u8; KITTY_CHUNK_SIZE / 4 * 3 * 2 + 1]);
+        let chunks = sequence.split("\x1b\\").filter(|chunk| !chunk.is_empty());
+
+        assert_eq!(
+            chunks
+                .map(|chunk| chunk.split(';').next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["\x1b_Ga=T,f=100,q=2,C=1,m=1", "\x1b_Gm=1", "\x1b_Gm=0"]
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use anyhow::Context;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+/// The name of the key bindings file, kept next to the config file
+pub const KEYS_FILE_NAME: &str = "keys.toml";
+
+/// [KeyAction] is what a key press means to the app, whichever key it is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Hover the next widget of the chat page
+    FocusNext,
+    /// Hover the previous widget of the chat page
+    FocusPrevious,
+    /// Activate the hovered widget, to type in it or move through it
+    Activate,
+    /// Send the message input
+    Send,
+    Quit,
+    /// Scroll back through the messages of the active room
+    ScrollUp,
+    ScrollDown,
+    /// Return to the latest messages of the active room
+    ScrollToLatest,
+    /// Collapse or expand the panel of the rooms, on the left of the messages
+    ToggleLeftPanel,
+    /// Collapse or expand the panel of the room users, on the right of the messages
+    ToggleRightPanel,
+    NarrowLeftPanel,
+    WidenLeftPanel,
+    NarrowRightPanel,
+    WidenRightPanel,
+    /// Show the overlay listing the key bindings and the slash commands
+    ShowHelp,
+    /// Open the palette searching through the actions, such as switching rooms
+    OpenCommandPalette,
+    /// Open the popup finding a room or a conversation by its name
+    OpenRoomSwitcher,
+    /// Open the search bar, to search the messages of the active room
+    Search,
+    /// Complete the @user, #room or /command before the cursor of the message input
+    Complete,
+    /// Insert the input template of the active room, or remove it
+    ToggleInputTemplate,
+    /// Edit the last message sent to the active room, from the empty message input
+    EditLastMessage,
+    /// Recall the previous input sent to the active room, as in a shell
+    PreviousInput,
+    /// Recall the input sent after the recalled one, then what was being typed before recalling
+    NextInput,
+}
+
+impl KeyAction {
+    /// Describes the action in the usage of the widgets and the key bindings overlay
+    pub fn describe(&self) -> &'static str {
+        match self {
+            KeyAction::FocusNext => "to hover the next widget",
+            KeyAction::FocusPrevious => "to hover the previous widget",
+            KeyAction::Activate => "to activate the hovered widget",
+            KeyAction::Send => "to send your message",
+            KeyAction::Quit => "to exit",
+            KeyAction::ScrollUp => "to scroll messages back",
+            KeyAction::ScrollDown => "to scroll messages forward",
+            KeyAction::ScrollToLatest => "to return to latest",
+            KeyAction::ToggleLeftPanel => "to collapse or expand the rooms",
+            KeyAction::ToggleRightPanel => "to collapse or expand the room users",
+            KeyAction::NarrowLeftPanel => "to narrow the rooms",
+            KeyAction::WidenLeftPanel => "to widen the rooms",
+            KeyAction::NarrowRightPanel => "to narrow the room users",
+            KeyAction::WidenRightPanel => "to widen the room users",
+            KeyAction::ShowHelp => "to show the help",
+            KeyAction::OpenCommandPalette => "to open the command palette",
+            KeyAction::OpenRoomSwitcher => "to switch to another room",
+            KeyAction::Search => "to search the messages of the room",
+            KeyAction::Complete => "to complete a @user, #room or /command",
+            KeyAction::ToggleInputTemplate => "to insert or remove the input template",
+            KeyAction::EditLastMessage => "to edit your last message, when the input is empty",
+            KeyAction::PreviousInput => "to recall the previous input sent to the room",
+            KeyAction::NextInput => "to recall the next input sent to the room",
+        }
+    }
+}
+
+/// [KeyBinding] is a key along with the modifiers held while pressing it, as in `Ctrl+c`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// The shift of a character is already in the character, as `Q` rather than `Shift+q`
+    fn normalized(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+
+        KeyBinding { code, modifiers }
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        *self == KeyBinding::normalized(key.code, key.modifiers)
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 15] = [
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+];
+
+impl FromStr for KeyBinding {
+    type Err = anyhow::Error;
+
+    /// Parses a key such as `q`, `PageUp`, `Space`, `F5` or `Ctrl+Alt+x`, the names are not case sensitive
+    fn from_str(binding: &str) -> Result<Self, Self::Err> {
+        let mut parts = binding.split('+').collect::<Vec<_>>();
+        // the plus key itself is the last part, as in `Ctrl++`
+        let key = match parts.pop() {
+            Some("") if binding.ends_with('+') => {
+                parts.pop();
+                "+"
+            }
+            Some(key) => key,
+            None => "",
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => anyhow::bail!("unknown modifier '{}' in key '{}'", modifier, binding),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(char), None) => KeyCode::Char(char),
+            _ if key.eq_ignore_ascii_case("space") => KeyCode::Char(' '),
+            _ => NAMED_KEYS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, code)| *code)
+                .or_else(|| {
+                    key.strip_prefix(['F', 'f'])
+                        .and_then(|number| number.parse().ok())
+                        .filter(|number| (1..=12).contains(number))
+                        .map(KeyCode::F)
+                })
+                .with_context(|| format!("unknown key '{}'", binding))?,
+        };
+
+        Ok(KeyBinding::normalized(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(char) => write!(f, "{}", char),
+            KeyCode::F(number) => write!(f, "F{}", number),
+            code => write!(
+                f,
+                "{}",
+                NAMED_KEYS
+                    .iter()
+                    .find(|(_, named)| *named == code)
+                    .map(|(name, _)| *name)
+                    .unwrap_or("?")
+            ),
+        }
+    }
+}
+
+/// The key bindings file, each action lists the keys bound to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindingsFile {
+    pub focus_next: Vec<String>,
+    pub focus_previous: Vec<String>,
+    pub activate: Vec<String>,
+    pub send: Vec<String>,
+    pub quit: Vec<String>,
+    pub scroll_up: Vec<String>,
+    pub scroll_down: Vec<String>,
+    pub scroll_to_latest: Vec<String>,
+    pub toggle_left_panel: Vec<String>,
+    pub toggle_right_panel: Vec<String>,
+    pub narrow_left_panel: Vec<String>,
+    pub widen_left_panel: Vec<String>,
+    pub narrow_right_panel: Vec<String>,
+    pub widen_right_panel: Vec<String>,
+    pub show_help: Vec<String>,
+    pub open_command_palette: Vec<String>,
+    pub open_room_switcher: Vec<String>,
+    pub search: Vec<String>,
+    pub complete: Vec<String>,
+    pub toggle_input_template: Vec<String>,
+    pub edit_last_message: Vec<String>,
+    pub previous_input: Vec<String>,
+    pub next_input: Vec<String>,
+}
+
+impl Default for KeyBindingsFile {
+    fn default() -> Self {
+        let keys = |keys: &[&str]| keys.iter().map(|key| String::from(*key)).collect();
+
+        KeyBindingsFile {
+            focus_next: keys(&["Right"]),
+            focus_previous: keys(&["Left"]),
+            activate: keys(&["e"]),
+            send: keys(&["Enter"]),
+            quit: keys(&["q", "Ctrl+c"]),
+            scroll_up: keys(&["PageUp"]),
+            scroll_down: keys(&["PageDown"]),
+            scroll_to_latest: keys(&["End"]),
+            toggle_left_panel: keys(&["["]),
+            toggle_right_panel: keys(&["]"]),
+            // the arrows move the borders of the panels, the left one without and the right one with Shift
+            narrow_left_panel: keys(&["Ctrl+Left"]),
+            widen_left_panel: keys(&["Ctrl+Right"]),
+            narrow_right_panel: keys(&["Ctrl+Shift+Right"]),
+            widen_right_panel: keys(&["Ctrl+Shift+Left"]),
+            show_help: keys(&["?"]),
+            open_command_palette: keys(&["Ctrl+p"]),
+            open_room_switcher: keys(&["Ctrl+k"]),
+            search: keys(&["Ctrl+f"]),
+            complete: keys(&["Tab"]),
+            toggle_input_template: keys(&["Ctrl+t"]),
+            // Up edits the last message from the empty input and goes through the sent inputs otherwise,
+            // Ctrl+Up goes through them from the empty input as well
+            edit_last_message: keys(&["Up"]),
+            previous_input: keys(&["Up", "Ctrl+Up"]),
+            next_input: keys(&["Down"]),
+        }
+    }
+}
+
+/// [Keymap] translates the key presses into the [KeyAction]s they are bound to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    /// The keys of each action, in the order they are listed in the key bindings overlay
+    bindings: Vec<(KeyAction, Vec<KeyBinding>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::try_from(&KeyBindingsFile::default()).expect("the default key bindings are valid")
+    }
+}
+
+impl TryFrom<&KeyBindingsFile> for Keymap {
+    type Error = anyhow::Error;
+
+    fn try_from(file: &KeyBindingsFile) -> Result<Self, Self::Error> {
+        let bindings = [
+            (KeyAction::FocusNext, &file.focus_next),
+            (KeyAction::FocusPrevious, &file.focus_previous),
+            (KeyAction::Activate, &file.activate),
+            (KeyAction::Send, &file.send),
+            (KeyAction::Quit, &file.quit),
+            (KeyAction::ScrollUp, &file.scroll_up),
+            (KeyAction::ScrollDown, &file.scroll_down),
+            (KeyAction::ScrollToLatest, &file.scroll_to_latest),
+            (KeyAction::ToggleLeftPanel, &file.toggle_left_panel),
+            (KeyAction::ToggleRightPanel, &file.toggle_right_panel),
+            (KeyAction::NarrowLeftPanel, &file.narrow_left_panel),
+            (KeyAction::WidenLeftPanel, &file.widen_left_panel),
+            (KeyAction::NarrowRightPanel, &file.narrow_right_panel),
+            (KeyAction::WidenRightPanel, &file.widen_right_panel),
+            (KeyAction::ShowHelp, &file.show_help),
+            (KeyAction::OpenCommandPalette, &file.open_command_palette),
+            (KeyAction::OpenRoomSwitcher, &file.open_room_switcher),
+            (KeyAction::Search, &file.search),
+            (KeyAction::Complete, &file.complete),
+            (KeyAction::ToggleInputTemplate, &file.toggle_input_template),
+            (KeyAction::EditLastMessage, &file.edit_last_message),
+            (KeyAction::PreviousInput, &file.previous_input),
+            (KeyAction::NextInput, &file.next_input),
+        ]
+        .into_iter()
+        .map(|(action, keys)| {
+            let keys = keys
+                .iter()
+                .map(|key| key.parse())
+                .collect::<anyhow::Result<Vec<KeyBinding>>>()?;
+
+            Ok((action, keys))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+        Ok(Keymap { bindings })
+    }
+}
+
+impl Keymap {
+    /// Returns the action the key is bound to, the first one listed if it is bound to several
+    pub fn action(&self, key: &KeyEvent) -> Option<KeyAction> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.iter().any(|binding| binding.matches(key)))
+            .map(|(action, _)| *action)
+    }
+
+    /// Is the key bound to the action
+    pub fn is(&self, key: &KeyEvent, action: KeyAction) -> bool {
+        self.keys(action).iter().any(|binding| binding.matches(key))
+    }
+
+    pub fn keys(&self, action: KeyAction) -> &[KeyBinding] {
+        self.bindings
+            .iter()
+            .find(|(bound_action, _)| *bound_action == action)
+            .map(|(_, keys)| keys.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The names of the keys bound to the action, as shown in the usage of the widgets
+    pub fn labels(&self, action: KeyAction) -> Vec<String> {
+        self.keys(action)
+            .iter()
+            .map(KeyBinding::to_string)
+            .collect()
+    }
+
+    /// Every action with the keys bound to it
+    pub fn bindings(&self) -> impl Iterator<Item = (KeyAction, &[KeyBinding])> {
+        self.bindings
+            .iter()
+            .map(|(action, keys)| (*action, keys.as_slice()))
+    }
+}
+
+/// Loads the key bindings file, creating it with the default bindings if it does not exist
+pub fn load(path: &Path) -> anyhow::Result<Keymap> {
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("could not create the config directory")?;
+        }
+        std::fs::write(path, toml::to_string_pretty(&KeyBindingsFile::default())?)
+            .context("could not write the key bindings file")?;
+
+        return Ok(Keymap::default());
+    }
+
+    let file: KeyBindingsFile = toml::from_str(
+        &std::fs::read_to_string(path).context("could not read the key bindings file")?,
+    )
+    .context("could not parse the key bindings file")?;
+
+    Keymap::try_from(&file).context("invalid key in the key bindings file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_key_bindings_are_parsed_and_printed() {
+        for binding in [
+            "q",
+            "Q",
+            "Ctrl+c",
+            "Alt+Shift+Enter",
+            "PageUp",
+            "Space",
+            "F5",
+            "Ctrl++",
+        ] {
+            assert_eq!(binding.parse::<KeyBinding>().unwrap().to_string(), binding);
+        }
+
+        assert_eq!(
+            "ctrl+pagedown".parse::<KeyBinding>().unwrap(),
+            KeyBinding {
+                code: KeyCode::PageDown,
+                modifiers: KeyModifiers::CONTROL,
+            }
+        );
+        assert!("Hyper+q".parse::<KeyBinding>().is_err());
+        assert!("F13".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn test_rebound_keys_are_translated() {
+        let keymap = Keymap::try_from(&KeyBindingsFile {
+            send: vec!["Ctrl+s".into()],
+            quit: vec!["Q".into()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('s'), KeyModifiers::CONTROL)),
+            Some(KeyAction::Send)
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Enter, KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('Q'), KeyModifiers::SHIFT)),
+            Some(KeyAction::Quit)
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('q'), KeyModifiers::NONE)),
+            None
+        );
+        assert!(keymap.is(
+            &key(KeyCode::Right, KeyModifiers::NONE),
+            KeyAction::FocusNext
+        ));
+    }
+
+    #[test]
+    fn test_missing_actions_keep_their_default_keys() {
+        let file: KeyBindingsFile = toml::from_str(r#"quit = ["Ctrl+q"]"#).unwrap();
+        let keymap = Keymap::try_from(&file).unwrap();
+
+        assert_eq!(keymap.labels(KeyAction::Quit), vec!["Ctrl+q"]);
+        assert_eq!(keymap.labels(KeyAction::Send), vec!["Enter"]);
+    }
+}
@@ -1,9 +1,14 @@
+use config::LoadedConfig;
 use state_store::StateStore;
 use termination::create_termination;
 use ui_management::UiManager;
 
+mod config;
+mod graphics;
+mod keymap;
 mod state_store;
 mod termination;
+mod theme;
 mod ui_management;
 
 use termination::{Interrupted, Terminator};
@@ -11,12 +16,17 @@ use termination::{Interrupted, Terminator};
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let (terminator, mut interrupt_rx) = create_termination();
-    let (state_store, state_rx) = StateStore::new();
+    let config_path = config::config_path();
+    let loaded_config = match config_path.as_ref() {
+        Some(path) => config::load(path)?,
+        None => LoadedConfig::Current(Default::default()),
+    };
+    let (state_store, state_rx, terminal_rx) = StateStore::new(config_path, loaded_config);
     let (ui_manager, action_rx) = UiManager::new();
 
     tokio::try_join!(
         state_store.main_loop(terminator, action_rx, interrupt_rx.resubscribe()),
-        ui_manager.main_loop(state_rx, interrupt_rx.resubscribe()),
+        ui_manager.main_loop(state_rx, terminal_rx, interrupt_rx.resubscribe()),
     )?;
 
     if let Ok(reason) = interrupt_rx.recv().await {
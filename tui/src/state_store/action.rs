@@ -1,7 +1,256 @@
-#[derive(Debug, Clone)]
+use comms::event::{RoomRole, SpaceRole};
+
+use crate::config::LayoutConfig;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
-    ConnectToServerRequest { addr: String },
-    SendMessage { content: String },
-    SelectRoom { room: String },
+    ConnectToServerRequest {
+        addr: String,
+    },
+    /// Log in on the connected server, the username is registered on its first login
+    Login {
+        username: String,
+        password: String,
+    },
+    SendMessage {
+        content: String,
+    },
+    /// Replace the content of a message the user has sent to the active room
+    EditMessage {
+        id: u64,
+        content: String,
+    },
+    /// Reply to a message of the active room with the next message sent to it
+    ReplyToMessage {
+        id: u64,
+    },
+    CancelReply,
+    /// Delete a message the user has sent to the active room
+    DeleteMessage {
+        id: u64,
+    },
+    /// Delete the last message the user has sent to the active room
+    DeleteLastMessage,
+    /// React to a message of the active room, or take the reaction back
+    ReactToMessage {
+        id: u64,
+        emoji: String,
+    },
+    SelectRoom {
+        room: String,
+    },
+    /// Join a room by its name, including the private rooms which are not listed by the server
+    JoinRoom {
+        room: String,
+    },
+    /// Ask the user to confirm leaving the room, the active one when none is given
+    LeaveRoom {
+        room: Option<String>,
+    },
+    /// Leave the room the user was asked about
+    ConfirmLeaveRoom,
+    /// Stay in the room the user was asked about
+    CancelLeaveRoom,
+    /// Create a room, which the user is taken to once the server creates it
+    CreateRoom {
+        room: String,
+        description: String,
+    },
+    /// Delete the active room, only its creator is allowed to
+    DeleteRoom,
+    /// Change the topic of the active room, only its owner and moderators are allowed to
+    SetRoomTopic {
+        topic: String,
+    },
+    /// Change the role of the user in the active room, as its owner
+    SetRoomRole {
+        user_id: String,
+        role: RoomRole,
+    },
+    /// Kick the user out of the active room, as its owner or a moderator
+    KickUser {
+        user_id: String,
+    },
+    /// Ban the user from the active room, as its owner or a moderator
+    BanUser {
+        user_id: String,
+    },
+    /// Mute the user in the active room for the given minutes, zero unmutes the user
+    MuteUser {
+        user_id: String,
+        minutes: u64,
+    },
+    /// Invite the user to the active room
+    InviteUser {
+        user_id: String,
+    },
+    AcceptInvitation {
+        room: String,
+    },
+    DeclineInvitation {
+        room: String,
+    },
+    /// Send a direct message to the user, opening the conversation with them
+    /// Open the conversation of direct messages with the user, without sending a message yet
+    OpenDirectMessage {
+        user_id: String,
+    },
+    SendDirectMessage {
+        user_id: String,
+        content: String,
+    },
+    JumpToDate {
+        timestamp: u64,
+    },
+    ReturnToLatest,
+    /// Scroll the messages of the active room, back to the older messages when positive
+    ScrollMessages {
+        items: isize,
+    },
+    /// Search the messages of the active room, the hits are highlighted and the list is positioned at them
+    SearchMessages {
+        query: String,
+    },
+    /// Position the message list at the older hit of the search when true, the newer one otherwise
+    MoveSearch {
+        older: bool,
+    },
+    CloseSearch,
+    /// Search the stored history of the active room on the server, the matches are shown over the chat page
+    SearchHistory {
+        query: String,
+    },
+    /// Share a file with the active room, uploading it in chunks
+    SendFile {
+        path: String,
+    },
+    /// Download a file shared with a room and save it to the given path
+    SaveFile {
+        file_id: String,
+        path: String,
+    },
+    /// Request the next page of the older matches of the history search
+    LoadMoreSearchResults,
+    CloseHistorySearch,
+    /// List the public rooms of the server from the first page, whether they are joined or not
+    BrowseRooms,
+    /// Request the next page of the public rooms
+    LoadMoreRooms,
+    CopyToClipboard {
+        content: String,
+    },
+    SaveToFile {
+        content: String,
+    },
+    /// Opens the link in the browser, or copies it without one
+    OpenLink {
+        url: String,
+    },
+    ToggleInputTemplates,
+    /// Switch to the built-in theme with the given name, which is kept in the config file
+    SetTheme {
+        name: String,
+    },
+    /// Resize the side panels of the chat page, which is kept in the config file
+    SetLayout {
+        layout: LayoutConfig,
+    },
+    /// Hold the desktop notifications back, or let them through again
+    ToggleDoNotDisturb,
+    /// The terminal has gained or lost the focus
+    SetTerminalFocus {
+        is_focused: bool,
+    },
+    /// Show the overlay listing the key bindings and the slash commands
+    ShowHelp,
+    CloseHelp,
+    /// Switch to the built-in theme following the current one
+    ToggleTheme,
+    /// Drop the connection to the server and resume the session on a new one
+    Reconnect,
+    /// Hide the announcement of the server shown above the messages
+    DismissAnnouncement,
+    /// Keep the text of the message input as the draft of the room, empty content discards it
+    SaveDraft {
+        room: String,
+        content: String,
+    },
+    AddHighlightWord {
+        word: String,
+    },
+    RemoveHighlightWord {
+        word: String,
+    },
+    ListHighlightWords,
+    /// Hide the messages of the user in every room and drop their direct messages, kept in the config file
+    IgnoreUser {
+        user_id: String,
+    },
+    UnignoreUser {
+        user_id: String,
+    },
+    ListIgnoredUsers,
+    JoinSpace {
+        space: String,
+    },
+    LeaveSpace {
+        space: String,
+    },
+    SetSpaceRole {
+        space: String,
+        user_id: String,
+        role: SpaceRole,
+    },
+    RemoveSpaceMember {
+        space: String,
+        user_id: String,
+    },
+    ListSpaceMembers {
+        space: String,
+    },
+    /// Show the usage of the slash commands in the active room
+    ShowCommandHelp {
+        lines: Vec<String>,
+    },
+    ShowToast {
+        content: String,
+    },
+    ExportRoomHistory,
+    /// Mark the room, or the active room, as a favorite, or unmark it if it is one already
+    ToggleFavoriteRoom {
+        room: Option<String>,
+    },
+    /// Switch the room list between ordering by activity and by name alone
+    ToggleRoomSort,
+    /// Write the messages loaded in the room, or in the active room, to a markdown file at the path or in the current directory
+    ExportChatLog {
+        room: Option<String>,
+        path: Option<String>,
+    },
+    /// Go away with the status message shown to the other users, or come back without one
+    SetAwayMessage {
+        away_message: Option<String>,
+    },
+    /// Change the name the user is shown with, or go back to their id without one
+    ChangeNickname {
+        nickname: Option<String>,
+    },
+    /// Ask the server for the profile of the user, shown in a popup once it replies
+    GetUserProfile {
+        user_id: String,
+    },
+    /// Change the fields of the profile of the user which are given, the empty ones are cleared
+    UpdateProfile {
+        bio: Option<String>,
+        pronouns: Option<String>,
+        timezone: Option<String>,
+        color: Option<String>,
+    },
+    CloseUserProfile,
+    ExportMyData,
+    DeleteMyAccount,
+    ApplyConfigMigration,
+    /// Leave the page telling the server requires a newer client
+    DismissIncompatibleServer,
     Exit,
 }
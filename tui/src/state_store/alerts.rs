@@ -0,0 +1,67 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use crate::config::AlertPolicy;
+
+use super::scheduler::{ScheduledTask, Scheduler};
+
+/// How long the title of the terminal tells about the new activity before it is restored
+const TITLE_FLASH_DURATION: Duration = Duration::from_secs(3);
+
+/// [Alerter] tells about the new messages of the inactive rooms with the bell or the title of the terminal
+///
+/// The title is restored by the [ScheduledTask::RestoreTitle] it schedules, which outlives the connection.
+#[derive(Debug, Default)]
+pub struct Alerter {
+    /// Whether the title tells about new messages, until it is restored
+    is_title_flashed: bool,
+}
+
+impl Alerter {
+    /// Alerts about a new message in the room, following the policy
+    pub fn alert(&mut self, policy: AlertPolicy, room: &str, scheduler: &mut Scheduler) {
+        // the alert is only a courtesy, failing to write it to the terminal is ignored
+        let _ = match policy {
+            AlertPolicy::None => Ok(()),
+            AlertPolicy::Bell => write_to_terminal("\x07"),
+            AlertPolicy::Flash => self.flash_title(room, scheduler),
+        };
+    }
+
+    fn flash_title(&mut self, room: &str, scheduler: &mut Scheduler) -> io::Result<()> {
+        // the title of the user is pushed on the title stack of the terminal, to be popped back later
+        if !self.is_title_flashed {
+            write_to_terminal("\x1b[22;0t")?;
+            self.is_title_flashed = true;
+        }
+        scheduler.schedule_once(
+            ScheduledTask::RestoreTitle,
+            TITLE_FLASH_DURATION,
+            Instant::now(),
+        );
+
+        write_to_terminal(&format!("\x1b]0;● new messages in {}\x07", room))
+    }
+
+    /// Restores the title of the user, once the flash is over
+    pub fn restore_title(&mut self) {
+        if std::mem::take(&mut self.is_title_flashed) {
+            let _ = write_to_terminal("\x1b[23;0t");
+        }
+    }
+}
+
+impl Drop for Alerter {
+    fn drop(&mut self) {
+        self.restore_title();
+    }
+}
+
+fn write_to_terminal(sequence: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
@@ -0,0 +1,29 @@
+use std::process::{Command, Stdio};
+
+/// Opens the link in the default browser, returns false if there is no graphical session to open it in
+pub fn open_in_browser(url: &str) -> anyhow::Result<bool> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // unlike `start`, the link is not interpreted by a shell
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    {
+        Command::new("xdg-open")
+    } else {
+        return Ok(false);
+    };
+
+    let mut child = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // the opener is waited for aside, the browser may take a while to start
+    std::thread::spawn(move || child.wait());
+
+    Ok(true)
+}
@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, TimeZone};
+
+use super::state::{user_label, MessageBoxItem};
+
+/// Renders the items of the room as a markdown chat log, one paragraph per message or notification
+///
+/// The errors of the user's own commands are left out, they are not part of the conversation.
+pub fn render<'a>(
+    room: &str,
+    items: impl Iterator<Item = &'a MessageBoxItem>,
+    display_names: &HashMap<String, String>,
+) -> String {
+    let title = match room.strip_prefix('@') {
+        Some(user_id) => format!("Direct messages with @{}", user_id),
+        None => format!("#{}", room),
+    };
+    let mut log = format!("# {}\n", title);
+
+    for item in items {
+        let paragraph = match item {
+            MessageBoxItem::Message {
+                user_id,
+                content,
+                timestamp,
+                is_edited,
+                ..
+            } => format!(
+                "{}**{}**: {}{}",
+                time_prefix(*timestamp),
+                user_label(display_names, user_id),
+                // a single line break is lost in markdown, the lines of the message are kept apart
+                content.lines().collect::<Vec<_>>().join("  \n"),
+                if *is_edited { " _(edited)_" } else { "" },
+            ),
+            MessageBoxItem::Notification(notification) => format!("_{}_", notification),
+            MessageBoxItem::File {
+                id,
+                user_id,
+                name,
+                timestamp,
+                ..
+            } => format!(
+                "{}**{}** shared the file `{}` ({})",
+                time_prefix(Some(*timestamp)),
+                user_label(display_names, user_id),
+                name,
+                id,
+            ),
+            MessageBoxItem::Error(_) => continue,
+        };
+
+        log.push('\n');
+        log.push_str(&paragraph);
+        log.push('\n');
+    }
+
+    log
+}
+
+/// Writes the chat log of the room to the given path, or to a new file in the current directory,
+/// returning the path of the file
+///
+/// A file named after the room is created inside of the given path if it is a directory.
+pub fn save(room: &str, path: Option<&str>, log: &str) -> anyhow::Result<PathBuf> {
+    let default_name = || -> anyhow::Result<String> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        Ok(format!("chat-log-{}-{}.md", room, millis))
+    };
+
+    let path = match path.map(Path::new) {
+        Some(path) if path.is_dir() => path.join(default_name()?),
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(default_name()?),
+    };
+
+    std::fs::write(&path, log)?;
+
+    Ok(path)
+}
+
+/// Formats the time of the item as a prefix of its line, items without a time have none
+fn time_prefix(timestamp: Option<u64>) -> String {
+    timestamp
+        .and_then(|timestamp| Local.timestamp_millis_opt(timestamp as i64).single())
+        .map(|date_time| format!("`[{}]` ", date_time.format("%Y-%m-%d %H:%M:%S")))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(user_id: &str, content: &str, is_edited: bool) -> MessageBoxItem {
+        MessageBoxItem::Message {
+            id: 0,
+            user_id: String::from(user_id),
+            content: String::from(content),
+            timestamp: None,
+            reply_to: None,
+            is_edited,
+            reactions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_log_holds_the_messages_and_notifications_but_not_the_errors() {
+        let items = [
+            MessageBoxItem::Notification(String::from("@bob joined the room")),
+            message("alice", "hello\nworld", false),
+            MessageBoxItem::Error(String::from("You are muted")),
+            message("bob", "hi", true),
+        ];
+        let display_names = HashMap::from([(String::from("alice"), String::from("Alice"))]);
+
+        assert_eq!(
+            render("general", items.iter(), &display_names),
+            "# #general\n\
+             \n_@bob joined the room_\n\
+             \n**Alice (@alice)**: hello  \nworld\n\
+             \n**@bob**: hi _(edited)_\n"
+        );
+    }
+
+    #[test]
+    fn test_direct_messages_are_titled_after_the_user() {
+        assert_eq!(
+            render("@alice", std::iter::empty(), &HashMap::new()),
+            "# Direct messages with @alice\n"
+        );
+    }
+}
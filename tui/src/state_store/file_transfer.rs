@@ -0,0 +1,398 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use comms::{
+    command,
+    event::{FileChunkReplyEvent, UploadProgressReplyEvent},
+    file_transfer::{self, MAX_CHUNK_SIZE, MAX_FILE_SIZE},
+};
+
+use super::TransferProgress;
+
+#[derive(Debug)]
+struct Upload {
+    name: String,
+    content: Vec<u8>,
+    /// The number of bytes the server has received so far
+    received: u64,
+}
+
+#[derive(Debug)]
+struct Download {
+    /// Where the file is written to once all of its content is received,
+    /// None for the previews of the images which are kept in memory
+    path: Option<PathBuf>,
+    content: Vec<u8>,
+    /// The size of the file, known once the first chunk is received
+    size: u64,
+}
+
+/// What to do after a chunk of a download is received
+#[derive(Debug, PartialEq)]
+pub enum DownloadStep {
+    /// Ask the server for the next chunk
+    Next(command::DownloadFileCommand),
+    /// The whole file is received and written to the given path
+    Saved(PathBuf),
+    /// The whole content of an image to preview is received
+    Received(Vec<u8>),
+}
+
+/// [FileTransfers] keeps the content of the files being uploaded and downloaded,
+/// sending the next chunk of an upload or asking for the next chunk of a download as the server answers
+#[derive(Debug, Default)]
+pub struct FileTransfers {
+    /// The id of the next upload, unique for the connection
+    next_upload_id: u64,
+    /// The uploads, by the id they were started with
+    uploads: HashMap<String, Upload>,
+    /// The downloads, by the id of the shared file
+    downloads: HashMap<String, Download>,
+}
+
+impl FileTransfers {
+    /// Reads the file and returns the command starting its upload to the room
+    pub fn start_upload(
+        &mut self,
+        room: &str,
+        path: &Path,
+    ) -> anyhow::Result<command::StartUploadCommand> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+        if std::fs::metadata(path)?.len() > MAX_FILE_SIZE {
+            return Err(anyhow::anyhow!(
+                "files are at most {} MiB large",
+                MAX_FILE_SIZE / 1024 / 1024
+            ));
+        }
+
+        let content = std::fs::read(path)?;
+        if content.is_empty() {
+            return Err(anyhow::anyhow!("{} is empty", path.display()));
+        }
+
+        let upload_id = self.next_upload_id.to_string();
+        self.next_upload_id += 1;
+
+        let command = command::StartUploadCommand {
+            upload_id: upload_id.clone(),
+            room: String::from(room),
+            name: String::from(name),
+            size: content.len() as u64,
+            checksum: file_transfer::checksum(&content),
+        };
+        self.uploads.insert(
+            upload_id,
+            Upload {
+                name: String::from(name),
+                content,
+                received: 0,
+            },
+        );
+
+        Ok(command)
+    }
+
+    /// Returns the command sending the chunk the server asks for,
+    /// None once the whole file is received or if the upload is unknown
+    pub fn next_upload_chunk(
+        &mut self,
+        event: &UploadProgressReplyEvent,
+    ) -> Option<command::UploadChunkCommand> {
+        let upload = self.uploads.get_mut(&event.upload_id)?;
+        let offset = (event.received as usize).min(upload.content.len());
+
+        if offset == upload.content.len() {
+            self.uploads.remove(&event.upload_id);
+            return None;
+        }
+
+        upload.received = offset as u64;
+        let end = (offset + MAX_CHUNK_SIZE).min(upload.content.len());
+
+        Some(command::UploadChunkCommand {
+            upload_id: event.upload_id.clone(),
+            offset: offset as u64,
+            data: file_transfer::encode_chunk(&upload.content[offset..end]),
+        })
+    }
+
+    /// Returns the command asking for the first chunk of a shared file, which is saved to the given path
+    pub fn start_download(
+        &mut self,
+        file_id: &str,
+        path: PathBuf,
+    ) -> anyhow::Result<command::DownloadFileCommand> {
+        self.start(file_id, Some(path))
+    }
+
+    /// Returns the command asking for the first chunk of a shared image, which is kept in memory to preview it
+    pub fn start_preview(&mut self, file_id: &str) -> anyhow::Result<command::DownloadFileCommand> {
+        self.start(file_id, None)
+    }
+
+    fn start(
+        &mut self,
+        file_id: &str,
+        path: Option<PathBuf>,
+    ) -> anyhow::Result<command::DownloadFileCommand> {
+        if self.downloads.contains_key(file_id) {
+            return Err(anyhow::anyhow!("file {} is already being saved", file_id));
+        }
+
+        self.downloads.insert(
+            String::from(file_id),
+            Download {
+                path,
+                content: vec![],
+                size: 0,
+            },
+        );
+
+        Ok(command::DownloadFileCommand {
+            file_id: String::from(file_id),
+            offset: 0,
+        })
+    }
+
+    /// Appends a received chunk to its download, and writes the file once all of its content is received
+    ///
+    /// A download which fails is dropped. None if the download is unknown.
+    pub fn receive_chunk(
+        &mut self,
+        event: &FileChunkReplyEvent,
+    ) -> Option<anyhow::Result<DownloadStep>> {
+        let download = self.downloads.get_mut(&event.file_id)?;
+        let step = Self::append_chunk(download, event);
+
+        if !matches!(step, Ok(DownloadStep::Next(_))) {
+            self.downloads.remove(&event.file_id);
+        }
+
+        Some(step)
+    }
+
+    fn append_chunk(
+        download: &mut Download,
+        event: &FileChunkReplyEvent,
+    ) -> anyhow::Result<DownloadStep> {
+        if event.offset != download.content.len() as u64 {
+            return Err(anyhow::anyhow!("received the chunks out of order"));
+        }
+
+        let chunk = file_transfer::decode_chunk(&event.data)?;
+        let received = download.content.len() as u64 + chunk.len() as u64;
+        if received > event.size || (chunk.is_empty() && received < event.size) {
+            return Err(anyhow::anyhow!("received more or less than the file"));
+        }
+
+        download.size = event.size;
+        download.content.extend_from_slice(&chunk);
+        if received < event.size {
+            return Ok(DownloadStep::Next(command::DownloadFileCommand {
+                file_id: event.file_id.clone(),
+                offset: received,
+            }));
+        }
+
+        if file_transfer::checksum(&download.content) != event.checksum {
+            return Err(anyhow::anyhow!(
+                "the content of the file does not match its checksum"
+            ));
+        }
+
+        match download.path.as_ref() {
+            Some(path) => {
+                std::fs::write(path, &download.content)?;
+
+                Ok(DownloadStep::Saved(path.clone()))
+            }
+            None => Ok(DownloadStep::Received(std::mem::take(
+                &mut download.content,
+            ))),
+        }
+    }
+
+    /// Is the file being downloaded to preview it rather than to save it
+    pub fn is_preview(&self, file_id: &str) -> bool {
+        self.downloads
+            .get(file_id)
+            .is_some_and(|download| download.path.is_none())
+    }
+
+    /// Drops an upload or a download the server has stopped, returns the name of its file
+    pub fn cancel(&mut self, transfer_id: &str) -> Option<String> {
+        self.uploads
+            .remove(transfer_id)
+            .map(|upload| upload.name)
+            .or_else(|| {
+                self.downloads.remove(transfer_id).map(|download| {
+                    download
+                        .path
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|| String::from(transfer_id))
+                })
+            })
+    }
+
+    /// Drops every transfer, the server forgets about them along with the connection
+    pub fn clear(&mut self) {
+        self.uploads.clear();
+        self.downloads.clear();
+    }
+
+    /// The progress of the transfers, to show it to the user
+    pub fn progress(&self) -> Vec<TransferProgress> {
+        let uploads = self.uploads.values().map(|upload| TransferProgress {
+            name: upload.name.clone(),
+            is_upload: true,
+            transferred: upload.received,
+            size: upload.content.len() as u64,
+        });
+        // the previews are shown in place of the images instead
+        let downloads = self.downloads.values().filter_map(|download| {
+            Some(TransferProgress {
+                name: download
+                    .path
+                    .as_ref()?
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                is_upload: false,
+                transferred: download.content.len() as u64,
+                size: download.size,
+            })
+        });
+
+        let mut progress = uploads.chain(downloads).collect::<Vec<_>>();
+        progress.sort_by(|a, b| a.name.cmp(&b.name));
+
+        progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "chat-file-transfer-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn test_upload_is_sent_in_chunks() {
+        let path = temp_path("upload.bin");
+        let content = vec![7u8; MAX_CHUNK_SIZE + 10];
+        std::fs::write(&path, &content).unwrap();
+
+        let mut transfers = FileTransfers::default();
+        let start = transfers.start_upload("general", &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(start.size, content.len() as u64);
+        assert_eq!(start.checksum, file_transfer::checksum(&content));
+
+        let progress = |received| UploadProgressReplyEvent {
+            upload_id: start.upload_id.clone(),
+            received,
+        };
+        let first = transfers.next_upload_chunk(&progress(0)).unwrap();
+        assert_eq!(first.offset, 0);
+        assert_eq!(
+            file_transfer::decode_chunk(&first.data).unwrap().len(),
+            MAX_CHUNK_SIZE
+        );
+
+        let second = transfers.next_upload_chunk(&progress(MAX_CHUNK_SIZE as u64));
+        assert_eq!(
+            second.map(|chunk| chunk.offset),
+            Some(MAX_CHUNK_SIZE as u64)
+        );
+        assert_eq!(transfers.progress()[0].transferred, MAX_CHUNK_SIZE as u64);
+
+        assert_eq!(
+            transfers.next_upload_chunk(&progress(content.len() as u64)),
+            None
+        );
+        assert!(transfers.progress().is_empty());
+    }
+
+    #[test]
+    fn test_download_is_saved_once_its_checksum_matches() {
+        let path = temp_path("download.txt");
+        let mut transfers = FileTransfers::default();
+        transfers.start_download("f1", path.clone()).unwrap();
+        assert!(transfers.start_download("f1", path.clone()).is_err());
+
+        let chunk = |offset, data: &[u8], checksum: &str| FileChunkReplyEvent {
+            file_id: String::from("f1"),
+            offset,
+            data: file_transfer::encode_chunk(data),
+            size: 6,
+            checksum: String::from(checksum),
+        };
+        let checksum = file_transfer::checksum(b"abcdef");
+
+        assert_eq!(
+            transfers
+                .receive_chunk(&chunk(0, b"abc", &checksum))
+                .unwrap()
+                .unwrap(),
+            DownloadStep::Next(command::DownloadFileCommand {
+                file_id: String::from("f1"),
+                offset: 3,
+            })
+        );
+        assert_eq!(
+            transfers
+                .receive_chunk(&chunk(3, b"def", &checksum))
+                .unwrap()
+                .unwrap(),
+            DownloadStep::Saved(path.clone())
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"abcdef");
+        std::fs::remove_file(&path).unwrap();
+
+        // a corrupted download is dropped without writing the file
+        transfers.start_download("f1", path.clone()).unwrap();
+        assert!(transfers
+            .receive_chunk(&chunk(0, b"abcdeg", &checksum))
+            .unwrap()
+            .is_err());
+        assert!(transfers
+            .receive_chunk(&chunk(0, b"abcdef", &checksum))
+            .is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_preview_is_kept_in_memory() {
+        let mut transfers = FileTransfers::default();
+        transfers.start_preview("f2").unwrap();
+
+        assert!(transfers.is_preview("f2"));
+        assert!(transfers.progress().is_empty());
+
+        let step = transfers.receive_chunk(&FileChunkReplyEvent {
+            file_id: String::from("f2"),
+            offset: 0,
+            data: file_transfer::encode_chunk(b"png"),
+            size: 3,
+            checksum: file_transfer::checksum(b"png"),
+        });
+
+        assert_eq!(
+            step.unwrap().unwrap(),
+            DownloadStep::Received(b"png".to_vec())
+        );
+        assert!(!transfers.is_preview("f2"));
+    }
+}
@@ -0,0 +1,102 @@
+use comms::event;
+
+use super::{LoginStatus, MessageBoxItem, RoomData, ServerConnectionStatus, SpaceData, State};
+
+const TEST_SERVER_ADDR: &str = "localhost:8080";
+const TEST_USER_ID: &str = "tester";
+
+/// Builder-style fixture helpers for assembling a [State] in tests
+impl State {
+    /// Creates a state which is connected and logged in to a server and knows about the given rooms,
+    /// given as `(name, description)` pairs. None of the rooms are joined.
+    pub fn test_with_rooms(rooms: &[(&str, &str)]) -> Self {
+        State {
+            server_connection_status: ServerConnectionStatus::Connected {
+                addr: String::from(TEST_SERVER_ADDR),
+            },
+            login_status: LoginStatus::LoggedIn,
+            user_id: String::from(TEST_USER_ID),
+            room_data_map: rooms
+                .iter()
+                .map(|(name, description)| {
+                    (
+                        String::from(*name),
+                        RoomData::new(
+                            String::from(*name),
+                            String::from(*description),
+                            event::HistoryVisibility::default(),
+                        ),
+                    )
+                })
+                .collect(),
+            ..State::default()
+        }
+    }
+
+    /// Sets the id of the logged in user
+    pub fn with_user_id(mut self, user_id: &str) -> Self {
+        self.user_id = String::from(user_id);
+        self
+    }
+
+    /// Marks the room as joined with the given users, including the logged in user
+    ///
+    /// Panics if the room does not exist
+    pub fn with_joined_room(mut self, room: &str, users: &[&str]) -> Self {
+        let user_id = self.user_id.clone();
+        let room_data = self.test_room_data_mut(room);
+
+        room_data.has_joined = true;
+        room_data.users = users.iter().map(|user| String::from(*user)).collect();
+        room_data.users.insert(user_id);
+
+        self
+    }
+
+    /// Adds a space grouping the given rooms, which the logged in user is the admin of
+    pub fn with_joined_space(mut self, space: &str, rooms: &[&str]) -> Self {
+        self.space_data_map.insert(
+            String::from(space),
+            SpaceData {
+                rooms: rooms.iter().map(|room| String::from(*room)).collect(),
+                members: [(self.user_id.clone(), event::SpaceRole::Admin)].into(),
+                has_joined: true,
+            },
+        );
+        self
+    }
+
+    /// Sets the given room as the active room
+    ///
+    /// Panics if the room does not exist
+    pub fn with_active_room(mut self, room: &str) -> Self {
+        self.try_set_active_room(room)
+            .unwrap_or_else(|| panic!("room '{}' does not exist in the fixture", room));
+        self
+    }
+
+    /// Appends a message to the given room, with the next id of the room
+    ///
+    /// Panics if the room does not exist
+    pub fn with_message(mut self, room: &str, user_id: &str, content: &str) -> Self {
+        let room_data = self.test_room_data_mut(room);
+        let id = room_data.messages.len() as u64;
+
+        room_data.messages.push(MessageBoxItem::Message {
+            id,
+            user_id: String::from(user_id),
+            content: String::from(content),
+            timestamp: None,
+            reply_to: None,
+            is_edited: false,
+            reactions: vec![],
+        });
+        self
+    }
+
+    fn test_room_data_mut(&mut self, room: &str) -> &mut RoomData {
+        self.room_data_map
+            .get_mut(room)
+            .unwrap_or_else(|| panic!("room '{}' does not exist in the fixture", room))
+    }
+}
@@ -0,0 +1,45 @@
+/// Returns true if the content mentions the user with `@user_id`, or contains one of their highlight words
+///
+/// Matching is case insensitive, and only whole words match, so `rust` does not match `trusty`.
+pub fn is_highlighted(content: &str, user_id: &str, highlight_words: &[String]) -> bool {
+    let content = content.to_lowercase();
+    let mention = format!("@{}", user_id.to_lowercase());
+
+    (!user_id.is_empty() && contains_word(&content, &mention))
+        || highlight_words
+            .iter()
+            .filter(|word| !word.is_empty())
+            .any(|word| contains_word(&content, &word.to_lowercase()))
+}
+
+fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+fn contains_word(content: &str, word: &str) -> bool {
+    content.match_indices(word).any(|(idx, _)| {
+        let before = content[..idx].chars().next_back();
+        let after = content[idx + word.len()..].chars().next();
+
+        !before.map(is_word_char).unwrap_or(false) && !after.map(is_word_char).unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mention_is_highlighted() {
+        assert!(is_highlighted("hey @Alice, look", "alice", &[]));
+        assert!(!is_highlighted("hey @alicia", "alice", &[]));
+    }
+
+    #[test]
+    fn test_highlight_word_matches_whole_words() {
+        let words = vec![String::from("Rust")];
+
+        assert!(is_highlighted("I love rust!", "alice", &words));
+        assert!(!is_highlighted("a trusty tool", "alice", &words));
+    }
+}
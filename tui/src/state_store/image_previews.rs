@@ -0,0 +1,344 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::graphics::{self, GraphicsProtocol};
+
+use super::{
+    links,
+    scheduler::{ScheduledTask, Scheduler},
+    ImagePreview, MessageBoxItem, State,
+};
+
+/// The extensions of the images which are previewed
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "gif"];
+/// The largest images fetched to preview them
+const MAX_PREVIEW_BYTES: u64 = 2 * 1024 * 1024;
+/// How long fetching an image may take before its preview fails
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the previews the active room does not show are dropped
+const FLUSH_PERIOD: Duration = Duration::from_secs(60);
+
+/// Where the image referenced by a message comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageSource {
+    /// An image linked to by a message
+    Url(String),
+    /// An image file shared with the room
+    File { id: String, name: String },
+}
+
+/// The key of the preview of a file shared with the room
+pub fn file_preview_key(file_id: &str) -> String {
+    format!("file:{}", file_id)
+}
+
+impl ImageSource {
+    /// The key the preview of the image is kept by
+    pub fn key(&self) -> String {
+        match self {
+            ImageSource::Url(url) => url.clone(),
+            ImageSource::File { id, .. } => file_preview_key(id),
+        }
+    }
+
+    /// The name of the image, shown in place of its preview
+    pub fn label(&self) -> &str {
+        match self {
+            ImageSource::Url(url) => url
+                .rsplit('/')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or(url),
+            ImageSource::File { name, .. } => name,
+        }
+    }
+}
+
+fn has_image_extension(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, extension)| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// The image a message references, the first link to an image or the image file shared with the room
+pub fn image_source_of(mbi: &MessageBoxItem) -> Option<ImageSource> {
+    match mbi {
+        MessageBoxItem::Message { content, .. } => links::find_links(content)
+            .into_iter()
+            .map(|range| &content[range])
+            .find(|url| {
+                // the query and the fragment are not part of the path of the image
+                let path = url.split(['?', '#']).next().unwrap_or_default();
+
+                has_image_extension(path)
+            })
+            .map(|url| ImageSource::Url(String::from(url))),
+        MessageBoxItem::File { id, name, size, .. }
+            if has_image_extension(name) && *size <= MAX_PREVIEW_BYTES =>
+        {
+            Some(ImageSource::File {
+                id: id.clone(),
+                name: name.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// [ImagePreviews] fetches and decodes the images referenced by the messages of the active room in the background,
+/// handing the previews over to the state once they are ready
+///
+/// The images shared with the room are downloaded through the file transfers, only their decoding happens here.
+pub struct ImagePreviews {
+    /// The protocol the terminal draws the images with, no image is fetched without one
+    protocol: Option<GraphicsProtocol>,
+    client: reqwest::Client,
+    preview_tx: UnboundedSender<(String, ImagePreview)>,
+    preview_rx: UnboundedReceiver<(String, ImagePreview)>,
+}
+
+impl ImagePreviews {
+    pub fn new(protocol: Option<GraphicsProtocol>) -> Self {
+        let (preview_tx, preview_rx) = mpsc::unbounded_channel();
+
+        ImagePreviews {
+            protocol,
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            preview_tx,
+            preview_rx,
+        }
+    }
+
+    /// Schedules the previews the active room does not show to be dropped every once in a while,
+    /// so the previews of the rooms visited earlier do not pile up
+    pub fn schedule_flush(&self, scheduler: &mut Scheduler) {
+        scheduler.schedule_periodic(
+            ScheduledTask::FlushImagePreviews,
+            FLUSH_PERIOD,
+            Instant::now(),
+        );
+    }
+
+    /// Starts fetching the images of the active room which have no preview yet, marking them as loading,
+    /// returns the ids of the files shared with the room to download
+    ///
+    /// The linked images are only fetched once the user opts in, as fetching them tells their hosts the address
+    /// of the viewer. The files shared with the room come from the chat server.
+    pub fn request_missing(&mut self, state: &mut State) -> Vec<String> {
+        if self.protocol.is_none() {
+            return vec![];
+        }
+
+        let sources = state
+            .active_room
+            .as_ref()
+            .and_then(|active_room| state.room_data_map.get(active_room))
+            .map(|room_data| {
+                room_data
+                    .messages
+                    .asc_iter()
+                    .filter_map(image_source_of)
+                    .filter(|source| !state.image_previews.contains_key(&source.key()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut file_ids = vec![];
+        for source in sources {
+            match source {
+                ImageSource::Url(url) if state.preview_image_links => {
+                    state
+                        .image_previews
+                        .insert(url.clone(), ImagePreview::Loading);
+                    self.fetch(url);
+                }
+                ImageSource::File { id, .. } if state.can_transfer_files => {
+                    state
+                        .image_previews
+                        .insert(file_preview_key(&id), ImagePreview::Loading);
+                    file_ids.push(id);
+                }
+                ImageSource::Url(_) | ImageSource::File { .. } => (),
+            }
+        }
+
+        file_ids
+    }
+
+    fn fetch(&self, url: String) {
+        let client = self.client.clone();
+        let previews = self.clone_handle();
+
+        tokio::spawn(async move {
+            match download(&client, &url).await {
+                Ok(content) => previews.decode(url, content),
+                Err(err) => previews.send(url, ImagePreview::Failed(err.to_string())),
+            }
+        });
+    }
+
+    /// Decodes the content of an image into its preview in the background
+    pub fn decode(&self, key: String, content: Vec<u8>) {
+        self.clone_handle().decode(key, content);
+    }
+
+    fn clone_handle(&self) -> PreviewHandle {
+        PreviewHandle {
+            protocol: self.protocol,
+            preview_tx: self.preview_tx.clone(),
+        }
+    }
+
+    /// The next preview which is ready or has failed, along with its key
+    pub async fn next_preview(&mut self) -> Option<(String, ImagePreview)> {
+        self.preview_rx.recv().await
+    }
+}
+
+/// Hands the previews decoded in the background over to the [ImagePreviews]
+struct PreviewHandle {
+    protocol: Option<GraphicsProtocol>,
+    preview_tx: UnboundedSender<(String, ImagePreview)>,
+}
+
+impl PreviewHandle {
+    fn decode(self, key: String, content: Vec<u8>) {
+        let Some(protocol) = self.protocol else {
+            return;
+        };
+        // the size of the cells is taken now, the previews are not scaled again once the terminal changes
+        let cell_size = graphics::cell_size();
+
+        tokio::task::spawn_blocking(move || {
+            let preview = match graphics::prepare_preview(protocol, &content, cell_size) {
+                Ok(image) => ImagePreview::Ready(Arc::new(image)),
+                Err(err) => ImagePreview::Failed(err.to_string()),
+            };
+
+            self.send(key, preview);
+        });
+    }
+
+    fn send(&self, key: String, preview: ImagePreview) {
+        let _ = self.preview_tx.send((key, preview));
+    }
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> anyhow::Result<Vec<u8>> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let too_large = || {
+        anyhow::anyhow!(
+            "the image is larger than {} MiB",
+            MAX_PREVIEW_BYTES / 1024 / 1024
+        )
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_PREVIEW_BYTES)
+    {
+        return Err(too_large());
+    }
+
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        content.extend_from_slice(&chunk);
+
+        if content.len() as u64 > MAX_PREVIEW_BYTES {
+            return Err(too_large());
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> MessageBoxItem {
+        MessageBoxItem::Message {
+            id: 1,
+            user_id: String::from("alice"),
+            content: String::from(content),
+            timestamp: None,
+            reply_to: None,
+            is_edited: false,
+            reactions: vec![],
+        }
+    }
+
+    fn file(name: &str, size: u64) -> MessageBoxItem {
+        MessageBoxItem::File {
+            id: String::from("f1"),
+            user_id: String::from("alice"),
+            name: String::from(name),
+            size,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_images_are_found_in_the_messages() {
+        assert_eq!(
+            image_source_of(&message("look (https://example.com/cat.PNG?size=large)")),
+            Some(ImageSource::Url(String::from(
+                "https://example.com/cat.PNG?size=large"
+            )))
+        );
+        assert_eq!(
+            image_source_of(&message("see https://example.com/notes.txt")),
+            None
+        );
+        assert_eq!(image_source_of(&message("ftp://example.com/cat.png")), None);
+
+        let source = image_source_of(&file("cat.jpg", 1024)).unwrap();
+        assert_eq!(source.key(), "file:f1");
+        assert_eq!(source.label(), "cat.jpg");
+        assert_eq!(
+            image_source_of(&file("cat.jpg", MAX_PREVIEW_BYTES + 1)),
+            None
+        );
+        assert_eq!(image_source_of(&file("cat.zip", 1024)), None);
+
+        assert_eq!(
+            ImageSource::Url(String::from("https://example.com/a/cat.gif")).label(),
+            "cat.gif"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_the_linked_images_are_only_fetched_once_opted_in() {
+        let mut image_previews = ImagePreviews::new(Some(GraphicsProtocol::Kitty));
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general")
+            .with_message("general", "alice", "look https://example.com/cat.png");
+        state.can_transfer_files = true;
+        state
+            .room_data_map
+            .get_mut("general")
+            .unwrap()
+            .messages
+            .push(file("cat.jpg", 1024));
+
+        // the files shared with the room come from the chat server, they are previewed either way
+        assert_eq!(image_previews.request_missing(&mut state), vec!["f1"]);
+        assert!(!state
+            .image_previews
+            .contains_key("https://example.com/cat.png"));
+
+        state.preview_image_links = true;
+
+        assert!(image_previews.request_missing(&mut state).is_empty());
+        assert!(matches!(
+            state.image_previews.get("https://example.com/cat.png"),
+            Some(ImagePreview::Loading)
+        ));
+    }
+}
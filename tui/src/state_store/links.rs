@@ -0,0 +1,76 @@
+use std::ops::Range;
+
+/// The schemes of the links found in the messages
+const LINK_SCHEMES: [&str; 2] = ["https://", "http://"];
+/// The punctuation ending a sentence, which is not part of a link it follows
+const TRAILING_PUNCTUATION: [char; 9] = ['.', ',', ';', ':', '!', '?', '\'', '"', '>'];
+
+/// Finds the web links in the text, as the ranges of their bytes
+///
+/// A link runs up to the next whitespace, the punctuation ending a sentence and a closing parenthesis
+/// which does not close one of the link are left out of it.
+pub fn find_links(text: &str) -> Vec<Range<usize>> {
+    let mut links = vec![];
+    let mut search_from = 0;
+
+    while let Some(start) = LINK_SCHEMES
+        .iter()
+        .filter_map(|scheme| text[search_from..].find(scheme))
+        .min()
+        .map(|idx| search_from + idx)
+    {
+        let end = text[start..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |len| start + len);
+        // a scheme within a word, as in `xhttp://`, does not start a link
+        let is_word_start = !text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric);
+        let link = trim_link_end(&text[start..end]);
+
+        if is_word_start && !link.ends_with("//") {
+            links.push(start..start + link.len());
+        }
+        search_from = end;
+    }
+
+    links
+}
+
+fn trim_link_end(link: &str) -> &str {
+    let mut link = link.trim_end_matches(TRAILING_PUNCTUATION);
+
+    // as in `(see https://en.wikipedia.org/wiki/Rust_(programming_language))`
+    while link.ends_with(')') && link.matches('(').count() < link.matches(')').count() {
+        link = link[..link.len() - 1].trim_end_matches(TRAILING_PUNCTUATION);
+    }
+
+    link
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(text: &str) -> Vec<&str> {
+        find_links(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_links_are_found_in_text() {
+        assert_eq!(
+            links("see https://example.com/a?b=c, or http://example.org."),
+            vec!["https://example.com/a?b=c", "http://example.org"]
+        );
+        assert_eq!(
+            links("(https://en.wikipedia.org/wiki/Rust_(programming_language))"),
+            vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+        );
+        assert_eq!(links("<https://example.com>"), vec!["https://example.com"]);
+        assert!(links("xhttps://example.com and https:// alone").is_empty());
+    }
+}
@@ -1,7 +1,25 @@
+pub use self::search::MessageSearch;
 pub use self::state::*;
 pub use self::state_store::StateStore;
+pub use self::terminal::TerminalRequest;
 
 pub mod action;
+mod alerts;
+mod browser;
+mod chat_log;
+mod file_transfer;
+#[cfg(test)]
+mod fixtures;
+pub mod highlights;
+pub mod image_previews;
+pub mod links;
+mod notifier;
+mod room_export;
+pub mod scheduler;
+mod search;
+mod snippets;
 mod state;
 #[allow(clippy::module_inception)]
 mod state_store;
+pub mod terminal;
+mod user_data_export;
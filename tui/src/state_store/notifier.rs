@@ -0,0 +1,23 @@
+/// A desktop notification about a message addressed to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+}
+
+/// Shows the notification on the desktop, in the background as the notification daemon may be slow to answer
+#[cfg(feature = "desktop-notifications")]
+pub fn notify(notification: Notification) {
+    tokio::task::spawn_blocking(move || {
+        // the notification is only a courtesy, it is dropped when no notification daemon is running
+        let _ = notify_rust::Notification::new()
+            .appname("rust-chat-server")
+            .summary(&notification.summary)
+            .body(&notification.body)
+            .show();
+    });
+}
+
+/// Drops the notification, the client is built without the desktop notifications
+#[cfg(not(feature = "desktop-notifications"))]
+pub fn notify(_notification: Notification) {}
@@ -0,0 +1,57 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use comms::event::RoomHistoryChunkReplyEvent;
+
+/// [RoomExport] writes the chunks of an exported room history to a file as they arrive
+///
+/// It keeps the cursor of the last received chunk, so an interrupted export can be resumed.
+#[derive(Debug)]
+pub struct RoomExport {
+    path: PathBuf,
+    cursor: Option<u64>,
+}
+
+impl RoomExport {
+    /// Creates a new json lines file in the current directory for the export of the given room
+    pub fn create(room: &str) -> anyhow::Result<Self> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = PathBuf::from(format!("chat-room-export-{}-{}.jsonl", room, millis));
+
+        std::fs::File::create(&path)?;
+
+        Ok(RoomExport { path, cursor: None })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The cursor to resume the export after
+    pub fn cursor(&self) -> Option<u64> {
+        self.cursor
+    }
+
+    /// Appends the messages of the chunk to the file, one json object per line
+    pub fn append(&mut self, chunk: &RoomHistoryChunkReplyEvent) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+
+        for message in chunk.messages.iter() {
+            serde_json::to_writer(&mut file, message)?;
+            file.write_all(b"\n")?;
+        }
+
+        self.cursor = chunk.cursor;
+
+        Ok(())
+    }
+
+    /// Removes the file of an export which could not be completed
+    pub fn discard(self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+/// Tasks which can be scheduled to run on the state, either once or periodically
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduledTask {
+    /// Restores the title of the terminal once it has told about new messages for a while
+    RestoreTitle,
+    /// Drops the image previews the active room does not show
+    FlushImagePreviews,
+    /// Hides the toast which is currently shown
+    ExpireToast,
+    /// Hides the warning shown while the server is rate limiting the user
+    ExpireRateLimitWarning,
+    /// Rolls back the join of a room, unless the server has confirmed it by then
+    ExpireRoomJoin { room: String },
+    /// Tries to reconnect to the server the connection to has dropped
+    Reconnect,
+    /// Reconnects to the server, unless it has pinged the client by then
+    DetectStalledConnection,
+}
+
+impl ScheduledTask {
+    /// Whether the task is about the connection to the server, and is cancelled along with it
+    fn is_bound_to_connection(&self) -> bool {
+        !matches!(
+            self,
+            ScheduledTask::RestoreTitle | ScheduledTask::FlushImagePreviews
+        )
+    }
+}
+
+#[derive(Debug)]
+struct ScheduledEntry {
+    task: ScheduledTask,
+    due_at: Instant,
+    /// The period to reschedule the task with, after it is due
+    period: Option<Duration>,
+}
+
+/// [Scheduler] keeps track of the tasks to run at a later time
+///
+/// It does not own a timer, it is polled with the current time by a single interval instead.
+/// A task can only be scheduled once, scheduling it again replaces the previous schedule.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    entries: Vec<ScheduledEntry>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    /// Schedules the task to be due once, after the given delay
+    pub fn schedule_once(&mut self, task: ScheduledTask, delay: Duration, now: Instant) {
+        self.schedule(task, now + delay, None);
+    }
+
+    /// Schedules the task to be due every period, starting one period from now
+    pub fn schedule_periodic(&mut self, task: ScheduledTask, period: Duration, now: Instant) {
+        self.schedule(task, now + period, Some(period));
+    }
+
+    pub fn cancel(&mut self, task: &ScheduledTask) {
+        self.entries.retain(|entry| entry.task != *task);
+    }
+
+    /// Cancels the tasks about the connection to the server, once it is gone
+    ///
+    /// The tasks about the terminal and the caches keep running whether the client is connected or not.
+    pub fn cancel_connection_tasks(&mut self) {
+        self.entries
+            .retain(|entry| !entry.task.is_bound_to_connection());
+    }
+
+    /// Returns the tasks which are due at the given time, rescheduling the periodic ones
+    ///
+    /// A periodic task which has missed multiple periods is only returned once.
+    pub fn take_due(&mut self, now: Instant) -> Vec<ScheduledTask> {
+        let mut due = vec![];
+
+        self.entries.retain_mut(|entry| {
+            if entry.due_at > now {
+                return true;
+            }
+
+            due.push(entry.task.clone());
+
+            match entry.period {
+                Some(period) => {
+                    while entry.due_at <= now {
+                        entry.due_at += period;
+                    }
+
+                    true
+                }
+                None => false,
+            }
+        });
+
+        due
+    }
+
+    fn schedule(&mut self, task: ScheduledTask, due_at: Instant, period: Option<Duration>) {
+        self.cancel(&task);
+        self.entries.push(ScheduledEntry {
+            task,
+            due_at,
+            period,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_one_shot_task_is_due_once() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule_once(ScheduledTask::ExpireToast, SECOND, now);
+
+        assert!(scheduler.take_due(now).is_empty());
+        assert_eq!(
+            scheduler.take_due(now + SECOND),
+            vec![ScheduledTask::ExpireToast]
+        );
+        assert!(scheduler.take_due(now + SECOND * 2).is_empty());
+    }
+
+    #[test]
+    fn test_periodic_task_is_rescheduled() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule_periodic(ScheduledTask::FlushImagePreviews, SECOND, now);
+
+        assert_eq!(
+            scheduler.take_due(now + SECOND),
+            vec![ScheduledTask::FlushImagePreviews]
+        );
+        // missed periods are collapsed into a single run
+        assert_eq!(
+            scheduler.take_due(now + SECOND * 5),
+            vec![ScheduledTask::FlushImagePreviews]
+        );
+        assert!(scheduler.take_due(now + SECOND * 5).is_empty());
+    }
+
+    #[test]
+    fn test_the_tasks_outside_of_the_connection_outlive_it() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule_periodic(ScheduledTask::FlushImagePreviews, SECOND, now);
+        scheduler.schedule_once(ScheduledTask::RestoreTitle, SECOND, now);
+        scheduler.schedule_once(ScheduledTask::DetectStalledConnection, SECOND, now);
+        scheduler.cancel_connection_tasks();
+
+        assert_eq!(
+            scheduler.take_due(now + SECOND),
+            vec![
+                ScheduledTask::FlushImagePreviews,
+                ScheduledTask::RestoreTitle
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rescheduling_replaces_the_task() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule_once(ScheduledTask::ExpireToast, SECOND, now);
+        scheduler.schedule_once(ScheduledTask::ExpireToast, SECOND * 3, now);
+
+        assert!(scheduler.take_due(now + SECOND * 2).is_empty());
+        assert_eq!(
+            scheduler.take_due(now + SECOND * 3),
+            vec![ScheduledTask::ExpireToast]
+        );
+    }
+
+    #[test]
+    fn test_cancelled_task_is_not_due() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule_once(ScheduledTask::ExpireToast, SECOND, now);
+        scheduler.cancel(&ScheduledTask::ExpireToast);
+
+        assert!(scheduler.take_due(now + SECOND).is_empty());
+    }
+}
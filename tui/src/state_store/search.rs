@@ -0,0 +1,123 @@
+use circular_queue::CircularQueue;
+
+use super::MessageBoxItem;
+
+/// [MessageSearch] is a search through the messages kept for a room
+///
+/// The hits are indexed by the position of the messages in the room, the oldest first,
+/// and indexed again whenever the messages of the room change.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageSearch {
+    /// The room being searched
+    pub room: String,
+    pub query: String,
+    /// The positions of the matching messages, the oldest first
+    pub hits: Vec<usize>,
+    /// The hit the message list is positioned at, as an index into the hits
+    pub current: usize,
+}
+
+/// Does the item match the query, which is lowercase, regardless of the case of the item
+fn is_match(mbi: &MessageBoxItem, query: &str) -> bool {
+    let text = match mbi {
+        MessageBoxItem::Message { content, .. } => content,
+        MessageBoxItem::Notification(content) | MessageBoxItem::Error(content) => content,
+        MessageBoxItem::File { name, .. } => name,
+    };
+
+    text.to_lowercase().contains(query)
+}
+
+impl MessageSearch {
+    /// Searches the messages, starting from the latest hit
+    pub fn new(room: String, query: String, messages: &CircularQueue<MessageBoxItem>) -> Self {
+        let mut search = MessageSearch {
+            room,
+            query,
+            ..Default::default()
+        };
+        search.index(messages);
+        search.current = search.hits.len().saturating_sub(1);
+
+        search
+    }
+
+    /// Finds the hits again, keeping the current one if it is still there
+    pub fn index(&mut self, messages: &CircularQueue<MessageBoxItem>) {
+        let query = self.query.to_lowercase();
+
+        self.hits = if query.is_empty() {
+            vec![]
+        } else {
+            messages
+                .asc_iter()
+                .enumerate()
+                .filter(|(_, mbi)| is_match(mbi, &query))
+                .map(|(position, _)| position)
+                .collect()
+        };
+        self.current = self.current.min(self.hits.len().saturating_sub(1));
+    }
+
+    /// The position of the message the list is positioned at
+    pub fn current_hit(&self) -> Option<usize> {
+        self.hits.get(self.current).copied()
+    }
+
+    /// Moves to an older hit, wrapping around to the latest one
+    pub fn older(&mut self) {
+        if !self.hits.is_empty() {
+            self.current = self.current.checked_sub(1).unwrap_or(self.hits.len() - 1);
+        }
+    }
+
+    /// Moves to a newer hit, wrapping around to the oldest one
+    pub fn newer(&mut self) {
+        if !self.hits.is_empty() {
+            self.current = (self.current + 1) % self.hits.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(contents: &[&str]) -> CircularQueue<MessageBoxItem> {
+        let mut messages = CircularQueue::with_capacity(contents.len());
+        for content in contents {
+            messages.push(MessageBoxItem::Notification(String::from(*content)));
+        }
+
+        messages
+    }
+
+    #[test]
+    fn test_hits_are_found_regardless_of_the_case() {
+        let mut search = MessageSearch::new(
+            String::from("general"),
+            String::from("Rust"),
+            &messages(&["rust is fun", "so is go", "RUSTACEANS"]),
+        );
+
+        assert_eq!(search.hits, vec![0, 2]);
+        assert_eq!(search.current_hit(), Some(2));
+
+        search.older();
+        assert_eq!(search.current_hit(), Some(0));
+        search.older();
+        assert_eq!(search.current_hit(), Some(2));
+        search.newer();
+        assert_eq!(search.current_hit(), Some(0));
+    }
+
+    #[test]
+    fn test_empty_query_has_no_hits() {
+        let mut search =
+            MessageSearch::new(String::from("general"), String::new(), &messages(&["hi"]));
+        search.newer();
+
+        assert!(search.hits.is_empty());
+        assert_eq!(search.current_hit(), None);
+    }
+}
@@ -0,0 +1,14 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Saves the content to a new file in the current directory, returning the path of the file
+pub fn save_to_file(content: &str) -> anyhow::Result<PathBuf> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = PathBuf::from(format!("chat-snippet-{}.txt", millis));
+
+    std::fs::write(&path, content)?;
+
+    Ok(path)
+}
@@ -1,15 +1,93 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use circular_queue::CircularQueue;
 use comms::event;
+use ratatui::style::Color;
+
+use super::{
+    highlights, image_previews::image_source_of, notifier::Notification, scheduler::ScheduledTask,
+    search::MessageSearch,
+};
+use crate::{
+    config::{AlertPolicy, ClientConfig, ConfigMigration, LayoutConfig, RoomSort},
+    graphics::PreviewImage,
+    keymap::Keymap,
+    theme::Theme,
+};
 
 #[derive(Debug, Clone)]
 pub enum MessageBoxItem {
-    Message { user_id: String, content: String },
+    Message {
+        /// The id of the message in the room, direct messages are not numbered
+        id: u64,
+        user_id: String,
+        content: String,
+        /// The time the message was sent at, unknown for the live messages of older servers
+        timestamp: Option<u64>,
+        /// The id of the message this message replies to
+        reply_to: Option<u64>,
+        /// Has the author edited the message since sending it
+        is_edited: bool,
+        /// The reactions to the message, shown under it
+        reactions: Vec<event::Reaction>,
+    },
     Notification(String),
+    /// A command of the user which the server could not run
+    Error(String),
+    /// A file shared with the room, which can be saved by its id
+    File {
+        id: String,
+        user_id: String,
+        name: String,
+        /// The size of the file in bytes
+        size: u64,
+        timestamp: u64,
+    },
 }
 
 const MAX_MESSAGES_TO_STORE_PER_ROOM: usize = 100;
+/// How many messages a room can hold once older messages are loaded by scrolling back
+const MAX_PAGED_MESSAGES_PER_ROOM: usize = 1000;
+
+/// The key of the conversation of direct messages with the given user in the room data map,
+/// room slugs never start with an `@`, so the two can not collide
+pub fn direct_message_room(user_id: &str) -> String {
+    format!("@{}", user_id)
+}
+
+/// Names the user by the name they are shown with, as in `Alice (@alice)`, or by their id if they have none
+pub fn user_label(display_names: &HashMap<String, String>, user_id: &str) -> String {
+    match display_names.get(user_id) {
+        Some(display_name) => format!("{} (@{})", display_name, user_id),
+        None => format!("@{}", user_id),
+    }
+}
+
+/// Names the role, as in `@bob is now a moderator`
+pub fn room_role_label(role: event::RoomRole) -> &'static str {
+    match role {
+        event::RoomRole::Member => "a member",
+        event::RoomRole::Moderator => "a moderator",
+        event::RoomRole::Owner => "the owner",
+    }
+}
+
+/// Describes what a moderator has done to a user, as in `@bob was muted for 10 minutes by @alice`
+pub fn moderation_label(action: &event::ModerationAction) -> String {
+    match action {
+        event::ModerationAction::Kicked => String::from("kicked"),
+        event::ModerationAction::Banned => String::from("banned"),
+        event::ModerationAction::Muted { seconds } => {
+            format!("muted for {} minutes", seconds.div_ceil(60))
+        }
+        event::ModerationAction::Unmuted => String::from("unmuted"),
+    }
+}
 
 /// RoomData holds the data for a room
 #[derive(Debug, Clone)]
@@ -18,14 +96,46 @@ pub struct RoomData {
     pub name: String,
     /// The description of the Room
     pub description: String,
-    /// List of users in the room
-    pub users: HashSet<String>,
+    /// The users in the room, kept sorted as they are listed
+    pub users: BTreeSet<String>,
     /// History of recorded messages
     pub messages: CircularQueue<MessageBoxItem>,
     /// Has joined the room
     pub has_joined: bool,
-    /// Has unread messages
-    pub has_unread: bool,
+    /// Is waiting for the server to confirm joining the room, which is shown as joined meanwhile
+    pub is_join_pending: bool,
+    /// How many messages arrived while the room was not active
+    pub unread_count: usize,
+    /// How many of the unread messages mention or highlight the user
+    pub unread_mention_count: usize,
+    /// How much of the room history is shared with new members
+    pub history_visibility: event::HistoryVisibility,
+    /// Is waiting for the server to send the room history
+    pub is_fetching_history: bool,
+    /// Is waiting for the server to send the messages older than the oldest one kept
+    pub is_fetching_older: bool,
+    /// May the server have visible messages older than the oldest one kept
+    pub has_older_messages: bool,
+    /// The timestamp the message list should be positioned at, instead of the latest messages
+    pub jump_target: Option<u64>,
+    /// The template which pre-populates the message input when composing in the room
+    pub input_template: Option<String>,
+    /// How many items the message list is scrolled back from the latest messages
+    pub scroll_offset: usize,
+    /// Is a conversation of direct messages with the user named by the room, rather than a room
+    pub is_direct_message: bool,
+    /// Is a private room, which is not listed by the server, hence it is dropped when joining it fails
+    pub is_private: bool,
+    /// The message the next message sent to the room replies to
+    pub replying_to: Option<u64>,
+    /// The role of the user in the room, deciding which commands are available
+    pub role: event::RoomRole,
+    /// The text left in the message input when moving away from the room, restored when coming back
+    pub draft: Option<String>,
+    /// The id of the last message read in the room, the messages are read up to the latest one when leaving the room
+    pub last_read_id: Option<u64>,
+    /// The new messages are divided from the read ones after this message, set when entering the room
+    pub unread_marker: Option<u64>,
 }
 
 impl Default for RoomData {
@@ -33,68 +143,663 @@ impl Default for RoomData {
         RoomData {
             name: String::new(),
             description: String::new(),
-            users: HashSet::new(),
+            users: BTreeSet::new(),
             messages: CircularQueue::with_capacity(MAX_MESSAGES_TO_STORE_PER_ROOM),
             has_joined: false,
-            has_unread: false,
+            is_join_pending: false,
+            unread_count: 0,
+            unread_mention_count: 0,
+            history_visibility: event::HistoryVisibility::default(),
+            is_fetching_history: false,
+            is_fetching_older: false,
+            has_older_messages: true,
+            jump_target: None,
+            input_template: None,
+            scroll_offset: 0,
+            is_direct_message: false,
+            is_private: false,
+            replying_to: None,
+            role: event::RoomRole::default(),
+            draft: None,
+            last_read_id: None,
+            unread_marker: None,
         }
     }
 }
 
 impl RoomData {
-    pub fn new(name: String, description: String) -> Self {
+    pub fn new(
+        name: String,
+        description: String,
+        history_visibility: event::HistoryVisibility,
+    ) -> Self {
         RoomData {
             name,
             description,
+            history_visibility,
             ..Default::default()
         }
     }
+
+    /// Appends a received item, keeping the scrolled back view in place
+    fn push_item(&mut self, item: MessageBoxItem) {
+        if self.scroll_offset > 0 {
+            self.scroll_offset = (self.scroll_offset + 1).min(self.messages.capacity());
+        }
+
+        self.messages.push(item);
+    }
+
+    /// Replaces the content of the message with the given id, if it is still kept
+    fn edit_message(&mut self, message_id: u64, new_content: &str) {
+        for mbi in self.messages.iter_mut() {
+            if let MessageBoxItem::Message {
+                id,
+                content,
+                is_edited,
+                ..
+            } = mbi
+            {
+                if *id == message_id {
+                    *content = String::from(new_content);
+                    *is_edited = true;
+                }
+            }
+        }
+    }
+
+    /// Replaces the reactions to the message with the given id, if it is still kept
+    fn set_reactions(&mut self, message_id: u64, new_reactions: &[event::Reaction]) {
+        for mbi in self.messages.iter_mut() {
+            if let MessageBoxItem::Message { id, reactions, .. } = mbi {
+                if *id == message_id {
+                    *reactions = new_reactions.to_vec();
+                }
+            }
+        }
+    }
+
+    /// Removes the message with the given id, if it is still kept
+    fn delete_message(&mut self, message_id: u64) {
+        let mut messages = CircularQueue::with_capacity(self.messages.capacity());
+
+        for mbi in self.messages.asc_iter() {
+            if !matches!(mbi, MessageBoxItem::Message { id, .. } if *id == message_id) {
+                messages.push(mbi.clone());
+            }
+        }
+
+        self.messages = messages;
+    }
+
+    /// Drops the messages older than the given id, which the server does not keep anymore
+    fn truncate_history(&mut self, before: u64) {
+        let mut messages = CircularQueue::with_capacity(self.messages.capacity());
+
+        for mbi in self.messages.asc_iter() {
+            if !matches!(mbi, MessageBoxItem::Message { id, .. } if *id < before) {
+                messages.push(mbi.clone());
+            }
+        }
+
+        self.messages = messages;
+    }
+
+    /// Puts the given history messages ahead of the items which are already received.
+    ///
+    /// The history is read after joining the room, hence it already contains most of the messages
+    /// received since then. The notifications and the messages newer than the history are kept
+    /// from the received items, unless the history is a window around a date, which replaces all of the items.
+    fn merge_history(&mut self, event: &event::RoomHistoryReplyEvent) {
+        let mut messages = CircularQueue::with_capacity(MAX_MESSAGES_TO_STORE_PER_ROOM);
+        let last_history_id = event.messages.last().map(|message| message.id);
+
+        for message in event.messages.iter() {
+            messages.push(history_item(message));
+        }
+
+        if event.around.is_none() {
+            for mbi in self.messages.asc_iter() {
+                let is_newer = match mbi {
+                    MessageBoxItem::Message { id, .. } => {
+                        last_history_id.map(|last| *id > last).unwrap_or(true)
+                    }
+                    MessageBoxItem::Notification(_)
+                    | MessageBoxItem::Error(_)
+                    | MessageBoxItem::File { .. } => true,
+                };
+
+                if is_newer {
+                    messages.push(mbi.clone());
+                }
+            }
+        }
+
+        self.messages = messages;
+        self.last_read_id = self.last_read_id.max(event.last_read);
+        self.is_fetching_history = false;
+        self.is_fetching_older = false;
+        self.has_older_messages = true;
+        self.scroll_offset = 0;
+        self.jump_target = event.around;
+    }
+
+    /// The id of the latest message kept
+    fn newest_message_id(&self) -> Option<u64> {
+        self.messages.iter().find_map(|mbi| match mbi {
+            MessageBoxItem::Message { id, .. } => Some(*id),
+            MessageBoxItem::Notification(_)
+            | MessageBoxItem::Error(_)
+            | MessageBoxItem::File { .. } => None,
+        })
+    }
+
+    /// Divides the messages received since the last one read, if there are any
+    fn place_unread_marker(&mut self) {
+        let newest_message_id = self.newest_message_id();
+
+        self.unread_marker = self
+            .last_read_id
+            .filter(|last_read_id| newest_message_id.is_some_and(|newest| newest > *last_read_id));
+    }
+
+    /// The id of the oldest message kept, the older messages are fetched from there
+    fn oldest_message_id(&self) -> Option<u64> {
+        self.messages.asc_iter().find_map(|mbi| match mbi {
+            MessageBoxItem::Message { id, .. } => Some(*id),
+            MessageBoxItem::Notification(_)
+            | MessageBoxItem::Error(_)
+            | MessageBoxItem::File { .. } => None,
+        })
+    }
+
+    /// Puts the older messages ahead of the items which are already received
+    ///
+    /// The room grows to hold them, up to a limit. The scrolled back view stays in place,
+    /// as it is counted from the latest messages.
+    fn prepend_older_messages(&mut self, event: &event::OlderMessagesReplyEvent) {
+        self.is_fetching_older = false;
+
+        // the messages were replaced meanwhile, such as by jumping to a date
+        if self.oldest_message_id() != Some(event.before_message_id) {
+            return;
+        }
+
+        let older = event
+            .messages
+            .iter()
+            .filter(|message| message.id < event.before_message_id)
+            .map(history_item)
+            .collect::<Vec<_>>();
+        let capacity = self
+            .messages
+            .capacity()
+            .max(self.messages.len() + older.len())
+            .min(MAX_PAGED_MESSAGES_PER_ROOM);
+        let mut messages = CircularQueue::with_capacity(capacity);
+
+        for mbi in older.into_iter().chain(self.messages.asc_iter().cloned()) {
+            messages.push(mbi);
+        }
+
+        self.messages = messages;
+        self.has_older_messages = event.has_more;
+    }
+}
+
+fn history_item(message: &event::HistoryMessage) -> MessageBoxItem {
+    MessageBoxItem::Message {
+        id: message.id,
+        user_id: message.user_id.clone(),
+        content: message.content.clone(),
+        timestamp: Some(message.timestamp),
+        reply_to: message.reply_to,
+        is_edited: message.is_edited,
+        reactions: message.reactions.clone(),
+    }
+}
+
+/// SpaceData holds the data for a space, which groups rooms together
+#[derive(Debug, Clone, Default)]
+pub struct SpaceData {
+    /// The rooms of the space, in the order defined by the server
+    pub rooms: Vec<String>,
+    /// Members of the space and their roles, only known once joined
+    pub members: HashMap<String, event::SpaceRole>,
+    /// Has joined the space
+    pub has_joined: bool,
+}
+
+/// HistorySearch holds the matches of a search through the stored history of a room,
+/// paged in from the server
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistorySearch {
+    pub room: String,
+    pub query: String,
+    /// The matching messages received so far, the newest first
+    pub results: Vec<event::HistoryMessage>,
+    /// Where the next page of the older matches starts, None once every match is received
+    pub cursor: Option<u64>,
+    /// Is a page of matches requested from the server
+    pub is_loading: bool,
+}
+
+/// RoomDirectory holds the public rooms of the server, paged in from the server while the user browses them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoomDirectory {
+    /// The rooms received so far, ordered by name
+    pub rooms: Vec<event::DirectoryRoom>,
+    /// Where the next page of the rooms starts, None once every room is received
+    pub cursor: Option<String>,
+    /// Is a page of rooms requested from the server
+    pub is_loading: bool,
+}
+
+/// TransferProgress tells how much of a file being uploaded or downloaded is transferred
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferProgress {
+    pub name: String,
+    pub is_upload: bool,
+    /// The number of bytes transferred so far
+    pub transferred: u64,
+    /// The size of the file in bytes, zero until it is known
+    pub size: u64,
+}
+
+/// ImagePreview is the preview of an image referenced by a message, fetched and decoded in the background
+#[derive(Debug, Clone)]
+pub enum ImagePreview {
+    Loading,
+    Ready(Arc<PreviewImage>),
+    /// Why the image could not be previewed
+    Failed(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum ServerConnectionStatus {
     Uninitalized,
     Connecting,
-    Connected { addr: String },
-    Errored { err: String },
+    Connected {
+        addr: String,
+    },
+    /// The connection dropped, the state is kept while reconnecting to the server
+    Reconnecting {
+        addr: String,
+        attempt: u32,
+    },
+    Errored {
+        err: String,
+    },
+    /// The server does not serve the protocol version of this client anymore
+    Incompatible {
+        addr: String,
+        min_protocol_version: u16,
+    },
+}
+
+/// Whether the user has logged in on the connected server
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginStatus {
+    LoggedOut,
+    LoggingIn,
+    LoggedIn,
+    Rejected { reason: String },
 }
 
 /// State holds the state of the application
 #[derive(Debug, Clone)]
 pub struct State {
     pub server_connection_status: ServerConnectionStatus,
+    pub login_status: LoginStatus,
     /// Currently active room
     pub active_room: Option<String>,
+    /// The rooms and the conversations the user has opened, the most recent first
+    pub recent_rooms: Vec<String>,
     /// The id of the user
     pub user_id: String,
     /// Storage of room data
     pub room_data_map: HashMap<String, RoomData>,
-    /// Timer since app was opened
-    pub timer: usize,
+    /// Storage of space data
+    pub space_data_map: HashMap<String, SpaceData>,
+    /// A short lived message shown to the user, such as the result of an action
+    pub toast: Option<String>,
+    /// Shown on the message input while the server is rate limiting the messages of the user
+    pub rate_limit_warning: Option<String>,
+    /// The longest message the connected server accepts, as told by its welcome
+    pub max_message_chars: Option<usize>,
+    /// How often the connected server pings the client, if it does
+    pub heartbeat_interval: Option<Duration>,
+    /// How long the client took to answer the last ping of the server, as measured by the server
+    pub round_trip: Option<Duration>,
+    /// Should the room input templates pre-populate the message input
+    pub use_input_templates: bool,
+    /// The server address pre-populated on the connect page
+    pub default_server_addr: String,
+    /// Words which highlight a message like a mention does
+    pub highlight_words: Vec<String>,
+    /// The users whose messages are hidden in every room and whose direct messages are dropped
+    pub ignored_users: Vec<String>,
+    /// The rooms listed ahead of the others
+    pub favorite_rooms: Vec<String>,
+    /// How the rooms which are not in a space are ordered in the room list
+    pub room_sort: RoomSort,
+    /// The strftime format of the time the messages are prefixed with, no prefix when empty
+    pub time_format: String,
+    /// Translates the key presses into what they mean to the app
+    pub keymap: Keymap,
+    /// The colors of the UI
+    pub theme: Theme,
+    /// Is the overlay listing the key bindings and the slash commands shown over the chat page
+    pub is_help_open: bool,
+    /// The changes to review before the config file is upgraded to the current schema
+    pub config_migration: Option<ConfigMigration>,
+    /// The error of the last attempt to write the upgraded config file
+    pub config_migration_error: Option<String>,
+    /// The joined room the user is asked to confirm leaving, until they answer
+    pub leave_prompt: Option<String>,
+    /// Invitations to private rooms waiting for the user to accept or decline them, oldest first
+    pub pending_invitations: Vec<event::RoomInvitationBroadcastEvent>,
+    /// The presence of the users who are not offline, by their ids
+    pub presences: HashMap<String, event::UserPresence>,
+    /// The names the users who changed it are shown with, by their ids, kept after they go offline
+    pub display_names: HashMap<String, String>,
+    /// Can the user change the name they are shown with, as told by the welcome of the server
+    pub can_change_nickname: bool,
+    /// The colors the names of the users who picked one are shown with, by their ids, kept after they go offline
+    pub user_colors: HashMap<String, Color>,
+    /// The profile shown in the popup, from the reply of the server until it is closed
+    pub user_profile: Option<event::UserProfile>,
+    /// Can the user look at the profiles and fill their own, as told by the welcome of the server
+    pub can_view_profiles: bool,
+    /// Can the public rooms be browsed, whether they are joined or not, as told by the welcome of the server
+    pub can_browse_rooms: bool,
+    /// The public rooms listed while the room list browses them
+    pub room_directory: Option<RoomDirectory>,
+    /// Should the mentions and the direct messages be notified on the desktop
+    pub desktop_notifications: bool,
+    /// Are the desktop notifications held back for now, as toggled with `/dnd`
+    pub is_do_not_disturb: bool,
+    /// Is the terminal focused, as told by the terminal itself
+    pub is_terminal_focused: bool,
+    /// How the new messages of the inactive rooms are told about
+    pub alert_policy: AlertPolicy,
+    /// Are the images linked to by the messages fetched to preview them
+    pub preview_image_links: bool,
+    /// How the chat page shares its width between the side panels and the messages
+    pub layout: LayoutConfig,
+    /// Does the chat page take the keys as vim does
+    pub vim_mode: bool,
+    /// The search through the messages of the active room, if the user is searching them
+    pub search: Option<MessageSearch>,
+    /// Can the server search the stored history of the rooms, as told by its welcome
+    pub can_search_history: bool,
+    /// Can the server send the messages older than the ones received, as told by its welcome
+    pub can_fetch_older_messages: bool,
+    /// Does the server keep the last message read in each room, as told by its welcome
+    pub can_mark_read: bool,
+    /// The search through the stored history of a room, shown over the chat page while open
+    pub history_search: Option<HistorySearch>,
+    /// Can files be shared with the rooms of the server, as told by its welcome
+    pub can_transfer_files: bool,
+    /// The files being uploaded and downloaded
+    pub transfers: Vec<TransferProgress>,
+    /// The previews of the images referenced by the messages, by the key of their source
+    pub image_previews: HashMap<String, ImagePreview>,
+    /// The notice of the server shutting down, shown until the client is connected to the server again
+    pub server_shutdown: Option<event::ServerShuttingDownEvent>,
+    /// The latest announcement of the server, shown above the messages until it is dismissed
+    pub announcement: Option<event::AnnouncementEvent>,
 }
 
 impl Default for State {
     fn default() -> Self {
+        State::from_config(&ClientConfig::default())
+    }
+}
+
+impl State {
+    /// Creates the initial state of the application from the settings of the user
+    pub fn from_config(config: &ClientConfig) -> Self {
         State {
             server_connection_status: ServerConnectionStatus::Uninitalized,
+            login_status: LoginStatus::LoggedOut,
             active_room: None,
+            recent_rooms: vec![],
             user_id: String::new(),
             room_data_map: HashMap::new(),
-            timer: 0,
+            space_data_map: HashMap::new(),
+            toast: None,
+            rate_limit_warning: None,
+            max_message_chars: None,
+            heartbeat_interval: None,
+            round_trip: None,
+            use_input_templates: config.use_input_templates,
+            default_server_addr: config.server_addr.clone(),
+            highlight_words: config.highlight_words.clone(),
+            ignored_users: config.ignored_users.clone(),
+            favorite_rooms: config.favorite_rooms.clone(),
+            room_sort: config.room_sort,
+            time_format: config.time_format.clone(),
+            keymap: config.keymap.clone(),
+            theme: Theme::resolve(&config.theme, &config.colors),
+            is_help_open: false,
+            config_migration: None,
+            config_migration_error: None,
+            leave_prompt: None,
+            pending_invitations: Vec::new(),
+            presences: HashMap::new(),
+            display_names: HashMap::new(),
+            can_change_nickname: false,
+            user_colors: HashMap::new(),
+            user_profile: None,
+            can_view_profiles: false,
+            can_browse_rooms: false,
+            room_directory: None,
+            desktop_notifications: config.desktop_notifications,
+            is_do_not_disturb: false,
+            is_terminal_focused: true,
+            alert_policy: config.alert,
+            preview_image_links: config.preview_image_links,
+            layout: config.layout,
+            vim_mode: config.vim_mode,
+            search: None,
+            can_search_history: false,
+            can_fetch_older_messages: false,
+            can_mark_read: false,
+            history_search: None,
+            can_transfer_files: false,
+            transfers: Vec::new(),
+            image_previews: HashMap::new(),
+            server_shutdown: None,
+            announcement: None,
         }
     }
-}
 
-impl State {
+    /// The room a message is sent to, as shown in the room list, if it is new activity in an inactive room
+    pub fn alerting_room(&self, event: &event::Event) -> Option<String> {
+        let (room, label) = match event {
+            event::Event::UserMessage(event) if self.is_ignored(&event.user_id) => return None,
+            event::Event::DirectMessage(event) if self.is_ignored(&event.from_user_id) => {
+                return None
+            }
+            event::Event::UserMessage(event) if event.user_id != self.user_id => {
+                (event.room.clone(), format!("#{}", event.room))
+            }
+            event::Event::DirectMessage(event) if event.from_user_id != self.user_id => {
+                let room = direct_message_room(&event.from_user_id);
+
+                (room.clone(), room)
+            }
+            _ => return None,
+        };
+
+        (self.active_room.as_ref() != Some(&room)).then_some(label)
+    }
+
+    /// The desktop notification of a message mentioning the user or sent to them directly,
+    /// unless the user is looking at the room it is sent to
+    pub fn notification_for(&self, event: &event::Event) -> Option<Notification> {
+        if !self.desktop_notifications || self.is_do_not_disturb {
+            return None;
+        }
+
+        let (room, notification) = match event {
+            event::Event::UserMessage(event)
+                if self.is_highlighted(&event.user_id, &event.content)
+                    && !self.is_ignored(&event.user_id) =>
+            {
+                (
+                    event.room.clone(),
+                    Notification {
+                        summary: format!("@{} in #{}", event.user_id, event.room),
+                        body: event.content.clone(),
+                    },
+                )
+            }
+            event::Event::DirectMessage(event)
+                if event.from_user_id != self.user_id && !self.is_ignored(&event.from_user_id) =>
+            {
+                (
+                    direct_message_room(&event.from_user_id),
+                    Notification {
+                        summary: format!("@{} sent you a direct message", event.from_user_id),
+                        body: event.content.clone(),
+                    },
+                )
+            }
+            _ => return None,
+        };
+
+        let is_looking = self.is_terminal_focused && self.active_room.as_ref() == Some(&room);
+
+        (!is_looking).then_some(notification)
+    }
+
     pub fn handle_server_event(&mut self, event: &event::Event) {
         match event {
+            event::Event::Welcome(event) => {
+                self.max_message_chars = Some(event.max_message_chars);
+                self.heartbeat_interval = event.heartbeat_interval.map(Duration::from_millis);
+                self.can_search_history = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::MESSAGE_SEARCH);
+                self.can_fetch_older_messages = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::HISTORY_PAGINATION);
+                self.can_mark_read = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::READ_MARKERS);
+                self.can_transfer_files = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::FILE_TRANSFER);
+                self.can_change_nickname = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::NICKNAMES);
+                self.can_view_profiles = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::PROFILES);
+                self.can_browse_rooms = event
+                    .features
+                    .iter()
+                    .any(|feature| feature == comms::protocol::features::ROOM_DIRECTORY);
+            }
+            event::Event::ServerShuttingDown(event) => {
+                self.server_shutdown = Some(event.clone());
+            }
+            // a newer announcement replaces the one shown, even if it was not dismissed yet
+            event::Event::Announcement(event) => {
+                self.announcement = Some(event.clone());
+            }
+            event::Event::PresenceSnapshot(event) => {
+                self.presences = event
+                    .users
+                    .iter()
+                    .map(|presence| (presence.user_id.clone(), presence.clone()))
+                    .collect();
+                for presence in event.users.iter() {
+                    self.set_display_name(&presence.user_id, presence.display_name.as_deref());
+                    self.set_user_color(&presence.user_id, presence.color.as_deref());
+                }
+            }
+            event::Event::PresenceChanged(event) => {
+                let presence = &event.presence;
+                self.set_display_name(&presence.user_id, presence.display_name.as_deref());
+                self.set_user_color(&presence.user_id, presence.color.as_deref());
+
+                if presence.status == event::PresenceStatus::Offline {
+                    self.presences.remove(&presence.user_id);
+                } else {
+                    self.presences
+                        .insert(presence.user_id.clone(), presence.clone());
+                }
+            }
+            event::Event::NicknameChanged(event) => {
+                self.set_display_name(&event.user_id, event.display_name.as_deref());
+                let notification = match event.display_name.as_ref() {
+                    Some(display_name) => {
+                        format!("@{} is now known as {}", event.user_id, display_name)
+                    }
+                    None => format!("@{} is known by their id again", event.user_id),
+                };
+
+                // the rooms the user is in are told, their earlier messages are shown with the new name
+                for room_data in self
+                    .room_data_map
+                    .values_mut()
+                    .filter(|room_data| room_data.users.contains(&event.user_id))
+                {
+                    room_data.push_item(MessageBoxItem::Notification(notification.clone()));
+                }
+            }
+            event::Event::UserProfile(event) => {
+                let profile = &event.profile;
+                self.set_display_name(&profile.user_id, profile.display_name.as_deref());
+                self.set_user_color(&profile.user_id, profile.color.as_deref());
+                self.user_profile = Some(profile.clone());
+            }
+            event::Event::LoginResult(event) => {
+                // an accepted login is followed by the login successful event
+                if !event.is_accepted {
+                    self.login_status = LoginStatus::Rejected {
+                        reason: event.reason.clone().unwrap_or_default(),
+                    };
+                }
+            }
             event::Event::LoginSuccessful(event) => {
+                self.login_status = LoginStatus::LoggedIn;
                 self.user_id = event.user_id.clone();
                 self.room_data_map = event
                     .rooms
                     .clone()
                     .into_iter()
-                    .map(|r| (r.name.clone(), RoomData::new(r.name, r.description)))
+                    .map(|r| {
+                        (
+                            r.name.clone(),
+                            RoomData {
+                                input_template: r.input_template,
+                                ..RoomData::new(r.name, r.description, r.history_visibility)
+                            },
+                        )
+                    })
+                    .collect();
+                self.space_data_map = event
+                    .spaces
+                    .iter()
+                    .map(|s| {
+                        (
+                            s.name.clone(),
+                            SpaceData {
+                                rooms: s.rooms.clone(),
+                                ..SpaceData::default()
+                            },
+                        )
+                    })
                     .collect();
             }
             event::Event::RoomParticipation(event) => {
@@ -114,64 +819,2100 @@ impl State {
                         }
                     }
 
-                    room_data
-                        .messages
-                        .push(MessageBoxItem::Notification(format!(
-                            "{} has {} the room",
-                            event.user_id,
-                            match event.status {
-                                event::RoomParticipationStatus::Joined => "joined",
-                                event::RoomParticipationStatus::Left => "left",
-                            }
-                        )));
+                    room_data.push_item(MessageBoxItem::Notification(format!(
+                        "{} has {} the room",
+                        event.user_id,
+                        match event.status {
+                            event::RoomParticipationStatus::Joined => "joined",
+                            event::RoomParticipationStatus::Left => "left",
+                        }
+                    )));
                 }
             }
             event::Event::UserJoinedRoom(event) => {
-                self.room_data_map.get_mut(&event.room).unwrap().users =
-                    event.users.clone().into_iter().collect();
+                // the room may have been deleted while the join was on its way
+                let Some(room_data) = self.room_data_map.get_mut(&event.room) else {
+                    return;
+                };
+
+                room_data.users = event.users.clone().into_iter().collect();
+                room_data.role = event.role;
+                room_data.has_joined = true;
+                room_data.is_join_pending = false;
+                // the server replays the history of the room after the join
+                room_data.is_fetching_history = true;
             }
             event::Event::UserMessage(event) => {
-                let room_data = self.room_data_map.get_mut(&event.room).unwrap();
+                let is_highlighted = self.is_highlighted(&event.user_id, &event.content);
+                let is_ignored = self.is_ignored(&event.user_id);
+                let Some(room_data) = self.room_data_map.get_mut(&event.room) else {
+                    return;
+                };
 
-                room_data.messages.push(MessageBoxItem::Message {
+                room_data.push_item(MessageBoxItem::Message {
+                    id: event.id,
                     user_id: event.user_id.clone(),
                     content: event.content.clone(),
+                    // older servers do not send the timestamp, which is then left at zero
+                    timestamp: (event.timestamp > 0).then_some(event.timestamp),
+                    reply_to: event.reply_to,
+                    is_edited: false,
+                    reactions: vec![],
+                });
+
+                // the messages of the ignored users are kept hidden, they are shown again once unignored
+                if self.active_room.as_ref() != Some(&event.room) && !is_ignored {
+                    room_data.unread_count += 1;
+                    if is_highlighted {
+                        room_data.unread_mention_count += 1;
+                    }
+                }
+            }
+            event::Event::FileShared(event) => {
+                let is_ignored = self.is_ignored(&event.user_id);
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.push_item(MessageBoxItem::File {
+                        id: event.file_id.clone(),
+                        user_id: event.user_id.clone(),
+                        name: event.name.clone(),
+                        size: event.size,
+                        timestamp: event.timestamp,
+                    });
+
+                    if self.active_room.as_ref() != Some(&event.room) && !is_ignored {
+                        room_data.unread_count += 1;
+                    }
+                }
+            }
+            event::Event::MessageEdited(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.edit_message(event.id, &event.content);
+                }
+            }
+            event::Event::MessageDeleted(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.delete_message(event.id);
+                }
+            }
+            event::Event::HistoryTruncated(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.truncate_history(event.before);
+                }
+            }
+            event::Event::MessageReactions(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.set_reactions(event.id, &event.reactions);
+                }
+            }
+            event::Event::RoomHistory(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.merge_history(event);
+
+                    // the last read message is only known once the history of a joined room is received
+                    if self.active_room.as_ref() == Some(&event.room) {
+                        room_data.place_unread_marker();
+                    }
+                }
+            }
+            event::Event::OlderMessages(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.prepend_older_messages(event);
+                }
+            }
+            event::Event::SearchResults(event) => {
+                // the results of a search replaced or closed meanwhile are dropped
+                if let Some(search) = self
+                    .history_search
+                    .as_mut()
+                    .filter(|search| search.room == event.room && search.query == event.query)
+                {
+                    search.results.extend(event.messages.iter().cloned());
+                    search.cursor = event.cursor;
+                    search.is_loading = false;
+                }
+            }
+            event::Event::RoomDirectory(event) => {
+                // a page requested before the directory was browsed again is dropped
+                if let Some(directory) = self
+                    .room_directory
+                    .as_mut()
+                    .filter(|directory| directory.is_loading && directory.cursor == event.after)
+                {
+                    directory.rooms.extend(event.rooms.iter().cloned());
+                    directory.cursor = event.cursor.clone();
+                    directory.is_loading = false;
+                }
+            }
+            event::Event::UserJoinedSpace(event) => {
+                if let Some(space_data) = self.space_data_map.get_mut(&event.space) {
+                    space_data.has_joined = true;
+                    space_data.members = event
+                        .members
+                        .iter()
+                        .map(|member| (member.user_id.clone(), member.role))
+                        .collect();
+                }
+            }
+            event::Event::SpaceMembership(event) => {
+                if let Some(space_data) = self.space_data_map.get_mut(&event.space) {
+                    match event.role {
+                        Some(role) => {
+                            space_data.members.insert(event.user_id.clone(), role);
+                        }
+                        None => {
+                            space_data.members.remove(&event.user_id);
+                        }
+                    }
+
+                    // the server leaves the rooms of the space along with it
+                    if event.user_id == self.user_id && event.role.is_none() {
+                        space_data.has_joined = false;
+                        space_data.members.clear();
+
+                        for room in space_data.rooms.iter() {
+                            if let Some(room_data) = self.room_data_map.get_mut(room) {
+                                room_data.has_joined = false;
+                                room_data.users.clear();
+                            }
+                        }
+                    }
+                }
+            }
+            // the direct messages of the ignored users are dropped, rather than opening a conversation
+            event::Event::DirectMessage(event) if self.is_ignored(&event.from_user_id) => {}
+            event::Event::DirectMessage(event) => {
+                let is_sent = event.from_user_id == self.user_id;
+                let room = self.open_direct_message(if is_sent {
+                    &event.to_user_id
+                } else {
+                    &event.from_user_id
+                });
+                let is_active = self.active_room.as_ref() == Some(&room);
+                let Some(room_data) = self.room_data_map.get_mut(&room) else {
+                    return;
+                };
+
+                room_data.push_item(MessageBoxItem::Message {
+                    id: 0,
+                    user_id: event.from_user_id.clone(),
+                    content: event.content.clone(),
+                    timestamp: Some(event.timestamp),
+                    reply_to: None,
+                    is_edited: false,
+                    reactions: vec![],
                 });
 
-                if let Some(active_room) = self.active_room.as_ref() {
-                    if !active_room.eq(&event.room) {
-                        room_data.has_unread = true;
+                // a direct message is addressed to the user, hence it is marked like a mention
+                if !is_sent && !is_active {
+                    room_data.unread_count += 1;
+                    room_data.unread_mention_count += 1;
+                }
+            }
+            event::Event::RoomInvitation(event) => {
+                // a repeated invitation replaces the previous one, rooms which are already joined need no invitation
+                let has_joined = self
+                    .room_data_map
+                    .get(&event.room.name)
+                    .map(|room_data| room_data.has_joined)
+                    .unwrap_or(false);
+
+                self.pending_invitations
+                    .retain(|invitation| invitation.room.name != event.room.name);
+
+                if !has_joined {
+                    self.pending_invitations.push(event.clone());
+                }
+            }
+            event::Event::RoomCreated(event) => {
+                self.room_data_map
+                    .entry(event.room.name.clone())
+                    .or_insert_with(|| RoomData {
+                        input_template: event.room.input_template.clone(),
+                        ..RoomData::new(
+                            event.room.name.clone(),
+                            event.room.description.clone(),
+                            event.room.history_visibility.clone(),
+                        )
+                    });
+            }
+            event::Event::RoomDeleted(event) => {
+                self.room_data_map.remove(&event.room);
+
+                if self.active_room.as_deref() == Some(event.room.as_str()) {
+                    self.active_room = None;
+                }
+            }
+            event::Event::RoomTopicChanged(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.description = event.topic.clone();
+                    room_data.push_item(MessageBoxItem::Notification(format!(
+                        r#"@{} changed the topic to "{}""#,
+                        event.changed_by, event.topic
+                    )));
+                }
+            }
+            event::Event::RoomRole(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    if event.user_id == self.user_id {
+                        room_data.role = event.role;
                     }
+
+                    room_data.push_item(MessageBoxItem::Notification(format!(
+                        "@{} is now {}",
+                        event.user_id,
+                        room_role_label(event.role)
+                    )));
+                }
+            }
+            event::Event::UserModerated(event) => {
+                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
+                    room_data.push_item(MessageBoxItem::Notification(format!(
+                        "@{} was {} by @{}",
+                        event.user_id,
+                        moderation_label(&event.action),
+                        event.moderator_id
+                    )));
+                }
+
+                let is_removed = matches!(
+                    event.action,
+                    event::ModerationAction::Kicked | event::ModerationAction::Banned
+                );
+
+                if is_removed && event.user_id == self.user_id {
+                    self.leave_room(&event.room);
+                }
+            }
+            // shown in the active room, or as a toast by the state store if there is none
+            event::Event::CommandError(event) => {
+                if let Some(room_data) = self
+                    .active_room
+                    .as_ref()
+                    .and_then(|active_room| self.room_data_map.get_mut(active_room))
+                {
+                    room_data.push_item(MessageBoxItem::Error(event.message.clone()));
                 }
             }
+            // handled by the state store, since they are not reflected to the state
+            event::Event::RoomInvitationDenied(_)
+            | event::Event::ModerationDenied(_)
+            | event::Event::RoomManagementDenied(_)
+            | event::Event::DirectMessageDenied(_)
+            | event::Event::MessageChangeDenied(_)
+            | event::Event::RoomJoinDenied(_)
+            | event::Event::SpaceCommandDenied(_)
+            | event::Event::RateLimited(_)
+            | event::Event::CommandAck(_)
+            | event::Event::RoomHistoryChunk(_)
+            | event::Event::RoomHistoryExportDenied(_)
+            | event::Event::UploadProgress(_)
+            | event::Event::FileChunk(_)
+            | event::Event::FileTransferDenied(_)
+            | event::Event::UserDataExport(_)
+            | event::Event::AccountDeletionScheduled(_)
+            | event::Event::SessionKicked(_)
+            | event::Event::Disconnected(_)
+            | event::Event::ProtocolRejected(_)
+            | event::Event::Ping(_)
+            | event::Event::ResumeResult(_) => {}
         }
+
+        self.refresh_search();
     }
 
-    pub fn mark_connection_request_start(&mut self) {
-        self.server_connection_status = ServerConnectionStatus::Connecting;
+    /// Shows a toast until it is expired by the scheduler, replacing the current one
+    pub fn show_toast(&mut self, content: String) {
+        self.toast = Some(content);
     }
 
-    /// Processes the result of a connection request to change the state of the application
-    pub fn process_connection_request_result(&mut self, result: anyhow::Result<String>) {
-        self.server_connection_status = match result {
-            Ok(addr) => ServerConnectionStatus::Connected { addr: addr.clone() },
-            Err(err) => ServerConnectionStatus::Errored {
-                err: err.to_string(),
-            },
+    /// Warns the user to slow down until it is expired by the scheduler, after the given wait
+    pub fn show_rate_limit_warning(&mut self, retry_after: Duration) {
+        let seconds = retry_after.as_millis().div_ceil(1000).max(1);
+
+        self.rate_limit_warning = Some(format!("slow down, retry in {}s", seconds));
+    }
+
+    /// Marks the room as waiting for the history requested from the server
+    pub fn mark_history_fetch_start(&mut self, room: &str) {
+        if let Some(room_data) = self.room_data_map.get_mut(room) {
+            room_data.is_fetching_history = true;
         }
     }
 
-    /// Tries to set the active room as the given room. Returns the [RoomData] associated to the room.
-    pub fn try_set_active_room(&mut self, room: &str) -> Option<&RoomData> {
-        let room_data = self.room_data_map.get_mut(room)?;
-        room_data.has_unread = false;
+    /// Marks the room as joined until the server confirms or denies the join
+    pub fn mark_room_join_pending(&mut self, room: &str) {
+        if let Some(room_data) = self.room_data_map.get_mut(room) {
+            room_data.is_join_pending = true;
+        }
+    }
 
-        self.active_room = Some(String::from(room));
+    /// Marks the room as left, returns false if it was not joined
+    pub fn leave_room(&mut self, room: &str) -> bool {
+        let Some(room_data) = self
+            .room_data_map
+            .get_mut(room)
+            .filter(|room_data| room_data.has_joined)
+        else {
+            return false;
+        };
 
-        Some(room_data)
+        room_data.has_joined = false;
+        room_data.users.clear();
+
+        // the private rooms are only known while they are joined
+        if room_data.is_private {
+            self.room_data_map.remove(room);
+        }
+
+        if self.active_room.as_deref() == Some(room) {
+            self.active_room = None;
+        }
+
+        true
+    }
+
+    /// Asks the user to confirm leaving the joined room, returns false if it is not a room they can leave
+    pub fn prompt_leave_room(&mut self, room: &str) -> bool {
+        let can_leave = !self.is_direct_message(room)
+            && self
+                .room_data_map
+                .get(room)
+                .is_some_and(|room_data| room_data.has_joined);
+
+        if can_leave {
+            self.leave_prompt = Some(String::from(room));
+        }
+
+        can_leave
+    }
+
+    /// Leaves the room the user confirmed leaving, returns it unless it was left meanwhile
+    pub fn confirm_leave_room(&mut self) -> Option<String> {
+        let room = self.leave_prompt.take()?;
+
+        self.leave_room(&room).then_some(room)
+    }
+
+    /// Keeps the name the user is shown with, or forgets it when they have none
+    fn set_display_name(&mut self, user_id: &str, display_name: Option<&str>) {
+        match display_name {
+            Some(display_name) => {
+                self.display_names
+                    .insert(String::from(user_id), String::from(display_name));
+            }
+            None => {
+                self.display_names.remove(user_id);
+            }
+        }
+    }
+
+    /// Keeps the color the name of the user is shown with, or forgets it when they have none
+    ///
+    /// The colors which can not be parsed are forgotten as well, the name is shown as the others are.
+    fn set_user_color(&mut self, user_id: &str, color: Option<&str>) {
+        match color.and_then(|color| Color::from_str(color).ok()) {
+            Some(color) => {
+                self.user_colors.insert(String::from(user_id), color);
+            }
+            None => {
+                self.user_colors.remove(user_id);
+            }
+        }
+    }
+
+    /// Shows each line as a notification in the active room
+    pub fn push_notifications(&mut self, lines: &[String]) {
+        let Some(room_data) = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get_mut(active_room))
+        else {
+            return;
+        };
+
+        for line in lines {
+            room_data.push_item(MessageBoxItem::Notification(line.clone()));
+        }
+    }
+
+    /// Reverts a join which the server has denied or not confirmed in time,
+    /// returns false if the join is not pending anymore
+    pub fn roll_back_room_join(&mut self, room: &str) -> bool {
+        let Some(room_data) = self
+            .room_data_map
+            .get_mut(room)
+            .filter(|room_data| room_data.is_join_pending)
+        else {
+            return false;
+        };
+
+        room_data.is_join_pending = false;
+
+        // the private rooms are only known while they are joined
+        if room_data.is_private {
+            self.room_data_map.remove(room);
+        }
+
+        if self.active_room.as_deref() == Some(room) {
+            self.active_room = None;
+        }
+
+        true
+    }
+
+    pub fn mark_connection_request_start(&mut self) {
+        self.server_connection_status = ServerConnectionStatus::Connecting;
     }
 
-    pub fn tick_timer(&mut self) {
-        self.timer += 1;
+    pub fn mark_login_start(&mut self) {
+        self.login_status = LoginStatus::LoggingIn;
+    }
+
+    pub fn mark_reconnecting(&mut self, addr: String, attempt: u32) {
+        self.server_connection_status = ServerConnectionStatus::Reconnecting { addr, attempt };
+    }
+
+    /// The rooms the user has joined, without the conversations of direct messages
+    pub fn joined_rooms(&self) -> Vec<String> {
+        self.room_data_map
+            .values()
+            .filter(|room_data| room_data.has_joined && !room_data.is_direct_message)
+            .map(|room_data| room_data.name.clone())
+            .collect()
+    }
+
+    /// The address of the server, if connected to one
+    pub fn connected_addr(&self) -> Option<&str> {
+        match &self.server_connection_status {
+            ServerConnectionStatus::Connected { addr } => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Tells why a message can not be sent, when it is longer than the connected server accepts
+    pub fn message_length_warning(&self, content: &str) -> Option<String> {
+        let max_message_chars = self.max_message_chars?;
+        let chars = content.chars().count();
+
+        (chars > max_message_chars).then(|| {
+            format!(
+                "Message not sent, it is {} characters long while the server accepts at most {}",
+                chars, max_message_chars
+            )
+        })
+    }
+
+    /// Leaves the server which has rejected the protocol version of this client
+    pub fn mark_protocol_rejected(&mut self, addr: String, min_protocol_version: u16) {
+        self.server_connection_status = ServerConnectionStatus::Incompatible {
+            addr,
+            min_protocol_version,
+        };
+    }
+
+    pub fn dismiss_announcement(&mut self) {
+        self.announcement = None;
+    }
+
+    /// Goes back to the connect page, after the user has been told the server is incompatible
+    pub fn dismiss_incompatible_server(&mut self) {
+        if let ServerConnectionStatus::Incompatible { .. } = self.server_connection_status {
+            self.server_connection_status = ServerConnectionStatus::Uninitalized;
+        }
+    }
+
+    /// Processes the result of a connection request to change the state of the application
+    pub fn process_connection_request_result(&mut self, result: anyhow::Result<String>) {
+        self.server_shutdown = None;
+        // the round trip of the previous connection tells nothing about the new one
+        self.round_trip = None;
+        self.server_connection_status = match result {
+            Ok(addr) => ServerConnectionStatus::Connected { addr: addr.clone() },
+            Err(err) => ServerConnectionStatus::Errored {
+                err: err.to_string(),
+            },
+        }
+    }
+
+    /// Tries to set the active room as the given room. Returns the [RoomData] associated to the room.
+    pub fn try_set_active_room(&mut self, room: &str) -> Option<&RoomData> {
+        let is_entering = self.active_room.as_deref() != Some(room);
+        if is_entering {
+            // the divider of the room left is placed again when coming back
+            if let Some(left_room_data) = self
+                .active_room
+                .as_ref()
+                .and_then(|active_room| self.room_data_map.get_mut(active_room))
+            {
+                left_room_data.unread_marker = None;
+            }
+        }
+
+        let room_data = self.room_data_map.get_mut(room)?;
+        room_data.unread_count = 0;
+        room_data.unread_mention_count = 0;
+        if is_entering {
+            room_data.place_unread_marker();
+        }
+
+        self.active_room = Some(String::from(room));
+        self.recent_rooms.retain(|recent_room| recent_room != room);
+        self.recent_rooms.insert(0, String::from(room));
+        // the search is kept to the room it was started in
+        if self
+            .search
+            .as_ref()
+            .is_some_and(|search| search.room != room)
+        {
+            self.search = None;
+        }
+
+        Some(room_data)
+    }
+
+    /// Marks the messages of the room as read up to the latest one, once the user has left it
+    ///
+    /// Returns the room and the id of the latest message when it is to be marked as read on the server as well.
+    pub fn mark_room_read(&mut self, room: &str) -> Option<(String, u64)> {
+        let room_data = self.room_data_map.get_mut(room)?;
+        let newest_message_id = room_data
+            .newest_message_id()
+            .filter(|newest| room_data.last_read_id < Some(*newest))?;
+        room_data.last_read_id = Some(newest_message_id);
+
+        // the server keeps no history of the direct messages
+        (self.can_mark_read && room_data.has_joined && !room_data.is_direct_message)
+            .then(|| (String::from(room), newest_message_id))
+    }
+
+    /// Searches the messages of the active room for the query, replacing the current search
+    pub fn search_active_room(&mut self, query: String) {
+        let Some((room, room_data)) = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get_key_value(active_room))
+        else {
+            return;
+        };
+
+        self.search = Some(MessageSearch::new(room.clone(), query, &room_data.messages));
+    }
+
+    /// Moves the search to an older or a newer hit
+    pub fn move_search(&mut self, older: bool) {
+        if let Some(search) = self.search.as_mut() {
+            if older {
+                search.older();
+            } else {
+                search.newer();
+            }
+        }
+    }
+
+    /// Starts a search through the stored history of the active room, returns the room to search
+    ///
+    /// The direct messages are not stored by the server, so they can not be searched.
+    pub fn start_history_search(&mut self, query: String) -> Option<String> {
+        let room = self
+            .active_room
+            .clone()
+            .filter(|room| !self.is_direct_message(room))?;
+
+        self.history_search = Some(HistorySearch {
+            room: room.clone(),
+            query,
+            is_loading: true,
+            ..Default::default()
+        });
+
+        Some(room)
+    }
+
+    /// Marks the next page of the history search as requested, returns where it starts
+    ///
+    /// Returns None while a page is already requested or once every match is received.
+    pub fn load_more_history_search(&mut self) -> Option<(String, String, u64)> {
+        let search = self
+            .history_search
+            .as_mut()
+            .filter(|search| !search.is_loading)?;
+        let cursor = search.cursor?;
+        search.is_loading = true;
+
+        Some((search.room.clone(), search.query.clone(), cursor))
+    }
+
+    /// Starts browsing the public rooms from the first page, dropping the rooms listed before
+    pub fn start_room_directory(&mut self) {
+        self.room_directory = Some(RoomDirectory {
+            is_loading: true,
+            ..Default::default()
+        });
+    }
+
+    /// Marks the next page of the room directory as requested, returns the room it starts after
+    ///
+    /// Returns None while a page is already requested or once every room is received.
+    pub fn load_more_room_directory(&mut self) -> Option<String> {
+        let directory = self
+            .room_directory
+            .as_mut()
+            .filter(|directory| !directory.is_loading)?;
+        let cursor = directory.cursor.clone()?;
+        directory.is_loading = true;
+
+        Some(cursor)
+    }
+
+    /// Indexes the hits of the search again, as the messages of its room may have changed
+    fn refresh_search(&mut self) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+
+        match self.room_data_map.get(&search.room) {
+            Some(room_data) if self.active_room.as_ref() == Some(&search.room) => {
+                search.index(&room_data.messages)
+            }
+            _ => self.search = None,
+        }
+    }
+
+    /// Creates the conversation of direct messages with the given user if it does not exist yet,
+    /// returns its key in the room data map
+    pub fn open_direct_message(&mut self, user_id: &str) -> String {
+        let room = direct_message_room(user_id);
+
+        self.room_data_map
+            .entry(room.clone())
+            .or_insert_with(|| RoomData {
+                name: String::from(user_id),
+                description: format!("direct messages with @{}", user_id),
+                users: BTreeSet::from([self.user_id.clone(), String::from(user_id)]),
+                has_joined: true,
+                is_direct_message: true,
+                ..Default::default()
+            });
+
+        room
+    }
+
+    /// Adds the private room to the room list if it is not there yet, so it can be joined
+    pub fn add_private_room(&mut self, room: &event::RoomDetail) {
+        self.room_data_map
+            .entry(room.name.clone())
+            .or_insert_with(|| RoomData {
+                input_template: room.input_template.clone(),
+                is_private: true,
+                ..RoomData::new(
+                    room.name.clone(),
+                    room.description.clone(),
+                    room.history_visibility.clone(),
+                )
+            });
+    }
+
+    /// Removes the pending invitation to the room, returns it if there was one
+    pub fn take_invitation(&mut self, room: &str) -> Option<event::RoomInvitationBroadcastEvent> {
+        let index = self
+            .pending_invitations
+            .iter()
+            .position(|invitation| invitation.room.name == room)?;
+
+        Some(self.pending_invitations.remove(index))
+    }
+
+    /// Accepts the pending invitation to the room by adding it to the room list,
+    /// returns false if the user was not invited to the room
+    pub fn accept_invitation(&mut self, room: &str) -> bool {
+        let Some(invitation) = self.take_invitation(room) else {
+            return false;
+        };
+
+        self.add_private_room(&invitation.room);
+
+        true
+    }
+
+    /// Is the given room a conversation of direct messages
+    /// The id and content of the last message the user has sent to the active room, direct messages can not be changed
+    pub fn last_own_message(&self) -> Option<(u64, String)> {
+        let room_data = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get(active_room))
+            .filter(|room_data| !room_data.is_direct_message)?;
+
+        room_data.messages.iter().find_map(|mbi| match mbi {
+            MessageBoxItem::Message {
+                id,
+                user_id,
+                content,
+                ..
+            } if *user_id == self.user_id => Some((*id, content.clone())),
+            _ => None,
+        })
+    }
+
+    pub fn is_direct_message(&self, room: &str) -> bool {
+        self.room_data_map
+            .get(room)
+            .map(|room_data| room_data.is_direct_message)
+            .unwrap_or(false)
+    }
+
+    /// Scrolls the message list of the active room by the given number of items,
+    /// back to the older messages when positive and towards the latest ones when negative
+    pub fn scroll_active_room(&mut self, items: isize) {
+        let Some(room_data) = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get_mut(active_room))
+        else {
+            return;
+        };
+
+        room_data.scroll_offset = room_data
+            .scroll_offset
+            .saturating_add_signed(items)
+            .min(room_data.messages.len());
+    }
+
+    /// Marks the older messages of the active room as requested once it is scrolled back to the oldest one kept,
+    /// returns the room and the id of the message to fetch the older messages before
+    pub fn start_older_messages_fetch(&mut self) -> Option<(String, u64)> {
+        if !self.can_fetch_older_messages {
+            return None;
+        }
+
+        let active_room = self.active_room.as_ref()?;
+        let room_data = self
+            .room_data_map
+            .get_mut(active_room)
+            .filter(|room_data| {
+                room_data.has_joined
+                    && !room_data.is_direct_message
+                    && room_data.has_older_messages
+                    && !room_data.is_fetching_older
+                    && !room_data.is_fetching_history
+                    && room_data.scroll_offset >= room_data.messages.len()
+                    && room_data.messages.len() < MAX_PAGED_MESSAGES_PER_ROOM
+            })?;
+        let before_message_id = room_data.oldest_message_id()?;
+        room_data.is_fetching_older = true;
+
+        Some((active_room.clone(), before_message_id))
+    }
+
+    /// Sets the message the next message sent to the active room replies to, or stops replying
+    pub fn reply_in_active_room(&mut self, reply_to: Option<u64>) {
+        if let Some(room_data) = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get_mut(active_room))
+        {
+            room_data.replying_to = reply_to;
+        }
+    }
+
+    /// The role of the user in the space, if they are a member
+    pub fn role_in_space(&self, space: &str) -> Option<event::SpaceRole> {
+        self.space_data_map
+            .get(space)
+            .and_then(|space_data| space_data.members.get(&self.user_id))
+            .copied()
+    }
+
+    /// Is the message a mention of the user, or does it contain one of their highlight words
+    pub fn is_highlighted(&self, user_id: &str, content: &str) -> bool {
+        user_id != self.user_id
+            && highlights::is_highlighted(content, &self.user_id, &self.highlight_words)
+    }
+
+    /// Adds a highlight word, returns false if it is already added
+    pub fn add_highlight_word(&mut self, word: &str) -> bool {
+        if self
+            .highlight_words
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(word))
+        {
+            return false;
+        }
+
+        self.highlight_words.push(String::from(word));
+
+        true
+    }
+
+    /// Removes a highlight word, returns false if it was not added
+    pub fn remove_highlight_word(&mut self, word: &str) -> bool {
+        let count = self.highlight_words.len();
+        self.highlight_words
+            .retain(|existing| !existing.eq_ignore_ascii_case(word));
+
+        self.highlight_words.len() != count
+    }
+
+    /// Is the room listed ahead of the others
+    pub fn is_favorite_room(&self, room: &str) -> bool {
+        self.favorite_rooms.iter().any(|favorite| favorite == room)
+    }
+
+    /// Marks the room as a favorite, or unmarks it if it is one already, returns whether it is a favorite now
+    pub fn toggle_favorite_room(&mut self, room: &str) -> bool {
+        if self.is_favorite_room(room) {
+            self.favorite_rooms.retain(|favorite| favorite != room);
+            return false;
+        }
+
+        self.favorite_rooms.push(String::from(room));
+
+        true
+    }
+
+    /// Switches the room list between the two orders, returns the order switched to
+    pub fn toggle_room_sort(&mut self) -> RoomSort {
+        self.room_sort = match self.room_sort {
+            RoomSort::Activity => RoomSort::Alphabetical,
+            RoomSort::Alphabetical => RoomSort::Activity,
+        };
+
+        self.room_sort
+    }
+
+    /// Are the messages of the user hidden, the user themselves is never ignored
+    pub fn is_ignored(&self, user_id: &str) -> bool {
+        user_id != self.user_id && self.ignored_users.iter().any(|ignored| ignored == user_id)
+    }
+
+    /// Ignores the user, returns false if they are already ignored
+    pub fn ignore_user(&mut self, user_id: &str) -> bool {
+        if self.ignored_users.iter().any(|ignored| ignored == user_id) {
+            return false;
+        }
+
+        self.ignored_users.push(String::from(user_id));
+
+        true
+    }
+
+    /// Stops ignoring the user, returns false if they were not ignored
+    pub fn unignore_user(&mut self, user_id: &str) -> bool {
+        let count = self.ignored_users.len();
+        self.ignored_users.retain(|ignored| ignored != user_id);
+
+        self.ignored_users.len() != count
+    }
+
+    /// Keeps the text of the message input as the draft of the room, empty text discards the draft
+    pub fn save_draft(&mut self, room: &str, content: String) {
+        if let Some(room_data) = self.room_data_map.get_mut(room) {
+            room_data.draft = Some(content).filter(|content| !content.is_empty());
+        }
+    }
+
+    pub fn toggle_input_templates(&mut self) {
+        self.use_input_templates = !self.use_input_templates;
+    }
+
+    /// Drops the previews of the images the active room does not reference, they are fetched again once shown
+    fn flush_image_previews(&mut self) {
+        let shown_keys = self
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.room_data_map.get(active_room))
+            .map(|room_data| {
+                room_data
+                    .messages
+                    .asc_iter()
+                    .filter_map(image_source_of)
+                    .map(|source| source.key())
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+
+        self.image_previews
+            .retain(|key, _| shown_keys.contains(key));
+    }
+
+    /// Runs a task which has become due in the scheduler
+    pub fn run_scheduled_task(&mut self, task: &ScheduledTask) {
+        match task {
+            ScheduledTask::ExpireToast => self.toast = None,
+            ScheduledTask::ExpireRateLimitWarning => self.rate_limit_warning = None,
+            ScheduledTask::FlushImagePreviews => self.flush_image_previews(),
+            // run by the state store, since they need the connection to the server, a toast or the terminal
+            ScheduledTask::RestoreTitle
+            | ScheduledTask::ExpireRoomJoin { .. }
+            | ScheduledTask::Reconnect
+            | ScheduledTask::DetectStalledConnection => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_event(room: &str, user_id: &str, content: &str) -> event::Event {
+        event::Event::UserMessage(event::UserMessageBroadcastEvent {
+            room: room.into(),
+            id: 0,
+            user_id: user_id.into(),
+            content: content.into(),
+            timestamp: 0,
+            reply_to: None,
+        })
+    }
+
+    #[test]
+    fn test_message_in_inactive_room_is_unread() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &[])
+            .with_joined_room("rust", &[])
+            .with_active_room("general");
+
+        state.handle_server_event(&message_event("rust", "alice", "hi"));
+        state.handle_server_event(&message_event("rust", "bob", "hello"));
+        state.handle_server_event(&message_event("general", "bob", "hey"));
+
+        assert_eq!(state.room_data_map["rust"].unread_count, 2);
+        assert_eq!(state.room_data_map["general"].unread_count, 0);
+        assert_eq!(state.room_data_map["rust"].messages.len(), 2);
+
+        state.try_set_active_room("rust");
+
+        assert_eq!(state.room_data_map["rust"].unread_count, 0);
+    }
+
+    #[test]
+    fn test_opened_rooms_are_listed_most_recent_first() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", ""), ("random", "")])
+            .with_active_room("general")
+            .with_active_room("rust")
+            .with_active_room("random");
+
+        state.try_set_active_room("general");
+
+        assert_eq!(state.recent_rooms, vec!["general", "random", "rust"]);
+    }
+
+    #[test]
+    fn test_mentions_and_highlight_words_are_counted() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_joined_room("rust", &[])
+            .with_active_room("general");
+        state.add_highlight_word("tokio");
+
+        state.handle_server_event(&message_event("rust", "alice", "anyone using Tokio?"));
+
+        state.handle_server_event(&message_event("rust", "bob", "ping @me"));
+        state.handle_server_event(&message_event("rust", "bob", "nevermind"));
+
+        assert_eq!(state.room_data_map["rust"].unread_mention_count, 2);
+        assert_eq!(state.room_data_map["rust"].unread_count, 3);
+
+        state.try_set_active_room("rust");
+
+        assert_eq!(state.room_data_map["rust"].unread_mention_count, 0);
+    }
+
+    #[test]
+    fn test_removal_from_space_leaves_its_rooms() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &[])
+            .with_joined_room("rust", &["alice"])
+            .with_joined_space("engineering", &["rust"]);
+
+        state.handle_server_event(&event::Event::SpaceMembership(
+            event::SpaceMembershipBroadcastEvent {
+                space: "engineering".into(),
+                user_id: state.user_id.clone(),
+                role: None,
+            },
+        ));
+
+        assert!(!state.space_data_map["engineering"].has_joined);
+        assert!(!state.room_data_map["rust"].has_joined);
+        assert!(state.room_data_map["general"].has_joined);
+        assert_eq!(state.role_in_space("engineering"), None);
+    }
+
+    #[test]
+    fn test_room_users_follow_participation_events() {
+        let mut state = State::test_with_rooms(&[("general", "")]).with_user_id("me");
+
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "general".into(),
+                users: vec!["me".into(), "carol".into(), "alice".into()],
+                role: event::RoomRole::Moderator,
+            },
+        ));
+        assert_eq!(
+            state.room_data_map["general"].role,
+            event::RoomRole::Moderator
+        );
+
+        for (user_id, status) in [
+            ("bob", event::RoomParticipationStatus::Joined),
+            ("carol", event::RoomParticipationStatus::Left),
+        ] {
+            state.handle_server_event(&event::Event::RoomParticipation(
+                event::RoomParticipationBroacastEvent {
+                    room: "general".into(),
+                    user_id: user_id.into(),
+                    status,
+                },
+            ));
+        }
+
+        assert_eq!(
+            state.room_data_map["general"]
+                .users
+                .iter()
+                .collect::<Vec<_>>(),
+            vec!["alice", "bob", "me"]
+        );
+    }
+
+    #[test]
+    fn test_left_room_is_no_longer_active() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+
+        assert!(state.leave_room("general"));
+        assert!(!state.room_data_map["general"].has_joined);
+        assert!(state.room_data_map["general"].users.is_empty());
+        assert_eq!(state.active_room, None);
+        assert!(!state.leave_room("rust"));
+    }
+
+    #[test]
+    fn test_room_is_left_once_confirmed() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+
+        assert!(!state.prompt_leave_room("rust"));
+        assert_eq!(state.leave_prompt, None);
+
+        assert!(state.prompt_leave_room("general"));
+        assert!(state.room_data_map["general"].has_joined);
+        assert_eq!(state.confirm_leave_room(), Some(String::from("general")));
+        assert!(!state.room_data_map["general"].has_joined);
+        assert_eq!(state.leave_prompt, None);
+
+        // the room is still listed, to be joined again
+        assert!(state.room_data_map.contains_key("general"));
+        assert_eq!(state.confirm_leave_room(), None);
+    }
+
+    #[test]
+    fn test_rooms_are_created_and_deleted() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::RoomCreated(
+            event::RoomCreatedBroadcastEvent {
+                room: event::RoomDetail {
+                    name: String::from("rust"),
+                    description: String::from("all things rust"),
+                    history_visibility: event::HistoryVisibility::default(),
+                    input_template: None,
+                },
+                created_by: String::from("alice"),
+            },
+        ));
+        assert_eq!(state.room_data_map["rust"].description, "all things rust");
+        assert!(!state.room_data_map["rust"].has_joined);
+
+        state.handle_server_event(&event::Event::RoomDeleted(
+            event::RoomDeletedBroadcastEvent {
+                room: String::from("general"),
+            },
+        ));
+        assert!(!state.room_data_map.contains_key("general"));
+        assert_eq!(state.active_room, None);
+    }
+
+    #[test]
+    fn test_the_events_of_a_deleted_room_arriving_late_are_ignored() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::RoomDeleted(
+            event::RoomDeletedBroadcastEvent {
+                room: String::from("general"),
+            },
+        ));
+        state.handle_server_event(&event::Event::UserMessage(
+            event::UserMessageBroadcastEvent {
+                room: String::from("general"),
+                id: 0,
+                user_id: String::from("alice"),
+                content: String::from("helo"),
+                timestamp: 0,
+                reply_to: None,
+            },
+        ));
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: String::from("general"),
+                users: vec![String::from("alice")],
+                role: event::RoomRole::Member,
+            },
+        ));
+
+        assert!(!state.room_data_map.contains_key("general"));
+    }
+
+    #[test]
+    fn test_shutdown_notice_is_kept_until_reconnected() {
+        let mut state = State::test_with_rooms(&[("general", "")]);
+        let notice = event::ServerShuttingDownEvent {
+            reason: String::from("maintenance"),
+            grace_seconds: 10,
+        };
+
+        state.handle_server_event(&event::Event::ServerShuttingDown(notice.clone()));
+        assert_eq!(state.server_shutdown, Some(notice));
+
+        state.mark_reconnecting(String::from("localhost:8080"), 1);
+        assert!(state.server_shutdown.is_some());
+
+        state.process_connection_request_result(Ok(String::from("localhost:8080")));
+        assert_eq!(state.server_shutdown, None);
+    }
+
+    #[test]
+    fn test_announcement_is_kept_until_dismissed() {
+        let mut state = State::test_with_rooms(&[("general", "")]);
+        let announcement = |text: &str| event::AnnouncementEvent {
+            text: String::from(text),
+        };
+
+        state.handle_server_event(&event::Event::Announcement(announcement(
+            "maintenance at noon",
+        )));
+        state.handle_server_event(&event::Event::Announcement(announcement(
+            "maintenance at one",
+        )));
+        assert_eq!(state.announcement, Some(announcement("maintenance at one")));
+
+        state.mark_reconnecting(String::from("localhost:8080"), 1);
+        state.process_connection_request_result(Ok(String::from("localhost:8080")));
+        assert!(state.announcement.is_some());
+
+        state.dismiss_announcement();
+        assert_eq!(state.announcement, None);
+    }
+
+    #[test]
+    fn test_topic_change_updates_description_and_notifies() {
+        let mut state =
+            State::test_with_rooms(&[("general", "chit chat")]).with_joined_room("general", &[]);
+
+        state.handle_server_event(&event::Event::RoomTopicChanged(
+            event::RoomTopicChangedBroadcastEvent {
+                room: String::from("general"),
+                topic: String::from("release day"),
+                changed_by: String::from("alice"),
+            },
+        ));
+
+        let room_data = &state.room_data_map["general"];
+        assert_eq!(room_data.description, "release day");
+        assert!(matches!(
+            room_data.messages.iter().next(),
+            Some(MessageBoxItem::Notification(line)) if line == r#"@alice changed the topic to "release day""#
+        ));
+    }
+
+    #[test]
+    fn test_kicked_user_leaves_the_room() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_user_id("bob")
+            .with_joined_room("general", &["alice", "bob"])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::UserModerated(
+            event::UserModeratedBroadcastEvent {
+                room: String::from("general"),
+                user_id: String::from("bob"),
+                action: event::ModerationAction::Kicked,
+                moderator_id: String::from("alice"),
+            },
+        ));
+
+        let room_data = &state.room_data_map["general"];
+        assert!(!room_data.has_joined);
+        assert_eq!(state.active_room, None);
+        assert!(matches!(
+            room_data.messages.iter().next(),
+            Some(MessageBoxItem::Notification(line)) if line == "@bob was kicked by @alice"
+        ));
+    }
+
+    #[test]
+    fn test_pending_join_is_confirmed_or_rolled_back() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")]);
+
+        state.try_set_active_room("general");
+        state.mark_room_join_pending("general");
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "general".into(),
+                users: vec![state.user_id.clone()],
+                role: event::RoomRole::Member,
+            },
+        ));
+
+        assert!(state.room_data_map["general"].has_joined);
+        assert!(!state.roll_back_room_join("general"));
+
+        state.try_set_active_room("rust");
+        state.mark_room_join_pending("rust");
+
+        assert!(state.roll_back_room_join("rust"));
+        assert!(!state.room_data_map["rust"].has_joined);
+        assert_eq!(state.active_room, None);
+    }
+
+    #[test]
+    fn test_history_is_merged_ahead_of_notifications() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_message("general", "alice", "live message");
+        state
+            .room_data_map
+            .get_mut("general")
+            .unwrap()
+            .messages
+            .push(MessageBoxItem::Notification(String::from(
+                "bob has joined the room",
+            )));
+
+        state.handle_server_event(&event::Event::RoomHistory(event::RoomHistoryReplyEvent {
+            room: "general".into(),
+            messages: vec![event::HistoryMessage {
+                id: 0,
+                user_id: "alice".into(),
+                content: "live message".into(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            around: None,
+            last_read: None,
+        }));
+
+        let messages = state.room_data_map["general"]
+            .messages
+            .asc_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                MessageBoxItem::Message {
+                    timestamp: Some(1),
+                    ..
+                },
+                MessageBoxItem::Notification(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_messages_are_edited_deleted_and_reacted_to() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_active_room("general")
+            .with_message("general", "me", "first")
+            .with_message("general", "me", "typo")
+            .with_message("general", "alice", "reply");
+
+        assert_eq!(state.last_own_message(), Some((1, String::from("typo"))));
+
+        state.handle_server_event(&event::Event::MessageEdited(
+            event::MessageEditedBroadcastEvent {
+                room: "general".into(),
+                id: 1,
+                content: "fixed".into(),
+            },
+        ));
+
+        assert!(matches!(
+            state.room_data_map["general"].messages.iter().nth(1),
+            Some(MessageBoxItem::Message { content, is_edited: true, .. }) if content == "fixed"
+        ));
+
+        state.handle_server_event(&event::Event::MessageDeleted(
+            event::MessageDeletedBroadcastEvent {
+                room: "general".into(),
+                id: 1,
+            },
+        ));
+
+        assert_eq!(state.room_data_map["general"].messages.len(), 2);
+        assert_eq!(state.last_own_message(), Some((0, String::from("first"))));
+
+        let reactions = vec![event::Reaction {
+            emoji: "👍".into(),
+            count: 2,
+        }];
+        state.handle_server_event(&event::Event::MessageReactions(
+            event::MessageReactionsBroadcastEvent {
+                room: "general".into(),
+                id: 0,
+                reactions: reactions.clone(),
+            },
+        ));
+
+        assert!(matches!(
+            state.room_data_map["general"].messages.iter().last(),
+            Some(MessageBoxItem::Message { reactions: actual, .. }) if *actual == reactions
+        ));
+    }
+
+    #[test]
+    fn test_truncated_history_drops_the_older_messages() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_message("general", "alice", "pruned")
+            .with_message("general", "alice", "kept")
+            .with_message("general", "bob", "newest");
+
+        state.handle_server_event(&event::Event::HistoryTruncated(
+            event::HistoryTruncatedBroadcastEvent {
+                room: "general".into(),
+                before: 1,
+            },
+        ));
+
+        let contents = state.room_data_map["general"]
+            .messages
+            .asc_iter()
+            .filter_map(|mbi| match mbi {
+                MessageBoxItem::Message { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["kept", "newest"]);
+    }
+
+    #[test]
+    fn test_messages_newer_than_history_are_kept() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_message("general", "alice", "replayed message")
+            .with_message("general", "alice", "newer message");
+
+        state.handle_server_event(&event::Event::RoomHistory(event::RoomHistoryReplyEvent {
+            room: "general".into(),
+            messages: vec![event::HistoryMessage {
+                id: 0,
+                user_id: "alice".into(),
+                content: "replayed message".into(),
+                timestamp: 1,
+                reply_to: None,
+                is_edited: false,
+                reactions: vec![],
+            }],
+            around: None,
+            last_read: None,
+        }));
+
+        let messages = state.room_data_map["general"]
+            .messages
+            .asc_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                MessageBoxItem::Message {
+                    id: 0,
+                    timestamp: Some(1),
+                    ..
+                },
+                MessageBoxItem::Message {
+                    id: 1,
+                    timestamp: None,
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_scrolled_back_view_is_kept_on_new_messages() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general")
+            .with_message("general", "alice", "first")
+            .with_message("general", "alice", "second");
+
+        state.scroll_active_room(10);
+        assert_eq!(state.room_data_map["general"].scroll_offset, 2);
+
+        state.handle_server_event(&message_event("general", "bob", "third"));
+        assert_eq!(state.room_data_map["general"].scroll_offset, 3);
+
+        state.scroll_active_room(isize::MIN);
+        assert_eq!(state.room_data_map["general"].scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_direct_message_opens_conversation() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::DirectMessage(
+            event::DirectMessageBroadcastEvent {
+                from_user_id: "alice".into(),
+                to_user_id: "me".into(),
+                content: "psst".into(),
+                timestamp: 1,
+            },
+        ));
+
+        let room_data = &state.room_data_map[&direct_message_room("alice")];
+        assert!(room_data.is_direct_message);
+        assert_eq!(room_data.unread_mention_count, 1);
+        assert_eq!(room_data.messages.len(), 1);
+        assert!(state.is_direct_message("@alice"));
+        assert!(!state.is_direct_message("general"));
+    }
+
+    #[test]
+    fn test_favorite_rooms_and_room_sort_are_toggled() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")]);
+
+        assert!(state.toggle_favorite_room("rust"));
+        assert!(state.is_favorite_room("rust"));
+        assert!(!state.toggle_favorite_room("rust"));
+        assert!(state.favorite_rooms.is_empty());
+
+        assert_eq!(state.toggle_room_sort(), RoomSort::Alphabetical);
+        assert_eq!(state.toggle_room_sort(), RoomSort::Activity);
+    }
+
+    #[test]
+    fn test_ignored_users_are_not_counted_nor_messaged() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_joined_room("rust", &[])
+            .with_active_room("general");
+        assert!(state.ignore_user("spammer"));
+        assert!(!state.ignore_user("spammer"));
+
+        let direct_message = event::Event::DirectMessage(event::DirectMessageBroadcastEvent {
+            from_user_id: "spammer".into(),
+            to_user_id: "me".into(),
+            content: "buy now".into(),
+            timestamp: 1,
+        });
+        state.handle_server_event(&message_event("rust", "spammer", "ping @me"));
+        state.handle_server_event(&direct_message);
+
+        assert_eq!(state.room_data_map["rust"].unread_count, 0);
+        assert_eq!(state.room_data_map["rust"].messages.len(), 1);
+        assert!(!state
+            .room_data_map
+            .contains_key(&direct_message_room("spammer")));
+        assert_eq!(state.alerting_room(&direct_message), None);
+
+        assert!(state.unignore_user("spammer"));
+        state.handle_server_event(&direct_message);
+
+        assert!(state
+            .room_data_map
+            .contains_key(&direct_message_room("spammer")));
+    }
+
+    #[test]
+    fn test_accepted_invitation_is_dropped_when_join_fails() {
+        let mut state = State::test_with_rooms(&[("general", "")]);
+
+        state.handle_server_event(&event::Event::RoomInvitation(
+            event::RoomInvitationBroadcastEvent {
+                room: event::RoomDetail {
+                    name: "staff".into(),
+                    description: "".into(),
+                    history_visibility: event::HistoryVisibility::default(),
+                    input_template: None,
+                },
+                from_user_id: "alice".into(),
+            },
+        ));
+
+        assert_eq!(state.pending_invitations.len(), 1);
+        assert!(state.accept_invitation("staff"));
+        assert!(state.pending_invitations.is_empty());
+        assert!(state.room_data_map["staff"].is_private);
+
+        state.try_set_active_room("staff");
+        state.mark_room_join_pending("staff");
+
+        assert!(state.roll_back_room_join("staff"));
+        assert!(!state.room_data_map.contains_key("staff"));
+    }
+
+    #[test]
+    fn test_command_error_is_shown_in_active_room() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::CommandError(event::CommandErrorEvent {
+            request_id: None,
+            code: event::ErrorCode::MessageTooLong,
+            message: "messages are at most 2000 characters long".into(),
+        }));
+
+        assert!(matches!(
+            state.room_data_map["general"].messages.iter().last(),
+            Some(MessageBoxItem::Error(line)) if line == "messages are at most 2000 characters long"
+        ));
+    }
+
+    #[test]
+    fn test_mentions_and_direct_messages_are_notified_unless_looked_at() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("random", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_joined_room("random", &[])
+            .with_active_room("general");
+        let direct_message = event::Event::DirectMessage(event::DirectMessageBroadcastEvent {
+            from_user_id: "alice".into(),
+            to_user_id: "me".into(),
+            content: "psst".into(),
+            timestamp: 1,
+        });
+
+        assert_eq!(
+            state.notification_for(&message_event("random", "alice", "hey @me")),
+            Some(Notification {
+                summary: "@alice in #random".into(),
+                body: "hey @me".into(),
+            })
+        );
+        assert!(state.notification_for(&direct_message).is_some());
+        assert_eq!(
+            state.notification_for(&message_event("random", "alice", "hey")),
+            None
+        );
+        assert_eq!(
+            state.notification_for(&message_event("general", "alice", "hey @me")),
+            None
+        );
+
+        state.is_terminal_focused = false;
+        assert!(state
+            .notification_for(&message_event("general", "alice", "hey @me"))
+            .is_some());
+
+        state.is_do_not_disturb = true;
+        assert_eq!(state.notification_for(&direct_message), None);
+    }
+
+    #[test]
+    fn test_messages_of_inactive_rooms_alert() {
+        let state = State::test_with_rooms(&[("general", ""), ("random", "")])
+            .with_user_id("me")
+            .with_joined_room("general", &[])
+            .with_joined_room("random", &[])
+            .with_active_room("general");
+
+        assert_eq!(
+            state.alerting_room(&message_event("random", "alice", "hey")),
+            Some(String::from("#random"))
+        );
+        assert_eq!(
+            state.alerting_room(&message_event("general", "alice", "hey")),
+            None
+        );
+        assert_eq!(
+            state.alerting_room(&message_event("random", "me", "hey")),
+            None
+        );
+        assert_eq!(
+            state.alerting_room(&event::Event::DirectMessage(
+                event::DirectMessageBroadcastEvent {
+                    from_user_id: "alice".into(),
+                    to_user_id: "me".into(),
+                    content: "psst".into(),
+                    timestamp: 1,
+                }
+            )),
+            Some(String::from("@alice"))
+        );
+    }
+
+    #[test]
+    fn test_search_follows_the_messages_of_its_room() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("random", "")])
+            .with_joined_room("general", &[])
+            .with_joined_room("random", &[])
+            .with_active_room("general")
+            .with_message("general", "alice", "Rust is fun")
+            .with_message("general", "bob", "so is Go");
+
+        state.search_active_room(String::from("rust"));
+        assert_eq!(
+            state.search.as_ref().map(|search| search.hits.clone()),
+            Some(vec![0])
+        );
+
+        state.handle_server_event(&message_event("general", "bob", "rustaceans unite"));
+        state.handle_server_event(&message_event("random", "bob", "rust again"));
+        assert_eq!(
+            state.search.as_ref().map(|search| search.hits.clone()),
+            Some(vec![0, 2])
+        );
+
+        state.try_set_active_room("random");
+        assert_eq!(state.search, None);
+    }
+
+    #[test]
+    fn test_history_search_pages_in_the_matches_of_its_query() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+        let results = |query: &str, id: u64, cursor: Option<u64>| {
+            event::Event::SearchResults(event::SearchResultsReplyEvent {
+                room: String::from("general"),
+                query: String::from(query),
+                messages: vec![event::HistoryMessage {
+                    id,
+                    user_id: String::from("alice"),
+                    content: String::from("release notes"),
+                    timestamp: id * 1000,
+                    reply_to: None,
+                    is_edited: false,
+                    reactions: vec![],
+                }],
+                cursor,
+            })
+        };
+
+        assert_eq!(
+            state.start_history_search(String::from("release")),
+            Some(String::from("general"))
+        );
+        // a page requested before the search was replaced is dropped
+        state.handle_server_event(&results("notes", 7, None));
+        assert_eq!(state.load_more_history_search(), None);
+
+        state.handle_server_event(&results("release", 9, Some(9)));
+        assert_eq!(
+            state.load_more_history_search(),
+            Some((String::from("general"), String::from("release"), 9))
+        );
+        assert_eq!(state.load_more_history_search(), None);
+
+        state.handle_server_event(&results("release", 4, None));
+        let search = state.history_search.as_ref().unwrap();
+        assert_eq!(
+            search
+                .results
+                .iter()
+                .map(|message| message.id)
+                .collect::<Vec<_>>(),
+            vec![9, 4]
+        );
+        assert!(!search.is_loading);
+        assert_eq!(state.load_more_history_search(), None);
+    }
+
+    #[test]
+    fn test_room_directory_pages_in_the_public_rooms() {
+        let mut state = State::default();
+        let page = |after: Option<&str>, room: &str, cursor: Option<&str>| {
+            event::Event::RoomDirectory(event::RoomDirectoryReplyEvent {
+                after: after.map(String::from),
+                rooms: vec![event::DirectoryRoom {
+                    name: String::from(room),
+                    description: String::new(),
+                    member_count: 1,
+                    last_activity: None,
+                }],
+                cursor: cursor.map(String::from),
+            })
+        };
+
+        // the pages are dropped until the directory is browsed
+        state.handle_server_event(&page(None, "general", None));
+        assert_eq!(state.room_directory, None);
+
+        state.start_room_directory();
+        assert_eq!(state.load_more_room_directory(), None);
+        state.handle_server_event(&page(None, "general", Some("general")));
+        assert_eq!(
+            state.load_more_room_directory(),
+            Some(String::from("general"))
+        );
+        // a page of a previous browse is dropped
+        state.handle_server_event(&page(None, "general", Some("general")));
+        state.handle_server_event(&page(Some("general"), "rust", None));
+
+        let directory = state.room_directory.as_ref().unwrap();
+        assert_eq!(
+            directory
+                .rooms
+                .iter()
+                .map(|room| room.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["general", "rust"]
+        );
+        assert!(!directory.is_loading);
+        assert_eq!(state.load_more_room_directory(), None);
+    }
+
+    #[test]
+    fn test_older_messages_are_fetched_once_scrolled_back_to_the_oldest_one() {
+        let mut state = State::test_with_rooms(&[("general", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+        state.can_fetch_older_messages = true;
+        let history_message = |id: u64| event::HistoryMessage {
+            id,
+            user_id: String::from("alice"),
+            content: format!("message {}", id),
+            timestamp: id * 1000,
+            reply_to: None,
+            is_edited: false,
+            reactions: vec![],
+        };
+        state.handle_server_event(&event::Event::RoomHistory(event::RoomHistoryReplyEvent {
+            room: String::from("general"),
+            messages: (100..200).map(history_message).collect(),
+            around: None,
+            last_read: None,
+        }));
+
+        state.scroll_active_room(10);
+        assert_eq!(state.start_older_messages_fetch(), None);
+
+        state.scroll_active_room(100);
+        assert_eq!(
+            state.start_older_messages_fetch(),
+            Some((String::from("general"), 100))
+        );
+        // a single page is requested at a time
+        assert_eq!(state.start_older_messages_fetch(), None);
+
+        state.handle_server_event(&event::Event::OlderMessages(
+            event::OlderMessagesReplyEvent {
+                room: String::from("general"),
+                before_message_id: 100,
+                messages: (50..100).map(history_message).collect(),
+                has_more: false,
+            },
+        ));
+        let room_data = state.room_data_map.get("general").unwrap();
+        assert_eq!(room_data.messages.len(), 150);
+        assert_eq!(room_data.oldest_message_id(), Some(50));
+        // the view is counted from the latest messages, so it stays in place
+        assert_eq!(room_data.scroll_offset, 100);
+
+        state.scroll_active_room(100);
+        assert_eq!(state.start_older_messages_fetch(), None);
+    }
+
+    #[test]
+    fn test_new_messages_are_divided_when_coming_back_to_the_room() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("random", "")])
+            .with_joined_room("general", &[])
+            .with_joined_room("random", &[])
+            .with_active_room("general")
+            .with_message("general", "alice", "hi")
+            .with_message("general", "bob", "hello");
+        state.can_mark_read = true;
+
+        state.try_set_active_room("random");
+        assert_eq!(
+            state.mark_room_read("general"),
+            Some((String::from("general"), 1))
+        );
+        // nothing new was read since
+        assert_eq!(state.mark_room_read("general"), None);
+
+        state.handle_server_event(&event::Event::UserMessage(
+            event::UserMessageBroadcastEvent {
+                room: String::from("general"),
+                id: 2,
+                user_id: String::from("alice"),
+                content: String::from("anyone?"),
+                timestamp: 0,
+                reply_to: None,
+            },
+        ));
+        state.try_set_active_room("general");
+        assert_eq!(state.room_data_map["general"].unread_marker, Some(1));
+
+        state.try_set_active_room("random");
+        assert_eq!(state.room_data_map["general"].unread_marker, None);
+        assert_eq!(
+            state.mark_room_read("general"),
+            Some((String::from("general"), 2))
+        );
+    }
+
+    #[test]
+    fn test_shared_files_are_kept_with_the_messages() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("random", "")])
+            .with_joined_room("general", &[])
+            .with_joined_room("random", &[])
+            .with_active_room("random")
+            .with_message("general", "alice", "hi");
+
+        state.handle_server_event(&event::Event::FileShared(event::FileSharedBroadcastEvent {
+            room: String::from("general"),
+            file_id: String::from("f1"),
+            user_id: String::from("alice"),
+            name: String::from("notes.txt"),
+            size: 3,
+            checksum: String::from("abc"),
+            timestamp: 1,
+        }));
+        assert_eq!(state.room_data_map["general"].unread_count, 1);
+
+        // the shared files are not in the history, they are kept like the notifications
+        state.handle_server_event(&event::Event::RoomHistory(event::RoomHistoryReplyEvent {
+            room: String::from("general"),
+            messages: vec![],
+            around: None,
+            last_read: None,
+        }));
+        assert!(matches!(
+            state.room_data_map["general"].messages.iter().next(),
+            Some(MessageBoxItem::File { id, name, .. }) if id == "f1" && name == "notes.txt"
+        ));
+    }
+
+    #[test]
+    fn test_rate_limit_warning_rounds_up_and_expires() {
+        let mut state = State::default();
+
+        state.show_rate_limit_warning(Duration::from_millis(1200));
+        assert_eq!(
+            state.rate_limit_warning.as_deref(),
+            Some("slow down, retry in 2s")
+        );
+
+        state.run_scheduled_task(&ScheduledTask::ExpireRateLimitWarning);
+        assert_eq!(state.rate_limit_warning, None);
+    }
+
+    #[test]
+    fn test_the_previews_the_active_room_does_not_show_are_flushed() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+        state.handle_server_event(&event::Event::UserMessage(
+            event::UserMessageBroadcastEvent {
+                room: String::from("general"),
+                id: 0,
+                user_id: String::from("alice"),
+                content: String::from("look https://example.com/cat.png"),
+                timestamp: 0,
+                reply_to: None,
+            },
+        ));
+        for url in ["https://example.com/cat.png", "https://example.com/dog.png"] {
+            state
+                .image_previews
+                .insert(String::from(url), ImagePreview::Loading);
+        }
+
+        state.run_scheduled_task(&ScheduledTask::FlushImagePreviews);
+
+        assert_eq!(
+            state.image_previews.keys().collect::<Vec<_>>(),
+            vec!["https://example.com/cat.png"]
+        );
+    }
+
+    #[test]
+    fn test_welcome_limits_the_message_length() {
+        let mut state = State::default();
+        assert_eq!(state.message_length_warning(&"a".repeat(5000)), None);
+
+        state.handle_server_event(&event::Event::Welcome(event::WelcomeReplyEvent {
+            protocol_version: 4,
+            max_message_chars: 3,
+            features: vec![],
+            heartbeat_interval: None,
+        }));
+
+        assert_eq!(state.message_length_warning("abc"), None);
+        assert_eq!(
+            state.message_length_warning("abcd").as_deref(),
+            Some("Message not sent, it is 4 characters long while the server accepts at most 3")
+        );
+    }
+
+    #[test]
+    fn test_welcome_sets_the_heartbeat_interval() {
+        let mut state = State::default();
+
+        state.handle_server_event(&event::Event::Welcome(event::WelcomeReplyEvent {
+            protocol_version: 5,
+            max_message_chars: 2000,
+            features: vec![String::from(comms::protocol::features::HEARTBEAT)],
+            heartbeat_interval: Some(15000),
+        }));
+
+        assert_eq!(state.heartbeat_interval, Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn test_presence_changes_replace_the_snapshot() {
+        let mut state = State::default();
+        let presence = |user_id: &str, status| event::UserPresence {
+            user_id: user_id.into(),
+            status,
+            away_message: None,
+            display_name: None,
+            color: None,
+        };
+
+        state.handle_server_event(&event::Event::PresenceSnapshot(
+            event::PresenceSnapshotReplyEvent {
+                users: vec![
+                    presence("alice", event::PresenceStatus::Online),
+                    presence("bob", event::PresenceStatus::Online),
+                ],
+            },
+        ));
+        state.handle_server_event(&event::Event::PresenceChanged(
+            event::PresenceChangedBroadcastEvent {
+                presence: event::UserPresence {
+                    away_message: Some("lunch".into()),
+                    ..presence("alice", event::PresenceStatus::Away)
+                },
+            },
+        ));
+        state.handle_server_event(&event::Event::PresenceChanged(
+            event::PresenceChangedBroadcastEvent {
+                presence: presence("bob", event::PresenceStatus::Offline),
+            },
+        ));
+
+        assert_eq!(state.presences.len(), 1);
+        assert_eq!(
+            state.presences["alice"].away_message.as_deref(),
+            Some("lunch")
+        );
+    }
+
+    #[test]
+    fn test_display_names_are_kept_and_told_to_the_rooms() {
+        let mut state = State::test_with_rooms(&[("general", "General talk")])
+            .with_joined_room("general", &["alice", "bob"])
+            .with_active_room("general");
+
+        state.handle_server_event(&event::Event::PresenceSnapshot(
+            event::PresenceSnapshotReplyEvent {
+                users: vec![event::UserPresence {
+                    user_id: "alice".into(),
+                    status: event::PresenceStatus::Online,
+                    away_message: None,
+                    display_name: Some("Alice".into()),
+                    color: None,
+                }],
+            },
+        ));
+        state.handle_server_event(&event::Event::NicknameChanged(
+            event::NicknameChangedBroadcastEvent {
+                user_id: "bob".into(),
+                display_name: Some("Bobby".into()),
+            },
+        ));
+        state.handle_server_event(&event::Event::NicknameChanged(
+            event::NicknameChangedBroadcastEvent {
+                user_id: "alice".into(),
+                display_name: None,
+            },
+        ));
+
+        assert_eq!(user_label(&state.display_names, "alice"), "@alice");
+        assert_eq!(user_label(&state.display_names, "bob"), "Bobby (@bob)");
+        assert!(matches!(
+            state.room_data_map["general"].messages.iter().next(),
+            Some(MessageBoxItem::Notification(notification))
+                if notification == "@alice is known by their id again"
+        ));
+    }
+
+    #[test]
+    fn test_profiles_are_shown_and_their_colors_kept() {
+        let mut state = State::default();
+
+        state.handle_server_event(&event::Event::UserProfile(event::UserProfileReplyEvent {
+            profile: event::UserProfile {
+                user_id: "alice".into(),
+                display_name: Some("Alice".into()),
+                bio: Some("Down the rabbit hole".into()),
+                color: Some("#ff8800".into()),
+                ..Default::default()
+            },
+        }));
+
+        assert_eq!(
+            state
+                .user_profile
+                .as_ref()
+                .and_then(|profile| profile.bio.as_deref()),
+            Some("Down the rabbit hole")
+        );
+        assert_eq!(state.user_colors["alice"], Color::Rgb(255, 136, 0));
+        assert_eq!(user_label(&state.display_names, "alice"), "Alice (@alice)");
+
+        state.handle_server_event(&event::Event::PresenceChanged(
+            event::PresenceChangedBroadcastEvent {
+                presence: event::UserPresence {
+                    user_id: "alice".into(),
+                    status: event::PresenceStatus::Online,
+                    away_message: None,
+                    display_name: Some("Alice".into()),
+                    color: Some("not a color".into()),
+                },
+            },
+        ));
+
+        assert!(!state.user_colors.contains_key("alice"));
+    }
+
+    #[test]
+    fn test_drafts_are_kept_per_room() {
+        let mut state =
+            State::test_with_rooms(&[("general", "General talk"), ("rust", "Rustaceans")]);
+
+        state.save_draft("general", String::from("half a thought"));
+        state.save_draft("rust", String::from("fn main"));
+        state.save_draft("rust", String::new());
+        state.save_draft("unknown", String::from("lost"));
+
+        assert_eq!(
+            state.room_data_map["general"].draft.as_deref(),
+            Some("half a thought")
+        );
+        assert_eq!(state.room_data_map["rust"].draft, None);
+        assert!(!state.room_data_map.contains_key("unknown"));
     }
 }
@@ -1,45 +1,1902 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
-use comms::{
-    command,
-    transport::{
-        self,
-        client::{CommandWriter, EventStream},
-    },
+use chrono::{Local, TimeZone};
+use client::connection::{
+    self, ServerHandle, MAX_RECONNECT_ATTEMPTS, MISSED_PINGS_BEFORE_STALLED, RECONNECT_TIMEOUT,
 };
-use tokio::{
-    net::TcpStream,
-    sync::{
-        broadcast,
-        mpsc::{self, UnboundedReceiver, UnboundedSender},
-    },
+use comms::{command, event, transport::client::CommandWriter};
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
 };
 use tokio_stream::StreamExt;
 
-use crate::{Interrupted, Terminator};
+use crate::{
+    config::{self, ClientConfig, LayoutConfig, LoadedConfig, RoomSort},
+    graphics,
+    theme::{self, Theme, BUILT_IN_THEMES},
+    Interrupted, Terminator,
+};
 
-use super::{action::Action, State};
+use super::{
+    action::Action,
+    alerts::Alerter,
+    browser, chat_log,
+    file_transfer::{DownloadStep, FileTransfers},
+    image_previews::{self, ImagePreviews},
+    moderation_label, notifier,
+    room_export::RoomExport,
+    scheduler::{ScheduledTask, Scheduler},
+    snippets,
+    terminal::TerminalRequest,
+    user_data_export, ImagePreview, LoginStatus, ServerConnectionStatus, State,
+};
+
+/// Resolution of the scheduler, the scheduled tasks are run at most this late
+const SCHEDULER_RESOLUTION: Duration = Duration::from_millis(250);
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+/// How long a room is shown as joined without the server confirming it
+const ROOM_JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many of the latest messages are fetched when returning to the latest messages
+const HISTORY_FETCH_LIMIT: usize = 100;
+/// How many of the older messages are fetched once the messages are scrolled back to the oldest one
+const OLDER_MESSAGES_PAGE_SIZE: usize = 50;
+/// How many matches of a history search are requested at once
+const HISTORY_SEARCH_PAGE_SIZE: usize = 20;
+/// How many rooms of the room directory are requested at once
+const ROOM_DIRECTORY_PAGE_SIZE: usize = 20;
 
 pub struct StateStore {
     state_tx: UnboundedSender<State>,
+    /// The terminal is written to by the UI manager, which owns it
+    terminal_tx: UnboundedSender<TerminalRequest>,
+    /// Where the config is written to once a migration is accepted
+    config_path: Option<PathBuf>,
+    loaded_config: LoadedConfig,
 }
 
 impl StateStore {
-    pub fn new() -> (Self, UnboundedReceiver<State>) {
+    pub fn new(
+        config_path: Option<PathBuf>,
+        loaded_config: LoadedConfig,
+    ) -> (
+        Self,
+        UnboundedReceiver<State>,
+        UnboundedReceiver<TerminalRequest>,
+    ) {
         let (state_tx, state_rx) = mpsc::unbounded_channel::<State>();
+        let (terminal_tx, terminal_rx) = mpsc::unbounded_channel::<TerminalRequest>();
+
+        (
+            StateStore {
+                state_tx,
+                terminal_tx,
+                config_path,
+                loaded_config,
+            },
+            state_rx,
+            terminal_rx,
+        )
+    }
+}
+
+fn format_local_date_time(timestamp: u64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|date_time| date_time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| String::from("an unknown date"))
+}
+
+/// Shows the toast on the state, and schedules it to be expired
+fn show_toast(state: &mut State, scheduler: &mut Scheduler, toast: String) {
+    state.show_toast(toast);
+    scheduler.schedule_once(ScheduledTask::ExpireToast, TOAST_DURATION, Instant::now());
+}
+
+/// Expects the next ping of a server which pings its clients, the connection is reconnected if a few of them are missed
+fn expect_ping(state: &State, scheduler: &mut Scheduler) {
+    if let Some(heartbeat_interval) = state.heartbeat_interval {
+        scheduler.schedule_once(
+            ScheduledTask::DetectStalledConnection,
+            heartbeat_interval * MISSED_PINGS_BEFORE_STALLED,
+            Instant::now(),
+        );
+    }
+}
+
+/// Writes the config to the file if there is one, returns the toast to show for the result
+fn save_config(config_path: Option<&PathBuf>, config: &ClientConfig, success: String) -> String {
+    match config_path.map(|config_path| config::save(config_path, config)) {
+        Some(Err(err)) => format!("Could not save the config: {}", err),
+        _ => success,
+    }
+}
+
+/// What the client needs to get back into the session once reconnected to the server
+#[derive(Debug, Default)]
+struct Reconnection {
+    /// Given by the server once logged in, to resume the session if the connection drops
+    resume_token: Option<String>,
+    /// The credentials to log in again with, if the server could not resume the session
+    credentials: Option<(String, String)>,
+    /// The rooms to join again once logged in again, and the room to make active again
+    rooms_to_rejoin: Option<(Vec<String>, Option<String>)>,
+}
+
+/// [JoinRequests] keeps the rooms being joined by the ids of the requests joining them,
+/// the server answers each request with an acknowledgement or an error carrying its id
+#[derive(Debug, Default)]
+struct JoinRequests {
+    next_id: u64,
+    rooms: HashMap<String, String>,
+}
+
+impl JoinRequests {
+    /// Returns the id of a new request joining the room
+    fn start(&mut self, room: &str) -> String {
+        self.next_id += 1;
+
+        let request_id = format!("join-{}", self.next_id);
+        self.rooms.insert(request_id.clone(), String::from(room));
+
+        request_id
+    }
 
-        (StateStore { state_tx }, state_rx)
+    fn contains(&self, request_id: &str) -> bool {
+        self.rooms.contains_key(request_id)
     }
+
+    /// Returns the room the answered request was joining
+    fn finish(&mut self, request_id: &str) -> Option<String> {
+        self.rooms.remove(request_id)
+    }
+}
+
+/// Requests a page of the matches of the query in the stored history of the room, older than the cursor if given
+async fn search_history(
+    command_writer: &mut CommandWriter,
+    room: String,
+    query: String,
+    before: Option<u64>,
+) -> anyhow::Result<()> {
+    command_writer
+        .write(&command::UserCommand::SearchMessages(
+            command::SearchMessagesCommand {
+                room,
+                query,
+                limit: Some(HISTORY_SEARCH_PAGE_SIZE),
+                before,
+            },
+        ))
+        .await
+        .context("could not search the room history")
+}
+
+/// Requests a page of the public rooms, after the room of the cursor if given
+async fn browse_rooms(
+    command_writer: &mut CommandWriter,
+    after: Option<String>,
+) -> anyhow::Result<()> {
+    command_writer
+        .write(&command::UserCommand::BrowseRooms(
+            command::BrowseRoomsCommand {
+                after,
+                limit: Some(ROOM_DIRECTORY_PAGE_SIZE),
+            },
+        ))
+        .await
+        .context("could not browse the rooms")
+}
+
+/// Makes the room the active one, and joins it unless it is already joined or being joined
+async fn select_room(
+    state: &mut State,
+    scheduler: &mut Scheduler,
+    join_requests: &mut JoinRequests,
+    command_writer: &mut CommandWriter,
+    room: String,
+) -> anyhow::Result<()> {
+    state.try_set_active_room(room.as_str());
+
+    join_room(state, scheduler, join_requests, command_writer, room).await
+}
+
+/// Joins the room unless it is already joined or being joined
+async fn join_room(
+    state: &mut State,
+    scheduler: &mut Scheduler,
+    join_requests: &mut JoinRequests,
+    command_writer: &mut CommandWriter,
+    room: String,
+) -> anyhow::Result<()> {
+    // the room is shown as joined right away, and rolled back if the server fails the request or does not answer it in time
+    if let Some(false) = state
+        .room_data_map
+        .get(&room)
+        .map(|room_data| room_data.has_joined || room_data.is_join_pending)
+    {
+        state.mark_room_join_pending(&room);
+        scheduler.schedule_once(
+            ScheduledTask::ExpireRoomJoin { room: room.clone() },
+            ROOM_JOIN_TIMEOUT,
+            Instant::now(),
+        );
+        command_writer
+            .write_request(&command::CommandRequest {
+                request_id: Some(join_requests.start(&room)),
+                command: command::UserCommand::JoinRoom(command::JoinRoomCommand { room }),
+            })
+            .await
+            .context("could not join room")?;
+    }
+
+    Ok(())
 }
 
-type ServerHandle = (EventStream, CommandWriter);
+async fn delete_message(
+    command_writer: &mut CommandWriter,
+    room: String,
+    id: u64,
+) -> anyhow::Result<()> {
+    command_writer
+        .write(&command::UserCommand::DeleteMessage(
+            command::DeleteMessageCommand { room, id },
+        ))
+        .await
+        .context("could not delete message")
+}
+
+/// Sends the moderation command made for the active room, which can not be a conversation of direct messages
+async fn moderate_user(
+    state: &mut State,
+    scheduler: &mut Scheduler,
+    command_writer: &mut CommandWriter,
+    command_for_room: impl FnOnce(String) -> command::UserCommand,
+) -> anyhow::Result<()> {
+    match state
+        .active_room
+        .clone()
+        .filter(|room| !state.is_direct_message(room))
+    {
+        Some(active_room) => command_writer
+            .write(&command_for_room(active_room))
+            .await
+            .context("could not moderate user"),
+        None => {
+            show_toast(
+                state,
+                scheduler,
+                String::from("Enter a room you moderate to moderate its users"),
+            );
+
+            Ok(())
+        }
+    }
+}
+
+/// Whether the connection is kept once a server event is handled, the server ending the session on some of them
+enum Connection {
+    Kept,
+    Ended,
+}
+
+/// What the main loop works on, kept across the connections to the server
+struct LoopContext {
+    config: ClientConfig,
+    state: State,
+    /// Where the config is written to once a setting is changed
+    config_path: Option<PathBuf>,
+    /// The terminal is written to by the UI manager, which owns it
+    terminal_tx: UnboundedSender<TerminalRequest>,
+    scheduler: Scheduler,
+    /// Exports are kept across reconnections, so they can be resumed once the room is joined again
+    room_exports: HashMap<String, RoomExport>,
+    reconnection: Reconnection,
+    join_requests: JoinRequests,
+    /// Transfers are not resumed, the server forgets about them along with the connection
+    file_transfers: FileTransfers,
+    /// The previews are kept across reconnections, as the images of the messages do not change
+    image_previews: ImagePreviews,
+    alerter: Alerter,
+}
+
+impl LoopContext {
+    fn new(
+        config_path: Option<PathBuf>,
+        terminal_tx: UnboundedSender<TerminalRequest>,
+        loaded_config: LoadedConfig,
+    ) -> Self {
+        // until a pending migration is accepted, the app runs with the upgraded settings
+        let (config, state) = match loaded_config {
+            LoadedConfig::Current(config) => {
+                let state = State::from_config(&config);
+
+                (config, state)
+            }
+            LoadedConfig::NeedsMigration(migration) => {
+                let config = migration.upgraded.clone();
+                let state = State {
+                    config_migration: Some(migration),
+                    ..State::from_config(&config)
+                };
+
+                (config, state)
+            }
+        };
+
+        let mut scheduler = Scheduler::new();
+        let image_previews = ImagePreviews::new(graphics::detect());
+        image_previews.schedule_flush(&mut scheduler);
+
+        LoopContext {
+            config,
+            state,
+            config_path,
+            terminal_tx,
+            scheduler,
+            room_exports: HashMap::new(),
+            reconnection: Reconnection::default(),
+            join_requests: JoinRequests::default(),
+            file_transfers: FileTransfers::default(),
+            image_previews,
+            alerter: Alerter::default(),
+        }
+    }
+
+    fn show_toast(&mut self, toast: String) {
+        show_toast(&mut self.state, &mut self.scheduler, toast);
+    }
+
+    /// Writes the config to the file if there is one, returns the toast to show for the result
+    fn save_config(&self, success: String) -> String {
+        save_config(self.config_path.as_ref(), &self.config, success)
+    }
+
+    /// Goes back to the connect page, forgetting the session
+    fn reset(&mut self) {
+        self.reconnection = Reconnection::default();
+        self.state = State::from_config(&self.config);
+        self.scheduler.cancel_connection_tasks();
+    }
+
+    async fn connect(&mut self, addr: String) -> Option<ServerHandle> {
+        match connection::connect(&addr).await {
+            Ok(server_handle) => {
+                self.state.process_connection_request_result(Ok(addr));
+
+                Some(server_handle)
+            }
+            Err(err) => {
+                self.state.process_connection_request_result(Err(err));
+
+                None
+            }
+        }
+    }
+
+    /// Tries to resume the session on the server, going back to the connect page after too many attempts
+    async fn reconnect(&mut self) -> Option<ServerHandle> {
+        let (ServerConnectionStatus::Reconnecting { addr, attempt }, Some(resume_token)) = (
+            self.state.server_connection_status.clone(),
+            self.reconnection.resume_token.clone(),
+        ) else {
+            return None;
+        };
+
+        let result =
+            tokio::time::timeout(RECONNECT_TIMEOUT, connection::resume(&addr, resume_token))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+
+        match result {
+            // the server replies whether the session is resumed
+            Ok(server_handle) => {
+                self.state.process_connection_request_result(Ok(addr));
+
+                Some(server_handle)
+            }
+            Err(err) if attempt >= MAX_RECONNECT_ATTEMPTS => {
+                self.reset();
+                self.state
+                    .process_connection_request_result(Err(anyhow::anyhow!(
+                        "could not reconnect to {}: {}",
+                        addr,
+                        err
+                    )));
+
+                None
+            }
+            Err(_) => {
+                self.state.mark_reconnecting(addr, attempt + 1);
+                self.scheduler.schedule_once(
+                    ScheduledTask::Reconnect,
+                    connection::reconnect_delay(attempt + 1),
+                    Instant::now(),
+                );
+
+                None
+            }
+        }
+    }
+
+    /// The state is kept while reconnecting, the session is resumed or the rooms are joined again once reconnected
+    fn handle_dropped_connection(&mut self) {
+        let was_logged_in = self.state.login_status == LoginStatus::LoggedIn;
+
+        // the requests are not answered anymore, the rooms are joined again with new ones
+        self.join_requests = JoinRequests::default();
+        self.file_transfers.clear();
+        self.state.transfers.clear();
+        // the previews being downloaded are requested again once reconnected
+        self.state
+            .image_previews
+            .retain(|_, preview| !matches!(preview, ImagePreview::Loading));
+        // the reconnected server pings again from its welcome on
+        self.scheduler
+            .cancel(&ScheduledTask::DetectStalledConnection);
+
+        match self.state.connected_addr().map(String::from) {
+            Some(addr) if was_logged_in && self.reconnection.resume_token.is_some() => {
+                self.state.mark_reconnecting(addr, 1);
+                self.scheduler.schedule_once(
+                    ScheduledTask::Reconnect,
+                    connection::reconnect_delay(1),
+                    Instant::now(),
+                );
+            }
+            _ => {
+                self.reset();
+
+                // the server gives up on the clients which fail to log in
+                if !was_logged_in {
+                    self.state
+                        .process_connection_request_result(Err(anyhow::anyhow!(
+                            "the server closed the connection before logging in"
+                        )));
+                }
+            }
+        }
+    }
+
+    /// Accepts the pending migration of the config, writing the upgraded config to its file
+    fn apply_config_migration(&mut self) {
+        let Some(migration) = self.state.config_migration.clone() else {
+            return;
+        };
+
+        let result = match self.config_path.as_ref() {
+            Some(config_path) => config::save(config_path, &migration.upgraded),
+            None => Ok(()),
+        };
+
+        match result {
+            Ok(_) => {
+                self.config = migration.upgraded;
+                self.state = State::from_config(&self.config);
+            }
+            Err(err) => {
+                self.state.config_migration_error = Some(err.to_string());
+            }
+        }
+    }
+
+    /// Runs the scheduled tasks which are due while connected, returns whether the connection is found stalled
+    fn run_due_tasks_while_connected(&mut self) -> bool {
+        let mut is_stalled = false;
+
+        for task in self.scheduler.take_due(Instant::now()) {
+            match task {
+                // the connection may never end on its own, such as when the network of the client changes
+                ScheduledTask::DetectStalledConnection => {
+                    self.show_toast(String::from("The server stopped answering, reconnecting"));
+                    is_stalled = true;
+                }
+                task => self.run_scheduled_task(task),
+            }
+        }
+
+        is_stalled
+    }
+
+    /// Runs the scheduled tasks which are due while disconnected, returns the connection once reconnected
+    async fn run_due_tasks_while_disconnected(&mut self) -> Option<ServerHandle> {
+        let mut opt_server_handle = None;
+
+        for task in self.scheduler.take_due(Instant::now()) {
+            match task {
+                ScheduledTask::Reconnect => {
+                    if let Some(server_handle) = self.reconnect().await {
+                        opt_server_handle = Some(server_handle);
+                    }
+                }
+                task => self.run_scheduled_task(task),
+            }
+        }
+
+        opt_server_handle
+    }
+
+    fn run_scheduled_task(&mut self, task: ScheduledTask) {
+        match task {
+            ScheduledTask::ExpireRoomJoin { room } => {
+                if self.state.roll_back_room_join(&room) {
+                    self.show_toast(format!("Joining #{} timed out", room));
+                }
+            }
+            ScheduledTask::RestoreTitle => self.alerter.restore_title(),
+            task => self.state.run_scheduled_task(&task),
+        }
+    }
+
+    /// The images of the active room are previewed as they show up, the shared ones are downloaded like the other files
+    async fn request_image_previews(
+        &mut self,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        for file_id in self.image_previews.request_missing(&mut self.state) {
+            // a file being saved is previewed once it is saved
+            let Ok(download) = self.file_transfers.start_preview(&file_id) else {
+                self.state
+                    .image_previews
+                    .remove(&image_previews::file_preview_key(&file_id));
+                continue;
+            };
+
+            command_writer
+                .write(&command::UserCommand::DownloadFile(download))
+                .await
+                .context("could not download the preview")?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles an event of the server, a command which could not be written meaning the connection has dropped
+    async fn handle_server_event(
+        &mut self,
+        event: event::Event,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<Connection> {
+        match event {
+            event::Event::UserDataExport(export) => {
+                let toast = match user_data_export::save(&export) {
+                    Ok(path) => format!("Exported your data to {}", path.display()),
+                    Err(err) => format!("Could not save the data export: {}", err),
+                };
+
+                self.show_toast(toast);
+            }
+            // the server ends the session of a deleted account, go back to the connect page
+            event::Event::AccountDeletionScheduled(event) => {
+                self.reset();
+                self.state
+                    .process_connection_request_result(Err(anyhow::anyhow!(
+                        "your account has been deleted, your messages will be anonymized on {}",
+                        format_local_date_time(event.delete_at)
+                    )));
+
+                return Ok(Connection::Ended);
+            }
+            // the operator closed the session, reconnecting would only get around the kick
+            event::Event::SessionKicked(event) => {
+                self.reset();
+                self.state
+                    .process_connection_request_result(Err(anyhow::anyhow!(
+                        "you were disconnected by the server: {}",
+                        event.reason
+                    )));
+
+                return Ok(Connection::Ended);
+            }
+            // the connection is closed right after, and reconnected as any dropped connection
+            event::Event::Disconnected(_) => {
+                self.show_toast(String::from(
+                    "The server disconnected this client for falling behind, reconnecting",
+                ));
+            }
+            // the server does not serve this client, there is no point in reconnecting
+            event::Event::ProtocolRejected(event) => {
+                let addr = self.state.connected_addr().unwrap_or_default().to_string();
+
+                self.reset();
+                self.state
+                    .mark_protocol_rejected(addr, event.min_protocol_version);
+
+                return Ok(Connection::Ended);
+            }
+            event::Event::ResumeResult(event) => {
+                self.handle_resume_result(event, command_writer).await?;
+            }
+            event::Event::Ping(event) => {
+                expect_ping(&self.state, &mut self.scheduler);
+                self.state.round_trip = event.round_trip.map(Duration::from_millis);
+
+                command_writer
+                    .write(&command::UserCommand::Pong(command::PongCommand {
+                        nonce: event.nonce,
+                    }))
+                    .await
+                    .context("could not answer the ping")?;
+            }
+            event::Event::RoomHistoryChunk(chunk) => self.append_room_export(&chunk),
+            event::Event::RoomHistoryExportDenied(event) => {
+                if let Some(room_export) = self.room_exports.remove(&event.room) {
+                    room_export.discard();
+                }
+
+                self.show_toast(format!(
+                    "Exporting the history of #{} is not allowed",
+                    event.room
+                ));
+            }
+            event::Event::RoomJoinDenied(event) => {
+                if self.state.roll_back_room_join(&event.room) {
+                    self.scheduler.cancel(&ScheduledTask::ExpireRoomJoin {
+                        room: event.room.clone(),
+                    });
+                    self.show_toast(format!("Could not join #{}: {}", event.room, event.reason));
+                }
+            }
+            event::Event::RoomInvitationDenied(event) => {
+                self.show_toast(format!(
+                    "Could not invite @{} to #{}: {}",
+                    event.user_id, event.room, event.reason
+                ));
+            }
+            event::Event::MessageChangeDenied(event) => {
+                self.show_toast(format!(
+                    "Could not change the message in #{}: {}",
+                    event.room, event.reason
+                ));
+            }
+            event::Event::DirectMessageDenied(event) => {
+                self.show_toast(format!(
+                    "Could not message @{}: {}",
+                    event.user_id, event.reason
+                ));
+            }
+            event::Event::RoomManagementDenied(event) => {
+                self.show_toast(format!(
+                    "Could not manage #{}: {}",
+                    event.room, event.reason
+                ));
+            }
+            event::Event::ModerationDenied(event) => {
+                self.show_toast(format!("Refused in #{}: {}", event.room, event.reason));
+            }
+            event::Event::UploadProgress(event) => {
+                if let Some(chunk) = self.file_transfers.next_upload_chunk(&event) {
+                    command_writer
+                        .write(&command::UserCommand::UploadChunk(chunk))
+                        .await
+                        .context("could not upload the file")?;
+                }
+
+                self.state.transfers = self.file_transfers.progress();
+            }
+            event::Event::FileChunk(event) => {
+                self.receive_file_chunk(&event, command_writer).await?;
+            }
+            event::Event::FileTransferDenied(event) => self.deny_file_transfer(event),
+            event::Event::RateLimited(event) => {
+                let retry_after = Duration::from_millis(event.retry_after);
+
+                self.state.show_rate_limit_warning(retry_after);
+                self.scheduler.schedule_once(
+                    ScheduledTask::ExpireRateLimitWarning,
+                    retry_after,
+                    Instant::now(),
+                );
+            }
+            event::Event::CommandAck(event) => {
+                if let Some(room) = self.join_requests.finish(&event.request_id) {
+                    self.scheduler
+                        .cancel(&ScheduledTask::ExpireRoomJoin { room });
+                }
+            }
+            event::Event::CommandError(event::CommandErrorEvent {
+                request_id: Some(request_id),
+                message,
+                ..
+            }) if self.join_requests.contains(&request_id) => {
+                if let Some(room) = self.join_requests.finish(&request_id) {
+                    self.scheduler
+                        .cancel(&ScheduledTask::ExpireRoomJoin { room: room.clone() });
+
+                    if self.state.roll_back_room_join(&room) {
+                        self.show_toast(format!("Could not join #{}: {}", room, message));
+                    }
+                }
+            }
+            event::Event::SpaceCommandDenied(event) => {
+                self.show_toast(format!(
+                    "Could not manage the space {}: {}",
+                    event.space, event.reason
+                ));
+            }
+            event => self.apply_server_event(event, command_writer).await?,
+        }
+
+        Ok(Connection::Kept)
+    }
+
+    /// The dropped session is taken over with its rooms, otherwise the server waits for a login
+    async fn handle_resume_result(
+        &mut self,
+        event: event::ResumeResultReplyEvent,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        if event.is_accepted {
+            self.show_toast(String::from(
+                "Reconnected to the server, your session is resumed",
+            ));
+        } else if let Some((username, password)) = self.reconnection.credentials.clone() {
+            // the state is kept until logged in again, the rooms are joined again then
+            self.reconnection.rooms_to_rejoin =
+                Some((self.state.joined_rooms(), self.state.active_room.clone()));
+
+            command_writer
+                .write(&command::UserCommand::Login(command::LoginCommand {
+                    username,
+                    password,
+                }))
+                .await
+                .context("could not log in")?;
+        } else {
+            let addr = self
+                .state
+                .connected_addr()
+                .map(String::from)
+                .unwrap_or_default();
+
+            self.reset();
+            self.state.process_connection_request_result(Ok(addr));
+            self.state.login_status = LoginStatus::Rejected {
+                reason: format!(
+                    "could not resume the session, {}",
+                    event.reason.unwrap_or_default()
+                ),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn append_room_export(&mut self, chunk: &event::RoomHistoryChunkReplyEvent) {
+        let Some(room_export) = self.room_exports.get_mut(&chunk.room) else {
+            return;
+        };
+
+        match room_export.append(chunk) {
+            Ok(_) if chunk.is_last => {
+                let toast = format!(
+                    "Exported the history of #{} to {}",
+                    chunk.room,
+                    room_export.path().display()
+                );
+
+                self.room_exports.remove(&chunk.room);
+                self.show_toast(toast);
+            }
+            Ok(_) => (),
+            Err(err) => {
+                self.room_exports.remove(&chunk.room);
+                self.show_toast(format!("Could not save the history export: {}", err));
+            }
+        }
+    }
+
+    async fn receive_file_chunk(
+        &mut self,
+        event: &event::FileChunkReplyEvent,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        let is_preview = self.file_transfers.is_preview(&event.file_id);
+
+        match self.file_transfers.receive_chunk(event) {
+            Some(Ok(DownloadStep::Next(next))) => {
+                command_writer
+                    .write(&command::UserCommand::DownloadFile(next))
+                    .await
+                    .context("could not download the file")?;
+            }
+            Some(Ok(DownloadStep::Saved(path))) => {
+                self.show_toast(format!("Saved the file to {}", path.display()));
+            }
+            Some(Ok(DownloadStep::Received(content))) => {
+                self.image_previews
+                    .decode(image_previews::file_preview_key(&event.file_id), content);
+            }
+            Some(Err(err)) if is_preview => {
+                self.state.image_previews.insert(
+                    image_previews::file_preview_key(&event.file_id),
+                    ImagePreview::Failed(err.to_string()),
+                );
+            }
+            Some(Err(err)) => {
+                self.show_toast(format!("Could not save the file: {}", err));
+            }
+            None => (),
+        }
+
+        self.state.transfers = self.file_transfers.progress();
+
+        Ok(())
+    }
+
+    fn deny_file_transfer(&mut self, event: event::FileTransferDeniedReplyEvent) {
+        if self.file_transfers.is_preview(&event.transfer_id) {
+            self.file_transfers.cancel(&event.transfer_id);
+            self.state.image_previews.insert(
+                image_previews::file_preview_key(&event.transfer_id),
+                ImagePreview::Failed(event.reason),
+            );
+        } else if let Some(name) = self.file_transfers.cancel(&event.transfer_id) {
+            self.show_toast(format!("Could not transfer {}: {}", name, event.reason));
+        }
+
+        self.state.transfers = self.file_transfers.progress();
+    }
+
+    /// Applies the event to the state, then follows up on it
+    async fn apply_server_event(
+        &mut self,
+        event: event::Event,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        if let event::Event::CommandError(event) = &event {
+            if self.state.active_room.is_none() {
+                self.show_toast(event.message.clone());
+            }
+        }
+
+        if let event::Event::RoomDeleted(event) = &event {
+            if self.state.joined_rooms().contains(&event.room) {
+                self.show_toast(format!("#{} was deleted", event.room));
+            }
+        }
+
+        if let event::Event::UserModerated(event) = &event {
+            if event.user_id == self.state.user_id {
+                self.show_toast(format!(
+                    "You were {} in #{} by @{}",
+                    moderation_label(&event.action),
+                    event.room,
+                    event.moderator_id
+                ));
+            }
+        }
+
+        let notification = self.state.notification_for(&event);
+        let alerting_room = self.state.alerting_room(&event);
+        self.state.handle_server_event(&event);
+
+        if let Some(notification) = notification {
+            notifier::notify(notification);
+        }
+        if let Some(room) = alerting_room {
+            self.alerter
+                .alert(self.state.alert_policy, &room, &mut self.scheduler);
+        }
+
+        match event {
+            // the server pings the client from its welcome on
+            event::Event::Welcome(_) => expect_ping(&self.state, &mut self.scheduler),
+            // the creator of a room is taken to it right away
+            event::Event::RoomCreated(event) if event.created_by == self.state.user_id => {
+                select_room(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    &mut self.join_requests,
+                    command_writer,
+                    event.room.name,
+                )
+                .await?;
+            }
+            event::Event::LoginResult(event::LoginResultReplyEvent {
+                is_new_user: true, ..
+            }) => {
+                self.show_toast(String::from("Registered your account, welcome!"));
+            }
+            event::Event::LoginSuccessful(event) => {
+                self.reconnection.resume_token = event.resume_token;
+
+                if let Some((rooms, active_room)) = self.reconnection.rooms_to_rejoin.take() {
+                    for room in rooms {
+                        join_room(
+                            &mut self.state,
+                            &mut self.scheduler,
+                            &mut self.join_requests,
+                            command_writer,
+                            room,
+                        )
+                        .await?;
+                    }
+
+                    if let Some(active_room) = active_room {
+                        self.state.try_set_active_room(&active_room);
+                    }
+
+                    self.show_toast(String::from(
+                        "Reconnected to the server, joining your rooms again",
+                    ));
+                }
+            }
+            // the server replays the visible history of the room right after confirming the join
+            event::Event::UserJoinedRoom(event) => {
+                self.scheduler.cancel(&ScheduledTask::ExpireRoomJoin {
+                    room: event.room.clone(),
+                });
+
+                if let Some(room_export) = self.room_exports.get(&event.room) {
+                    command_writer
+                        .write(&command::UserCommand::ExportRoomHistory(
+                            command::ExportRoomHistoryCommand {
+                                room: event.room,
+                                after: room_export.cursor(),
+                            },
+                        ))
+                        .await
+                        .context("could not export room history")?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Handles an action of the UI, a command which could not be written meaning the connection has dropped
+    async fn handle_action(
+        &mut self,
+        action: Action,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        let previous_room = self.state.active_room.clone();
+
+        self.run_action(action, command_writer).await?;
+
+        // the room left is read up to its latest message, the newer ones are divided when coming back
+        if let Some((room, id)) = previous_room
+            .filter(|room| self.state.active_room.as_ref() != Some(room))
+            .and_then(|room| self.state.mark_room_read(&room))
+        {
+            command_writer
+                .write(&command::UserCommand::MarkRead(command::MarkReadCommand {
+                    room,
+                    id,
+                }))
+                .await
+                .context("could not mark the room as read")?;
+        }
 
-async fn create_server_handle(addr: &str) -> anyhow::Result<ServerHandle> {
-    let stream = TcpStream::connect(addr).await?;
-    let (event_stream, command_writer) = transport::client::split_tcp_stream(stream);
+        Ok(())
+    }
+
+    /// The active room, unless it is a conversation of direct messages
+    fn active_chat_room(&self) -> Option<String> {
+        self.state
+            .active_room
+            .clone()
+            .filter(|room| !self.state.is_direct_message(room))
+    }
+
+    async fn run_action(
+        &mut self,
+        action: Action,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        match action {
+            Action::Login { username, password } => {
+                // kept in memory only, to log in again if the server can not resume the session after reconnecting
+                self.reconnection.credentials = Some((username.clone(), password.clone()));
+                self.state.mark_login_start();
+                command_writer
+                    .write(&command::UserCommand::Login(command::LoginCommand {
+                        username,
+                        password,
+                    }))
+                    .await
+                    .context("could not log in")?;
+            }
+            Action::SendMessage { content } => self.send_message(content, command_writer).await?,
+            Action::ReplyToMessage { id } => self.state.reply_in_active_room(Some(id)),
+            Action::CancelReply => self.state.reply_in_active_room(None),
+            Action::EditMessage { id, content } => {
+                if let Some(active_room) = self.active_chat_room() {
+                    command_writer
+                        .write(&command::UserCommand::EditMessage(
+                            command::EditMessageCommand {
+                                room: active_room,
+                                id,
+                                content,
+                            },
+                        ))
+                        .await
+                        .context("could not edit message")?;
+                }
+            }
+            Action::DeleteMessage { id } => {
+                if let Some(active_room) = self.active_chat_room() {
+                    delete_message(command_writer, active_room, id).await?;
+                }
+            }
+            Action::DeleteLastMessage => {
+                match (
+                    self.state.active_room.clone(),
+                    self.state.last_own_message(),
+                ) {
+                    (Some(active_room), Some((id, _))) => {
+                        delete_message(command_writer, active_room, id).await?
+                    }
+                    _ => {
+                        self.show_toast(String::from("You have no message to delete in this room"))
+                    }
+                }
+            }
+            Action::ReactToMessage { id, emoji } => {
+                if let Some(active_room) = self.active_chat_room() {
+                    command_writer
+                        .write(&command::UserCommand::ReactToMessage(
+                            command::ReactToMessageCommand {
+                                room: active_room,
+                                id,
+                                emoji,
+                            },
+                        ))
+                        .await
+                        .context("could not react to message")?;
+                }
+            }
+            Action::OpenDirectMessage { user_id } => {
+                let room = self.state.open_direct_message(&user_id);
+                self.state.try_set_active_room(&room);
+            }
+            Action::SendDirectMessage { user_id, content } => {
+                let room = self.state.open_direct_message(&user_id);
+                self.state.try_set_active_room(&room);
+
+                command_writer
+                    .write(&command::UserCommand::SendDirectMessage(
+                        command::SendDirectMessageCommand { user_id, content },
+                    ))
+                    .await
+                    .context("could not send direct message")?;
+            }
+            Action::SelectRoom { room } => {
+                select_room(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    &mut self.join_requests,
+                    command_writer,
+                    room,
+                )
+                .await?;
+            }
+            Action::JoinRoom { room } => {
+                // a room which is not listed is private, its details are only known once invited
+                self.state.add_private_room(&event::RoomDetail {
+                    name: room.clone(),
+                    description: String::from("private room"),
+                    history_visibility: event::HistoryVisibility::default(),
+                    input_template: None,
+                });
+                select_room(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    &mut self.join_requests,
+                    command_writer,
+                    room,
+                )
+                .await?;
+            }
+            Action::CreateRoom { room, description } => {
+                command_writer
+                    .write(&command::UserCommand::CreateRoom(
+                        command::CreateRoomCommand { room, description },
+                    ))
+                    .await
+                    .context("could not create room")?;
+            }
+            Action::DeleteRoom => match self.active_chat_room() {
+                Some(active_room) => {
+                    command_writer
+                        .write(&command::UserCommand::DeleteRoom(
+                            command::DeleteRoomCommand { room: active_room },
+                        ))
+                        .await
+                        .context("could not delete room")?;
+                }
+                None => self.show_toast(String::from("Enter a room you created to delete it")),
+            },
+            Action::SetRoomTopic { topic } => match self.active_chat_room() {
+                Some(active_room) => {
+                    command_writer
+                        .write(&command::UserCommand::SetRoomTopic(
+                            command::SetRoomTopicCommand {
+                                room: active_room,
+                                topic,
+                            },
+                        ))
+                        .await
+                        .context("could not set room topic")?;
+                }
+                None => self.show_toast(String::from("Enter a room to change its topic")),
+            },
+            Action::SetRoomRole { user_id, role } => {
+                moderate_user(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    command_writer,
+                    |room| {
+                        command::UserCommand::SetRoomRole(command::SetRoomRoleCommand {
+                            room,
+                            user_id,
+                            role,
+                        })
+                    },
+                )
+                .await?;
+            }
+            Action::KickUser { user_id } => {
+                moderate_user(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    command_writer,
+                    |room| {
+                        command::UserCommand::KickUser(command::KickUserCommand { room, user_id })
+                    },
+                )
+                .await?;
+            }
+            Action::BanUser { user_id } => {
+                moderate_user(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    command_writer,
+                    |room| command::UserCommand::BanUser(command::BanUserCommand { room, user_id }),
+                )
+                .await?;
+            }
+            Action::MuteUser { user_id, minutes } => {
+                moderate_user(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    command_writer,
+                    |room| {
+                        command::UserCommand::MuteUser(command::MuteUserCommand {
+                            room,
+                            user_id,
+                            seconds: minutes * 60,
+                        })
+                    },
+                )
+                .await?;
+            }
+            Action::LeaveRoom { room } => match room.or_else(|| self.state.active_room.clone()) {
+                Some(room) if self.state.prompt_leave_room(&room) => {}
+                Some(room) if !self.state.is_direct_message(&room) => {
+                    self.show_toast(format!("You have not joined #{}", room));
+                }
+                _ => self.show_toast(String::from("Enter a joined room to leave it")),
+            },
+            Action::ConfirmLeaveRoom => {
+                if let Some(room) = self.state.confirm_leave_room() {
+                    command_writer
+                        .write(&command::UserCommand::LeaveRoom(
+                            command::LeaveRoomCommand { room },
+                        ))
+                        .await
+                        .context("could not leave room")?;
+                }
+            }
+            Action::CancelLeaveRoom => self.state.leave_prompt = None,
+            Action::ShowCommandHelp { lines } => self.state.push_notifications(&lines),
+            Action::ShowToast { content } => self.show_toast(content),
+            Action::InviteUser { user_id } => match self.active_chat_room() {
+                Some(active_room) => {
+                    command_writer
+                        .write(&command::UserCommand::InviteUser(
+                            command::InviteUserCommand {
+                                room: active_room,
+                                user_id,
+                            },
+                        ))
+                        .await
+                        .context("could not invite user")?;
+                }
+                None => self.show_toast(String::from("Enter a private room to invite users to it")),
+            },
+            Action::AcceptInvitation { room } => {
+                // the invitation may have been answered already
+                if !self.state.accept_invitation(&room) {
+                    return Ok(());
+                }
+
+                select_room(
+                    &mut self.state,
+                    &mut self.scheduler,
+                    &mut self.join_requests,
+                    command_writer,
+                    room,
+                )
+                .await?;
+            }
+            Action::DeclineInvitation { room } => {
+                if let Some(invitation) = self.state.take_invitation(&room) {
+                    command_writer
+                        .write(&command::UserCommand::DeclineInvitation(
+                            command::DeclineInvitationCommand {
+                                room: invitation.room.name,
+                            },
+                        ))
+                        .await
+                        .context("could not decline invitation")?;
+                }
+            }
+            Action::JumpToDate { timestamp } => {
+                // the server keeps no history of the direct messages
+                if let Some(active_room) = self.active_chat_room() {
+                    self.state.mark_history_fetch_start(&active_room);
+                    command_writer
+                        .write(&command::UserCommand::FetchRoomHistory(
+                            command::FetchRoomHistoryCommand {
+                                room: active_room,
+                                around: Some(timestamp),
+                                limit: None,
+                            },
+                        ))
+                        .await
+                        .context("could not fetch room history")?;
+                }
+            }
+            Action::ScrollMessages { items } => self.scroll_messages(items, command_writer).await?,
+            Action::SearchMessages { query } => self.state.search_active_room(query),
+            Action::MoveSearch { older } => self.state.move_search(older),
+            Action::CloseSearch => self.state.search = None,
+            Action::SearchHistory { query } => {
+                if !self.state.can_search_history {
+                    self.show_toast(String::from("The server can not search the history"));
+                } else if let Some(room) = self.state.start_history_search(query.clone()) {
+                    search_history(command_writer, room, query, None).await?;
+                } else {
+                    self.show_toast(String::from(
+                        "Only the history of the rooms can be searched",
+                    ));
+                }
+            }
+            Action::LoadMoreSearchResults => {
+                if let Some((room, query, cursor)) = self.state.load_more_history_search() {
+                    search_history(command_writer, room, query, Some(cursor)).await?;
+                }
+            }
+            Action::BrowseRooms => {
+                if self.state.can_browse_rooms {
+                    self.state.start_room_directory();
+                    browse_rooms(command_writer, None).await?;
+                } else {
+                    self.show_toast(String::from("The server can not list its rooms"));
+                }
+            }
+            Action::LoadMoreRooms => {
+                if let Some(after) = self.state.load_more_room_directory() {
+                    browse_rooms(command_writer, Some(after)).await?;
+                }
+            }
+            Action::CloseHistorySearch => self.state.history_search = None,
+            Action::ReturnToLatest => self.return_to_latest(command_writer).await?,
+            Action::SendFile { path } => self.send_file(path, command_writer).await?,
+            Action::SaveFile { file_id, path } => {
+                self.save_file(file_id, path, command_writer).await?
+            }
+            Action::ExportRoomHistory => self.export_room_history(command_writer).await?,
+            Action::ExportChatLog { room, path } => self.export_chat_log(room, path),
+            Action::SetAwayMessage { away_message } => {
+                command_writer
+                    .write(&command::UserCommand::SetPresence(
+                        command::SetPresenceCommand { away_message },
+                    ))
+                    .await
+                    .context("could not set presence")?;
+            }
+            Action::ChangeNickname { nickname } => {
+                if !self.state.can_change_nickname {
+                    self.show_toast(String::from("The server can not change nicknames"));
+                    return Ok(());
+                }
+
+                command_writer
+                    .write(&command::UserCommand::ChangeNickname(
+                        command::ChangeNicknameCommand { nickname },
+                    ))
+                    .await
+                    .context("could not change the nickname")?;
+            }
+            Action::GetUserProfile { user_id } => {
+                if !self.state.can_view_profiles {
+                    self.show_toast(String::from("The server has no profiles"));
+                    return Ok(());
+                }
+
+                command_writer
+                    .write(&command::UserCommand::GetUserProfile(
+                        command::GetUserProfileCommand { user_id },
+                    ))
+                    .await
+                    .context("could not get the profile")?;
+            }
+            Action::UpdateProfile {
+                bio,
+                pronouns,
+                timezone,
+                color,
+            } => {
+                if !self.state.can_view_profiles {
+                    self.show_toast(String::from("The server has no profiles"));
+                    return Ok(());
+                }
+
+                command_writer
+                    .write(&command::UserCommand::UpdateProfile(
+                        command::UpdateProfileCommand {
+                            bio,
+                            pronouns,
+                            timezone,
+                            color,
+                        },
+                    ))
+                    .await
+                    .context("could not update the profile")?;
+            }
+            Action::CloseUserProfile => self.state.user_profile = None,
+            Action::ExportMyData => {
+                command_writer
+                    .write(&command::UserCommand::ExportMyData(
+                        command::ExportMyDataCommand,
+                    ))
+                    .await
+                    .context("could not export user data")?;
+            }
+            Action::DeleteMyAccount => {
+                command_writer
+                    .write(&command::UserCommand::DeleteMyAccount(
+                        command::DeleteMyAccountCommand,
+                    ))
+                    .await
+                    .context("could not delete account")?;
+            }
+            Action::AddHighlightWord { word } => self.add_highlight_word(word),
+            Action::RemoveHighlightWord { word } => self.remove_highlight_word(word),
+            Action::ListHighlightWords => self.list_highlight_words(),
+            Action::ToggleFavoriteRoom { room } => self.toggle_favorite_room(room),
+            Action::ToggleRoomSort => self.toggle_room_sort(),
+            Action::IgnoreUser { user_id } => self.ignore_user(user_id),
+            Action::UnignoreUser { user_id } => self.unignore_user(user_id),
+            Action::ListIgnoredUsers => self.list_ignored_users(),
+            Action::JoinSpace { space } => {
+                match self
+                    .state
+                    .space_data_map
+                    .get(&space)
+                    .map(|space_data| space_data.has_joined)
+                {
+                    Some(false) => {
+                        command_writer
+                            .write(&command::UserCommand::JoinSpace(
+                                command::JoinSpaceCommand { space },
+                            ))
+                            .await
+                            .context("could not join space")?;
+                    }
+                    Some(true) => self.show_toast(format!("You are already a member of {}", space)),
+                    None => self.show_toast(format!("There is no space named {}", space)),
+                }
+            }
+            Action::LeaveSpace { space } => {
+                if self.state.role_in_space(&space).is_some() {
+                    command_writer
+                        .write(&command::UserCommand::LeaveSpace(
+                            command::LeaveSpaceCommand { space },
+                        ))
+                        .await
+                        .context("could not leave space")?;
+                } else {
+                    self.show_toast(format!("You are not a member of {}", space));
+                }
+            }
+            Action::SetSpaceRole {
+                space,
+                user_id,
+                role,
+            } => {
+                command_writer
+                    .write(&command::UserCommand::SetSpaceRole(
+                        command::SetSpaceRoleCommand {
+                            space,
+                            user_id,
+                            role,
+                        },
+                    ))
+                    .await
+                    .context("could not set space role")?;
+            }
+            Action::RemoveSpaceMember { space, user_id } => {
+                command_writer
+                    .write(&command::UserCommand::RemoveSpaceMember(
+                        command::RemoveSpaceMemberCommand { space, user_id },
+                    ))
+                    .await
+                    .context("could not remove space member")?;
+            }
+            Action::ListSpaceMembers { space } => self.list_space_members(space),
+            Action::ToggleInputTemplates => self.state.toggle_input_templates(),
+            Action::SetTheme { name } => self.set_theme(name),
+            Action::ToggleTheme => self.toggle_theme(),
+            Action::SetLayout { layout } => self.set_layout(layout),
+            Action::ToggleDoNotDisturb => self.toggle_do_not_disturb(),
+            Action::SetTerminalFocus { is_focused } => self.state.is_terminal_focused = is_focused,
+            Action::ShowHelp => self.state.is_help_open = true,
+            Action::CloseHelp => self.state.is_help_open = false,
+            Action::DismissAnnouncement => self.state.dismiss_announcement(),
+            Action::SaveDraft { room, content } => self.state.save_draft(&room, content),
+            Action::CopyToClipboard { content } => self.copy_to_clipboard(content),
+            Action::OpenLink { url } => self.open_link(url),
+            Action::SaveToFile { content } => {
+                let toast = match snippets::save_to_file(&content) {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(err) => format!("Could not save to a file: {}", err),
+                };
+
+                self.show_toast(toast);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    async fn send_message(
+        &mut self,
+        content: String,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        let direct_message_user_id = self
+            .state
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.state.room_data_map.get(active_room))
+            .filter(|room_data| room_data.is_direct_message)
+            .map(|room_data| room_data.name.clone());
+
+        if let Some(warning) = self.state.message_length_warning(&content) {
+            self.show_toast(warning);
+        } else if let Some(user_id) = direct_message_user_id {
+            command_writer
+                .write(&command::UserCommand::SendDirectMessage(
+                    command::SendDirectMessageCommand { user_id, content },
+                ))
+                .await
+                .context("could not send direct message")?;
+        } else if let Some(active_room) = self.state.active_room.clone() {
+            let reply_to = self
+                .state
+                .room_data_map
+                .get_mut(&active_room)
+                .and_then(|room_data| room_data.replying_to.take());
+
+            command_writer
+                .write(&command::UserCommand::SendMessage(
+                    command::SendMessageCommand {
+                        room: active_room,
+                        content,
+                        reply_to,
+                    },
+                ))
+                .await
+                .context("could not send message")?;
+        }
+
+        Ok(())
+    }
+
+    async fn scroll_messages(
+        &mut self,
+        items: isize,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        self.state.scroll_active_room(items);
+
+        // the older messages are loaded as the messages are scrolled back past the oldest one
+        if let Some((room, before_message_id)) = self.state.start_older_messages_fetch() {
+            command_writer
+                .write(&command::UserCommand::FetchMessagesBefore(
+                    command::FetchMessagesBeforeCommand {
+                        room,
+                        before_message_id,
+                        limit: Some(OLDER_MESSAGES_PAGE_SIZE),
+                    },
+                ))
+                .await
+                .context("could not fetch the older messages")?;
+        }
+
+        Ok(())
+    }
+
+    async fn return_to_latest(&mut self, command_writer: &mut CommandWriter) -> anyhow::Result<()> {
+        self.state.scroll_active_room(isize::MIN);
+
+        let jumped_room = self.state.active_room.clone().filter(|active_room| {
+            self.state
+                .room_data_map
+                .get(active_room)
+                .map(|room_data| room_data.jump_target.is_some())
+                .unwrap_or(false)
+        });
+
+        if let Some(active_room) = jumped_room {
+            self.state.mark_history_fetch_start(&active_room);
+            command_writer
+                .write(&command::UserCommand::FetchRoomHistory(
+                    command::FetchRoomHistoryCommand {
+                        room: active_room,
+                        around: None,
+                        limit: Some(HISTORY_FETCH_LIMIT),
+                    },
+                ))
+                .await
+                .context("could not fetch room history")?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_file(
+        &mut self,
+        path: String,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        let Some(active_room) = self.state.active_room.clone() else {
+            return Ok(());
+        };
+
+        if !self.state.can_transfer_files {
+            self.show_toast(String::from("The server can not share files"));
+        } else if self.state.is_direct_message(&active_room) {
+            self.show_toast(String::from("Files can only be shared with the rooms"));
+        } else {
+            match self
+                .file_transfers
+                .start_upload(&active_room, Path::new(&path))
+            {
+                Ok(start) => {
+                    command_writer
+                        .write(&command::UserCommand::StartUpload(start))
+                        .await
+                        .context("could not start the upload")?;
+                    self.state.transfers = self.file_transfers.progress();
+                }
+                Err(err) => {
+                    self.show_toast(format!("Could not send {}: {}", path, err));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn save_file(
+        &mut self,
+        file_id: String,
+        path: String,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        if !self.state.can_transfer_files {
+            self.show_toast(String::from("The server can not share files"));
+            return Ok(());
+        }
+
+        match self
+            .file_transfers
+            .start_download(&file_id, PathBuf::from(path))
+        {
+            Ok(download) => {
+                command_writer
+                    .write(&command::UserCommand::DownloadFile(download))
+                    .await
+                    .context("could not start the download")?;
+                self.state.transfers = self.file_transfers.progress();
+            }
+            Err(err) => {
+                self.show_toast(format!("Could not save the file: {}", err));
+            }
+        }
 
-    Ok((event_stream, command_writer))
+        Ok(())
+    }
+
+    async fn export_room_history(
+        &mut self,
+        command_writer: &mut CommandWriter,
+    ) -> anyhow::Result<()> {
+        let Some(active_room) = self.state.active_room.clone() else {
+            return Ok(());
+        };
+
+        if self.state.is_direct_message(&active_room) {
+            self.show_toast(String::from("Direct messages can not be exported"));
+        } else if self.room_exports.contains_key(&active_room) {
+            self.show_toast(format!(
+                "The history of #{} is already being exported",
+                active_room
+            ));
+        } else {
+            match RoomExport::create(&active_room) {
+                Ok(room_export) => {
+                    self.room_exports.insert(active_room.clone(), room_export);
+                    command_writer
+                        .write(&command::UserCommand::ExportRoomHistory(
+                            command::ExportRoomHistoryCommand {
+                                room: active_room,
+                                after: None,
+                            },
+                        ))
+                        .await
+                        .context("could not export room history")?;
+                }
+                Err(err) => {
+                    self.show_toast(format!("Could not create the history export: {}", err));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn export_chat_log(&mut self, room: Option<String>, path: Option<String>) {
+        let Some(room) = room.or_else(|| self.state.active_room.clone()) else {
+            return;
+        };
+        let label = if room.starts_with('@') {
+            room.clone()
+        } else {
+            format!("#{}", room)
+        };
+
+        let Some(room_data) = self.state.room_data_map.get(&room) else {
+            self.show_toast(format!("There is no {} to export", label));
+            return;
+        };
+
+        let log = chat_log::render(
+            &room,
+            room_data.messages.asc_iter(),
+            &self.state.display_names,
+        );
+        let toast = match chat_log::save(&room, path.as_deref(), &log) {
+            Ok(path) => format!("Exported {} to {}", label, path.display()),
+            Err(err) => format!("Could not export {}: {}", label, err),
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn add_highlight_word(&mut self, word: String) {
+        let toast = if self.state.add_highlight_word(&word) {
+            self.config.highlight_words = self.state.highlight_words.clone();
+            self.save_config(format!("Highlighting \"{}\"", word))
+        } else {
+            format!("\"{}\" is already highlighted", word)
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn remove_highlight_word(&mut self, word: String) {
+        let toast = if self.state.remove_highlight_word(&word) {
+            self.config.highlight_words = self.state.highlight_words.clone();
+            self.save_config(format!("No longer highlighting \"{}\"", word))
+        } else {
+            format!("\"{}\" is not highlighted", word)
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn list_highlight_words(&mut self) {
+        let toast = if self.state.highlight_words.is_empty() {
+            String::from("No highlight words, add one with /highlight add <word>")
+        } else {
+            format!("Highlight words: {}", self.state.highlight_words.join(", "))
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn toggle_favorite_room(&mut self, room: Option<String>) {
+        let Some(room) = room.or_else(|| self.state.active_room.clone()) else {
+            return;
+        };
+
+        let toast = if self.state.is_direct_message(&room) {
+            String::from("Direct messages can not be favorites")
+        } else if !self.state.room_data_map.contains_key(&room) {
+            format!("There is no #{} to mark as a favorite", room)
+        } else {
+            let success = if self.state.toggle_favorite_room(&room) {
+                format!("#{} is a favorite", room)
+            } else {
+                format!("#{} is no longer a favorite", room)
+            };
+            self.config.favorite_rooms = self.state.favorite_rooms.clone();
+            self.save_config(success)
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn toggle_room_sort(&mut self) {
+        let success = match self.state.toggle_room_sort() {
+            RoomSort::Activity => "Listing the favorite and unread rooms first",
+            RoomSort::Alphabetical => "Listing the rooms by name",
+        };
+        self.config.room_sort = self.state.room_sort;
+        let toast = self.save_config(String::from(success));
+
+        self.show_toast(toast);
+    }
+
+    fn ignore_user(&mut self, user_id: String) {
+        let toast = if user_id == self.state.user_id {
+            String::from("You can not ignore yourself")
+        } else if self.state.ignore_user(&user_id) {
+            self.config.ignored_users = self.state.ignored_users.clone();
+            self.save_config(format!("Ignoring @{}", user_id))
+        } else {
+            format!("@{} is already ignored", user_id)
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn unignore_user(&mut self, user_id: String) {
+        let toast = if self.state.unignore_user(&user_id) {
+            self.config.ignored_users = self.state.ignored_users.clone();
+            self.save_config(format!("No longer ignoring @{}", user_id))
+        } else {
+            format!("@{} is not ignored", user_id)
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn list_ignored_users(&mut self) {
+        let toast = if self.state.ignored_users.is_empty() {
+            String::from("No ignored users, ignore one with /ignore <user>")
+        } else {
+            let users = self
+                .state
+                .ignored_users
+                .iter()
+                .map(|user_id| format!("@{}", user_id))
+                .collect::<Vec<_>>();
+
+            format!(
+                "Ignored users: {}, unignore one with /unignore <user>",
+                users.join(", ")
+            )
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn list_space_members(&mut self, space: String) {
+        let toast = match self.state.space_data_map.get(&space) {
+            Some(space_data) if space_data.has_joined => {
+                let mut members = space_data
+                    .members
+                    .iter()
+                    .map(|(user_id, role)| match role {
+                        event::SpaceRole::Admin => format!("{} (admin)", user_id),
+                        event::SpaceRole::Member => user_id.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                members.sort();
+
+                format!("Members of {}: {}", space, members.join(", "))
+            }
+            Some(_) => format!("Join {} to see its members", space),
+            None => format!("There is no space named {}", space),
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn set_theme(&mut self, name: String) {
+        let toast = if Theme::built_in(&name).is_some() {
+            self.config.theme = name.clone();
+            self.state.theme = Theme::resolve(&self.config.theme, &self.config.colors);
+            self.save_config(format!("Switched to the {} theme", name))
+        } else {
+            format!(
+                "Unknown theme \"{}\", pick one of {}",
+                name,
+                BUILT_IN_THEMES.join(", ")
+            )
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn toggle_theme(&mut self) {
+        self.config.theme = String::from(theme::next_built_in(&self.config.theme));
+        self.state.theme = Theme::resolve(&self.config.theme, &self.config.colors);
+
+        let toast = self.save_config(format!("Switched to the {} theme", self.config.theme));
+        self.show_toast(toast);
+    }
+
+    fn set_layout(&mut self, layout: LayoutConfig) {
+        self.state.layout = layout;
+        self.config.layout = layout;
+
+        // the panels are resized a step at a time, only a failure to save them is told
+        if let Some(Err(err)) = self
+            .config_path
+            .as_ref()
+            .map(|config_path| config::save(config_path, &self.config))
+        {
+            self.show_toast(format!("Could not save the config: {}", err));
+        }
+    }
+
+    fn toggle_do_not_disturb(&mut self) {
+        self.state.is_do_not_disturb = !self.state.is_do_not_disturb;
+
+        let toast = if self.state.is_do_not_disturb {
+            "Do not disturb, the desktop notifications are held back"
+        } else if self.state.desktop_notifications {
+            "The desktop notifications are back on"
+        } else {
+            "The desktop notifications are turned off in the config file"
+        };
+        self.show_toast(String::from(toast));
+    }
+
+    fn copy_to_clipboard(&mut self, content: String) {
+        let toast = match self
+            .terminal_tx
+            .send(TerminalRequest::CopyToClipboard(content))
+        {
+            Ok(_) => String::from("Copied to the clipboard"),
+            Err(err) => format!("Could not copy to the clipboard: {}", err),
+        };
+
+        self.show_toast(toast);
+    }
+
+    fn open_link(&mut self, url: String) {
+        let toast = match browser::open_in_browser(&url) {
+            Ok(true) => format!("Opened {} in the browser", url),
+            // the link can still be opened on the machine the terminal runs on, as over ssh
+            Ok(false) => match self.terminal_tx.send(TerminalRequest::CopyToClipboard(url)) {
+                Ok(_) => {
+                    String::from("No browser to open the link with, copied it to the clipboard")
+                }
+                Err(err) => format!("Could not copy the link to the clipboard: {}", err),
+            },
+            Err(err) => format!("Could not open the link: {}", err),
+        };
+
+        self.show_toast(toast);
+    }
 }
 
 impl StateStore {
@@ -49,91 +1906,100 @@ impl StateStore {
         mut action_rx: UnboundedReceiver<Action>,
         mut interrupt_rx: broadcast::Receiver<Interrupted>,
     ) -> anyhow::Result<Interrupted> {
+        let StateStore {
+            state_tx,
+            terminal_tx,
+            config_path,
+            loaded_config,
+        } = self;
         let mut opt_server_handle: Option<ServerHandle> = None;
-        let mut state = State::default();
+        let mut context = LoopContext::new(config_path, terminal_tx, loaded_config);
 
         // the initial state once
-        self.state_tx.send(state.clone())?;
+        state_tx.send(context.state.clone())?;
 
-        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        let mut ticker = tokio::time::interval(SCHEDULER_RESOLUTION);
 
         let result = loop {
             if let Some((event_stream, command_writer)) = opt_server_handle.as_mut() {
+                // the connection is handed over to the reconnection once the select is done with it
+                let mut is_connection_dropped = false;
+
                 tokio::select! {
                     // Handle the server events as they come in
                     maybe_event = event_stream.next() => match maybe_event {
-                        Some(Ok(event)) => {
-                            state.handle_server_event(&event);
-                        },
-                        // server disconnected, we need to reset the state
-                        None => {
-                            opt_server_handle = None;
-                            state = State::default();
+                        Some(Ok(event)) => match context.handle_server_event(event, command_writer).await {
+                            Ok(Connection::Kept) => (),
+                            Ok(Connection::Ended) => opt_server_handle = None,
+                            Err(_) => is_connection_dropped = true,
                         },
+                        // server disconnected, we need to reconnect or reset the state
+                        None => is_connection_dropped = true,
                         _ => (),
                     },
                     // Handle the actions coming from the UI
                     // and process them to do async operations
-                    Some(action) = action_rx.recv() => match action {
-                        Action::SendMessage { content } => {
-                            if let Some(active_room) = state.active_room.as_ref() {
-                                command_writer
-                                    .write(&command::UserCommand::SendMessage(
-                                        command::SendMessageCommand {
-                                            room: active_room.clone(),
-                                            content,
-                                        },
-                                    ))
-                                    .await
-                                    .context("could not send message")?;
-                            }
-                        },
-                        Action::SelectRoom { room } => {
-                            if let Some(false) = state.try_set_active_room(room.as_str()).map(|room_data| room_data.has_joined) {
-                                command_writer
-                                    .write(&command::UserCommand::JoinRoom(command::JoinRoomCommand {
-                                        room,
-                                    }))
-                                    .await
-                                    .context("could not join room")?;
-                            }
-                        },
-                        Action::Exit => {
+                    Some(action) = action_rx.recv() => {
+                        if let Action::Exit = action {
                             let _ = terminator.terminate(Interrupted::UserInt);
 
                             break Interrupted::UserInt;
-                        },
-                        _ => (),
+                        }
+
+                        // the connection is dropped on purpose, then resumed as when it drops on its own
+                        if let Action::Reconnect = action {
+                            context.show_toast(String::from("Reconnecting to the server"));
+                            is_connection_dropped = true;
+                        }
+
+                        if let Err(err) = context.handle_action(action, command_writer).await {
+                            context.show_toast(format!("The connection to the server dropped, {}", err));
+                            is_connection_dropped = true;
+                        }
+                    },
+                    // Hand the previews of the images over to the state once they are decoded
+                    Some((key, preview)) = context.image_previews.next_preview() => {
+                        context.state.image_previews.insert(key, preview);
                     },
-                    // Tick to terminate the select every N milliseconds
+                    // Tick to run the scheduled tasks which are due
                     _ = ticker.tick() => {
-                        state.tick_timer();
+                        is_connection_dropped |= context.run_due_tasks_while_connected();
                     },
                     // Catch and handle interrupt signal to gracefully shutdown
                     Ok(interrupted) = interrupt_rx.recv() => {
                         break interrupted;
                     }
                 }
+
+                if let Some((_, command_writer)) = opt_server_handle
+                    .as_mut()
+                    .filter(|_| !is_connection_dropped)
+                {
+                    is_connection_dropped = context
+                        .request_image_previews(command_writer)
+                        .await
+                        .is_err();
+                }
+
+                if is_connection_dropped {
+                    opt_server_handle = None;
+                    context.handle_dropped_connection();
+                }
             } else {
                 tokio::select! {
                     Some(action) = action_rx.recv() => match action {
                         Action::ConnectToServerRequest { addr } => {
-                            state.mark_connection_request_start();
+                            context.state.mark_connection_request_start();
                             // emit event to re-render any part depending on the connection status
-                            self.state_tx.send(state.clone())?;
-
-                            match create_server_handle(&addr).await {
-                                Ok(server_handle) => {
-                                    // set the server handle and change status for further processing
-                                    let _ = opt_server_handle.insert(server_handle);
-                                    state.process_connection_request_result(Ok(addr));
-                                    // ticker needs to be resetted to avoid showing time spent inputting and connecting to the server address
-                                    ticker.reset();
-                                },
-                                Err(err) => {
-                                    state.process_connection_request_result(Err(err));
-                                }
-                            }
+                            state_tx.send(context.state.clone())?;
+
+                            opt_server_handle = context.connect(addr).await;
+                        },
+                        Action::DismissIncompatibleServer => {
+                            context.state.dismiss_incompatible_server();
+                        },
+                        Action::ApplyConfigMigration => {
+                            context.apply_config_migration();
                         },
                         Action::Exit => {
                             let _ = terminator.terminate(Interrupted::UserInt);
@@ -142,6 +2008,10 @@ impl StateStore {
                         },
                         _ => (),
                     },
+                    // Tick to try to reconnect, and to run the other scheduled tasks meanwhile
+                    _ = ticker.tick() => {
+                        opt_server_handle = context.run_due_tasks_while_disconnected().await;
+                    },
                     // Catch and handle interrupt signal to gracefully shutdown
                     Ok(interrupted) = interrupt_rx.recv() => {
                         break interrupted;
@@ -149,7 +2019,7 @@ impl StateStore {
                 }
             }
 
-            self.state_tx.send(state.clone())?;
+            state_tx.send(context.state.clone())?;
         };
 
         Ok(result)
@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// [TerminalRequest] asks the UI manager, which owns the terminal, to write to it between two frames
+///
+/// The state store never writes to the terminal itself, as its writes could interleave with the frames.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminalRequest {
+    /// Copies the content to the system clipboard
+    CopyToClipboard(String),
+}
+
+impl TerminalRequest {
+    /// Writes the escape sequence of the request at once
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let sequence = match self {
+            // OSC 52 works over ssh and does not require a clipboard daemon
+            TerminalRequest::CopyToClipboard(content) => {
+                format!("\x1b]52;c;{}\x07", STANDARD.encode(content))
+            }
+        };
+
+        writer.write_all(sequence.as_bytes())?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_the_clipboard_is_written_in_one_escape_sequence() {
+        let mut written = vec![];
+
+        TerminalRequest::CopyToClipboard(String::from("helo"))
+            .write_to(&mut written)
+            .unwrap();
+
+        assert_eq!(written, b"\x1b]52;c;aGVsbw==\x07");
+    }
+}
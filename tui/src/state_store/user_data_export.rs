@@ -0,0 +1,16 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use comms::event::UserDataExportReplyEvent;
+
+/// Saves the data exported by the server to a new json file in the current directory, returning the path of the file
+pub fn save(export: &UserDataExportReplyEvent) -> anyhow::Result<PathBuf> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = PathBuf::from(format!("chat-data-export-{}.json", millis));
+
+    std::fs::write(&path, serde_json::to_string_pretty(export)?)?;
+
+    Ok(path)
+}
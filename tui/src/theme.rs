@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use ratatui::style::Color;
+
+/// The names of the themes shipped with the client, the first one is the default
+pub const BUILT_IN_THEMES: [&str; 3] = ["dark", "light", "high-contrast"];
+
+/// The built-in theme following the one with the given name, in the order they are listed, the default one
+/// following an unknown theme
+pub fn next_built_in(name: &str) -> &'static str {
+    let next = BUILT_IN_THEMES
+        .iter()
+        .position(|built_in| *built_in == name)
+        .map_or(0, |index| (index + 1) % BUILT_IN_THEMES.len());
+
+    BUILT_IN_THEMES[next]
+}
+
+/// [Theme] holds the colors of the UI by the role they play, rather than where they are used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// The border of the widget handling input, and of the overlays
+    pub active_border: Color,
+    /// The border of the hovered widget
+    pub hovered_border: Color,
+    /// The text typed into the inputs
+    pub input: Color,
+    /// The user's own name, the messages highlighting them and the rooms mentioning them
+    pub accent: Color,
+    /// The toasts, the rate limits and the connection drops
+    pub warning: Color,
+    pub error: Color,
+    /// The online users and the added settings
+    pub success: Color,
+    /// The offline users and the rooms being joined
+    pub muted: Color,
+    /// The background of the selected entry of the lists
+    pub selection_bg: Color,
+    pub selection_fg: Color,
+    /// The background of the selected message
+    pub selected_message_bg: Color,
+    pub code: Color,
+    pub quote: Color,
+    pub mention: Color,
+    /// The colors the names of the users are picked from in the messages, unless they picked their own
+    pub names: [Color; 6],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            active_border: Color::Yellow,
+            hovered_border: Color::Blue,
+            input: Color::Yellow,
+            accent: Color::Yellow,
+            warning: Color::Yellow,
+            error: Color::Red,
+            success: Color::Green,
+            muted: Color::DarkGray,
+            // yellow that would work for both dark / light modes
+            selection_bg: Color::Rgb(255, 223, 102),
+            selection_fg: Color::Reset,
+            selected_message_bg: Color::DarkGray,
+            code: Color::Cyan,
+            quote: Color::Gray,
+            mention: Color::Magenta,
+            names: [
+                Color::LightBlue,
+                Color::LightGreen,
+                Color::LightMagenta,
+                Color::LightCyan,
+                Color::LightRed,
+                Color::Rgb(255, 175, 95),
+            ],
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            active_border: Color::Blue,
+            hovered_border: Color::Cyan,
+            input: Color::Blue,
+            accent: Color::Magenta,
+            warning: Color::Rgb(175, 95, 0),
+            error: Color::Red,
+            success: Color::Green,
+            muted: Color::Gray,
+            selection_bg: Color::Rgb(255, 223, 102),
+            selection_fg: Color::Black,
+            selected_message_bg: Color::Rgb(215, 215, 215),
+            code: Color::Blue,
+            quote: Color::DarkGray,
+            mention: Color::Magenta,
+            names: [
+                Color::Blue,
+                Color::Rgb(0, 135, 0),
+                Color::Magenta,
+                Color::Rgb(0, 135, 135),
+                Color::Red,
+                Color::Rgb(175, 95, 0),
+            ],
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Theme {
+            active_border: Color::LightYellow,
+            hovered_border: Color::LightCyan,
+            input: Color::White,
+            accent: Color::LightYellow,
+            warning: Color::LightYellow,
+            error: Color::LightRed,
+            success: Color::LightGreen,
+            muted: Color::Gray,
+            selection_bg: Color::White,
+            selection_fg: Color::Black,
+            selected_message_bg: Color::Blue,
+            code: Color::LightCyan,
+            quote: Color::White,
+            mention: Color::LightMagenta,
+            names: [
+                Color::LightCyan,
+                Color::LightGreen,
+                Color::LightMagenta,
+                Color::LightBlue,
+                Color::LightRed,
+                Color::White,
+            ],
+        }
+    }
+
+    /// The color the name of the user is shown with, picked from the names of the theme by a hash of their id
+    ///
+    /// The hash is FNV-1a rather than the one of the standard library, so a user keeps their color
+    /// from a run to the next.
+    pub fn name_color(&self, user_id: &str) -> Color {
+        let hash = user_id.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+
+        self.names[(hash % self.names.len() as u64) as usize]
+    }
+
+    /// Returns the built-in theme with the given name
+    pub fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// The built-in theme with the given name, or the default one, with the colors overridden by their role
+    ///
+    /// The unknown roles and the colors which can not be parsed, such as `"#12345"`, are ignored.
+    pub fn resolve(name: &str, colors: &BTreeMap<String, String>) -> Self {
+        let mut theme = Theme::built_in(name).unwrap_or_default();
+
+        for (role, color) in colors {
+            let Ok(color) = Color::from_str(color) else {
+                continue;
+            };
+
+            if let Some(slot) = theme.role_mut(role) {
+                *slot = color;
+            }
+        }
+
+        theme
+    }
+
+    fn role_mut(&mut self, role: &str) -> Option<&mut Color> {
+        Some(match role {
+            "active_border" => &mut self.active_border,
+            "hovered_border" => &mut self.hovered_border,
+            "input" => &mut self.input,
+            "accent" => &mut self.accent,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "success" => &mut self.success,
+            "muted" => &mut self.muted,
+            "selection_bg" => &mut self.selection_bg,
+            "selection_fg" => &mut self.selection_fg,
+            "selected_message_bg" => &mut self.selected_message_bg,
+            "code" => &mut self.code,
+            "quote" => &mut self.quote,
+            "mention" => &mut self.mention,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors_override_the_theme() {
+        let colors = BTreeMap::from([
+            (String::from("accent"), String::from("light blue")),
+            (String::from("error"), String::from("#ff8800")),
+            (String::from("mention"), String::from("not a color")),
+            (String::from("background"), String::from("black")),
+        ]);
+
+        let theme = Theme::resolve("light", &colors);
+
+        assert_eq!(theme.accent, Color::LightBlue);
+        assert_eq!(theme.error, Color::Rgb(255, 136, 0));
+        assert_eq!(theme.mention, Theme::light().mention);
+        assert_eq!(theme.input, Theme::light().input);
+    }
+
+    #[test]
+    fn test_unknown_theme_falls_back_to_the_default() {
+        assert_eq!(Theme::resolve("solarized", &BTreeMap::new()), Theme::dark());
+
+        for name in BUILT_IN_THEMES {
+            assert!(Theme::built_in(name).is_some());
+        }
+    }
+
+    #[test]
+    fn test_name_colors_are_stable_and_from_the_theme() {
+        let theme = Theme::light();
+
+        assert_eq!(theme.name_color("alice"), theme.name_color("alice"));
+        assert!(theme.names.contains(&theme.name_color("bob")));
+        assert!(["alice", "bob", "carol", "dave", "erin"]
+            .iter()
+            .any(|user_id| theme.name_color(user_id) != theme.name_color("alice")));
+    }
+
+    #[test]
+    fn test_toggled_themes_cycle() {
+        assert_eq!(next_built_in("dark"), "light");
+        assert_eq!(next_built_in("high-contrast"), "dark");
+        assert_eq!(next_built_in("solarized"), "dark");
+    }
+}
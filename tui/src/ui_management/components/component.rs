@@ -1,4 +1,4 @@
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyEvent, MouseEvent};
 use ratatui::{prelude::Backend, Frame};
 use tokio::sync::mpsc::UnboundedSender;
 
@@ -15,6 +15,9 @@ pub trait Component {
     fn name(&self) -> &str;
 
     fn handle_key_event(&mut self, key: KeyEvent);
+
+    /// Most components are keyboard only, hence the mouse events are ignored unless handled
+    fn handle_mouse_event(&mut self, _mouse: MouseEvent) {}
 }
 
 pub trait ComponentRender<Props> {
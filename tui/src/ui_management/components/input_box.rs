@@ -1,8 +1,9 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
 use ratatui::{
-    prelude::{Backend, Rect},
+    prelude::{Alignment, Backend, Rect},
     style::{Color, Style, Stylize},
-    widgets::{Block, Borders, Paragraph},
+    text::Span,
+    widgets::{block, Block, Borders, Paragraph},
     Frame,
 };
 use tokio::sync::mpsc::UnboundedSender;
@@ -14,8 +15,10 @@ use super::{Component, ComponentRender};
 pub struct InputBox {
     /// Current value of the input box
     text: String,
-    /// Position of cursor in the editor area.
+    /// Position of cursor in the editor area, in characters rather than bytes
     cursor_position: usize,
+    /// Should the text be hidden behind a mask, as for a password
+    is_masked: bool,
 }
 
 impl InputBox {
@@ -25,7 +28,20 @@ impl InputBox {
 
     pub fn set_text(&mut self, new_text: &str) {
         self.text = String::from(new_text);
-        self.cursor_position = self.text.len();
+        self.cursor_position = self.text.chars().count();
+    }
+
+    pub fn cursor_position(&self) -> usize {
+        self.cursor_position
+    }
+
+    /// Replaces the characters between the positions with the given text, and moves the cursor after it
+    pub fn replace_range(&mut self, start: usize, end: usize, replacement: &str) {
+        let before = self.text.chars().take(start);
+        let after = self.text.chars().skip(end);
+
+        self.text = before.chain(replacement.chars()).chain(after).collect();
+        self.cursor_position = self.clamp_cursor(start + replacement.chars().count());
     }
 
     pub fn reset(&mut self) {
@@ -37,6 +53,10 @@ impl InputBox {
         self.text.is_empty()
     }
 
+    pub fn set_masked(&mut self, is_masked: bool) {
+        self.is_masked = is_masked;
+    }
+
     fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.cursor_position.saturating_sub(1);
         self.cursor_position = self.clamp_cursor(cursor_moved_left);
@@ -48,7 +68,13 @@ impl InputBox {
     }
 
     fn enter_char(&mut self, new_char: char) {
-        self.text.insert(self.cursor_position, new_char);
+        let byte_index = self
+            .text
+            .char_indices()
+            .nth(self.cursor_position)
+            .map(|(byte_index, _)| byte_index)
+            .unwrap_or(self.text.len());
+        self.text.insert(byte_index, new_char);
 
         self.move_cursor_right();
     }
@@ -76,7 +102,7 @@ impl InputBox {
     }
 
     fn clamp_cursor(&self, new_cursor_pos: usize) -> usize {
-        new_cursor_pos.clamp(0, self.text.len())
+        new_cursor_pos.clamp(0, self.text.chars().count())
     }
 }
 
@@ -86,6 +112,7 @@ impl Component for InputBox {
             //
             text: String::new(),
             cursor_position: 0,
+            is_masked: false,
         }
     }
 
@@ -127,19 +154,30 @@ pub struct RenderProps {
     pub title: String,
     pub area: Rect,
     pub border_color: Color,
+    /// The color of the typed text
+    pub text_color: Color,
     pub show_cursor: bool,
+    /// Shown on the right of the title, such as how many characters are typed
+    pub counter: Option<Span<'static>>,
 }
 
 impl ComponentRender<RenderProps> for InputBox {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
-        let input = Paragraph::new(self.text.as_str())
-            .style(Style::default().fg(Color::Yellow))
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .fg(props.border_color)
-                    .title(props.title),
-            );
+        let text = if self.is_masked {
+            "*".repeat(self.text.chars().count())
+        } else {
+            self.text.clone()
+        };
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .fg(props.border_color)
+            .title(props.title);
+        if let Some(counter) = props.counter {
+            block = block.title(block::Title::from(counter).alignment(Alignment::Right));
+        }
+        let input = Paragraph::new(text)
+            .style(Style::default().fg(props.text_color))
+            .block(block);
         frame.render_widget(input, props.area);
 
         // Cursor is hidden by default, so we need to make it visible if the input box is selected
@@ -0,0 +1,354 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::Span,
+};
+
+use crate::{state_store::links, theme::Theme};
+
+const CODE_FENCE: &str = "```";
+const QUOTE_PREFIX: &str = "> ";
+const MENTION_PREFIX: char = '@';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentKind {
+    /// Plain text without any markup
+    Plain,
+    /// Code surrounded by single backticks
+    InlineCode,
+    /// Code surrounded by triple backticks
+    CodeBlock,
+    /// A line starting with `> `
+    Quote,
+    /// A user mentioned with `@user`, the prefix included
+    Mention,
+    /// A web link, such as `https://example.com`
+    Link,
+}
+
+/// A continuous part of a message with a single kind of markup, markup characters excluded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub text: String,
+}
+
+impl Segment {
+    fn new(kind: SegmentKind, text: &str) -> Self {
+        Segment {
+            kind,
+            text: String::from(text),
+        }
+    }
+
+    /// Is the segment a selectable region such as a code block or a quote
+    pub fn is_region(&self) -> bool {
+        !matches!(
+            self.kind,
+            SegmentKind::Plain | SegmentKind::Mention | SegmentKind::Link
+        )
+    }
+}
+
+/// A message parsed into segments, keeping the regions which can be selected by the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedMessage {
+    pub segments: Vec<Segment>,
+}
+
+impl ParsedMessage {
+    /// The selectable regions of the message, in the order they appear
+    pub fn regions(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|segment| segment.is_region())
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.regions().count()
+    }
+
+    /// The links of the message, in the order they appear
+    pub fn links(&self) -> impl Iterator<Item = &str> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.kind == SegmentKind::Link)
+            .map(|segment| segment.text.as_str())
+    }
+
+    /// Renders the segments to spans, highlighting the region with the given index
+    pub fn to_spans<'a>(&self, selected_region: Option<usize>, theme: &Theme) -> Vec<Span<'a>> {
+        let mut region_idx = 0;
+
+        self.segments
+            .iter()
+            .map(|segment| {
+                let mut style = match segment.kind {
+                    SegmentKind::Plain => Style::default(),
+                    SegmentKind::InlineCode | SegmentKind::CodeBlock => {
+                        Style::default().fg(theme.code)
+                    }
+                    SegmentKind::Quote => Style::default()
+                        .fg(theme.quote)
+                        .add_modifier(Modifier::ITALIC),
+                    SegmentKind::Mention => Style::default()
+                        .fg(theme.mention)
+                        .add_modifier(Modifier::BOLD),
+                    SegmentKind::Link => Style::default().add_modifier(Modifier::UNDERLINED),
+                };
+
+                if segment.is_region() {
+                    if selected_region == Some(region_idx) {
+                        style = style.add_modifier(Modifier::REVERSED);
+                    }
+
+                    region_idx += 1;
+                }
+
+                // the message list renders a single line per message
+                let text = match segment.kind {
+                    SegmentKind::Quote => format!("│ {}", segment.text),
+                    _ => segment.text.clone(),
+                };
+
+                Span::styled(text.replace('\n', " "), style)
+            })
+            .collect()
+    }
+}
+
+/// Parses the markup of a message into segments
+///
+/// Supports code blocks (```), inline code (`) and quote lines (> ), links and mentions (@user) are parsed out of the plain text.
+pub fn parse(content: &str) -> ParsedMessage {
+    let mut segments = vec![];
+
+    // code blocks are split first, since they may span multiple lines and contain other markup
+    for (idx, part) in content.split(CODE_FENCE).enumerate() {
+        let is_inside_fence = idx % 2 == 1;
+
+        if is_inside_fence {
+            segments.push(Segment::new(
+                SegmentKind::CodeBlock,
+                part.trim_matches('\n'),
+            ));
+        } else {
+            parse_lines(part, &mut segments);
+        }
+    }
+
+    // an unclosed fence is not a code block, treat the whole content as is
+    if content.matches(CODE_FENCE).count() % 2 == 1 {
+        segments.clear();
+        parse_lines(content, &mut segments);
+    }
+
+    merge_plain_segments(&mut segments);
+
+    ParsedMessage { segments }
+}
+
+fn parse_lines(text: &str, segments: &mut Vec<Segment>) {
+    for (idx, line) in text.split('\n').enumerate() {
+        if idx > 0 {
+            segments.push(Segment::new(SegmentKind::Plain, "\n"));
+        }
+
+        if let Some(quote) = line.strip_prefix(QUOTE_PREFIX) {
+            segments.push(Segment::new(SegmentKind::Quote, quote));
+        } else {
+            parse_inline_code(line, segments);
+        }
+    }
+}
+
+fn parse_inline_code(line: &str, segments: &mut Vec<Segment>) {
+    let parts = line.split('`').collect::<Vec<_>>();
+    // an unclosed backtick is kept as a plain character
+    let closed_parts = if parts.len() % 2 == 0 {
+        parts.len() - 1
+    } else {
+        parts.len()
+    };
+
+    for (idx, part) in parts.iter().take(closed_parts).enumerate() {
+        if idx % 2 == 1 {
+            segments.push(Segment::new(SegmentKind::InlineCode, part));
+        } else {
+            parse_links(part, segments);
+        }
+    }
+
+    if closed_parts < parts.len() {
+        parse_links(&format!("`{}", parts[closed_parts]), segments);
+    }
+}
+
+/// Splits the links out of plain text, the rest of which may hold mentions
+fn parse_links(text: &str, segments: &mut Vec<Segment>) {
+    let mut plain_start = 0;
+
+    for link in links::find_links(text) {
+        parse_mentions(&text[plain_start..link.start], segments);
+        segments.push(Segment::new(SegmentKind::Link, &text[link.clone()]));
+        plain_start = link.end;
+    }
+
+    parse_mentions(&text[plain_start..], segments);
+}
+
+fn is_mention_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_' || char == '-'
+}
+
+/// Splits the `@user` mentions out of plain text, an `@` within a word such as an email address is not a mention
+fn parse_mentions(text: &str, segments: &mut Vec<Segment>) {
+    let mut plain_start = 0;
+    let mut chars = text.char_indices().peekable();
+    let mut previous = None;
+
+    while let Some((idx, char)) = chars.next() {
+        let is_mention_start = char == MENTION_PREFIX
+            && !previous.map(is_mention_char).unwrap_or(false)
+            && chars
+                .peek()
+                .map(|(_, next)| is_mention_char(*next))
+                .unwrap_or(false);
+        previous = Some(char);
+
+        if !is_mention_start {
+            continue;
+        }
+
+        let mut end = text.len();
+        while let Some((next_idx, next)) = chars.peek().copied() {
+            if !is_mention_char(next) {
+                end = next_idx;
+                break;
+            }
+            previous = Some(next);
+            chars.next();
+        }
+
+        segments.push(Segment::new(SegmentKind::Plain, &text[plain_start..idx]));
+        segments.push(Segment::new(SegmentKind::Mention, &text[idx..end]));
+        plain_start = end;
+    }
+
+    segments.push(Segment::new(SegmentKind::Plain, &text[plain_start..]));
+}
+
+/// Joins consecutive plain segments and drops the empty ones
+fn merge_plain_segments(segments: &mut Vec<Segment>) {
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+
+    for segment in segments.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.kind == SegmentKind::Plain && segment.kind == SegmentKind::Plain => {
+                last.text.push_str(&segment.text);
+            }
+            _ if segment.kind == SegmentKind::Plain && segment.text.is_empty() => {}
+            _ => merged.push(segment),
+        }
+    }
+
+    *segments = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(kind: SegmentKind, text: &str) -> Segment {
+        Segment::new(kind, text)
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            parse("hello world").segments,
+            vec![segment(SegmentKind::Plain, "hello world")]
+        );
+    }
+
+    #[test]
+    fn test_inline_code() {
+        assert_eq!(
+            parse("run `cargo test` now").segments,
+            vec![
+                segment(SegmentKind::Plain, "run "),
+                segment(SegmentKind::InlineCode, "cargo test"),
+                segment(SegmentKind::Plain, " now"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_backtick_is_plain() {
+        assert_eq!(
+            parse("a ` b").segments,
+            vec![segment(SegmentKind::Plain, "a ` b")]
+        );
+    }
+
+    #[test]
+    fn test_code_block() {
+        assert_eq!(
+            parse("see ```fn main() {}``` here").segments,
+            vec![
+                segment(SegmentKind::Plain, "see "),
+                segment(SegmentKind::CodeBlock, "fn main() {}"),
+                segment(SegmentKind::Plain, " here"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        let parsed = parse("> quoted\nreply");
+
+        assert_eq!(
+            parsed.segments,
+            vec![
+                segment(SegmentKind::Quote, "quoted"),
+                segment(SegmentKind::Plain, "\nreply"),
+            ]
+        );
+        assert_eq!(parsed.region_count(), 1);
+    }
+
+    #[test]
+    fn test_mentions() {
+        let parsed = parse("hi @alice and @guest-1a2b, mail bob@example.com or `@code`");
+
+        assert_eq!(
+            parsed.segments,
+            vec![
+                segment(SegmentKind::Plain, "hi "),
+                segment(SegmentKind::Mention, "@alice"),
+                segment(SegmentKind::Plain, " and "),
+                segment(SegmentKind::Mention, "@guest-1a2b"),
+                segment(SegmentKind::Plain, ", mail bob@example.com or "),
+                segment(SegmentKind::InlineCode, "@code"),
+            ]
+        );
+        assert_eq!(parsed.region_count(), 1);
+    }
+
+    #[test]
+    fn test_links() {
+        let parsed = parse("docs at https://example.com/@alice, ask @bob");
+
+        assert_eq!(
+            parsed.segments,
+            vec![
+                segment(SegmentKind::Plain, "docs at "),
+                segment(SegmentKind::Link, "https://example.com/@alice"),
+                segment(SegmentKind::Plain, ", ask "),
+                segment(SegmentKind::Mention, "@bob"),
+            ]
+        );
+        assert_eq!(
+            parsed.links().collect::<Vec<_>>(),
+            vec!["https://example.com/@alice"]
+        );
+        assert_eq!(parsed.region_count(), 0);
+    }
+}
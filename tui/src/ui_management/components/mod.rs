@@ -1,4 +1,6 @@
 mod component;
 
 pub mod input_box;
+pub mod markdown;
+pub mod wrap;
 pub use component::{Component, ComponentRender};
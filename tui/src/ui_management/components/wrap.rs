@@ -0,0 +1,239 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Builds the wrapped lines, span by span, keeping the styles of the wrapped text
+struct LineBuilder<'a> {
+    lines: Vec<Line<'a>>,
+    spans: Vec<Span<'a>>,
+    /// The width of the spans of the current line, the indentation included
+    line_width: usize,
+    width: usize,
+    indent: usize,
+}
+
+impl<'a> LineBuilder<'a> {
+    /// Whether nothing but the indentation is on the current line
+    fn is_line_empty(&self) -> bool {
+        let start_width = if self.lines.is_empty() {
+            0
+        } else {
+            self.indent
+        };
+
+        self.line_width <= start_width
+    }
+
+    fn remaining_width(&self) -> usize {
+        self.width.saturating_sub(self.line_width)
+    }
+
+    fn push(&mut self, text: String, style: Style) {
+        if text.is_empty() {
+            return;
+        }
+
+        self.line_width += text.width();
+        self.spans.push(Span::styled(text, style));
+    }
+
+    /// Moves to a new line, indented under the content of the first one
+    fn break_line(&mut self) {
+        self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+        self.line_width = 0;
+        if self.indent > 0 {
+            self.push(" ".repeat(self.indent), Style::default());
+        }
+    }
+
+    fn push_chunk(&mut self, chunk: Option<Chunk>) {
+        for (text, style) in chunk.map(|chunk| chunk.pieces).unwrap_or_default() {
+            self.push(text, style);
+        }
+    }
+
+    /// Pushes the pieces of a word, breaking it across lines wherever the line is full
+    fn push_broken(&mut self, pieces: Vec<(String, Style)>) {
+        for (text, style) in pieces {
+            let mut part = String::new();
+
+            for char in text.chars() {
+                let char_width = char.width().unwrap_or(0);
+                if self.line_width + part.width() + char_width > self.width
+                    && !(self.is_line_empty() && part.is_empty())
+                {
+                    self.push(std::mem::take(&mut part), style);
+                    self.break_line();
+                }
+                part.push(char);
+            }
+
+            self.push(part, style);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Line<'a>> {
+        if !self.spans.is_empty() || self.lines.is_empty() {
+            self.lines.push(Line::from(self.spans));
+        }
+
+        self.lines
+    }
+}
+
+/// A run of whitespace or a word, made of the pieces of the spans it spreads over
+struct Chunk {
+    is_space: bool,
+    pieces: Vec<(String, Style)>,
+}
+
+impl Chunk {
+    fn width(&self) -> usize {
+        self.pieces.iter().map(|(text, _)| text.width()).sum()
+    }
+}
+
+/// Splits the spans into words and runs of whitespace, a word can spread over spans of different styles
+fn chunks(spans: Vec<Span<'_>>) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = vec![];
+
+    for span in spans {
+        let mut rest = span.content.as_ref();
+
+        while let Some(first) = rest.chars().next() {
+            let is_space = first.is_whitespace();
+            let end = rest
+                .find(|char: char| char.is_whitespace() != is_space)
+                .unwrap_or(rest.len());
+            let piece = (String::from(&rest[..end]), span.style);
+            rest = &rest[end..];
+
+            match chunks.last_mut() {
+                Some(chunk) if chunk.is_space == is_space => chunk.pieces.push(piece),
+                _ => chunks.push(Chunk {
+                    is_space,
+                    pieces: vec![piece],
+                }),
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Wraps the spans into lines no wider than the given width, breaking between words where possible
+///
+/// The lines after the first one are indented by `indent` columns, so they line up under the
+/// content rather than a prefix such as the name of the sender. The indentation is left out when
+/// it would take more than half of the width.
+pub fn wrap<'a>(spans: Vec<Span<'a>>, width: usize, indent: usize) -> Vec<Line<'a>> {
+    if width == 0 {
+        return vec![Line::from(spans)];
+    }
+
+    let mut builder = LineBuilder {
+        lines: vec![],
+        spans: vec![],
+        line_width: 0,
+        width,
+        indent: if indent * 2 <= width { indent } else { 0 },
+    };
+
+    // the whitespace is only pushed before the next word on the same line, so lines do not end with it
+    let mut pending_space: Option<Chunk> = None;
+
+    for chunk in chunks(spans) {
+        if chunk.is_space {
+            pending_space = Some(chunk);
+            continue;
+        }
+
+        let space = pending_space.take();
+        let space_width = space.as_ref().map(Chunk::width).unwrap_or(0);
+        let chunk_width = chunk.width();
+
+        if space_width + chunk_width <= builder.remaining_width() {
+            builder.push_chunk(space);
+            builder.push_chunk(Some(chunk));
+        } else if chunk_width <= builder.width - builder.indent && !builder.is_line_empty() {
+            // words fitting in a line are moved to the next one
+            builder.break_line();
+            builder.push_chunk(Some(chunk));
+        } else {
+            // the longer ones are broken wherever the line is full
+            if space_width < builder.remaining_width() {
+                builder.push_chunk(space);
+            } else if !builder.is_line_empty() {
+                builder.break_line();
+            }
+            builder.push_broken(chunk.pieces);
+        }
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Modifier, Stylize};
+
+    use super::*;
+
+    fn texts(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lines_are_broken_between_words_with_a_hanging_indent() {
+        let spans = vec![
+            Span::raw("@alice: "),
+            Span::raw("the quick brown "),
+            Span::raw("fox").bold(),
+            Span::raw(" jumps over the lazy dog"),
+        ];
+
+        let lines = wrap(spans, 20, 8);
+
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "@alice: the quick",
+                "        brown fox",
+                "        jumps over",
+                "        the lazy dog",
+            ]
+        );
+        assert!(lines
+            .iter()
+            .all(|line| line.spans.iter().map(|span| span.width()).sum::<usize>() <= 20));
+        assert!(lines[1]
+            .spans
+            .iter()
+            .any(|span| span.content == "fox" && span.style.add_modifier.contains(Modifier::BOLD)));
+    }
+
+    #[test]
+    fn test_long_words_are_broken_by_their_width() {
+        let spans = vec![Span::raw("@bob: "), Span::raw("日本語のテキスト")];
+
+        assert_eq!(
+            texts(&wrap(spans.clone(), 12, 6)),
+            vec!["@bob: 日本語", "      のテキ", "      スト"]
+        );
+        // the indentation would take more than half of the width
+        assert_eq!(
+            texts(&wrap(spans, 10, 6)),
+            vec!["@bob: 日本", "語のテキス", "ト"]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use ratatui::{prelude::*, widgets::*, Frame};
+
+use crate::config::LayoutConfig;
+
+/// The smallest terminal the pages are laid out in, a smaller one is asked to be enlarged instead
+pub const MIN_WIDTH: u16 = 40;
+pub const MIN_HEIGHT: u16 = 14;
+
+/// The widest share of the width a side panel can take, so the messages are always left some room
+const MAX_PANEL_PERCENT: u16 = 40;
+/// The narrowest share of the width a side panel is resized to, a narrower one is better collapsed
+const MIN_PANEL_PERCENT: u16 = 10;
+/// How much a key press widens or narrows a side panel, in percent of the width
+pub const RESIZE_STEP_PERCENT: i32 = 5;
+
+/// Which side of the messages a panel of the chat page is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// [PanelLayout] shares the width of the chat page between the side panels and the messages
+///
+/// A panel is collapsed while the terminal is narrower than configured for it, unless the user has toggled it,
+/// which holds until they toggle it again.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PanelLayout {
+    config: LayoutConfig,
+    /// Is the left panel shown, as last toggled by the user
+    left_toggled: Option<bool>,
+    right_toggled: Option<bool>,
+}
+
+impl PanelLayout {
+    /// Keeps the panels the user toggled, the rest follow the new config
+    pub fn with_config(self, config: LayoutConfig) -> Self {
+        PanelLayout { config, ..self }
+    }
+
+    pub fn config(&self) -> LayoutConfig {
+        self.config
+    }
+
+    pub fn is_shown(&self, side: Side, width: u16) -> bool {
+        let (toggled, collapse_below) = match side {
+            Side::Left => (self.left_toggled, self.config.collapse_left_panel_below),
+            Side::Right => (self.right_toggled, self.config.collapse_right_panel_below),
+        };
+
+        toggled.unwrap_or(width >= collapse_below)
+    }
+
+    /// Collapses the panel if it is shown in a terminal of the given width, expands it otherwise
+    pub fn toggle(&mut self, side: Side, width: u16) {
+        let is_shown = self.is_shown(side, width);
+
+        match side {
+            Side::Left => self.left_toggled = Some(!is_shown),
+            Side::Right => self.right_toggled = Some(!is_shown),
+        }
+    }
+
+    /// The config with the panel widened by the given percent of the width, or narrowed when it is negative
+    pub fn resized(&self, side: Side, delta_percent: i32) -> LayoutConfig {
+        let percent = match side {
+            Side::Left => self.config.left_panel_percent,
+            Side::Right => self.config.right_panel_percent,
+        };
+
+        self.with_percent(side, i32::from(percent) + delta_percent)
+    }
+
+    /// The config with the inner border of the panel dragged to the column, in a page of the given width
+    pub fn dragged(&self, side: Side, column: u16, width: u16) -> LayoutConfig {
+        let width = i32::from(width.max(1));
+        let column = i32::from(column);
+        let panel_width = match side {
+            Side::Left => column + 1,
+            Side::Right => width - column,
+        };
+
+        self.with_percent(side, panel_width * 100 / width)
+    }
+
+    fn with_percent(&self, side: Side, percent: i32) -> LayoutConfig {
+        let percent =
+            percent.clamp(i32::from(MIN_PANEL_PERCENT), i32::from(MAX_PANEL_PERCENT)) as u16;
+
+        match side {
+            Side::Left => LayoutConfig {
+                left_panel_percent: percent,
+                ..self.config
+            },
+            Side::Right => LayoutConfig {
+                right_panel_percent: percent,
+                ..self.config
+            },
+        }
+    }
+
+    /// The widths of the left panel, the messages and the right panel, a collapsed panel taking none
+    pub fn constraints(&self, width: u16) -> [Constraint; 3] {
+        let percent = |side: Side, percent: u16| {
+            if self.is_shown(side, width) {
+                percent.min(MAX_PANEL_PERCENT)
+            } else {
+                0
+            }
+        };
+
+        [
+            Constraint::Percentage(percent(Side::Left, self.config.left_panel_percent)),
+            Constraint::Min(1),
+            Constraint::Percentage(percent(Side::Right, self.config.right_panel_percent)),
+        ]
+    }
+}
+
+/// Splits the area along the direction, into a part per constraint
+///
+/// An area too small for the constraints squeezes the parts, down to empty ones, rather than failing.
+pub fn split<const N: usize>(
+    area: Rect,
+    direction: Direction,
+    constraints: [Constraint; N],
+) -> [Rect; N] {
+    let parts = Layout::default()
+        .direction(direction)
+        .constraints(constraints.as_ref())
+        .split(area);
+
+    std::array::from_fn(|index| parts.get(index).copied().unwrap_or_default())
+}
+
+/// Returns a rect of the given size, centered inside the given area
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+pub fn is_too_small(area: Rect) -> bool {
+    area.width < MIN_WIDTH || area.height < MIN_HEIGHT
+}
+
+/// Renders the notice asking to enlarge the terminal, in place of the page it is too small for
+pub fn render_too_small<B: Backend>(frame: &mut Frame<B>) {
+    let area = frame.size();
+    let notice = Paragraph::new(Text::from(vec![
+        Line::from(Span::from("Terminal too small").bold()),
+        Line::from(
+            Span::from(format!(
+                "{}x{}, resize it to at least {}x{}",
+                area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+            ))
+            .dim(),
+        ),
+    ]))
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true });
+
+    frame.render_widget(notice, centered_rect(area.width, 3, area));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panels_collapse_below_their_width() {
+        let panel_layout = PanelLayout::default();
+
+        assert!(panel_layout.is_shown(Side::Left, 100));
+        assert!(panel_layout.is_shown(Side::Right, 100));
+        assert!(panel_layout.is_shown(Side::Left, 90));
+        assert!(!panel_layout.is_shown(Side::Right, 90));
+        assert!(!panel_layout.is_shown(Side::Left, 60));
+        assert_eq!(
+            panel_layout.constraints(90),
+            [
+                Constraint::Percentage(20),
+                Constraint::Min(1),
+                Constraint::Percentage(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toggled_panels_hold_whatever_the_width() {
+        let mut panel_layout = PanelLayout::default();
+
+        panel_layout.toggle(Side::Left, 120);
+        panel_layout.toggle(Side::Right, 60);
+
+        assert!(!panel_layout.is_shown(Side::Left, 200));
+        assert!(panel_layout.is_shown(Side::Right, 60));
+
+        panel_layout.toggle(Side::Left, 200);
+        assert!(panel_layout.is_shown(Side::Left, 40));
+    }
+
+    #[test]
+    fn test_panels_are_resized_within_bounds() {
+        let panel_layout = PanelLayout::default();
+
+        assert_eq!(
+            panel_layout.resized(Side::Left, RESIZE_STEP_PERCENT),
+            LayoutConfig {
+                left_panel_percent: 25,
+                ..LayoutConfig::default()
+            }
+        );
+        assert_eq!(
+            panel_layout.resized(Side::Right, -50).right_panel_percent,
+            MIN_PANEL_PERCENT
+        );
+        // the border of the right panel dragged to the middle of a 100 columns page
+        assert_eq!(
+            panel_layout
+                .dragged(Side::Right, 70, 100)
+                .right_panel_percent,
+            30
+        );
+        assert_eq!(
+            panel_layout.dragged(Side::Left, 80, 100).left_panel_percent,
+            MAX_PANEL_PERCENT
+        );
+    }
+
+    #[test]
+    fn test_panels_leave_room_for_the_messages() {
+        let panel_layout = PanelLayout::default().with_config(LayoutConfig {
+            left_panel_percent: 70,
+            ..LayoutConfig::default()
+        });
+
+        assert_eq!(
+            panel_layout.constraints(120)[0],
+            Constraint::Percentage(MAX_PANEL_PERCENT)
+        );
+    }
+}
@@ -1,5 +1,10 @@
 pub use ui_manager::UiManager;
 
 mod components;
+mod layout;
 mod pages;
+#[cfg(test)]
+mod snapshot_tests;
+#[cfg(test)]
+pub mod test_harness;
 mod ui_manager;
@@ -1,15 +1,35 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap, time::Duration};
 
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use comms::event::{
+    AnnouncementEvent, HistoryVisibility, PresenceStatus, RoomInvitationBroadcastEvent,
+    ServerShuttingDownEvent, UserPresence,
+};
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::{prelude::*, widgets::*, Frame};
 use tokio::sync::mpsc::UnboundedSender;
 
-use crate::state_store::{action::Action, MessageBoxItem, RoomData, State};
+use crate::{
+    graphics::ImagePlacement,
+    keymap::{KeyAction, Keymap},
+    state_store::{
+        action::Action, user_label, MessageSearch, RoomData, ServerConnectionStatus, State,
+    },
+    theme::Theme,
+};
 
 use super::{
     components::{
+        command_palette::{self, CommandPalette},
+        date_picker::{self, DatePicker},
+        direct_message_list::{self, DirectMessageList},
         message_input_box::{self, MessageInputBox},
+        message_list::{self, MessageList},
         room_list::{self, RoomList},
+        room_switcher::{self, RoomSwitcher},
+        search_results::{self, SearchResults},
+        user_profile::{self, UserProfilePopup},
     },
     section::{
         usage::{widget_usage_to_text, HasUsageInfo, UsageInfo, UsageInfoLine},
@@ -17,20 +37,25 @@ use super::{
     },
 };
 use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::layout::{self, centered_rect, PanelLayout, Side, RESIZE_STEP_PERCENT};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Section {
     MessageInput,
     RoomList,
+    DirectMessageList,
+    MessageList,
 }
 
 impl Section {
-    pub const COUNT: usize = 2;
+    pub const COUNT: usize = 4;
 
     fn to_usize(&self) -> usize {
         match self {
             Section::MessageInput => 0,
             Section::RoomList => 1,
+            Section::DirectMessageList => 2,
+            Section::MessageList => 3,
         }
     }
 }
@@ -42,6 +67,8 @@ impl TryFrom<usize> for Section {
         match value {
             0 => Ok(Section::MessageInput),
             1 => Ok(Section::RoomList),
+            2 => Ok(Section::DirectMessageList),
+            3 => Ok(Section::MessageList),
             _ => Err(()),
         }
     }
@@ -52,10 +79,38 @@ struct Props {
     user_id: String,
     /// The currently active room
     active_room: Option<String>,
-    /// The timer for the chat page
-    timer: usize,
+    /// The connection to the server, told by the status bar
+    server_connection_status: ServerConnectionStatus,
+    /// How long the client took to answer the last ping of the server
+    round_trip: Option<Duration>,
     /// The room data map
     room_data_map: HashMap<String, RoomData>,
+    /// The oldest invitation waiting for an answer, which is prompted to the user
+    pending_invitation: Option<RoomInvitationBroadcastEvent>,
+    /// The room the user is asked to confirm leaving, which takes the keys until they answer
+    leave_prompt: Option<String>,
+    /// The attempt to reconnect to the server, while the connection is dropped
+    reconnect_attempt: Option<u32>,
+    /// Why the server is shutting down, and how long until it closes the connection
+    server_shutdown: Option<ServerShuttingDownEvent>,
+    /// The announcement of the server shown above the messages, until it is dismissed
+    announcement: Option<AnnouncementEvent>,
+    /// The presence of the users who are not offline
+    presences: HashMap<String, UserPresence>,
+    /// The names the users who changed it are shown with, in place of their ids
+    display_names: HashMap<String, String>,
+    /// The colors the names of the users who picked one are shown with
+    user_colors: HashMap<String, Color>,
+    /// Does selecting a room user show their profile, rather than messaging them
+    can_view_profiles: bool,
+    keymap: Keymap,
+    theme: Theme,
+    /// Is the overlay listing the key bindings shown, handling input
+    is_help_open: bool,
+    /// The search through the messages of the active room
+    search: Option<MessageSearch>,
+    /// Are the keys taken as vim does, outside of the sections
+    vim_mode: bool,
 }
 
 impl From<&State> for Props {
@@ -63,13 +118,59 @@ impl From<&State> for Props {
         Props {
             user_id: state.user_id.clone(),
             active_room: state.active_room.clone(),
-            timer: state.timer,
+            server_connection_status: state.server_connection_status.clone(),
+            round_trip: state.round_trip,
             room_data_map: state.room_data_map.clone(),
+            pending_invitation: state.pending_invitations.first().cloned(),
+            leave_prompt: state.leave_prompt.clone(),
+            reconnect_attempt: match state.server_connection_status {
+                ServerConnectionStatus::Reconnecting { attempt, .. } => Some(attempt),
+                _ => None,
+            },
+            server_shutdown: state.server_shutdown.clone(),
+            announcement: state.announcement.clone(),
+            presences: state.presences.clone(),
+            display_names: state.display_names.clone(),
+            user_colors: state.user_colors.clone(),
+            can_view_profiles: state.can_view_profiles,
+            keymap: state.keymap.clone(),
+            theme: state.theme,
+            is_help_open: state.is_help_open,
+            search: state
+                .search
+                .clone()
+                .filter(|search| state.active_room.as_ref() == Some(&search.room)),
+            vim_mode: state.vim_mode,
         }
     }
 }
 
+/// The colored dot shown next to the name of a user, telling whether they are online, away or offline
+pub(super) fn presence_dot<'a>(status: PresenceStatus, theme: &Theme) -> Span<'a> {
+    match status {
+        PresenceStatus::Online => Span::from("●").fg(theme.success),
+        PresenceStatus::Away => Span::from("●").fg(theme.warning),
+        PresenceStatus::Offline => Span::from("○").fg(theme.muted),
+    }
+}
+
 const DEFAULT_HOVERED_SECTION: Section = Section::MessageInput;
+/// How many items the message list is scrolled by PageUp and PageDown
+const SCROLL_PAGE_SIZE: isize = 10;
+/// How many items the message list is scrolled by each step of the mouse wheel
+const SCROLL_WHEEL_STEP: isize = 3;
+/// The keys the help lists along with the bound ones, as they are not bound in the key bindings file
+const UNBOUND_KEYS: [(&str, &str); 2] = [
+    ("/", "to search the messages of the room"),
+    ("g", "to jump to a date"),
+];
+/// The keys of the vim mode, taken outside of the sections, in place of `g` jumping to a date
+const VIM_KEYS: [(&str, &str); 4] = [
+    ("i", "to type a message"),
+    ("j, k", "to scroll the messages down or up"),
+    ("gg, G", "to jump to the oldest or the latest message"),
+    ("gd", "to jump to a date"),
+];
 
 /// ChatPage handles the UI and the state of the chat page
 pub struct ChatPage {
@@ -85,8 +186,35 @@ pub struct ChatPage {
     // Child Components
     /// The room list widget that handles the listing of the rooms
     pub room_list: RoomList,
+    /// The list of the conversations of direct messages
+    pub direct_message_list: DirectMessageList,
     /// The input box widget that handles the message input
     pub message_input_box: MessageInputBox,
+    /// The message list widget that renders the messages of the active room
+    pub message_list: MessageList,
+    /// The overlay for picking a date to jump to in the room history
+    pub date_picker: DatePicker,
+    /// Is the date picker overlay open, handling input
+    pub is_date_picker_open: bool,
+    /// The overlay listing the matches of a search through the room history, open while there is one
+    pub search_results: SearchResults,
+    /// The query typed into the search bar, None unless it is handling input
+    search_input: Option<String>,
+    /// The overlay searching through the actions, handling input while open
+    pub command_palette: CommandPalette,
+    /// The popup finding a room or a conversation by its name, handling input while open
+    pub room_switcher: RoomSwitcher,
+    pub user_profile: UserProfilePopup,
+    /// How many lines the help overlay is scrolled down by
+    help_scroll: u16,
+    /// Was `g` pressed in the vim mode, waiting for the key completing it
+    is_g_pending: bool,
+    /// The area the page was last rendered to, which tells what the mouse clicks on
+    rendered_area: Cell<Rect>,
+    /// Which side panels are shown and how wide they are
+    panel_layout: PanelLayout,
+    /// The panel whose border is being dragged with the mouse, resized as it moves
+    dragged_border: Option<Side>,
 }
 
 impl ChatPage {
@@ -98,6 +226,8 @@ impl ChatPage {
         match section {
             Section::MessageInput => &self.message_input_box,
             Section::RoomList => &self.room_list,
+            Section::DirectMessageList => &self.direct_message_list,
+            Section::MessageList => &self.message_list,
         }
     }
 
@@ -105,6 +235,8 @@ impl ChatPage {
         match section {
             Section::MessageInput => &mut self.message_input_box,
             Section::RoomList => &mut self.room_list,
+            Section::DirectMessageList => &mut self.direct_message_list,
+            Section::MessageList => &mut self.message_list,
         }
     }
 
@@ -115,266 +247,556 @@ impl ChatPage {
         match section {
             Section::MessageInput => &mut self.message_input_box,
             Section::RoomList => &mut self.room_list,
+            Section::DirectMessageList => &mut self.direct_message_list,
+            Section::MessageList => &mut self.message_list,
         }
     }
 
     fn hover_next(&mut self) {
-        let idx: usize = self.last_hovered_section.to_usize();
-        let next_idx = (idx + 1) % Section::COUNT;
-        self.last_hovered_section = Section::try_from(next_idx).unwrap();
+        self.hover_by(1);
     }
 
     fn hover_previous(&mut self) {
-        let idx: usize = self.last_hovered_section.to_usize();
-        let previous_idx = if idx == 0 {
-            Section::COUNT - 1
-        } else {
-            idx - 1
-        };
-        self.last_hovered_section = Section::try_from(previous_idx).unwrap();
+        self.hover_by(Section::COUNT - 1);
+    }
+
+    /// Hovers the section the given number of sections away, skipping the ones of a collapsed panel
+    fn hover_by(&mut self, step: usize) {
+        let mut idx: usize = self.last_hovered_section.to_usize();
+
+        // the messages and their input are always shown, so a section is found
+        loop {
+            idx = (idx + step) % Section::COUNT;
+            let section = Section::try_from(idx).unwrap();
+
+            if self.is_section_shown(&section) {
+                self.last_hovered_section = section;
+                return;
+            }
+        }
+    }
+
+    /// Is the section on the page as last rendered, they are all taken as shown before the first render
+    fn is_section_shown(&self, section: &Section) -> bool {
+        let rendered_area = self.rendered_area.get();
+
+        match section {
+            Section::RoomList | Section::DirectMessageList => {
+                rendered_area.area() == 0
+                    || self.panel_layout.is_shown(Side::Left, rendered_area.width)
+            }
+            Section::MessageInput | Section::MessageList => true,
+        }
+    }
+
+    /// Collapses or expands the panel, moving the hover away from the sections it hides
+    fn toggle_panel(&mut self, side: Side) {
+        self.panel_layout
+            .toggle(side, self.rendered_area.get().width);
+
+        if !self.is_section_shown(&self.last_hovered_section) {
+            self.last_hovered_section = DEFAULT_HOVERED_SECTION;
+        }
     }
 
     fn calculate_border_color(&self, section: Section) -> Color {
         match (self.active_section.as_ref(), &self.last_hovered_section) {
-            (Some(active_section), _) if active_section.eq(&section) => Color::Yellow,
-            (_, last_hovered_section) if last_hovered_section.eq(&section) => Color::Blue,
+            (Some(active_section), _) if active_section.eq(&section) => {
+                self.props.theme.active_border
+            }
+            (_, last_hovered_section) if last_hovered_section.eq(&section) => {
+                self.props.theme.hovered_border
+            }
             _ => Color::Reset,
         }
     }
 
-    fn disable_section(&mut self, section: &Section) {
-        self.get_section_activation_for_section(section)
-            .deactivate();
+    fn open_date_picker(&mut self) {
+        if self.props.active_room.is_none() {
+            return;
+        }
 
-        self.active_section = None;
+        self.date_picker.activate();
+        self.is_date_picker_open = true;
     }
-}
 
-impl Component for ChatPage {
-    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
-    where
-        Self: Sized,
-    {
-        ChatPage {
-            action_tx: action_tx.clone(),
-            // set the props
-            props: Props::from(state),
-            // internal component state
-            active_section: Option::None,
-            last_hovered_section: DEFAULT_HOVERED_SECTION,
-            // child components
-            room_list: RoomList::new(state, action_tx.clone()),
-            message_input_box: MessageInputBox::new(state, action_tx),
-        }
-        .move_with_state(state)
+    fn close_date_picker(&mut self) {
+        self.date_picker.deactivate();
+        self.is_date_picker_open = false;
     }
 
-    fn move_with_state(self, state: &State) -> Self
-    where
-        Self: Sized,
-    {
-        ChatPage {
-            props: Props::from(state),
-            // propogate the update to the child components
-            room_list: self.room_list.move_with_state(state),
-            message_input_box: self.message_input_box.move_with_state(state),
-            ..self
+    fn open_help(&mut self) {
+        let _ = self.action_tx.send(Action::ShowHelp);
+        // reflect the change right away instead of waiting for the state update
+        self.props.is_help_open = true;
+    }
+
+    fn close_help(&mut self) {
+        let _ = self.action_tx.send(Action::CloseHelp);
+        self.props.is_help_open = false;
+        self.help_scroll = 0;
+    }
+
+    /// Scrolls the help overlay by the given lines, down when positive, until its last line is at the top
+    fn scroll_help(&mut self, lines: i32) {
+        let (keys, commands) = self.help_lines();
+        let last_line = keys.len().max(commands.len()).saturating_sub(1) as i32;
+
+        self.help_scroll = (i32::from(self.help_scroll) + lines).clamp(0, last_line) as u16;
+    }
+
+    /// The lines of the help overlay, the keys on one side and the slash commands on the other
+    fn help_lines(&self) -> (Vec<Line<'static>>, Vec<Line<'static>>) {
+        // the keys are short enough to be aligned, the usages of the commands are not
+        let help_line = |keys: String, width: usize, description: String| {
+            Line::from(vec![
+                Span::from(format!("{:<width$}  ", keys)).bold(),
+                description.into(),
+            ])
+        };
+
+        let keys = self
+            .props
+            .keymap
+            .bindings()
+            .map(|(action, keys)| {
+                let keys = keys
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                help_line(keys, 16, String::from(action.describe()))
+            })
+            .chain(
+                UNBOUND_KEYS
+                    .iter()
+                    .filter(|(keys, _)| !self.props.vim_mode || *keys != "g")
+                    .chain(VIM_KEYS.iter().filter(|_| self.props.vim_mode))
+                    .map(|(keys, description)| {
+                        help_line(String::from(*keys), 16, String::from(*description))
+                    }),
+            )
+            .collect();
+
+        let commands = self
+            .message_input_box
+            .command_help_lines()
+            .into_iter()
+            .map(|(usage, description)| help_line(usage, 0, description))
+            .collect();
+
+        (keys, commands)
+    }
+
+    /// Renders the help over the whole page, the keys on the left and the slash commands on the right
+    fn render_help<B: Backend>(&self, frame: &mut Frame<B>) {
+        let area = frame.size();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(self.props.theme.active_border))
+            .title("Help")
+            .title(
+                block::Title::from(Span::from(" ↑ ↓ to scroll, Esc to close ").dim())
+                    .alignment(Alignment::Right),
+            );
+        let [keys_area, commands_area] = layout::split(
+            block.inner(area),
+            Direction::Horizontal,
+            [Constraint::Percentage(50), Constraint::Percentage(50)],
+        );
+        let (keys, commands) = self.help_lines();
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        for (title, lines, area) in [
+            ("Keys", keys, keys_area),
+            ("Commands", commands, commands_area),
+        ] {
+            let column = Paragraph::new(Text::from(lines))
+                .wrap(Wrap { trim: false })
+                .scroll((self.help_scroll, 0))
+                .block(
+                    Block::default()
+                        .borders(Borders::TOP)
+                        .title(title)
+                        // keeps the columns apart
+                        .padding(Padding {
+                            left: 0,
+                            right: 2,
+                            top: 0,
+                            bottom: 0,
+                        }),
+                );
+            frame.render_widget(column, area);
         }
     }
 
-    fn name(&self) -> &str {
-        "Chat Page"
+    /// Opens the search bar in place of the room information, with the query of the current search
+    fn open_search(&mut self) {
+        if self.props.active_room.is_none() {
+            return;
+        }
+
+        if let Some(active_section) = self.active_section.clone() {
+            self.disable_section(&active_section);
+        }
+        self.search_input = Some(
+            self.props
+                .search
+                .as_ref()
+                .map(|search| search.query.clone())
+                .unwrap_or_default(),
+        );
     }
 
-    fn handle_key_event(&mut self, key: KeyEvent) {
-        if key.kind != KeyEventKind::Press {
+    /// Types into the search bar, the messages are searched as the query changes
+    fn handle_search_input_key(&mut self, key: KeyEvent) {
+        let Some(query) = self.search_input.as_mut() else {
             return;
+        };
+
+        match key.code {
+            // the hits are kept highlighted, to move between them
+            KeyCode::Enter if !query.is_empty() => {
+                self.search_input = None;
+            }
+            KeyCode::Enter | KeyCode::Esc => {
+                self.search_input = None;
+                let _ = self.action_tx.send(Action::CloseSearch);
+            }
+            KeyCode::Backspace => {
+                query.pop();
+                let _ = self.action_tx.send(Action::SearchMessages {
+                    query: query.clone(),
+                });
+            }
+            KeyCode::Char(char) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                query.push(char);
+                let _ = self.action_tx.send(Action::SearchMessages {
+                    query: query.clone(),
+                });
+            }
+            _ => {}
         }
+    }
 
-        let active_section = self.active_section.clone();
+    fn search_line(&self) -> Line<'static> {
+        let query = self
+            .search_input
+            .clone()
+            .or_else(|| {
+                self.props
+                    .search
+                    .as_ref()
+                    .map(|search| search.query.clone())
+            })
+            .unwrap_or_default();
+        let mut spans = vec![Span::from("/").bold(), Span::raw(query.clone())];
+        if self.search_input.is_some() {
+            spans.push(Span::from("▏").fg(self.props.theme.input));
+        }
 
-        match active_section {
-            None => match key.code {
-                KeyCode::Char('e') => {
-                    let last_hovered_section = self.last_hovered_section.clone();
+        match self.props.search.as_ref() {
+            Some(search) if !query.is_empty() && search.hits.is_empty() => {
+                spans.push(Span::from("  no matches").fg(self.props.theme.warning));
+            }
+            Some(search) if !query.is_empty() => {
+                spans.push(
+                    Span::from(format!(
+                        "  {} of {} matches",
+                        search.current + 1,
+                        search.hits.len()
+                    ))
+                    .dim(),
+                );
+            }
+            _ => {}
+        }
 
-                    self.active_section = Some(last_hovered_section.clone());
-                    self.get_section_activation_for_section(&last_hovered_section)
-                        .activate();
-                }
-                KeyCode::Left => self.hover_previous(),
-                KeyCode::Right => self.hover_next(),
-                KeyCode::Char('q') => {
-                    let _ = self.action_tx.send(Action::Exit);
-                }
-                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    let _ = self.action_tx.send(Action::Exit);
-                }
+        Line::from(spans)
+    }
+
+    /// Takes the key as vim does in the normal mode, returns whether it was one of the vim keys
+    ///
+    /// `g` waits for the next key, `gg` jumping to the oldest message and `gd` opening the date picker, while any
+    /// other key following it is dropped.
+    fn handle_vim_key(&mut self, key: &KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.is_g_pending = false;
+            return false;
+        }
+
+        if std::mem::take(&mut self.is_g_pending) {
+            match key.code {
+                KeyCode::Char('g') => self.scroll_messages(isize::MAX),
+                KeyCode::Char('d') => self.open_date_picker(),
                 _ => {}
-            },
-            Some(section) => {
-                self.get_component_for_section_mut(&section)
-                    .handle_key_event(key);
+            }
 
-                // disable the section according to the action taken
-                // the section is disabled when escape is pressed
-                // or when enter is pressed on the room list
-                match section {
-                    Section::RoomList if key.code == KeyCode::Enter => {
-                        self.disable_section(&section)
-                    }
-                    _ if key.code == KeyCode::Esc => self.disable_section(&section),
-                    _ => (),
-                }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('i') => self.enable_section(Section::MessageInput),
+            KeyCode::Char('j') => self.scroll_messages(-1),
+            KeyCode::Char('k') => self.scroll_messages(1),
+            KeyCode::Char('g') => self.is_g_pending = true,
+            KeyCode::Char('G') => {
+                let _ = self.action_tx.send(Action::ReturnToLatest);
             }
+            _ => return false,
         }
+
+        true
     }
-}
 
-const NO_ROOM_SELECTED_MESSAGE: &str = "Join at least one room to start chatting!";
+    fn scroll_messages(&self, items: isize) {
+        if self.props.active_room.is_some() {
+            let _ = self.action_tx.send(Action::ScrollMessages { items });
+        }
+    }
 
-fn calculate_list_offset(height: u16, items_len: usize) -> usize {
-    // go back by (container height + 2 for borders) to get the offset
-    items_len.saturating_sub(height as usize - 2)
-}
+    /// Answers the prompted invitation, if there is one
+    fn answer_invitation(&self, accept: bool) {
+        // the invitation is not prompted while searching
+        if self.props.search.is_some() {
+            return;
+        }
 
-impl ComponentRender<()> for ChatPage {
-    fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
-        let [left, middle, right] = *Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(
-                [
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(60),
-                    Constraint::Percentage(20),
-                ]
-                .as_ref(),
-            )
-            .split(frame.size())
-        else {
-            panic!("The main layout should have 3 chunks")
-        };
+        if let Some(invitation) = self.props.pending_invitation.as_ref() {
+            let room = invitation.room.name.clone();
+            let _ = self.action_tx.send(if accept {
+                Action::AcceptInvitation { room }
+            } else {
+                Action::DeclineInvitation { room }
+            });
+        }
+    }
 
-        let [container_room_list, container_user_info] = *Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(4)].as_ref())
-            .split(left)
-        else {
-            panic!("The left layout should have 2 chunks")
-        };
+    fn enable_section(&mut self, section: Section) {
+        self.get_section_activation_for_section(&section).activate();
+
+        self.last_hovered_section = section.clone();
+        self.active_section = Some(section);
+    }
+
+    fn disable_section(&mut self, section: &Section) {
+        self.get_section_activation_for_section(section)
+            .deactivate();
+
+        self.active_section = None;
+    }
+
+    /// Moves the input to the given section, deactivating the active one
+    fn focus_section(&mut self, section: Section) {
+        if self.active_section.as_ref() == Some(&section) {
+            return;
+        }
+
+        if let Some(active_section) = self.active_section.clone() {
+            self.disable_section(&active_section);
+        }
+        self.enable_section(section);
+    }
+
+    fn handle_click(&mut self, mouse: &MouseEvent) {
+        let layout = ChatLayout::split(
+            self.rendered_area.get(),
+            self.props.announcement.is_some(),
+            &self.panel_layout,
+        );
+        let left_panel = layout.left_panel.as_ref();
+
+        if layout
+            .announcement
+            .is_some_and(|announcement| contains(announcement, mouse))
+        {
+            let _ = self.action_tx.send(Action::DismissAnnouncement);
+        } else if contains(layout.input, mouse) {
+            self.focus_section(Section::MessageInput);
+        } else if let Some(row) = left_panel.and_then(|panel| clicked_row(panel.room_list, mouse)) {
+            self.focus_section(Section::RoomList);
+            self.room_list.click(row);
+
+            // as with Enter, opening a room moves the input away from the room list
+            if self.room_list.is_room_selected() {
+                self.disable_section(&Section::RoomList);
+            }
+        } else if let Some(row) =
+            left_panel.and_then(|panel| clicked_row(panel.direct_message_list, mouse))
+        {
+            self.focus_section(Section::DirectMessageList);
+
+            if self.direct_message_list.click(row) {
+                self.disable_section(&Section::DirectMessageList);
+            }
+        } else if let Some((height, row)) = layout.right_panel.as_ref().and_then(|panel| {
+            clicked_row(panel.room_users, mouse).map(|row| (panel.room_users.height, row))
+        }) {
+            self.select_room_user(height, row);
+        }
+    }
+
+    /// Widens or narrows the panel if it is shown, keeping its new width in the config
+    fn resize_panel(&mut self, side: Side, delta_percent: i32) {
+        if !self
+            .panel_layout
+            .is_shown(side, self.rendered_area.get().width)
+        {
+            return;
+        }
+
+        let layout = self.panel_layout.resized(side, delta_percent);
+        self.panel_layout = self.panel_layout.with_config(layout);
+        let _ = self.action_tx.send(Action::SetLayout { layout });
+    }
+
+    /// Returns the panel whose inner border is at the mouse, on either side of the line between it and the messages
+    fn border_at(&self, mouse: &MouseEvent) -> Option<Side> {
+        let layout = ChatLayout::split(
+            self.rendered_area.get(),
+            self.props.announcement.is_some(),
+            &self.panel_layout,
+        );
+        let is_on_border = |column: u16| mouse.column + 1 == column || mouse.column == column;
+
+        if layout
+            .left_panel
+            .is_some_and(|panel| is_on_border(panel.room_list.right()))
+        {
+            Some(Side::Left)
+        } else if layout
+            .right_panel
+            .is_some_and(|panel| is_on_border(panel.room_users.x))
+        {
+            Some(Side::Right)
+        } else {
+            None
+        }
+    }
+
+    fn drag_border(&mut self, mouse: &MouseEvent) {
+        if let Some(side) = self.dragged_border {
+            let layout =
+                self.panel_layout
+                    .dragged(side, mouse.column, self.rendered_area.get().width);
+            self.panel_layout = self.panel_layout.with_config(layout);
+        }
+    }
+
+    /// Keeps the width the dragged panel was dropped at in the config
+    fn drop_border(&mut self) {
+        if self.dragged_border.take().is_some() {
+            let _ = self.action_tx.send(Action::SetLayout {
+                layout: self.panel_layout.config(),
+            });
+        }
+    }
 
+    /// Renders the rooms, the direct messages and the user on the left of the messages
+    fn render_left_panel<B: Backend>(&self, frame: &mut Frame<B>, panel: &LeftPanel) {
         self.room_list.render(
             frame,
             room_list::RenderProps {
                 border_color: self.calculate_border_color(Section::RoomList),
-                area: container_room_list,
+                area: panel.room_list,
             },
         );
 
-        let user_info = Paragraph::new(Text::from(vec![
-            Line::from(format!("User: @{}", self.props.user_id)),
-            Line::from(format!("Chatting for: {} secs", self.props.timer)),
-        ]))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("User Information"),
+        self.direct_message_list.render(
+            frame,
+            direct_message_list::RenderProps {
+                border_color: self.calculate_border_color(Section::DirectMessageList),
+                area: panel.direct_message_list,
+            },
         );
-        frame.render_widget(user_info, container_user_info);
-
-        let [container_highlight, container_messages, container_input] = *Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(
-                [
-                    Constraint::Length(3),
-                    Constraint::Min(1),
-                    Constraint::Length(3),
-                ]
-                .as_ref(),
-            )
-            .split(middle)
-        else {
-            panic!("The middle layout should have 3 chunks")
+    }
+
+    /// Renders the line at the bottom of the page telling the connection, the user, the active room, the unread
+    /// messages and whether the keys are typed in
+    fn render_status_bar<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let theme = &self.props.theme;
+        let separator = || Span::from(" │ ").fg(theme.muted);
+
+        let mut spans = match &self.props.server_connection_status {
+            ServerConnectionStatus::Connected { addr } => {
+                let mut spans = vec![Span::from(" ● ").fg(theme.success), Span::raw(addr.clone())];
+                if let Some(round_trip) = self.props.round_trip {
+                    spans.push(Span::from(format!(" {}ms", round_trip.as_millis())).dim());
+                }
+
+                spans
+            }
+            ServerConnectionStatus::Reconnecting { addr, attempt } => vec![
+                Span::from(" ◌ ").fg(theme.warning),
+                Span::raw(format!("reconnecting to {} (attempt {})", addr, attempt)),
+            ],
+            _ => vec![Span::from(" ○ ").fg(theme.muted), Span::raw("disconnected")],
         };
 
-        let top_line = if let Some(room_data) = self
+        spans.push(separator());
+        spans.push(Span::from(format!("@{}", self.props.user_id)).bold());
+
+        if let Some(room_data) = self
             .props
             .active_room
             .as_ref()
             .and_then(|active_room| self.get_room_data(active_room))
         {
-            Line::from(vec![
-                "on ".into(),
-                Span::from(format!("#{}", room_data.name)).bold(),
-                " for ".into(),
-                Span::from(format!(r#""{}""#, room_data.description)).italic(),
-            ])
-        } else {
-            Line::from(NO_ROOM_SELECTED_MESSAGE)
-        };
-        let text = Text::from(top_line);
-
-        let help_message = Paragraph::new(text).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Active Room Information"),
-        );
-        frame.render_widget(help_message, container_highlight);
+            spans.push(separator());
+            spans.push(Span::raw(if room_data.is_direct_message {
+                format!("@{}", room_data.name)
+            } else {
+                format!("#{}", room_data.name)
+            }));
+        }
 
-        let messages = if let Some(active_room) = self.props.active_room.as_ref() {
-            self.get_room_data(active_room)
-                .map(|room_data| {
-                    let message_offset =
-                        calculate_list_offset(container_messages.height, room_data.messages.len());
-
-                    room_data
-                        .messages
-                        .asc_iter()
-                        .skip(message_offset)
-                        .map(|mbi| {
-                            let line = match mbi {
-                                MessageBoxItem::Message { user_id, content } => {
-                                    Line::from(Span::raw(format!("@{}: {}", user_id, content)))
-                                }
-                                MessageBoxItem::Notification(content) => {
-                                    Line::from(Span::raw(content.clone()).italic())
-                                }
-                            };
+        let (unread_count, unread_mention_count) =
+            self.props
+                .room_data_map
+                .values()
+                .fold((0, 0), |(unread, mentions), room_data| {
+                    (
+                        unread + room_data.unread_count,
+                        mentions + room_data.unread_mention_count,
+                    )
+                });
+        spans.push(separator());
+        spans.push(match (unread_count, unread_mention_count) {
+            (0, _) => Span::from("no unread").dim(),
+            (unread, 0) => Span::raw(format!("{} unread", unread)),
+            (unread, 1) => Span::from(format!("{} unread, 1 mention", unread)).fg(theme.mention),
+            (unread, mentions) => {
+                Span::from(format!("{} unread, {} mentions", unread, mentions)).fg(theme.mention)
+            }
+        });
 
-                            ListItem::new(line)
-                        })
-                        .collect::<Vec<ListItem>>()
-                })
-                .unwrap_or_default()
+        let is_typing =
+            self.active_section == Some(Section::MessageInput) || self.search_input.is_some();
+        let mode = if is_typing {
+            Span::from(" INSERT ")
+                .bold()
+                .fg(theme.selection_fg)
+                .bg(theme.accent)
         } else {
-            vec![ListItem::new(Line::from(NO_ROOM_SELECTED_MESSAGE))]
+            Span::from(" NORMAL ")
+                .bold()
+                .fg(theme.selection_fg)
+                .bg(theme.muted)
         };
 
-        let messages =
-            List::new(messages).block(Block::default().borders(Borders::ALL).title("Messages"));
-        frame.render_widget(messages, container_messages);
-
-        self.message_input_box.render(
-            frame,
-            message_input_box::RenderProps {
-                border_color: self.calculate_border_color(Section::MessageInput),
-                area: container_input,
-                show_cursor: self
-                    .active_section
-                    .as_ref()
-                    .map(|active_section| active_section.eq(&Section::MessageInput))
-                    .unwrap_or(false),
-            },
+        let [status, mode_area] = layout::split(
+            area,
+            Direction::Horizontal,
+            [Constraint::Min(1), Constraint::Length(8)],
         );
+        frame.render_widget(Paragraph::new(Line::from(spans)), status);
+        frame.render_widget(Paragraph::new(Line::from(mode)), mode_area);
+    }
 
-        let [container_room_users, container_usage] = *Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(10)].as_ref())
-            .split(right)
-        else {
-            panic!("The left layout should have 2 chunks")
-        };
-
+    /// Renders the users of the active room and the usage of the hovered widget on the right of the messages
+    fn render_right_panel<B: Backend>(&self, frame: &mut Frame<B>, panel: &RightPanel) {
         let (room_users_list_items, room_users_len) = self
             .props
             .active_room
@@ -383,7 +805,7 @@ impl ComponentRender<()> for ChatPage {
                 self.get_room_data(active_room).map(|room_data| {
                     let room_users_len = room_data.users.len();
                     let users_offset =
-                        calculate_list_offset(container_room_users.height, room_users_len);
+                        calculate_list_offset(panel.room_users.height, room_users_len);
 
                     (
                         room_data
@@ -391,7 +813,40 @@ impl ComponentRender<()> for ChatPage {
                             .iter()
                             .skip(users_offset)
                             .map(|user_id| {
-                                ListItem::new(Line::from(Span::raw(format!("@{user_id}"))))
+                                let presence = self.props.presences.get(user_id);
+                                let mut spans = vec![
+                                    presence_dot(
+                                        presence
+                                            .map(|presence| presence.status)
+                                            .unwrap_or(PresenceStatus::Offline),
+                                        &self.props.theme,
+                                    ),
+                                    Span::raw(" "),
+                                ];
+
+                                // the logged in user is highlighted among the others
+                                let label = user_label(&self.props.display_names, user_id);
+                                if user_id == &self.props.user_id {
+                                    spans
+                                        .push(Span::from(label).bold().fg(self.props.theme.accent));
+                                    spans.push(Span::from(" (you)").dim());
+                                } else {
+                                    let mut label = Span::raw(label);
+                                    if let Some(color) = self.props.user_colors.get(user_id) {
+                                        label = label.fg(*color);
+                                    }
+                                    spans.push(label);
+                                }
+
+                                if let Some(away_message) =
+                                    presence.and_then(|presence| presence.away_message.as_ref())
+                                {
+                                    spans.push(
+                                        Span::from(format!(" {away_message}")).dim().italic(),
+                                    );
+                                }
+
+                                ListItem::new(Line::from(spans))
                             })
                             .collect::<Vec<ListItem<'_>>>(),
                         room_users_len,
@@ -406,48 +861,1259 @@ impl ComponentRender<()> for ChatPage {
                 .title(format!("Room Users ({})", room_users_len)),
         );
 
-        frame.render_widget(room_users_list, container_room_users);
+        frame.render_widget(room_users_list, panel.room_users);
 
         let mut usage_text: Text = widget_usage_to_text(self.usage_info());
         usage_text.patch_style(Style::default());
         let usage = Paragraph::new(usage_text)
             .wrap(Wrap { trim: true })
             .block(Block::default().borders(Borders::ALL).title("Usage"));
-        frame.render_widget(usage, container_usage);
+        frame.render_widget(usage, panel.usage);
     }
-}
 
-impl HasUsageInfo for ChatPage {
-    fn usage_info(&self) -> UsageInfo {
-        if let Some(section) = self.active_section.as_ref() {
-            let handler: &dyn HasUsageInfo = match section {
-                Section::RoomList => &self.room_list,
-                Section::MessageInput => &self.message_input_box,
-            };
-
-            handler.usage_info()
+    /// Shows the profile of the room user shown at the given row, from which they can be messaged,
+    /// or opens the conversation of direct messages with them if the server has no profiles
+    fn select_room_user(&mut self, height: u16, row: usize) {
+        let Some(room_data) = self
+            .props
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.get_room_data(active_room))
+        else {
+            return;
+        };
+
+        let users_offset = calculate_list_offset(height, room_data.users.len());
+        let Some(user_id) = room_data.users.iter().nth(users_offset + row) else {
+            return;
+        };
+
+        if self.props.can_view_profiles {
+            let _ = self.action_tx.send(Action::GetUserProfile {
+                user_id: user_id.clone(),
+            });
+        } else if *user_id != self.props.user_id {
+            let _ = self.action_tx.send(Action::OpenDirectMessage {
+                user_id: user_id.clone(),
+            });
+        }
+    }
+
+    /// Where the previews of the images are drawn, none while a popup or an overlay may cover the messages
+    pub fn image_placements(&self) -> Vec<ImagePlacement> {
+        let is_covered = self.props.pending_invitation.is_some()
+            || self.props.leave_prompt.is_some()
+            || self.props.is_help_open
+            || self.command_palette.is_open()
+            || self.room_switcher.is_open()
+            || self.user_profile.is_open()
+            || self.is_date_picker_open
+            || self.search_results.is_open();
+
+        if is_covered {
+            vec![]
+        } else {
+            self.message_list.image_placements()
+        }
+    }
+}
+
+impl Component for ChatPage {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
+    where
+        Self: Sized,
+    {
+        ChatPage {
+            action_tx: action_tx.clone(),
+            // set the props
+            props: Props::from(state),
+            // internal component state
+            active_section: Option::None,
+            last_hovered_section: DEFAULT_HOVERED_SECTION,
+            // child components
+            room_list: RoomList::new(state, action_tx.clone()),
+            direct_message_list: DirectMessageList::new(state, action_tx.clone()),
+            message_input_box: MessageInputBox::new(state, action_tx.clone()),
+            message_list: MessageList::new(state, action_tx.clone()),
+            date_picker: DatePicker::new(state, action_tx.clone()),
+            is_date_picker_open: false,
+            search_results: SearchResults::new(state, action_tx.clone()),
+            search_input: None,
+            command_palette: CommandPalette::new(state, action_tx.clone()),
+            room_switcher: RoomSwitcher::new(state, action_tx.clone()),
+            user_profile: UserProfilePopup::new(state, action_tx),
+            help_scroll: 0,
+            is_g_pending: false,
+            rendered_area: Cell::new(Rect::default()),
+            panel_layout: PanelLayout::default(),
+            dragged_border: None,
+        }
+        .move_with_state(state)
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        ChatPage {
+            props: Props::from(state),
+            // propogate the update to the child components
+            room_list: self.room_list.move_with_state(state),
+            direct_message_list: self.direct_message_list.move_with_state(state),
+            message_input_box: self.message_input_box.move_with_state(state),
+            message_list: self.message_list.move_with_state(state),
+            date_picker: self.date_picker.move_with_state(state),
+            search_results: self.search_results.move_with_state(state),
+            command_palette: self.command_palette.move_with_state(state),
+            room_switcher: self.room_switcher.move_with_state(state),
+            user_profile: self.user_profile.move_with_state(state),
+            // the panel being dragged keeps its width until it is dropped
+            panel_layout: match self.dragged_border {
+                Some(_) => self.panel_layout,
+                None => self.panel_layout.with_config(state.layout),
+            },
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Chat Page"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        if self.props.is_help_open {
+            match key.code {
+                KeyCode::Up => self.scroll_help(-1),
+                KeyCode::Down => self.scroll_help(1),
+                KeyCode::PageUp => self.scroll_help(-(SCROLL_PAGE_SIZE as i32)),
+                KeyCode::PageDown => self.scroll_help(SCROLL_PAGE_SIZE as i32),
+                KeyCode::Enter | KeyCode::Esc => self.close_help(),
+                _ if self.props.keymap.is(&key, KeyAction::ShowHelp) => self.close_help(),
+                _ => {}
+            }
+
+            return;
+        }
+
+        if self.props.leave_prompt.is_some() {
+            let action = match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => Action::ConfirmLeaveRoom,
+                KeyCode::Char('n') | KeyCode::Esc => Action::CancelLeaveRoom,
+                _ => return,
+            };
+            let _ = self.action_tx.send(action);
+            // reflect the answer right away instead of waiting for the state update
+            self.props.leave_prompt = None;
+
+            return;
+        }
+
+        if self.command_palette.is_open() {
+            self.command_palette.handle_key_event(key);
+
+            return;
+        }
+
+        if self.room_switcher.is_open() {
+            self.room_switcher.handle_key_event(key);
+
+            return;
+        }
+
+        if self.user_profile.is_open() {
+            self.user_profile.handle_key_event(key);
+
+            return;
+        }
+
+        if self.is_date_picker_open {
+            self.date_picker.handle_key_event(key);
+
+            if matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+                self.close_date_picker();
+            }
+
+            return;
+        }
+
+        if self.search_results.is_open() {
+            self.search_results.handle_key_event(key);
+
+            return;
+        }
+
+        if self.search_input.is_some() {
+            self.handle_search_input_key(key);
+
+            return;
+        }
+
+        // the search, the palette and the switcher are opened whatever the active section
+        if self.props.keymap.is(&key, KeyAction::Search) {
+            self.open_search();
+
+            return;
+        }
+
+        if self.props.keymap.is(&key, KeyAction::OpenCommandPalette) {
+            self.command_palette.open();
+
+            return;
+        }
+
+        if self.props.keymap.is(&key, KeyAction::OpenRoomSwitcher) {
+            self.room_switcher.open();
+
+            return;
+        }
+
+        if self.active_section.is_none() && self.props.vim_mode && self.handle_vim_key(&key) {
+            return;
+        }
+
+        let active_section = self.active_section.clone();
+
+        match active_section {
+            None => match self.props.keymap.action(&key) {
+                Some(KeyAction::Activate) => self.enable_section(self.last_hovered_section.clone()),
+                Some(KeyAction::FocusPrevious) => self.hover_previous(),
+                Some(KeyAction::FocusNext) => self.hover_next(),
+                Some(KeyAction::ScrollToLatest) => {
+                    let _ = self.action_tx.send(Action::ReturnToLatest);
+                }
+                Some(KeyAction::ScrollUp) => self.scroll_messages(SCROLL_PAGE_SIZE),
+                Some(KeyAction::ScrollDown) => self.scroll_messages(-SCROLL_PAGE_SIZE),
+                Some(KeyAction::ToggleLeftPanel) => self.toggle_panel(Side::Left),
+                Some(KeyAction::ToggleRightPanel) => self.toggle_panel(Side::Right),
+                Some(KeyAction::NarrowLeftPanel) => {
+                    self.resize_panel(Side::Left, -RESIZE_STEP_PERCENT)
+                }
+                Some(KeyAction::WidenLeftPanel) => {
+                    self.resize_panel(Side::Left, RESIZE_STEP_PERCENT)
+                }
+                Some(KeyAction::NarrowRightPanel) => {
+                    self.resize_panel(Side::Right, -RESIZE_STEP_PERCENT)
+                }
+                Some(KeyAction::WidenRightPanel) => {
+                    self.resize_panel(Side::Right, RESIZE_STEP_PERCENT)
+                }
+                Some(KeyAction::Quit) => {
+                    let _ = self.action_tx.send(Action::Exit);
+                }
+                Some(KeyAction::ShowHelp) => self.open_help(),
+                // the keys which are not bound are kept as they are, the popups were opened above
+                Some(
+                    KeyAction::Send
+                    | KeyAction::OpenCommandPalette
+                    | KeyAction::OpenRoomSwitcher
+                    | KeyAction::Search
+                    | KeyAction::Complete
+                    | KeyAction::ToggleInputTemplate
+                    | KeyAction::EditLastMessage
+                    | KeyAction::PreviousInput
+                    | KeyAction::NextInput,
+                )
+                | None => match key.code {
+                    KeyCode::Char('/') => self.open_search(),
+                    KeyCode::Char('n') if self.props.search.is_some() => {
+                        let _ = self.action_tx.send(Action::MoveSearch { older: true });
+                    }
+                    KeyCode::Char('N') if self.props.search.is_some() => {
+                        let _ = self.action_tx.send(Action::MoveSearch { older: false });
+                    }
+                    KeyCode::Esc if self.props.search.is_some() => {
+                        let _ = self.action_tx.send(Action::CloseSearch);
+                    }
+                    KeyCode::Esc if self.props.announcement.is_some() => {
+                        let _ = self.action_tx.send(Action::DismissAnnouncement);
+                    }
+                    KeyCode::Char('g') => self.open_date_picker(),
+                    KeyCode::Char('y') => self.answer_invitation(true),
+                    KeyCode::Char('n') => self.answer_invitation(false),
+                    _ => {}
+                },
+            },
+            Some(section) => {
+                self.get_component_for_section_mut(&section)
+                    .handle_key_event(key);
+
+                // disable the section according to the action taken
+                // the section is disabled when escape is pressed
+                // or when enter is pressed on a room of the room list
+                match section {
+                    Section::RoomList
+                        if key.code == KeyCode::Enter && self.room_list.is_room_selected() =>
+                    {
+                        self.disable_section(&section)
+                    }
+                    Section::DirectMessageList
+                        if key.code == KeyCode::Enter
+                            && self.direct_message_list.is_conversation_selected() =>
+                    {
+                        self.disable_section(&section)
+                    }
+                    // replying moves on to the message input to type the reply
+                    Section::MessageList
+                        if key.code == KeyCode::Char('r')
+                            && self.message_list.selected_reply_target().is_some() =>
+                    {
+                        self.disable_section(&section);
+                        self.enable_section(Section::MessageInput);
+                    }
+                    _ if key.code == KeyCode::Esc => self.disable_section(&section),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        // the wheel scrolls the messages regardless of the active section, unless the date picker is open,
+        // while clicking moves the input to the clicked section
+        if self.is_date_picker_open
+            || self.search_results.is_open()
+            || self.command_palette.is_open()
+            || self.room_switcher.is_open()
+            || self.props.is_help_open
+        {
+            return;
+        }
+
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_messages(SCROLL_WHEEL_STEP),
+            MouseEventKind::ScrollDown => self.scroll_messages(-SCROLL_WHEEL_STEP),
+            MouseEventKind::Down(MouseButton::Left) => match self.border_at(&mouse) {
+                Some(side) => self.dragged_border = Some(side),
+                None => self.handle_click(&mouse),
+            },
+            MouseEventKind::Drag(MouseButton::Left) => self.drag_border(&mouse),
+            MouseEventKind::Up(MouseButton::Left) => self.drop_border(),
+            _ => (),
+        }
+    }
+}
+
+pub(super) const NO_ROOM_SELECTED_MESSAGE: &str = "Join at least one room to start chatting!";
+
+pub(super) fn calculate_list_offset(height: u16, items_len: usize) -> usize {
+    // go back by (container height + 2 for borders) to get the offset
+    items_len.saturating_sub(height as usize - 2)
+}
+
+fn history_visibility_label(history_visibility: &HistoryVisibility) -> String {
+    match history_visibility {
+        HistoryVisibility::None => "history visible since you joined".into(),
+        HistoryVisibility::Last { count } => format!("last {} messages visible on join", count),
+        HistoryVisibility::All => "full history visible".into(),
+    }
+}
+
+impl ComponentRender<()> for ChatPage {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
+        self.rendered_area.set(frame.size());
+        let ChatLayout {
+            highlight: container_highlight,
+            announcement: container_announcement,
+            messages: container_messages,
+            input: container_input,
+            left_panel,
+            right_panel,
+            status_bar,
+        } = ChatLayout::split(
+            frame.size(),
+            self.props.announcement.is_some(),
+            &self.panel_layout,
+        );
+
+        if let Some(left_panel) = left_panel.as_ref() {
+            self.render_left_panel(frame, left_panel);
+        }
+        if let Some(right_panel) = right_panel.as_ref() {
+            self.render_right_panel(frame, right_panel);
+        }
+        self.render_status_bar(frame, status_bar);
+
+        let top_line = if let Some(room_data) = self
+            .props
+            .active_room
+            .as_ref()
+            .and_then(|active_room| self.get_room_data(active_room))
+        {
+            if room_data.is_direct_message {
+                Line::from(vec![
+                    "with ".into(),
+                    Span::from(format!("@{}", room_data.name)).bold(),
+                    Span::from(" (direct messages are not kept by the server)").dim(),
+                ])
+            } else {
+                Line::from(vec![
+                    "on ".into(),
+                    Span::from(format!("#{}", room_data.name)).bold(),
+                    " for ".into(),
+                    Span::from(format!(r#""{}""#, room_data.description)).italic(),
+                    Span::from(format!(
+                        " ({})",
+                        history_visibility_label(&room_data.history_visibility)
+                    ))
+                    .dim(),
+                ])
+            }
         } else {
+            Line::from(NO_ROOM_SELECTED_MESSAGE)
+        };
+        let text = Text::from(top_line);
+
+        // the banner takes the place of the room information until the connection is back
+        let help_message = match (self.props.reconnect_attempt, &self.props.server_shutdown) {
+            (Some(attempt), server_shutdown) => Paragraph::new(Line::from(vec![
+                Span::from("Reconnecting…").bold(),
+                Span::from(match server_shutdown {
+                    Some(server_shutdown) => format!(
+                        " the server shut down, {} (attempt {})",
+                        server_shutdown.reason, attempt
+                    ),
+                    None => format!(" the connection to the server dropped (attempt {})", attempt),
+                })
+                .dim(),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.props.theme.warning))
+                    .title("Connection Lost"),
+            ),
+            (None, Some(server_shutdown)) => Paragraph::new(Line::from(vec![
+                Span::from("The server is shutting down").bold(),
+                Span::from(format!(
+                    ", {}. It closes the connection within {} seconds, and is reconnected to once it is back.",
+                    server_shutdown.reason, server_shutdown.grace_seconds
+                ))
+                .dim(),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.props.theme.warning))
+                    .title("Server Shutting Down"),
+            ),
+            (None, None) if self.search_input.is_some() || self.props.search.is_some() => {
+                Paragraph::new(self.search_line()).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(if self.search_input.is_some() {
+                            self.props.theme.active_border
+                        } else {
+                            Color::Reset
+                        }))
+                        .title("Search"),
+                )
+            }
+            (None, None) => Paragraph::new(text).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Active Room Information"),
+            ),
+        };
+        frame.render_widget(help_message, container_highlight);
+
+        if let (Some(announcement), Some(container_announcement)) =
+            (self.props.announcement.as_ref(), container_announcement)
+        {
+            let banner = Paragraph::new(Line::from(Span::from(announcement.text.as_str()).bold()))
+                .wrap(Wrap { trim: true })
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(self.props.theme.accent))
+                        .title("Announcement")
+                        .title(
+                            block::Title::from(Span::from(" Esc to dismiss ").dim())
+                                .alignment(Alignment::Right),
+                        ),
+                );
+            frame.render_widget(banner, container_announcement);
+        }
+
+        self.message_list.render(
+            frame,
+            message_list::RenderProps {
+                area: container_messages,
+                border_color: self.calculate_border_color(Section::MessageList),
+            },
+        );
+
+        self.message_input_box.render(
+            frame,
+            message_input_box::RenderProps {
+                border_color: self.calculate_border_color(Section::MessageInput),
+                area: container_input,
+                show_cursor: self
+                    .active_section
+                    .as_ref()
+                    .map(|active_section| active_section.eq(&Section::MessageInput))
+                    .unwrap_or(false),
+            },
+        );
+
+        if let Some(invitation) = self.props.pending_invitation.as_ref().filter(|_| {
+            self.active_section.is_none()
+                && !self.is_date_picker_open
+                && !self.search_results.is_open()
+                && self.search_input.is_none()
+                && self.props.search.is_none()
+        }) {
+            let area = centered_rect(50, 4, frame.size());
+            let prompt = Paragraph::new(Text::from(vec![
+                Line::from(vec![
+                    Span::from(format!("@{}", invitation.from_user_id)).bold(),
+                    " invited you to ".into(),
+                    Span::from(format!("#{}", invitation.room.name)).bold(),
+                ]),
+                Line::from(Span::from("y to accept, n to decline").dim()),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.props.theme.active_border))
+                    .title("Invitation"),
+            );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(prompt, area);
+        }
+
+        if self.is_date_picker_open {
+            self.date_picker.render(
+                frame,
+                date_picker::RenderProps {
+                    area: centered_rect(30, 5, frame.size()),
+                    border_color: self.props.theme.active_border,
+                },
+            );
+        }
+
+        if self.search_results.is_open() {
+            let size = frame.size();
+
+            self.search_results.render(
+                frame,
+                search_results::RenderProps {
+                    area: centered_rect(size.width * 3 / 4, size.height * 2 / 3, size),
+                    border_color: self.props.theme.active_border,
+                },
+            );
+        }
+
+        if self.command_palette.is_open() {
+            self.command_palette.render(
+                frame,
+                command_palette::RenderProps {
+                    area: centered_rect(60, 12, frame.size()),
+                    border_color: self.props.theme.active_border,
+                },
+            );
+        }
+
+        if self.room_switcher.is_open() {
+            self.room_switcher.render(
+                frame,
+                room_switcher::RenderProps {
+                    area: centered_rect(40, 12, frame.size()),
+                    border_color: self.props.theme.active_border,
+                },
+            );
+        }
+
+        if self.user_profile.is_open() {
+            self.user_profile.render(
+                frame,
+                user_profile::RenderProps {
+                    area: centered_rect(50, 12, frame.size()),
+                    border_color: self.props.theme.active_border,
+                },
+            );
+        }
+
+        if let Some(room) = self.props.leave_prompt.as_ref() {
+            let area = centered_rect(50, 4, frame.size());
+            let prompt = Paragraph::new(Text::from(vec![
+                Line::from(vec![
+                    "Leave ".into(),
+                    Span::from(format!("#{}", room)).bold(),
+                    "?".into(),
+                ]),
+                Line::from(Span::from("y to leave, n to stay").dim()),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.props.theme.active_border))
+                    .title("Leave room"),
+            );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(prompt, area);
+        }
+
+        // the help covers the whole page, the other overlays included
+        if self.props.is_help_open {
+            self.render_help(frame);
+        }
+    }
+}
+
+/// [ChatLayout] is where each part of the chat page is rendered, the mouse events are routed by it as well
+struct ChatLayout {
+    highlight: Rect,
+    /// The banner of the announcement above the messages, while there is one
+    announcement: Option<Rect>,
+    messages: Rect,
+    input: Rect,
+    /// The rooms, the direct messages and the user, unless the panel is collapsed
+    left_panel: Option<LeftPanel>,
+    /// The users of the room and the usage, unless the panel is collapsed
+    right_panel: Option<RightPanel>,
+    /// The line at the bottom of the page, below the panels
+    status_bar: Rect,
+}
+
+struct LeftPanel {
+    room_list: Rect,
+    direct_message_list: Rect,
+}
+
+struct RightPanel {
+    room_users: Rect,
+    usage: Rect,
+}
+
+impl ChatLayout {
+    fn split(area: Rect, has_announcement: bool, panel_layout: &PanelLayout) -> Self {
+        let [area, status_bar] = layout::split(
+            area,
+            Direction::Vertical,
+            [Constraint::Min(1), Constraint::Length(1)],
+        );
+        let [left, middle, right] = layout::split(
+            area,
+            Direction::Horizontal,
+            panel_layout.constraints(area.width),
+        );
+
+        let left_panel = panel_layout.is_shown(Side::Left, area.width).then(|| {
+            let [room_list, direct_message_list] = layout::split(
+                left,
+                Direction::Vertical,
+                [Constraint::Min(1), Constraint::Length(8)],
+            );
+
+            LeftPanel {
+                room_list,
+                direct_message_list,
+            }
+        });
+
+        let right_panel = panel_layout.is_shown(Side::Right, area.width).then(|| {
+            let [room_users, usage] = layout::split(
+                right,
+                Direction::Vertical,
+                [Constraint::Min(1), Constraint::Length(10)],
+            );
+
+            RightPanel { room_users, usage }
+        });
+
+        let [highlight, announcement, messages, input] = layout::split(
+            middle,
+            Direction::Vertical,
+            [
+                Constraint::Length(3),
+                Constraint::Length(if has_announcement { 4 } else { 0 }),
+                Constraint::Min(1),
+                Constraint::Length(3),
+            ],
+        );
+
+        ChatLayout {
+            highlight,
+            announcement: has_announcement.then_some(announcement),
+            messages,
+            input,
+            left_panel,
+            right_panel,
+            status_bar,
+        }
+    }
+}
+
+/// Returns the row of the list clicked at, counted from the first item shown inside its borders
+fn clicked_row(area: Rect, mouse: &MouseEvent) -> Option<usize> {
+    let is_inside = mouse.column > area.x
+        && mouse.column < area.right().saturating_sub(1)
+        && mouse.row > area.y
+        && mouse.row < area.bottom().saturating_sub(1);
+
+    is_inside.then(|| usize::from(mouse.row - area.y - 1))
+}
+
+fn contains(area: Rect, mouse: &MouseEvent) -> bool {
+    mouse.column >= area.x
+        && mouse.column < area.right()
+        && mouse.row >= area.y
+        && mouse.row < area.bottom()
+}
+
+impl HasUsageInfo for ChatPage {
+    fn usage_info(&self) -> UsageInfo {
+        if self.props.leave_prompt.is_some() {
             UsageInfo {
-                description: Some("Select a widget".into()),
+                description: Some("Leave the room, it can be joined again later".into()),
                 lines: vec![
                     UsageInfoLine {
-                        keys: vec!["q".into()],
-                        description: "to exit".into(),
+                        keys: vec!["y".into(), "Enter".into()],
+                        description: "to leave".into(),
                     },
                     UsageInfoLine {
-                        keys: vec!["←".into(), "→".into()],
-                        description: "to hover widgets".into(),
+                        keys: vec!["n".into(), "Esc".into()],
+                        description: "to stay".into(),
                     },
+                ],
+            }
+        } else if self.command_palette.is_open() {
+            self.command_palette.usage_info()
+        } else if self.room_switcher.is_open() {
+            self.room_switcher.usage_info()
+        } else if self.user_profile.is_open() {
+            self.user_profile.usage_info()
+        } else if self.is_date_picker_open {
+            self.date_picker.usage_info()
+        } else if self.search_results.is_open() {
+            self.search_results.usage_info()
+        } else if self.search_input.is_some() {
+            UsageInfo {
+                description: Some("Search the messages of the room".into()),
+                lines: vec![
                     UsageInfoLine {
-                        keys: vec!["e".into()],
-                        description: format!(
-                            "to activate {}",
-                            self.get_component_for_section(&self.last_hovered_section)
-                                .name()
-                        ),
+                        keys: vec!["Enter".into()],
+                        description: "to move between the matches".into(),
+                    },
+                    UsageInfoLine {
+                        keys: vec!["Esc".into()],
+                        description: "to cancel".into(),
                     },
                 ],
             }
+        } else if let Some(section) = self.active_section.as_ref() {
+            let handler: &dyn HasUsageInfo = match section {
+                Section::RoomList => &self.room_list,
+                Section::DirectMessageList => &self.direct_message_list,
+                Section::MessageInput => &self.message_input_box,
+                Section::MessageList => &self.message_list,
+            };
+
+            handler.usage_info()
+        } else {
+            let keymap = &self.props.keymap;
+
+            let mut lines = vec![
+                UsageInfoLine {
+                    keys: keymap.labels(KeyAction::Quit),
+                    description: KeyAction::Quit.describe().into(),
+                },
+                UsageInfoLine {
+                    keys: [
+                        keymap.labels(KeyAction::FocusPrevious),
+                        keymap.labels(KeyAction::FocusNext),
+                    ]
+                    .concat(),
+                    description: "to hover widgets".into(),
+                },
+                UsageInfoLine {
+                    keys: keymap.labels(KeyAction::Activate),
+                    description: format!(
+                        "to activate {}",
+                        self.get_component_for_section(&self.last_hovered_section)
+                            .name()
+                    ),
+                },
+                UsageInfoLine {
+                    keys: vec!["g".into()],
+                    description: "to jump to a date".into(),
+                },
+                UsageInfoLine {
+                    keys: [
+                        keymap.labels(KeyAction::ScrollUp),
+                        keymap.labels(KeyAction::ScrollDown),
+                    ]
+                    .concat(),
+                    description: "to scroll messages".into(),
+                },
+                UsageInfoLine {
+                    keys: keymap.labels(KeyAction::ScrollToLatest),
+                    description: KeyAction::ScrollToLatest.describe().into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["/".into(), "Ctrl+f".into()],
+                    description: "to search the messages".into(),
+                },
+                UsageInfoLine {
+                    keys: [
+                        keymap.labels(KeyAction::ToggleLeftPanel),
+                        keymap.labels(KeyAction::ToggleRightPanel),
+                    ]
+                    .concat(),
+                    description: "to collapse or expand the side panels".into(),
+                },
+            ];
+
+            if self.props.search.is_some() {
+                lines.extend([
+                    UsageInfoLine {
+                        keys: vec!["n".into(), "N".into()],
+                        description: "to move to an older or newer match".into(),
+                    },
+                    UsageInfoLine {
+                        keys: vec!["Esc".into()],
+                        description: "to stop searching".into(),
+                    },
+                ]);
+            } else {
+                lines.push(UsageInfoLine {
+                    keys: vec!["y".into(), "n".into()],
+                    description: "to accept or decline an invitation".into(),
+                });
+            }
+
+            if self.props.announcement.is_some() {
+                lines.push(UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to dismiss the announcement".into(),
+                });
+            }
+
+            UsageInfo {
+                description: Some("Select a widget".into()),
+                lines,
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use comms::event;
+
+    use super::*;
+    use crate::config::LayoutConfig;
+    use crate::keymap::KeyBindingsFile;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn general_room() -> State {
+        State::test_with_rooms(&[("general", "General talk"), ("rust", "Rustaceans")])
+            .with_user_id("me")
+            .with_joined_room("general", &["alice", "me"])
+            .with_active_room("general")
+    }
+
+    #[test]
+    fn test_activates_the_hovered_section_until_esc() {
+        let state = general_room().with_message("general", "alice", "hi");
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness.press(KeyCode::Char('e'));
+        assert_eq!(
+            harness.component().active_section,
+            Some(Section::MessageInput)
+        );
+
+        // the keys of the page are typed into the active section
+        harness.type_text("q").press(KeyCode::Esc);
+        assert_eq!(harness.component().active_section, None);
+        assert!(harness.component().message_input_box.input_box.is_empty());
+
+        harness.press(KeyCode::Left).press(KeyCode::Char('e'));
+        assert_eq!(
+            harness.component().active_section,
+            Some(Section::MessageList)
+        );
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SaveDraft {
+                room: "general".into(),
+                content: "q".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_replying_moves_on_to_the_message_input() {
+        let state = general_room()
+            .with_message("general", "alice", "lunch?")
+            .with_message("general", "alice", "anyone?");
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness
+            .press(KeyCode::Left)
+            .press(KeyCode::Char('e'))
+            .press(KeyCode::Up)
+            .press(KeyCode::Char('r'));
+        assert_eq!(
+            harness.component().active_section,
+            Some(Section::MessageInput)
+        );
+
+        harness.type_text("sure").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ReplyToMessage { id: 0 },
+                Action::SendMessage {
+                    content: "sure".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_searches_the_messages() {
+        let mut state = general_room().with_message("general", "alice", "rust is fun");
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness
+            .press(KeyCode::Char('/'))
+            .type_text("ru")
+            .press(KeyCode::Backspace);
+        assert_eq!(harness.component().search_input.as_deref(), Some("r"));
+
+        harness.press(KeyCode::Enter);
+        assert_eq!(harness.component().search_input, None);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SearchMessages { query: "r".into() },
+                Action::SearchMessages { query: "ru".into() },
+                Action::SearchMessages { query: "r".into() },
+            ]
+        );
+
+        // the hits are moved between once the query is typed, instead of answering invitations
+        state.search_active_room("r".into());
+        harness
+            .apply_state(&state)
+            .press(KeyCode::Char('n'))
+            .press_with_modifiers(KeyCode::Char('N'), KeyModifiers::SHIFT)
+            .press(KeyCode::Esc);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::MoveSearch { older: true },
+                Action::MoveSearch { older: false },
+                Action::CloseSearch,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_routes_the_clicks() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // on a 100x30 terminal the rooms are listed from the second row of the first 20 columns,
+        // the room users from the second row of the last 20 columns, and the input is on the last 3 rows
+        harness.render(100, 30).click(50, 28);
+        assert_eq!(
+            harness.component().active_section,
+            Some(Section::MessageInput)
+        );
+
+        harness
+            .type_text("hi")
+            .press(KeyCode::Enter)
+            .click(2, 2)
+            .click(82, 1)
+            .click(82, 2);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SendMessage {
+                    content: "hi".into()
+                },
+                Action::SelectRoom {
+                    room: "rust".into()
+                },
+                Action::OpenDirectMessage {
+                    user_id: "alice".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shows_the_profile_of_the_clicked_room_user() {
+        let mut state = general_room();
+        state.can_view_profiles = true;
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness.render(100, 30).click(82, 1);
+        state.user_profile = Some(event::UserProfile {
+            user_id: "alice".into(),
+            ..Default::default()
+        });
+        // the keys are taken by the popup rather than the page
+        harness
+            .apply_state(&state)
+            .press(KeyCode::Char('q'))
+            .press(KeyCode::Enter);
+
+        assert!(!harness.component().user_profile.is_open());
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::GetUserProfile {
+                    user_id: "alice".into()
+                },
+                Action::OpenDirectMessage {
+                    user_id: "alice".into()
+                },
+                Action::CloseUserProfile,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_routes_no_clicks_to_collapsed_panels() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // the messages take the place of the collapsed panels
+        harness
+            .render(100, 30)
+            .press(KeyCode::Char('['))
+            .press(KeyCode::Char(']'))
+            .render(100, 30)
+            .click(2, 2)
+            .click(82, 2);
+
+        assert!(!harness.component().is_section_shown(&Section::RoomList));
+        assert!(harness.drain_actions().is_empty());
+    }
+
+    #[test]
+    fn test_resizes_the_side_panels() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // on a 100x30 terminal, once the rooms are widened to 25%, their border with the messages is on the 25th column
+        harness
+            .render(100, 30)
+            .press_with_modifiers(KeyCode::Right, KeyModifiers::CONTROL)
+            .press_with_modifiers(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+            .render(100, 30)
+            .drag((25, 10), (34, 10));
+
+        let layout = LayoutConfig {
+            left_panel_percent: 35,
+            right_panel_percent: 25,
+            ..LayoutConfig::default()
+        };
+        assert_eq!(harness.component().panel_layout.config(), layout);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SetLayout {
+                    layout: LayoutConfig {
+                        left_panel_percent: 25,
+                        ..LayoutConfig::default()
+                    }
+                },
+                Action::SetLayout {
+                    layout: LayoutConfig {
+                        left_panel_percent: 25,
+                        right_panel_percent: 25,
+                        ..LayoutConfig::default()
+                    }
+                },
+                Action::SetLayout { layout },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_follows_the_rebound_keys() {
+        let state = State {
+            keymap: Keymap::try_from(&KeyBindingsFile {
+                send: vec!["Ctrl+s".into()],
+                quit: vec!["Ctrl+q".into()],
+                ..Default::default()
+            })
+            .unwrap(),
+            ..general_room()
+        };
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness
+            .press(KeyCode::Char('e'))
+            .type_text("hi")
+            .press(KeyCode::Enter)
+            .press_with_modifiers(KeyCode::Char('s'), KeyModifiers::CONTROL)
+            .press(KeyCode::Esc)
+            .press(KeyCode::Char('q'))
+            .press_with_modifiers(KeyCode::Char('q'), KeyModifiers::CONTROL);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SendMessage {
+                    content: "hi".into()
+                },
+                Action::Exit,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_opens_the_search_with_its_bound_key() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // the search is opened while typing a message as well
+        harness
+            .press(KeyCode::Char('e'))
+            .press_with_modifiers(KeyCode::Char('f'), KeyModifiers::CONTROL);
+        assert_eq!(harness.component().search_input.as_deref(), Some(""));
+
+        let state = State {
+            keymap: Keymap::try_from(&KeyBindingsFile {
+                search: vec!["F3".into()],
+                ..Default::default()
+            })
+            .unwrap(),
+            ..general_room()
+        };
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness.press_with_modifiers(KeyCode::Char('f'), KeyModifiers::CONTROL);
+        assert_eq!(harness.component().search_input, None);
+
+        harness.press(KeyCode::F(3));
+        assert_eq!(harness.component().search_input.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_takes_the_vim_keys() {
+        let state = State {
+            vim_mode: true,
+            ..general_room()
+        };
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        // the keys are typed into the message input once in the insert mode, until Esc is pressed
+        harness
+            .press(KeyCode::Char('k'))
+            .press(KeyCode::Char('j'))
+            .type_text("gg")
+            .press_with_modifiers(KeyCode::Char('G'), KeyModifiers::SHIFT)
+            .type_text("gx")
+            .type_text("ijk")
+            .press(KeyCode::Enter)
+            .press(KeyCode::Esc)
+            .press(KeyCode::Char('k'))
+            .type_text("gd");
+        assert!(harness.component().is_date_picker_open);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ScrollMessages { items: 1 },
+                Action::ScrollMessages { items: -1 },
+                Action::ScrollMessages { items: isize::MAX },
+                Action::ReturnToLatest,
+                Action::SendMessage {
+                    content: "jk".into()
+                },
+                Action::ScrollMessages { items: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_opens_the_popups_from_any_section() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // the keys of the palette are not typed into the message input
+        harness
+            .press(KeyCode::Char('e'))
+            .press_with_modifiers(KeyCode::Char('p'), KeyModifiers::CONTROL)
+            .type_text("rst");
+        assert!(harness.component().command_palette.is_open());
+        assert!(harness.component().message_input_box.input_box.is_empty());
+
+        harness
+            .press(KeyCode::Enter)
+            .press_with_modifiers(KeyCode::Char('k'), KeyModifiers::CONTROL);
+        assert!(!harness.component().command_palette.is_open());
+        assert!(harness.component().room_switcher.is_open());
+
+        harness.press(KeyCode::Esc).press(KeyCode::Enter);
+        assert!(!harness.component().room_switcher.is_open());
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SelectRoom {
+                room: "rust".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_the_leave_prompt_takes_the_keys_until_answered() {
+        let mut state = general_room();
+        state.leave_prompt = Some("general".into());
+        let mut harness = TestHarness::<ChatPage>::new(&state);
+
+        harness
+            .press(KeyCode::Char('e'))
+            .press(KeyCode::Char('n'))
+            .press(KeyCode::Char('e'));
+        assert_eq!(harness.drain_actions(), vec![Action::CancelLeaveRoom]);
+        assert_eq!(
+            harness.component().active_section,
+            Some(Section::MessageInput)
+        );
+
+        harness
+            .apply_state(&state)
+            .press(KeyCode::Esc)
+            .press(KeyCode::Char('y'));
+        assert_eq!(harness.drain_actions(), vec![Action::CancelLeaveRoom]);
+
+        harness.apply_state(&state).press(KeyCode::Char('y'));
+        assert_eq!(harness.drain_actions(), vec![Action::ConfirmLeaveRoom]);
+    }
+
+    #[test]
+    fn test_toggles_the_help() {
+        let mut harness = TestHarness::<ChatPage>::new(&general_room());
+
+        // the keys are not handled by the page while the help is open
+        harness
+            .press(KeyCode::Char('?'))
+            .press(KeyCode::Down)
+            .press(KeyCode::Char('q'));
+        assert!(harness.component().props.is_help_open);
+        assert_eq!(harness.component().help_scroll, 1);
+
+        harness.press(KeyCode::Char('?'));
+        assert!(!harness.component().props.is_help_open);
+        assert_eq!(harness.component().help_scroll, 0);
+
+        harness.press(KeyCode::Char('q'));
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::ShowHelp, Action::CloseHelp, Action::Exit]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+/// The sources the words of the message input are completed from
+pub struct CompletionSources<'a> {
+    /// The users of the active room, completing the `@user` mentions
+    pub users: &'a [String],
+    /// The rooms in the room list, completing the `#room` references
+    pub rooms: &'a [String],
+    /// The names of the slash commands, completing the first word when it starts with a `/`
+    pub commands: &'a [&'a str],
+}
+
+/// [Completion] cycles through the candidates completing the word before the cursor
+///
+/// The positions are in characters rather than bytes, as the cursor of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// Where the completed word starts
+    start: usize,
+    /// Where the inserted candidate ends, the cursor is placed there
+    end: usize,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+impl Completion {
+    /// Starts completing the word before the cursor, returns None if there is nothing to complete it with
+    pub fn start(text: &str, cursor: usize, sources: &CompletionSources) -> Option<Self> {
+        let before_cursor = text.chars().take(cursor).collect::<Vec<_>>();
+        let start = before_cursor
+            .iter()
+            .rposition(|char| char.is_whitespace())
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        let word = before_cursor[start..].iter().collect::<String>();
+
+        let candidates = candidates(&word, start == 0, sources);
+        if candidates.is_empty() {
+            return None;
+        }
+
+        Some(Completion {
+            start,
+            end: cursor,
+            candidates,
+            index: 0,
+        })
+    }
+
+    /// The candidate to insert, followed by a space, and the range of characters it replaces
+    pub fn current(&self) -> (usize, usize, String) {
+        (
+            self.start,
+            self.end,
+            format!("{} ", self.candidates[self.index]),
+        )
+    }
+
+    /// Records where the inserted candidate ends, so the next candidate replaces it
+    pub fn inserted(&mut self, end: usize) {
+        self.end = end;
+    }
+
+    /// Moves on to the next candidate, going back to the first one after the last one
+    pub fn cycle(&mut self) {
+        self.index = (self.index + 1) % self.candidates.len();
+    }
+}
+
+/// Finds the candidates starting with the word, ignoring the case, in alphabetical order
+fn candidates(word: &str, is_first_word: bool, sources: &CompletionSources) -> Vec<String> {
+    let (sigil, names): (char, Vec<&str>) = match word.chars().next() {
+        Some('@') => ('@', sources.users.iter().map(String::as_str).collect()),
+        Some('#') => ('#', sources.rooms.iter().map(String::as_str).collect()),
+        Some('/') if is_first_word => ('/', sources.commands.to_vec()),
+        _ => return vec![],
+    };
+    let prefix = word[sigil.len_utf8()..].to_lowercase();
+
+    let mut candidates = names
+        .into_iter()
+        .filter(|name| name.to_lowercase().starts_with(&prefix))
+        .map(|name| format!("{}{}", sigil, name))
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(text: &str, cursor: usize, sources: &CompletionSources) -> Vec<String> {
+        let Some(mut completion) = Completion::start(text, cursor, sources) else {
+            return vec![];
+        };
+
+        let mut completed = vec![];
+        for _ in 0..completion.candidates.len() {
+            completed.push(completion.current().2);
+            completion.cycle();
+        }
+
+        completed
+    }
+
+    #[test]
+    fn test_mentions_and_rooms_are_completed_in_order() {
+        let users = vec!["bob".into(), "Alice".into(), "alex".into()];
+        let rooms = vec!["rust".into(), "general".into()];
+        let sources = CompletionSources {
+            users: &users,
+            rooms: &rooms,
+            commands: &["join"],
+        };
+
+        assert_eq!(complete("hi @al", 6, &sources), vec!["@Alice ", "@alex "]);
+        assert_eq!(complete("see #r", 6, &sources), vec!["#rust "]);
+        assert!(complete("hi al", 5, &sources).is_empty());
+    }
+
+    #[test]
+    fn test_commands_are_only_completed_as_the_first_word() {
+        let sources = CompletionSources {
+            users: &[],
+            rooms: &[],
+            commands: &["join", "leave"],
+        };
+
+        assert_eq!(complete("/j", 2, &sources), vec!["/join "]);
+        assert!(complete("hi /j", 5, &sources).is_empty());
+    }
+
+    #[test]
+    fn test_word_before_the_cursor_is_completed() {
+        let users = vec!["ümit".into()];
+        let sources = CompletionSources {
+            users: &users,
+            rooms: &[],
+            commands: &[],
+        };
+        // the cursor is in the middle of the text, after a multi-byte character
+        let completion = Completion::start("çok @ü güzel", 6, &sources).unwrap();
+
+        assert_eq!(completion.current(), (4, 6, String::from("@ümit ")));
+    }
+}
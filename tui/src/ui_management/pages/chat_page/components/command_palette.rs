@@ -0,0 +1,304 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::{
+    fuzzy::fuzzy_score,
+    section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine},
+};
+use crate::{
+    state_store::{action::Action, direct_message_room, State},
+    theme::Theme,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+/// An action of the palette, with the label it is searched and listed by
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteEntry {
+    pub label: String,
+    pub action: Action,
+}
+
+struct Props {
+    /// The rooms and the conversations of direct messages to switch to, by their name
+    rooms: Vec<String>,
+    conversations: Vec<String>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        let (mut conversations, mut rooms): (Vec<_>, Vec<_>) = state
+            .room_data_map
+            .values()
+            .partition(|room_data| room_data.is_direct_message);
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        conversations.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Props {
+            rooms: rooms
+                .iter()
+                .map(|room_data| room_data.name.clone())
+                .collect(),
+            conversations: conversations
+                .iter()
+                .map(|room_data| room_data.name.clone())
+                .collect(),
+            theme: state.theme,
+        }
+    }
+}
+
+/// CommandPalette is an overlay searching through the actions by their label, such as switching rooms, and
+/// dispatching the one picked
+pub struct CommandPalette {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped CommandPalette Props
+    props: Props,
+    // Internal Component State
+    is_open: bool,
+    query: String,
+    /// Index of the selected entry, among the ones matching the query
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    /// Every action of the palette, the rooms to switch to first
+    fn entries(&self) -> Vec<PaletteEntry> {
+        let rooms = self.props.rooms.iter().map(|room| PaletteEntry {
+            label: format!("Switch to #{}", room),
+            action: Action::SelectRoom { room: room.clone() },
+        });
+        let conversations = self.props.conversations.iter().map(|user_id| PaletteEntry {
+            label: format!("Switch to @{}", user_id),
+            action: Action::SelectRoom {
+                room: direct_message_room(user_id),
+            },
+        });
+        let actions = [
+            ("Toggle the theme", Action::ToggleTheme),
+            ("Reconnect to the server", Action::Reconnect),
+            ("Toggle do not disturb", Action::ToggleDoNotDisturb),
+            ("Return to the latest messages", Action::ReturnToLatest),
+            ("Show the help", Action::ShowHelp),
+            ("Quit", Action::Exit),
+        ]
+        .into_iter()
+        .map(|(label, action)| PaletteEntry {
+            label: String::from(label),
+            action,
+        });
+
+        rooms.chain(conversations).chain(actions).collect()
+    }
+
+    /// The entries matching the query, the best matches first
+    fn matches(&self) -> Vec<PaletteEntry> {
+        let mut matches = self
+            .entries()
+            .into_iter()
+            .filter_map(|entry| Some((fuzzy_score(&self.query, &entry.label)?, entry)))
+            .collect::<Vec<_>>();
+        // the sort is stable, the entries scoring the same keep their order
+        matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    fn dispatch_selected(&mut self) {
+        if let Some(entry) = self.matches().into_iter().nth(self.selected) {
+            let _ = self.action_tx.send(entry.action);
+        }
+
+        self.close();
+    }
+}
+
+impl Component for CommandPalette {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            is_open: false,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Command Palette"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Enter => self.dispatch_selected(),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.matches().len().saturating_sub(1))
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            KeyCode::Char(char) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.query.push(char);
+                self.selected = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+impl ComponentRender<RenderProps> for CommandPalette {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let matches = self.matches();
+        let has_matches = !matches.is_empty();
+        let items = if has_matches {
+            matches
+                .into_iter()
+                .map(|entry| ListItem::new(Line::from(entry.label)))
+                .collect()
+        } else {
+            vec![ListItem::new(Line::from(
+                Span::from("no matching action").dim(),
+            ))]
+        };
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(props.border_color))
+                    .title(format!("> {}", self.query)),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg),
+            );
+
+        let mut list_state = ListState::default();
+        list_state.select(has_matches.then_some(self.selected));
+
+        frame.render_widget(Clear, props.area);
+        frame.render_stateful_widget(list, props.area, &mut list_state);
+    }
+}
+
+impl HasUsageInfo for CommandPalette {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Type to search the actions".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to close".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to select an action".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Enter".into()],
+                    description: "to run it".into(),
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn palette() -> TestHarness<CommandPalette> {
+        let state = State::test_with_rooms(&[
+            ("general", "General talk"),
+            ("rust", "Talk about the Rust programming language"),
+        ])
+        .with_joined_room("general", &["alice"])
+        .with_active_room("general");
+        let mut harness = TestHarness::<CommandPalette>::new(&state);
+
+        harness.component_mut().open();
+        harness
+    }
+
+    #[test]
+    fn test_dispatches_the_best_match_of_the_query() {
+        let mut harness = palette();
+
+        harness.type_text("rst").press(KeyCode::Enter);
+        assert!(!harness.component().is_open());
+
+        harness.component_mut().open();
+        harness.type_text("theme").press(KeyCode::Enter);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SelectRoom {
+                    room: "rust".into()
+                },
+                Action::ToggleTheme,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dispatches_nothing_once_closed() {
+        let mut harness = palette();
+
+        harness.type_text("quit").press(KeyCode::Esc);
+        assert!(!harness.component().is_open());
+
+        // the query is cleared once opened again
+        harness.component_mut().open();
+        assert!(harness.component().query.is_empty());
+
+        assert!(harness.drain_actions().is_empty());
+    }
+}
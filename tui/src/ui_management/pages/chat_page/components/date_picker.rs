@@ -0,0 +1,154 @@
+use chrono::{Days, Local, NaiveDate};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    prelude::{Alignment, Backend, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
+use crate::{
+    state_store::{action::Action, State},
+    ui_management::pages::chat_page::section::SectionActivation,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+/// Converts the start of the given day in the local timezone to milliseconds since the unix epoch
+pub fn start_of_day_timestamp(date: &NaiveDate) -> Option<u64> {
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|date_time| date_time.timestamp_millis().max(0) as u64)
+}
+
+/// DatePicker is an overlay for picking a date to jump to in the history of the active room
+pub struct DatePicker {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    // Internal Component State
+    /// The currently picked date
+    date: NaiveDate,
+}
+
+impl DatePicker {
+    fn move_days(&mut self, days: i64) {
+        let moved = if days >= 0 {
+            self.date.checked_add_days(Days::new(days as u64))
+        } else {
+            self.date.checked_sub_days(Days::new(days.unsigned_abs()))
+        };
+
+        // do not allow picking a date in the future, there is no history there
+        if let Some(moved) = moved.filter(|moved| *moved <= Local::now().date_naive()) {
+            self.date = moved;
+        }
+    }
+
+    fn jump_to_date(&self) {
+        if let Some(timestamp) = start_of_day_timestamp(&self.date) {
+            let _ = self.action_tx.send(Action::JumpToDate { timestamp });
+        }
+    }
+}
+
+impl Component for DatePicker {
+    fn new(_state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            date: Local::now().date_naive(),
+        }
+    }
+
+    fn move_with_state(self, _state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self { ..self }
+    }
+
+    fn name(&self) -> &str {
+        "Date Picker"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Left => self.move_days(-1),
+            KeyCode::Right => self.move_days(1),
+            KeyCode::Up => self.move_days(-7),
+            KeyCode::Down => self.move_days(7),
+            KeyCode::Enter => self.jump_to_date(),
+            _ => (),
+        }
+    }
+}
+
+impl SectionActivation for DatePicker {
+    fn activate(&mut self) {
+        self.date = Local::now().date_naive();
+    }
+
+    fn deactivate(&mut self) {}
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+impl ComponentRender<RenderProps> for DatePicker {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let text = Text::from(vec![
+            Line::from(""),
+            Line::from(vec![
+                "◀ ".into(),
+                Span::from(self.date.format("%Y-%m-%d").to_string()).bold(),
+                " ▶".into(),
+            ]),
+            Line::from(Span::from(self.date.format("%A").to_string()).italic()),
+        ]);
+
+        let date_picker = Paragraph::new(text).alignment(Alignment::Center).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(props.border_color))
+                .title("Jump to Date"),
+        );
+
+        frame.render_widget(Clear, props.area);
+        frame.render_widget(date_picker, props.area);
+    }
+}
+
+impl HasUsageInfo for DatePicker {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Pick a date to jump to in the room history".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to cancel".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["←".into(), "→".into()],
+                    description: "to change the day".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to change the week".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Enter".into()],
+                    description: "to jump".into(),
+                },
+            ],
+        }
+    }
+}
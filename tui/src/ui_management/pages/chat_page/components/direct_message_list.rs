@@ -0,0 +1,278 @@
+use std::cell::Cell;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
+use crate::{
+    state_store::{action::Action, direct_message_room, State},
+    theme::Theme,
+    ui_management::pages::chat_page::section::SectionActivation,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+pub struct ConversationState {
+    /// The id of the user the conversation is with
+    pub user_id: String,
+    pub has_unread: bool,
+}
+
+struct Props {
+    /// The conversations of direct messages, sorted by the id of the other user
+    conversations: Vec<ConversationState>,
+    /// Current active room, which may be a conversation
+    active_room: Option<String>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        let mut conversations = state
+            .room_data_map
+            .values()
+            .filter(|room_data| room_data.is_direct_message)
+            .map(|room_data| ConversationState {
+                user_id: room_data.name.clone(),
+                has_unread: room_data.unread_count > 0,
+            })
+            .collect::<Vec<_>>();
+
+        conversations.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+
+        Self {
+            conversations,
+            active_room: state.active_room.clone(),
+            theme: state.theme,
+        }
+    }
+}
+
+/// DirectMessageList lists the conversations of direct messages, next to the room list
+///
+/// A conversation is opened with the `/dm` command, or when someone messages the user.
+pub struct DirectMessageList {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped DirectMessageList Props
+    props: Props,
+    // Internal Component State
+    /// List with optional selection and current offset
+    pub list_state: ListState,
+    /// The index of the first conversation shown when last rendered, to find the clicked conversation
+    rendered_offset: Cell<usize>,
+}
+
+impl DirectMessageList {
+    /// Selects the conversation shown at the given row and opens it, returns whether there was one
+    pub fn click(&mut self, row: usize) -> bool {
+        let idx = self.rendered_offset.get() + row;
+        if idx >= self.props.conversations.len() {
+            return false;
+        }
+
+        self.list_state.select(Some(idx));
+        self.open_selected();
+
+        true
+    }
+
+    fn open_selected(&mut self) {
+        let Some(conversation) = self
+            .list_state
+            .selected()
+            .and_then(|idx| self.props.conversations.get(idx))
+        else {
+            return;
+        };
+
+        let _ = self.action_tx.send(Action::SelectRoom {
+            room: direct_message_room(&conversation.user_id),
+        });
+    }
+
+    fn next(&mut self) {
+        let len = self.props.conversations.len();
+        if len == 0 {
+            return;
+        }
+
+        let i = match self.list_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.list_state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        let len = self.props.conversations.len();
+        if len == 0 {
+            return;
+        }
+
+        let i = match self.list_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.list_state.select(Some(i));
+    }
+
+    /// Is a conversation selected, which is opened on enter
+    pub fn is_conversation_selected(&self) -> bool {
+        self.list_state
+            .selected()
+            .map(|idx| idx < self.props.conversations.len())
+            .unwrap_or(false)
+    }
+}
+
+impl Component for DirectMessageList {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            //
+            list_state: ListState::default(),
+            rendered_offset: Cell::new(0),
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Direct Messages"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Up => self.previous(),
+            KeyCode::Down => self.next(),
+            KeyCode::Enter => self.open_selected(),
+            _ => (),
+        }
+    }
+}
+
+impl SectionActivation for DirectMessageList {
+    fn activate(&mut self) {
+        let idx = self
+            .props
+            .active_room
+            .as_ref()
+            .and_then(|active_room| {
+                self.props.conversations.iter().position(|conversation| {
+                    direct_message_room(&conversation.user_id).eq(active_room)
+                })
+            })
+            .unwrap_or(0);
+
+        *self.list_state.offset_mut() = 0;
+        self.list_state.select(Some(idx));
+    }
+
+    fn deactivate(&mut self) {
+        *self.list_state.offset_mut() = 0;
+        self.list_state.select(None);
+    }
+}
+
+pub struct RenderProps {
+    pub border_color: Color,
+    pub area: Rect,
+}
+
+impl ComponentRender<RenderProps> for DirectMessageList {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let active_room = self.props.active_room.clone();
+        let items: Vec<ListItem> = self
+            .props
+            .conversations
+            .iter()
+            .map(|conversation| {
+                let is_active = self.list_state.selected().is_none()
+                    && active_room.as_ref() == Some(&direct_message_room(&conversation.user_id));
+                let content = Line::from(Span::raw(format!(
+                    "@{}{}",
+                    conversation.user_id,
+                    if conversation.has_unread { "*" } else { "" }
+                )));
+
+                let style = if is_active {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else if conversation.has_unread {
+                    Style::default()
+                        .fg(self.props.theme.accent)
+                        .add_modifier(Modifier::BOLD | Modifier::ITALIC)
+                } else {
+                    Style::default()
+                };
+
+                ListItem::new(content).style(style.bg(Color::Reset))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::new().fg(props.border_color))
+                    .title("Direct Messages"),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(">");
+
+        let mut list_state = self.list_state.clone();
+        frame.render_stateful_widget(list, props.area, &mut list_state);
+        self.rendered_offset.set(list_state.offset());
+    }
+}
+
+impl HasUsageInfo for DirectMessageList {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Select the conversation to talk in".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to cancel".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to navigate".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Enter".into()],
+                    description: "to open the conversation".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["/dm <user> <message>".into()],
+                    description: "to start a conversation".into(),
+                },
+            ],
+        }
+    }
+}
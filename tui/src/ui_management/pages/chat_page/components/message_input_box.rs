@@ -1,30 +1,107 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use comms::event::RoomRole;
+use crossterm::event::{KeyEvent, KeyEventKind};
 use ratatui::{
     prelude::{Backend, Rect},
-    style::Color,
+    style::{Color, Stylize},
+    text::Span,
     Frame,
 };
 use tokio::sync::mpsc::UnboundedSender;
 
+use super::super::completion::{Completion, CompletionSources};
+use super::super::input_history::InputHistory;
 use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
+use super::super::slash_commands::{SlashCommandRegistry, Submission};
 use crate::ui_management::components::{
     input_box::{self, InputBox},
     Component, ComponentRender,
 };
 use crate::{
-    state_store::{action::Action, State},
+    keymap::{KeyAction, Keymap},
+    state_store::{action::Action, MessageBoxItem, State},
+    theme::Theme,
     ui_management::pages::chat_page::section::SectionActivation,
 };
 
 struct Props {
     /// Active room that the user is chatting in
     active_room: Option<String>,
+    /// The input template of the active room
+    input_template: Option<String>,
+    /// Should the input template pre-populate the input
+    use_input_templates: bool,
+    /// The users of the active room, to complete the mentions with
+    room_users: Vec<String>,
+    /// The rooms in the room list, to complete the room references with
+    rooms: Vec<String>,
+    /// The id and content of the last message the user has sent to the active room, to edit it
+    last_own_message: Option<(u64, String)>,
+    /// The author of the message the next message replies to, if it is still loaded
+    replying_to: Option<Option<String>>,
+    /// The role of the user in the active room, which limits the commands
+    role: RoomRole,
+    /// Shown while the server is rate limiting the messages of the user
+    rate_limit_warning: Option<String>,
+    /// The number of characters a message can be at most, once the server has told it
+    max_message_chars: Option<usize>,
+    /// The text left in the input when the user moved away from the active room
+    draft: Option<String>,
+    keymap: Keymap,
+    theme: Theme,
 }
 
 impl From<&State> for Props {
     fn from(state: &State) -> Self {
         Self {
             active_room: state.active_room.clone(),
+            input_template: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .and_then(|room_data| room_data.input_template.clone()),
+            use_input_templates: state.use_input_templates,
+            room_users: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .map(|room_data| room_data.users.iter().cloned().collect())
+                .unwrap_or_default(),
+            rooms: state
+                .room_data_map
+                .values()
+                .filter(|room_data| !room_data.is_direct_message)
+                .map(|room_data| room_data.name.clone())
+                .collect(),
+            last_own_message: state.last_own_message(),
+            rate_limit_warning: state.rate_limit_warning.clone(),
+            max_message_chars: state.max_message_chars,
+            keymap: state.keymap.clone(),
+            theme: state.theme,
+            draft: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .and_then(|room_data| room_data.draft.clone()),
+            role: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .map(|room_data| room_data.role)
+                .unwrap_or_default(),
+            replying_to: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .and_then(|room_data| {
+                    let reply_to = room_data.replying_to?;
+
+                    Some(room_data.messages.iter().find_map(|mbi| match mbi {
+                        MessageBoxItem::Message { id, user_id, .. } if *id == reply_to => {
+                            Some(user_id.clone())
+                        }
+                        _ => None,
+                    }))
+                }),
         }
     }
 }
@@ -35,20 +112,199 @@ pub struct MessageInputBox {
     props: Props,
     // Internal State for the Component
     pub input_box: InputBox,
+    /// The commands which are dispatched as actions instead of being sent as messages
+    slash_commands: SlashCommandRegistry,
+    /// The completion cycled through by pressing Tab repeatedly
+    completion: Option<Completion>,
+    /// The id of the message being edited, submitting the input replaces its content
+    editing: Option<u64>,
+    /// The inputs sent to each room, recalled with Up and Down
+    input_history: InputHistory,
 }
 
 impl MessageInputBox {
+    /// The usage and description of each command the role of the user in the active room allows
+    pub fn command_help_lines(&self) -> Vec<(String, String)> {
+        self.slash_commands.help_lines(self.props.role)
+    }
+
+    /// Restores the draft of the active room into the input, which discards it from the state
+    fn restore_draft(&mut self) {
+        let (Some(active_room), Some(draft)) =
+            (self.props.active_room.as_ref(), self.props.draft.take())
+        else {
+            return;
+        };
+
+        self.input_box.set_text(&draft);
+        let _ = self.action_tx.send(Action::SaveDraft {
+            room: active_room.clone(),
+            content: String::new(),
+        });
+    }
+
+    /// Keeps the text of the input as the draft of the active room, unless it is only the input template
+    fn save_draft(&mut self) {
+        let Some(active_room) = self.props.active_room.as_ref() else {
+            return;
+        };
+
+        // the edited message is not a draft, it is still sent as it was
+        if self.editing.is_some()
+            || self.input_box.is_empty()
+            || self.props.input_template.as_deref() == Some(self.input_box.text())
+        {
+            return;
+        }
+
+        let _ = self.action_tx.send(Action::SaveDraft {
+            room: active_room.clone(),
+            content: self.input_box.text().to_string(),
+        });
+    }
+
+    /// Pre-populates the empty input with the template of the active room, if enabled
+    fn apply_input_template(&mut self) {
+        if let Some(input_template) = self.props.input_template.as_ref() {
+            if self.props.use_input_templates && self.input_box.is_empty() {
+                self.input_box.set_text(input_template);
+            }
+        }
+    }
+
+    fn toggle_input_template(&mut self) {
+        let _ = self.action_tx.send(Action::ToggleInputTemplates);
+
+        // reflect the change right away instead of waiting for the state update
+        self.props.use_input_templates = !self.props.use_input_templates;
+        if self.props.use_input_templates {
+            self.apply_input_template();
+        } else if self.props.input_template.as_deref() == Some(self.input_box.text()) {
+            self.input_box.reset();
+        }
+    }
+
+    /// Completes the word before the cursor, or replaces it with the next candidate when Tab is pressed again
+    fn complete(&mut self) {
+        match self.completion.as_mut() {
+            Some(completion) => completion.cycle(),
+            None => {
+                let commands = self.slash_commands.names(self.props.role);
+
+                self.completion = Completion::start(
+                    self.input_box.text(),
+                    self.input_box.cursor_position(),
+                    &CompletionSources {
+                        users: &self.props.room_users,
+                        rooms: &self.props.rooms,
+                        commands: &commands,
+                    },
+                );
+            }
+        }
+
+        if let Some(completion) = self.completion.as_mut() {
+            let (start, end, candidate) = completion.current();
+
+            self.input_box.replace_range(start, end, &candidate);
+            completion.inserted(self.input_box.cursor_position());
+        }
+    }
+
+    /// Recalls the last message of the user into the empty input, to edit it
+    ///
+    /// Returns whether the user has sent a message to edit.
+    fn edit_last_message(&mut self) -> bool {
+        let Some((id, content)) = self.props.last_own_message.clone() else {
+            return false;
+        };
+
+        self.input_box.set_text(&content);
+        self.editing = Some(id);
+
+        true
+    }
+
+    /// Recalls the input sent before the one in the input, keeping what was typed to come back to
+    fn recall_previous_input(&mut self) {
+        let Some(active_room) = self.props.active_room.as_ref() else {
+            return;
+        };
+
+        if let Some(input) = self
+            .input_history
+            .previous(active_room, self.input_box.text())
+        {
+            self.input_box.set_text(input);
+            self.editing = None;
+        }
+    }
+
+    /// Recalls the input sent after the one in the input, or what was typed before recalling
+    fn recall_next_input(&mut self) {
+        let Some(active_room) = self.props.active_room.as_ref() else {
+            return;
+        };
+
+        if let Some(input) = self.input_history.next(active_room) {
+            self.input_box.set_text(input);
+            self.editing = None;
+        }
+    }
+
     fn submit_message(&mut self) {
         if self.input_box.is_empty() {
             return;
         }
 
+        let action = match self
+            .slash_commands
+            .parse(self.input_box.text(), self.props.role)
+        {
+            Submission::Message(content) => match self.editing {
+                Some(id) => Action::EditMessage { id, content },
+                None => Action::SendMessage { content },
+            },
+            Submission::Command(action) => action,
+            // keep the text so the user can fix the command
+            Submission::InvalidArgs { usage } => {
+                let _ = self.action_tx.send(Action::ShowToast {
+                    content: format!("Usage: {}", usage),
+                });
+
+                return;
+            }
+            Submission::NotAllowed { name, role } => {
+                let _ = self.action_tx.send(Action::ShowToast {
+                    content: format!(
+                        "Only {} of the room can use /{}",
+                        match role {
+                            RoomRole::Owner => "the owner",
+                            _ => "the owner and the moderators",
+                        },
+                        name
+                    ),
+                });
+
+                return;
+            }
+            Submission::UnknownCommand { name } => {
+                let _ = self.action_tx.send(Action::ShowToast {
+                    content: format!("Unknown command /{}, type /help to list the commands", name),
+                });
+
+                return;
+            }
+        };
+
         // TODO: handle the error scenario
-        let _ = self.action_tx.send(Action::SendMessage {
-            content: String::from(self.input_box.text()),
-        });
+        let _ = self.action_tx.send(action);
 
+        if let Some(active_room) = self.props.active_room.as_ref() {
+            self.input_history.push(active_room, self.input_box.text());
+        }
         self.input_box.reset();
+        self.editing = None;
     }
 }
 
@@ -59,6 +315,10 @@ impl Component for MessageInputBox {
             props: Props::from(state),
             //
             input_box: InputBox::new(state, action_tx),
+            slash_commands: SlashCommandRegistry::default(),
+            completion: None,
+            editing: None,
+            input_history: InputHistory::default(),
         }
     }
 
@@ -81,21 +341,80 @@ impl Component for MessageInputBox {
             return;
         }
 
+        let keymap = &self.props.keymap;
+
+        if keymap.is(&key, KeyAction::ToggleInputTemplate) {
+            self.toggle_input_template();
+            return;
+        }
+
         if self.props.active_room.is_some() {
-            self.input_box.handle_key_event(key);
+            if keymap.is(&key, KeyAction::Complete) {
+                self.complete();
+                return;
+            }
+
+            // a key bound to recalling the inputs as well edits the last message only from the empty input,
+            // and recalls them when the user has not sent any message yet
+            let edits_last_message = keymap.is(&key, KeyAction::EditLastMessage);
+            if edits_last_message && self.input_box.is_empty() && self.edit_last_message() {
+                return;
+            }
+
+            // any other key accepts the completion
+            self.completion = None;
+
+            let keymap = &self.props.keymap;
+            if keymap.is(&key, KeyAction::PreviousInput) {
+                self.recall_previous_input();
+                return;
+            }
+            if keymap.is(&key, KeyAction::NextInput) {
+                self.recall_next_input();
+                return;
+            }
+            if edits_last_message {
+                return;
+            }
+
+            // changing the recalled input makes it the one being typed
+            if let Some(active_room) = self.props.active_room.as_ref() {
+                self.input_history.stop_browsing(active_room);
+            }
 
-            if key.code == KeyCode::Enter {
+            if self.props.keymap.is(&key, KeyAction::Send) {
                 self.submit_message();
+                return;
+            }
+
+            self.input_box.handle_key_event(key);
+
+            // clearing the input gives up editing the message
+            if self.input_box.is_empty() {
+                self.editing = None;
             }
         }
     }
 }
 
 impl SectionActivation for MessageInputBox {
-    fn activate(&mut self) {}
+    fn activate(&mut self) {
+        self.restore_draft();
+        self.apply_input_template();
+    }
 
     fn deactivate(&mut self) {
+        self.save_draft();
+        if let Some(active_room) = self.props.active_room.as_ref() {
+            self.input_history.stop_browsing(active_room);
+        }
         self.input_box.reset();
+        self.completion = None;
+        self.editing = None;
+
+        if self.props.replying_to.is_some() {
+            let _ = self.action_tx.send(Action::CancelReply);
+        }
     }
 }
 
@@ -107,13 +426,53 @@ pub struct RenderProps {
 
 impl ComponentRender<RenderProps> for MessageInputBox {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let title = match (
+            self.editing.is_some(),
+            self.props.replying_to.as_ref(),
+            self.props.input_template.is_some(),
+            self.props.use_input_templates,
+        ) {
+            (true, _, _, _) => "Message Input (editing)".into(),
+            (false, Some(Some(user_id)), _, _) => {
+                format!("Message Input (replying to @{})", user_id)
+            }
+            (false, Some(None), _, _) => "Message Input (replying)".into(),
+            (false, None, true, true) => "Message Input (template on)".into(),
+            (false, None, true, false) => "Message Input (template off)".into(),
+            (false, None, false, _) => "Message Input".into(),
+        };
+        // the rate limit warning takes over the title until it expires
+        let (title, mut border_color) = match self.props.rate_limit_warning.as_ref() {
+            Some(rate_limit_warning) => (
+                format!("Message Input ({})", rate_limit_warning),
+                self.props.theme.warning,
+            ),
+            None => (title, props.border_color),
+        };
+        let counter = self.props.max_message_chars.and_then(|max_message_chars| {
+            let chars = self.input_box.text().chars().count();
+            if chars == 0 {
+                return None;
+            }
+
+            let counter = Span::from(format!(" {}/{} ", chars, max_message_chars));
+            if chars > max_message_chars {
+                border_color = self.props.theme.error;
+                Some(counter.fg(self.props.theme.error).bold())
+            } else {
+                Some(counter.dim())
+            }
+        });
+
         self.input_box.render(
             frame,
             input_box::RenderProps {
-                title: "Message Input".into(),
+                title,
                 area: props.area,
-                border_color: props.border_color,
+                border_color,
+                text_color: self.props.theme.input,
                 show_cursor: props.show_cursor,
+                counter,
             },
         )
     }
@@ -130,19 +489,330 @@ impl HasUsageInfo for MessageInputBox {
                 }],
             }
         } else {
+            let mut lines = vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to cancel".into(),
+                },
+                UsageInfoLine {
+                    keys: self.props.keymap.labels(KeyAction::Send),
+                    description: KeyAction::Send.describe().into(),
+                },
+                UsageInfoLine {
+                    keys: [
+                        self.props.keymap.labels(KeyAction::PreviousInput),
+                        self.props.keymap.labels(KeyAction::NextInput),
+                    ]
+                    .concat(),
+                    description: "to recall the inputs you sent to the room".into(),
+                },
+                UsageInfoLine {
+                    keys: self.props.keymap.labels(KeyAction::EditLastMessage),
+                    description: KeyAction::EditLastMessage.describe().into(),
+                },
+                UsageInfoLine {
+                    keys: self.props.keymap.labels(KeyAction::Complete),
+                    description: KeyAction::Complete.describe().into(),
+                },
+            ];
+            lines.extend(
+                self.slash_commands
+                    .help_lines(self.props.role)
+                    .into_iter()
+                    .map(|(usage, description)| UsageInfoLine {
+                        keys: vec![usage],
+                        description,
+                    }),
+            );
+            lines.push(UsageInfoLine {
+                keys: vec!["Ctrl+t".into()],
+                description: "to toggle the room template".into(),
+            });
+
             UsageInfo {
                 description: Some("Type your message to send a message to the active room".into()),
-                lines: vec![
-                    UsageInfoLine {
-                        keys: vec!["Esc".into()],
-                        description: "to cancel".into(),
-                    },
-                    UsageInfoLine {
-                        keys: vec!["Enter".into()],
-                        description: "to send your message".into(),
-                    },
-                ],
+                lines,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+    use crate::keymap::KeyBindingsFile;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn general_room() -> State {
+        State::test_with_rooms(&[("general", "General talk")])
+            .with_user_id("me")
+            .with_joined_room("general", &["alice", "alex"])
+            .with_active_room("general")
+    }
+
+    #[test]
+    fn test_sends_the_message_to_the_active_room() {
+        let mut harness = TestHarness::<MessageInputBox>::new(&general_room());
+
+        harness.type_text("hello").press(KeyCode::Enter);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SendMessage {
+                content: "hello".into()
+            }]
+        );
+        assert!(harness.component().input_box.is_empty());
+    }
+
+    #[test]
+    fn test_does_not_send_without_active_room() {
+        let state = State::test_with_rooms(&[("general", "General talk")]);
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness.type_text("hello").press(KeyCode::Enter);
+
+        assert!(harness.drain_actions().is_empty());
+        assert!(harness.component().input_box.is_empty());
+    }
+
+    #[test]
+    fn test_dispatches_the_slash_commands() {
+        let mut harness = TestHarness::<MessageInputBox>::new(&general_room());
+
+        // the rejected command is kept in the input for the user to fix it
+        harness.type_text("/whois bob").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::ShowToast {
+                content: "Unknown command /whois, type /help to list the commands".into()
+            }]
+        );
+        assert_eq!(harness.component().input_box.text(), "/whois bob");
+
+        harness.component_mut().input_box.reset();
+        harness.type_text("/leave").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::LeaveRoom { room: None }]
+        );
+        assert!(harness.component().input_box.is_empty());
+    }
+
+    #[test]
+    fn test_edits_the_last_message() {
+        let state = general_room()
+            .with_message("general", "me", "helo")
+            .with_message("general", "alice", "hi");
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness.press(KeyCode::Up);
+        assert_eq!(harness.component().input_box.text(), "helo");
+        assert_eq!(harness.component().editing, Some(0));
+
+        harness
+            .press(KeyCode::Backspace)
+            .type_text("lo")
+            .press(KeyCode::Enter);
+        assert_eq!(harness.component().editing, None);
+
+        // the commands are not sent as the edit of the message
+        harness.type_text("/delete").press(KeyCode::Enter);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::EditMessage {
+                    id: 0,
+                    content: "hello".into()
+                },
+                Action::DeleteLastMessage,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clearing_the_input_gives_up_editing() {
+        let state = general_room().with_message("general", "me", "hi");
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness
+            .press(KeyCode::Up)
+            .press(KeyCode::Backspace)
+            .press(KeyCode::Backspace);
+        assert_eq!(harness.component().editing, None);
+
+        harness.type_text("hey").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SendMessage {
+                content: "hey".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recalls_the_inputs_from_the_empty_input() {
+        let state = general_room().with_message("general", "me", "helo");
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness
+            .press(KeyCode::Up)
+            .press(KeyCode::Backspace)
+            .type_text("lo")
+            .press(KeyCode::Enter);
+        // Up edits the last message from the empty input only, Ctrl+Up recalls the saved edit
+        harness.press_with_modifiers(KeyCode::Up, KeyModifiers::CONTROL);
+        assert_eq!(harness.component().input_box.text(), "hello");
+        assert_eq!(harness.component().editing, None);
+
+        // Up goes on through the inputs once one is recalled
+        harness
+            .press(KeyCode::Down)
+            .type_text("again")
+            .press(KeyCode::Up);
+        assert_eq!(harness.component().input_box.text(), "hello");
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::EditMessage {
+                id: 0,
+                content: "hello".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_edits_the_last_message_with_the_rebound_keys() {
+        let state = State {
+            keymap: Keymap::try_from(&KeyBindingsFile {
+                edit_last_message: vec!["Ctrl+e".into()],
+                previous_input: vec!["Up".into()],
+                ..Default::default()
+            })
+            .unwrap(),
+            ..general_room().with_message("general", "me", "helo")
+        };
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness
+            .press_with_modifiers(KeyCode::Char('e'), KeyModifiers::CONTROL)
+            .press(KeyCode::Backspace)
+            .type_text("lo")
+            .press(KeyCode::Enter);
+        // Up alone only recalls the inputs, even from the empty input
+        harness.press(KeyCode::Up);
+        assert_eq!(harness.component().input_box.text(), "hello");
+        assert_eq!(harness.component().editing, None);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::EditMessage {
+                id: 0,
+                content: "hello".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recalls_the_sent_inputs() {
+        let mut harness = TestHarness::<MessageInputBox>::new(&general_room());
+
+        harness
+            .type_text("first")
+            .press(KeyCode::Enter)
+            .type_text("second")
+            .press(KeyCode::Enter)
+            .press(KeyCode::Up);
+        // with no message of the user to edit, Up recalls the inputs from the empty input as well
+        assert_eq!(harness.component().input_box.text(), "second");
+
+        harness
+            .press(KeyCode::Down)
+            .type_text("draft")
+            .press(KeyCode::Up)
+            .press(KeyCode::Up);
+        assert_eq!(harness.component().input_box.text(), "first");
+
+        // going past the latest input brings back what was typed
+        harness.press(KeyCode::Down).press(KeyCode::Down);
+        assert_eq!(harness.component().input_box.text(), "draft");
+
+        harness
+            .press(KeyCode::Up)
+            .type_text("!")
+            .press(KeyCode::Enter);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SendMessage {
+                    content: "first".into()
+                },
+                Action::SendMessage {
+                    content: "second".into()
+                },
+                Action::SendMessage {
+                    content: "second!".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_saves_and_restores_the_draft_of_the_room() {
+        let mut state = general_room();
+        let mut harness = TestHarness::<MessageInputBox>::new(&state);
+
+        harness.type_text("half").component_mut().deactivate();
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SaveDraft {
+                room: "general".into(),
+                content: "half".into()
+            }]
+        );
+        assert!(harness.component().input_box.is_empty());
+
+        // the restored draft is discarded from the state, to be saved again if left unsent
+        state.save_draft("general", "half".into());
+        harness.apply_state(&state).component_mut().activate();
+        assert_eq!(harness.component().input_box.text(), "half");
+
+        harness.type_text(" done").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SaveDraft {
+                    room: "general".into(),
+                    content: String::new()
+                },
+                Action::SendMessage {
+                    content: "half done".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cycles_through_the_completions() {
+        let mut harness = TestHarness::<MessageInputBox>::new(&general_room());
+
+        harness.type_text("hi @al").press(KeyCode::Tab);
+        assert_eq!(harness.component().input_box.text(), "hi @alex ");
+
+        harness.press(KeyCode::Tab);
+        assert_eq!(harness.component().input_box.text(), "hi @alice ");
+
+        // any other key accepts the completion
+        harness.type_text("!").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SendMessage {
+                content: "hi @alice !".into()
+            }]
+        );
+    }
+}
@@ -0,0 +1,1069 @@
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local, NaiveDate, TimeZone,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    layout::Alignment,
+    prelude::{Backend, Margin, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{
+        block::{Position, Title},
+        Block, Borders, List, ListItem, Scrollbar, ScrollbarOrientation, ScrollbarState,
+    },
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
+use crate::graphics::{ImagePlacement, PreviewImage};
+use crate::state_store::{
+    action::Action,
+    highlights,
+    image_previews::{image_source_of, ImageSource},
+    user_label, ImagePreview, MessageBoxItem, MessageSearch, RoomData, State, TransferProgress,
+};
+use crate::theme::Theme;
+use crate::ui_management::components::{markdown, wrap::wrap, Component, ComponentRender};
+use crate::ui_management::pages::chat_page::section::SectionActivation;
+
+use super::super::chat_page::{calculate_list_offset, NO_ROOM_SELECTED_MESSAGE};
+
+/// The emojis the selected message can be reacted to with, by pressing their number
+const REACTION_EMOJIS: [&str; 5] = ["👍", "❤️", "😂", "🎉", "👀"];
+/// How many characters of the replied message are quoted above a reply
+const REPLY_QUOTE_LENGTH: usize = 50;
+/// How far the previews of the images are indented under their message
+const PREVIEW_INDENT: u16 = 4;
+
+struct Props {
+    /// The data of the currently active room
+    active_room_data: Option<RoomData>,
+    /// The toast to show at the bottom of the list
+    toast: Option<String>,
+    /// The id of the user, to highlight the messages mentioning them
+    user_id: String,
+    highlight_words: Vec<String>,
+    /// The users whose messages are hidden
+    ignored_users: Vec<String>,
+    /// The format of the time the messages are prefixed with
+    time_format: String,
+    theme: Theme,
+    /// The search through the messages of the active room
+    search: Option<MessageSearch>,
+    /// The files being uploaded and downloaded, shown at the bottom of the list
+    transfers: Vec<TransferProgress>,
+    /// The previews of the images referenced by the messages
+    image_previews: HashMap<String, ImagePreview>,
+    /// The names the users who changed it are shown with, in place of their ids
+    display_names: HashMap<String, String>,
+    /// The colors the names of the users who picked one are shown with
+    user_colors: HashMap<String, Color>,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        Self {
+            active_room_data: state
+                .active_room
+                .as_ref()
+                .and_then(|active_room| state.room_data_map.get(active_room))
+                .cloned(),
+            toast: state.toast.clone(),
+            user_id: state.user_id.clone(),
+            highlight_words: state.highlight_words.clone(),
+            ignored_users: state
+                .ignored_users
+                .iter()
+                .filter(|user_id| **user_id != state.user_id)
+                .cloned()
+                .collect(),
+            time_format: state.time_format.clone(),
+            theme: state.theme,
+            search: state
+                .search
+                .clone()
+                .filter(|search| state.active_room.as_ref() == Some(&search.room)),
+            transfers: state.transfers.clone(),
+            image_previews: state.image_previews.clone(),
+            display_names: state.display_names.clone(),
+            user_colors: state.user_colors.clone(),
+        }
+    }
+}
+
+/// MessageList renders the messages of the active room, separating the messages sent on different days
+///
+/// When activated, a message can be selected to copy or save it, or one of its code and quote regions,
+/// to react or reply to it, or to delete it if the user has sent it.
+pub struct MessageList {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped MessageList Props
+    props: Props,
+    // Internal Component State
+    /// Index of the selected message in the messages of the active room
+    selected_message: Option<usize>,
+    /// Index of the selected region in the selected message
+    selected_region: Option<usize>,
+    /// Where the previews of the images were left room for by the last render, drawn over the list afterwards
+    image_placements: RefCell<Vec<ImagePlacement>>,
+}
+
+/// Converts a timestamp in milliseconds since the unix epoch to a date in the local timezone
+pub fn timestamp_to_local_date(timestamp: u64) -> Option<NaiveDate> {
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|date_time| date_time.date_naive())
+}
+
+/// Formats the time of a timestamp in the local timezone, returns None if the format is empty or invalid
+fn format_local_time(timestamp: u64, format: &str) -> Option<String> {
+    // formatting with an invalid specifier panics, the prefix is left out instead
+    if format.is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|date_time| date_time.format(format).to_string())
+}
+
+/// Formats a number of bytes for humans, as in `1.5 MiB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Describes the transfers in progress, as in `↑ notes.txt 42%`
+fn transfers_label(transfers: &[TransferProgress]) -> String {
+    transfers
+        .iter()
+        .map(|transfer| {
+            let direction = if transfer.is_upload { "↑" } else { "↓" };
+            let percent = (transfer.transferred * 100)
+                .checked_div(transfer.size)
+                .unwrap_or(0);
+
+            format!("{} {} {}%", direction, transfer.name, percent)
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Divides the messages received since the room was last read from the read ones
+fn unread_divider<'a>(theme: &Theme) -> ListItem<'a> {
+    ListItem::new(Line::from(
+        Span::from("─── new messages ───").fg(theme.accent),
+    ))
+}
+
+fn date_separator<'a>(date: &NaiveDate) -> ListItem<'a> {
+    ListItem::new(Line::from(
+        Span::from(format!("─── {} ───", date.format("%A, %b %-d %Y"))).dim(),
+    ))
+}
+
+/// Quotes the beginning of the replied message above a reply
+fn reply_quote<'a>(
+    room_data: &RoomData,
+    reply_to: u64,
+    display_names: &HashMap<String, String>,
+) -> ListItem<'a> {
+    let replied = room_data.messages.iter().find_map(|mbi| match mbi {
+        MessageBoxItem::Message {
+            id,
+            user_id,
+            content,
+            ..
+        } if *id == reply_to => Some((user_id, content)),
+        _ => None,
+    });
+
+    let quote = match replied {
+        Some((user_id, content)) => {
+            let mut quote = content
+                .replace('\n', " ")
+                .chars()
+                .take(REPLY_QUOTE_LENGTH)
+                .collect::<String>();
+            if content.chars().count() > REPLY_QUOTE_LENGTH {
+                quote.push('…');
+            }
+
+            format!("  ┌ {}: {}", user_label(display_names, user_id), quote)
+        }
+        None => String::from("  ┌ a message which is not loaded"),
+    };
+
+    ListItem::new(Line::from(Span::from(quote).dim()))
+}
+
+/// Says what is shown in place of the preview of an image, which is not ready or does not fit
+fn image_placeholder<'a>(source: &ImageSource, status: Option<&str>) -> ListItem<'a> {
+    let mut spans = vec![Span::from(format!("    🖼 {}", source.label()))];
+    if let Some(status) = status {
+        spans.push(Span::from(format!(" ({})", status)).dim());
+    }
+
+    ListItem::new(Line::from(spans))
+}
+
+/// The list items built for a room, alongside the positions of interest in the list
+struct BuiltItems<'a> {
+    items: Vec<ListItem<'a>>,
+    /// Index of the item the list should be positioned at, if the room has a jump target
+    jump_idx: Option<usize>,
+    /// Index of the item of the selected message
+    selected_idx: Option<usize>,
+    /// Index of the item of the current hit of the search
+    search_idx: Option<usize>,
+    /// The previews of the images, by the index of the first of the blank items left for them
+    previews: Vec<(usize, String, Arc<PreviewImage>)>,
+}
+
+impl MessageList {
+    fn messages(&self) -> Vec<&MessageBoxItem> {
+        self.props
+            .active_room_data
+            .as_ref()
+            .map(|room_data| room_data.messages.asc_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Is the item a message or a file shared by an ignored user, which is not shown
+    fn is_hidden(&self, mbi: &MessageBoxItem) -> bool {
+        let user_id = match mbi {
+            MessageBoxItem::Message { user_id, .. } | MessageBoxItem::File { user_id, .. } => {
+                user_id
+            }
+            MessageBoxItem::Notification(_) | MessageBoxItem::Error(_) => return false,
+        };
+
+        self.props.ignored_users.contains(user_id)
+    }
+
+    /// Moves the selection to the closest message in the given direction, skipping notifications
+    /// and the hidden messages
+    fn move_selection(&mut self, forward: bool) {
+        let messages = self.messages();
+        let is_message = |idx: &usize| {
+            matches!(messages[*idx], MessageBoxItem::Message { .. })
+                && !self.is_hidden(messages[*idx])
+        };
+
+        let next = match (self.selected_message, forward) {
+            (None, _) => (0..messages.len()).rev().find(is_message),
+            (Some(selected), true) => (selected + 1..messages.len()).find(is_message),
+            (Some(selected), false) => (0..selected.min(messages.len())).rev().find(is_message),
+        };
+
+        if next.is_some() {
+            self.selected_message = next;
+            self.selected_region = None;
+        }
+    }
+
+    fn selected_content(&self) -> Option<String> {
+        let messages = self.messages();
+        let MessageBoxItem::Message { content, .. } = messages.get(self.selected_message?)? else {
+            return None;
+        };
+
+        match self.selected_region {
+            Some(region_idx) => markdown::parse(content)
+                .regions()
+                .nth(region_idx)
+                .map(|region| region.text.clone()),
+            None => Some(content.clone()),
+        }
+    }
+
+    /// Reacts to the selected message with the emoji of the given number, starting from 1
+    fn react(&self, number: usize) {
+        let messages = self.messages();
+        let Some(MessageBoxItem::Message { id, .. }) =
+            self.selected_message.and_then(|idx| messages.get(idx))
+        else {
+            return;
+        };
+
+        if let Some(emoji) = number
+            .checked_sub(1)
+            .and_then(|idx| REACTION_EMOJIS.get(idx))
+        {
+            let _ = self.action_tx.send(Action::ReactToMessage {
+                id: *id,
+                emoji: String::from(*emoji),
+            });
+        }
+    }
+
+    /// The id of the selected message, if it can be replied to
+    pub fn selected_reply_target(&self) -> Option<u64> {
+        // direct messages are not numbered, hence they can not be replied to
+        let is_direct_message = self
+            .props
+            .active_room_data
+            .as_ref()
+            .map(|room_data| room_data.is_direct_message)
+            .unwrap_or(false);
+        if is_direct_message {
+            return None;
+        }
+
+        match self.messages().get(self.selected_message?)? {
+            MessageBoxItem::Message { id, .. } => Some(*id),
+            MessageBoxItem::Notification(_)
+            | MessageBoxItem::Error(_)
+            | MessageBoxItem::File { .. } => None,
+        }
+    }
+
+    /// Opens the first link of the selected message
+    fn open_selected_link(&self) {
+        let messages = self.messages();
+        let Some(MessageBoxItem::Message { content, .. }) =
+            self.selected_message.and_then(|idx| messages.get(idx))
+        else {
+            return;
+        };
+
+        let action = match markdown::parse(content).links().next() {
+            Some(url) => Action::OpenLink {
+                url: String::from(url),
+            },
+            None => Action::ShowToast {
+                content: String::from("The message has no link"),
+            },
+        };
+        let _ = self.action_tx.send(action);
+    }
+
+    /// Replies to the selected message with the next message sent to the room
+    fn reply_to_selected(&self) {
+        if let Some(id) = self.selected_reply_target() {
+            let _ = self.action_tx.send(Action::ReplyToMessage { id });
+        }
+    }
+
+    /// Moves the selection from a reply to the message it replies to
+    fn select_replied_message(&mut self) {
+        let messages = self.messages();
+        let Some(MessageBoxItem::Message {
+            reply_to: Some(reply_to),
+            ..
+        }) = self.selected_message.and_then(|idx| messages.get(idx))
+        else {
+            return;
+        };
+
+        let replied_idx = messages
+            .iter()
+            .position(|mbi| matches!(mbi, MessageBoxItem::Message { id, .. } if id == reply_to));
+
+        match replied_idx {
+            Some(idx) => {
+                self.selected_message = Some(idx);
+                self.selected_region = None;
+            }
+            None => {
+                let _ = self.action_tx.send(Action::ShowToast {
+                    content: String::from("The replied message is not loaded anymore"),
+                });
+            }
+        }
+    }
+
+    /// Deletes the selected message, if the user has sent it
+    fn delete_selected(&self) {
+        let messages = self.messages();
+        let Some(MessageBoxItem::Message { id, user_id, .. }) =
+            self.selected_message.and_then(|idx| messages.get(idx))
+        else {
+            return;
+        };
+
+        let action = if *user_id == self.props.user_id {
+            Action::DeleteMessage { id: *id }
+        } else {
+            Action::ShowToast {
+                content: String::from("You can only delete your own messages"),
+            }
+        };
+        let _ = self.action_tx.send(action);
+    }
+
+    /// Cycles through the regions of the selected message, going back to the whole message after the last one
+    /// The style of the message at the given position if it is a hit of the search, the current hit stands out
+    fn search_hit_style(&self, message_idx: usize) -> Option<Style> {
+        let search = self.props.search.as_ref()?;
+
+        if search.current_hit() == Some(message_idx) {
+            Some(
+                Style::default()
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg),
+            )
+        } else if search.hits.binary_search(&message_idx).is_ok() {
+            Some(Style::default().add_modifier(Modifier::UNDERLINED))
+        } else {
+            None
+        }
+    }
+
+    fn cycle_region(&mut self) {
+        let messages = self.messages();
+        let Some(MessageBoxItem::Message { content, .. }) =
+            self.selected_message.and_then(|idx| messages.get(idx))
+        else {
+            return;
+        };
+
+        let region_count = markdown::parse(content).region_count();
+
+        self.selected_region = match self.selected_region {
+            None if region_count > 0 => Some(0),
+            Some(region_idx) if region_idx + 1 < region_count => Some(region_idx + 1),
+            _ => None,
+        };
+    }
+
+    /// Leaves blank items for the preview of the image the message references, or a placeholder line if it can not be shown
+    fn push_image_preview<'a>(
+        &self,
+        mbi: &MessageBoxItem,
+        width: usize,
+        items: &mut Vec<ListItem<'a>>,
+        previews: &mut Vec<(usize, String, Arc<PreviewImage>)>,
+    ) {
+        let Some(source) = image_source_of(mbi) else {
+            return;
+        };
+        let key = source.key();
+
+        let item = match self.props.image_previews.get(&key) {
+            Some(ImagePreview::Ready(image))
+                if (image.columns + PREVIEW_INDENT) as usize <= width =>
+            {
+                previews.push((items.len(), key, image.clone()));
+                items.extend((0..image.rows).map(|_| ListItem::new(Line::default())));
+                return;
+            }
+            Some(ImagePreview::Ready(_)) => {
+                image_placeholder(&source, Some("too wide to preview here"))
+            }
+            Some(ImagePreview::Loading) => image_placeholder(&source, Some("loading preview…")),
+            Some(ImagePreview::Failed(reason)) => {
+                image_placeholder(&source, Some(&format!("no preview: {}", reason)))
+            }
+            // the terminal can not draw images, or the linked images are not fetched
+            None => image_placeholder(&source, None),
+        };
+
+        items.push(item);
+    }
+
+    /// Builds the items of the room, wrapping the messages to the given width
+    ///
+    /// Each item of the list is a single line, so the positions and the offsets count lines.
+    /// The name of the author leading their messages, in the color they picked, or else in the one
+    /// the theme derives from their id so each author keeps theirs
+    fn author_spans<'a>(&self, user_id: &str) -> [Span<'a>; 2] {
+        let color = self
+            .props
+            .user_colors
+            .get(user_id)
+            .copied()
+            .unwrap_or_else(|| self.props.theme.name_color(user_id));
+
+        [
+            Span::from(user_label(&self.props.display_names, user_id)).fg(color),
+            Span::raw(": "),
+        ]
+    }
+
+    fn build_items<'a>(&self, room_data: &RoomData, width: usize) -> BuiltItems<'a> {
+        let mut items = Vec::with_capacity(room_data.messages.len());
+        let mut previews = vec![];
+        let mut last_date: Option<NaiveDate> = None;
+        let mut jump_idx: Option<usize> = None;
+        let mut selected_idx: Option<usize> = None;
+        let mut search_idx: Option<usize> = None;
+        let mut is_unread_divided = false;
+
+        for (message_idx, mbi) in room_data.messages.asc_iter().enumerate() {
+            if self.is_hidden(mbi) {
+                continue;
+            }
+
+            let hit_style = self.search_hit_style(message_idx);
+            if self
+                .props
+                .search
+                .as_ref()
+                .and_then(MessageSearch::current_hit)
+                == Some(message_idx)
+            {
+                search_idx = Some(items.len());
+            }
+
+            match mbi {
+                MessageBoxItem::Message {
+                    id,
+                    user_id,
+                    content,
+                    timestamp,
+                    reply_to,
+                    is_edited,
+                    reactions,
+                } => {
+                    if let Some(timestamp) = timestamp {
+                        let item_idx = items.len();
+
+                        if let Some(date) = timestamp_to_local_date(*timestamp) {
+                            if last_date != Some(date) {
+                                items.push(date_separator(&date));
+                                last_date = Some(date);
+                            }
+                        }
+
+                        let is_jump_target_reached = room_data
+                            .jump_target
+                            .map(|jump_target| *timestamp >= jump_target)
+                            .unwrap_or(false);
+                        if jump_idx.is_none() && is_jump_target_reached {
+                            jump_idx = Some(item_idx);
+                        }
+                    }
+
+                    if !is_unread_divided
+                        && room_data.unread_marker.is_some_and(|marker| *id > marker)
+                    {
+                        items.push(unread_divider(&self.props.theme));
+                        is_unread_divided = true;
+                    }
+
+                    if let Some(reply_to) = reply_to {
+                        items.push(reply_quote(room_data, *reply_to, &self.props.display_names));
+                    }
+
+                    let is_selected = self.selected_message == Some(message_idx);
+                    let selected_region = self.selected_region.filter(|_| is_selected);
+
+                    let mut spans = timestamp
+                        .and_then(|timestamp| format_local_time(timestamp, &self.props.time_format))
+                        .map(|time| vec![Span::from(format!("[{}] ", time)).dim()])
+                        .unwrap_or_default();
+                    spans.extend(self.author_spans(user_id));
+                    // the continuation lines are aligned under the content
+                    let indent = spans.iter().map(Span::width).sum();
+                    spans.extend(
+                        markdown::parse(content).to_spans(selected_region, &self.props.theme),
+                    );
+                    if *is_edited {
+                        spans.push(Span::from(" (edited)").dim());
+                    }
+
+                    let mut style = Style::default();
+                    if *user_id != self.props.user_id
+                        && highlights::is_highlighted(
+                            content,
+                            &self.props.user_id,
+                            &self.props.highlight_words,
+                        )
+                    {
+                        style = style.fg(self.props.theme.accent);
+                    }
+                    if let Some(hit_style) = hit_style {
+                        style = style.patch(hit_style);
+                    }
+                    if is_selected {
+                        selected_idx = Some(items.len());
+                        style = style
+                            .bg(self.props.theme.selected_message_bg)
+                            .add_modifier(Modifier::BOLD);
+                    }
+
+                    items.extend(
+                        wrap(spans, width, indent)
+                            .into_iter()
+                            .map(|line| ListItem::new(line).style(style)),
+                    );
+                    self.push_image_preview(mbi, width, &mut items, &mut previews);
+
+                    // the reactions are a separate item, under the last line of the message
+                    if !reactions.is_empty() {
+                        let reactions = reactions
+                            .iter()
+                            .map(|reaction| format!("{} {}", reaction.emoji, reaction.count))
+                            .collect::<Vec<_>>()
+                            .join("  ");
+
+                        items.push(ListItem::new(Line::from(
+                            Span::from(format!("    {}", reactions)).dim(),
+                        )));
+                    }
+                }
+                MessageBoxItem::Notification(content) => {
+                    items.extend(
+                        wrap(vec![Span::raw(content.clone()).italic()], width, 0)
+                            .into_iter()
+                            .map(|line| ListItem::new(line).style(hit_style.unwrap_or_default())),
+                    );
+                }
+                MessageBoxItem::Error(content) => {
+                    let span = Span::raw(content.clone())
+                        .italic()
+                        .fg(self.props.theme.error);
+                    items.extend(
+                        wrap(vec![span], width, 0)
+                            .into_iter()
+                            .map(|line| ListItem::new(line).style(hit_style.unwrap_or_default())),
+                    );
+                }
+                MessageBoxItem::File {
+                    id,
+                    user_id,
+                    name,
+                    size,
+                    timestamp,
+                } => {
+                    if let Some(date) = timestamp_to_local_date(*timestamp) {
+                        if last_date != Some(date) {
+                            items.push(date_separator(&date));
+                            last_date = Some(date);
+                        }
+                    }
+
+                    let mut spans = format_local_time(*timestamp, &self.props.time_format)
+                        .map(|time| vec![Span::from(format!("[{}] ", time)).dim()])
+                        .unwrap_or_default();
+                    spans.extend(self.author_spans(user_id));
+                    let indent = spans.iter().map(Span::width).sum();
+                    spans.push(
+                        Span::from(format!("📎 {} ({})", name, format_size(*size)))
+                            .fg(self.props.theme.accent),
+                    );
+                    spans.push(Span::from(format!("  /save {} <path>", id)).dim());
+
+                    items.extend(
+                        wrap(spans, width, indent)
+                            .into_iter()
+                            .map(|line| ListItem::new(line).style(hit_style.unwrap_or_default())),
+                    );
+                    self.push_image_preview(mbi, width, &mut items, &mut previews);
+                }
+            }
+        }
+
+        BuiltItems {
+            items,
+            jump_idx,
+            selected_idx,
+            search_idx,
+            previews,
+        }
+    }
+
+    fn title(room_data: &RoomData) -> String {
+        if room_data.is_fetching_history {
+            String::from("Messages (loading history…)")
+        } else if room_data.is_fetching_older {
+            String::from("Messages (loading older messages…)")
+        } else if let Some(date) = room_data.jump_target.and_then(timestamp_to_local_date) {
+            format!("Messages (viewing {}, press End for latest)", date)
+        } else if room_data.scroll_offset > 0 {
+            String::from("Messages (scrolled back, press End for latest)")
+        } else {
+            String::from("Messages")
+        }
+    }
+
+    /// Where the previews of the images are drawn over the list, as of the last render
+    pub fn image_placements(&self) -> Vec<ImagePlacement> {
+        self.image_placements.borrow().clone()
+    }
+}
+
+impl Component for MessageList {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            //
+            selected_message: None,
+            selected_region: None,
+            image_placements: RefCell::new(vec![]),
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Message List"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Up => self.move_selection(false),
+            KeyCode::Down => self.move_selection(true),
+            KeyCode::Tab => self.cycle_region(),
+            KeyCode::Char('d') => self.delete_selected(),
+            KeyCode::Char('r') => self.reply_to_selected(),
+            KeyCode::Char('o') => self.select_replied_message(),
+            KeyCode::Char('l') => self.open_selected_link(),
+            KeyCode::Char(char @ '1'..='9') => self.react(char as usize - '0' as usize),
+            KeyCode::Char('c') => {
+                if let Some(content) = self.selected_content() {
+                    let _ = self.action_tx.send(Action::CopyToClipboard { content });
+                }
+            }
+            KeyCode::Char('s') => {
+                if let Some(content) = self.selected_content() {
+                    let _ = self.action_tx.send(Action::SaveToFile { content });
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl SectionActivation for MessageList {
+    fn activate(&mut self) {
+        self.selected_message = None;
+        self.selected_region = None;
+        self.move_selection(false);
+    }
+
+    fn deactivate(&mut self) {
+        self.selected_message = None;
+        self.selected_region = None;
+    }
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+impl ComponentRender<RenderProps> for MessageList {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let mut image_placements = vec![];
+        let (items, title, scrollbar_state) = if let Some(room_data) =
+            self.props.active_room_data.as_ref()
+        {
+            let BuiltItems {
+                items,
+                jump_idx,
+                selected_idx,
+                search_idx,
+                previews,
+            } = self.build_items(room_data, props.area.width.saturating_sub(2) as usize);
+            let latest_offset = calculate_list_offset(props.area.height, items.len());
+            let offset = match (selected_idx.or(search_idx), jump_idx) {
+                // keep the selected message, or the current hit of the search, in the view
+                (Some(selected_idx), _) => selected_idx.min(latest_offset),
+                // position the jump target at the top, as long as the list can still be filled
+                (None, Some(jump_idx)) => jump_idx
+                    .min(latest_offset)
+                    .saturating_sub(room_data.scroll_offset),
+                (None, None) => latest_offset.saturating_sub(room_data.scroll_offset),
+            };
+            // the scrollbar is only shown when the items do not fit in the view
+            let scrollbar_state = (latest_offset > 0).then(|| {
+                ScrollbarState::default()
+                    .content_length(u16::try_from(latest_offset + 1).unwrap_or(u16::MAX))
+                    .position(u16::try_from(offset).unwrap_or(u16::MAX))
+            });
+
+            // only the previews entirely in view are drawn, the terminals would draw the rest over the borders
+            let visible_rows = props.area.height.saturating_sub(2) as usize;
+            image_placements = previews
+                .into_iter()
+                .filter(|(item_idx, _, image)| {
+                    *item_idx >= offset && item_idx + image.rows as usize <= offset + visible_rows
+                })
+                .map(|(item_idx, key, image)| ImagePlacement {
+                    key,
+                    area: Rect::new(
+                        props.area.x + 1 + PREVIEW_INDENT,
+                        props.area.y + 1 + (item_idx - offset) as u16,
+                        image.columns,
+                        image.rows,
+                    ),
+                    image,
+                })
+                .collect();
+
+            (
+                items.into_iter().skip(offset).collect::<Vec<ListItem>>(),
+                Self::title(room_data),
+                scrollbar_state,
+            )
+        } else {
+            (
+                vec![ListItem::new(Line::from(NO_ROOM_SELECTED_MESSAGE))],
+                String::from("Messages"),
+                None,
+            )
+        };
+
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::new().fg(props.border_color))
+            .title(title);
+        if let Some(toast) = self.props.toast.as_ref() {
+            block = block.title(
+                Title::from(Span::from(format!(" {} ", toast)).fg(self.props.theme.warning))
+                    .position(Position::Bottom),
+            );
+        }
+        if !self.props.transfers.is_empty() {
+            block = block.title(
+                Title::from(
+                    Span::from(format!(" {} ", transfers_label(&self.props.transfers)))
+                        .fg(self.props.theme.accent),
+                )
+                .position(Position::Bottom)
+                .alignment(Alignment::Right),
+            );
+        }
+
+        let messages = List::new(items).block(block);
+        frame.render_widget(messages, props.area);
+        self.image_placements.replace(image_placements);
+
+        if let Some(mut scrollbar_state) = scrollbar_state {
+            frame.render_stateful_widget(
+                Scrollbar::default()
+                    .orientation(ScrollbarOrientation::VerticalRight)
+                    .begin_symbol(None)
+                    .end_symbol(None),
+                props.area.inner(&Margin {
+                    vertical: 1,
+                    horizontal: 0,
+                }),
+                &mut scrollbar_state,
+            );
+        }
+    }
+}
+
+impl HasUsageInfo for MessageList {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Select a message, or a code or quote region in it".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to cancel".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to select a message".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Tab".into()],
+                    description: "to cycle regions".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["c".into(), "s".into()],
+                    description: "to copy or save".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["r".into()],
+                    description: "to reply".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["o".into()],
+                    description: "to select the replied message".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["l".into()],
+                    description: "to open the link".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["d".into()],
+                    description: "to delete your message".into(),
+                },
+                UsageInfoLine {
+                    keys: vec![format!("1-{}", REACTION_EMOJIS.len())],
+                    description: format!("to react with {}", REACTION_EMOJIS.join(" ")),
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn general_room() -> State {
+        State::test_with_rooms(&[("general", "General talk")])
+            .with_user_id("me")
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general")
+    }
+
+    #[test]
+    fn test_activating_selects_the_latest_message() {
+        let state = general_room()
+            .with_message("general", "alice", "first")
+            .with_message("general", "alice", "second");
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        assert_eq!(harness.component().selected_message, Some(1));
+
+        harness.press(KeyCode::Up).press(KeyCode::Up);
+        assert_eq!(harness.component().selected_message, Some(0));
+
+        harness.component_mut().deactivate();
+        assert_eq!(harness.component().selected_message, None);
+    }
+
+    #[test]
+    fn test_reacts_to_the_selected_message() {
+        let state = general_room()
+            .with_message("general", "alice", "first")
+            .with_message("general", "alice", "second");
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        harness
+            .press(KeyCode::Char('1'))
+            .press(KeyCode::Up)
+            .press(KeyCode::Char('4'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ReactToMessage {
+                    id: 1,
+                    emoji: "👍".into()
+                },
+                Action::ReactToMessage {
+                    id: 0,
+                    emoji: "🎉".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_opens_the_link_of_the_selected_message() {
+        let state = general_room()
+            .with_message("general", "alice", "docs at <https://example.com/docs>.")
+            .with_message("general", "alice", "no link here");
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        harness
+            .press(KeyCode::Char('l'))
+            .press(KeyCode::Up)
+            .press(KeyCode::Char('l'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ShowToast {
+                    content: "The message has no link".into()
+                },
+                Action::OpenLink {
+                    url: "https://example.com/docs".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deletes_only_own_selected_message() {
+        let state = general_room()
+            .with_message("general", "me", "mine")
+            .with_message("general", "alice", "theirs");
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        harness
+            .press(KeyCode::Char('d'))
+            .press(KeyCode::Up)
+            .press(KeyCode::Char('d'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ShowToast {
+                    content: "You can only delete your own messages".into()
+                },
+                Action::DeleteMessage { id: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replies_to_the_selected_message() {
+        let state = general_room()
+            .with_message("general", "alice", "lunch?")
+            .with_message("general", "alice", "anyone?");
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        harness.press(KeyCode::Up);
+        assert_eq!(harness.component().selected_reply_target(), Some(0));
+
+        harness.press(KeyCode::Char('r'));
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::ReplyToMessage { id: 0 }]
+        );
+    }
+
+    #[test]
+    fn test_direct_messages_can_not_be_replied_to() {
+        let mut state = general_room().with_message("general", "alice", "hi");
+        state
+            .room_data_map
+            .get_mut("general")
+            .unwrap()
+            .is_direct_message = true;
+        let mut harness = TestHarness::<MessageList>::new(&state);
+
+        harness.component_mut().activate();
+        harness.press(KeyCode::Char('r'));
+
+        assert_eq!(harness.component().selected_reply_target(), None);
+        assert!(harness.drain_actions().is_empty());
+    }
+}
@@ -1,2 +1,9 @@
+pub mod command_palette;
+pub mod date_picker;
+pub mod direct_message_list;
 pub mod message_input_box;
+pub mod message_list;
 pub mod room_list;
+pub mod room_switcher;
+pub mod search_results;
+pub mod user_profile;
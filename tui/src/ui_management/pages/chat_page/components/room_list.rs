@@ -1,57 +1,142 @@
+use std::{cell::Cell, collections::HashSet};
+
+use chrono::{Local, TimeZone};
+use comms::event::SpaceRole;
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
 use ratatui::{
-    prelude::{Backend, Rect},
-    style::{Color, Modifier, Style},
+    prelude::{Backend, Constraint, Direction, Rect},
+    style::{Color, Modifier, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 use tokio::sync::mpsc::UnboundedSender;
 
 use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
 use crate::{
-    state_store::{action::Action, State},
+    config::RoomSort,
+    state_store::{action::Action, RoomDirectory, State},
+    theme::Theme,
     ui_management::pages::chat_page::section::SectionActivation,
 };
 
 use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::layout;
 
 pub struct RoomState {
     pub name: String,
-    pub description: String,
     pub has_joined: bool,
-    pub has_unread: bool,
+    pub is_join_pending: bool,
+    /// Has text left in the message input when moving away from it
+    pub has_draft: bool,
+    pub unread_count: usize,
+    pub unread_mention_count: usize,
+    pub is_favorite: bool,
+}
+
+pub struct SpaceState {
+    pub name: String,
+    /// The rooms of the space, in the order defined by the server
+    pub rooms: Vec<String>,
+    pub has_joined: bool,
+    pub is_admin: bool,
 }
 
 struct Props {
     /// List of rooms and current state of those rooms
     rooms: Vec<RoomState>,
+    /// List of spaces grouping the rooms
+    spaces: Vec<SpaceState>,
     /// Current active room
     active_room: Option<String>,
+    /// Can the public rooms be browsed, the rooms which are not joined are left out of the list when they can
+    can_browse_rooms: bool,
+    /// The public rooms listed by the browse tab
+    room_directory: Option<RoomDirectory>,
+    theme: Theme,
+}
+
+/// The tabs of the room list, the joined rooms are listed unless the public rooms are browsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomListTab {
+    Joined,
+    Browse,
 }
 
 impl From<&State> for Props {
     fn from(state: &State) -> Self {
+        // the conversations of direct messages are listed separately,
+        // and the rooms which are not joined are found by browsing when the server lists them
         let mut rooms = state
             .room_data_map
             .iter()
+            .filter(|(_, room_data)| !room_data.is_direct_message)
+            .filter(|(_, room_data)| {
+                !state.can_browse_rooms || room_data.has_joined || room_data.is_join_pending
+            })
             .map(|(name, room_data)| RoomState {
                 name: name.clone(),
-                description: room_data.description.clone(),
                 has_joined: room_data.has_joined,
-                has_unread: room_data.has_unread,
+                is_join_pending: room_data.is_join_pending,
+                has_draft: room_data.draft.is_some(),
+                unread_count: room_data.unread_count,
+                unread_mention_count: room_data.unread_mention_count,
+                is_favorite: state.is_favorite_room(name),
             })
             .collect::<Vec<RoomState>>();
 
-        rooms.sort_by(|room_a, room_b| room_a.name.cmp(&room_b.name));
+        match state.room_sort {
+            // the rooms with unread mentions rank above those with unread messages alone
+            RoomSort::Activity => rooms.sort_by_key(|room| {
+                (
+                    !room.is_favorite,
+                    room.unread_mention_count == 0,
+                    room.unread_count == 0,
+                    room.name.clone(),
+                )
+            }),
+            RoomSort::Alphabetical => rooms.sort_by(|room_a, room_b| room_a.name.cmp(&room_b.name)),
+        }
+
+        let mut spaces = state
+            .space_data_map
+            .iter()
+            .map(|(name, space_data)| SpaceState {
+                name: name.clone(),
+                rooms: space_data.rooms.clone(),
+                has_joined: space_data.has_joined,
+                is_admin: state.role_in_space(name) == Some(SpaceRole::Admin),
+            })
+            .collect::<Vec<SpaceState>>();
+
+        spaces.sort_by(|space_a, space_b| space_a.name.cmp(&space_b.name));
 
         Self {
             rooms,
+            spaces,
             active_room: state.active_room.clone(),
+            can_browse_rooms: state.can_browse_rooms,
+            room_directory: state.room_directory.clone(),
+            theme: state.theme,
         }
     }
 }
 
+/// An entry of the room list, spaces are top-level nodes with their rooms nested under them
+enum RoomListNode<'a> {
+    Space {
+        space: &'a SpaceState,
+        is_collapsed: bool,
+        /// Unread state of the rooms hidden under a collapsed space
+        unread_count: usize,
+        unread_mention_count: usize,
+    },
+    Room {
+        room: &'a RoomState,
+        is_nested: bool,
+    },
+}
+
 pub struct RoomList {
     /// Sending actions to the state store
     action_tx: UnboundedSender<Action>,
@@ -60,54 +145,305 @@ pub struct RoomList {
     // Internal Component State
     /// List with optional selection and current offset
     pub list_state: ListState,
+    /// Spaces whose rooms are hidden in the list
+    collapsed_spaces: HashSet<String>,
+    /// The index of the first node shown when last rendered, to find the clicked node
+    rendered_offset: Cell<usize>,
+    /// How many rows the list took when last rendered, the preview of the browsed room is below them
+    rendered_list_height: Cell<usize>,
+    tab: RoomListTab,
 }
 
 impl RoomList {
-    fn next(&mut self) {
-        let i = match self.list_state.selected() {
-            Some(i) => {
-                if i >= self.props.rooms.len() - 1 {
+    /// The entries of the list as they are rendered, rooms which are not in a space are listed after the spaces
+    fn nodes(&self) -> Vec<RoomListNode<'_>> {
+        let mut nodes = vec![];
+
+        for space in self.props.spaces.iter() {
+            let is_collapsed = self.collapsed_spaces.contains(&space.name);
+            let rooms = self
+                .props
+                .rooms
+                .iter()
+                .filter(|room| space.rooms.contains(&room.name))
+                .collect::<Vec<_>>();
+
+            nodes.push(RoomListNode::Space {
+                space,
+                is_collapsed,
+                unread_count: if is_collapsed {
+                    rooms.iter().map(|room| room.unread_count).sum()
+                } else {
                     0
+                },
+                unread_mention_count: if is_collapsed {
+                    rooms.iter().map(|room| room.unread_mention_count).sum()
                 } else {
-                    i + 1
+                    0
+                },
+            });
+
+            if !is_collapsed {
+                for room in space.rooms.iter() {
+                    if let Some(room) = rooms.iter().find(|room_state| room_state.name.eq(room)) {
+                        nodes.push(RoomListNode::Room {
+                            room,
+                            is_nested: true,
+                        });
+                    }
                 }
             }
+        }
+
+        for room in self.props.rooms.iter() {
+            if !self
+                .props
+                .spaces
+                .iter()
+                .any(|space| space.rooms.contains(&room.name))
+            {
+                nodes.push(RoomListNode::Room {
+                    room,
+                    is_nested: false,
+                });
+            }
+        }
+
+        nodes
+    }
+
+    /// Collapses or expands the selected space, or the space of the selected room
+    fn set_selected_collapsed(&mut self, is_collapsed: bool) {
+        let Some(selected_idx) = self.list_state.selected() else {
+            return;
+        };
+
+        // the nested rooms are skipped backwards until their space is found
+        let mut space_name = None;
+        for node in self.nodes().iter().take(selected_idx + 1).rev() {
+            match node {
+                RoomListNode::Room {
+                    is_nested: true, ..
+                } => continue,
+                RoomListNode::Space { space, .. } => space_name = Some(space.name.clone()),
+                RoomListNode::Room { .. } => {}
+            }
+
+            break;
+        }
+
+        if let Some(space_name) = space_name {
+            if is_collapsed {
+                self.collapsed_spaces.insert(space_name.clone());
+            } else {
+                self.collapsed_spaces.remove(&space_name);
+            }
+
+            // keep the selection on the space, since its rooms may have been hidden
+            let idx = self.get_space_idx(&space_name).unwrap_or(0);
+            self.list_state.select(Some(idx));
+        }
+    }
+
+    /// The public rooms listed by the browse tab, none until the first page is received
+    fn directory_rooms(&self) -> &[comms::event::DirectoryRoom] {
+        self.props
+            .room_directory
+            .as_ref()
+            .map(|directory| directory.rooms.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// How many entries the list of the current tab has
+    fn len(&self) -> usize {
+        match self.tab {
+            RoomListTab::Joined => self.nodes().len(),
+            RoomListTab::Browse => self.directory_rooms().len(),
+        }
+    }
+
+    fn next(&mut self) {
+        let len = self.len();
+        if len == 0 {
+            return;
+        }
+
+        let i = match self.list_state.selected() {
+            Some(i) if i + 1 >= len => {
+                // the next public rooms are paged in once the last one received is reached
+                if let Some(directory) = self
+                    .props
+                    .room_directory
+                    .as_ref()
+                    .filter(|_| self.tab == RoomListTab::Browse)
+                {
+                    if directory.cursor.is_some() && !directory.is_loading {
+                        let _ = self.action_tx.send(Action::LoadMoreRooms);
+                    }
+                    return;
+                }
+
+                0
+            }
+            Some(i) => i + 1,
             None => 0,
         };
         self.list_state.select(Some(i));
     }
 
     fn previous(&mut self) {
+        let len = self.len();
+        if len == 0 {
+            return;
+        }
+
         let i = match self.list_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.props.rooms.len() - 1
-                } else {
-                    i - 1
-                }
-            }
+            Some(0) => len - 1,
+            Some(i) => i - 1,
             None => 0,
         };
 
         self.list_state.select(Some(i));
     }
 
-    pub(super) fn rooms(&self) -> &Vec<RoomState> {
-        &self.props.rooms
+    /// Switches between the joined rooms and the public rooms, the public rooms are listed again from the server
+    fn toggle_tab(&mut self) {
+        if !self.props.can_browse_rooms {
+            return;
+        }
+
+        self.tab = match self.tab {
+            RoomListTab::Joined => {
+                let _ = self.action_tx.send(Action::BrowseRooms);
+                // the rooms listed before are shown until the first page is received
+                self.props.room_directory = None;
+                RoomListTab::Browse
+            }
+            RoomListTab::Browse => RoomListTab::Joined,
+        };
+        self.select_active_room();
+    }
+
+    /// Is a room selected rather than a space, which stays selected after being joined or collapsed
+    pub fn is_room_selected(&self) -> bool {
+        let Some(idx) = self.list_state.selected() else {
+            return false;
+        };
+
+        match self.tab {
+            RoomListTab::Joined => self
+                .nodes()
+                .into_iter()
+                .nth(idx)
+                .map(|node| matches!(node, RoomListNode::Room { .. }))
+                .unwrap_or(false),
+            RoomListTab::Browse => idx < self.directory_rooms().len(),
+        }
+    }
+
+    /// Selects the active room among the joined rooms, or the first public room
+    fn select_active_room(&mut self) {
+        let idx: usize = match self.tab {
+            RoomListTab::Joined => self
+                .props
+                .active_room
+                .as_ref()
+                .and_then(|room_name| self.get_room_idx(room_name.as_str()))
+                .unwrap_or(0),
+            RoomListTab::Browse => 0,
+        };
+
+        *self.list_state.offset_mut() = 0;
+        self.list_state.select(Some(idx));
     }
 
     fn get_room_idx(&self, name: &str) -> Option<usize> {
-        self.props
-            .rooms
-            .iter()
-            .enumerate()
-            .find_map(|(idx, room_state)| {
-                if room_state.name == name {
-                    Some(idx)
-                } else {
-                    None
-                }
-            })
+        self.nodes().iter().position(|node| match node {
+            RoomListNode::Room { room, .. } => room.name == name,
+            RoomListNode::Space { .. } => false,
+        })
+    }
+
+    fn get_space_idx(&self, name: &str) -> Option<usize> {
+        self.nodes().iter().position(|node| match node {
+            RoomListNode::Space { space, .. } => space.name == name,
+            RoomListNode::Room { .. } => false,
+        })
+    }
+
+    /// Selects the node shown at the given row and opens it, as if Enter was pressed on it
+    pub fn click(&mut self, row: usize) {
+        let idx = self.rendered_offset.get() + row;
+        if row >= self.rendered_list_height.get() || idx >= self.len() {
+            return;
+        }
+
+        self.list_state.select(Some(idx));
+        self.open_selected();
+    }
+
+    /// Opens the selected room, or joins the selected space
+    /// Asks to leave the selected room, if it is a joined one
+    fn leave_selected(&mut self) {
+        if self.tab == RoomListTab::Browse {
+            return;
+        }
+
+        let Some(selected_idx) = self.list_state.selected() else {
+            return;
+        };
+
+        if let Some(RoomListNode::Room { room, .. }) = self.nodes().get(selected_idx) {
+            if room.has_joined {
+                let _ = self.action_tx.send(Action::LeaveRoom {
+                    room: Some(room.name.clone()),
+                });
+            }
+        }
+    }
+
+    fn open_selected(&mut self) {
+        let Some(selected_idx) = self.list_state.selected() else {
+            return;
+        };
+
+        // the public rooms are joined, or made active if they are joined already
+        if self.tab == RoomListTab::Browse {
+            if let Some(room) = self.directory_rooms().get(selected_idx) {
+                let _ = self.action_tx.send(Action::SelectRoom {
+                    room: room.name.clone(),
+                });
+            }
+            return;
+        }
+
+        // the rooms of a joined space are shown or hidden, instead of being joined
+        let toggled = match self.nodes().get(selected_idx) {
+            Some(RoomListNode::Space {
+                space,
+                is_collapsed,
+                ..
+            }) if space.has_joined => Some(!*is_collapsed),
+            _ => None,
+        };
+
+        if let Some(is_collapsed) = toggled {
+            self.set_selected_collapsed(is_collapsed);
+            return;
+        }
+
+        let action = match self.nodes().get(selected_idx) {
+            Some(RoomListNode::Room { room, .. }) => Action::SelectRoom {
+                room: room.name.clone(),
+            },
+            Some(RoomListNode::Space { space, .. }) => Action::JoinSpace {
+                space: space.name.clone(),
+            },
+            None => return,
+        };
+
+        // TODO: handle the error scenario somehow
+        let _ = self.action_tx.send(action);
     }
 }
 
@@ -118,6 +454,10 @@ impl Component for RoomList {
             props: Props::from(state),
             //
             list_state: ListState::default(),
+            collapsed_spaces: HashSet::new(),
+            rendered_offset: Cell::new(0),
+            rendered_list_height: Cell::new(usize::MAX),
+            tab: RoomListTab::Joined,
         }
     }
 
@@ -147,17 +487,15 @@ impl Component for RoomList {
             KeyCode::Down => {
                 self.next();
             }
-            KeyCode::Enter if self.list_state.selected().is_some() => {
-                let selected_idx = self.list_state.selected().unwrap();
-
-                let rooms = self.rooms();
-                let room_state = rooms.get(selected_idx).unwrap();
-
-                // TODO: handle the error scenario somehow
-                let _ = self.action_tx.send(Action::SelectRoom {
-                    room: room_state.name.clone(),
-                });
+            KeyCode::Left => {
+                self.set_selected_collapsed(true);
+            }
+            KeyCode::Right => {
+                self.set_selected_collapsed(false);
             }
+            KeyCode::Tab => self.toggle_tab(),
+            KeyCode::Enter => self.open_selected(),
+            KeyCode::Char('l') => self.leave_selected(),
             _ => (),
         }
     }
@@ -165,81 +503,280 @@ impl Component for RoomList {
 
 impl SectionActivation for RoomList {
     fn activate(&mut self) {
-        let idx: usize = self
-            .props
-            .active_room
-            .as_ref()
-            .and_then(|room_name| self.get_room_idx(room_name.as_str()))
-            .unwrap_or(0);
-
-        *self.list_state.offset_mut() = 0;
-        self.list_state.select(Some(idx));
+        self.select_active_room();
     }
 
     fn deactivate(&mut self) {
+        // the joined rooms are listed again once the list is left
+        self.tab = RoomListTab::Joined;
         *self.list_state.offset_mut() = 0;
         self.list_state.select(None);
     }
 }
 
+/// Marks the mentions with an `@` and their number, followed by the number of unread messages, as in `#general@1 (3)`
+pub fn unread_marker(unread_count: usize, unread_mention_count: usize) -> String {
+    format!(
+        "{}{}",
+        if unread_mention_count > 0 {
+            format!("@{}", unread_mention_count)
+        } else {
+            String::new()
+        },
+        if unread_count > 0 {
+            format!(" ({})", unread_count)
+        } else {
+            String::new()
+        }
+    )
+}
+
 pub struct RenderProps {
     pub border_color: Color,
     pub area: Rect,
 }
 
-impl ComponentRender<RenderProps> for RoomList {
-    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
-        let active_room = self.props.active_room.clone();
-        let room_list: Vec<ListItem> = self
-            .rooms()
-            .iter()
-            .map(|room_state| {
-                let room_tag = format!(
-                    "#{}{}",
-                    room_state.name,
-                    if room_state.has_unread { "*" } else { "" }
-                );
-                let content = Line::from(Span::raw(room_tag));
+/// Formats the time of the latest message of a public room in the local timezone
+fn format_last_activity(timestamp: u64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|date_time| date_time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
 
-                let style = if self.list_state.selected().is_none()
-                    && active_room.is_some()
-                    && active_room.as_ref().unwrap().eq(&room_state.name)
-                {
-                    Style::default().add_modifier(Modifier::BOLD)
-                } else if room_state.has_unread {
-                    Style::default().add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC)
-                } else {
-                    Style::default()
-                };
+impl RoomList {
+    /// The tabs, with the current one underlined, or the plain title if the public rooms can not be browsed
+    fn title(&self) -> Line<'static> {
+        if !self.props.can_browse_rooms {
+            return Line::from("Rooms");
+        }
+
+        let tab = |label: &'static str, tab: RoomListTab| {
+            if self.tab == tab {
+                Span::from(label).bold().underlined()
+            } else {
+                Span::from(label).fg(self.props.theme.muted)
+            }
+        };
+
+        Line::from(vec![
+            tab("Joined", RoomListTab::Joined),
+            Span::raw(" · "),
+            tab("Browse", RoomListTab::Browse),
+        ])
+    }
 
-                ListItem::new(content).style(style.bg(Color::Reset))
+    /// The public rooms with their member count, the joined ones are checked
+    fn directory_items(&self) -> Vec<ListItem<'static>> {
+        let Some(directory) = self.props.room_directory.as_ref() else {
+            return vec![ListItem::new(Span::from("loading…").dim())];
+        };
+
+        let mut items = directory
+            .rooms
+            .iter()
+            .map(|room| {
+                let has_joined = self
+                    .props
+                    .rooms
+                    .iter()
+                    .any(|room_state| room_state.name == room.name && room_state.has_joined);
+                let mut spans = vec![
+                    Span::raw(format!("#{}", room.name)),
+                    Span::from(format!(" ({})", room.member_count)).dim(),
+                ];
+                if has_joined {
+                    spans.push(Span::from(" ✓").fg(self.props.theme.accent));
+                }
+
+                ListItem::new(Line::from(spans))
             })
-            .collect();
+            .collect::<Vec<_>>();
 
-        let room_list = List::new(room_list)
+        let status = if directory.is_loading {
+            Some("loading…")
+        } else if directory.rooms.is_empty() {
+            Some("no public rooms")
+        } else if directory.cursor.is_some() {
+            Some("↓ for more rooms")
+        } else {
+            None
+        };
+        items.extend(status.map(|status| ListItem::new(Span::from(status).dim())));
+
+        items
+    }
+
+    /// The topic and the activity of the selected public room, shown before joining it
+    fn directory_preview(&self) -> Option<Paragraph<'static>> {
+        let room = self
+            .list_state
+            .selected()
+            .and_then(|idx| self.directory_rooms().get(idx))?;
+
+        let topic = if room.description.is_empty() {
+            Line::from(Span::from("no topic").italic())
+        } else {
+            Line::from(room.description.clone())
+        };
+        let activity = match room.last_activity {
+            Some(timestamp) => format!("last message {}", format_last_activity(timestamp)),
+            None => String::from("no recent messages"),
+        };
+
+        Some(
+            Paragraph::new(vec![
+                topic,
+                Line::from(Span::from(format!("{} users", room.member_count)).dim()),
+                Line::from(Span::from(activity).dim()),
+            ])
+            .wrap(Wrap { trim: true })
             .block(
                 Block::default()
-                    .borders(Borders::ALL)
-                    .border_style(Style::new().fg(props.border_color))
-                    .title("Rooms"),
-            )
+                    .borders(Borders::TOP)
+                    .border_style(Style::new().fg(self.props.theme.muted)),
+            ),
+        )
+    }
+}
+
+impl ComponentRender<RenderProps> for RoomList {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::new().fg(props.border_color))
+            .title(self.title());
+        let inner_area = block.inner(props.area);
+        frame.render_widget(block, props.area);
+
+        let room_list = match self.tab {
+            RoomListTab::Joined => self.joined_items(),
+            RoomListTab::Browse => self.directory_items(),
+        };
+
+        // the preview of the selected public room takes the lower part of the list, up to half of it
+        let (list_area, preview) = match self
+            .directory_preview()
+            .filter(|_| self.tab == RoomListTab::Browse)
+        {
+            Some(preview) => {
+                let [list_area, preview_area] = layout::split(
+                    inner_area,
+                    Direction::Vertical,
+                    [Constraint::Min(1), Constraint::Max(inner_area.height / 2)],
+                );
+
+                (list_area, Some((preview, preview_area)))
+            }
+            None => (inner_area, None),
+        };
+
+        let room_list = List::new(room_list)
             .highlight_style(
                 Style::default()
-                    // yellow that would work for both dark / light modes
-                    .bg(Color::Rgb(255, 223, 102))
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg)
                     .add_modifier(Modifier::BOLD),
             )
             .highlight_symbol(">");
 
         let mut app_room_list_state = self.list_state.clone();
-        frame.render_stateful_widget(room_list, props.area, &mut app_room_list_state);
+        frame.render_stateful_widget(room_list, list_area, &mut app_room_list_state);
+        self.rendered_offset.set(app_room_list_state.offset());
+        self.rendered_list_height.set(list_area.height as usize);
+
+        if let Some((preview, preview_area)) = preview {
+            frame.render_widget(preview, preview_area);
+        }
+    }
+}
+
+impl RoomList {
+    /// The spaces and the joined rooms, or every room if the public rooms can not be browsed
+    fn joined_items(&self) -> Vec<ListItem<'static>> {
+        let active_room = self.props.active_room.clone();
+        let room_list: Vec<ListItem> = self
+            .nodes()
+            .iter()
+            .map(|node| match node {
+                RoomListNode::Space {
+                    space,
+                    is_collapsed,
+                    unread_count,
+                    unread_mention_count,
+                } => {
+                    let space_tag = format!(
+                        "{} {}{}{}",
+                        if *is_collapsed { "▸" } else { "▾" },
+                        space.name,
+                        if space.is_admin { " (admin)" } else { "" },
+                        unread_marker(*unread_count, *unread_mention_count)
+                    );
+                    let content = Line::from(Span::raw(space_tag));
+
+                    let style = if space.has_joined {
+                        Style::default().add_modifier(Modifier::UNDERLINED)
+                    } else {
+                        Style::default().fg(self.props.theme.muted)
+                    };
+
+                    ListItem::new(content).style(style.bg(Color::Reset))
+                }
+                RoomListNode::Room {
+                    room: room_state,
+                    is_nested,
+                } => {
+                    let room_tag = format!(
+                        "{}{}#{}{}{}",
+                        if *is_nested { "  " } else { "" },
+                        if room_state.is_favorite { "★ " } else { "" },
+                        room_state.name,
+                        unread_marker(room_state.unread_count, room_state.unread_mention_count),
+                        if room_state.is_join_pending {
+                            " (joining)"
+                        } else {
+                            ""
+                        }
+                    );
+                    let mut spans = vec![Span::raw(room_tag)];
+                    if room_state.has_draft {
+                        spans.push(Span::from(" ✎ draft").dim());
+                    }
+                    let content = Line::from(spans);
+
+                    let style = if room_state.is_join_pending {
+                        Style::default()
+                            .fg(self.props.theme.muted)
+                            .add_modifier(Modifier::ITALIC)
+                    } else if self.list_state.selected().is_none()
+                        && active_room.is_some()
+                        && active_room.as_ref().unwrap().eq(&room_state.name)
+                    {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    } else if room_state.unread_mention_count > 0 {
+                        Style::default()
+                            .fg(self.props.theme.accent)
+                            .add_modifier(Modifier::BOLD | Modifier::ITALIC)
+                    } else if room_state.unread_count > 0 {
+                        Style::default().add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC)
+                    } else {
+                        Style::default()
+                    };
+
+                    ListItem::new(content).style(style.bg(Color::Reset))
+                }
+            })
+            .collect();
+
+        room_list
     }
 }
 
 impl HasUsageInfo for RoomList {
     fn usage_info(&self) -> UsageInfo {
-        UsageInfo {
-            description: Some("Select the room to talk in".into()),
+        let mut usage_info = UsageInfo {
+            description: Some("Select the room to talk in, or the space to join".into()),
             lines: vec![
                 UsageInfoLine {
                     keys: vec!["Esc".into()],
@@ -249,11 +786,141 @@ impl HasUsageInfo for RoomList {
                     keys: vec!["↑".into(), "↓".into()],
                     description: "to navigate".into(),
                 },
+                UsageInfoLine {
+                    keys: vec!["←".into(), "→".into()],
+                    description: "to collapse or expand a space".into(),
+                },
                 UsageInfoLine {
                     keys: vec!["Enter".into()],
-                    description: "to join room".into(),
+                    description: "to join room or space".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["l".into()],
+                    description: "to leave the room".into(),
                 },
             ],
+        };
+
+        if self.props.can_browse_rooms {
+            usage_info.lines.push(UsageInfoLine {
+                keys: vec!["Tab".into()],
+                description: "to browse the public rooms, or go back to the joined ones".into(),
+            });
         }
+
+        usage_info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event;
+
+    use super::*;
+    use crate::state_store::SpaceData;
+    use crate::ui_management::test_harness::TestHarness;
+
+    #[test]
+    fn test_joins_the_selected_space() {
+        let mut state = State::test_with_rooms(&[("rust", "")]);
+        state.space_data_map.insert(
+            "engineering".into(),
+            SpaceData {
+                rooms: vec!["rust".into()],
+                ..SpaceData::default()
+            },
+        );
+        let mut harness = TestHarness::<RoomList>::new(&state);
+
+        harness.component_mut().activate();
+        harness.press(KeyCode::Enter);
+
+        assert!(!harness.component().is_room_selected());
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::JoinSpace {
+                space: "engineering".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_browses_and_joins_the_public_rooms() {
+        let mut state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+        state.can_browse_rooms = true;
+        let mut harness = TestHarness::<RoomList>::new(&state);
+
+        harness.component_mut().activate();
+        harness.press(KeyCode::Tab);
+        assert_eq!(harness.component().tab, RoomListTab::Browse);
+        assert_eq!(harness.drain_actions(), vec![Action::BrowseRooms]);
+
+        let directory_room = |name: &str| event::DirectoryRoom {
+            name: name.into(),
+            description: String::new(),
+            member_count: 1,
+            last_activity: None,
+        };
+        state.room_directory = Some(RoomDirectory {
+            rooms: vec![directory_room("general"), directory_room("rust")],
+            cursor: Some("rust".into()),
+            is_loading: false,
+        });
+
+        // the next page is requested once the last room received is passed
+        harness
+            .apply_state(&state)
+            .press(KeyCode::Down)
+            .press(KeyCode::Down)
+            .press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::LoadMoreRooms,
+                Action::SelectRoom {
+                    room: "rust".into()
+                },
+            ]
+        );
+
+        // the joined rooms are listed again once the list is left
+        harness.component_mut().deactivate();
+        assert_eq!(harness.component().tab, RoomListTab::Joined);
+    }
+
+    #[test]
+    fn test_does_not_browse_without_the_server_support() {
+        let state = State::test_with_rooms(&[("general", "")]).with_joined_room("general", &[]);
+        let mut harness = TestHarness::<RoomList>::new(&state);
+
+        harness.component_mut().activate();
+        harness.press(KeyCode::Tab);
+
+        assert_eq!(harness.component().tab, RoomListTab::Joined);
+        assert!(harness.drain_actions().is_empty());
+    }
+
+    #[test]
+    fn test_asks_to_leave_only_the_joined_rooms() {
+        let state = State::test_with_rooms(&[("general", ""), ("rust", "")])
+            .with_joined_room("general", &[])
+            .with_active_room("general");
+        let mut harness = TestHarness::<RoomList>::new(&state);
+
+        // the active room is selected once the list is activated
+        harness.component_mut().activate();
+        harness
+            .press(KeyCode::Char('l'))
+            .press(KeyCode::Down)
+            .press(KeyCode::Char('l'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::LeaveRoom {
+                room: Some("general".into())
+            }]
+        );
     }
 }
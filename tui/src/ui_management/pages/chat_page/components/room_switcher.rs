@@ -0,0 +1,332 @@
+use std::cmp::Reverse;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{
+    super::{
+        fuzzy::fuzzy_score,
+        section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine},
+    },
+    room_list::unread_marker,
+};
+use crate::{
+    state_store::{action::Action, State},
+    theme::Theme,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+/// A room or a conversation of direct messages the switcher can open
+struct SwitchTarget {
+    /// The key of the room in the state, `@user` for a conversation
+    room: String,
+    /// The name shown and matched, as `#room` or `@user`
+    label: String,
+    unread_count: usize,
+    unread_mention_count: usize,
+    /// How many rooms were opened since this one, none if it was never opened
+    recency: Option<usize>,
+}
+
+struct Props {
+    /// Every room and conversation but the active one
+    targets: Vec<SwitchTarget>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        let mut targets = state
+            .room_data_map
+            .iter()
+            .filter(|(room, _)| state.active_room.as_ref() != Some(*room))
+            .map(|(room, room_data)| SwitchTarget {
+                room: room.clone(),
+                label: if room_data.is_direct_message {
+                    format!("@{}", room_data.name)
+                } else {
+                    format!("#{}", room_data.name)
+                },
+                unread_count: room_data.unread_count,
+                unread_mention_count: room_data.unread_mention_count,
+                recency: state
+                    .recent_rooms
+                    .iter()
+                    .position(|recent_room| recent_room == room),
+            })
+            .collect::<Vec<_>>();
+        // the map has no order, the targets ranking the same are listed by name
+        targets.sort_by(|a, b| a.label.cmp(&b.label));
+
+        Props {
+            targets,
+            theme: state.theme,
+        }
+    }
+}
+
+/// RoomSwitcher is an overlay finding a room or a conversation by a few letters of its name, and opening it
+///
+/// The matches are ranked by how well they match, then by their unread mentions and messages, then by how
+/// recently they were opened.
+pub struct RoomSwitcher {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped RoomSwitcher Props
+    props: Props,
+    // Internal Component State
+    is_open: bool,
+    query: String,
+    /// Index of the selected target, among the ones matching the query
+    selected: usize,
+}
+
+impl RoomSwitcher {
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    /// The targets matching the query, the best ranked first
+    fn matches(&self) -> Vec<&SwitchTarget> {
+        let mut matches = self
+            .props
+            .targets
+            .iter()
+            .filter_map(|target| Some((fuzzy_score(&self.query, &target.label)?, target)))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(score, target)| {
+            (
+                Reverse(*score),
+                Reverse(target.unread_mention_count),
+                Reverse(target.unread_count),
+                target.recency.unwrap_or(usize::MAX),
+            )
+        });
+
+        matches.into_iter().map(|(_, target)| target).collect()
+    }
+
+    fn open_selected(&mut self) {
+        if let Some(target) = self.matches().get(self.selected) {
+            let _ = self.action_tx.send(Action::SelectRoom {
+                room: target.room.clone(),
+            });
+        }
+
+        self.close();
+    }
+}
+
+impl Component for RoomSwitcher {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            is_open: false,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Room Switcher"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Enter => self.open_selected(),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.matches().len().saturating_sub(1))
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            KeyCode::Char(char) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.query.push(char);
+                self.selected = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+impl ComponentRender<RenderProps> for RoomSwitcher {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let matches = self.matches();
+        let has_matches = !matches.is_empty();
+        let items = if has_matches {
+            matches
+                .into_iter()
+                .map(|target| {
+                    let marker = Span::from(unread_marker(
+                        target.unread_count,
+                        target.unread_mention_count,
+                    ));
+
+                    ListItem::new(Line::from(vec![
+                        Span::raw(target.label.clone()),
+                        if target.unread_mention_count > 0 {
+                            marker.fg(self.props.theme.mention)
+                        } else {
+                            marker.dim()
+                        },
+                    ]))
+                })
+                .collect()
+        } else {
+            vec![ListItem::new(Line::from(
+                Span::from("no matching room").dim(),
+            ))]
+        };
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(props.border_color))
+                    .title(format!("Switch to {}", self.query)),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg),
+            );
+
+        let mut list_state = ListState::default();
+        list_state.select(has_matches.then_some(self.selected));
+
+        frame.render_widget(Clear, props.area);
+        frame.render_stateful_widget(list, props.area, &mut list_state);
+    }
+}
+
+impl HasUsageInfo for RoomSwitcher {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Type a few letters of a room or a user".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to close".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to select a room".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Enter".into()],
+                    description: "to open it".into(),
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    #[test]
+    fn test_ranks_the_unread_then_the_recent_rooms() {
+        let mut state = State::test_with_rooms(&[
+            ("general", "General talk"),
+            ("rust", "Talk about the Rust programming language"),
+            ("random", "Off topic"),
+            ("releases", "Announcements of the releases"),
+        ])
+        .with_joined_room("general", &["alice"])
+        .with_active_room("random")
+        .with_active_room("releases")
+        .with_active_room("general");
+        state.room_data_map.get_mut("rust").unwrap().unread_count = 2;
+        let mut harness = TestHarness::<RoomSwitcher>::new(&state);
+
+        // the unread room ranks first, then the room opened most recently, the active one is left out
+        harness.component_mut().open();
+        harness.press(KeyCode::Enter);
+        assert!(!harness.component().is_open());
+
+        harness.component_mut().open();
+        harness.press(KeyCode::Down).press(KeyCode::Enter);
+
+        harness.component_mut().open();
+        harness.type_text("rnd").press(KeyCode::Enter);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::SelectRoom {
+                    room: "rust".into()
+                },
+                Action::SelectRoom {
+                    room: "releases".into()
+                },
+                Action::SelectRoom {
+                    room: "random".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_the_selection_stays_among_the_matches() {
+        let state = State::test_with_rooms(&[("general", ""), ("rust", "")]);
+        let mut harness = TestHarness::<RoomSwitcher>::new(&state);
+
+        harness.component_mut().open();
+        harness
+            .type_text("rust")
+            .press(KeyCode::Down)
+            .press(KeyCode::Down);
+        assert_eq!(harness.component().selected, 0);
+
+        harness.press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SelectRoom {
+                room: "rust".into()
+            }]
+        );
+    }
+}
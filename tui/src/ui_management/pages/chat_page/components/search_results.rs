@@ -0,0 +1,293 @@
+use chrono::{Local, TimeZone};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine};
+use crate::{
+    state_store::{action::Action, HistorySearch, State},
+    theme::Theme,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+struct Props {
+    /// The search through the stored history of a room, the overlay is open while there is one
+    history_search: Option<HistorySearch>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        Props {
+            history_search: state.history_search.clone(),
+            theme: state.theme,
+        }
+    }
+}
+
+/// SearchResults is an overlay listing the matches of a search through the stored history of a room
+pub struct SearchResults {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped SearchResults Props
+    props: Props,
+    // Internal Component State
+    /// Index of the selected match, the newest first
+    selected: usize,
+}
+
+impl SearchResults {
+    pub fn is_open(&self) -> bool {
+        self.props.history_search.is_some()
+    }
+
+    /// Closes the overlay right away instead of waiting for the state update
+    pub fn close(&mut self) {
+        let _ = self.action_tx.send(Action::CloseHistorySearch);
+        self.props.history_search = None;
+    }
+
+    fn select_older(&mut self) {
+        let Some(search) = self.props.history_search.as_ref() else {
+            return;
+        };
+
+        if self.selected + 1 < search.results.len() {
+            self.selected += 1;
+        } else if search.cursor.is_some() && !search.is_loading {
+            // the older matches are paged in once the last one received is reached
+            let _ = self.action_tx.send(Action::LoadMoreSearchResults);
+        }
+    }
+
+    fn jump_to_selected(&mut self) {
+        let Some(message) = self
+            .props
+            .history_search
+            .as_ref()
+            .and_then(|search| search.results.get(self.selected))
+        else {
+            return;
+        };
+
+        let _ = self.action_tx.send(Action::JumpToDate {
+            timestamp: message.timestamp,
+        });
+        self.close();
+    }
+}
+
+impl Component for SearchResults {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            selected: 0,
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        let props = Props::from(state);
+        let is_same_search = match (&self.props.history_search, &props.history_search) {
+            (Some(old), Some(new)) => old.room == new.room && old.query == new.query,
+            _ => false,
+        };
+
+        Self {
+            props,
+            // a new search starts from its newest match
+            selected: if is_same_search { self.selected } else { 0 },
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Search Results"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.select_older(),
+            KeyCode::Enter => self.jump_to_selected(),
+            KeyCode::Esc => self.close(),
+            _ => (),
+        }
+    }
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+fn format_date(timestamp: u64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|date_time| date_time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+impl ComponentRender<RenderProps> for SearchResults {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let Some(search) = self.props.history_search.as_ref() else {
+            return;
+        };
+
+        let mut items = search
+            .results
+            .iter()
+            .map(|message| {
+                ListItem::new(Line::from(vec![
+                    Span::from(format!("[{}] ", format_date(message.timestamp))).dim(),
+                    Span::from(format!("@{}", message.user_id)).bold(),
+                    Span::from(": "),
+                    Span::from(message.content.clone()),
+                ]))
+            })
+            .collect::<Vec<_>>();
+
+        let status = if search.is_loading {
+            "searching…"
+        } else if search.results.is_empty() {
+            "no matches"
+        } else if search.cursor.is_some() {
+            "↓ for the older matches"
+        } else {
+            "no older matches"
+        };
+        items.push(ListItem::new(Line::from(Span::from(status).dim())));
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(props.border_color))
+                    .title(format!(r#"Search #{} for "{}""#, search.room, search.query)),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(self.props.theme.selection_bg)
+                    .fg(self.props.theme.selection_fg),
+            );
+
+        let mut list_state = ListState::default();
+        if !search.results.is_empty() {
+            list_state.select(Some(self.selected));
+        }
+
+        frame.render_widget(Clear, props.area);
+        frame.render_stateful_widget(list, props.area, &mut list_state);
+    }
+}
+
+impl HasUsageInfo for SearchResults {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Pick a match to jump to in the room history".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to close".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to select a match".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Enter".into()],
+                    description: "to jump".into(),
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event;
+
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn history_message(id: u64, content: &str, timestamp: u64) -> event::HistoryMessage {
+        event::HistoryMessage {
+            id,
+            user_id: "alice".into(),
+            content: content.into(),
+            timestamp,
+            reply_to: None,
+            is_edited: false,
+            reactions: vec![],
+        }
+    }
+
+    fn searching(query: &str) -> State {
+        let mut state = State::test_with_rooms(&[("general", "General talk")])
+            .with_joined_room("general", &["alice"])
+            .with_active_room("general");
+        state.start_history_search(query.into());
+        state.handle_server_event(&event::Event::SearchResults(
+            event::SearchResultsReplyEvent {
+                room: "general".into(),
+                query: query.into(),
+                messages: vec![
+                    history_message(9, "the release is out", 2_000),
+                    history_message(4, "release notes are due", 1_000),
+                ],
+                cursor: Some(4),
+            },
+        ));
+        state
+    }
+
+    #[test]
+    fn test_jumps_to_the_selected_match() {
+        let mut harness = TestHarness::<SearchResults>::new(&searching("release"));
+
+        // the older matches are requested once the last one received is passed
+        harness
+            .press(KeyCode::Down)
+            .press(KeyCode::Down)
+            .press(KeyCode::Enter);
+
+        assert!(!harness.component().is_open());
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::LoadMoreSearchResults,
+                Action::JumpToDate { timestamp: 1_000 },
+                Action::CloseHistorySearch,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_a_new_search_starts_from_its_newest_match() {
+        let mut harness = TestHarness::<SearchResults>::new(&searching("release"));
+
+        harness.press(KeyCode::Down);
+        assert_eq!(harness.component().selected, 1);
+
+        harness.apply_state(&searching("release"));
+        assert_eq!(harness.component().selected, 1);
+
+        harness.apply_state(&searching("notes"));
+        assert_eq!(harness.component().selected, 0);
+    }
+}
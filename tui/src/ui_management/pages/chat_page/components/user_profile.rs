@@ -0,0 +1,273 @@
+use std::str::FromStr;
+
+use comms::event::{PresenceStatus, UserProfile};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::super::{
+    chat_page::presence_dot,
+    section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine},
+};
+use crate::{
+    state_store::{action::Action, user_label, State},
+    theme::Theme,
+};
+
+use crate::ui_management::components::{Component, ComponentRender};
+
+struct Props {
+    /// The logged in user, who can not message themselves
+    user_id: String,
+    /// The profile shown, none while the popup is closed
+    profile: Option<UserProfile>,
+    /// Whether the user of the profile is online, away or offline
+    status: PresenceStatus,
+    /// The color their name is shown with, if they picked one which can be parsed
+    color: Option<Color>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        let profile = state.user_profile.clone();
+        let user_id = profile.as_ref().map(|profile| profile.user_id.as_str());
+
+        Props {
+            user_id: state.user_id.clone(),
+            status: user_id
+                .and_then(|user_id| state.presences.get(user_id))
+                .map(|presence| presence.status)
+                .unwrap_or(PresenceStatus::Offline),
+            color: profile
+                .as_ref()
+                .and_then(|profile| profile.color.as_deref())
+                .and_then(|color| Color::from_str(color).ok()),
+            profile,
+            theme: state.theme,
+        }
+    }
+}
+
+/// UserProfilePopup is a popup showing the profile of a user picked in the room users, until it is closed
+///
+/// It is open while the state holds a profile, which the server sends once asked for it.
+pub struct UserProfilePopup {
+    /// Sending actions to the state store
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped UserProfilePopup Props
+    props: Props,
+}
+
+impl UserProfilePopup {
+    pub fn is_open(&self) -> bool {
+        self.props.profile.is_some()
+    }
+
+    fn close(&mut self) {
+        let _ = self.action_tx.send(Action::CloseUserProfile);
+        // reflect the change right away instead of waiting for the state update
+        self.props.profile = None;
+    }
+
+    fn is_own_profile(&self) -> bool {
+        self.props
+            .profile
+            .as_ref()
+            .is_some_and(|profile| profile.user_id == self.props.user_id)
+    }
+
+    fn open_direct_message(&mut self) {
+        let Some(profile) = self.props.profile.as_ref() else {
+            return;
+        };
+
+        if !self.is_own_profile() {
+            let _ = self.action_tx.send(Action::OpenDirectMessage {
+                user_id: profile.user_id.clone(),
+            });
+        }
+
+        self.close();
+    }
+}
+
+impl Component for UserProfilePopup {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "User Profile"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Enter => self.open_direct_message(),
+            _ => (),
+        }
+    }
+}
+
+pub struct RenderProps {
+    pub area: Rect,
+    pub border_color: Color,
+}
+
+impl ComponentRender<RenderProps> for UserProfilePopup {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        let Some(profile) = self.props.profile.as_ref() else {
+            return;
+        };
+
+        let theme = &self.props.theme;
+        let display_names = profile
+            .display_name
+            .iter()
+            .map(|display_name| (profile.user_id.clone(), display_name.clone()))
+            .collect();
+        let mut name = Span::from(user_label(&display_names, &profile.user_id)).bold();
+        if let Some(color) = self.props.color {
+            name = name.fg(color);
+        }
+
+        let mut lines = vec![Line::from(vec![
+            presence_dot(self.props.status, theme),
+            Span::raw(" "),
+            name,
+        ])];
+        let fields = [
+            ("Pronouns", &profile.pronouns),
+            ("Timezone", &profile.timezone),
+        ];
+        for (label, value) in fields {
+            if let Some(value) = value {
+                lines.push(Line::from(vec![
+                    Span::from(format!("{}: ", label)).dim(),
+                    Span::raw(value.clone()),
+                ]));
+            }
+        }
+        if let (Some(color), Some(hex)) = (self.props.color, profile.color.as_ref()) {
+            lines.push(Line::from(vec![
+                Span::from("Color: ").dim(),
+                Span::from("■ ").fg(color),
+                Span::raw(hex.clone()),
+            ]));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(match profile.bio.as_ref() {
+            Some(bio) => Line::from(bio.clone()),
+            None => Line::from(Span::from("No bio yet").dim().italic()),
+        });
+
+        let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(props.border_color))
+                .title("Profile"),
+        );
+
+        frame.render_widget(Clear, props.area);
+        frame.render_widget(paragraph, props.area);
+    }
+}
+
+impl HasUsageInfo for UserProfilePopup {
+    fn usage_info(&self) -> UsageInfo {
+        let mut lines = vec![UsageInfoLine {
+            keys: vec!["Esc".into()],
+            description: "to close".into(),
+        }];
+        if !self.is_own_profile() {
+            lines.push(UsageInfoLine {
+                keys: vec!["Enter".into()],
+                description: "to message them".into(),
+            });
+        }
+
+        UsageInfo {
+            description: Some("The profile of the user".into()),
+            lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn showing_the_profile_of(user_id: &str) -> State {
+        State {
+            user_profile: Some(UserProfile {
+                user_id: user_id.into(),
+                ..Default::default()
+            }),
+            ..State::default().with_user_id("me")
+        }
+    }
+
+    #[test]
+    fn test_messages_the_user_and_closes() {
+        let mut harness = TestHarness::<UserProfilePopup>::new(&showing_the_profile_of("alice"));
+
+        // the other keys are taken by the popup without doing anything
+        harness.press(KeyCode::Char('q')).press(KeyCode::Enter);
+
+        assert!(!harness.component().is_open());
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::OpenDirectMessage {
+                    user_id: "alice".into()
+                },
+                Action::CloseUserProfile,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_does_not_message_the_user_themselves() {
+        let mut harness = TestHarness::<UserProfilePopup>::new(&showing_the_profile_of("me"));
+
+        harness.press(KeyCode::Enter);
+
+        assert_eq!(harness.drain_actions(), vec![Action::CloseUserProfile]);
+    }
+
+    #[test]
+    fn test_is_closed_without_a_profile() {
+        let mut harness = TestHarness::<UserProfilePopup>::new(&State::default());
+
+        harness.press(KeyCode::Enter);
+
+        assert!(!harness.component().is_open());
+        assert!(harness.drain_actions().is_empty());
+    }
+}
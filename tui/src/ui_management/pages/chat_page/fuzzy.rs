@@ -0,0 +1,52 @@
+/// Scores how well the query matches the text, none if the characters of the query are not all in the text, in order
+///
+/// The characters are compared without case, and the spaces of the query are ignored. A character following
+/// the one matched before, or starting a word, scores higher, so `gen` ranks `#general` above `#green-energy`.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+
+    for query_char in query
+        .to_lowercase()
+        .chars()
+        .filter(|char| !char.is_whitespace())
+    {
+        let index = next + text[next..].iter().position(|char| *char == query_char)?;
+        let is_word_start = index == 0 || !text[index - 1].is_alphanumeric();
+
+        score += 1;
+        if last_match.is_some_and(|last_match| last_match + 1 == index) {
+            score += 2;
+        }
+        if is_word_start {
+            score += 3;
+        }
+
+        last_match = Some(index);
+        next = index + 1;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_matches_characters_in_order() {
+        assert!(fuzzy_score("swgen", "Switch to #general").is_some());
+        assert!(fuzzy_score("Sw Gen", "switch to #general").is_some());
+        assert_eq!(fuzzy_score("negs", "Switch to #general"), None);
+        assert_eq!(fuzzy_score("", "Quit"), Some(0));
+    }
+
+    #[test]
+    fn test_consecutive_and_word_start_characters_rank_higher() {
+        assert_eq!(fuzzy_score("gen", "#general"), Some(10));
+        assert_eq!(fuzzy_score("gen", "#green-energy"), Some(6));
+        assert!(fuzzy_score("rec", "Reconnect") > fuzzy_score("rec", "Reveal the secret"));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, VecDeque};
+
+/// How many sent inputs are remembered for each room, the oldest ones are forgotten first
+pub const MAX_INPUT_HISTORY: usize = 50;
+
+/// The inputs sent to a room, and where the user is while browsing them
+#[derive(Debug, Default)]
+struct RoomInputHistory {
+    /// The sent inputs, the oldest first
+    entries: VecDeque<String>,
+    /// The entry recalled into the input, None while the user is typing a draft
+    index: Option<usize>,
+    /// The text typed before browsing the entries, restored when browsing past the newest one
+    draft: String,
+}
+
+/// [InputHistory] recalls the inputs sent to each room, as the history of a shell
+#[derive(Debug, Default)]
+pub struct InputHistory {
+    rooms: HashMap<String, RoomInputHistory>,
+}
+
+impl InputHistory {
+    /// Remembers the sent input, unless it repeats the last one, and stops browsing
+    pub fn push(&mut self, room: &str, input: &str) {
+        let history = self.rooms.entry(room.to_string()).or_default();
+        history.index = None;
+        history.draft.clear();
+
+        if history.entries.back().map(String::as_str) == Some(input) {
+            return;
+        }
+
+        if history.entries.len() == MAX_INPUT_HISTORY {
+            history.entries.pop_front();
+        }
+        history.entries.push_back(input.to_string());
+    }
+
+    /// Returns the entry before the recalled one, keeping the current input as the draft when browsing starts
+    pub fn previous(&mut self, room: &str, current: &str) -> Option<&str> {
+        let history = self.rooms.get_mut(room)?;
+
+        let index = match history.index {
+            None if history.entries.is_empty() => return None,
+            None => {
+                history.draft = current.to_string();
+                history.entries.len() - 1
+            }
+            Some(index) => index.saturating_sub(1),
+        };
+        history.index = Some(index);
+
+        Some(&history.entries[index])
+    }
+
+    /// Returns the entry after the recalled one, or the draft once past the newest entry
+    pub fn next(&mut self, room: &str) -> Option<&str> {
+        let history = self.rooms.get_mut(room)?;
+
+        let index = history.index?;
+        if index + 1 < history.entries.len() {
+            history.index = Some(index + 1);
+
+            return Some(&history.entries[index + 1]);
+        }
+
+        history.index = None;
+
+        Some(&history.draft)
+    }
+
+    /// Stops browsing, the recalled entry becomes the input being typed
+    pub fn stop_browsing(&mut self, room: &str) {
+        if let Some(history) = self.rooms.get_mut(room) {
+            history.index = None;
+            history.draft.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browsing_restores_the_draft() {
+        let mut history = InputHistory::default();
+        history.push("general", "first");
+        history.push("general", "second");
+
+        assert_eq!(history.previous("general", "draft"), Some("second"));
+        assert_eq!(history.previous("general", "second"), Some("first"));
+        assert_eq!(history.previous("general", "first"), Some("first"));
+        assert_eq!(history.next("general"), Some("second"));
+        assert_eq!(history.next("general"), Some("draft"));
+        assert_eq!(history.next("general"), None);
+    }
+
+    #[test]
+    fn test_history_is_kept_per_room() {
+        let mut history = InputHistory::default();
+        history.push("general", "hello");
+        history.push("general", "hello");
+        history.push("random", "hey");
+
+        assert_eq!(history.previous("general", ""), Some("hello"));
+        assert_eq!(history.previous("general", "hello"), Some("hello"));
+        assert_eq!(history.previous("rust", ""), None);
+    }
+
+    #[test]
+    fn test_oldest_entries_are_forgotten() {
+        let mut history = InputHistory::default();
+        for idx in 0..=MAX_INPUT_HISTORY {
+            history.push("general", &idx.to_string());
+        }
+
+        let mut oldest = None;
+        for _ in 0..=MAX_INPUT_HISTORY {
+            oldest = history.previous("general", "").map(String::from);
+        }
+
+        assert_eq!(oldest.as_deref(), Some("1"));
+    }
+}
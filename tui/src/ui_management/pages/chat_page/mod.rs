@@ -1,6 +1,10 @@
 #[allow(clippy::module_inception)]
 mod chat_page;
+mod completion;
 mod components;
+mod fuzzy;
+mod input_history;
 mod section;
+mod slash_commands;
 
 pub use chat_page::ChatPage;
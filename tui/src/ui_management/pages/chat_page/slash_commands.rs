@@ -0,0 +1,932 @@
+use chrono::NaiveDate;
+use comms::event::{RoomRole, SpaceRole};
+
+use super::components::date_picker::start_of_day_timestamp;
+use crate::state_store::action::Action;
+
+/// Messages starting with the prefix are commands, unless it is doubled to send the message as it is
+const COMMAND_PREFIX: char = '/';
+const HELP_COMMAND: &str = "help";
+/// How long `/mute` mutes a user when no duration is given
+const DEFAULT_MUTE_MINUTES: u64 = 10;
+
+/// [SlashCommand] is a command typed into the message input, as in `/name args`
+#[derive(Debug, Clone)]
+pub struct SlashCommand {
+    pub name: &'static str,
+    /// The arguments of the command as shown to the user, such as `<user> <message>`
+    pub args: &'static str,
+    pub description: &'static str,
+    /// The least role in the active room allowed to use the command, it is hidden from the others
+    pub role: RoomRole,
+    /// Parses the arguments following the name, returns None if they are invalid
+    pub parse: fn(&str) -> Option<Action>,
+}
+
+impl SlashCommand {
+    /// The command as shown to the user, with its arguments
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("{}{}", COMMAND_PREFIX, self.name)
+        } else {
+            format!("{}{} {}", COMMAND_PREFIX, self.name, self.args)
+        }
+    }
+}
+
+/// The outcome of submitting the text of the message input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submission {
+    /// A message to send, with the doubled prefix of an escaped message removed
+    Message(String),
+    /// A command whose arguments could be parsed into an action
+    Command(Action),
+    /// A known command with invalid arguments, the usage is shown so the user can fix them
+    InvalidArgs {
+        usage: String,
+    },
+    UnknownCommand {
+        name: String,
+    },
+    /// A known command the role of the user in the active room does not allow
+    NotAllowed {
+        name: String,
+        role: RoomRole,
+    },
+}
+
+/// [SlashCommandRegistry] holds the commands which can be typed into the message input
+///
+/// Each command parses its own arguments into an [Action], hence a command is added by registering it,
+/// without changes to the input itself.
+#[derive(Debug, Clone)]
+pub struct SlashCommandRegistry {
+    commands: Vec<SlashCommand>,
+}
+
+impl Default for SlashCommandRegistry {
+    fn default() -> Self {
+        let mut registry = SlashCommandRegistry { commands: vec![] };
+
+        registry
+            .register(SlashCommand {
+                name: "join",
+                args: "<room>",
+                description: "to join a room, including the private ones",
+                role: RoomRole::Member,
+                parse: parse_join,
+            })
+            .register(SlashCommand {
+                name: "leave",
+                args: "[room]",
+                description: "to leave a room, the active one without a name",
+                role: RoomRole::Member,
+                parse: parse_leave,
+            })
+            .register(SlashCommand {
+                name: "create",
+                args: "<room> <description>",
+                description: "to create a room",
+                role: RoomRole::Member,
+                parse: parse_create,
+            })
+            .register(SlashCommand {
+                name: "delete-room",
+                args: "",
+                description: "to delete the active room, if you created it",
+                role: RoomRole::Owner,
+                parse: |args| args.trim().is_empty().then_some(Action::DeleteRoom),
+            })
+            .register(SlashCommand {
+                name: "topic",
+                args: "<topic>",
+                description: "to change the topic of the room, as its owner or a moderator",
+                role: RoomRole::Moderator,
+                parse: |args| {
+                    (!args.trim().is_empty()).then(|| Action::SetRoomTopic {
+                        topic: String::from(args.trim()),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "delete",
+                args: "",
+                description: "to delete your last message in the room",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::DeleteLastMessage),
+            })
+            .register(SlashCommand {
+                name: "goto",
+                args: "YYYY-MM-DD",
+                description: "to jump to a date",
+                role: RoomRole::Member,
+                parse: parse_goto,
+            })
+            .register(SlashCommand {
+                name: "search",
+                args: "<query>",
+                description: "to search the history of the room",
+                role: RoomRole::Member,
+                parse: |args| {
+                    let query = args.trim();
+
+                    (!query.is_empty()).then(|| Action::SearchHistory {
+                        query: String::from(query),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "highlight",
+                args: "add|list|remove",
+                description: "to manage highlight words",
+                role: RoomRole::Member,
+                parse: parse_highlight,
+            })
+            .register(SlashCommand {
+                name: "fav",
+                args: "[room]",
+                description: "to list a room ahead of the others, or not anymore",
+                role: RoomRole::Member,
+                parse: parse_favorite,
+            })
+            .register(SlashCommand {
+                name: "sort",
+                args: "",
+                description: "to list the rooms by activity or by name",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ToggleRoomSort),
+            })
+            .register(SlashCommand {
+                name: "ignore",
+                args: "<user>",
+                description: "to hide the messages of a user and drop their direct messages",
+                role: RoomRole::Member,
+                parse: |args| parse_user(args).map(|user_id| Action::IgnoreUser { user_id }),
+            })
+            .register(SlashCommand {
+                name: "unignore",
+                args: "<user>",
+                description: "to show the messages of an ignored user again",
+                role: RoomRole::Member,
+                parse: |args| parse_user(args).map(|user_id| Action::UnignoreUser { user_id }),
+            })
+            .register(SlashCommand {
+                name: "ignored",
+                args: "",
+                description: "to list the ignored users",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ListIgnoredUsers),
+            })
+            .register(SlashCommand {
+                name: "dm",
+                args: "<user> <message>",
+                description: "to message a user directly",
+                role: RoomRole::Member,
+                parse: parse_direct_message,
+            })
+            .register(SlashCommand {
+                name: "invite",
+                args: "<user>",
+                description: "to invite a user to this private room",
+                role: RoomRole::Member,
+                parse: parse_invite,
+            })
+            .register(SlashCommand {
+                name: "mod",
+                args: "<user>",
+                description: "to make a user a moderator of the room, as its owner",
+                role: RoomRole::Owner,
+                parse: |args| {
+                    parse_user(args).map(|user_id| Action::SetRoomRole {
+                        user_id,
+                        role: RoomRole::Moderator,
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "unmod",
+                args: "<user>",
+                description: "to make a moderator a member of the room again, as its owner",
+                role: RoomRole::Owner,
+                parse: |args| {
+                    parse_user(args).map(|user_id| Action::SetRoomRole {
+                        user_id,
+                        role: RoomRole::Member,
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "kick",
+                args: "<user>",
+                description: "to kick a user out of the room, as a moderator",
+                role: RoomRole::Moderator,
+                parse: |args| parse_user(args).map(|user_id| Action::KickUser { user_id }),
+            })
+            .register(SlashCommand {
+                name: "ban",
+                args: "<user>",
+                description: "to ban a user from the room, as a moderator",
+                role: RoomRole::Moderator,
+                parse: |args| parse_user(args).map(|user_id| Action::BanUser { user_id }),
+            })
+            .register(SlashCommand {
+                name: "mute",
+                args: "<user> [minutes]",
+                description: "to mute a user in the room, 0 minutes unmutes them",
+                role: RoomRole::Moderator,
+                parse: parse_mute,
+            })
+            .register(SlashCommand {
+                name: "space",
+                args: "join|leave|members|promote|demote|kick <space> [user]",
+                description: "to manage your spaces, or their members as an admin",
+                role: RoomRole::Member,
+                parse: parse_space,
+            })
+            .register(SlashCommand {
+                name: "away",
+                args: "<message>",
+                description: "to show the other users you are away, with a status message",
+                role: RoomRole::Member,
+                parse: |args| {
+                    (!args.trim().is_empty()).then(|| Action::SetAwayMessage {
+                        away_message: Some(String::from(args.trim())),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "back",
+                args: "",
+                description: "to show the other users you are back",
+                role: RoomRole::Member,
+                parse: |args| {
+                    args.trim()
+                        .is_empty()
+                        .then_some(Action::SetAwayMessage { away_message: None })
+                },
+            })
+            .register(SlashCommand {
+                name: "nick",
+                args: "[name]",
+                description: "to change the name you are shown with, or go back to your id",
+                role: RoomRole::Member,
+                parse: |args| {
+                    Some(Action::ChangeNickname {
+                        nickname: Some(String::from(args.trim())).filter(|name| !name.is_empty()),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "profile",
+                args: "<user> | bio|pronouns|timezone|color [value]",
+                description: "to look at the profile of a user, or change a field of yours",
+                role: RoomRole::Member,
+                parse: parse_profile,
+            })
+            .register(SlashCommand {
+                name: "send-file",
+                args: "<path>",
+                description: "to share a file with the room",
+                role: RoomRole::Member,
+                parse: |args| {
+                    let path = args.trim();
+
+                    (!path.is_empty()).then(|| Action::SendFile {
+                        path: String::from(path),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "save",
+                args: "<id> <path>",
+                description: "to save a file shared with the room",
+                role: RoomRole::Member,
+                parse: parse_save,
+            })
+            .register(SlashCommand {
+                name: "export-room",
+                args: "",
+                description: "to export the room history",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ExportRoomHistory),
+            })
+            .register(SlashCommand {
+                name: "export",
+                args: "[#room|@user] [path]",
+                description: "to save the loaded messages to a markdown file",
+                role: RoomRole::Member,
+                parse: parse_export,
+            })
+            .register(SlashCommand {
+                name: "export-my-data",
+                args: "",
+                description: "to download your data",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ExportMyData),
+            })
+            .register(SlashCommand {
+                name: "delete-my-account",
+                args: "",
+                description: "to delete your account",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::DeleteMyAccount),
+            })
+            .register(SlashCommand {
+                name: "theme",
+                args: "<name>",
+                description: "to switch to the dark, light or high-contrast theme",
+                role: RoomRole::Member,
+                parse: |args| {
+                    let name = args.trim();
+
+                    (!name.is_empty() && !name.contains(' ')).then(|| Action::SetTheme {
+                        name: String::from(name),
+                    })
+                },
+            })
+            .register(SlashCommand {
+                name: "dnd",
+                args: "",
+                description: "to hold back the desktop notifications, or let them through again",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ToggleDoNotDisturb),
+            })
+            .register(SlashCommand {
+                name: "keys",
+                args: "",
+                description: "to list the key bindings and the commands",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::ShowHelp),
+            })
+            .register(SlashCommand {
+                name: "quit",
+                args: "",
+                description: "to quit the application",
+                role: RoomRole::Member,
+                parse: |args| args.trim().is_empty().then_some(Action::Exit),
+            });
+
+        registry
+    }
+}
+
+impl SlashCommandRegistry {
+    /// Adds a command, replacing the one with the same name
+    pub fn register(&mut self, command: SlashCommand) -> &mut Self {
+        self.commands
+            .retain(|registered| registered.name != command.name);
+        self.commands.push(command);
+        self
+    }
+
+    /// The names of the commands the role allows, `help` included
+    pub fn names(&self, role: RoomRole) -> Vec<&'static str> {
+        self.commands
+            .iter()
+            .filter(|command| command.role <= role)
+            .map(|command| command.name)
+            .chain(std::iter::once(HELP_COMMAND))
+            .collect()
+    }
+
+    /// The usage and description of each command the role allows, `/help` included, in the order they are registered
+    pub fn help_lines(&self, role: RoomRole) -> Vec<(String, String)> {
+        self.commands
+            .iter()
+            .filter(|command| command.role <= role)
+            .map(|command| (command.usage(), String::from(command.description)))
+            .chain(std::iter::once((
+                format!("{}{}", COMMAND_PREFIX, HELP_COMMAND),
+                String::from("to list the commands"),
+            )))
+            .collect()
+    }
+
+    /// Decides whether the text is a message or a command, and parses the command into its action
+    /// if the role of the user in the active room allows it
+    pub fn parse(&self, text: &str, role: RoomRole) -> Submission {
+        let Some(command) = text.strip_prefix(COMMAND_PREFIX) else {
+            return Submission::Message(String::from(text));
+        };
+
+        // a doubled prefix sends the rest as a message starting with the prefix
+        if command.starts_with(COMMAND_PREFIX) {
+            return Submission::Message(String::from(command));
+        }
+
+        let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+
+        if name == HELP_COMMAND {
+            return Submission::Command(Action::ShowCommandHelp {
+                lines: self
+                    .help_lines(role)
+                    .into_iter()
+                    .map(|(usage, description)| format!("{} {}", usage, description))
+                    .collect(),
+            });
+        }
+
+        match self.commands.iter().find(|command| command.name == name) {
+            Some(command) if command.role > role => Submission::NotAllowed {
+                name: String::from(name),
+                role: command.role,
+            },
+            Some(command) => match (command.parse)(args) {
+                Some(action) => Submission::Command(action),
+                None => Submission::InvalidArgs {
+                    usage: command.usage(),
+                },
+            },
+            None => Submission::UnknownCommand {
+                name: String::from(name),
+            },
+        }
+    }
+}
+
+/// Parses the `/goto YYYY-MM-DD` command
+fn parse_goto(args: &str) -> Option<Action> {
+    let date = NaiveDate::parse_from_str(args.trim(), "%Y-%m-%d").ok()?;
+
+    Some(Action::JumpToDate {
+        timestamp: start_of_day_timestamp(&date)?,
+    })
+}
+
+/// Parses the `/highlight add|list|remove [word]` command
+fn parse_highlight(args: &str) -> Option<Action> {
+    match args.trim().split_once(' ') {
+        Some(("add", word)) if !word.trim().is_empty() => Some(Action::AddHighlightWord {
+            word: String::from(word.trim()),
+        }),
+        Some(("remove", word)) if !word.trim().is_empty() => Some(Action::RemoveHighlightWord {
+            word: String::from(word.trim()),
+        }),
+        None if args.trim() == "list" => Some(Action::ListHighlightWords),
+        _ => None,
+    }
+}
+
+/// Parses the `/space join|leave|members <space>` and `/space promote|demote|kick <space> <user>` commands
+fn parse_space(args: &str) -> Option<Action> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["join", space] => Some(Action::JoinSpace {
+            space: String::from(*space),
+        }),
+        ["leave", space] => Some(Action::LeaveSpace {
+            space: String::from(*space),
+        }),
+        ["members", space] => Some(Action::ListSpaceMembers {
+            space: String::from(*space),
+        }),
+        ["promote", space, user_id] => Some(Action::SetSpaceRole {
+            space: String::from(*space),
+            user_id: String::from(*user_id),
+            role: SpaceRole::Admin,
+        }),
+        ["demote", space, user_id] => Some(Action::SetSpaceRole {
+            space: String::from(*space),
+            user_id: String::from(*user_id),
+            role: SpaceRole::Member,
+        }),
+        ["kick", space, user_id] => Some(Action::RemoveSpaceMember {
+            space: String::from(*space),
+            user_id: String::from(*user_id),
+        }),
+        _ => None,
+    }
+}
+
+/// Parses the `/profile` command, which shows the profile of a user or changes a field of the user's own,
+/// a field given without a value is cleared
+fn parse_profile(args: &str) -> Option<Action> {
+    let args = args.trim();
+    let (field, value) = args.split_once(' ').unwrap_or((args, ""));
+    let value = Some(String::from(value.trim()));
+
+    match field {
+        "bio" => Some(Action::UpdateProfile {
+            bio: value,
+            pronouns: None,
+            timezone: None,
+            color: None,
+        }),
+        "pronouns" => Some(Action::UpdateProfile {
+            bio: None,
+            pronouns: value,
+            timezone: None,
+            color: None,
+        }),
+        "timezone" => Some(Action::UpdateProfile {
+            bio: None,
+            pronouns: None,
+            timezone: value,
+            color: None,
+        }),
+        "color" => Some(Action::UpdateProfile {
+            bio: None,
+            pronouns: None,
+            timezone: None,
+            color: value,
+        }),
+        _ => parse_user(args).map(|user_id| Action::GetUserProfile { user_id }),
+    }
+}
+
+/// Parses the `/dm <user> <message>` command
+fn parse_direct_message(args: &str) -> Option<Action> {
+    let (user_id, content) = args.trim_start().split_once(' ')?;
+
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    Some(Action::SendDirectMessage {
+        user_id: String::from(user_id.trim_start_matches('@')),
+        content: String::from(content.trim()),
+    })
+}
+
+/// Parses the single `<user>` argument of a command, with or without its `@`
+fn parse_user(args: &str) -> Option<String> {
+    let user_id = args.trim().trim_start_matches('@');
+
+    if user_id.is_empty() || user_id.contains(' ') {
+        return None;
+    }
+
+    Some(String::from(user_id))
+}
+
+/// Parses the `/invite <user>` command
+fn parse_invite(args: &str) -> Option<Action> {
+    parse_user(args).map(|user_id| Action::InviteUser { user_id })
+}
+
+/// Parses the `/mute <user> [minutes]` command
+fn parse_mute(args: &str) -> Option<Action> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+
+    let (user_id, minutes) = match args.as_slice() {
+        [user_id] => (*user_id, DEFAULT_MUTE_MINUTES),
+        [user_id, minutes] => (*user_id, minutes.parse().ok()?),
+        _ => return None,
+    };
+
+    Some(Action::MuteUser {
+        user_id: parse_user(user_id)?,
+        minutes,
+    })
+}
+
+/// Parses the `/create <room> <description>` command, the server validates the name
+fn parse_create(args: &str) -> Option<Action> {
+    let (room, description) = args.trim().split_once(' ')?;
+    let room = room.trim_start_matches('#');
+
+    if room.is_empty() || description.trim().is_empty() {
+        return None;
+    }
+
+    Some(Action::CreateRoom {
+        room: String::from(room),
+        description: String::from(description.trim()),
+    })
+}
+
+/// Parses the `/save <id> <path>` command, the path may contain spaces
+fn parse_save(args: &str) -> Option<Action> {
+    let (file_id, path) = args.trim().split_once(' ')?;
+
+    if path.trim().is_empty() {
+        return None;
+    }
+
+    Some(Action::SaveFile {
+        file_id: String::from(file_id),
+        path: String::from(path.trim()),
+    })
+}
+
+/// Parses the `/export [#room|@user] [path]` command, the room is told apart from the path by its `#` or `@`
+fn parse_export(args: &str) -> Option<Action> {
+    let args = args.trim();
+    let (room, path) = match args.split_once(' ') {
+        Some((room, path)) if room.starts_with(['#', '@']) => (Some(room), path.trim()),
+        _ if args.starts_with(['#', '@']) => (Some(args), ""),
+        _ => (None, args),
+    };
+    // direct messages are kept under the `@` of the user, the rooms under their bare name
+    let room = room.map(|room| room.trim_start_matches('#'));
+
+    if room.is_some_and(|room| room.is_empty() || room == "@") {
+        return None;
+    }
+
+    Some(Action::ExportChatLog {
+        room: room.map(String::from),
+        path: (!path.is_empty()).then(|| String::from(path)),
+    })
+}
+
+/// Parses the `/fav [room]` command, the active room is meant without one
+fn parse_favorite(args: &str) -> Option<Action> {
+    let room = args.trim().trim_start_matches('#');
+
+    if room.contains(' ') {
+        return None;
+    }
+
+    Some(Action::ToggleFavoriteRoom {
+        room: (!room.is_empty()).then(|| String::from(room)),
+    })
+}
+
+/// Parses the `/leave [room]` command, the active room is meant without one
+fn parse_leave(args: &str) -> Option<Action> {
+    let room = args.trim().trim_start_matches('#');
+
+    if room.contains(' ') {
+        return None;
+    }
+
+    Some(Action::LeaveRoom {
+        room: (!room.is_empty()).then(|| String::from(room)),
+    })
+}
+
+/// Parses the `/join <room>` command
+fn parse_join(args: &str) -> Option<Action> {
+    let room = args.trim().trim_start_matches('#');
+
+    if room.is_empty() || room.contains(' ') {
+        return None;
+    }
+
+    Some(Action::JoinRoom {
+        room: String::from(room),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_and_escaped_text_is_a_message() {
+        let registry = SlashCommandRegistry::default();
+
+        assert_eq!(
+            registry.parse("hello", RoomRole::Member),
+            Submission::Message(String::from("hello"))
+        );
+        assert_eq!(
+            registry.parse("//shrug", RoomRole::Member),
+            Submission::Message(String::from("/shrug"))
+        );
+    }
+
+    #[test]
+    fn test_command_is_parsed_into_its_action() {
+        let registry = SlashCommandRegistry::default();
+
+        assert_eq!(
+            registry.parse("/dm @alice see you", RoomRole::Member),
+            Submission::Command(Action::SendDirectMessage {
+                user_id: String::from("alice"),
+                content: String::from("see you"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/ignore @spammer", RoomRole::Member),
+            Submission::Command(Action::IgnoreUser {
+                user_id: String::from("spammer"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/ignored", RoomRole::Member),
+            Submission::Command(Action::ListIgnoredUsers)
+        );
+        assert_eq!(
+            registry.parse("/join #rust", RoomRole::Member),
+            Submission::Command(Action::JoinRoom {
+                room: String::from("rust"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/create #rust-jobs Rust job offers", RoomRole::Member),
+            Submission::Command(Action::CreateRoom {
+                room: String::from("rust-jobs"),
+                description: String::from("Rust job offers"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/mute @bob", RoomRole::Moderator),
+            Submission::Command(Action::MuteUser {
+                user_id: String::from("bob"),
+                minutes: 10,
+            })
+        );
+        assert_eq!(
+            registry.parse("/away out for lunch", RoomRole::Member),
+            Submission::Command(Action::SetAwayMessage {
+                away_message: Some(String::from("out for lunch")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/nick  Alice Liddell ", RoomRole::Member),
+            Submission::Command(Action::ChangeNickname {
+                nickname: Some(String::from("Alice Liddell")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/nick", RoomRole::Member),
+            Submission::Command(Action::ChangeNickname { nickname: None })
+        );
+        assert_eq!(
+            registry.parse("/profile @alice", RoomRole::Member),
+            Submission::Command(Action::GetUserProfile {
+                user_id: String::from("alice"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/profile bio  Down the rabbit hole ", RoomRole::Member),
+            Submission::Command(Action::UpdateProfile {
+                bio: Some(String::from("Down the rabbit hole")),
+                pronouns: None,
+                timezone: None,
+                color: None,
+            })
+        );
+        assert_eq!(
+            registry.parse("/profile color", RoomRole::Member),
+            Submission::Command(Action::UpdateProfile {
+                bio: None,
+                pronouns: None,
+                timezone: None,
+                color: Some(String::new()),
+            })
+        );
+        assert_eq!(
+            registry.parse("/profile", RoomRole::Member),
+            Submission::InvalidArgs {
+                usage: String::from("/profile <user> | bio|pronouns|timezone|color [value]"),
+            }
+        );
+        assert_eq!(
+            registry.parse("/theme light", RoomRole::Member),
+            Submission::Command(Action::SetTheme {
+                name: String::from("light"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/search  release notes ", RoomRole::Member),
+            Submission::Command(Action::SearchHistory {
+                query: String::from("release notes"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/send-file docs/notes.txt", RoomRole::Member),
+            Submission::Command(Action::SendFile {
+                path: String::from("docs/notes.txt"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/save f1 my notes.txt", RoomRole::Member),
+            Submission::Command(Action::SaveFile {
+                file_id: String::from("f1"),
+                path: String::from("my notes.txt"),
+            })
+        );
+        assert_eq!(
+            registry.parse("/fav", RoomRole::Member),
+            Submission::Command(Action::ToggleFavoriteRoom { room: None })
+        );
+        assert_eq!(
+            registry.parse("/fav #rust", RoomRole::Member),
+            Submission::Command(Action::ToggleFavoriteRoom {
+                room: Some(String::from("rust")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/leave #rust", RoomRole::Member),
+            Submission::Command(Action::LeaveRoom {
+                room: Some(String::from("rust")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/sort", RoomRole::Member),
+            Submission::Command(Action::ToggleRoomSort)
+        );
+        assert_eq!(
+            registry.parse("/export", RoomRole::Member),
+            Submission::Command(Action::ExportChatLog {
+                room: None,
+                path: None,
+            })
+        );
+        assert_eq!(
+            registry.parse("/export #rust logs/rust log.md", RoomRole::Member),
+            Submission::Command(Action::ExportChatLog {
+                room: Some(String::from("rust")),
+                path: Some(String::from("logs/rust log.md")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/export @alice", RoomRole::Member),
+            Submission::Command(Action::ExportChatLog {
+                room: Some(String::from("@alice")),
+                path: None,
+            })
+        );
+        assert_eq!(
+            registry.parse("/export notes.md", RoomRole::Member),
+            Submission::Command(Action::ExportChatLog {
+                room: None,
+                path: Some(String::from("notes.md")),
+            })
+        );
+        assert_eq!(
+            registry.parse("/dnd", RoomRole::Member),
+            Submission::Command(Action::ToggleDoNotDisturb)
+        );
+        assert_eq!(
+            registry.parse("/keys", RoomRole::Member),
+            Submission::Command(Action::ShowHelp)
+        );
+        assert_eq!(
+            registry.parse("/quit", RoomRole::Member),
+            Submission::Command(Action::Exit)
+        );
+    }
+
+    #[test]
+    fn test_invalid_and_unknown_commands_are_reported() {
+        let registry = SlashCommandRegistry::default();
+
+        assert_eq!(
+            registry.parse("/invite", RoomRole::Member),
+            Submission::InvalidArgs {
+                usage: String::from("/invite <user>")
+            }
+        );
+        assert_eq!(
+            registry.parse("/mute bob soon", RoomRole::Moderator),
+            Submission::InvalidArgs {
+                usage: String::from("/mute <user> [minutes]")
+            }
+        );
+        assert_eq!(
+            registry.parse("/nope now", RoomRole::Member),
+            Submission::UnknownCommand {
+                name: String::from("nope")
+            }
+        );
+    }
+
+    #[test]
+    fn test_commands_are_limited_by_role() {
+        let registry = SlashCommandRegistry::default();
+
+        assert_eq!(
+            registry.parse("/kick bob", RoomRole::Member),
+            Submission::NotAllowed {
+                name: String::from("kick"),
+                role: RoomRole::Moderator,
+            }
+        );
+        assert_eq!(
+            registry.parse("/kick bob", RoomRole::Owner),
+            Submission::Command(Action::KickUser {
+                user_id: String::from("bob"),
+            })
+        );
+        assert!(!registry.names(RoomRole::Member).contains(&"kick"));
+        assert!(registry.names(RoomRole::Moderator).contains(&"kick"));
+        assert!(!registry.names(RoomRole::Moderator).contains(&"delete-room"));
+    }
+
+    #[test]
+    fn test_registered_command_is_parsed_and_listed() {
+        let mut registry = SlashCommandRegistry::default();
+
+        registry.register(SlashCommand {
+            name: "latest",
+            args: "",
+            description: "to return to the latest messages",
+            role: RoomRole::Member,
+            parse: |_| Some(Action::ReturnToLatest),
+        });
+
+        assert_eq!(
+            registry.parse("/latest", RoomRole::Member),
+            Submission::Command(Action::ReturnToLatest)
+        );
+        assert!(registry.help_lines(RoomRole::Member).contains(&(
+            String::from("/latest"),
+            String::from("to return to the latest messages")
+        )));
+    }
+}
@@ -0,0 +1,210 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{prelude::*, widgets::*, Frame};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::{ConfigChange, ConfigMigration, CONFIG_VERSION};
+use crate::state_store::{action::Action, State};
+use crate::theme::Theme;
+
+use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::layout;
+
+struct Props {
+    migration: Option<ConfigMigration>,
+    error_message: Option<String>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        Props {
+            migration: state.config_migration.clone(),
+            error_message: state.config_migration_error.clone(),
+            theme: state.theme,
+        }
+    }
+}
+
+/// ConfigMigrationPage shows the changes needed to upgrade the config file of the user,
+/// and writes the upgraded file once they are accepted
+pub struct ConfigMigrationPage {
+    /// Action sender
+    pub action_tx: UnboundedSender<Action>,
+    // Mapped Props from State
+    props: Props,
+}
+
+fn change_line<'a>(change: &ConfigChange, theme: &Theme) -> Line<'a> {
+    match change {
+        ConfigChange::Added { key, value } => Line::from(vec![
+            Span::from("+ ").fg(theme.success),
+            Span::from(format!("{} = {}", key, value)).bold(),
+            Span::from("  new setting").dim(),
+        ]),
+        ConfigChange::Reset {
+            key,
+            previous,
+            value,
+        } => Line::from(vec![
+            Span::from("~ ").fg(theme.warning),
+            Span::from(format!("{} = {} → {}", key, previous, value)).bold(),
+            Span::from("  invalid value, reset to the default").dim(),
+        ]),
+        ConfigChange::Removed { key, previous } => Line::from(vec![
+            Span::from("- ").fg(theme.error),
+            Span::from(format!("{} = {}", key, previous)).crossed_out(),
+            Span::from("  no longer used").dim(),
+        ]),
+    }
+}
+
+impl Component for ConfigMigrationPage {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
+    where
+        Self: Sized,
+    {
+        ConfigMigrationPage {
+            action_tx,
+            props: Props::from(state),
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        ConfigMigrationPage {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Config Migration Page"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Enter => {
+                let _ = self.action_tx.send(Action::ApplyConfigMigration);
+            }
+            KeyCode::Char('q') => {
+                let _ = self.action_tx.send(Action::Exit);
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let _ = self.action_tx.send(Action::Exit);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ComponentRender<()> for ConfigMigrationPage {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
+        let Some(migration) = self.props.migration.as_ref() else {
+            return;
+        };
+
+        let [_, horizontal_centered, _] = layout::split(
+            frame.size(),
+            Direction::Horizontal,
+            [
+                Constraint::Ratio(1, 6),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 6),
+            ],
+        );
+
+        let [container_changes, container_help_text, container_error_message] = layout::split(
+            horizontal_centered,
+            Direction::Vertical,
+            [
+                Constraint::Min(5),
+                Constraint::Length(2),
+                Constraint::Length(3),
+            ],
+        );
+
+        let mut lines = vec![
+            Line::from(format!(
+                "Your config file was written for version {}, the current version is {}.",
+                migration.from_version, CONFIG_VERSION
+            )),
+            Line::from(""),
+        ];
+        if migration.changes.is_empty() {
+            lines.push(Line::from(
+                Span::from("Only the version of the file will be updated.").italic(),
+            ));
+        }
+        lines.extend(
+            migration
+                .changes
+                .iter()
+                .map(|change| change_line(change, &self.props.theme)),
+        );
+
+        let changes = Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.props.theme.active_border))
+                    .title("Upgrade Config"),
+            );
+        frame.render_widget(changes, container_changes);
+
+        let help_text = Paragraph::new(Text::from(Line::from(vec![
+            "Press ".into(),
+            "<Enter>".bold(),
+            " to write the upgraded config, ".into(),
+            "<q>".bold(),
+            " to quit without changes".into(),
+        ])));
+        frame.render_widget(help_text, container_help_text);
+
+        let error_message = Paragraph::new(if let Some(err) = self.props.error_message.as_ref() {
+            Text::from(format!("Error: {}", err.as_str()))
+        } else {
+            Text::from("")
+        })
+        .wrap(Wrap { trim: true })
+        .style(
+            Style::default()
+                .fg(self.props.theme.error)
+                .add_modifier(Modifier::ITALIC),
+        );
+        frame.render_widget(error_message, container_error_message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientConfig;
+    use crate::ui_management::test_harness::TestHarness;
+
+    #[test]
+    fn test_applies_the_migration_or_exits() {
+        let state = State {
+            config_migration: Some(ConfigMigration {
+                from_version: 0,
+                changes: vec![],
+                upgraded: ClientConfig::default(),
+            }),
+            ..State::default()
+        };
+        let mut harness = TestHarness::<ConfigMigrationPage>::new(&state);
+
+        harness.press(KeyCode::Enter).press(KeyCode::Char('q'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::ApplyConfigMigration, Action::Exit]
+        );
+    }
+}
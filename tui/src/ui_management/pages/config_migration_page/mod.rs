@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod config_migration_page;
+
+pub use config_migration_page::ConfigMigrationPage;
@@ -4,12 +4,15 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::state_store::ServerConnectionStatus;
 use crate::state_store::{action::Action, State};
+use crate::theme::Theme;
 
 use crate::ui_management::components::input_box;
 use crate::ui_management::components::{input_box::InputBox, Component, ComponentRender};
+use crate::ui_management::layout;
 
 struct Props {
     error_message: Option<String>,
+    theme: Theme,
 }
 
 impl From<&State> for Props {
@@ -22,6 +25,7 @@ impl From<&State> for Props {
             } else {
                 None
             },
+            theme: state.theme,
         }
     }
 }
@@ -48,15 +52,13 @@ impl ConnectPage {
     }
 }
 
-const DEFAULT_SERVER_ADDR: &str = "localhost:8080";
-
 impl Component for ConnectPage {
     fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
     where
         Self: Sized,
     {
         let mut input_box = InputBox::new(state, action_tx.clone());
-        input_box.set_text(DEFAULT_SERVER_ADDR);
+        input_box.set_text(&state.default_server_addr);
 
         ConnectPage {
             action_tx: action_tx.clone(),
@@ -106,67 +108,56 @@ impl Component for ConnectPage {
 
 impl ComponentRender<()> for ConnectPage {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
-        let [_, vertical_centered, _] = *Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(
-                [
-                    Constraint::Ratio(1, 3),
-                    Constraint::Min(1),
-                    Constraint::Ratio(1, 3),
-                ]
-                .as_ref(),
-            )
-            .split(frame.size())
-        else {
-            panic!("The main layout should have 3 chunks")
-        };
-
-        let [_, both_centered, _] = *Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(
-                [
-                    Constraint::Ratio(1, 3),
-                    Constraint::Min(1),
-                    Constraint::Ratio(1, 3),
-                ]
-                .as_ref(),
-            )
-            .split(vertical_centered)
-        else {
-            panic!("The horizontal layout should have 3 chunks")
-        };
-
-        let [container_addr_input, container_help_text, container_error_message] =
-            *Layout::default()
-                .direction(Direction::Vertical)
-                .constraints(
-                    [
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                        Constraint::Min(1),
-                    ]
-                    .as_ref(),
-                )
-                .split(both_centered)
-        else {
-            panic!("The left layout should have 3 chunks")
-        };
+        let [_, vertical_centered, _] = layout::split(
+            frame.size(),
+            Direction::Vertical,
+            [
+                Constraint::Ratio(1, 3),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 3),
+            ],
+        );
+
+        let [_, both_centered, _] = layout::split(
+            vertical_centered,
+            Direction::Horizontal,
+            [
+                Constraint::Ratio(1, 3),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 3),
+            ],
+        );
+
+        let [container_addr_input, container_help_text, container_error_message] = layout::split(
+            both_centered,
+            Direction::Vertical,
+            [
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(1),
+            ],
+        );
 
         self.input_box.render(
             frame,
             input_box::RenderProps {
                 title: "Server Host and Port".into(),
                 area: container_addr_input,
-                border_color: Color::Yellow,
+                border_color: self.props.theme.active_border,
+                text_color: self.props.theme.input,
                 show_cursor: true,
+                counter: None,
             },
         );
 
-        let help_text = Paragraph::new(Text::from(Line::from(vec![
-            "Press ".into(),
-            "<Enter>".bold(),
-            " to connect".into(),
-        ])));
+        let help_text = Paragraph::new(Text::from(vec![
+            Line::from(vec![
+                "Press ".into(),
+                "<Enter>".bold(),
+                " to connect".into(),
+            ]),
+            Line::from(Span::from("Prefix the address with tls:// to connect over TLS").dim()),
+        ]));
         frame.render_widget(help_text, container_help_text);
 
         let error_message = Paragraph::new(if let Some(err) = self.props.error_message.as_ref() {
@@ -177,10 +168,43 @@ impl ComponentRender<()> for ConnectPage {
         .wrap(Wrap { trim: true })
         .style(
             Style::default()
-                .fg(Color::Red)
+                .fg(self.props.theme.error)
                 .add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC),
         );
 
         frame.render_widget(error_message, container_error_message);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    #[test]
+    fn test_connects_to_the_typed_address() {
+        let mut harness = TestHarness::<ConnectPage>::new(&State::default());
+
+        harness.press(KeyCode::Enter);
+        for _ in 0.."8080".len() {
+            harness.press(KeyCode::Backspace);
+        }
+        harness
+            .type_text("9000")
+            .press(KeyCode::Enter)
+            .press_with_modifiers(KeyCode::Char('c'), KeyModifiers::CONTROL);
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![
+                Action::ConnectToServerRequest {
+                    addr: "localhost:8080".into()
+                },
+                Action::ConnectToServerRequest {
+                    addr: "localhost:9000".into()
+                },
+                Action::Exit,
+            ]
+        );
+    }
+}
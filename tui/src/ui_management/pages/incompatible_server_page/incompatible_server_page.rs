@@ -0,0 +1,168 @@
+use comms::protocol::PROTOCOL_VERSION;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{prelude::*, widgets::*, Frame};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::state_store::{action::Action, ServerConnectionStatus, State};
+use crate::theme::Theme;
+
+use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::layout;
+
+struct Props {
+    /// The address of the server and the least protocol version it serves
+    incompatible_server: Option<(String, u16)>,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        Props {
+            incompatible_server: match &state.server_connection_status {
+                ServerConnectionStatus::Incompatible {
+                    addr,
+                    min_protocol_version,
+                } => Some((addr.clone(), *min_protocol_version)),
+                _ => None,
+            },
+            theme: state.theme,
+        }
+    }
+}
+
+/// IncompatibleServerPage tells the user the server requires a newer client,
+/// since there is no point in reconnecting to it
+pub struct IncompatibleServerPage {
+    /// Action sender
+    pub action_tx: UnboundedSender<Action>,
+    // Mapped Props from State
+    props: Props,
+}
+
+impl Component for IncompatibleServerPage {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
+    where
+        Self: Sized,
+    {
+        IncompatibleServerPage {
+            action_tx,
+            props: Props::from(state),
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        IncompatibleServerPage {
+            props: Props::from(state),
+            ..self
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Incompatible Server Page"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Enter => {
+                let _ = self.action_tx.send(Action::DismissIncompatibleServer);
+            }
+            KeyCode::Char('q') => {
+                let _ = self.action_tx.send(Action::Exit);
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let _ = self.action_tx.send(Action::Exit);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ComponentRender<()> for IncompatibleServerPage {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
+        let Some((addr, min_protocol_version)) = self.props.incompatible_server.as_ref() else {
+            return;
+        };
+
+        let [_, horizontal_centered, _] = layout::split(
+            frame.size(),
+            Direction::Horizontal,
+            [
+                Constraint::Ratio(1, 6),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 6),
+            ],
+        );
+
+        let [_, container_explanation, container_help_text, _] = layout::split(
+            horizontal_centered,
+            Direction::Vertical,
+            [
+                Constraint::Ratio(1, 3),
+                Constraint::Length(6),
+                Constraint::Length(2),
+                Constraint::Ratio(1, 3),
+            ],
+        );
+
+        let explanation = Paragraph::new(Text::from(vec![
+            Line::from(vec![
+                Span::from(addr.as_str()).bold(),
+                Span::from(" requires a newer client."),
+            ]),
+            Line::from(""),
+            Line::from(format!(
+                "It serves protocol version {} or later, this client speaks version {}. Update the client to connect to it.",
+                min_protocol_version, PROTOCOL_VERSION
+            )),
+        ]))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(self.props.theme.error))
+                .title("Incompatible Server"),
+        );
+        frame.render_widget(explanation, container_explanation);
+
+        let help_text = Paragraph::new(Text::from(Line::from(vec![
+            "Press ".into(),
+            "<Enter>".bold(),
+            " to connect to another server, ".into(),
+            "<q>".bold(),
+            " to quit".into(),
+        ])));
+        frame.render_widget(help_text, container_help_text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    #[test]
+    fn test_goes_back_to_connect_or_exits() {
+        let state = State {
+            server_connection_status: ServerConnectionStatus::Incompatible {
+                addr: "localhost:8080".into(),
+                min_protocol_version: 5,
+            },
+            ..State::default()
+        };
+        let mut harness = TestHarness::<IncompatibleServerPage>::new(&state);
+
+        harness.press(KeyCode::Enter).press(KeyCode::Char('q'));
+
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::DismissIncompatibleServer, Action::Exit]
+        );
+    }
+}
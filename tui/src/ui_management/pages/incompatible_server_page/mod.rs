@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod incompatible_server_page;
+
+pub use incompatible_server_page::IncompatibleServerPage;
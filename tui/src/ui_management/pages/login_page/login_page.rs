@@ -0,0 +1,293 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{prelude::*, widgets::*, Frame};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::state_store::{action::Action, LoginStatus, ServerConnectionStatus, State};
+use crate::theme::Theme;
+
+use crate::ui_management::components::input_box;
+use crate::ui_management::components::{input_box::InputBox, Component, ComponentRender};
+use crate::ui_management::layout;
+
+struct Props {
+    /// The address of the server to log in on
+    addr: String,
+    login_status: LoginStatus,
+    theme: Theme,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        Props {
+            addr: if let ServerConnectionStatus::Connected { addr } =
+                &state.server_connection_status
+            {
+                addr.clone()
+            } else {
+                String::new()
+            },
+            login_status: state.login_status.clone(),
+            theme: state.theme,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Username,
+    Password,
+}
+
+/// LoginPage asks for the credentials of the user once connected to the server
+pub struct LoginPage {
+    /// Action sender
+    pub action_tx: UnboundedSender<Action>,
+    // Mapped Props from State
+    props: Props,
+    // Internal State
+    /// The field receiving the typed keys
+    focused_field: Field,
+    // Internal Components
+    username_input_box: InputBox,
+    password_input_box: InputBox,
+}
+
+impl LoginPage {
+    fn focused_input_box(&mut self) -> &mut InputBox {
+        match self.focused_field {
+            Field::Username => &mut self.username_input_box,
+            Field::Password => &mut self.password_input_box,
+        }
+    }
+
+    fn toggle_focus(&mut self) {
+        self.focused_field = match self.focused_field {
+            Field::Username => Field::Password,
+            Field::Password => Field::Username,
+        };
+    }
+
+    /// Sends the credentials once both are filled in, otherwise focuses the empty field
+    fn submit(&mut self) {
+        if self.props.login_status == LoginStatus::LoggingIn {
+            return;
+        }
+
+        if self.username_input_box.is_empty() {
+            self.focused_field = Field::Username;
+        } else if self.password_input_box.is_empty() {
+            self.focused_field = Field::Password;
+        } else {
+            let _ = self.action_tx.send(Action::Login {
+                username: String::from(self.username_input_box.text()),
+                password: String::from(self.password_input_box.text()),
+            });
+        }
+    }
+}
+
+impl Component for LoginPage {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self
+    where
+        Self: Sized,
+    {
+        let mut password_input_box = InputBox::new(state, action_tx.clone());
+        password_input_box.set_masked(true);
+
+        LoginPage {
+            action_tx: action_tx.clone(),
+            //
+            props: Props::from(state),
+            //
+            focused_field: Field::Username,
+            //
+            username_input_box: InputBox::new(state, action_tx),
+            password_input_box,
+        }
+        .move_with_state(state)
+    }
+
+    fn move_with_state(mut self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        let props = Props::from(state);
+
+        // a rejected password is cleared, so it can be typed again
+        if self.props.login_status == LoginStatus::LoggingIn
+            && matches!(props.login_status, LoginStatus::Rejected { .. })
+        {
+            self.password_input_box.reset();
+            self.focused_field = Field::Password;
+        }
+
+        LoginPage { props, ..self }
+    }
+
+    fn name(&self) -> &str {
+        "Login Page"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Enter => self.submit(),
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => self.toggle_focus(),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let _ = self.action_tx.send(Action::Exit);
+            }
+            _ => self.focused_input_box().handle_key_event(key),
+        }
+    }
+}
+
+impl ComponentRender<()> for LoginPage {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
+        let [_, vertical_centered, _] = layout::split(
+            frame.size(),
+            Direction::Vertical,
+            [
+                Constraint::Ratio(1, 4),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 4),
+            ],
+        );
+
+        let [_, both_centered, _] = layout::split(
+            vertical_centered,
+            Direction::Horizontal,
+            [
+                Constraint::Ratio(1, 3),
+                Constraint::Min(1),
+                Constraint::Ratio(1, 3),
+            ],
+        );
+
+        let [container_username_input, container_password_input, container_help_text, container_status] =
+            layout::split(
+                both_centered,
+                Direction::Vertical,
+                [
+                    Constraint::Length(3),
+                    Constraint::Length(3),
+                    Constraint::Length(4),
+                    Constraint::Min(1),
+                ],
+            );
+
+        for (input_box, title, area, field) in [
+            (
+                &self.username_input_box,
+                format!("Username on {}", self.props.addr),
+                container_username_input,
+                Field::Username,
+            ),
+            (
+                &self.password_input_box,
+                String::from("Password"),
+                container_password_input,
+                Field::Password,
+            ),
+        ] {
+            let is_focused = self.focused_field == field;
+
+            input_box.render(
+                frame,
+                input_box::RenderProps {
+                    title,
+                    area,
+                    border_color: if is_focused {
+                        self.props.theme.active_border
+                    } else {
+                        Color::Reset
+                    },
+                    text_color: self.props.theme.input,
+                    show_cursor: is_focused,
+                    counter: None,
+                },
+            );
+        }
+
+        let help_text = Paragraph::new(Text::from(vec![
+            Line::from(vec![
+                "Press ".into(),
+                "<Enter>".bold(),
+                " to log in, ".into(),
+                "<Tab>".bold(),
+                " to switch fields".into(),
+            ]),
+            Line::from(
+                Span::from("A username nobody has taken yet is registered with the password").dim(),
+            ),
+        ]))
+        .wrap(Wrap { trim: true });
+        frame.render_widget(help_text, container_help_text);
+
+        let status = match &self.props.login_status {
+            LoginStatus::LoggingIn => Paragraph::new("Logging in..."),
+            LoginStatus::Rejected { reason } => Paragraph::new(format!("Error: {}", reason)).style(
+                Style::default()
+                    .fg(self.props.theme.error)
+                    .add_modifier(Modifier::ITALIC),
+            ),
+            LoginStatus::LoggedOut | LoginStatus::LoggedIn => Paragraph::new(""),
+        };
+        frame.render_widget(status.wrap(Wrap { trim: true }), container_status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn connected_state() -> State {
+        State {
+            server_connection_status: ServerConnectionStatus::Connected {
+                addr: "localhost:8080".into(),
+            },
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn test_sends_the_credentials_once_both_are_filled_in() {
+        let mut harness = TestHarness::<LoginPage>::new(&connected_state());
+
+        // Enter moves on to the empty field, rather than sending the credentials
+        harness
+            .press(KeyCode::Enter)
+            .type_text("alice")
+            .press(KeyCode::Enter);
+        assert!(harness.drain_actions().is_empty());
+        assert_eq!(harness.component().focused_field, Field::Password);
+
+        harness.type_text("password").press(KeyCode::Enter);
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::Login {
+                username: "alice".into(),
+                password: "password".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_does_not_send_the_credentials_again_while_logging_in() {
+        let mut harness = TestHarness::<LoginPage>::new(&connected_state());
+        harness
+            .type_text("alice")
+            .press(KeyCode::Tab)
+            .type_text("password")
+            .apply_state(&State {
+                login_status: LoginStatus::LoggingIn,
+                ..connected_state()
+            })
+            .press(KeyCode::Enter);
+
+        assert!(harness.drain_actions().is_empty());
+    }
+}
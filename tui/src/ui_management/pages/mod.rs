@@ -1,19 +1,35 @@
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyEvent, MouseEvent};
 use ratatui::{prelude::Backend, Frame};
 use tokio::sync::mpsc::UnboundedSender;
 
-use crate::state_store::{action::Action, ServerConnectionStatus, State};
+use crate::{
+    graphics::ImagePlacement,
+    state_store::{action::Action, LoginStatus, ServerConnectionStatus, State},
+};
 
-use self::{chat_page::ChatPage, connect_page::ConnectPage};
+use self::{
+    chat_page::ChatPage, config_migration_page::ConfigMigrationPage, connect_page::ConnectPage,
+    incompatible_server_page::IncompatibleServerPage, login_page::LoginPage,
+};
 
-use super::components::{Component, ComponentRender};
+use super::{
+    components::{Component, ComponentRender},
+    layout,
+};
 
 mod chat_page;
+mod config_migration_page;
 mod connect_page;
+mod incompatible_server_page;
+mod login_page;
 
+#[allow(clippy::enum_variant_names)]
 enum ActivePage {
     ChatPage,
     ConnectPage,
+    LoginPage,
+    ConfigMigrationPage,
+    IncompatibleServerPage,
 }
 
 struct Props {
@@ -24,7 +40,17 @@ impl From<&State> for Props {
     fn from(state: &State) -> Self {
         Props {
             active_page: match state.server_connection_status {
-                ServerConnectionStatus::Connected { .. } => ActivePage::ChatPage,
+                // the config needs to be upgraded before anything else
+                _ if state.config_migration.is_some() => ActivePage::ConfigMigrationPage,
+                // the chat page is kept while reconnecting, with a banner telling so
+                ServerConnectionStatus::Connected { .. }
+                | ServerConnectionStatus::Reconnecting { .. }
+                    if state.login_status == LoginStatus::LoggedIn =>
+                {
+                    ActivePage::ChatPage
+                }
+                ServerConnectionStatus::Connected { .. } => ActivePage::LoginPage,
+                ServerConnectionStatus::Incompatible { .. } => ActivePage::IncompatibleServerPage,
                 _ => ActivePage::ConnectPage,
             },
         }
@@ -36,6 +62,9 @@ pub struct AppRouter {
     //
     chat_page: ChatPage,
     connect_page: ConnectPage,
+    login_page: LoginPage,
+    config_migration_page: ConfigMigrationPage,
+    incompatible_server_page: IncompatibleServerPage,
 }
 
 impl AppRouter {
@@ -43,6 +72,9 @@ impl AppRouter {
         match self.props.active_page {
             ActivePage::ChatPage => &self.chat_page,
             ActivePage::ConnectPage => &self.connect_page,
+            ActivePage::LoginPage => &self.login_page,
+            ActivePage::ConfigMigrationPage => &self.config_migration_page,
+            ActivePage::IncompatibleServerPage => &self.incompatible_server_page,
         }
     }
 
@@ -50,6 +82,17 @@ impl AppRouter {
         match self.props.active_page {
             ActivePage::ChatPage => &mut self.chat_page,
             ActivePage::ConnectPage => &mut self.connect_page,
+            ActivePage::LoginPage => &mut self.login_page,
+            ActivePage::ConfigMigrationPage => &mut self.config_migration_page,
+            ActivePage::IncompatibleServerPage => &mut self.incompatible_server_page,
+        }
+    }
+
+    /// Where the previews of the images are drawn over the active page, as of the last render
+    pub fn image_placements(&self) -> Vec<ImagePlacement> {
+        match self.props.active_page {
+            ActivePage::ChatPage => self.chat_page.image_placements(),
+            _ => vec![],
         }
     }
 }
@@ -64,6 +107,9 @@ impl Component for AppRouter {
             //
             chat_page: ChatPage::new(state, action_tx.clone()),
             connect_page: ConnectPage::new(state, action_tx.clone()),
+            login_page: LoginPage::new(state, action_tx.clone()),
+            config_migration_page: ConfigMigrationPage::new(state, action_tx.clone()),
+            incompatible_server_page: IncompatibleServerPage::new(state, action_tx.clone()),
         }
         .move_with_state(state)
     }
@@ -77,6 +123,9 @@ impl Component for AppRouter {
             //
             chat_page: self.chat_page.move_with_state(state),
             connect_page: self.connect_page.move_with_state(state),
+            login_page: self.login_page.move_with_state(state),
+            config_migration_page: self.config_migration_page.move_with_state(state),
+            incompatible_server_page: self.incompatible_server_page.move_with_state(state),
         }
     }
 
@@ -88,13 +137,115 @@ impl Component for AppRouter {
     fn handle_key_event(&mut self, key: KeyEvent) {
         self.get_active_page_component_mut().handle_key_event(key)
     }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        self.get_active_page_component_mut()
+            .handle_mouse_event(mouse)
+    }
 }
 
 impl ComponentRender<()> for AppRouter {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, props: ()) {
+        if layout::is_too_small(frame.size()) {
+            return layout::render_too_small(frame);
+        }
+
         match self.props.active_page {
             ActivePage::ChatPage => self.chat_page.render(frame, props),
             ActivePage::ConnectPage => self.connect_page.render(frame, props),
+            ActivePage::LoginPage => self.login_page.render(frame, props),
+            ActivePage::ConfigMigrationPage => self.config_migration_page.render(frame, props),
+            ActivePage::IncompatibleServerPage => {
+                self.incompatible_server_page.render(frame, props)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyCode;
+
+    use super::*;
+    use crate::config::{ClientConfig, ConfigMigration};
+    use crate::ui_management::test_harness::TestHarness;
+
+    fn active_page(state: &State) -> String {
+        String::from(TestHarness::<AppRouter>::new(state).component().name())
+    }
+
+    #[test]
+    fn test_the_active_page_follows_the_connection() {
+        let connected = ServerConnectionStatus::Connected {
+            addr: "localhost:8080".into(),
+        };
+        let logged_in = State::test_with_rooms(&[("general", "General talk")]);
+
+        assert_eq!(active_page(&State::default()), "Connect Page");
+        assert_eq!(
+            active_page(&State {
+                server_connection_status: connected.clone(),
+                ..State::default()
+            }),
+            "Login Page"
+        );
+        assert_eq!(active_page(&logged_in), "Chat Page");
+        assert_eq!(
+            active_page(&State {
+                server_connection_status: ServerConnectionStatus::Incompatible {
+                    addr: "localhost:8080".into(),
+                    min_protocol_version: 5,
+                },
+                ..State::default()
+            }),
+            "Incompatible Server Page"
+        );
+    }
+
+    #[test]
+    fn test_the_chat_page_is_kept_while_reconnecting() {
+        let state = State {
+            server_connection_status: ServerConnectionStatus::Reconnecting {
+                addr: "localhost:8080".into(),
+                attempt: 2,
+            },
+            ..State::test_with_rooms(&[("general", "General talk")])
+                .with_joined_room("general", &["alice"])
+                .with_active_room("general")
+        };
+        let mut harness = TestHarness::<AppRouter>::new(&state);
+
+        // the keys still reach the chat page while the connection is restored
+        harness
+            .press(KeyCode::Char('e'))
+            .type_text("hello")
+            .press(KeyCode::Enter);
+
+        assert_eq!(harness.component().name(), "Chat Page");
+        assert_eq!(
+            harness.drain_actions(),
+            vec![Action::SendMessage {
+                content: "hello".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_the_config_migration_is_shown_before_connecting() {
+        let state = State {
+            config_migration: Some(ConfigMigration {
+                from_version: 0,
+                changes: vec![],
+                upgraded: ClientConfig::default(),
+            }),
+            ..State::default()
+        };
+        let mut harness = TestHarness::<AppRouter>::new(&state);
+
+        // Enter would connect on the connect page
+        harness.press(KeyCode::Enter);
+
+        assert_eq!(harness.component().name(), "Config Migration Page");
+        assert_eq!(harness.drain_actions(), vec![Action::ApplyConfigMigration]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Renders the pages from fixture states and compares what they show to the snapshots kept next to this file,
+//! so a change to the layout shows up as a failing test
+//!
+//! Run the tests with `UPDATE_SNAPSHOTS=1` to write the snapshots again, then review their diff.
+
+use std::{path::PathBuf, time::Duration};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use comms::event::DirectoryRoom;
+
+use crate::state_store::{
+    LoginStatus, MessageBoxItem, RoomDirectory, ServerConnectionStatus, State,
+};
+
+use super::pages::AppRouter;
+
+/// Environment variable writing the snapshots rather than comparing them, when set
+const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+const WIDTH: u16 = 100;
+const HEIGHT: u16 = 30;
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/ui_management/snapshots")
+        .join(format!("{}.txt", name))
+}
+
+/// Renders the state and compares it to the snapshot of the given name
+fn assert_snapshot(name: &str, state: &State, width: u16, height: u16) {
+    assert_text_snapshot(name, AppRouter::test_harness(state).snapshot(width, height));
+}
+
+fn assert_text_snapshot(name: &str, actual: String) {
+    let path = snapshot_path(name);
+
+    if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "no snapshot at {}, run the tests with {}=1 to write it",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV
+        )
+    });
+
+    if actual != expected {
+        let first_difference = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(actual, expected)| actual != expected)
+            .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+
+        panic!(
+            "snapshot {} differs from row {}, run the tests with {}=1 to accept the change\n\nexpected:\n{}\nactual:\n{}",
+            name, first_difference, UPDATE_SNAPSHOTS_ENV, expected, actual
+        );
+    }
+}
+
+fn chat_state() -> State {
+    State::test_with_rooms(&[
+        ("general", "General talk"),
+        ("rust", "Talk about the Rust programming language"),
+    ])
+    .with_joined_room("general", &["alice", "bob"])
+    .with_joined_room("rust", &["alice"])
+    .with_active_room("general")
+    .with_message("general", "alice", "hello everyone")
+    .with_message("general", "bob", "hi alice, how are you doing today?")
+    .with_message("general", "tester", "welcome @bob")
+}
+
+#[test]
+fn test_connect_page() {
+    assert_snapshot("connect_page", &State::default(), WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_connect_page_with_error() {
+    let state = State {
+        server_connection_status: ServerConnectionStatus::Errored {
+            err: String::from("connection refused"),
+        },
+        ..State::default()
+    };
+
+    assert_snapshot("connect_page_with_error", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_login_page() {
+    let state = State {
+        server_connection_status: ServerConnectionStatus::Connected {
+            addr: String::from("localhost:8080"),
+        },
+        ..State::default()
+    };
+
+    assert_snapshot("login_page", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_login_page_with_rejected_login() {
+    let state = State {
+        server_connection_status: ServerConnectionStatus::Connected {
+            addr: String::from("localhost:8080"),
+        },
+        login_status: LoginStatus::Rejected {
+            reason: String::from("wrong password"),
+        },
+        ..State::default()
+    };
+
+    assert_snapshot("login_page_with_rejected_login", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_chat_page() {
+    assert_snapshot("chat_page", &chat_state(), WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_chat_page_with_the_side_panels_toggled() {
+    let mut harness = AppRouter::test_harness(&chat_state());
+
+    // the left panel is collapsed, while the right one is expanded though the terminal is too narrow for it
+    let snapshot = harness
+        .render(90, HEIGHT)
+        .press(KeyCode::Char('['))
+        .press(KeyCode::Char(']'))
+        .snapshot(90, HEIGHT);
+
+    assert_text_snapshot("chat_page_with_the_side_panels_toggled", snapshot);
+}
+
+#[test]
+fn test_chat_page_status_bar_while_typing() {
+    let mut state = chat_state();
+    state.round_trip = Some(Duration::from_millis(23));
+    let rust = state.room_data_map.get_mut("rust").unwrap();
+    rust.unread_count = 4;
+    rust.unread_mention_count = 1;
+    let mut harness = AppRouter::test_harness(&state);
+
+    // the status bar tells the latency, the unread messages and that the keys are typed in the message input
+    let snapshot = harness
+        .render(WIDTH, HEIGHT)
+        .press(KeyCode::Char('e'))
+        .snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_status_bar_while_typing", snapshot);
+}
+
+#[test]
+fn test_chat_page_with_a_message_over_the_length_limit() {
+    let mut state = chat_state();
+    state.max_message_chars = Some(10);
+    let mut harness = AppRouter::test_harness(&state);
+
+    // the counter of the input turns red along with its border once the message is too long
+    let snapshot = harness
+        .render(WIDTH, HEIGHT)
+        .press(KeyCode::Char('e'))
+        .type_text("hello everyone")
+        .snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_with_a_message_over_the_length_limit", snapshot);
+}
+
+#[test]
+fn test_chat_page_with_the_help_open() {
+    let mut harness = AppRouter::test_harness(&chat_state());
+
+    let snapshot = harness.press(KeyCode::Char('?')).snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_with_the_help_open", snapshot);
+}
+
+#[test]
+fn test_chat_page_with_the_command_palette_open() {
+    let mut harness = AppRouter::test_harness(&chat_state());
+
+    let snapshot = harness
+        .press_with_modifiers(KeyCode::Char('p'), KeyModifiers::CONTROL)
+        .type_text("to")
+        .snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_with_the_command_palette_open", snapshot);
+}
+
+#[test]
+fn test_chat_page_with_the_room_switcher_open() {
+    let mut state = chat_state();
+    let rust = state.room_data_map.get_mut("rust").unwrap();
+    rust.unread_count = 3;
+    rust.unread_mention_count = 1;
+    let mut harness = AppRouter::test_harness(&state);
+
+    let snapshot = harness
+        .press_with_modifiers(KeyCode::Char('k'), KeyModifiers::CONTROL)
+        .snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_with_the_room_switcher_open", snapshot);
+}
+
+#[test]
+fn test_chat_page_browsing_the_public_rooms() {
+    let mut state = chat_state();
+    state.can_browse_rooms = true;
+    let mut harness = AppRouter::test_harness(&state);
+    state.room_directory = Some(RoomDirectory {
+        rooms: vec![
+            DirectoryRoom {
+                name: String::from("general"),
+                description: String::from("General talk"),
+                member_count: 3,
+                last_activity: None,
+            },
+            DirectoryRoom {
+                name: String::from("offtopic"),
+                description: String::from("Anything goes, as long as it is kind"),
+                member_count: 12,
+                last_activity: None,
+            },
+        ],
+        cursor: None,
+        is_loading: false,
+    });
+
+    let snapshot = harness
+        .press(KeyCode::Right)
+        .press(KeyCode::Char('e'))
+        .press(KeyCode::Tab)
+        .apply_state(&state)
+        .press(KeyCode::Down)
+        .snapshot(WIDTH, HEIGHT);
+
+    assert_text_snapshot("chat_page_browsing_the_public_rooms", snapshot);
+}
+
+#[test]
+fn test_chat_page_with_a_user_profile_open() {
+    let mut state = chat_state();
+    state.user_profile = Some(comms::event::UserProfile {
+        user_id: "alice".into(),
+        display_name: Some("Alice".into()),
+        bio: Some("Curious about everything, mostly rabbits".into()),
+        pronouns: Some("she/her".into()),
+        timezone: Some("Europe/London".into()),
+        color: Some("#ff8800".into()),
+    });
+
+    assert_snapshot("chat_page_with_a_user_profile_open", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_chat_page_in_a_small_terminal() {
+    assert_snapshot("chat_page_in_a_small_terminal", &chat_state(), 60, 20);
+}
+
+#[test]
+fn test_chat_page_with_errors() {
+    let mut state = chat_state();
+    state
+        .room_data_map
+        .get_mut("general")
+        .unwrap()
+        .messages
+        .push(MessageBoxItem::Error(String::from(
+            "the message is longer than the 2000 characters allowed",
+        )));
+    state.rate_limit_warning = Some(String::from("sending too fast, retry in 2s"));
+
+    assert_snapshot("chat_page_with_errors", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_chat_page_while_reconnecting() {
+    let state = State {
+        server_connection_status: ServerConnectionStatus::Reconnecting {
+            addr: String::from("localhost:8080"),
+            attempt: 2,
+        },
+        ..chat_state()
+    };
+
+    assert_snapshot("chat_page_while_reconnecting", &state, WIDTH, HEIGHT);
+}
+
+#[test]
+fn test_terminal_too_small() {
+    assert_snapshot("terminal_too_small", &chat_state(), 30, 10);
+}
@@ -0,0 +1,147 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{backend::TestBackend, Terminal};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use unicode_width::UnicodeWidthStr;
+
+use crate::state_store::{action::Action, State};
+
+use super::{
+    components::{Component, ComponentRender},
+    pages::AppRouter,
+};
+
+/// [TestHarness] drives a component without a terminal or a state store,
+/// so it can be tested by pressing keys and inspecting the emitted actions
+///
+/// The components of the chat page are driven on their own, the pages and the routing between them through the [AppRouter].
+pub struct TestHarness<C> {
+    component: Option<C>,
+    action_rx: UnboundedReceiver<Action>,
+}
+
+impl AppRouter {
+    /// Creates the app from the given state, wired to a channel the harness collects actions from
+    pub fn test_harness(state: &State) -> TestHarness<AppRouter> {
+        TestHarness::new(state)
+    }
+}
+
+impl<C: Component> TestHarness<C> {
+    /// Creates the component from the given state, wired to a channel the harness collects actions from
+    pub fn new(state: &State) -> Self {
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+
+        TestHarness {
+            component: Some(C::new(state, action_tx)),
+            action_rx,
+        }
+    }
+
+    pub fn component(&self) -> &C {
+        self.component.as_ref().unwrap()
+    }
+
+    pub fn component_mut(&mut self) -> &mut C {
+        self.component.as_mut().unwrap()
+    }
+
+    /// Moves the component to the given state, as the state store would do after an update
+    pub fn apply_state(&mut self, state: &State) -> &mut Self {
+        self.component = self
+            .component
+            .take()
+            .map(|component| component.move_with_state(state));
+        self
+    }
+
+    /// Sends a key press without modifiers to the component
+    pub fn press(&mut self, code: KeyCode) -> &mut Self {
+        self.press_with_modifiers(code, KeyModifiers::NONE)
+    }
+
+    pub fn press_with_modifiers(&mut self, code: KeyCode, modifiers: KeyModifiers) -> &mut Self {
+        self.component_mut()
+            .handle_key_event(KeyEvent::new(code, modifiers));
+        self
+    }
+
+    /// Clicks the left mouse button at the given cell of the terminal
+    pub fn click(&mut self, column: u16, row: u16) -> &mut Self {
+        self.mouse(MouseEventKind::Down(MouseButton::Left), column, row)
+    }
+
+    /// Holds the left mouse button down from a cell of the terminal to another, then releases it
+    pub fn drag(&mut self, from: (u16, u16), to: (u16, u16)) -> &mut Self {
+        self.click(from.0, from.1)
+            .mouse(MouseEventKind::Drag(MouseButton::Left), to.0, to.1)
+            .mouse(MouseEventKind::Up(MouseButton::Left), to.0, to.1)
+    }
+
+    fn mouse(&mut self, kind: MouseEventKind, column: u16, row: u16) -> &mut Self {
+        self.component_mut().handle_mouse_event(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        });
+        self
+    }
+
+    /// Presses the key for each character of the given text
+    pub fn type_text(&mut self, text: &str) -> &mut Self {
+        for char in text.chars() {
+            self.press(KeyCode::Char(char));
+        }
+        self
+    }
+
+    /// Returns the actions emitted by the component since the last call
+    pub fn drain_actions(&mut self) -> Vec<Action> {
+        let mut actions = vec![];
+
+        while let Ok(action) = self.action_rx.try_recv() {
+            actions.push(action);
+        }
+
+        actions
+    }
+}
+
+impl<C: Component + ComponentRender<()>> TestHarness<C> {
+    /// Renders the component to a terminal of the given size, which the mouse events are routed by
+    pub fn render(&mut self, width: u16, height: u16) -> &mut Self {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let component = self.component();
+
+        terminal.draw(|frame| component.render(frame, ())).unwrap();
+        self
+    }
+
+    /// Renders the component to a terminal of the given size and returns the text it shows, a line per row
+    ///
+    /// The trailing spaces of the rows are left out, along with the cells a wide character spills over.
+    pub fn snapshot(&mut self, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let component = self.component();
+
+        terminal.draw(|frame| component.render(frame, ())).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let mut snapshot = String::new();
+        for y in 0..height {
+            let mut row = String::new();
+            let mut x = 0;
+
+            while x < width {
+                let symbol = &buffer.get(x, y).symbol;
+                row.push_str(symbol);
+                x += symbol.width().max(1) as u16;
+            }
+
+            snapshot.push_str(row.trim_end());
+            snapshot.push('\n');
+        }
+
+        snapshot
+    }
+}
@@ -5,7 +5,10 @@ use std::{
 
 use anyhow::Context;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, Event, EventStream},
+    event::{
+        DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture, Event,
+        EventStream,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -17,7 +20,8 @@ use tokio::sync::{
 use tokio_stream::StreamExt;
 
 use crate::{
-    state_store::{action::Action, State},
+    graphics::{self, GraphicsProtocol, ImagePlacement},
+    state_store::{action::Action, State, TerminalRequest},
     ui_management::components::{Component, ComponentRender},
     Interrupted,
 };
@@ -40,6 +44,7 @@ impl UiManager {
     pub async fn main_loop(
         self,
         mut state_rx: UnboundedReceiver<State>,
+        mut terminal_rx: UnboundedReceiver<TerminalRequest>,
         mut interrupt_rx: broadcast::Receiver<Interrupted>,
     ) -> anyhow::Result<Interrupted> {
         // consume the first state to initialize the ui app
@@ -52,6 +57,8 @@ impl UiManager {
         let mut terminal = setup_terminal()?;
         let mut ticker = tokio::time::interval(RENDERING_TICK_RATE);
         let mut crossterm_events = EventStream::new();
+        // the previews of the images are drawn over the cells ratatui left blank for them
+        let mut drawn_images: Vec<ImagePlacement> = vec![];
 
         let result: anyhow::Result<Interrupted> = loop {
             tokio::select! {
@@ -62,6 +69,21 @@ impl UiManager {
                     Some(Ok(Event::Key(key)))  => {
                         app_router.handle_key_event(key);
                     },
+                    Some(Ok(Event::Mouse(mouse))) => {
+                        app_router.handle_mouse_event(mouse);
+                    },
+                    // the mentions are notified on the desktop while the terminal is not focused
+                    Some(Ok(Event::FocusGained)) => {
+                        let _ = self.action_tx.send(Action::SetTerminalFocus { is_focused: true });
+                    },
+                    Some(Ok(Event::FocusLost)) => {
+                        let _ = self.action_tx.send(Action::SetTerminalFocus { is_focused: false });
+                    },
+                    // the resized screen is redrawn from scratch, along with the images
+                    Some(Ok(Event::Resize(_, _))) => {
+                        let _ = graphics::delete_kitty_images(terminal.backend_mut(), &drawn_images);
+                        drawn_images.clear();
+                    },
                     None => break Ok(Interrupted::UserInt),
                     _ => (),
                 },
@@ -69,6 +91,15 @@ impl UiManager {
                 Some(state) = state_rx.recv() => {
                     app_router = app_router.move_with_state(&state);
                 },
+                // the requests are written between two frames, so that they do not interleave with them
+                Some(request) = terminal_rx.recv() => {
+                    if let Err(err) = request
+                        .write_to(terminal.backend_mut())
+                        .context("could not write to the terminal")
+                    {
+                        break Err(err);
+                    }
+                },
                 // Catch and handle interrupt signal to gracefully shutdown
                 Ok(interrupted) = interrupt_rx.recv() => {
                     break Ok(interrupted);
@@ -81,6 +112,17 @@ impl UiManager {
             {
                 break Err(err);
             }
+
+            let image_placements = app_router.image_placements();
+            if image_placements != drawn_images {
+                if let Err(err) =
+                    redraw_images(&mut terminal, &app_router, &drawn_images, &image_placements)
+                {
+                    break Err(err);
+                }
+
+                drawn_images = image_placements;
+            }
         };
 
         restore_terminal(&mut terminal)?;
@@ -89,12 +131,41 @@ impl UiManager {
     }
 }
 
+/// Replaces the drawn images with the ones at the new placements
+fn redraw_images(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app_router: &AppRouter,
+    drawn_images: &[ImagePlacement],
+    image_placements: &[ImagePlacement],
+) -> anyhow::Result<()> {
+    graphics::delete_kitty_images(terminal.backend_mut(), drawn_images)?;
+
+    // the other protocols draw into the cells, which ratatui only redraws once they change
+    if drawn_images
+        .iter()
+        .any(|placement| placement.image.protocol != GraphicsProtocol::Kitty)
+    {
+        terminal.clear()?;
+        terminal
+            .draw(|frame| app_router.render(frame, ()))
+            .context("could not render to the terminal")?;
+    }
+
+    graphics::draw_images(terminal.backend_mut(), image_placements)
+        .context("could not draw the images")
+}
+
 fn setup_terminal() -> anyhow::Result<Terminal<CrosstermBackend<Stdout>>> {
     let mut stdout = io::stdout();
 
     enable_raw_mode()?;
 
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableFocusChange
+    )?;
 
     Ok(Terminal::new(CrosstermBackend::new(stdout))?)
 }
@@ -105,7 +176,8 @@ fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> anyhow
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableFocusChange
     )?;
 
     Ok(terminal.show_cursor()?)